@@ -0,0 +1,107 @@
+//! Analysis time on synthetic manifests of large hosts.
+//!
+//! Run with `cargo bench -p xcprobe-analyzer`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use xcprobe_analyzer::{analyze_bundle, AnalyzeOptions};
+use xcprobe_bundle_schema::{Bundle, Manifest};
+
+/// A host running `apps` applications of a master and three workers, one
+/// service in ten of them managed by systemd, plus as many idle system
+/// processes.
+fn large_manifest(apps: u32) -> Manifest {
+    let mut processes = Vec::new();
+    let mut services = Vec::new();
+    let mut ports = Vec::new();
+    let mut config_files = Vec::new();
+    let mut log_files = Vec::new();
+    for app in 0..apps {
+        let master = 10_000 + app * 4;
+        let workdir = format!("/opt/app{}", app);
+        for (n, pid) in (master..master + 4).enumerate() {
+            processes.push(json!({
+                "pid": pid, "ppid": if n == 0 { 1 } else { master },
+                "user": format!("app{}", app), "command": "/usr/bin/node",
+                "args": ["server.js"], "full_cmdline": "/usr/bin/node server.js",
+                "working_directory": workdir
+            }));
+        }
+        ports.push(json!({
+            "protocol": "tcp", "local_address": "0.0.0.0",
+            "local_port": 20_000 + app, "state": "LISTEN", "pid": master
+        }));
+        config_files.push(file(&format!("{}/config.json", workdir), master));
+        log_files.push(file(&format!("/var/log/app{}.log", app), master));
+        if app % 10 == 0 {
+            services.push(json!({
+                "name": format!("app{}.service", app), "state": "active",
+                "exec_start": "/usr/bin/node server.js", "working_directory": workdir,
+                "exec_start_pre": [], "exec_start_post": [], "environment": {},
+                "environment_files": [], "dependencies": [], "wanted_by": [],
+                "main_pid": master
+            }));
+        }
+    }
+    for pid in 100..100 + apps * 4 {
+        processes.push(json!({
+            "pid": pid, "ppid": 1, "user": "root", "command": "/usr/sbin/agetty",
+            "args": ["tty1"], "full_cmdline": "/usr/sbin/agetty tty1"
+        }));
+    }
+
+    serde_json::from_value(json!({
+        "schema_version": "1.0.0",
+        "collection_id": "00000000-0000-0000-0000-000000000000",
+        "collected_at": "2026-01-01T00:00:00Z",
+        "completed_at": null,
+        "system": {
+            "hostname": "large01", "os_type": "linux", "os_version": null,
+            "kernel_version": null, "architecture": null, "uptime_seconds": null,
+            "timezone": null
+        },
+        "processes": processes, "services": services, "ports": ports,
+        "connections": [], "packages": [], "scheduled_tasks": [],
+        "config_files": config_files, "log_files": log_files,
+        "environment_files": [], "collection_mode": "local", "errors": []
+    }))
+    .unwrap()
+}
+
+fn file(path: &str, opened_by: u32) -> Value {
+    json!({
+        "path": path, "size_bytes": 100, "modified_at": null, "owner": null,
+        "permissions": null, "content_hash": null, "attachment_ref": null,
+        "discovery_method": "open_files", "discovery_evidence_ref": null,
+        "opened_by": [opened_by]
+    })
+}
+
+fn bench_analyze(c: &mut Criterion) {
+    let options = AnalyzeOptions {
+        reproducible: true,
+        ..Default::default()
+    };
+    let mut group = c.benchmark_group("analyze");
+    group.sample_size(10);
+    for apps in [250, 2_500] {
+        let bundle = Bundle {
+            manifest: large_manifest(apps),
+            audit: Vec::new(),
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let processes = bundle.manifest.processes.len();
+        group.throughput(Throughput::Elements(processes as u64));
+        group.bench_with_input(
+            BenchmarkId::new("processes", processes),
+            &bundle,
+            |b, bundle| b.iter(|| analyze_bundle(bundle, &options).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_analyze);
+criterion_main!(benches);
@@ -0,0 +1,364 @@
+//! Host address rewriting in configuration files.
+//!
+//! Configuration files collected on the source host name that host (its
+//! hostname or IPv4 addresses) and reach other applications on `localhost`.
+//! Inside the compose network those addresses point nowhere, so each
+//! occurrence is replaced by a template variable defaulting to the compose
+//! service name: `10.0.0.5:5432` becomes `${APP_1_ADDR}`, set to
+//! `app-1:5432`, when `app-1` listens on port 5432.
+
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, EnvVarSpec};
+
+/// Confidence of a rewrite: the address may also be meant for something
+/// outside the migrated applications.
+const REWRITE_CONFIDENCE: f64 = 0.8;
+
+/// Loopback names, rewritten only when they reach another cluster.
+const LOOPBACK: &[&str] = &["localhost", "127.0.0.1"];
+
+/// A rewritten address and the variable replacing it.
+struct Rewrite {
+    variable: String,
+    default_value: String,
+    /// Port kept after the variable, when only the host is rewritten.
+    port: Option<u16>,
+}
+
+impl Rewrite {
+    fn replacement(&self) -> String {
+        match self.port {
+            Some(port) => format!("${{{}}}:{}", self.variable, port),
+            None => format!("${{{}}}", self.variable),
+        }
+    }
+}
+
+/// Rewrite the source host's addresses in the configuration files of the
+/// clusters. Rewritten files become templates, and their variables
+/// environment variables of the cluster.
+pub fn rewrite_host_addresses(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    let hosts = host_addresses(bundle);
+    let Some(pattern) = address_pattern(&hosts) else {
+        return;
+    };
+    let listeners: BTreeMap<u16, String> = clusters
+        .iter()
+        .flat_map(|c| c.ports.iter().map(|p| (p.port, c.id.clone())))
+        .collect();
+
+    for cluster in clusters.iter_mut().filter(|c| c.database.is_none()) {
+        let mut new_vars: Vec<(String, String, Option<String>)> = Vec::new();
+        for config in cluster.config_files.iter_mut().filter(|c| !c.templated) {
+            let Some(content) = config
+                .evidence_ref
+                .as_ref()
+                .and_then(|r| bundle.evidence.get(r))
+                .and_then(|e| e.output())
+            else {
+                continue;
+            };
+            let mut rewrites: BTreeMap<String, Rewrite> = BTreeMap::new();
+            let rewritten = rewrite_content(&content, &pattern, |host, port| {
+                let rewrite = resolve(host, port, &cluster.id, &listeners)?;
+                let replacement = rewrite.replacement();
+                rewrites
+                    .entry(match port {
+                        Some(port) => format!("{}:{}", host, port),
+                        None => host.to_string(),
+                    })
+                    .or_insert(rewrite);
+                Some(replacement)
+            });
+            if rewrites.is_empty() {
+                continue;
+            }
+
+            for (original, rewrite) in rewrites {
+                cluster.decisions.push(Decision::new(
+                    format!(
+                        "Rewrite {} as {} in {}",
+                        original,
+                        rewrite.replacement(),
+                        config.source_path
+                    ),
+                    format!(
+                        "Address of the source host; defaults to the compose service {}",
+                        rewrite.default_value
+                    ),
+                    config.evidence_ref.iter().cloned().collect(),
+                    REWRITE_CONFIDENCE,
+                ));
+                if !config.template_vars.contains(&rewrite.variable) {
+                    config.template_vars.push(rewrite.variable.clone());
+                }
+                new_vars.push((
+                    rewrite.variable,
+                    rewrite.default_value,
+                    config.evidence_ref.clone(),
+                ));
+            }
+            config.templated = true;
+            config.content = Some(rewritten);
+        }
+
+        for (name, default_value, evidence_ref) in new_vars {
+            if cluster.env_vars.iter().any(|e| e.name == name) {
+                continue;
+            }
+            cluster.env_vars.push(EnvVarSpec {
+                name,
+                required: true,
+                default_value: Some(default_value),
+                description: Some("Address of a service in the compose network".to_string()),
+                sensitive: false,
+                evidence_ref,
+            });
+        }
+    }
+}
+
+/// Hostname, short hostname and IPv4 addresses the source host listens or
+/// connects from, loopback and wildcard addresses excluded.
+fn host_addresses(bundle: &Bundle) -> Vec<String> {
+    let manifest = &bundle.manifest;
+    let mut hosts: Vec<String> = Vec::new();
+    let hostname = manifest.system.hostname.trim();
+    if !hostname.is_empty() && !LOOPBACK.contains(&hostname) {
+        hosts.push(hostname.to_string());
+        if let Some((short, _)) = hostname.split_once('.') {
+            hosts.push(short.to_string());
+        }
+    }
+
+    let local_addresses = manifest
+        .ports
+        .iter()
+        .map(|p| p.local_address.as_str())
+        .chain(
+            manifest
+                .connections
+                .iter()
+                .map(|c| c.local_address.as_str()),
+        );
+    for address in local_addresses {
+        let address = address.trim_start_matches("::ffff:");
+        let Ok(ip) = address.parse::<Ipv4Addr>() else {
+            continue;
+        };
+        if !ip.is_loopback() && !ip.is_unspecified() && !hosts.contains(&ip.to_string()) {
+            hosts.push(ip.to_string());
+        }
+    }
+    hosts
+}
+
+/// Pattern matching the host addresses and loopback names, with an
+/// optional port. Longer names come first so that `web01.example.com` is
+/// not matched as `web01`.
+fn address_pattern(hosts: &[String]) -> Option<Regex> {
+    let mut names: Vec<&str> = hosts
+        .iter()
+        .map(String::as_str)
+        .chain(LOOPBACK.iter().copied())
+        .collect();
+    names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+    let alternatives: Vec<String> = names.iter().map(|n| regex::escape(n)).collect();
+    Regex::new(&format!(
+        r"(?i)({})(?::(\d{{1,5}}))?",
+        alternatives.join("|")
+    ))
+    .ok()
+}
+
+/// Replace the addresses matched by `pattern` by what `replace` returns
+/// for them. Matches inside longer names (`db.web01.example.com`,
+/// `10.0.0.50`) are left alone.
+fn rewrite_content(
+    content: &str,
+    pattern: &Regex,
+    mut replace: impl FnMut(&str, Option<u16>) -> Option<String>,
+) -> String {
+    let part_of_name = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let mut output = String::with_capacity(content.len());
+    let mut last = 0;
+    for caps in pattern.captures_iter(content) {
+        let whole = caps.get(0).expect("match");
+        let before = content[..whole.start()].chars().next_back();
+        let mut after = content[whole.end()..].chars();
+        let next = after.next();
+        if before.is_some_and(|c| part_of_name(c) || c == '.')
+            || next.is_some_and(part_of_name)
+            || (next == Some('.') && after.next().is_some_and(|c| c.is_ascii_alphanumeric()))
+        {
+            continue;
+        }
+        let port = match caps.get(2).map(|p| p.as_str().parse::<u16>()) {
+            Some(Ok(port)) => Some(port),
+            Some(Err(_)) => continue,
+            None => None,
+        };
+        let Some(replacement) = replace(&caps[1], port) else {
+            continue;
+        };
+        output.push_str(&content[last..whole.start()]);
+        output.push_str(&replacement);
+        last = whole.end();
+    }
+    output.push_str(&content[last..]);
+    output
+}
+
+/// The rewrite of `host:port` in a file of cluster `own`: the address of
+/// the cluster listening on the port, else the cluster's own service name
+/// with the port kept. Loopback addresses are rewritten only when another
+/// cluster listens on the port.
+fn resolve(
+    host: &str,
+    port: Option<u16>,
+    own: &str,
+    listeners: &BTreeMap<u16, String>,
+) -> Option<Rewrite> {
+    let listener = port.and_then(|p| listeners.get(&p).map(|id| (p, id)));
+    let loopback = LOOPBACK.iter().any(|l| l.eq_ignore_ascii_case(host));
+    match listener {
+        Some((port, id)) if !(loopback && id == own) => Some(Rewrite {
+            variable: format!("{}_ADDR", variable_prefix(id)),
+            default_value: format!("{}:{}", id, port),
+            port: None,
+        }),
+        _ if loopback => None,
+        _ => Some(Rewrite {
+            variable: format!("{}_HOST", variable_prefix(own)),
+            default_value: own.to_string(),
+            port,
+        }),
+    }
+}
+
+fn variable_prefix(cluster_id: &str) -> String {
+    cluster_id.to_uppercase().replace(['-', '.'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use xcprobe_bundle_schema::{ClusterPort, ConfigFileSpec, Evidence, Manifest, PortInfo};
+
+    fn cluster(id: &str, port: u16, config: Option<&str>) -> AppCluster {
+        AppCluster {
+            id: id.to_string(),
+            name: id.to_string(),
+            app_type: "api".to_string(),
+            ports: vec![ClusterPort {
+                port,
+                protocol: "tcp".to_string(),
+                ..Default::default()
+            }],
+            config_files: config
+                .iter()
+                .map(|path| ConfigFileSpec {
+                    source_path: path.to_string(),
+                    container_path: path.to_string(),
+                    evidence_ref: Some("evidence/config_1.txt".to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            confidence: 0.9,
+            ..Default::default()
+        }
+    }
+
+    fn bundle(config: &str) -> Bundle {
+        let mut manifest = Manifest::default();
+        manifest.system.hostname = "web01.example.com".to_string();
+        manifest.ports.push(PortInfo {
+            protocol: "tcp".to_string(),
+            local_address: "10.0.0.5".to_string(),
+            local_port: 8080,
+            state: "LISTEN".to_string(),
+            pid: None,
+            process_name: None,
+            evidence_ref: None,
+            pid_source: None,
+            pid_evidence_ref: None,
+            family: None,
+            socket_path: None,
+        });
+        Bundle {
+            manifest,
+            audit: vec![],
+            evidence: HashMap::from([(
+                "evidence/config_1.txt".to_string(),
+                Evidence::from_command_output(
+                    "config_1",
+                    "cat /etc/app/app.conf",
+                    config.as_bytes().to_vec(),
+                    "evidence/config_1.txt",
+                ),
+            )]),
+            checksums: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_rewrites_host_addresses_as_service_aliases() {
+        let config = "db_url = postgres://10.0.0.5:5432/app\n\
+                      cache = localhost:6379\n\
+                      public_url = http://web01.example.com/\n\
+                      callback = http://web01:8080/hook\n\
+                      admin = http://localhost:8080/\n\
+                      mirror = 10.0.0.50:5432\n";
+        let mut clusters = vec![
+            cluster("app-0", 8080, Some("/etc/app/app.conf")),
+            cluster("app-1", 5432, None),
+            cluster("app-2", 6379, None),
+        ];
+
+        rewrite_host_addresses(&bundle(config), &mut clusters);
+
+        let config = &clusters[0].config_files[0];
+        assert!(config.templated);
+        assert_eq!(
+            config.content.as_deref(),
+            Some(
+                "db_url = postgres://${APP_1_ADDR}/app\n\
+                 cache = ${APP_2_ADDR}\n\
+                 public_url = http://${APP_0_HOST}/\n\
+                 callback = http://${APP_0_ADDR}/hook\n\
+                 admin = http://localhost:8080/\n\
+                 mirror = 10.0.0.50:5432\n"
+            )
+        );
+        assert_eq!(
+            config.template_vars,
+            vec!["APP_1_ADDR", "APP_2_ADDR", "APP_0_HOST", "APP_0_ADDR"]
+        );
+        let default = |name: &str| {
+            clusters[0]
+                .env_vars
+                .iter()
+                .find(|e| e.name == name)
+                .and_then(|e| e.default_value.as_deref())
+        };
+        assert_eq!(default("APP_1_ADDR"), Some("app-1:5432"));
+        assert_eq!(default("APP_0_HOST"), Some("app-0"));
+        assert!(clusters[0]
+            .decisions
+            .iter()
+            .any(|d| d.decision == "Rewrite 10.0.0.5:5432 as ${APP_1_ADDR} in /etc/app/app.conf"));
+    }
+
+    #[test]
+    fn test_unknown_port_keeps_port() {
+        let mut clusters = vec![cluster("app-0", 8080, Some("/etc/app/app.conf"))];
+        rewrite_host_addresses(&bundle("peer=10.0.0.5:9999\n"), &mut clusters);
+        assert_eq!(
+            clusters[0].config_files[0].content.as_deref(),
+            Some("peer=${APP_0_HOST}:9999\n")
+        );
+    }
+}
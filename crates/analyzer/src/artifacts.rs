@@ -0,0 +1,793 @@
+//! In-memory artifact model.
+//!
+//! Generated files are first rendered into an [`ArtifactSet`], validated as a
+//! whole, and only then written to disk through a staging directory. A failure
+//! at any point before the final rename leaves the output directory untouched.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, warn};
+use xcprobe_bundle_schema::{
+    ArtifactType, GeneratedArtifact, PackManifest, PackPlan, PACK_DIR, PACK_MANIFEST_FILE,
+};
+
+/// Default mode for generated files.
+pub const FILE_MODE: u32 = 0o644;
+
+/// Mode for generated scripts.
+pub const EXECUTABLE_MODE: u32 = 0o755;
+
+/// A single generated file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactFile {
+    /// File content.
+    pub content: String,
+    /// Unix permission bits.
+    pub mode: u32,
+}
+
+/// A set of generated files keyed by their path relative to the output directory.
+#[derive(Debug, Clone, Default)]
+pub struct ArtifactSet {
+    files: BTreeMap<PathBuf, ArtifactFile>,
+}
+
+impl ArtifactSet {
+    /// Create an empty artifact set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file. Fails if a file was already registered at the same path.
+    pub fn add(&mut self, path: impl Into<PathBuf>, content: String, mode: u32) -> Result<()> {
+        let path = path.into();
+        if self.files.contains_key(&path) {
+            anyhow::bail!("Artifact path collision: {}", path.display());
+        }
+        self.files.insert(path, ArtifactFile { content, mode });
+        Ok(())
+    }
+
+    /// Merge another set into this one, failing on any path collision.
+    pub fn merge(&mut self, other: ArtifactSet) -> Result<()> {
+        for (path, file) in other.files {
+            self.add(path, file.content, file.mode)?;
+        }
+        Ok(())
+    }
+
+    /// Get a file by relative path.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&ArtifactFile> {
+        self.files.get(path.as_ref())
+    }
+
+    /// Iterate over all files in path order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &ArtifactFile)> {
+        self.files.iter().map(|(p, f)| (p.as_path(), f))
+    }
+
+    /// Number of files in the set.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Describe every file of the set for the plan's `artifacts`: cluster,
+    /// type and content hash, in path order.
+    pub fn describe(&self) -> Vec<GeneratedArtifact> {
+        self.files
+            .iter()
+            .map(|(path, file)| {
+                // Files under a directory belong to the cluster it is named after
+                let cluster_id = match path.parent().and_then(|p| p.iter().next()) {
+                    Some(dir) => dir.to_string_lossy().into_owned(),
+                    None => String::new(),
+                };
+                let (artifact_type, description) = describe_path(path);
+                GeneratedArtifact {
+                    cluster_id,
+                    artifact_type,
+                    path: path.to_string_lossy().replace('\\', "/"),
+                    description: Some(description.to_string()),
+                    content_hash: Some(xcprobe_common::hash::sha256_str(&file.content)),
+                    preserved: false,
+                    build: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Top-level entries of the set already present in `output_dir`.
+    pub fn existing_outputs(&self, output_dir: &Path) -> Vec<PathBuf> {
+        self.top_level()
+            .into_iter()
+            .filter(|entry| output_dir.join(entry).exists())
+            .collect()
+    }
+
+    /// Keep the files of `output_dir` edited since they were generated.
+    ///
+    /// Every file under an entry the set replaces (pack directories aside)
+    /// is compared with the hash recorded for it in `previous`. Files that
+    /// still match are regenerated, or dropped if no longer generated; the
+    /// others, edited or added by hand, replace the generated content.
+    /// Returns the kept paths.
+    pub fn keep_edited(
+        &mut self,
+        output_dir: &Path,
+        previous: &[GeneratedArtifact],
+    ) -> Result<Vec<PathBuf>> {
+        let recorded: BTreeMap<&str, Option<&str>> = previous
+            .iter()
+            .map(|a| (a.path.as_str(), a.content_hash.as_deref()))
+            .collect();
+
+        let mut existing = Vec::new();
+        for entry in self.top_level() {
+            collect_files(output_dir, &entry, &mut existing)?;
+        }
+        existing.sort();
+
+        let mut kept = Vec::new();
+        for path in existing {
+            let full_path = output_dir.join(&path);
+            let bytes = std::fs::read(&full_path)
+                .with_context(|| format!("Failed to read {}", full_path.display()))?;
+            let hash = xcprobe_common::hash::sha256_bytes(&bytes);
+            let key = path.to_string_lossy().replace('\\', "/");
+            let unchanged = recorded.get(key.as_str()).copied().flatten() == Some(hash.as_str());
+            let regenerated = self
+                .files
+                .get(&path)
+                .is_some_and(|f| xcprobe_common::hash::sha256_str(&f.content) == hash);
+            if unchanged || regenerated {
+                continue;
+            }
+
+            let content = String::from_utf8(bytes).map_err(|_| {
+                anyhow::anyhow!(
+                    "Cannot keep {}: not a text file. Move it out of the output directory",
+                    full_path.display()
+                )
+            })?;
+            let mode = file_mode(&full_path)?;
+            self.files
+                .insert(path.clone(), ArtifactFile { content, mode });
+            debug!("Keeping edited artifact: {}", path.display());
+            kept.push(path);
+        }
+
+        Ok(kept)
+    }
+
+    /// First component of every path, in order.
+    fn top_level(&self) -> BTreeSet<PathBuf> {
+        self.files
+            .keys()
+            .filter_map(|p| p.iter().next())
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Validate the whole set: safe relative paths, no file/directory
+    /// collisions, and syntactically valid YAML, JSON and Dockerfiles.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        for (path, file) in &self.files {
+            if !is_safe_relative_path(path) {
+                problems.push(format!("{}: path must be relative", path.display()));
+                continue;
+            }
+
+            // A file cannot also be the parent directory of another file
+            for ancestor in path.ancestors().skip(1) {
+                if !ancestor.as_os_str().is_empty() && self.files.contains_key(ancestor) {
+                    problems.push(format!(
+                        "{}: parent {} is also a file",
+                        path.display(),
+                        ancestor.display()
+                    ));
+                }
+            }
+
+            let file_name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            if file_name == "Dockerfile" {
+                if let Err(e) = validate_dockerfile(&file.content) {
+                    problems.push(format!("{}: {}", path.display(), e));
+                }
+            } else if extension == "yaml" || extension == "yml" {
+                if let Err(e) = serde_yaml::from_str::<serde_yaml::Value>(&file.content) {
+                    problems.push(format!("{}: invalid YAML: {}", path.display(), e));
+                }
+            } else if extension == "json" {
+                if let Err(e) = serde_json::from_str::<serde_json::Value>(&file.content) {
+                    problems.push(format!("{}: invalid JSON: {}", path.display(), e));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Generated artifacts are invalid:\n  {}",
+                problems.join("\n  ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Write all files into `output_dir`.
+    ///
+    /// Files are written to a staging directory inside `output_dir` first, and
+    /// each top-level entry is then renamed into place, replacing any previous
+    /// version except for its pack directory. The staging directory is removed
+    /// on both success and failure.
+    pub fn write_atomic(&self, output_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(output_dir).context("Failed to create output directory")?;
+
+        let staging = output_dir.join(format!(".xcprobe-staging-{}", uuid::Uuid::new_v4()));
+        let result = self
+            .write_to(&staging)
+            .and_then(|_| promote_staging(&staging, output_dir));
+
+        if staging.exists() {
+            let _ = std::fs::remove_dir_all(&staging);
+        }
+
+        result
+    }
+
+    /// Write all files under `dir` without any staging, in parallel.
+    fn write_to(&self, dir: &Path) -> Result<()> {
+        self.files.par_iter().try_for_each(|(path, file)| {
+            let full_path = dir.join(path);
+            if let Some(parent) = full_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&full_path, &file.content)
+                .with_context(|| format!("Failed to write {}", full_path.display()))?;
+            set_mode(&full_path, file.mode)?;
+            debug!("Staged artifact: {}", path.display());
+            Ok(())
+        })
+    }
+}
+
+/// Mark the application files fetched by an earlier pack step into
+/// `output_dir` as collected, so that regenerated Dockerfiles copy them.
+/// A file counts when `pack-manifest.json` lists it for the same cluster and
+/// its copy is still under the cluster's `pack/` directory. Returns the
+/// number of files marked.
+pub fn mark_packed_files(plan: &mut PackPlan, output_dir: &Path) -> Result<usize> {
+    let manifest_path = output_dir.join(PACK_MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Ok(0);
+    }
+    let manifest: PackManifest = serde_json::from_str(
+        &std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?,
+    )
+    .with_context(|| format!("Invalid pack manifest {}", manifest_path.display()))?;
+
+    let mut marked = 0;
+    for cluster in &mut plan.clusters {
+        let cluster_dir = output_dir.join(&cluster.id);
+        for app_file in cluster.app_files.iter_mut().filter(|f| !f.collected) {
+            let listed = manifest
+                .files
+                .iter()
+                .any(|f| f.cluster_id == cluster.id && f.source_path == app_file.source_path);
+            if listed && cluster_dir.join(app_file.pack_path()).exists() {
+                app_file.collected = true;
+                marked += 1;
+            }
+        }
+    }
+    Ok(marked)
+}
+
+/// Move every top-level entry of `staging` into `output_dir`.
+fn promote_staging(staging: &Path, output_dir: &Path) -> Result<()> {
+    if !staging.exists() {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(staging)? {
+        let entry = entry?;
+        let target = output_dir.join(entry.file_name());
+
+        if target.is_dir() {
+            replace_dir(&entry.path(), &target, output_dir)?;
+            continue;
+        } else if target.exists() {
+            std::fs::remove_file(&target)?;
+        }
+
+        std::fs::rename(entry.path(), &target)
+            .with_context(|| format!("Failed to move artifact into {}", target.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Replace the directory `target` by `staged`, carrying its pack directory
+/// over. Files fetched by the pack step are not generated, so the previous
+/// version is kept outside the staging directory until they are in place.
+fn replace_dir(staged: &Path, target: &Path, output_dir: &Path) -> Result<()> {
+    let previous = output_dir.join(format!(".xcprobe-previous-{}", uuid::Uuid::new_v4()));
+    std::fs::rename(target, &previous)
+        .with_context(|| format!("Failed to move {} aside", target.display()))?;
+
+    if let Err(e) = std::fs::rename(staged, target) {
+        if let Err(restore) = std::fs::rename(&previous, target) {
+            warn!(
+                "Failed to restore {} from {}: {}",
+                target.display(),
+                previous.display(),
+                restore
+            );
+        }
+        return Err(e)
+            .with_context(|| format!("Failed to move artifact into {}", target.display()));
+    }
+
+    let pack = previous.join(PACK_DIR);
+    let new_pack = target.join(PACK_DIR);
+    if pack.is_dir() && !new_pack.exists() {
+        std::fs::rename(&pack, &new_pack).with_context(|| {
+            format!(
+                "Failed to move {} into {}; the previous version is kept in {}",
+                pack.display(),
+                new_pack.display(),
+                previous.display()
+            )
+        })?;
+    }
+    std::fs::remove_dir_all(&previous)?;
+    Ok(())
+}
+
+/// Add the files at or under `relative` in `base` to `files`, skipping
+/// pack directories.
+fn collect_files(base: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let full_path = base.join(relative);
+    if full_path.is_file() {
+        files.push(relative.to_path_buf());
+    } else if full_path.is_dir() {
+        for entry in std::fs::read_dir(&full_path)? {
+            let child = relative.join(entry?.file_name());
+            if child.components().count() == 2 && child.ends_with(PACK_DIR) {
+                continue;
+            }
+            collect_files(base, &child, files)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Result<u32> {
+    Ok(FILE_MODE)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Type and description of a generated file, from its path.
+fn describe_path(path: &Path) -> (ArtifactType, &'static str) {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    let in_templates = path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|d| d == "templates");
+    match file_name.as_ref() {
+        "Dockerfile" => (ArtifactType::Dockerfile, "Container image build"),
+        "entrypoint.sh" | "entrypoint.ps1" => (ArtifactType::Entrypoint, "Container entrypoint"),
+        "supervisord.conf" => (ArtifactType::Entrypoint, "Process supervisor configuration"),
+        "README.md" => (ArtifactType::Readme, "Cluster documentation"),
+        "MIGRATION.md" => (ArtifactType::Readme, "Database migration guide"),
+        "COMPATIBILITY.md" => (
+            ArtifactType::Readme,
+            "Windows to Linux compatibility report",
+        ),
+        "confidence.json" => (ArtifactType::ConfidenceReport, "Confidence report"),
+        "docker-compose.yaml" => (ArtifactType::ComposeFile, "Compose file"),
+        "docker-compose.dev.yaml" => (
+            ArtifactType::ComposeFile,
+            "Development stand-ins for external dependencies",
+        ),
+        "dependencies.mmd" => (ArtifactType::DependencyGraph, "Dependency graph (Mermaid)"),
+        "dependencies.dot" => (ArtifactType::DependencyGraph, "Dependency graph (Graphviz)"),
+        _ if in_templates => (ArtifactType::ConfigTemplate, "Configuration template"),
+        _ => (ArtifactType::Readme, "Generated file"),
+    }
+}
+
+/// Check that a path is relative and does not escape its root.
+fn is_safe_relative_path(path: &Path) -> bool {
+    !path.as_os_str().is_empty()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Instructions accepted in a Dockerfile.
+const DOCKERFILE_INSTRUCTIONS: &[&str] = &[
+    "FROM",
+    "RUN",
+    "CMD",
+    "LABEL",
+    "MAINTAINER",
+    "EXPOSE",
+    "ENV",
+    "ADD",
+    "COPY",
+    "ENTRYPOINT",
+    "VOLUME",
+    "USER",
+    "WORKDIR",
+    "ARG",
+    "ONBUILD",
+    "STOPSIGNAL",
+    "HEALTHCHECK",
+    "SHELL",
+];
+
+/// Basic structural validation of a Dockerfile.
+fn validate_dockerfile(content: &str) -> Result<()> {
+    let mut seen_from = false;
+    let mut continuation = false;
+
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line = raw_line.trim();
+        let was_continuation = continuation;
+        continuation = line.ends_with('\\');
+
+        if was_continuation || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let instruction = line
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_uppercase();
+
+        if !DOCKERFILE_INSTRUCTIONS.contains(&instruction.as_str()) {
+            anyhow::bail!("unknown instruction '{}' on line {}", instruction, idx + 1);
+        }
+
+        if !seen_from && instruction != "FROM" && instruction != "ARG" {
+            anyhow::bail!("'{}' before FROM on line {}", instruction, idx + 1);
+        }
+
+        if instruction == "FROM" {
+            seen_from = true;
+        }
+    }
+
+    if !seen_from {
+        anyhow::bail!("missing FROM instruction");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use xcprobe_bundle_schema::{AppCluster, AppFileSpec, ClusterPort, ClusterService};
+
+    #[test]
+    fn test_path_collision() {
+        let mut set = ArtifactSet::new();
+        set.add("app-0/Dockerfile", "FROM scratch\n".to_string(), FILE_MODE)
+            .unwrap();
+        assert!(set
+            .add("app-0/Dockerfile", "FROM scratch\n".to_string(), FILE_MODE)
+            .is_err());
+    }
+
+    #[test]
+    fn test_describe_artifacts() {
+        let mut set = ArtifactSet::new();
+        set.add("app-0/Dockerfile", "FROM scratch\n".to_string(), FILE_MODE)
+            .unwrap();
+        set.add("app-0/templates/app.conf.tmpl", String::new(), FILE_MODE)
+            .unwrap();
+        set.add(
+            "docker-compose.yaml",
+            "services: {}\n".to_string(),
+            FILE_MODE,
+        )
+        .unwrap();
+
+        let described: Vec<(String, ArtifactType, String)> = set
+            .describe()
+            .into_iter()
+            .map(|a| (a.cluster_id, a.artifact_type, a.path))
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                (
+                    "app-0".to_string(),
+                    ArtifactType::Dockerfile,
+                    "app-0/Dockerfile".to_string()
+                ),
+                (
+                    "app-0".to_string(),
+                    ArtifactType::ConfigTemplate,
+                    "app-0/templates/app.conf.tmpl".to_string()
+                ),
+                (
+                    String::new(),
+                    ArtifactType::ComposeFile,
+                    "docker-compose.yaml".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            set.describe()[0].content_hash.as_deref(),
+            Some(xcprobe_common::hash::sha256_str("FROM scratch\n").as_str())
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_content() {
+        let mut set = ArtifactSet::new();
+        set.add("app-0/Dockerfile", "RUN echo hi\n".to_string(), FILE_MODE)
+            .unwrap();
+        set.add("docker-compose.yaml", "services: [".to_string(), FILE_MODE)
+            .unwrap();
+        set.add("../escape", String::new(), FILE_MODE).unwrap();
+
+        let err = set.validate().unwrap_err().to_string();
+        assert!(err.contains("before FROM"));
+        assert!(err.contains("invalid YAML"));
+        assert!(err.contains("must be relative"));
+    }
+
+    #[test]
+    fn test_validate_dockerfile_continuations() {
+        let dockerfile = "# comment\nFROM debian\nHEALTHCHECK --interval=10s \\\n  CMD nc -z localhost 80 || exit 1\nCMD [\"app\"]\n";
+        assert!(validate_dockerfile(dockerfile).is_ok());
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_previous_output() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("app-0/pack/opt/app")).unwrap();
+        std::fs::write(dir.path().join("app-0/stale.txt"), "old").unwrap();
+        std::fs::write(dir.path().join("app-0/pack/opt/app/main.py"), "print()").unwrap();
+        std::fs::write(dir.path().join("packplan.json"), "{}").unwrap();
+
+        let mut set = ArtifactSet::new();
+        set.add(
+            "app-0/entrypoint.sh",
+            "#!/bin/bash\n".to_string(),
+            EXECUTABLE_MODE,
+        )
+        .unwrap();
+        set.write_atomic(dir.path()).unwrap();
+
+        assert!(dir.path().join("app-0/entrypoint.sh").exists());
+        assert!(!dir.path().join("app-0/stale.txt").exists());
+        // Pack output survives regeneration
+        assert!(dir.path().join("app-0/pack/opt/app/main.py").exists());
+        // Files not part of the set are left alone
+        assert!(dir.path().join("packplan.json").exists());
+        // No staging directory left behind
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                let name = e.file_name().to_string_lossy().into_owned();
+                name.starts_with(".xcprobe-staging") || name.starts_with(".xcprobe-previous")
+            })
+            .collect();
+        assert!(leftovers.is_empty());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join("app-0/entrypoint.sh"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, EXECUTABLE_MODE);
+        }
+    }
+
+    #[test]
+    fn test_keep_edited_files() {
+        let dir = tempdir().unwrap();
+        let generate = |dockerfile: &str, readme: &str| {
+            let mut set = ArtifactSet::new();
+            set.add("app-0/Dockerfile", dockerfile.to_string(), FILE_MODE)
+                .unwrap();
+            set.add("app-0/README.md", readme.to_string(), FILE_MODE)
+                .unwrap();
+            set
+        };
+        let first = generate("FROM debian\n", "# app\n");
+        first.write_atomic(dir.path()).unwrap();
+        let previous = first.describe();
+        assert_eq!(
+            first.existing_outputs(dir.path()),
+            vec![PathBuf::from("app-0")]
+        );
+
+        // The Dockerfile is edited and a file added; the README is not
+        std::fs::write(dir.path().join("app-0/Dockerfile"), "FROM ubuntu\n").unwrap();
+        std::fs::write(dir.path().join("app-0/notes.txt"), "todo\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("app-0/pack")).unwrap();
+        std::fs::write(dir.path().join("app-0/pack/app.jar"), [0xff, 0xfe]).unwrap();
+
+        let mut second = generate("FROM debian:12\n", "# app v2\n");
+        let kept = second.keep_edited(dir.path(), &previous).unwrap();
+        assert_eq!(
+            kept,
+            vec![
+                PathBuf::from("app-0/Dockerfile"),
+                PathBuf::from("app-0/notes.txt")
+            ]
+        );
+        second.write_atomic(dir.path()).unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("app-0/Dockerfile"), "FROM ubuntu\n");
+        assert_eq!(read("app-0/notes.txt"), "todo\n");
+        assert_eq!(read("app-0/README.md"), "# app v2\n");
+        assert!(dir.path().join("app-0/pack/app.jar").exists());
+    }
+
+    #[test]
+    fn test_generation_does_not_depend_on_jobs() {
+        let clusters: Vec<AppCluster> = (0..12)
+            .map(|n| AppCluster {
+                id: format!("app-{}", n),
+                name: format!("app{}", n),
+                app_type: "api".to_string(),
+                services: vec![ClusterService {
+                    name: format!("app{}.service", n),
+                    exec_start: Some(format!("/opt/app{}/bin/server", n)),
+                    user: Some("app".to_string()),
+                    ..Default::default()
+                }],
+                ports: vec![ClusterPort {
+                    port: 8000 + n,
+                    protocol: "tcp".to_string(),
+                    ..Default::default()
+                }],
+                base_image: Some("debian:12-slim".to_string()),
+                confidence: 0.9,
+                ..Default::default()
+            })
+            .collect();
+        let plan = xcprobe_bundle_schema::PackPlan {
+            clusters,
+            ..Default::default()
+        };
+
+        let generate = |jobs| {
+            let dir = tempdir().unwrap();
+            let options = crate::GenerateOptions {
+                jobs: Some(jobs),
+                ..Default::default()
+            };
+            let generation = crate::generate_artifacts(&plan, dir.path(), &options).unwrap();
+            let artifacts: Vec<(String, Option<String>)> = generation
+                .artifacts
+                .into_iter()
+                .map(|a| (a.path, a.content_hash))
+                .collect();
+            let warnings: Vec<String> = generation
+                .lint_warnings
+                .into_iter()
+                .map(|w| w.message)
+                .collect();
+            (artifacts, warnings)
+        };
+
+        let serial = generate(1);
+        assert_eq!(serial.0.len(), 12 * 4 + 3);
+        assert_eq!(generate(4), serial);
+    }
+
+    #[test]
+    fn test_mark_packed_files() {
+        let dir = tempdir().unwrap();
+        let file = |path: &str, kind: &str| AppFileSpec {
+            source_path: path.to_string(),
+            container_path: path.to_string(),
+            kind: kind.to_string(),
+            ..Default::default()
+        };
+        let mut plan = PackPlan {
+            source_bundle_id: "bundle-1".to_string(),
+            clusters: vec![AppCluster {
+                id: "app-0".to_string(),
+                name: "api".to_string(),
+                app_type: "api".to_string(),
+                app_files: vec![
+                    file("/opt/api", "working_directory"),
+                    file("/usr/local/bin/api", "binary"),
+                    file("/etc/api.yaml", "config"),
+                ],
+                confidence: 0.9,
+                ..Default::default()
+            }],
+            overall_confidence: 0.9,
+            ..Default::default()
+        };
+        assert_eq!(mark_packed_files(&mut plan, dir.path()).unwrap(), 0);
+
+        std::fs::create_dir_all(dir.path().join("app-0/pack/opt/api")).unwrap();
+        std::fs::write(dir.path().join("app-0/pack/opt/api/main.py"), "print()").unwrap();
+        std::fs::create_dir_all(dir.path().join("app-0/pack/usr/local/bin")).unwrap();
+        std::fs::write(dir.path().join("app-0/pack/usr/local/bin/api"), "").unwrap();
+        let packed = |source_path: &str, path: &str| {
+            serde_json::json!({
+                "cluster_id": "app-0", "source_path": source_path, "path": path,
+                "size": 7, "sha256": "abc", "mode": "0644"
+            })
+        };
+        std::fs::write(
+            dir.path().join(PACK_MANIFEST_FILE),
+            serde_json::json!({
+                "source_bundle_id": "bundle-1",
+                "files": [
+                    packed("/opt/api", "app-0/pack/opt/api/main.py"),
+                    // Listed, but its copy was removed
+                    packed("/etc/api.yaml", "app-0/pack/etc/api.yaml"),
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(mark_packed_files(&mut plan, dir.path()).unwrap(), 1);
+        let collected: Vec<bool> = plan.clusters[0]
+            .app_files
+            .iter()
+            .map(|f| f.collected)
+            .collect();
+        // The binary's copy is not in the manifest
+        assert_eq!(collected, vec![true, false, false]);
+    }
+}
@@ -0,0 +1,138 @@
+//! Docker build smoke test of generated artifacts.
+//!
+//! Runs `docker build` in each cluster directory, keeps the build logs under
+//! `build-logs/` in the output directory and records the outcome on the
+//! cluster's Dockerfile artifact in the pack plan.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+use tracing::{info, warn};
+use xcprobe_bundle_schema::{
+    AnalysisWarning, ArtifactType, BuildCheck, GeneratedArtifact, PackPlan,
+};
+
+/// Directory of the build logs, relative to the output directory.
+pub const BUILD_LOG_DIR: &str = "build-logs";
+
+/// Build every cluster image with `docker` (the docker CLI to run) and
+/// record the results in `plan`. Failed builds are added as warnings.
+pub fn check_builds(plan: &mut PackPlan, output_dir: &Path, docker: &str) -> Result<()> {
+    let log_dir = output_dir.join(BUILD_LOG_DIR);
+    std::fs::create_dir_all(&log_dir).context("Failed to create build log directory")?;
+
+    // Databases run their official image and have nothing to build
+    let cluster_ids: Vec<String> = plan
+        .clusters
+        .iter()
+        .filter(|c| c.database.is_none())
+        .map(|c| c.id.clone())
+        .collect();
+    for cluster_id in cluster_ids {
+        let image = format!("xcprobe/{}:build-check", cluster_id);
+        let log_path = format!("{}/{}.log", BUILD_LOG_DIR, cluster_id);
+
+        info!("Building {} ({})", cluster_id, image);
+        let started = Instant::now();
+        let output = Command::new(docker)
+            .args(["build", "--tag", &image, "."])
+            .current_dir(output_dir.join(&cluster_id))
+            .output()
+            .with_context(|| format!("Failed to run {} build", docker))?;
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let mut log = output.stdout;
+        log.extend_from_slice(&output.stderr);
+        std::fs::write(output_dir.join(&log_path), &log)
+            .with_context(|| format!("Failed to write {}", log_path))?;
+
+        let success = output.status.success();
+        if !success {
+            warn!("Build of {} failed, see {}", cluster_id, log_path);
+            plan.warnings.push(AnalysisWarning::new(
+                "docker_build_failed",
+                "error",
+                format!("docker build of {} failed, see {}", cluster_id, log_path),
+                vec![cluster_id.clone()],
+            ));
+        }
+
+        let build = BuildCheck {
+            success,
+            image,
+            log_path,
+            duration_ms,
+        };
+        dockerfile_artifact(plan, &cluster_id).build = Some(build);
+    }
+
+    Ok(())
+}
+
+/// The Dockerfile artifact entry of a cluster, added if missing.
+fn dockerfile_artifact<'a>(plan: &'a mut PackPlan, cluster_id: &str) -> &'a mut GeneratedArtifact {
+    let position = plan
+        .artifacts
+        .iter()
+        .position(|a| a.cluster_id == cluster_id && a.artifact_type == ArtifactType::Dockerfile);
+    let index = match position {
+        Some(index) => index,
+        None => {
+            plan.artifacts.push(GeneratedArtifact {
+                cluster_id: cluster_id.to_string(),
+                artifact_type: ArtifactType::Dockerfile,
+                path: format!("{}/Dockerfile", cluster_id),
+                description: None,
+                content_hash: None,
+                preserved: false,
+                build: None,
+            });
+            plan.artifacts.len() - 1
+        }
+    };
+    &mut plan.artifacts[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use xcprobe_bundle_schema::AppCluster;
+
+    fn plan(id: &str) -> PackPlan {
+        PackPlan {
+            clusters: vec![AppCluster {
+                id: id.to_string(),
+                name: id.to_string(),
+                app_type: "api".to_string(),
+                confidence: 0.9,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_builds_records_results() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("app-0")).unwrap();
+
+        // `true` and `false` stand in for a docker CLI that builds or fails
+        let mut ok = plan("app-0");
+        check_builds(&mut ok, dir.path(), "true").unwrap();
+        let build = ok.artifacts[0].build.as_ref().unwrap();
+        assert!(build.success);
+        assert_eq!(build.log_path, "build-logs/app-0.log");
+        assert!(dir.path().join("build-logs/app-0.log").exists());
+        assert!(ok.warnings.is_empty());
+
+        let mut failed = plan("app-0");
+        check_builds(&mut failed, dir.path(), "false").unwrap();
+        assert!(!failed.artifacts[0].build.as_ref().unwrap().success);
+        assert_eq!(failed.warnings[0].code, "docker_build_failed");
+
+        assert!(check_builds(&mut plan("app-0"), dir.path(), "/nonexistent/docker").is_err());
+    }
+}
@@ -1,25 +1,295 @@
 //! Application clustering logic.
 
+use crate::index::ManifestIndex;
 use crate::scoring::ProcessScore;
 use anyhow::Result;
 use std::collections::HashMap;
+use std::str::FromStr;
 use xcprobe_bundle_schema::{
-    AppCluster, Bundle, ClusterPort, ClusterProcess, ClusterService, ConfigFileSpec, Decision,
-    EnvVarSpec,
+    AppCluster, AppFileSpec, ClusterPort, ClusterProcess, ClusterService, ConfigFileSpec, Decision,
+    EnvVarSpec, PortInfo, ProcessInfo,
 };
+use xcprobe_redaction::Redactor;
+
+/// How aggressively clusters of the same application are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClusteringStrategy {
+    /// One cluster per service or standalone process tree.
+    PerService,
+    /// Merge clusters sharing a non-standard working directory.
+    #[default]
+    Merged,
+    /// Also merge clusters run by the same dedicated service user.
+    Aggressive,
+}
+
+impl FromStr for ClusteringStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "per-service" => Ok(ClusteringStrategy::PerService),
+            "merged" => Ok(ClusteringStrategy::Merged),
+            "aggressive" => Ok(ClusteringStrategy::Aggressive),
+            _ => Err(anyhow::anyhow!("Invalid clustering strategy: {}", s)),
+        }
+    }
+}
+
+/// Working directories shared by unrelated applications.
+const STANDARD_WORKDIRS: &[&str] = &[
+    "/",
+    "/root",
+    "/home",
+    "/tmp",
+    "/var/tmp",
+    "/opt",
+    "/srv",
+    "/usr",
+    "/usr/local",
+    "/var",
+    "/var/lib",
+    "/etc",
+];
+
+/// Confidence of a listener attributed to its process by the ports listing.
+const LISTING_PORT_CONFIDENCE: f64 = 0.95;
+
+/// Confidence of a listener attributed by matching its socket inode in
+/// `/proc/net` to a process's file descriptors.
+const PROC_NET_PORT_CONFIDENCE: f64 = 0.9;
+
+/// Confidence of a listener attributed by `lsof`.
+const LSOF_PORT_CONFIDENCE: f64 = 0.85;
+
+/// Users that run many unrelated services.
+const SHARED_USERS: &[&str] = &[
+    "root",
+    "nobody",
+    "daemon",
+    "www-data",
+    "apache",
+    "nginx",
+    "http",
+    "_www",
+    "system",
+    "localsystem",
+    "nt authority\\system",
+    "nt authority\\network service",
+    "nt authority\\local service",
+];
+
+/// Merge clusters that belong to the same application according to the
+/// strategy, then renumber cluster IDs. Batch clusters are never merged.
+pub fn merge_clusters(clusters: &mut Vec<AppCluster>, strategy: ClusteringStrategy, prefix: &str) {
+    if strategy == ClusteringStrategy::PerService {
+        return;
+    }
+
+    // Union clusters sharing a key; each group merges into its first cluster
+    let mut parent: Vec<usize> = (0..clusters.len()).collect();
+    let mut reasons: Vec<Option<String>> = vec![None; clusters.len()];
+    let mut owners: HashMap<String, usize> = HashMap::new();
+    for (index, cluster) in clusters.iter().enumerate() {
+        if cluster.schedule.is_some() {
+            continue;
+        }
+        let mut keys: Vec<(String, String)> = cluster_workdirs(cluster)
+            .into_iter()
+            .map(|wd| {
+                (
+                    format!("workdir:{}", wd),
+                    format!("working directory {}", wd),
+                )
+            })
+            .collect();
+        if strategy == ClusteringStrategy::Aggressive {
+            keys.extend(
+                cluster_users(cluster)
+                    .into_iter()
+                    .map(|user| (format!("user:{}", user), format!("service user {}", user))),
+            );
+        }
+        for (key, reason) in keys {
+            let Some(&owner) = owners.get(&key) else {
+                owners.insert(key, index);
+                continue;
+            };
+            let (a, b) = (find_root(&mut parent, owner), find_root(&mut parent, index));
+            if a == b {
+                continue;
+            }
+            let (root, merged) = (a.min(b), a.max(b));
+            parent[merged] = root;
+            // The groups may be linked by clusters already merged into them
+            reasons[merged] = Some(if a == owner && b == index {
+                format!("Both clusters share {}", reason)
+            } else {
+                format!(
+                    "Clusters {} and {} share {}",
+                    clusters[owner].name, cluster.name, reason
+                )
+            });
+        }
+    }
+
+    let merged: Vec<AppCluster> = std::mem::take(clusters);
+    let mut index_map: HashMap<usize, usize> = HashMap::new();
+    for (index, cluster) in merged.into_iter().enumerate() {
+        let root = find_root(&mut parent, index);
+        if root == index {
+            index_map.insert(index, clusters.len());
+            clusters.push(cluster);
+        } else {
+            let into = &mut clusters[index_map[&root]];
+            into.decisions.push(Decision::new(
+                format!("Merge cluster {} into {}", cluster.name, into.name),
+                reasons[index].take().unwrap_or_default(),
+                cluster.evidence_refs.clone(),
+                0.75,
+            ));
+            absorb_cluster(into, cluster);
+        }
+    }
+
+    renumber_clusters(clusters, prefix);
+}
+
+/// Root of the union-find group of `index`, compressing the path to it.
+fn find_root(parent: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut node = index;
+    while parent[node] != root {
+        node = std::mem::replace(&mut parent[node], root);
+    }
+    root
+}
+
+/// Give clusters contiguous `<prefix>-<n>` IDs after merging or removal.
+pub(crate) fn renumber_clusters(clusters: &mut [AppCluster], prefix: &str) {
+    for (n, cluster) in clusters.iter_mut().enumerate() {
+        cluster.id = format!("{}-{}", prefix, n);
+    }
+}
+
+/// Non-standard working directories of a cluster's services and processes.
+fn cluster_workdirs(cluster: &AppCluster) -> Vec<String> {
+    let mut workdirs: Vec<String> = cluster
+        .services
+        .iter()
+        .filter_map(|s| s.working_directory.as_deref())
+        .chain(
+            cluster
+                .processes
+                .iter()
+                .filter_map(|p| p.working_directory.as_deref()),
+        )
+        .filter_map(app_workdir)
+        .collect();
+    workdirs.dedup();
+    workdirs
+}
+
+/// A working directory without its trailing separator, unless it is a
+/// standard or home directory shared by unrelated programs.
+pub(crate) fn app_workdir(wd: &str) -> Option<String> {
+    let wd = match wd.trim_end_matches(['/', '\\']) {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    (!STANDARD_WORKDIRS.contains(&wd) && !is_home_directory(wd)).then(|| wd.to_string())
+}
+
+fn is_home_directory(path: &str) -> bool {
+    path.strip_prefix("/home/")
+        .is_some_and(|rest| !rest.is_empty() && !rest.contains('/'))
+}
+
+/// Dedicated (non-shared) users running a cluster's services and processes.
+fn cluster_users(cluster: &AppCluster) -> Vec<String> {
+    let mut users: Vec<String> = cluster
+        .services
+        .iter()
+        .filter_map(|s| s.user.as_deref())
+        .chain(cluster.processes.iter().map(|p| p.user.as_str()))
+        .filter_map(image_user)
+        .filter(|u| !u.is_empty() && !SHARED_USERS.contains(&u.to_lowercase().as_str()))
+        .map(str::to_string)
+        .collect();
+    users.dedup();
+    users
+}
+
+/// User of the image for the account a service runs as: `None` for root
+/// and the Windows built-in accounts (`LocalSystem`, `NT AUTHORITY\...`,
+/// `NT SERVICE\...`), the bare name of other Windows accounts
+/// (`CORP\svc_app`, `.\svc_app`, `svc_app@corp.example`).
+pub(crate) fn image_user(account: &str) -> Option<&str> {
+    let lower = account.to_lowercase();
+    if lower == "root"
+        || lower == "localsystem"
+        || lower.starts_with("nt authority\\")
+        || lower.starts_with("nt service\\")
+    {
+        return None;
+    }
+    let name = account.rsplit('\\').next().unwrap_or(account);
+    Some(name.split('@').next().unwrap_or(name))
+}
+
+/// Move the members and decisions of `other` into `cluster`.
+pub(crate) fn absorb_cluster(cluster: &mut AppCluster, other: AppCluster) {
+    if cluster.app_type == "unknown" {
+        cluster.app_type = other.app_type;
+    }
+    cluster.processes.extend(other.processes);
+    cluster.services.extend(other.services);
+    for port in other.ports {
+        if !cluster.ports.iter().any(|p| p.port == port.port) {
+            cluster.ports.push(port);
+        }
+    }
+    for env_var in other.env_vars {
+        if !cluster.env_vars.iter().any(|e| e.name == env_var.name) {
+            cluster.env_vars.push(env_var);
+        }
+    }
+    for config in other.config_files {
+        if !cluster
+            .config_files
+            .iter()
+            .any(|c| c.source_path == config.source_path)
+        {
+            cluster.config_files.push(config);
+        }
+    }
+    cluster.log_paths.extend(other.log_paths);
+    for evidence_ref in other.evidence_refs {
+        if !cluster.evidence_refs.contains(&evidence_ref) {
+            cluster.evidence_refs.push(evidence_ref);
+        }
+    }
+    cluster.decisions.extend(other.decisions);
+}
 
 /// Cluster processes and services into logical applications.
 pub fn cluster_applications(
-    bundle: &Bundle,
+    index: &ManifestIndex<'_>,
     scores: &HashMap<u32, ProcessScore>,
     prefix: &str,
 ) -> Result<Vec<AppCluster>> {
+    let manifest = index.manifest;
     let mut clusters = Vec::new();
     let mut assigned_services: std::collections::HashSet<String> = std::collections::HashSet::new();
+    // First cluster of each clustered process
+    let mut pid_clusters: HashMap<u32, usize> = HashMap::new();
     let mut cluster_id = 0;
 
     // First, cluster based on services (most reliable grouping)
-    for service in &bundle.manifest.services {
+    for service in &manifest.services {
         // Skip if already assigned or low-priority system service
         if assigned_services.contains(&service.name) {
             continue;
@@ -53,16 +323,29 @@ pub fn cluster_applications(
             id: format!("{}-{}", prefix, cluster_id),
             name: cluster_name.clone(),
             description: service.description.clone(),
-            app_type: detect_app_type(service, bundle),
+            app_type: detect_app_type(service, index),
             processes: Vec::new(),
             services: Vec::new(),
             ports: Vec::new(),
             env_vars: Vec::new(),
             config_files: Vec::new(),
+            app_files: Vec::new(),
             log_paths: Vec::new(),
             depends_on: Vec::new(),
             external_deps: Vec::new(),
             readiness: None,
+            runtime: None,
+            packages: Vec::new(),
+            package_files: Vec::new(),
+            system_packages: Vec::new(),
+            base_image: None,
+            schedule: None,
+            volumes: Vec::new(),
+            java: None,
+            replicas: None,
+            resources: None,
+            database: None,
+            programs: Vec::new(),
             confidence: 0.0,
             evidence_refs: Vec::new(),
             decisions: Vec::new(),
@@ -74,7 +357,7 @@ pub fn cluster_applications(
             exec_start: service.exec_start.clone(),
             user: service.user.clone(),
             working_directory: service.working_directory.clone(),
-            environment: service.environment.clone(),
+            environment: service.environment.clone().into_iter().collect(),
             environment_files: service.environment_files.clone(),
             evidence_ref: service.evidence_ref.clone(),
         });
@@ -94,41 +377,35 @@ pub fn cluster_applications(
 
         // Find associated process
         if let Some(main_pid) = service.main_pid {
-            if let Some(proc) = bundle.manifest.processes.iter().find(|p| p.pid == main_pid) {
-                cluster.processes.push(ClusterProcess {
-                    pid: proc.pid,
-                    command: proc.command.clone(),
-                    args: proc.args.clone(),
-                    user: proc.user.clone(),
-                    working_directory: proc.working_directory.clone(),
-                    evidence_ref: proc.evidence_ref.clone(),
-                });
+            if let Some(proc) = index.process(main_pid) {
+                cluster.processes.push(cluster_process(proc));
+                pid_clusters.entry(proc.pid).or_insert(clusters.len());
             }
         }
 
         // Find associated ports
         if let Some(main_pid) = service.main_pid {
-            for port in &bundle.manifest.ports {
-                if port.pid == Some(main_pid) {
-                    cluster.ports.push(ClusterPort {
-                        port: port.local_port,
-                        protocol: port.protocol.clone(),
-                        purpose: None,
-                        evidence_ref: port.evidence_ref.clone(),
-                    });
+            for port in index.ports(main_pid) {
+                cluster.ports.push(ClusterPort {
+                    port: port.local_port,
+                    protocol: port.protocol.clone(),
+                    purpose: None,
+                    evidence_ref: port.evidence_ref.clone(),
+                    host_port: None,
+                });
 
-                    cluster.decisions.push(Decision::new(
-                        format!("Service listens on port {}", port.local_port),
-                        "Port found via ss/netstat associated with service PID",
-                        port.evidence_ref.iter().cloned().collect(),
-                        0.95,
-                    ));
-                }
+                cluster.decisions.push(port_decision(
+                    port,
+                    format!("Service listens on port {}", port.local_port),
+                ));
             }
+            add_unix_sockets(&mut cluster, index, main_pid);
         }
 
-        // Extract environment variables from service
-        for name in service.environment.keys() {
+        // Extract environment variables from service, in name order
+        let mut names: Vec<&String> = service.environment.keys().collect();
+        names.sort();
+        for name in names {
             let sensitive = xcprobe_redaction::patterns::is_sensitive_key(name);
             cluster.env_vars.push(EnvVarSpec {
                 name: name.clone(),
@@ -142,17 +419,13 @@ pub fn cluster_applications(
 
         // Add environment files as configs
         for env_file in &service.environment_files {
-            if let Some(file_info) = bundle
-                .manifest
-                .environment_files
-                .iter()
-                .find(|f| &f.path == env_file)
-            {
+            if let Some(file_info) = index.environment_file(env_file) {
                 cluster.config_files.push(ConfigFileSpec {
                     source_path: env_file.clone(),
                     container_path: env_file.clone(),
                     templated: true,
                     template_vars: file_info.variable_names.clone(),
+                    content: None,
                     evidence_ref: file_info.evidence_ref.clone(),
                 });
 
@@ -173,16 +446,15 @@ pub fn cluster_applications(
 
         // Add config files from working directory
         if let Some(ref wd) = service.working_directory {
-            for config in &bundle.manifest.config_files {
-                if config.path.starts_with(wd) {
-                    cluster.config_files.push(ConfigFileSpec {
-                        source_path: config.path.clone(),
-                        container_path: config.path.clone(),
-                        templated: false,
-                        template_vars: vec![],
-                        evidence_ref: config.attachment_ref.clone(),
-                    });
-                }
+            for config in index.configs_under(wd) {
+                cluster.config_files.push(ConfigFileSpec {
+                    source_path: config.path.clone(),
+                    container_path: config.path.clone(),
+                    templated: false,
+                    template_vars: vec![],
+                    content: None,
+                    evidence_ref: config.attachment_ref.clone(),
+                });
             }
         }
 
@@ -191,48 +463,85 @@ pub fn cluster_applications(
         clusters.push(cluster);
     }
 
-    // Second pass: cluster standalone processes with high scores
-    for (pid, score) in scores {
-        if !score.is_business_process {
-            continue;
-        }
+    // Second pass: cluster standalone processes with high scores. Parents
+    // come before their children so that workers join their master's cluster.
+    let mut business: Vec<(u32, &ProcessScore, Vec<ProcessParent>)> = scores
+        .iter()
+        .filter(|(_, score)| score.is_business_process)
+        .map(|(pid, score)| (*pid, score, process_ancestry(*pid, index)))
+        .collect();
+    business.sort_by_key(|(pid, _, ancestry)| (ancestry.len(), *pid));
+    let mut tree_clusters: HashMap<u32, usize> = HashMap::new();
+
+    for (pid, score, ancestry) in &business {
+        let pid = *pid;
 
         // Check if already assigned via service
-        let already_assigned = clusters
-            .iter()
-            .any(|c| c.processes.iter().any(|p| p.pid == *pid));
-        if already_assigned {
+        if pid_clusters.contains_key(&pid) {
             continue;
         }
 
         // Find the process
-        let process = match bundle.manifest.processes.iter().find(|p| p.pid == *pid) {
-            Some(p) => p,
-            None => continue,
+        let Some(process) = index.process(pid) else {
+            continue;
         };
 
+        // Group with the cluster of the closest clustered ancestor, or with
+        // the cluster of a sibling under the same process tree root
+        let root = ancestry.last().map(|a| a.pid).unwrap_or(pid);
+        let parent_cluster = ancestry
+            .iter()
+            .find_map(|a| pid_clusters.get(&a.pid).copied())
+            .or_else(|| tree_clusters.get(&root).copied());
+        if let Some(position) = parent_cluster {
+            let parent = &ancestry[0];
+            let cluster = &mut clusters[position];
+            cluster.processes.push(cluster_process(process));
+            pid_clusters.insert(pid, position);
+            cluster
+                .evidence_refs
+                .extend(process.evidence_ref.iter().cloned());
+            cluster.decisions.push(Decision::new(
+                format!(
+                    "Group process {} (PID {}) with parent PID {}",
+                    process.command, pid, parent.pid
+                ),
+                parent.reason.clone(),
+                process.evidence_ref.iter().cloned().collect(),
+                0.85,
+            ));
+            add_process_ports(cluster, index, pid);
+            continue;
+        }
+
         // Create a cluster for this standalone process
         let mut cluster = AppCluster {
             id: format!("{}-{}", prefix, cluster_id),
             name: score.name.clone(),
             description: Some(format!("Standalone process: {}", process.full_cmdline)),
             app_type: "unknown".to_string(),
-            processes: vec![ClusterProcess {
-                pid: process.pid,
-                command: process.command.clone(),
-                args: process.args.clone(),
-                user: process.user.clone(),
-                working_directory: process.working_directory.clone(),
-                evidence_ref: process.evidence_ref.clone(),
-            }],
+            processes: vec![cluster_process(process)],
             services: Vec::new(),
             ports: Vec::new(),
             env_vars: Vec::new(),
             config_files: Vec::new(),
+            app_files: Vec::new(),
             log_paths: Vec::new(),
             depends_on: Vec::new(),
             external_deps: Vec::new(),
             readiness: None,
+            runtime: None,
+            packages: Vec::new(),
+            package_files: Vec::new(),
+            system_packages: Vec::new(),
+            base_image: None,
+            schedule: None,
+            volumes: Vec::new(),
+            java: None,
+            replicas: None,
+            resources: None,
+            database: None,
+            programs: Vec::new(),
             confidence: 0.0,
             evidence_refs: process.evidence_ref.iter().cloned().collect(),
             decisions: vec![Decision::new(
@@ -244,17 +553,10 @@ pub fn cluster_applications(
         };
 
         // Find associated ports
-        for port in &bundle.manifest.ports {
-            if port.pid == Some(*pid) {
-                cluster.ports.push(ClusterPort {
-                    port: port.local_port,
-                    protocol: port.protocol.clone(),
-                    purpose: None,
-                    evidence_ref: port.evidence_ref.clone(),
-                });
-            }
-        }
+        add_process_ports(&mut cluster, index, pid);
 
+        pid_clusters.insert(pid, clusters.len());
+        tree_clusters.insert(root, clusters.len());
         cluster_id += 1;
         clusters.push(cluster);
     }
@@ -267,8 +569,7 @@ pub fn cluster_applications(
         .flat_map(|c| c.ports.iter().map(|p| p.port))
         .collect();
 
-    let unmatched_ports: Vec<_> = bundle
-        .manifest
+    let unmatched_ports: Vec<_> = manifest
         .ports
         .iter()
         .filter(|p| !assigned_ports.contains(&p.local_port))
@@ -293,6 +594,7 @@ pub fn cluster_applications(
                     protocol: port.protocol.clone(),
                     purpose: None,
                     evidence_ref: port.evidence_ref.clone(),
+                    host_port: None,
                 });
                 matched = true;
             }
@@ -311,15 +613,526 @@ pub fn cluster_applications(
                 protocol: port.protocol.clone(),
                 purpose: None,
                 evidence_ref: port.evidence_ref.clone(),
+                host_port: None,
             });
         }
     }
 
+    // Instances of templated systemd units run as replicas of one cluster
+    if collapse_template_instances(&mut clusters) {
+        renumber_clusters(&mut clusters, prefix);
+        cluster_id = clusters.len();
+    }
+
+    // Fourth pass: business cron jobs become batch clusters
+    let mut seen_jobs = std::collections::HashSet::new();
+    for task in &manifest.scheduled_tasks {
+        let (Some(command), Some(schedule)) = (&task.command, &task.schedule) else {
+            continue;
+        };
+        if task.task_type != "cron"
+            || schedule == "@reboot"
+            || is_system_cron_job(command)
+            || !seen_jobs.insert(command.clone())
+        {
+            continue;
+        }
+
+        clusters.push(AppCluster {
+            id: format!("{}-{}", prefix, cluster_id),
+            name: task.name.trim_end_matches(".sh").replace(['.', '_'], "-"),
+            description: Some(format!("Cron job scheduled at {}", schedule)),
+            app_type: "batch".to_string(),
+            processes: Vec::new(),
+            services: vec![ClusterService {
+                name: task.name.clone(),
+                exec_start: Some(command.clone()),
+                user: task.user.clone(),
+                working_directory: None,
+                environment: Default::default(),
+                environment_files: Vec::new(),
+                evidence_ref: task.evidence_ref.clone(),
+            }],
+            ports: Vec::new(),
+            env_vars: Vec::new(),
+            config_files: Vec::new(),
+            app_files: Vec::new(),
+            log_paths: Vec::new(),
+            depends_on: Vec::new(),
+            external_deps: Vec::new(),
+            readiness: None,
+            runtime: None,
+            packages: Vec::new(),
+            package_files: Vec::new(),
+            system_packages: Vec::new(),
+            base_image: None,
+            schedule: Some(schedule.clone()),
+            volumes: Vec::new(),
+            java: None,
+            replicas: None,
+            resources: None,
+            database: None,
+            programs: Vec::new(),
+            confidence: 0.0,
+            evidence_refs: task.evidence_ref.iter().cloned().collect(),
+            decisions: vec![Decision::new(
+                format!("Create batch cluster for cron job {}", task.name),
+                format!("Scheduled job runs application command: {}", command),
+                task.evidence_ref.iter().cloned().collect(),
+                0.7,
+            )],
+        });
+        cluster_id += 1;
+    }
+
+    attach_open_files(index, &mut clusters);
+
     Ok(clusters)
 }
 
+/// Split a templated unit instance name (`worker@2.service`) into the
+/// template (`worker`) and the instance (`2`).
+/// Give each cluster the configuration and log files its processes hold
+/// open, wherever they are.
+fn attach_open_files(index: &ManifestIndex<'_>, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        let pids: Vec<u32> = cluster
+            .processes
+            .iter()
+            .map(|p| p.pid)
+            .chain(
+                cluster
+                    .services
+                    .iter()
+                    .filter_map(|s| index.service(&s.name).and_then(|m| m.main_pid)),
+            )
+            .collect();
+
+        for config in index.configs_opened_by(&pids) {
+            if cluster
+                .config_files
+                .iter()
+                .any(|c| c.source_path == config.path)
+            {
+                continue;
+            }
+            cluster.config_files.push(ConfigFileSpec {
+                source_path: config.path.clone(),
+                container_path: config.path.clone(),
+                templated: false,
+                template_vars: vec![],
+                content: None,
+                evidence_ref: config.attachment_ref.clone(),
+            });
+            cluster.decisions.push(Decision::new(
+                format!("Include config file {}", config.path),
+                "File held open by a process of the application".to_string(),
+                config.discovery_evidence_ref.iter().cloned().collect(),
+                0.8,
+            ));
+        }
+
+        for log in index.logs_opened_by(&pids) {
+            if !cluster.log_paths.contains(&log.path) {
+                cluster.log_paths.push(log.path.clone());
+            }
+        }
+    }
+}
+
+fn template_instance(service_name: &str) -> Option<(&str, &str)> {
+    let (template, instance) = service_name
+        .strip_suffix(".service")
+        .unwrap_or(service_name)
+        .split_once('@')?;
+    (!template.is_empty() && !instance.is_empty()).then_some((template, instance))
+}
+
+/// Collapse the clusters of instances of the same templated unit into the
+/// first instance's cluster, with one replica per instance. Returns whether
+/// any cluster was collapsed.
+fn collapse_template_instances(clusters: &mut Vec<AppCluster>) -> bool {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for (index, cluster) in clusters.iter().enumerate() {
+        let [service] = cluster.services.as_slice() else {
+            continue;
+        };
+        let Some((template, _)) = template_instance(&service.name) else {
+            continue;
+        };
+        match groups.iter_mut().find(|(t, _)| t == template) {
+            Some((_, members)) => members.push(index),
+            None => groups.push((template.to_string(), vec![index])),
+        }
+    }
+    groups.retain(|(_, members)| members.len() > 1);
+    if groups.is_empty() {
+        return false;
+    }
+
+    let mut slots: Vec<Option<AppCluster>> =
+        std::mem::take(clusters).into_iter().map(Some).collect();
+    for (template, members) in &groups {
+        let mut instances: Vec<AppCluster> =
+            members.iter().filter_map(|&i| slots[i].take()).collect();
+        let instance_names: Vec<String> = instances
+            .iter()
+            .map(|c| c.services[0].name.clone())
+            .collect();
+        let instance_ports: Vec<String> = instances
+            .iter()
+            .flat_map(|c| c.ports.iter().map(|p| p.port.to_string()))
+            .collect();
+        let evidence_refs: Vec<String> = instances
+            .iter()
+            .filter_map(|c| c.services[0].evidence_ref.clone())
+            .collect();
+
+        let mut cluster = instances.remove(0);
+        let mut reason = format!(
+            "{} instances of unit template {}@.service",
+            instance_names.len(),
+            template
+        );
+        if instance_ports.len() > cluster.ports.len() {
+            reason.push_str(&format!(
+                "; instances bind ports {}, published as a host port range per replica",
+                instance_ports.join(", ")
+            ));
+        }
+        for mut other in instances {
+            // The container runs the first instance's command and ports
+            other.ports.clear();
+            absorb_cluster(&mut cluster, other);
+        }
+        cluster.name = template.replace(['.', '_'], "-");
+        cluster.replicas = Some(members.len() as u32);
+        cluster.decisions.push(Decision::new(
+            format!(
+                "Collapse {} into one cluster with {} replicas",
+                instance_names.join(", "),
+                members.len()
+            ),
+            reason,
+            evidence_refs,
+            0.9,
+        ));
+        slots[members[0]] = Some(cluster);
+    }
+
+    *clusters = slots.into_iter().flatten().collect();
+    true
+}
+
+/// A parent process grouped with its child, and why.
+struct ProcessParent {
+    pid: u32,
+    reason: String,
+}
+
+/// The chain of parents a process is grouped with, closest first. A parent
+/// is followed while it shares the child's working directory or runs the
+/// same executable (forked workers such as gunicorn or nginx workers).
+fn process_ancestry(pid: u32, index: &ManifestIndex<'_>) -> Vec<ProcessParent> {
+    let mut ancestry: Vec<ProcessParent> = Vec::new();
+    let mut current = pid;
+    while let Some(child) = index.process(current) {
+        let Some(parent) = index.process(child.ppid).filter(|_| child.ppid != 0) else {
+            break;
+        };
+        if parent.pid == pid || ancestry.iter().any(|a| a.pid == parent.pid) {
+            break;
+        }
+        let reason = match (&child.working_directory, &parent.working_directory) {
+            (Some(wd), Some(parent_wd)) if wd == parent_wd => {
+                format!(
+                    "Child of PID {} sharing working directory {}",
+                    parent.pid, wd
+                )
+            }
+            _ if child.command == parent.command => format!(
+                "Child of PID {} running the same executable {}",
+                parent.pid, parent.command
+            ),
+            _ => break,
+        };
+        ancestry.push(ProcessParent {
+            pid: parent.pid,
+            reason,
+        });
+        current = parent.pid;
+    }
+    ancestry
+}
+
+fn cluster_process(process: &ProcessInfo) -> ClusterProcess {
+    ClusterProcess {
+        pid: process.pid,
+        command: process.command.clone(),
+        args: process.args.clone(),
+        user: process.user.clone(),
+        working_directory: process.working_directory.clone(),
+        evidence_ref: process.evidence_ref.clone(),
+    }
+}
+
+/// Add the ports a process listens on to its cluster, with a decision for
+/// those the ports listing did not attribute to the process itself.
+fn add_process_ports(cluster: &mut AppCluster, index: &ManifestIndex<'_>, pid: u32) {
+    for port in index.ports(pid) {
+        if !cluster.ports.iter().any(|p| p.port == port.local_port) {
+            cluster.ports.push(ClusterPort {
+                port: port.local_port,
+                protocol: port.protocol.clone(),
+                purpose: None,
+                evidence_ref: port.evidence_ref.clone(),
+                host_port: None,
+            });
+            if port.pid_source.is_some() {
+                cluster.decisions.push(port_decision(
+                    port,
+                    format!("Process {} listens on port {}", pid, port.local_port),
+                ));
+            }
+        }
+    }
+    add_unix_sockets(cluster, index, pid);
+}
+
+/// Record the Unix domain sockets a process listens on, through which other
+/// clusters may depend on its cluster.
+fn add_unix_sockets(cluster: &mut AppCluster, index: &ManifestIndex<'_>, pid: u32) {
+    for socket in index.unix_sockets(pid) {
+        let Some(path) = socket.socket_path.as_deref() else {
+            continue;
+        };
+        let decision = format!("Listens on Unix socket {}", path);
+        if cluster.decisions.iter().any(|d| d.decision == decision) {
+            continue;
+        }
+        cluster.decisions.push(Decision::new(
+            decision,
+            format!("Socket found via ss associated with PID {}", pid),
+            socket.evidence_ref.iter().cloned().collect(),
+            LISTING_PORT_CONFIDENCE,
+        ));
+    }
+}
+
+/// Decision attributing a listener to its process, noting how the process
+/// was found and how much that method is trusted.
+fn port_decision(port: &PortInfo, decision: String) -> Decision {
+    let pid = port.pid.unwrap_or_default();
+    let (reason, confidence) = match port.pid_source.as_deref() {
+        Some("proc_net") => (
+            format!(
+                "The ports listing gave no process; the socket inode in /proc/net \
+                 was found among the file descriptors of PID {}",
+                pid
+            ),
+            PROC_NET_PORT_CONFIDENCE,
+        ),
+        Some("lsof") => (
+            format!(
+                "The ports listing gave no process; lsof listed PID {} holding the socket",
+                pid
+            ),
+            LSOF_PORT_CONFIDENCE,
+        ),
+        _ => (
+            "Port found via ss/netstat associated with service PID".to_string(),
+            LISTING_PORT_CONFIDENCE,
+        ),
+    };
+    let evidence_refs = port
+        .evidence_ref
+        .iter()
+        .chain(port.pid_evidence_ref.iter())
+        .cloned()
+        .collect();
+    Decision::new(decision, reason, evidence_refs, confidence)
+}
+
+/// Executables run by distribution-provided cron jobs.
+const SYSTEM_CRON_COMMANDS: &[&str] = &[
+    "run-parts",
+    "anacron",
+    "logrotate",
+    "certbot",
+    "e2scrub_all",
+    "popularity-contest",
+    "debian-sa1",
+    "sa1",
+    "sa2",
+    "updatedb",
+    "mandb",
+    "sessionclean",
+    "ntpdate",
+    "apt-compat",
+    "dpkg",
+];
+
+/// Whether a cron command runs system maintenance rather than an application.
+fn is_system_cron_job(command: &str) -> bool {
+    command
+        .split(|c: char| c.is_whitespace() || "&|;()".contains(c))
+        .filter_map(|token| token.rsplit('/').next())
+        .any(|name| SYSTEM_CRON_COMMANDS.contains(&name))
+}
+
+/// Directories whose executables are provided by the base image.
+const SYSTEM_BIN_PREFIXES: &[&str] = &[
+    "/usr/bin/",
+    "/bin/",
+    "/usr/sbin/",
+    "/sbin/",
+    "/usr/lib/",
+    "/lib/",
+];
+
+/// Flag environment variables holding secrets under the redaction rules,
+/// which may add secret key names or allowlist built-in ones.
+pub fn mark_sensitive_env_vars(clusters: &mut [AppCluster], redactor: &Redactor) {
+    for env_var in clusters.iter_mut().flat_map(|c| c.env_vars.iter_mut()) {
+        env_var.sensitive = redactor.is_sensitive_key(&env_var.name);
+    }
+}
+
+/// Plan the application files to copy into the image: the working directory,
+/// application binaries outside of it, and static configuration files.
+/// Templated configuration files are rendered at startup instead. Paths
+/// whose owner and mode were collected keep them in the image.
+pub fn plan_app_files(index: &ManifestIndex<'_>, cluster: &mut AppCluster) {
+    let owner = cluster
+        .services
+        .first()
+        .and_then(|s| s.user.as_deref())
+        .and_then(image_user)
+        .map(str::to_string);
+    let workdir = cluster
+        .services
+        .first()
+        .and_then(|s| s.working_directory.clone())
+        .filter(|wd| wd != "/");
+    let within_workdir = |path: &str| {
+        workdir
+            .as_deref()
+            .map(|wd| path.starts_with(&format!("{}/", wd.trim_end_matches('/'))))
+            .unwrap_or(false)
+    };
+
+    let mut app_files: Vec<AppFileSpec> = Vec::new();
+    let mut add = |source_path: &str, kind: &str, evidence_ref: Option<String>| {
+        if app_files.iter().any(|f| f.source_path == source_path) {
+            return;
+        }
+        let source = index.file_stat(source_path);
+        app_files.push(AppFileSpec {
+            source_path: source_path.to_string(),
+            container_path: source_path.to_string(),
+            kind: kind.to_string(),
+            owner: match source.and_then(|f| f.owner.as_deref()) {
+                Some(source_owner) => copy_owner(source_owner, owner.as_deref()),
+                None => owner.clone(),
+            },
+            mode: source.and_then(|f| f.permissions.clone()),
+            size: source
+                .filter(|_| !matches!(kind, "working_directory" | "directory"))
+                .map(|f| f.size_bytes),
+            collected: false,
+            evidence_ref,
+        });
+    };
+
+    if let Some(ref wd) = workdir {
+        let evidence_ref = cluster
+            .services
+            .first()
+            .and_then(|s| s.evidence_ref.clone());
+        add(wd, "working_directory", evidence_ref);
+    }
+
+    let executables = cluster
+        .services
+        .iter()
+        .filter_map(|s| {
+            let exe = s.exec_start.as_deref()?.split_whitespace().next()?;
+            Some((exe, s.evidence_ref.clone()))
+        })
+        .chain(
+            cluster
+                .processes
+                .iter()
+                .map(|p| (p.command.as_str(), p.evidence_ref.clone())),
+        );
+    for (exe, evidence_ref) in executables {
+        // Executables under system prefixes come with the image, unless no
+        // package owns them
+        let custom = index
+            .package_owner(exe)
+            .is_some_and(|owner| owner.packages.is_empty());
+        let is_system = SYSTEM_BIN_PREFIXES.iter().any(|p| exe.starts_with(p)) && !custom;
+        if exe.starts_with('/') && !is_system && !within_workdir(exe) {
+            add(exe, "binary", evidence_ref);
+        }
+    }
+
+    for config in &cluster.config_files {
+        if !config.templated && !within_workdir(&config.source_path) {
+            add(&config.source_path, "config", config.evidence_ref.clone());
+        }
+    }
+
+    if let Some(ref java) = cluster.java {
+        let jars = java.main_jar.iter().map(|jar| (jar.as_str(), "jar"));
+        // `lib/*` classpath wildcards name every jar of the directory
+        let classpath = java
+            .classpath
+            .iter()
+            .map(|entry| match entry.strip_suffix("/*") {
+                Some(dir) => (dir, "directory"),
+                None if entry.ends_with(".jar") => (entry.as_str(), "jar"),
+                None => (entry.as_str(), "directory"),
+            });
+        for (path, kind) in jars.chain(classpath) {
+            let is_system = SYSTEM_BIN_PREFIXES.iter().any(|p| path.starts_with(p));
+            if path.starts_with('/') && !is_system && !within_workdir(path) {
+                add(path, kind, java.evidence_ref.clone());
+            }
+        }
+    }
+
+    cluster.app_files = app_files;
+}
+
+/// Owner of a copied path from its owner on the source system (`user:group`).
+/// Only root and the service user exist in the image, so files of other
+/// accounts go to the service user.
+fn copy_owner(source_owner: &str, service_user: Option<&str>) -> Option<String> {
+    let (user, group) = source_owner
+        .split_once(':')
+        .unwrap_or((source_owner, source_owner));
+    let known_group = |g: &str| g == "root" || Some(g) == service_user;
+    match service_user {
+        _ if user == "root" => {
+            (group != "root" && known_group(group)).then(|| format!("root:{}", group))
+        }
+        Some(service_user) if user == service_user => {
+            Some(if group == user || !known_group(group) {
+                user.to_string()
+            } else {
+                format!("{}:{}", user, group)
+            })
+        }
+        _ => service_user.map(str::to_string),
+    }
+}
+
 /// Detect the type of application from service/process characteristics.
-fn detect_app_type(service: &xcprobe_bundle_schema::ServiceInfo, bundle: &Bundle) -> String {
+fn detect_app_type(
+    service: &xcprobe_bundle_schema::ServiceInfo,
+    index: &ManifestIndex<'_>,
+) -> String {
     let name_lower = service.name.to_lowercase();
     let exec_lower = service
         .exec_start
@@ -358,20 +1171,562 @@ fn detect_app_type(service: &xcprobe_bundle_schema::ServiceInfo, bundle: &Bundle
 
     // Check ports for hints
     if let Some(main_pid) = service.main_pid {
-        for port in &bundle.manifest.ports {
-            if port.pid == Some(main_pid) {
-                match port.local_port {
-                    80 | 443 | 8080 | 8443 => return "web".to_string(),
-                    3000 | 5000 | 8000 => return "api".to_string(),
-                    5432 => return "database".to_string(),
-                    3306 => return "database".to_string(),
-                    6379 => return "cache".to_string(),
-                    5672 | 15672 => return "messagequeue".to_string(),
-                    _ => {}
-                }
+        for port in index.ports(main_pid) {
+            match port.local_port {
+                80 | 443 | 8080 | 8443 => return "web".to_string(),
+                3000 | 5000 | 8000 => return "api".to_string(),
+                5432 => return "database".to_string(),
+                3306 => return "database".to_string(),
+                6379 => return "cache".to_string(),
+                5672 | 15672 => return "messagequeue".to_string(),
+                _ => {}
             }
         }
     }
 
     "unknown".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::Bundle;
+
+    #[test]
+    fn test_plan_app_files() {
+        let mut cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: "app.service".to_string(),
+                exec_start: Some("/usr/local/bin/app --config /etc/app.yaml".to_string()),
+                user: Some("app".to_string()),
+                working_directory: Some("/opt/app".to_string()),
+                evidence_ref: Some("evidence/services_1.txt".to_string()),
+                ..Default::default()
+            }],
+            config_files: ["/etc/app.yaml", "/opt/app/settings.ini"]
+                .iter()
+                .map(|path| ConfigFileSpec {
+                    source_path: path.to_string(),
+                    container_path: path.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            confidence: 0.8,
+            ..Default::default()
+        };
+        let stat = |path: &str, owner: &str, mode: &str| xcprobe_bundle_schema::FileInfo {
+            path: path.to_string(),
+            size_bytes: 0,
+            modified_at: None,
+            owner: Some(owner.to_string()),
+            permissions: Some(mode.to_string()),
+            content_hash: None,
+            attachment_ref: None,
+            discovery_method: "working_directory".to_string(),
+            discovery_evidence_ref: None,
+            opened_by: Vec::new(),
+        };
+        let bundle = Bundle {
+            manifest: xcprobe_bundle_schema::Manifest {
+                config_files: vec![stat("/etc/app.yaml", "root:app", "0640")],
+                directories: vec![stat("/opt/app", "app:app", "0750")],
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+
+        plan_app_files(&ManifestIndex::new(&bundle.manifest), &mut cluster);
+
+        let planned: Vec<(&str, &str)> = cluster
+            .app_files
+            .iter()
+            .map(|f| (f.source_path.as_str(), f.kind.as_str()))
+            .collect();
+        // settings.ini is already part of the working directory
+        assert_eq!(
+            planned,
+            vec![
+                ("/opt/app", "working_directory"),
+                ("/usr/local/bin/app", "binary"),
+                ("/etc/app.yaml", "config"),
+            ]
+        );
+        // Source owners and modes are kept; uncollected paths go to the
+        // service user
+        let ownership: Vec<(Option<&str>, Option<&str>)> = cluster
+            .app_files
+            .iter()
+            .map(|f| (f.owner.as_deref(), f.mode.as_deref()))
+            .collect();
+        assert_eq!(
+            ownership,
+            vec![
+                (Some("app"), Some("0750")),
+                (Some("app"), None),
+                (Some("root:app"), Some("0640")),
+            ]
+        );
+        assert_eq!(copy_owner("root:root", Some("app")), None);
+        assert_eq!(
+            copy_owner("www-data:www-data", Some("app")).as_deref(),
+            Some("app")
+        );
+        assert_eq!(cluster.app_files[0].pack_path(), "pack/opt/app");
+
+        // JVM jars and classpath directories outside the working directory
+        cluster.java = crate::java::parse_java_command(
+            "java -cp /srv/lib/*:/opt/app/conf:/usr/share/java/log4j.jar com.example.Main",
+        );
+        plan_app_files(&ManifestIndex::new(&bundle.manifest), &mut cluster);
+        let java_files: Vec<(&str, &str)> = cluster.app_files[3..]
+            .iter()
+            .map(|f| (f.source_path.as_str(), f.kind.as_str()))
+            .collect();
+        assert_eq!(
+            java_files,
+            vec![
+                ("/srv/lib", "directory"),
+                ("/usr/share/java/log4j.jar", "jar")
+            ]
+        );
+        assert!(cluster.app_files[3].is_directory());
+
+        // Executables under system prefixes that no package owns are packed
+        cluster.java = None;
+        cluster.services[0].exec_start = Some("/usr/bin/app-agent --daemon".to_string());
+        let mut manifest = bundle.manifest.clone();
+        manifest
+            .package_owners
+            .push(xcprobe_bundle_schema::PackageOwner {
+                path: "/usr/bin/app-agent".to_string(),
+                packages: Vec::new(),
+                source: "dpkg".to_string(),
+                services: vec!["app.service".to_string()],
+                evidence_ref: None,
+            });
+        plan_app_files(&ManifestIndex::new(&manifest), &mut cluster);
+        assert_eq!(cluster.app_files[1].source_path, "/usr/bin/app-agent");
+        assert_eq!(cluster.app_files[1].kind, "binary");
+    }
+
+    fn cron_task(command: &str, schedule: &str) -> xcprobe_bundle_schema::ScheduledTask {
+        xcprobe_bundle_schema::ScheduledTask {
+            name: command
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .to_string(),
+            task_type: "cron".to_string(),
+            schedule: Some(schedule.to_string()),
+            command: Some(command.to_string()),
+            user: Some("app".to_string()),
+            enabled: true,
+            last_run: None,
+            next_run: None,
+            evidence_ref: Some("evidence/scheduled_tasks_1.txt".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_cron_jobs_become_batch_clusters() {
+        let bundle = Bundle {
+            manifest: xcprobe_bundle_schema::Manifest {
+                scheduled_tasks: vec![
+                    cron_task("cd / && run-parts --report /etc/cron.hourly", "17 * * * *"),
+                    cron_task("/opt/billing/bin/invoice_export --since 1h", "*/5 * * * *"),
+                    cron_task("/opt/billing/bin/invoice_export --since 1h", "*/5 * * * *"),
+                    cron_task("/usr/local/bin/warm-cache", "@reboot"),
+                ],
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+
+        let clusters = cluster_applications(
+            &ManifestIndex::new(&bundle.manifest),
+            &HashMap::new(),
+            "app",
+        )
+        .unwrap();
+        assert_eq!(clusters.len(), 1);
+        let batch = &clusters[0];
+        assert_eq!(batch.app_type, "batch");
+        assert_eq!(batch.name, "invoice-export");
+        assert_eq!(batch.schedule.as_deref(), Some("*/5 * * * *"));
+        assert_eq!(batch.services[0].user.as_deref(), Some("app"));
+        assert_eq!(
+            batch.services[0].exec_start.as_deref(),
+            Some("/opt/billing/bin/invoice_export --since 1h")
+        );
+        assert!(batch.decisions[0].has_evidence());
+    }
+
+    #[test]
+    fn test_merge_clusters_by_workdir_and_user() {
+        let service = |name: &str, user: &str, wd: &str| AppCluster {
+            id: format!("svc-{}", name),
+            name: name.to_string(),
+            app_type: "unknown".to_string(),
+            services: vec![ClusterService {
+                name: format!("{}.service", name),
+                user: Some(user.to_string()),
+                working_directory: Some(wd.to_string()),
+                evidence_ref: Some(format!("evidence/{}.txt", name)),
+                ..Default::default()
+            }],
+            evidence_refs: vec![format!("evidence/{}.txt", name)],
+            ..Default::default()
+        };
+        let clusters = || {
+            vec![
+                service("billing-api", "billing", "/opt/billing"),
+                service("billing-worker", "billing", "/opt/billing/"),
+                service("billing-export", "billing", "/var/lib/export"),
+                service("shop", "www-data", "/"),
+                service("blog", "www-data", "/"),
+            ]
+        };
+        let names = |clusters: &[AppCluster]| -> Vec<(String, usize)> {
+            clusters
+                .iter()
+                .map(|c| (c.id.clone(), c.services.len()))
+                .collect()
+        };
+
+        let mut per_service = clusters();
+        merge_clusters(&mut per_service, ClusteringStrategy::PerService, "app");
+        assert_eq!(per_service.len(), 5);
+
+        let mut merged = clusters();
+        merge_clusters(&mut merged, ClusteringStrategy::Merged, "app");
+        assert_eq!(
+            names(&merged),
+            vec![
+                ("app-0".to_string(), 2),
+                ("app-1".to_string(), 1),
+                ("app-2".to_string(), 1),
+                ("app-3".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            merged[0].decisions[0].reason,
+            "Both clusters share working directory /opt/billing"
+        );
+        assert!(merged[0].decisions[0].has_evidence());
+
+        let mut aggressive = clusters();
+        merge_clusters(&mut aggressive, ClusteringStrategy::Aggressive, "app");
+        assert_eq!(aggressive.len(), 3);
+        assert_eq!(aggressive[0].services.len(), 3);
+        assert_eq!(
+            "aggressive".parse::<ClusteringStrategy>().unwrap(),
+            ClusteringStrategy::Aggressive
+        );
+        assert!("clever".parse::<ClusteringStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_merge_clusters_joins_groups_linked_later() {
+        let service = |name: &str, workdirs: &[&str]| AppCluster {
+            name: name.to_string(),
+            services: vec![ClusterService {
+                name: format!("{}.service", name),
+                working_directory: Some(workdirs[0].to_string()),
+                ..Default::default()
+            }],
+            processes: workdirs[1..]
+                .iter()
+                .map(|wd| ClusterProcess {
+                    working_directory: Some(wd.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        // C links the groups B and A formed before it
+        let mut clusters = vec![
+            service("b", &["/opt/y"]),
+            service("a", &["/opt/x"]),
+            service("c", &["/opt/x", "/opt/y"]),
+        ];
+        merge_clusters(&mut clusters, ClusteringStrategy::Merged, "app");
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].name, "b");
+        assert_eq!(clusters[0].services.len(), 3);
+        let reasons: Vec<&str> = clusters[0]
+            .decisions
+            .iter()
+            .map(|d| d.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                "Clusters b and c share working directory /opt/y",
+                "Both clusters share working directory /opt/x",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_port_decision_notes_attribution() {
+        let mut port = PortInfo {
+            protocol: "tcp".to_string(),
+            local_address: "0.0.0.0".to_string(),
+            local_port: 8080,
+            state: "LISTEN".to_string(),
+            pid: Some(812),
+            process_name: Some("java".to_string()),
+            evidence_ref: Some("evidence/ss.txt".to_string()),
+            pid_source: None,
+            pid_evidence_ref: None,
+            family: None,
+            socket_path: None,
+        };
+        let decision = port_decision(&port, "Service listens on port 8080".to_string());
+        assert_eq!(decision.confidence, LISTING_PORT_CONFIDENCE);
+
+        port.pid_source = Some("proc_net".to_string());
+        port.pid_evidence_ref = Some("evidence/proc_net.txt".to_string());
+        let decision = port_decision(&port, "Process 812 listens on port 8080".to_string());
+        assert_eq!(
+            decision.reason,
+            "The ports listing gave no process; the socket inode in /proc/net \
+             was found among the file descriptors of PID 812"
+        );
+        assert_eq!(decision.confidence, PROC_NET_PORT_CONFIDENCE);
+        assert_eq!(
+            decision.evidence_refs,
+            vec!["evidence/ss.txt", "evidence/proc_net.txt"]
+        );
+    }
+
+    #[test]
+    fn test_template_instances_collapse_into_replicas() {
+        let service = |name: &str, pid: u32| xcprobe_bundle_schema::ServiceInfo {
+            name: name.to_string(),
+            display_name: None,
+            description: None,
+            state: "active".to_string(),
+            sub_state: Some("running".to_string()),
+            start_mode: None,
+            exec_start: Some("/opt/queue/bin/worker --port 800%i".to_string()),
+            exec_start_pre: vec![],
+            exec_start_post: vec![],
+            exec_stop: None,
+            working_directory: Some("/opt/queue".to_string()),
+            user: Some("queue".to_string()),
+            group: None,
+            environment: Default::default(),
+            environment_files: vec![],
+            unit_file_path: None,
+            dependencies: vec![],
+            wanted_by: vec![],
+            main_pid: Some(pid),
+            recovery: None,
+            evidence_ref: Some(format!("evidence/{}.txt", name)),
+        };
+        let port = |local_port: u16, pid: u32| xcprobe_bundle_schema::PortInfo {
+            protocol: "tcp".to_string(),
+            local_address: "0.0.0.0".to_string(),
+            local_port,
+            state: "LISTEN".to_string(),
+            pid: Some(pid),
+            process_name: None,
+            evidence_ref: Some("evidence/ports.txt".to_string()),
+            pid_source: None,
+            pid_evidence_ref: None,
+            family: None,
+            socket_path: None,
+        };
+        let bundle = Bundle {
+            manifest: xcprobe_bundle_schema::Manifest {
+                services: vec![
+                    service("worker@1.service", 101),
+                    service("api.service", 200),
+                    service("worker@2.service", 102),
+                ],
+                ports: vec![port(8001, 101), port(8002, 102)],
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+
+        let clusters = cluster_applications(
+            &ManifestIndex::new(&bundle.manifest),
+            &HashMap::new(),
+            "app",
+        )
+        .unwrap();
+        let names: Vec<(&str, &str)> = clusters
+            .iter()
+            .map(|c| (c.id.as_str(), c.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("app-0", "worker"), ("app-1", "api")]);
+        let worker = &clusters[0];
+        assert_eq!(worker.replicas, Some(2));
+        assert_eq!(worker.services.len(), 2);
+        assert_eq!(worker.ports.len(), 1);
+        assert_eq!(worker.ports[0].port, 8001);
+        let decision = worker.decisions.last().unwrap();
+        assert!(decision.reason.contains("bind ports 8001, 8002"));
+        assert_eq!(decision.evidence_refs.len(), 2);
+    }
+
+    #[test]
+    fn test_workers_grouped_with_parent_process() {
+        let process = |pid: u32, ppid: u32, command: &str, wd: Option<&str>| ProcessInfo {
+            pid,
+            ppid,
+            user: "app".to_string(),
+            command: command.to_string(),
+            args: vec![],
+            full_cmdline: command.to_string(),
+            start_time: None,
+            elapsed_time: None,
+            cpu_percent: None,
+            memory_percent: None,
+            working_directory: wd.map(str::to_string),
+            environment: None,
+            evidence_ref: Some("evidence/processes_1.txt".to_string()),
+            cmdline_evidence_ref: None,
+        };
+        let bundle = Bundle {
+            manifest: xcprobe_bundle_schema::Manifest {
+                processes: vec![
+                    process(100, 1, "/opt/venv/bin/gunicorn", None),
+                    process(101, 100, "/opt/venv/bin/gunicorn", None),
+                    process(102, 100, "/opt/venv/bin/gunicorn", None),
+                    process(200, 1, "/opt/report/bin/server", Some("/opt/report")),
+                    process(201, 200, "/usr/bin/python3", Some("/opt/report")),
+                    process(300, 200, "/opt/other/agent", Some("/opt/other")),
+                ],
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let scores: HashMap<u32, ProcessScore> = [100, 101, 102, 200, 201, 300]
+            .into_iter()
+            .map(|pid| {
+                let score = ProcessScore {
+                    pid,
+                    name: format!("proc-{}", pid),
+                    score: 0.8,
+                    reasons: vec![],
+                    is_business_process: true,
+                };
+                (pid, score)
+            })
+            .collect();
+
+        let clusters =
+            cluster_applications(&ManifestIndex::new(&bundle.manifest), &scores, "app").unwrap();
+        let pids: Vec<Vec<u32>> = clusters
+            .iter()
+            .map(|c| c.processes.iter().map(|p| p.pid).collect())
+            .collect();
+        assert_eq!(pids, vec![vec![100, 101, 102], vec![200, 201], vec![300]]);
+        assert!(clusters[0].decisions[1]
+            .reason
+            .contains("same executable /opt/venv/bin/gunicorn"));
+        assert!(clusters[1].decisions[1]
+            .reason
+            .contains("working directory /opt/report"));
+    }
+
+    #[test]
+    fn test_open_files_attached_to_cluster() {
+        let open_file = |path: &str, pids: Vec<u32>| xcprobe_bundle_schema::FileInfo {
+            path: path.to_string(),
+            size_bytes: 0,
+            modified_at: None,
+            owner: None,
+            permissions: None,
+            content_hash: None,
+            attachment_ref: None,
+            discovery_method: "open_fd".to_string(),
+            discovery_evidence_ref: Some("evidence/open_files_1.txt".to_string()),
+            opened_by: pids,
+        };
+        let bundle = Bundle {
+            manifest: xcprobe_bundle_schema::Manifest {
+                processes: vec![ProcessInfo {
+                    pid: 100,
+                    ppid: 1,
+                    user: "app".to_string(),
+                    command: "/data/billing/bin/billing".to_string(),
+                    args: vec![],
+                    full_cmdline: "/data/billing/bin/billing".to_string(),
+                    start_time: None,
+                    elapsed_time: None,
+                    cpu_percent: None,
+                    memory_percent: None,
+                    working_directory: None,
+                    environment: None,
+                    evidence_ref: None,
+                    cmdline_evidence_ref: None,
+                }],
+                config_files: vec![
+                    open_file("/data/billing/conf/billing.yml", vec![100]),
+                    open_file("/etc/other/other.conf", vec![200]),
+                ],
+                log_files: vec![open_file("/data/billing/logs/billing.log", vec![100])],
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let scores = HashMap::from([(
+            100,
+            ProcessScore {
+                pid: 100,
+                name: "billing".to_string(),
+                score: 0.8,
+                reasons: vec![],
+                is_business_process: true,
+            },
+        )]);
+
+        let clusters =
+            cluster_applications(&ManifestIndex::new(&bundle.manifest), &scores, "app").unwrap();
+        let cluster = &clusters[0];
+        let configs: Vec<&str> = cluster
+            .config_files
+            .iter()
+            .map(|c| c.source_path.as_str())
+            .collect();
+        assert_eq!(configs, vec!["/data/billing/conf/billing.yml"]);
+        assert_eq!(cluster.log_paths, vec!["/data/billing/logs/billing.log"]);
+        assert_eq!(
+            cluster.decisions.last().unwrap().evidence_refs,
+            vec!["evidence/open_files_1.txt"]
+        );
+    }
+
+    #[test]
+    fn test_image_user_of_windows_accounts() {
+        assert_eq!(image_user("LocalSystem"), None);
+        assert_eq!(image_user("NT AUTHORITY\\LocalService"), None);
+        assert_eq!(image_user("NT SERVICE\\MSSQLSERVER"), None);
+        assert_eq!(image_user("root"), None);
+        assert_eq!(image_user("CORP\\svc_app"), Some("svc_app"));
+        assert_eq!(image_user(".\\svc_app"), Some("svc_app"));
+        assert_eq!(image_user("svc_app@corp.example"), Some("svc_app"));
+        assert_eq!(image_user("app"), Some("app"));
+    }
+}
@@ -1,8 +1,9 @@
 //! Confidence scoring and validation.
 
+use crate::scoring::EvidenceWeighting;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use xcprobe_bundle_schema::{AppCluster, PackPlan};
+use xcprobe_bundle_schema::{AnalysisWarning, AppCluster, PackPlan, RejectedCluster};
 
 /// Confidence report for a cluster.
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,7 +36,7 @@ pub struct ValidationResult {
 }
 
 /// Calculate confidence score for a cluster.
-pub fn calculate_cluster_confidence(cluster: &mut AppCluster) {
+pub fn calculate_cluster_confidence(cluster: &mut AppCluster, weighting: &EvidenceWeighting) {
     if cluster.decisions.is_empty() {
         cluster.confidence = 0.0;
         return;
@@ -48,7 +49,7 @@ pub fn calculate_cluster_confidence(cluster: &mut AppCluster) {
     for decision in &cluster.decisions {
         // Weight by whether decision has evidence
         let weight = if decision.evidence_refs.is_empty() {
-            0.5 // Lower weight for decisions without evidence
+            weighting.unevidenced_weight
         } else {
             1.0
         };
@@ -71,7 +72,99 @@ pub fn calculate_cluster_confidence(cluster: &mut AppCluster) {
         .count() as f64
         / cluster.decisions.len() as f64;
 
-    cluster.confidence *= 0.5 + (evidence_ratio * 0.5);
+    let floor = weighting.evidence_ratio_floor;
+    cluster.confidence *= floor + (evidence_ratio * (1.0 - floor));
+}
+
+/// Overall confidence of a plan: the mean of cluster confidences, each
+/// weighted by its number of evidence-backed decisions.
+pub fn calculate_overall_confidence(clusters: &[AppCluster]) -> f64 {
+    let weight = |c: &AppCluster| c.decisions.iter().filter(|d| d.has_evidence()).count() as f64;
+    let total_weight: f64 = clusters.iter().map(weight).sum();
+    if total_weight == 0.0 {
+        return 0.0;
+    }
+
+    clusters
+        .iter()
+        .map(|c| c.confidence * weight(c))
+        .sum::<f64>()
+        / total_weight
+}
+
+/// Warnings for clusters dropped below `min_confidence`.
+pub fn filtered_cluster_warnings(
+    filtered: &[AppCluster],
+    min_confidence: f64,
+) -> Vec<AnalysisWarning> {
+    filtered
+        .iter()
+        .map(|c| {
+            AnalysisWarning::new(
+                "cluster_filtered",
+                "info",
+                format!(
+                    "Cluster {} ({}) dropped: confidence {:.2} is below the minimum {:.2}",
+                    c.id, c.name, c.confidence, min_confidence
+                ),
+                vec![c.id.clone()],
+            )
+        })
+        .collect()
+}
+
+/// Summarize a cluster dropped below `min_confidence` for review.
+pub fn reject_cluster(cluster: AppCluster, min_confidence: f64) -> RejectedCluster {
+    RejectedCluster {
+        reason: format!(
+            "Confidence {:.2} is below the minimum {:.2}",
+            cluster.confidence, min_confidence
+        ),
+        id: cluster.id,
+        name: cluster.name,
+        app_type: cluster.app_type,
+        confidence: cluster.confidence,
+        evidence_refs: cluster.evidence_refs,
+        decisions: cluster.decisions,
+    }
+}
+
+/// Warnings for dependency cycles, which have no valid startup order.
+pub fn cycle_warnings(cycles: &[Vec<String>]) -> Vec<AnalysisWarning> {
+    cycles
+        .iter()
+        .map(|cycle| {
+            AnalysisWarning::new(
+                "circular_dependency",
+                "error",
+                format!(
+                    "Circular dependency between clusters {}; startup order is undefined",
+                    cycle.join(", ")
+                ),
+                cycle.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Warnings for clusters without a service `exec_start` or supervised
+/// programs, whose Dockerfile gets no default command.
+pub fn missing_command_warnings(clusters: &[AppCluster]) -> Vec<AnalysisWarning> {
+    clusters
+        .iter()
+        .filter(|c| c.programs.is_empty() && !c.services.iter().any(|s| s.exec_start.is_some()))
+        .map(|c| {
+            AnalysisWarning::new(
+                "missing_exec_start",
+                "warning",
+                format!(
+                    "Cluster {} ({}) has no exec_start; set CMD in its Dockerfile",
+                    c.id, c.name
+                ),
+                vec![c.id.clone()],
+            )
+        })
+        .collect()
 }
 
 /// Validate that a pack plan has evidence for all decisions.
@@ -108,8 +201,12 @@ pub fn validate_plan_evidence(plan: &PackPlan) -> ValidationResult {
     }
 }
 
-/// Generate a confidence report for a cluster.
-pub fn generate_confidence_report(cluster: &AppCluster) -> Result<String> {
+/// Generate a confidence report for a cluster, including the lint issues
+/// of its generated Dockerfile.
+pub fn generate_confidence_report(
+    cluster: &AppCluster,
+    lint_issues: &[crate::lint::LintIssue],
+) -> Result<String> {
     let mut missing_evidence = Vec::new();
     let decisions: Vec<DecisionConfidence> = cluster
         .decisions
@@ -147,6 +244,10 @@ pub fn generate_confidence_report(cluster: &AppCluster) -> Result<String> {
         warnings.push("No network ports detected, verify if this is expected".to_string());
     }
 
+    for issue in lint_issues {
+        warnings.push(format!("Dockerfile {}", issue.describe()));
+    }
+
     let report = ConfidenceReport {
         cluster_id: cluster.id.clone(),
         overall_confidence: cluster.confidence,
@@ -175,10 +276,23 @@ mod tests {
             ports: vec![],
             env_vars: vec![],
             config_files: vec![],
+            app_files: Vec::new(),
             log_paths: vec![],
             depends_on: vec![],
             external_deps: vec![],
             readiness: None,
+            runtime: None,
+            packages: Vec::new(),
+            package_files: Vec::new(),
+            system_packages: Vec::new(),
+            base_image: None,
+            schedule: None,
+            volumes: Vec::new(),
+            java: None,
+            replicas: None,
+            resources: None,
+            database: None,
+            programs: Vec::new(),
             confidence: 0.0,
             evidence_refs: vec![],
             decisions: vec![
@@ -192,7 +306,7 @@ mod tests {
             ],
         };
 
-        calculate_cluster_confidence(&mut cluster);
+        calculate_cluster_confidence(&mut cluster, &EvidenceWeighting::default());
 
         // Confidence should be reasonable
         assert!(cluster.confidence > 0.5);
@@ -205,6 +319,7 @@ mod tests {
             schema_version: "1.0.0".to_string(),
             generated_at: chrono::Utc::now(),
             source_bundle_id: "test".to_string(),
+            source_os: None,
             clusters: vec![AppCluster {
                 id: "test".to_string(),
                 name: "test".to_string(),
@@ -215,10 +330,23 @@ mod tests {
                 ports: vec![],
                 env_vars: vec![],
                 config_files: vec![],
+                app_files: Vec::new(),
                 log_paths: vec![],
                 depends_on: vec![],
                 external_deps: vec![],
                 readiness: None,
+                runtime: None,
+                packages: Vec::new(),
+                package_files: Vec::new(),
+                system_packages: Vec::new(),
+                base_image: None,
+                schedule: None,
+                volumes: Vec::new(),
+                java: None,
+                replicas: None,
+                resources: None,
+                database: None,
+                programs: Vec::new(),
                 confidence: 0.8,
                 evidence_refs: vec![],
                 decisions: vec![
@@ -236,6 +364,7 @@ mod tests {
             artifacts: vec![],
             overall_confidence: 0.8,
             warnings: vec![],
+            rejected_clusters: vec![],
         };
 
         let result = validate_plan_evidence(&plan);
@@ -244,4 +373,61 @@ mod tests {
         assert_eq!(result.decisions_with_evidence, 1);
         assert_eq!(result.decisions_without_evidence.len(), 1);
     }
+
+    #[test]
+    fn test_overall_confidence_and_warnings() {
+        let cluster = |id: &str, confidence: f64, evidence: usize, exec_start: Option<&str>| {
+            let mut cluster = AppCluster {
+                id: id.to_string(),
+                name: id.to_string(),
+                app_type: "api".to_string(),
+                confidence,
+                ..Default::default()
+            };
+            for i in 0..evidence {
+                cluster.decisions.push(Decision::new(
+                    format!("Decision {}", i),
+                    "reason",
+                    vec!["evidence/test.txt".to_string()],
+                    confidence,
+                ));
+            }
+            cluster
+                .services
+                .push(xcprobe_bundle_schema::ClusterService {
+                    name: format!("{}.service", id),
+                    exec_start: exec_start.map(str::to_string),
+                    ..Default::default()
+                });
+            cluster
+        };
+
+        // Weighted 3:1 towards the better-evidenced cluster
+        let clusters = vec![
+            cluster("app-0", 0.9, 3, Some("/opt/app/server")),
+            cluster("app-1", 0.5, 1, None),
+        ];
+        assert!((calculate_overall_confidence(&clusters) - 0.8).abs() < 1e-9);
+        assert_eq!(calculate_overall_confidence(&[]), 0.0);
+
+        let warnings = missing_command_warnings(&clusters);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "missing_exec_start");
+        assert_eq!(warnings[0].affected_clusters, vec!["app-1"]);
+
+        let warnings = filtered_cluster_warnings(&clusters[1..], 0.6);
+        assert_eq!(warnings[0].code, "cluster_filtered");
+        assert!(warnings[0]
+            .message
+            .contains("0.50 is below the minimum 0.60"));
+
+        let rejected = reject_cluster(clusters[1].clone(), 0.6);
+        assert_eq!(rejected.id, "app-1");
+        assert_eq!(rejected.reason, "Confidence 0.50 is below the minimum 0.60");
+        assert_eq!(rejected.decisions.len(), 1);
+
+        let warnings = cycle_warnings(&[vec!["app-0".to_string(), "app-1".to_string()]]);
+        assert_eq!(warnings[0].severity, "error");
+        assert_eq!(warnings[0].affected_clusters.len(), 2);
+    }
 }
@@ -0,0 +1,545 @@
+//! Template variables in configuration files.
+//!
+//! Besides the host addresses rewritten by [`crate::aliases`], collected
+//! configuration files hard-code what a container is expected to set at run
+//! time: the values of the service's environment variables, the ports it
+//! listens on, and the endpoints and host names of its external
+//! dependencies. Each occurrence is replaced by a `${VAR}` reference whose
+//! default is the collected value, so the rendered template reproduces the
+//! source file until the variable is set. Environment files become
+//! `NAME=${NAME}` templates.
+
+use crate::dependencies::endpoint_host;
+use regex::Regex;
+use std::sync::LazyLock;
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, DependencyInfo, EnvVarSpec};
+
+/// Confidence of replacing the value of an environment variable.
+const ENV_VALUE_CONFIDENCE: f64 = 0.9;
+/// Confidence of replacing a listening port next to a `port` or `listen` key.
+const PORT_CONFIDENCE: f64 = 0.85;
+/// Confidence of replacing the endpoint or host name of a dependency.
+const ENDPOINT_CONFIDENCE: f64 = 0.75;
+
+/// Shortest environment variable value looked for, so that short values
+/// such as `1` or `on` do not match everywhere.
+const MIN_VALUE_LEN: usize = 4;
+
+/// URL scheme and authority, the path left out.
+static URL_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b[a-z][a-z0-9+.-]*://(?:[^\s/@:]+(?::[^\s/@]*)?@)?([a-z0-9.-]+)(?::\d{1,5})?")
+        .unwrap()
+});
+
+/// A port number set by a `port` or `listen` key.
+static PORT_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?im)(?:^|[^a-z0-9])(?:port|listen)["']?[ \t]*[:=]?[ \t]*["']?(\d{2,5})\b"#)
+        .unwrap()
+});
+
+/// An assignment of an environment file, with its `export` prefix.
+static ENV_ASSIGNMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^([ \t]*(?:export[ \t]+)?)([A-Za-z_][A-Za-z0-9_]*)=(.*)$").unwrap()
+});
+
+/// What a replaced value is.
+#[derive(Clone, Copy)]
+enum Kind {
+    EnvValue,
+    Endpoint,
+    Host,
+    Port,
+}
+
+impl Kind {
+    fn confidence(self) -> f64 {
+        match self {
+            Kind::EnvValue => ENV_VALUE_CONFIDENCE,
+            Kind::Port => PORT_CONFIDENCE,
+            Kind::Endpoint | Kind::Host => ENDPOINT_CONFIDENCE,
+        }
+    }
+}
+
+/// A value to replace by a variable.
+struct Substitution {
+    start: usize,
+    end: usize,
+    variable: String,
+    kind: Kind,
+    /// Why the value is a variable, for the decision.
+    reason: String,
+}
+
+/// Replace the values a container sets at run time in the configuration
+/// files of the clusters. Rewritten files become templates, and new
+/// variables environment variables of the cluster defaulting to the
+/// collected value.
+pub fn discover_template_vars(
+    bundle: &Bundle,
+    external: &[DependencyInfo],
+    clusters: &mut [AppCluster],
+) {
+    for cluster in clusters.iter_mut().filter(|c| c.database.is_none()) {
+        let hosts: Vec<String> = external
+            .iter()
+            .filter(|d| d.used_by.contains(&cluster.id))
+            .filter_map(|d| endpoint_host(&d.endpoint))
+            .filter(|host| !is_loopback(host))
+            .collect();
+        let env_files: Vec<String> = cluster
+            .services
+            .iter()
+            .flat_map(|s| s.environment_files.iter().cloned())
+            .collect();
+        let env_values = env_values(cluster);
+        let ports: Vec<u16> = cluster.ports.iter().map(|p| p.port).collect();
+
+        let mut new_vars: Vec<(String, String, Option<String>)> = Vec::new();
+        for config in cluster.config_files.iter_mut() {
+            let content = match config.content.clone() {
+                Some(content) => content,
+                None => match config
+                    .evidence_ref
+                    .as_ref()
+                    .and_then(|r| bundle.evidence.get(r))
+                    .and_then(|e| e.output())
+                {
+                    Some(content) => content,
+                    None => continue,
+                },
+            };
+            if config.templated && config.content.is_none() {
+                // Rendered from the collected file, whatever is replaced below
+                config.content = Some(content.clone());
+            }
+
+            if env_files.contains(&config.source_path) {
+                let (template, assignments) = env_file_template(&content);
+                if assignments.is_empty() {
+                    continue;
+                }
+                let names: Vec<&str> = assignments.iter().map(|(n, _)| n.as_str()).collect();
+                cluster.decisions.push(Decision::new(
+                    format!("Template environment file {}", config.source_path),
+                    format!("Variables {} are set by the container", names.join(", ")),
+                    config.evidence_ref.iter().cloned().collect(),
+                    ENV_VALUE_CONFIDENCE,
+                ));
+                for (name, value) in assignments {
+                    if !config.template_vars.contains(&name) {
+                        config.template_vars.push(name.clone());
+                    }
+                    if !cluster.env_vars.iter().any(|e| e.name == name) {
+                        let sensitive = xcprobe_redaction::patterns::is_sensitive_key(&name);
+                        cluster.env_vars.push(EnvVarSpec {
+                            default_value: Some(value)
+                                .filter(|v| !sensitive && !v.contains("REDACTED")),
+                            name,
+                            required: true,
+                            description: Some(format!(
+                                "From environment file: {}",
+                                config.source_path
+                            )),
+                            sensitive,
+                            evidence_ref: config.evidence_ref.clone(),
+                        });
+                    }
+                }
+                config.templated = true;
+                config.content = Some(template);
+                continue;
+            }
+
+            let mut found = Vec::new();
+            find_env_values(&content, &env_values, &mut found);
+            find_endpoints(&content, &hosts, &mut found);
+            find_hosts(&content, &hosts, &mut found);
+            find_ports(&content, &ports, &mut found);
+            let substitutions = without_overlaps(&content, found);
+            if substitutions.is_empty() {
+                continue;
+            }
+
+            let mut rewritten = String::with_capacity(content.len());
+            let mut last = 0;
+            let mut recorded: Vec<(&str, &str)> = Vec::new();
+            for substitution in &substitutions {
+                let original = &content[substitution.start..substitution.end];
+                rewritten.push_str(&content[last..substitution.start]);
+                rewritten.push_str(&format!("${{{}}}", substitution.variable));
+                last = substitution.end;
+
+                if recorded.contains(&(original, substitution.variable.as_str())) {
+                    continue;
+                }
+                recorded.push((original, &substitution.variable));
+                cluster.decisions.push(Decision::new(
+                    format!(
+                        "Template {} as ${{{}}} in {}",
+                        original, substitution.variable, config.source_path
+                    ),
+                    substitution.reason.clone(),
+                    config.evidence_ref.iter().cloned().collect(),
+                    substitution.kind.confidence(),
+                ));
+                if !config.template_vars.contains(&substitution.variable) {
+                    config.template_vars.push(substitution.variable.clone());
+                }
+                if !matches!(substitution.kind, Kind::EnvValue) {
+                    new_vars.push((
+                        substitution.variable.clone(),
+                        original.to_string(),
+                        config.evidence_ref.clone(),
+                    ));
+                }
+            }
+            rewritten.push_str(&content[last..]);
+            config.templated = true;
+            config.content = Some(rewritten);
+        }
+
+        for (name, default_value, evidence_ref) in new_vars {
+            if cluster.env_vars.iter().any(|e| e.name == name) {
+                continue;
+            }
+            cluster.env_vars.push(EnvVarSpec {
+                name,
+                required: true,
+                default_value: Some(default_value),
+                description: Some("Value found in a configuration file".to_string()),
+                sensitive: false,
+                evidence_ref,
+            });
+        }
+    }
+}
+
+/// The environment variable values of the cluster's services worth looking
+/// for, longest first: redacted, boolean and short values are left out.
+fn env_values(cluster: &AppCluster) -> Vec<(String, String)> {
+    let mut values: Vec<(String, String)> = cluster
+        .services
+        .iter()
+        .flat_map(|s| s.environment.iter())
+        .filter(|(name, value)| {
+            value.len() >= MIN_VALUE_LEN
+                && !value.contains("REDACTED")
+                && !matches!(value.to_lowercase().as_str(), "true" | "false")
+                && !xcprobe_redaction::patterns::is_sensitive_key(name)
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    values.sort_by(|a, b| (b.1.len(), &a.1, &a.0).cmp(&(a.1.len(), &b.1, &b.0)));
+    values.dedup_by(|a, b| a.1 == b.1);
+    values
+}
+
+/// A template of an environment file setting each variable to its value in
+/// the container, and the variables with their value in the file.
+fn env_file_template(content: &str) -> (String, Vec<(String, String)>) {
+    let mut assignments: Vec<(String, String)> = Vec::new();
+    let template = ENV_ASSIGNMENT.replace_all(content, |caps: &regex::Captures| {
+        let name = caps[2].to_string();
+        let line = format!("{}{}=${{{}}}", &caps[1], name, name);
+        if !assignments.iter().any(|(n, _)| *n == name) {
+            let value = caps[3].trim().trim_matches(|c| c == '"' || c == '\'');
+            assignments.push((name, value.to_string()));
+        }
+        line
+    });
+    (template.into_owned(), assignments)
+}
+
+fn find_env_values(content: &str, values: &[(String, String)], found: &mut Vec<Substitution>) {
+    for (name, value) in values {
+        for (start, _) in content.match_indices(value.as_str()) {
+            let end = start + value.len();
+            if !is_whole_value(content, start, end) {
+                continue;
+            }
+            found.push(Substitution {
+                start,
+                end,
+                variable: name.clone(),
+                kind: Kind::EnvValue,
+                reason: format!("Value of the environment variable {} of the service", name),
+            });
+        }
+    }
+}
+
+fn find_endpoints(content: &str, hosts: &[String], found: &mut Vec<Substitution>) {
+    for caps in URL_PATTERN.captures_iter(content) {
+        let (whole, host) = (caps.get(0).expect("match"), &caps[1]);
+        if !hosts.iter().any(|h| h.eq_ignore_ascii_case(host)) {
+            continue;
+        }
+        found.push(Substitution {
+            start: whole.start(),
+            end: whole.end(),
+            variable: format!("{}_URL", variable_name(host)),
+            kind: Kind::Endpoint,
+            reason: format!("Endpoint of the external dependency {}", host),
+        });
+    }
+}
+
+fn find_hosts(content: &str, hosts: &[String], found: &mut Vec<Substitution>) {
+    for host in hosts {
+        for (start, _) in content.match_indices(host.as_str()) {
+            let end = start + host.len();
+            if !is_whole_value(content, start, end) {
+                continue;
+            }
+            found.push(Substitution {
+                start,
+                end,
+                variable: format!("{}_HOST", variable_name(host)),
+                kind: Kind::Host,
+                reason: format!("Host of the external dependency {}", host),
+            });
+        }
+    }
+}
+
+fn find_ports(content: &str, ports: &[u16], found: &mut Vec<Substitution>) {
+    for caps in PORT_PATTERN.captures_iter(content) {
+        let number = caps.get(1).expect("port");
+        let Ok(port) = number.as_str().parse::<u16>() else {
+            continue;
+        };
+        if !ports.contains(&port) {
+            continue;
+        }
+        found.push(Substitution {
+            start: number.start(),
+            end: number.end(),
+            variable: if ports.len() == 1 {
+                "PORT".to_string()
+            } else {
+                format!("PORT_{}", port)
+            },
+            kind: Kind::Port,
+            reason: format!("Port {} the application listens on", port),
+        });
+    }
+}
+
+/// The substitutions in content order, dropping those overlapping an
+/// earlier found one or a `${VAR}` reference already in the file.
+fn without_overlaps(content: &str, found: Vec<Substitution>) -> Vec<Substitution> {
+    let mut taken: Vec<(usize, usize)> = content
+        .match_indices("${")
+        .filter_map(|(start, _)| Some((start, start + content[start..].find('}')? + 1)))
+        .collect();
+    let mut kept: Vec<Substitution> = Vec::new();
+    for substitution in found {
+        if taken
+            .iter()
+            .any(|&(start, end)| substitution.start < end && start < substitution.end)
+        {
+            continue;
+        }
+        taken.push((substitution.start, substitution.end));
+        kept.push(substitution);
+    }
+    kept.sort_by_key(|s| s.start);
+    kept
+}
+
+/// Whether `content[start..end]` is a whole word or value, not part of a
+/// longer name, number or path component.
+fn is_whole_value(content: &str, start: usize, end: usize) -> bool {
+    let part_of_name = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let before = content[..start].chars().next_back();
+    let mut after = content[end..].chars();
+    let next = after.next();
+    !(before.is_some_and(|c| part_of_name(c) || c == '.')
+        || next.is_some_and(part_of_name)
+        || (next == Some('.') && after.next().is_some_and(|c| c.is_ascii_alphanumeric())))
+}
+
+fn is_loopback(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost") || host.starts_with("127.") || host == "::1"
+}
+
+/// Environment variable name for a host: `db.example.com` gives
+/// `DB_EXAMPLE_COM`, an address `HOST_10_0_0_7`.
+fn variable_name(host: &str) -> String {
+    let name: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("HOST_{}", name)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use xcprobe_bundle_schema::{ClusterPort, ClusterService, ConfigFileSpec, Evidence, Manifest};
+
+    fn cluster(config_path: &str, env_file: &str) -> AppCluster {
+        AppCluster {
+            id: "app-0".to_string(),
+            name: "billing".to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: "billing.service".to_string(),
+                exec_start: Some("/opt/billing/bin/server".to_string()),
+                working_directory: Some("/opt/billing".to_string()),
+                environment: [
+                    ("BILLING_MODE", "production"),
+                    ("DEBUG", "true"),
+                    ("DB_PASSWORD", "hunter2hunter2"),
+                ]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+                environment_files: vec![env_file.to_string()],
+                ..Default::default()
+            }],
+            ports: vec![ClusterPort {
+                port: 8080,
+                protocol: "tcp".to_string(),
+                ..Default::default()
+            }],
+            config_files: [config_path, env_file]
+                .iter()
+                .map(|path| ConfigFileSpec {
+                    source_path: path.to_string(),
+                    container_path: path.to_string(),
+                    evidence_ref: Some(format!("evidence/config{}.txt", path)),
+                    ..Default::default()
+                })
+                .collect(),
+            external_deps: vec!["ext-0".to_string()],
+            confidence: 0.9,
+            ..Default::default()
+        }
+    }
+
+    fn bundle(files: &[(&str, &str)]) -> Bundle {
+        let evidence = files
+            .iter()
+            .map(|(path, content)| {
+                let evidence_ref = format!("evidence/config{}.txt", path);
+                let output = format!("=== STDOUT ===\n{}\n\n=== STDERR ===\n", content);
+                (
+                    evidence_ref.clone(),
+                    Evidence::from_command_output(
+                        "config",
+                        format!("cat {}", path),
+                        output.into_bytes(),
+                        evidence_ref,
+                    ),
+                )
+            })
+            .collect();
+        Bundle {
+            manifest: Manifest::default(),
+            audit: vec![],
+            evidence,
+            checksums: HashMap::new(),
+        }
+    }
+
+    fn dependency(endpoint: &str) -> DependencyInfo {
+        DependencyInfo {
+            id: "ext-0".to_string(),
+            dep_type: "database".to_string(),
+            endpoint: endpoint.to_string(),
+            port: Some(5432),
+            used_by: vec!["app-0".to_string()],
+            evidence_refs: vec![],
+            addresses: Vec::new(),
+            internal: None,
+        }
+    }
+
+    #[test]
+    fn test_config_values_become_variables() {
+        let config = "mode: production\n\
+                      debug: true\n\
+                      server.port: 8080\n\
+                      admin_port: 9090\n\
+                      db: postgres://billing@db.example.com:5432/billing\n\
+                      replica: db.example.com\n\
+                      mirror: db.example.com.backup\n\
+                      password: hunter2hunter2\n";
+        let mut clusters = vec![cluster("/opt/billing/app.yaml", "/etc/default/billing")];
+        let bundle = bundle(&[
+            ("/opt/billing/app.yaml", config),
+            (
+                "/etc/default/billing",
+                "# Billing\nexport BILLING_MODE=production\nWORKERS=4",
+            ),
+        ]);
+
+        discover_template_vars(
+            &bundle,
+            &[dependency("postgres://billing@db.example.com:5432/billing")],
+            &mut clusters,
+        );
+
+        let config = &clusters[0].config_files[0];
+        assert!(config.templated);
+        assert_eq!(
+            config.content.as_deref(),
+            Some(
+                "mode: ${BILLING_MODE}\n\
+                 debug: true\n\
+                 server.port: ${PORT}\n\
+                 admin_port: 9090\n\
+                 db: ${DB_EXAMPLE_COM_URL}/billing\n\
+                 replica: ${DB_EXAMPLE_COM_HOST}\n\
+                 mirror: db.example.com.backup\n\
+                 password: hunter2hunter2\n"
+            )
+        );
+        assert_eq!(
+            config.template_vars,
+            vec![
+                "BILLING_MODE",
+                "PORT",
+                "DB_EXAMPLE_COM_URL",
+                "DB_EXAMPLE_COM_HOST"
+            ]
+        );
+
+        let env_file = &clusters[0].config_files[1];
+        assert_eq!(
+            env_file.content.as_deref(),
+            Some("# Billing\nexport BILLING_MODE=${BILLING_MODE}\nWORKERS=${WORKERS}")
+        );
+
+        let default = |name: &str| {
+            clusters[0]
+                .env_vars
+                .iter()
+                .find(|e| e.name == name)
+                .and_then(|e| e.default_value.as_deref())
+        };
+        assert_eq!(
+            default("DB_EXAMPLE_COM_URL"),
+            Some("postgres://billing@db.example.com:5432")
+        );
+        assert_eq!(default("PORT"), Some("8080"));
+        assert_eq!(default("WORKERS"), Some("4"));
+        assert!(clusters[0]
+            .decisions
+            .iter()
+            .any(|d| d.decision == "Template 8080 as ${PORT} in /opt/billing/app.yaml"));
+    }
+}
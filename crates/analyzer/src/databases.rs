@@ -0,0 +1,557 @@
+//! Official images for database servers.
+//!
+//! Rebuilding a database server from the host's binaries gives a
+//! meaningless image. PostgreSQL, MySQL, MariaDB, MongoDB and Redis clusters
+//! instead run the official image tagged with the source version, with the
+//! data directory of the image in a named volume and the image's credential
+//! variables. The data itself is migrated by dump and restore, described in
+//! the generated `MIGRATION.md`.
+
+use crate::runtime::leading_version;
+use crate::volumes::volume_name;
+use regex::Regex;
+use xcprobe_bundle_schema::{
+    AppCluster, Bundle, DatabaseInfo, Decision, EnvVarSpec, Manifest, VolumeSpec,
+};
+
+/// A credential variable of an official database image.
+#[derive(Debug)]
+pub struct Credential {
+    pub name: &'static str,
+    pub sensitive: bool,
+    pub description: &'static str,
+    /// Variables of the source service carrying the same setting.
+    pub aliases: &'static [&'static str],
+}
+
+/// A database engine run from its official image.
+#[derive(Debug)]
+pub struct DatabaseEngine {
+    pub name: &'static str,
+    /// Executable names of the server.
+    markers: &'static [&'static str],
+    /// Executable names shared with another engine, only counted when a
+    /// package of this engine is installed (`mysqld` of MariaDB).
+    package_markers: &'static [&'static str],
+    /// Server package name patterns. With a capture group the version is
+    /// taken from the name, otherwise from the package version.
+    packages: &'static [&'static str],
+    /// Version in the executable path (`/usr/lib/postgresql/15/bin`).
+    path_version: Option<&'static str>,
+    /// Version components kept in the image tag.
+    tag_components: usize,
+    repository: &'static str,
+    /// Tag used when the version is unknown.
+    default_tag: &'static str,
+    /// Data directory in the official image.
+    pub data_dir: &'static str,
+    pub credentials: &'static [Credential],
+    /// Variables of the source service the image must not inherit.
+    replaced_env: &'static [&'static str],
+    /// Compose healthcheck test.
+    pub healthcheck: &'static [&'static str],
+    /// Command dumping all data on the source host.
+    pub dump_command: &'static str,
+    /// File written by the dump command.
+    pub dump_file: &'static str,
+    /// Command restoring the dump into the compose service `{service}`.
+    pub restore_command: &'static str,
+}
+
+/// Supported engines, MariaDB before MySQL since both may run `mysqld`.
+pub static ENGINES: &[DatabaseEngine] = &[
+    DatabaseEngine {
+        name: "postgres",
+        markers: &["postgres", "postmaster"],
+        package_markers: &[],
+        packages: &[
+            r"^postgresql-(\d+)$",
+            r"^postgresql(\d+)-server$",
+            r"^postgresql-server$",
+        ],
+        path_version: Some(r"/(?:postgresql/|pgsql-)(\d+)/"),
+        tag_components: 1,
+        repository: "postgres",
+        default_tag: "16",
+        data_dir: "/var/lib/postgresql/data",
+        credentials: &[
+            Credential {
+                name: "POSTGRES_USER",
+                sensitive: false,
+                description: "Superuser name (default postgres)",
+                aliases: &["PGUSER"],
+            },
+            Credential {
+                name: "POSTGRES_PASSWORD",
+                sensitive: true,
+                description: "Superuser password",
+                aliases: &["PGPASSWORD"],
+            },
+            Credential {
+                name: "POSTGRES_DB",
+                sensitive: false,
+                description: "Database created on first start",
+                aliases: &["PGDATABASE"],
+            },
+        ],
+        replaced_env: &["PGDATA"],
+        healthcheck: &["CMD", "pg_isready"],
+        dump_command: "sudo -u postgres pg_dumpall > dump.sql",
+        dump_file: "dump.sql",
+        restore_command: concat!(
+            "docker compose exec -T {service} ",
+            r#"sh -c 'psql -U "${POSTGRES_USER:-postgres}" -d postgres' < dump.sql"#
+        ),
+    },
+    DatabaseEngine {
+        name: "mariadb",
+        markers: &["mariadbd"],
+        package_markers: &["mysqld"],
+        packages: &[r"^mariadb-server-(\d+\.\d+)$", r"^mariadb-server$"],
+        path_version: None,
+        tag_components: 2,
+        repository: "mariadb",
+        default_tag: "11",
+        data_dir: "/var/lib/mysql",
+        credentials: &[
+            Credential {
+                name: "MARIADB_ROOT_PASSWORD",
+                sensitive: true,
+                description: "Root password",
+                aliases: &["MYSQL_ROOT_PASSWORD"],
+            },
+            Credential {
+                name: "MARIADB_DATABASE",
+                sensitive: false,
+                description: "Database created on first start",
+                aliases: &["MYSQL_DATABASE"],
+            },
+            Credential {
+                name: "MARIADB_USER",
+                sensitive: false,
+                description: "User created on first start",
+                aliases: &["MYSQL_USER"],
+            },
+            Credential {
+                name: "MARIADB_PASSWORD",
+                sensitive: true,
+                description: "Password of MARIADB_USER",
+                aliases: &["MYSQL_PASSWORD"],
+            },
+        ],
+        replaced_env: &[],
+        healthcheck: &["CMD", "healthcheck.sh", "--connect", "--innodb_initialized"],
+        dump_command: concat!(
+            "mariadb-dump --all-databases --single-transaction --routines ",
+            "--events --triggers -u root -p > dump.sql"
+        ),
+        dump_file: "dump.sql",
+        restore_command: concat!(
+            "docker compose exec -T {service} ",
+            r#"sh -c 'mariadb -uroot -p"$MARIADB_ROOT_PASSWORD"' < dump.sql"#
+        ),
+    },
+    DatabaseEngine {
+        name: "mysql",
+        markers: &["mysqld"],
+        package_markers: &[],
+        packages: &[
+            r"^mysql-server-(\d+\.\d+)$",
+            r"^mysql-server$",
+            r"^mysql-community-server$",
+        ],
+        path_version: None,
+        tag_components: 2,
+        repository: "mysql",
+        default_tag: "8.0",
+        data_dir: "/var/lib/mysql",
+        credentials: &[
+            Credential {
+                name: "MYSQL_ROOT_PASSWORD",
+                sensitive: true,
+                description: "Root password",
+                aliases: &[],
+            },
+            Credential {
+                name: "MYSQL_DATABASE",
+                sensitive: false,
+                description: "Database created on first start",
+                aliases: &[],
+            },
+            Credential {
+                name: "MYSQL_USER",
+                sensitive: false,
+                description: "User created on first start",
+                aliases: &[],
+            },
+            Credential {
+                name: "MYSQL_PASSWORD",
+                sensitive: true,
+                description: "Password of MYSQL_USER",
+                aliases: &[],
+            },
+        ],
+        replaced_env: &[],
+        healthcheck: &["CMD", "mysqladmin", "ping", "-h", "localhost"],
+        dump_command: concat!(
+            "mysqldump --all-databases --single-transaction --routines ",
+            "--events --triggers -u root -p > dump.sql"
+        ),
+        dump_file: "dump.sql",
+        restore_command: concat!(
+            "docker compose exec -T {service} ",
+            r#"sh -c 'mysql -uroot -p"$MYSQL_ROOT_PASSWORD"' < dump.sql"#
+        ),
+    },
+    DatabaseEngine {
+        name: "mongodb",
+        markers: &["mongod"],
+        package_markers: &[],
+        packages: &[r"^mongodb-org-server$", r"^mongodb-server$"],
+        path_version: None,
+        tag_components: 2,
+        repository: "mongo",
+        default_tag: "7.0",
+        data_dir: "/data/db",
+        credentials: &[
+            Credential {
+                name: "MONGO_INITDB_ROOT_USERNAME",
+                sensitive: false,
+                description: "Root user created on first start",
+                aliases: &[],
+            },
+            Credential {
+                name: "MONGO_INITDB_ROOT_PASSWORD",
+                sensitive: true,
+                description: "Password of the root user",
+                aliases: &[],
+            },
+        ],
+        replaced_env: &[],
+        healthcheck: &[
+            "CMD",
+            "mongosh",
+            "--quiet",
+            "--eval",
+            "db.adminCommand('ping')",
+        ],
+        dump_command: "mongodump --archive=dump.archive --gzip",
+        dump_file: "dump.archive",
+        restore_command: concat!(
+            "docker compose exec -T {service} sh -c 'mongorestore --archive --gzip ",
+            r#"-u "$MONGO_INITDB_ROOT_USERNAME" -p "$MONGO_INITDB_ROOT_PASSWORD" "#,
+            "--authenticationDatabase admin' < dump.archive"
+        ),
+    },
+    DatabaseEngine {
+        name: "redis",
+        markers: &["redis-server"],
+        package_markers: &[],
+        packages: &[r"^redis-server$", r"^redis$"],
+        path_version: None,
+        tag_components: 2,
+        repository: "redis",
+        default_tag: "7.2",
+        data_dir: "/data",
+        credentials: &[],
+        replaced_env: &[],
+        healthcheck: &["CMD", "redis-cli", "ping"],
+        dump_command: "redis-cli --rdb dump.rdb",
+        dump_file: "dump.rdb",
+        restore_command: concat!(
+            "docker compose stop {service} && ",
+            "docker compose cp dump.rdb {service}:/data/dump.rdb && ",
+            "docker compose start {service}"
+        ),
+    },
+];
+
+/// Engine by name.
+pub fn engine(name: &str) -> Option<&'static DatabaseEngine> {
+    ENGINES.iter().find(|e| e.name == name)
+}
+
+/// Run every database and cache cluster with a supported engine from its
+/// official image.
+pub fn detect_databases(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        if !matches!(cluster.app_type.as_str(), "database" | "cache") || cluster.database.is_some()
+        {
+            continue;
+        }
+        let exec = cluster
+            .services
+            .iter()
+            .filter_map(|s| s.exec_start.as_deref())
+            .chain(cluster.processes.iter().map(|p| p.command.as_str()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let Some(engine) = identify_engine(&bundle.manifest, &exec) else {
+            continue;
+        };
+        apply_engine(&bundle.manifest, cluster, engine, &exec);
+    }
+}
+
+fn identify_engine(manifest: &Manifest, exec: &str) -> Option<&'static DatabaseEngine> {
+    ENGINES.iter().find(|engine| {
+        engine.markers.iter().any(|m| exec.contains(m))
+            || (engine.package_markers.iter().any(|m| exec.contains(m))
+                && package_version(manifest, engine).is_some())
+    })
+}
+
+/// Version of the engine's server package, with the package evidence.
+fn package_version(
+    manifest: &Manifest,
+    engine: &DatabaseEngine,
+) -> Option<(String, Option<String>)> {
+    let patterns: Vec<Regex> = engine
+        .packages
+        .iter()
+        .map(|p| Regex::new(p).unwrap())
+        .collect();
+    manifest.packages.iter().find_map(|package| {
+        let caps = patterns.iter().find_map(|re| re.captures(&package.name))?;
+        let version = match caps.get(1) {
+            Some(m) => m.as_str().to_string(),
+            None => leading_version(&package.version)?,
+        };
+        Some((version, package.evidence_ref.clone()))
+    })
+}
+
+fn apply_engine(
+    manifest: &Manifest,
+    cluster: &mut AppCluster,
+    engine: &DatabaseEngine,
+    exec: &str,
+) {
+    let mut evidence_refs: Vec<String> = cluster
+        .services
+        .iter()
+        .filter_map(|s| s.evidence_ref.clone())
+        .collect();
+    let from_path = engine
+        .path_version
+        .and_then(|p| Regex::new(p).unwrap().captures(exec))
+        .map(|caps| caps[1].to_string());
+    let version = match from_path {
+        Some(version) => Some(version),
+        None => package_version(manifest, engine).map(|(version, evidence_ref)| {
+            evidence_refs.extend(evidence_ref);
+            version
+        }),
+    }
+    .map(|v| {
+        v.split('.')
+            .take(engine.tag_components)
+            .collect::<Vec<_>>()
+            .join(".")
+    });
+
+    let image = format!(
+        "{}:{}",
+        engine.repository,
+        version.as_deref().unwrap_or(engine.default_tag)
+    );
+    cluster.decisions.push(Decision::new(
+        format!("Run {} from official image {}", cluster.name, image),
+        match version {
+            Some(ref v) => format!(
+                "{} {} server; data migrated by dump and restore",
+                engine.name, v
+            ),
+            None => format!(
+                "{} server of unknown version; data migrated by dump and restore",
+                engine.name
+            ),
+        },
+        evidence_refs.clone(),
+        if version.is_some() { 0.9 } else { 0.7 },
+    ));
+
+    // The data volume moves to the image's data directory
+    let source_data_dir = match cluster.volumes.iter_mut().find(|v| v.kind == "database") {
+        Some(volume) => {
+            let source = std::mem::replace(&mut volume.container_path, engine.data_dir.to_string());
+            if source != engine.data_dir {
+                cluster.decisions.push(Decision::new(
+                    format!("Mount volume {} at {}", volume.name, engine.data_dir),
+                    format!("Data directory of the official image, replacing {}", source),
+                    volume.evidence_ref.iter().cloned().collect(),
+                    0.9,
+                ));
+            }
+            Some(source)
+        }
+        None => {
+            let name = volume_name(cluster, engine.data_dir);
+            cluster.volumes.push(VolumeSpec {
+                name,
+                container_path: engine.data_dir.to_string(),
+                kind: "database".to_string(),
+                evidence_ref: evidence_refs.first().cloned(),
+            });
+            None
+        }
+    };
+
+    cluster
+        .env_vars
+        .retain(|e| !engine.replaced_env.contains(&e.name.as_str()));
+    for credential in engine.credentials {
+        if cluster.env_vars.iter().any(|e| e.name == credential.name) {
+            continue;
+        }
+        let alias = cluster
+            .env_vars
+            .iter()
+            .find(|e| credential.aliases.contains(&e.name.as_str()));
+        cluster.env_vars.push(EnvVarSpec {
+            name: credential.name.to_string(),
+            required: credential.sensitive,
+            default_value: alias
+                .filter(|a| !a.sensitive && !credential.sensitive)
+                .and_then(|a| a.default_value.clone()),
+            description: Some(match alias {
+                Some(a) => format!("{} (was {})", credential.description, a.name),
+                None => credential.description.to_string(),
+            }),
+            sensitive: credential.sensitive,
+            evidence_ref: alias.and_then(|a| a.evidence_ref.clone()),
+        });
+    }
+
+    cluster.base_image = Some(image.clone());
+    cluster.database = Some(DatabaseInfo {
+        engine: engine.name.to_string(),
+        version,
+        image,
+        source_data_dir,
+        data_dir: engine.data_dir.to_string(),
+        evidence_refs,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use xcprobe_bundle_schema::{ClusterService, Package};
+
+    fn cluster(app_type: &str, exec_start: &str, volume: Option<&str>) -> AppCluster {
+        let env_var = |name: &str, default_value: &str| EnvVarSpec {
+            name: name.to_string(),
+            required: true,
+            default_value: Some(default_value.to_string()),
+            ..Default::default()
+        };
+        AppCluster {
+            id: "app-1".to_string(),
+            name: "db".to_string(),
+            app_type: app_type.to_string(),
+            services: vec![ClusterService {
+                name: "db.service".to_string(),
+                exec_start: Some(exec_start.to_string()),
+                evidence_ref: Some("evidence/services_1.txt".to_string()),
+                ..Default::default()
+            }],
+            env_vars: vec![
+                env_var("PGDATA", "/srv/pgdata"),
+                env_var("PGUSER", "billing"),
+            ],
+            volumes: volume
+                .iter()
+                .map(|path| VolumeSpec {
+                    name: "app-1-pgdata".to_string(),
+                    container_path: path.to_string(),
+                    kind: "database".to_string(),
+                    evidence_ref: Some("evidence/services_1.txt".to_string()),
+                })
+                .collect(),
+            confidence: 0.9,
+            ..Default::default()
+        }
+    }
+
+    fn bundle(packages: &[(&str, &str)]) -> Bundle {
+        Bundle {
+            manifest: Manifest {
+                packages: packages
+                    .iter()
+                    .map(|(name, version)| Package {
+                        name: name.to_string(),
+                        version: version.to_string(),
+                        architecture: None,
+                        description: None,
+                        install_date: None,
+                        source: "dpkg".to_string(),
+                        evidence_ref: Some("evidence/packages_1.txt".to_string()),
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_postgres_runs_version_matched_image() {
+        let mut clusters = vec![cluster(
+            "database",
+            "/usr/lib/postgresql/15/bin/postgres -D /srv/pgdata",
+            Some("/srv/pgdata"),
+        )];
+        detect_databases(&bundle(&[]), &mut clusters);
+
+        let cluster = &clusters[0];
+        let database = cluster.database.as_ref().unwrap();
+        assert_eq!(database.image, "postgres:15");
+        assert_eq!(database.source_data_dir.as_deref(), Some("/srv/pgdata"));
+        assert_eq!(cluster.base_image.as_deref(), Some("postgres:15"));
+        assert_eq!(
+            cluster.volumes[0].container_path,
+            "/var/lib/postgresql/data"
+        );
+        let names: Vec<&str> = cluster.env_vars.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "PGUSER",
+                "POSTGRES_USER",
+                "POSTGRES_PASSWORD",
+                "POSTGRES_DB"
+            ]
+        );
+        assert_eq!(
+            cluster.env_vars[1].default_value.as_deref(),
+            Some("billing")
+        );
+        assert!(cluster.env_vars[2].sensitive);
+    }
+
+    #[test]
+    fn test_mysqld_from_mariadb_package() {
+        let mut clusters = vec![
+            cluster("database", "/usr/sbin/mysqld", None),
+            cluster("cache", "/usr/bin/redis-server 127.0.0.1:6379", None),
+        ];
+        let bundle = bundle(&[("mariadb-server-10.11", "1:10.11.6-0+deb12u1")]);
+        detect_databases(&bundle, &mut clusters);
+
+        let mariadb = clusters[0].database.as_ref().unwrap();
+        assert_eq!(mariadb.engine, "mariadb");
+        assert_eq!(mariadb.image, "mariadb:10.11");
+        assert_eq!(mariadb.evidence_refs.len(), 2);
+        assert_eq!(clusters[0].volumes[0].container_path, "/var/lib/mysql");
+
+        // Unknown version: default tag with a lower confidence
+        let redis = clusters[1].database.as_ref().unwrap();
+        assert_eq!(redis.image, "redis:7.2");
+        assert_eq!(redis.source_data_dir, None);
+        assert_eq!(clusters[1].decisions[0].confidence, 0.7);
+    }
+}
@@ -0,0 +1,325 @@
+//! Pluggable dependency and application detectors.
+//!
+//! A [`Detector`] inspects the bundle and the clusters found so far and adds
+//! what it recognizes: dependencies on other clusters or external services,
+//! a refined application type, or plain decisions. The built-in detectors
+//! (configuration endpoints, database hosts, reverse proxies, Unix socket
+//! links, environment variables, systemd units) run first, then the detectors registered in
+//! [`AnalyzeOptions::detectors`](crate::AnalyzeOptions), in order. In-house
+//! middleware can be recognized this way without forking the analyzer.
+
+use crate::dependencies::{
+    endpoint_host, DbHostDetector, EndpointDetector, EnvVarDetector, ProxyDetector,
+    UnitDependencyDetector, UnixSocketDetector,
+};
+use anyhow::{Context, Result};
+use std::fmt;
+use tracing::debug;
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, DependencyInfo};
+
+/// A detector run on the clusters of a bundle.
+pub trait Detector: Send + Sync {
+    /// Name used in logs and errors.
+    fn name(&self) -> &'static str;
+
+    /// Inspect `ctx.bundle` and record findings on `ctx.clusters`.
+    fn detect(&self, ctx: &mut DetectionContext<'_>) -> Result<()>;
+}
+
+impl fmt::Debug for dyn Detector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Built-in detectors, in execution order.
+pub fn default_detectors() -> Vec<Box<dyn Detector>> {
+    vec![
+        Box::new(EndpointDetector),
+        Box::new(DbHostDetector),
+        Box::new(ProxyDetector),
+        Box::new(UnixSocketDetector),
+        Box::new(EnvVarDetector),
+        Box::new(UnitDependencyDetector),
+    ]
+}
+
+/// Run the built-in detectors, then `custom` ones. Returns the external
+/// dependencies found.
+pub fn run_detectors<D: AsRef<dyn Detector>>(
+    bundle: &Bundle,
+    clusters: &mut [AppCluster],
+    custom: &[D],
+) -> Result<Vec<DependencyInfo>> {
+    let builtin = default_detectors();
+    let mut ctx = DetectionContext {
+        bundle,
+        clusters,
+        external: Vec::new(),
+    };
+    let detectors = builtin
+        .iter()
+        .map(|d| d.as_ref())
+        .chain(custom.iter().map(|d| d.as_ref()));
+    for detector in detectors {
+        debug!("Running detector {}", detector.name());
+        detector
+            .detect(&mut ctx)
+            .with_context(|| format!("Detector {} failed", detector.name()))?;
+    }
+    Ok(ctx.external)
+}
+
+/// Bundle and clusters shared with a running detector.
+pub struct DetectionContext<'a> {
+    pub bundle: &'a Bundle,
+    pub clusters: &'a mut [AppCluster],
+    external: Vec<DependencyInfo>,
+}
+
+/// An external service a cluster depends on.
+#[derive(Debug, Clone)]
+pub struct ExternalDependency {
+    /// `database`, `cache`, `messagequeue`, `api`...
+    pub dep_type: String,
+    pub endpoint: String,
+    pub port: Option<u16>,
+}
+
+impl DetectionContext<'_> {
+    /// ID of the cluster listening on `port`.
+    pub fn cluster_for_port(&self, port: u16) -> Option<String> {
+        self.clusters
+            .iter()
+            .find(|c| c.ports.iter().any(|p| p.port == port))
+            .map(|c| c.id.clone())
+    }
+
+    /// Make the cluster at index `cluster` depend on cluster `dep_cluster_id`.
+    /// Returns false, recording nothing, for itself or a known dependency.
+    pub fn add_cluster_dependency(
+        &mut self,
+        cluster: usize,
+        dep_cluster_id: &str,
+        decision: Decision,
+    ) -> bool {
+        let cluster = &mut self.clusters[cluster];
+        if cluster.id == dep_cluster_id || cluster.depends_on.iter().any(|d| d == dep_cluster_id) {
+            return false;
+        }
+        cluster.depends_on.push(dep_cluster_id.to_string());
+        cluster.decisions.push(decision);
+        true
+    }
+
+    /// Record an external dependency of the cluster at index `cluster`.
+    /// Returns its ID (`ext-<n>`), shared by every cluster using the same
+    /// endpoint, however each configuration spells it.
+    pub fn add_external_dependency(
+        &mut self,
+        cluster: usize,
+        dependency: ExternalDependency,
+        decision: Decision,
+    ) -> String {
+        let index = match self.find_external(&dependency) {
+            Some(index) => {
+                // Keep the most precise description of the endpoint
+                let info = &mut self.external[index];
+                if info.port.is_none() && dependency.port.is_some() {
+                    info.endpoint = dependency.endpoint;
+                    info.port = dependency.port;
+                }
+                if info.dep_type == "unknown" {
+                    info.dep_type = dependency.dep_type;
+                }
+                index
+            }
+            None => {
+                let id = format!("ext-{}", self.external.len());
+                debug!(
+                    "External {} dependency {} ({})",
+                    dependency.dep_type, dependency.endpoint, id
+                );
+                self.external.push(DependencyInfo {
+                    id,
+                    dep_type: dependency.dep_type,
+                    endpoint: dependency.endpoint,
+                    port: dependency.port,
+                    used_by: Vec::new(),
+                    evidence_refs: Vec::new(),
+                    addresses: Vec::new(),
+                    internal: None,
+                });
+                self.external.len() - 1
+            }
+        };
+
+        let cluster = &mut self.clusters[cluster];
+        let info = &mut self.external[index];
+        if !info.used_by.contains(&cluster.id) {
+            info.used_by.push(cluster.id.clone());
+        }
+        for evidence_ref in &decision.evidence_refs {
+            if !info.evidence_refs.contains(evidence_ref) {
+                info.evidence_refs.push(evidence_ref.clone());
+            }
+        }
+        if !cluster.external_deps.contains(&info.id) {
+            cluster.external_deps.push(info.id.clone());
+        }
+        cluster.decisions.push(decision);
+        info.id.clone()
+    }
+
+    /// Index of the recorded external dependency on the same endpoint as
+    /// `dependency`: the same host, compared case-insensitively, and port.
+    /// An endpoint without a port, such as a `db_host=` setting, is the
+    /// endpoint of its host when the host has only one.
+    fn find_external(&self, dependency: &ExternalDependency) -> Option<usize> {
+        let host = normalized_host(&dependency.endpoint);
+        let same_host: Vec<usize> = (0..self.external.len())
+            .filter(|&i| normalized_host(&self.external[i].endpoint) == host)
+            .collect();
+        same_host
+            .iter()
+            .copied()
+            .find(|&i| self.external[i].port == dependency.port)
+            .or(match same_host.as_slice() {
+                [i] if self.external[*i].port.is_none() || dependency.port.is_none() => Some(*i),
+                _ => None,
+            })
+    }
+
+    /// Change the application type of the cluster at index `cluster`.
+    pub fn refine_app_type(&mut self, cluster: usize, app_type: &str, decision: Decision) {
+        let cluster = &mut self.clusters[cluster];
+        cluster.app_type = app_type.to_string();
+        cluster.decisions.push(decision);
+    }
+}
+
+/// Host of an endpoint in lowercase, or the whole endpoint when it names
+/// none.
+fn normalized_host(endpoint: &str) -> String {
+    endpoint_host(endpoint)
+        .unwrap_or_else(|| endpoint.to_string())
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Recognizes an in-house message broker from its service name.
+    struct AcmeBusDetector;
+
+    impl Detector for AcmeBusDetector {
+        fn name(&self) -> &'static str {
+            "acme-bus"
+        }
+
+        fn detect(&self, ctx: &mut DetectionContext<'_>) -> Result<()> {
+            for i in 0..ctx.clusters.len() {
+                if ctx.clusters[i].name.contains("acmebus") {
+                    ctx.refine_app_type(
+                        i,
+                        "messagequeue",
+                        Decision::new("Acme bus broker", "service name", vec![], 0.9),
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_detector_runs_after_builtins() {
+        let bundle = Bundle {
+            manifest: Default::default(),
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let mut clusters: Vec<AppCluster> = ["acmebus-broker", "billing"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| AppCluster {
+                id: format!("app-{}", i),
+                name: name.to_string(),
+                app_type: "service".to_string(),
+                confidence: 0.8,
+                ..Default::default()
+            })
+            .collect();
+
+        let custom: Vec<Box<dyn Detector>> = vec![Box::new(AcmeBusDetector)];
+        run_detectors(&bundle, &mut clusters, &custom).unwrap();
+
+        assert_eq!(clusters[0].app_type, "messagequeue");
+        assert_eq!(clusters[0].decisions[0].decision, "Acme bus broker");
+        assert_eq!(clusters[1].app_type, "service");
+        assert_eq!(format!("{:?}", custom[0]), "acme-bus");
+    }
+
+    #[test]
+    fn test_external_dependencies_merged_by_endpoint() {
+        let bundle = Bundle {
+            manifest: Default::default(),
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let mut clusters: Vec<AppCluster> = (0..2)
+            .map(|i| AppCluster {
+                id: format!("app-{}", i),
+                name: "app".to_string(),
+                app_type: "api".to_string(),
+                confidence: 0.8,
+                ..Default::default()
+            })
+            .collect();
+        let mut ctx = DetectionContext {
+            bundle: &bundle,
+            clusters: &mut clusters,
+            external: Vec::new(),
+        };
+        let mut add = |cluster: usize, dep_type: &str, endpoint: &str, port: Option<u16>| {
+            let evidence_ref = format!("evidence/{}.txt", endpoint);
+            ctx.add_external_dependency(
+                cluster,
+                ExternalDependency {
+                    dep_type: dep_type.to_string(),
+                    endpoint: endpoint.to_string(),
+                    port,
+                },
+                Decision::new("External dependency", "config", vec![evidence_ref], 0.8),
+            )
+        };
+
+        let ids = [
+            add(
+                0,
+                "database",
+                "postgres://app@DB.internal:5432/app",
+                Some(5432),
+            ),
+            add(1, "unknown", "host=db.internal", None),
+            add(1, "database", "db.internal:5432", Some(5432)),
+            add(1, "cache", "db.internal:6379", Some(6379)),
+            add(0, "unknown", "host=\"mq.internal\"", None),
+            add(1, "messagequeue", "amqp://mq.internal:5672", Some(5672)),
+        ];
+        assert_eq!(ids, ["ext-0", "ext-0", "ext-0", "ext-1", "ext-2", "ext-2"]);
+
+        let external = &ctx.external;
+        assert_eq!(external.len(), 3);
+        assert_eq!(external[0].used_by, ["app-0", "app-1"]);
+        assert_eq!(external[0].evidence_refs.len(), 3);
+        // The endpoint with a port describes the merged dependency
+        assert_eq!(external[2].endpoint, "amqp://mq.internal:5672");
+        assert_eq!(external[2].dep_type, "messagequeue");
+        assert_eq!(external[2].port, Some(5672));
+        assert_eq!(clusters[1].external_deps, ["ext-0", "ext-1", "ext-2"]);
+    }
+}
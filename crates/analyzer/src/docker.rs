@@ -1,43 +1,374 @@
 //! Docker artifact generation.
+//!
+//! Decisions (base image, healthcheck command, which variables to expose) are
+//! made here; layout lives in the templates rendered by [`TemplateEngine`].
+//! Applications collected from Windows hosts get Windows container artifacts
+//! (Server Core base image, PowerShell entrypoint) unless a Linux rewrite is
+//! asked for.
 
+use crate::clustering;
+use crate::databases;
+use crate::host_ports;
+use crate::templates::{self, TemplateEngine};
 use anyhow::Result;
-use xcprobe_bundle_schema::{AppCluster, ConfigFileSpec, PackPlan};
+use serde::Serialize;
+use std::borrow::Cow;
+use xcprobe_bundle_schema::{
+    AppCluster, ConfigFileSpec, Decision, PackPlan, PackageFile, ResourceUsage, RuntimeInfo,
+};
 
-/// Generate Dockerfile for a cluster.
-pub fn generate_dockerfile(cluster: &AppCluster) -> Result<String> {
-    let mut dockerfile = String::new();
+/// Base image of Windows containers.
+const WINDOWS_BASE_IMAGE: &str = "mcr.microsoft.com/windows/servercore:ltsc2022";
+
+/// Operating system of the generated images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContainerPlatform {
+    #[default]
+    Linux,
+    /// Windows containers, for applications collected from Windows hosts.
+    Windows,
+}
+
+#[derive(Serialize)]
+struct DockerfileContext<'a> {
+    name: &'a str,
+    confidence: String,
+    base_image: String,
+    app_type: &'a str,
+    workdir: String,
+    /// Distribution packages to install.
+    system_packages: &'a [String],
+    has_config_files: bool,
+    app_files: Vec<AppFileContext<'a>>,
+    has_modes: bool,
+    /// Account the container runs as.
+    user: Option<String>,
+    /// Accounts to create: the container's, or the supervised programs'.
+    users: Vec<String>,
+    /// Whether supervisord runs the cluster's programs.
+    supervised: bool,
+    ports: Vec<PortContext<'a>>,
+    env_vars: Vec<EnvVarContext<'a>>,
+    volumes: Vec<&'a str>,
+    healthcheck: Option<HealthcheckContext>,
+    cmd: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AppFileContext<'a> {
+    source: String,
+    destination: String,
+    owner: Option<&'a str>,
+    /// Source mode, when it differs from the mode the copy gets.
+    mode: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct PortContext<'a> {
+    port: u16,
+    /// Host port the compose file publishes the port on.
+    host_port: u16,
+    remapped: bool,
+    protocol: &'a str,
+    purpose: &'a str,
+}
+
+#[derive(Serialize)]
+struct EnvVarContext<'a> {
+    name: &'a str,
+    default_value: Option<Cow<'a, str>>,
+    sensitive: bool,
+}
+
+#[derive(Serialize)]
+struct HealthcheckContext {
+    interval_seconds: u32,
+    timeout_seconds: u32,
+    retries: u32,
+    command: String,
+}
+
+#[derive(Serialize)]
+struct SupervisorContext<'a> {
+    name: &'a str,
+    programs: Vec<ProgramContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct ProgramContext<'a> {
+    name: &'a str,
+    command: String,
+    user: Option<&'a str>,
+    directory: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct EntrypointContext<'a> {
+    name: &'a str,
+    templates: Vec<RenderedTemplateContext<'a>>,
+    has_dependencies: bool,
+    depends_on: &'a [String],
+}
+
+#[derive(Serialize)]
+struct RenderedTemplateContext<'a> {
+    template_name: String,
+    container_path: &'a str,
+    /// The template variables: an `envsubst` shell format for the shell
+    /// entrypoint, so that other `$` in the file are kept, or a list of
+    /// quoted names for the PowerShell one.
+    variables: String,
+}
+
+#[derive(Serialize)]
+struct ReadmeContext<'a> {
+    id: &'a str,
+    name: &'a str,
+    description: Option<&'a str>,
+    app_type: &'a str,
+    confidence_percent: String,
+    services: Vec<ReadmeServiceContext<'a>>,
+    ports: Vec<PortContext<'a>>,
+    env_vars: Vec<ReadmeEnvVarContext<'a>>,
+    has_remapped_ports: bool,
+    config_files: &'a [ConfigFileSpec],
+    package_files: &'a [PackageFile],
+    has_dependencies: bool,
+    depends_on: &'a [String],
+    external_deps: &'a [String],
+    run_env: Vec<&'a str>,
+    /// Official image of a database cluster, run instead of a build.
+    image: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ReadmeServiceContext<'a> {
+    name: &'a str,
+    exec_start: Option<&'a str>,
+    user: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ReadmeEnvVarContext<'a> {
+    name: &'a str,
+    required: &'static str,
+    sensitive: &'static str,
+    description: &'a str,
+}
+
+#[derive(Serialize)]
+struct MigrationContext<'a> {
+    id: &'a str,
+    name: &'a str,
+    engine: &'a str,
+    version: Option<&'a str>,
+    image: &'a str,
+    source_data_dir: Option<&'a str>,
+    data_dir: &'a str,
+    volume: Option<&'a str>,
+    credentials: Vec<ReadmeEnvVarContext<'a>>,
+    dump_command: &'a str,
+    dump_file: &'a str,
+    restore_command: String,
+}
+
+#[derive(Serialize)]
+struct ComposeContext<'a> {
+    services: Vec<ComposeServiceContext<'a>>,
+    /// Batch clusters run by the ofelia scheduler sidecar.
+    batch_jobs: Vec<&'a str>,
+    /// Named volumes of all clusters.
+    volumes: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ComposeServiceContext<'a> {
+    id: &'a str,
+    /// Official image run instead of building the cluster directory.
+    image: Option<&'a str>,
+    /// Port mappings (`8080:8080`, or a host port range for replicas).
+    ports: Vec<String>,
+    /// Whether a `deploy` section (replicas or reservations) is needed.
+    has_deploy: bool,
+    replicas: Option<u32>,
+    /// Resource reservations sized from sampled usage.
+    reservations: Option<ComposeReservations>,
+    has_environment: bool,
+    environment: Vec<ComposeEnvContext<'a>>,
+    depends_on: &'a [String],
+    healthcheck_port: Option<u16>,
+    /// Healthcheck test as a JSON array.
+    healthcheck_test: Option<String>,
+    job: Option<ComposeJobContext>,
+    volumes: Vec<ComposeVolumeContext<'a>>,
+    /// Memory limit sized from the JVM maximum heap.
+    mem_limit: Option<String>,
+}
+
+/// `deploy.resources.reservations` of a service.
+#[derive(Serialize)]
+struct ComposeReservations {
+    /// Cores, rounded up to a twentieth.
+    cpus: String,
+    /// Memory in MiB, e.g. `256M`.
+    memory: String,
+}
+
+impl ComposeReservations {
+    fn from_usage(usage: &ResourceUsage) -> Self {
+        let cpus = ((usage.cpu_p95 * 20.0).ceil() / 20.0).max(0.05);
+        let mib = usage.memory_p95_bytes.div_ceil(1024 * 1024).max(1);
+        Self {
+            cpus: format!("{:.2}", cpus),
+            memory: format!("{}M", mib),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ComposeVolumeContext<'a> {
+    name: &'a str,
+    container_path: &'a str,
+}
+
+/// Ofelia `job-exec` labels for a batch cluster.
+#[derive(Serialize)]
+struct ComposeJobContext {
+    schedule: String,
+    /// Command as a quoted YAML scalar.
+    command: String,
+}
+
+#[derive(Serialize)]
+struct ComposeEnvContext<'a> {
+    name: &'a str,
+    value: String,
+}
+
+/// Compose port mappings of a cluster. Replicas cannot share a host port,
+/// so each replica gets one host port of a range starting at the host
+/// port: the container port, unless another cluster publishes it.
+fn compose_ports(cluster: &AppCluster) -> Vec<String> {
+    let replicas = cluster.replicas.unwrap_or(1);
+    let mut mappings: Vec<String> = Vec::new();
+    for (i, p) in cluster.ports.iter().enumerate() {
+        // A port listed twice, for IPv4 and IPv6, is published once
+        if cluster.ports[..i].iter().any(|q| q.port == p.port) {
+            continue;
+        }
+        let host = p.host_port.unwrap_or(p.port);
+        let mapping = match host_ports::host_range(host, replicas) {
+            Some(range) if replicas > 1 => {
+                format!("{}-{}:{}", range.start(), range.end(), p.port)
+            }
+            _ => format!("{}:{}", host, p.port),
+        };
+        mappings.push(mapping);
+    }
+    mappings
+}
+
+/// Base image for a detected runtime language.
+fn runtime_base_image(language: &str) -> Option<&'static str> {
+    match language {
+        "node" => Some("node:20-alpine"),
+        "python" => Some("python:3.11-slim"),
+        "java" => Some("eclipse-temurin:17-jre-alpine"),
+        "dotnet" => Some("mcr.microsoft.com/dotnet/aspnet:8.0"),
+        "ruby" => Some("ruby:3.2-slim"),
+        "php" => Some("php:8.2-cli"),
+        _ => None,
+    }
+}
+
+/// Base image pinned to the detected runtime version, if the version is known.
+fn pinned_base_image(runtime: &RuntimeInfo) -> Option<String> {
+    let parts: Vec<&str> = runtime.version.as_deref()?.split('.').collect();
+    let major = *parts.first()?;
+    let major_minor = parts.get(1).map(|minor| format!("{}.{}", major, minor));
+
+    match runtime.language.as_str() {
+        "python" => Some(format!("python:{}-slim", major_minor?)),
+        "java" => {
+            // Legacy Java versions are reported as 1.8
+            let major = if major == "1" { *parts.get(1)? } else { major };
+            Some(format!("eclipse-temurin:{}-jre", major))
+        }
+        "node" => Some(format!("node:{}-alpine", major)),
+        "dotnet" => Some(format!("mcr.microsoft.com/dotnet/aspnet:{}", major_minor?)),
+        "ruby" => Some(format!("ruby:{}-slim", major_minor?)),
+        "php" => Some(format!("php:{}-cli", major_minor?)),
+        _ => None,
+    }
+}
+
+/// Select the base image of every cluster that has none yet. Images pinned
+/// to a detected runtime version are recorded with a decision.
+pub fn select_base_images(clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        if cluster.base_image.is_some() {
+            continue;
+        }
+        let pinned = match cluster.runtime {
+            Some(ref runtime) if cluster.app_type != "proxy" => {
+                pinned_base_image(runtime).map(|image| (image, runtime.clone()))
+            }
+            _ => None,
+        };
+
+        let image = match pinned {
+            Some((image, runtime)) => {
+                cluster.decisions.push(Decision::new(
+                    format!("Base image: {}", image),
+                    format!(
+                        "Matches detected {} {} runtime",
+                        runtime.language,
+                        runtime.version.unwrap_or_default()
+                    ),
+                    runtime.evidence_refs,
+                    0.85,
+                ));
+                image
+            }
+            None => default_base_image(cluster).to_string(),
+        };
 
-    // Determine base image based on app type
-    let base_image = match cluster.app_type.as_str() {
+        cluster.base_image = Some(image);
+    }
+}
+
+/// Pick an unpinned base image from the detected runtime, falling back to
+/// the cluster type and the service commands.
+fn default_base_image(cluster: &AppCluster) -> &'static str {
+    if cluster.app_type != "proxy" {
+        if let Some(image) = cluster
+            .runtime
+            .as_ref()
+            .and_then(|r| runtime_base_image(&r.language))
+        {
+            return image;
+        }
+    }
+
+    let exec_contains = |needles: &[&str]| {
+        cluster.services.iter().any(|s| {
+            s.exec_start
+                .as_ref()
+                .map(|e| needles.iter().any(|n| e.contains(n)))
+                .unwrap_or(false)
+        })
+    };
+
+    match cluster.app_type.as_str() {
         "api" | "web" => {
             // Try to detect language
-            if cluster.services.iter().any(|s| {
-                s.exec_start
-                    .as_ref()
-                    .map(|e| e.contains("node") || e.contains("npm"))
-                    .unwrap_or(false)
-            }) {
+            if exec_contains(&["node", "npm"]) {
                 "node:20-alpine"
-            } else if cluster.services.iter().any(|s| {
-                s.exec_start
-                    .as_ref()
-                    .map(|e| e.contains("python"))
-                    .unwrap_or(false)
-            }) {
+            } else if exec_contains(&["python"]) {
                 "python:3.11-slim"
-            } else if cluster.services.iter().any(|s| {
-                s.exec_start
-                    .as_ref()
-                    .map(|e| e.contains("java"))
-                    .unwrap_or(false)
-            }) {
+            } else if exec_contains(&["java"]) {
                 "eclipse-temurin:17-jre-alpine"
-            } else if cluster.services.iter().any(|s| {
-                s.exec_start
-                    .as_ref()
-                    .map(|e| e.contains("dotnet"))
-                    .unwrap_or(false)
-            }) {
+            } else if exec_contains(&["dotnet"]) {
                 "mcr.microsoft.com/dotnet/aspnet:8.0"
             } else {
                 "debian:bookworm-slim"
@@ -46,418 +377,1111 @@ pub fn generate_dockerfile(cluster: &AppCluster) -> Result<String> {
         "proxy" => "nginx:alpine",
         "worker" | "batch" => "debian:bookworm-slim",
         _ => "debian:bookworm-slim",
-    };
+    }
+}
 
-    dockerfile.push_str(&format!(
-        "# Auto-generated Dockerfile for {}\n",
-        cluster.name
-    ));
-    dockerfile.push_str(&format!("# Confidence: {:.2}\n", cluster.confidence));
-    dockerfile.push_str("#\n");
-    dockerfile.push_str("# IMPORTANT: Review and adjust before production use.\n");
-    dockerfile.push_str("# This is a lift-and-shift migration starting point.\n\n");
+/// Name of the rendered template file for a config file.
+pub fn config_template_name(config: &ConfigFileSpec) -> String {
+    // Mirror the container path, so that files with the same name in
+    // different directories keep their own template. Windows paths use
+    // backslashes and a drive letter
+    let path = config
+        .container_path
+        .split(['/', '\\'])
+        .map(|c| c.trim_end_matches(':'))
+        .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.is_empty() {
+        "config.tmpl".to_string()
+    } else {
+        format!("{}.tmpl", path)
+    }
+}
 
-    dockerfile.push_str(&format!("FROM {}\n\n", base_image));
+fn port_contexts(cluster: &AppCluster) -> Vec<PortContext<'_>> {
+    cluster
+        .ports
+        .iter()
+        .map(|p| PortContext {
+            port: p.port,
+            host_port: p.host_port.unwrap_or(p.port),
+            remapped: p.host_port.is_some(),
+            protocol: &p.protocol,
+            purpose: p.purpose.as_deref().unwrap_or("Unknown"),
+        })
+        .collect()
+}
 
-    // Add labels
-    dockerfile.push_str("LABEL maintainer=\"xcprobe-generated\"\n");
-    dockerfile.push_str(&format!("LABEL app.type=\"{}\"\n\n", cluster.app_type));
+/// COPY sources and destinations for the application files fetched by the
+/// pack step. Directories get a trailing slash so their contents are copied.
+fn app_file_contexts(cluster: &AppCluster) -> Vec<AppFileContext<'_>> {
+    cluster
+        .app_files
+        .iter()
+        .filter(|f| f.collected)
+        .map(|f| {
+            let suffix = if f.is_directory() { "/" } else { "" };
+            // Fetched files are written 0644, directories 0755
+            let default_mode = if f.is_directory() { "0755" } else { "0644" };
+            AppFileContext {
+                source: format!("{}{}", f.pack_path(), suffix),
+                destination: format!("{}{}", f.container_path.trim_end_matches('/'), suffix),
+                owner: f.owner.as_deref(),
+                mode: f.mode.as_deref().filter(|m| *m != default_mode),
+            }
+        })
+        .collect()
+}
 
-    // Add working directory
+/// Generate Dockerfile for a cluster.
+pub fn generate_dockerfile(engine: &TemplateEngine, cluster: &AppCluster) -> Result<String> {
     let workdir = cluster
         .services
         .first()
         .and_then(|s| s.working_directory.clone())
+        .or_else(|| {
+            cluster
+                .processes
+                .iter()
+                .find_map(|p| p.working_directory.clone())
+        })
         .unwrap_or_else(|| "/app".to_string());
 
-    dockerfile.push_str(&format!("WORKDIR {}\n\n", workdir));
-
-    // Copy entrypoint
-    dockerfile.push_str("# Copy entrypoint script\n");
-    dockerfile.push_str("COPY entrypoint.sh /entrypoint.sh\n");
-    dockerfile.push_str("RUN chmod +x /entrypoint.sh\n\n");
-
-    // Copy config templates
-    if !cluster.config_files.is_empty() {
-        dockerfile.push_str("# Copy configuration templates\n");
-        dockerfile.push_str("COPY templates/ /templates/\n\n");
-    }
-
-    // Copy application files placeholder
-    dockerfile.push_str("# Copy application files (adjust path as needed)\n");
-    dockerfile.push_str("# COPY pack/ /app/\n\n");
-
-    // Create user if service runs as non-root
-    if let Some(user) = cluster.services.first().and_then(|s| s.user.clone()) {
-        if user != "root" {
-            dockerfile.push_str("# Create application user\n");
-            dockerfile.push_str(&format!(
-                "RUN adduser --disabled-password --gecos '' {} || true\n",
-                user
-            ));
-            dockerfile.push_str(&format!("USER {}\n\n", user));
-        }
-    }
-
-    // Expose ports
-    if !cluster.ports.is_empty() {
-        dockerfile.push_str("# Expose ports\n");
-        for port in &cluster.ports {
-            dockerfile.push_str(&format!("EXPOSE {}\n", port.port));
-        }
-        dockerfile.push('\n');
-    }
-
-    // Environment variables (placeholders only)
-    if !cluster.env_vars.is_empty() {
-        dockerfile.push_str("# Environment variables (set at runtime)\n");
-        for env_var in &cluster.env_vars {
-            if !env_var.sensitive {
-                if let Some(ref default) = env_var.default_value {
-                    dockerfile.push_str(&format!("ENV {}=\"{}\"\n", env_var.name, default));
-                }
-            } else {
-                dockerfile.push_str(&format!(
-                    "# ENV {} - sensitive, set at runtime\n",
-                    env_var.name
-                ));
-            }
-        }
-        dockerfile.push('\n');
-    }
+    // Create user only if service runs as non-root. supervisord runs as
+    // root and starts each program as its own user
+    let supervised = !cluster.programs.is_empty();
+    let user = cluster
+        .services
+        .first()
+        .and_then(|s| s.user.as_deref())
+        .and_then(clustering::image_user)
+        .map(str::to_string)
+        .filter(|_| !supervised);
+    let mut users: Vec<String> = match user {
+        Some(ref user) => vec![user.clone()],
+        None => cluster
+            .programs
+            .iter()
+            .filter_map(|p| p.user.clone())
+            .collect(),
+    };
+    users.sort();
+    users.dedup();
 
-    // Healthcheck
-    if let Some(ref readiness) = cluster.readiness {
-        dockerfile.push_str(&format!(
-            "HEALTHCHECK --interval={}s --timeout={}s --retries={} \\\n",
-            readiness.interval_seconds, readiness.timeout_seconds, readiness.retries
-        ));
-        match readiness.check_type.as_str() {
+    let healthcheck = cluster.readiness.as_ref().map(|readiness| {
+        let command = match readiness.check_type.as_str() {
             "http" => {
                 let path = readiness.path.as_deref().unwrap_or("/health");
                 let port = readiness.port.unwrap_or(80);
-                dockerfile.push_str(&format!(
-                    "  CMD curl -f http://localhost:{}{} || exit 1\n\n",
-                    port, path
-                ));
+                format!("curl -f http://localhost:{}{} || exit 1", port, path)
             }
             "tcp" => {
                 let port = readiness.port.unwrap_or(80);
-                dockerfile.push_str(&format!("  CMD nc -z localhost {} || exit 1\n\n", port));
-            }
-            _ => {
-                dockerfile.push_str("  CMD exit 0\n\n");
+                format!("nc -z localhost {} || exit 1", port)
             }
+            _ => "exit 0".to_string(),
+        };
+        HealthcheckContext {
+            interval_seconds: readiness.interval_seconds,
+            timeout_seconds: readiness.timeout_seconds,
+            retries: readiness.retries,
+            command,
         }
-    }
+    });
 
-    // Entrypoint
-    dockerfile.push_str("ENTRYPOINT [\"/entrypoint.sh\"]\n");
-
-    // Default command from service
-    if let Some(service) = cluster.services.first() {
-        if let Some(ref exec_start) = service.exec_start {
-            // Parse exec_start to extract command and args
-            let parts: Vec<&str> = exec_start.split_whitespace().collect();
-            if !parts.is_empty() {
-                let cmd_json: Vec<String> = parts.iter().map(|s| format!("\"{}\"", s)).collect();
-                dockerfile.push_str(&format!("CMD [{}]\n", cmd_json.join(", ")));
-            }
-        }
-    }
+    // Default command from the first service's exec_start, or supervisord
+    let cmd = if supervised {
+        Some(r#""supervisord", "-c", "/etc/supervisord.conf""#.to_string())
+    } else {
+        cluster
+            .services
+            .first()
+            .and_then(|s| s.exec_start.as_ref())
+            .map(|exec_start| {
+                exec_start
+                    .split_whitespace()
+                    .map(|s| format!("\"{}\"", s))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .filter(|cmd| !cmd.is_empty())
+    };
+
+    let app_files = app_file_contexts(cluster);
+    let context = DockerfileContext {
+        name: &cluster.name,
+        confidence: format!("{:.2}", cluster.confidence),
+        base_image: cluster
+            .base_image
+            .clone()
+            .unwrap_or_else(|| default_base_image(cluster).to_string()),
+        app_type: &cluster.app_type,
+        workdir,
+        system_packages: &cluster.system_packages,
+        has_config_files: !cluster.config_files.is_empty(),
+        has_modes: app_files.iter().any(|f| f.mode.is_some()),
+        app_files,
+        user,
+        users,
+        supervised,
+        ports: port_contexts(cluster),
+        env_vars: cluster
+            .env_vars
+            .iter()
+            .map(|e| EnvVarContext {
+                name: &e.name,
+                default_value: e.default_value.as_deref().map(Cow::Borrowed),
+                sensitive: e.sensitive,
+            })
+            .collect(),
+        volumes: cluster
+            .volumes
+            .iter()
+            .map(|v| v.container_path.as_str())
+            .collect(),
+        healthcheck,
+        cmd,
+    };
 
-    Ok(dockerfile)
+    engine.render(templates::DOCKERFILE, &context)
 }
 
-/// Generate entrypoint.sh script.
-pub fn generate_entrypoint(cluster: &AppCluster) -> Result<String> {
-    let mut script = String::new();
-
-    script.push_str("#!/bin/bash\n");
-    script.push_str("set -e\n\n");
-
-    script.push_str("# Auto-generated entrypoint for ");
-    script.push_str(&cluster.name);
-    script.push_str("\n\n");
-
-    // Render templates
-    if cluster.config_files.iter().any(|c| c.templated) {
-        script.push_str("# Render configuration templates\n");
-        script.push_str("render_template() {\n");
-        script.push_str("  local src=\"$1\"\n");
-        script.push_str("  local dst=\"$2\"\n");
-        script.push_str("  envsubst < \"$src\" > \"$dst\"\n");
-        script.push_str("}\n\n");
-
-        for config in &cluster.config_files {
-            if config.templated {
-                let filename = std::path::Path::new(&config.source_path)
-                    .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "config".to_string());
-
-                script.push_str(&format!(
-                    "render_template /templates/{}.tmpl {}\n",
-                    filename, config.container_path
-                ));
-            }
+/// Windows base image of a cluster: a Windows image set in the plan, else
+/// Server Core, with the ASP.NET Core runtime for .NET applications.
+pub fn windows_base_image(cluster: &AppCluster) -> String {
+    if let Some(image) = cluster
+        .base_image
+        .as_deref()
+        .filter(|i| is_windows_image(i))
+    {
+        return image.to_string();
+    }
+    match cluster.runtime.as_ref() {
+        Some(runtime) if runtime.language == "dotnet" => {
+            let version = runtime
+                .version
+                .as_deref()
+                .and_then(|v| {
+                    let mut parts = v.split('.');
+                    Some(format!("{}.{}", parts.next()?, parts.next()?))
+                })
+                .unwrap_or_else(|| "8.0".to_string());
+            format!(
+                "mcr.microsoft.com/dotnet/aspnet:{}-windowsservercore-ltsc2022",
+                version
+            )
         }
-        script.push('\n');
+        _ => WINDOWS_BASE_IMAGE.to_string(),
     }
+}
+
+/// PowerShell check failing unless `port` accepts connections.
+fn windows_port_check(port: u16) -> String {
+    format!(
+        "if (-not (Test-NetConnection localhost -Port {} -InformationLevel Quiet)) {{ exit 1 }}",
+        port
+    )
+}
+
+/// Whether an image runs on Windows hosts only.
+pub fn is_windows_image(image: &str) -> bool {
+    image.starts_with("mcr.microsoft.com/windows/")
+        || image.contains("servercore")
+        || image.contains("nanoserver")
+}
 
-    // Wait for dependencies
-    if !cluster.depends_on.is_empty() || !cluster.external_deps.is_empty() {
-        script.push_str("# Wait for dependencies\n");
-        script.push_str("wait_for_port() {\n");
-        script.push_str("  local host=\"$1\"\n");
-        script.push_str("  local port=\"$2\"\n");
-        script.push_str("  local retries=\"${3:-30}\"\n");
-        script.push_str("  local wait=\"${4:-2}\"\n");
-        script.push_str("  \n");
-        script.push_str("  echo \"Waiting for $host:$port...\"\n");
-        script.push_str("  for i in $(seq 1 $retries); do\n");
-        script.push_str("    if nc -z \"$host\" \"$port\" 2>/dev/null; then\n");
-        script.push_str("      echo \"$host:$port is available\"\n");
-        script.push_str("      return 0\n");
-        script.push_str("    fi\n");
-        script.push_str("    sleep $wait\n");
-        script.push_str("  done\n");
-        script.push_str("  echo \"Timeout waiting for $host:$port\"\n");
-        script.push_str("  return 1\n");
-        script.push_str("}\n\n");
-
-        // Add example wait calls (user needs to configure)
-        script.push_str("# Example dependency waits (configure as needed):\n");
-        for dep in &cluster.depends_on {
-            script.push_str(&format!("# wait_for_port {} <port>\n", dep));
+/// A Windows command line as exec-form arguments: the executable, quoted
+/// or ending in `.exe`, then its arguments.
+fn windows_command(exec_start: &str) -> Vec<String> {
+    let exec_start = exec_start.trim();
+    let (program, arguments) = match exec_start.strip_prefix('"') {
+        Some(rest) => rest.split_once('"').unwrap_or((rest, "")),
+        // ASCII lowercase keeps byte offsets
+        None => match exec_start.to_ascii_lowercase().find(".exe") {
+            Some(end) => exec_start.split_at(end + 4),
+            None => exec_start
+                .split_once(char::is_whitespace)
+                .unwrap_or((exec_start, "")),
+        },
+    };
+    std::iter::once(program)
+        .chain(arguments.split_whitespace())
+        .filter(|a| !a.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Generate the Windows container Dockerfile of a cluster.
+pub fn generate_windows_dockerfile(
+    engine: &TemplateEngine,
+    cluster: &AppCluster,
+) -> Result<String> {
+    let command = cluster
+        .services
+        .first()
+        .and_then(|s| s.exec_start.as_deref())
+        .map(windows_command)
+        .unwrap_or_default();
+
+    // Windows accepts forward slashes, which need no escaping in a Dockerfile
+    let workdir = cluster
+        .services
+        .first()
+        .and_then(|s| s.working_directory.clone())
+        .or_else(|| {
+            let program = command.first()?;
+            program.rsplit_once('\\').map(|(dir, _)| dir.to_string())
+        })
+        .unwrap_or_else(|| "C:\\app".to_string())
+        .replace('\\', "/");
+
+    let healthcheck = cluster.readiness.as_ref().map(|readiness| {
+        let port = readiness.port.unwrap_or(80);
+        let script = match readiness.check_type.as_str() {
+            "http" => format!(
+                "try {{ Invoke-WebRequest -UseBasicParsing -Uri http://localhost:{}{} | Out-Null }} catch {{ exit 1 }}",
+                port,
+                readiness.path.as_deref().unwrap_or("/health")
+            ),
+            "tcp" => windows_port_check(port),
+            _ => "exit 0".to_string(),
+        };
+        HealthcheckContext {
+            interval_seconds: readiness.interval_seconds,
+            timeout_seconds: readiness.timeout_seconds,
+            retries: readiness.retries,
+            command: serde_json::json!(["powershell", "-NoProfile", "-Command", script])
+                .to_string(),
         }
-        script.push('\n');
-    }
+    });
 
-    // Execute command
-    script.push_str("# Execute the main command\n");
-    script.push_str("exec \"$@\"\n");
+    let app_files = app_file_contexts(cluster);
+    let context = DockerfileContext {
+        name: &cluster.name,
+        confidence: format!("{:.2}", cluster.confidence),
+        base_image: windows_base_image(cluster),
+        app_type: &cluster.app_type,
+        workdir,
+        system_packages: &[],
+        has_config_files: !cluster.config_files.is_empty(),
+        has_modes: false,
+        app_files,
+        user: cluster
+            .services
+            .first()
+            .and_then(|s| s.user.as_deref())
+            .and_then(clustering::image_user)
+            .map(str::to_string),
+        users: Vec::new(),
+        supervised: false,
+        ports: port_contexts(cluster),
+        // Backslashes of Windows paths are escapes in quoted Dockerfile values
+        env_vars: cluster
+            .env_vars
+            .iter()
+            .map(|e| EnvVarContext {
+                name: &e.name,
+                default_value: e
+                    .default_value
+                    .as_deref()
+                    .map(|v| Cow::Owned(v.replace('\\', "\\\\").replace('"', "\\\""))),
+                sensitive: e.sensitive,
+            })
+            .collect(),
+        volumes: cluster
+            .volumes
+            .iter()
+            .map(|v| v.container_path.as_str())
+            .collect(),
+        healthcheck,
+        cmd: Some(
+            command
+                .iter()
+                .map(|a| serde_json::Value::from(a.as_str()).to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+        .filter(|cmd| !cmd.is_empty()),
+    };
 
-    Ok(script)
+    engine.render(templates::DOCKERFILE_WINDOWS, &context)
 }
 
-/// Generate a config template.
-pub fn generate_config_template(config: &ConfigFileSpec) -> Result<String> {
-    let mut template = String::new();
-
-    template.push_str("# Auto-generated template from ");
-    template.push_str(&config.source_path);
-    template.push('\n');
-    template.push_str("#\n");
-    template.push_str("# Template variables:\n");
-    for var in &config.template_vars {
-        template.push_str(&format!("#   ${{{}}}\n", var));
+/// Generate the supervisord configuration of a multi-process cluster.
+/// Other clusters have none.
+pub fn generate_supervisord_conf(
+    engine: &TemplateEngine,
+    cluster: &AppCluster,
+) -> Result<Option<String>> {
+    if cluster.programs.is_empty() {
+        return Ok(None);
     }
-    template.push_str("#\n");
-    template.push_str("# Replace the content below with actual configuration,\n");
-    template.push_str("# using ${VAR_NAME} syntax for templated values.\n\n");
+    let context = SupervisorContext {
+        name: &cluster.name,
+        programs: cluster
+            .programs
+            .iter()
+            .map(|p| ProgramContext {
+                name: &p.name,
+                // systemd prefixes (`-`, `@`, `+`, `!`) are not part of the
+                // command, and `%` starts a supervisord expansion
+                command: p
+                    .command
+                    .trim_start_matches(['-', '@', '+', '!'])
+                    .replace('%', "%%"),
+                user: p.user.as_deref(),
+                directory: p.directory.as_deref(),
+            })
+            .collect(),
+    };
+    engine.render(templates::SUPERVISORD, &context).map(Some)
+}
 
-    // Placeholder content
-    for var in &config.template_vars {
-        template.push_str(&format!("# {}=${{{}}}\n", var, var));
-    }
+/// Generate entrypoint.sh script.
+pub fn generate_entrypoint(engine: &TemplateEngine, cluster: &AppCluster) -> Result<String> {
+    let context = EntrypointContext {
+        name: &cluster.name,
+        templates: cluster
+            .config_files
+            .iter()
+            .filter(|c| c.templated)
+            .map(|c| RenderedTemplateContext {
+                template_name: config_template_name(c),
+                container_path: &c.container_path,
+                variables: c
+                    .template_vars
+                    .iter()
+                    .map(|v| format!("${{{}}}", v))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect(),
+        has_dependencies: !cluster.depends_on.is_empty() || !cluster.external_deps.is_empty(),
+        depends_on: &cluster.depends_on,
+    };
+
+    engine.render(templates::ENTRYPOINT, &context)
+}
+
+/// Generate the entrypoint.ps1 script of a Windows container.
+pub fn generate_windows_entrypoint(
+    engine: &TemplateEngine,
+    cluster: &AppCluster,
+) -> Result<String> {
+    let context = EntrypointContext {
+        name: &cluster.name,
+        templates: cluster
+            .config_files
+            .iter()
+            .filter(|c| c.templated)
+            .map(|c| RenderedTemplateContext {
+                template_name: config_template_name(c),
+                container_path: &c.container_path,
+                variables: c
+                    .template_vars
+                    .iter()
+                    .map(|v| format!("'{}'", v))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+            .collect(),
+        has_dependencies: !cluster.depends_on.is_empty() || !cluster.external_deps.is_empty(),
+        depends_on: &cluster.depends_on,
+    };
 
-    Ok(template)
+    engine.render(templates::ENTRYPOINT_WINDOWS, &context)
+}
+
+/// Generate a config template.
+pub fn generate_config_template(
+    engine: &TemplateEngine,
+    config: &ConfigFileSpec,
+) -> Result<String> {
+    engine.render(templates::CONFIG_TEMPLATE, config)
 }
 
 /// Generate README for a cluster.
-pub fn generate_readme(cluster: &AppCluster) -> Result<String> {
-    let mut readme = String::new();
+pub fn generate_readme(engine: &TemplateEngine, cluster: &AppCluster) -> Result<String> {
+    let yes_no = |b: bool| if b { "Yes" } else { "No" };
 
-    readme.push_str(&format!("# {}\n\n", cluster.name));
+    let context = ReadmeContext {
+        id: &cluster.id,
+        name: &cluster.name,
+        description: cluster.description.as_deref(),
+        app_type: &cluster.app_type,
+        confidence_percent: format!("{:.0}", cluster.confidence * 100.0),
+        services: cluster
+            .services
+            .iter()
+            .map(|s| ReadmeServiceContext {
+                name: &s.name,
+                exec_start: s.exec_start.as_deref(),
+                user: s.user.as_deref(),
+            })
+            .collect(),
+        ports: port_contexts(cluster),
+        env_vars: cluster
+            .env_vars
+            .iter()
+            .map(|e| ReadmeEnvVarContext {
+                name: &e.name,
+                required: yes_no(e.required),
+                sensitive: yes_no(e.sensitive),
+                description: e.description.as_deref().unwrap_or(""),
+            })
+            .collect(),
+        has_remapped_ports: cluster.ports.iter().any(|p| p.host_port.is_some()),
+        config_files: &cluster.config_files,
+        package_files: &cluster.package_files,
+        has_dependencies: !cluster.depends_on.is_empty() || !cluster.external_deps.is_empty(),
+        depends_on: &cluster.depends_on,
+        external_deps: &cluster.external_deps,
+        run_env: cluster
+            .env_vars
+            .iter()
+            .filter(|e| e.required && !e.sensitive)
+            .map(|e| e.name.as_str())
+            .collect(),
+        image: cluster.database.as_ref().map(|d| d.image.as_str()),
+    };
 
-    if let Some(ref desc) = cluster.description {
-        readme.push_str(&format!("{}\n\n", desc));
-    }
+    engine.render(templates::README, &context)
+}
 
-    readme.push_str("## Overview\n\n");
-    readme.push_str(&format!("- **Type**: {}\n", cluster.app_type));
-    readme.push_str(&format!(
-        "- **Confidence**: {:.0}%\n",
-        cluster.confidence * 100.0
-    ));
-    readme.push('\n');
-
-    // Services
-    if !cluster.services.is_empty() {
-        readme.push_str("## Services\n\n");
-        for service in &cluster.services {
-            readme.push_str(&format!("- **{}**\n", service.name));
-            if let Some(ref exec) = service.exec_start {
-                readme.push_str(&format!("  - Command: `{}`\n", exec));
-            }
-            if let Some(ref user) = service.user {
-                readme.push_str(&format!("  - User: `{}`\n", user));
+/// Generate the data migration guide of a database cluster run from its
+/// official image. Other clusters have none.
+pub fn generate_migration(engine: &TemplateEngine, cluster: &AppCluster) -> Result<Option<String>> {
+    let Some(database) = cluster.database.as_ref() else {
+        return Ok(None);
+    };
+    let Some(db_engine) = databases::engine(&database.engine) else {
+        anyhow::bail!(
+            "Unknown database engine {} in cluster {}",
+            database.engine,
+            cluster.id
+        );
+    };
+
+    let context = MigrationContext {
+        id: &cluster.id,
+        name: &cluster.name,
+        engine: &database.engine,
+        version: database.version.as_deref(),
+        image: &database.image,
+        source_data_dir: database.source_data_dir.as_deref(),
+        data_dir: &database.data_dir,
+        volume: cluster
+            .volumes
+            .iter()
+            .find(|v| v.container_path == database.data_dir)
+            .map(|v| v.name.as_str()),
+        credentials: cluster
+            .env_vars
+            .iter()
+            .filter(|e| db_engine.credentials.iter().any(|c| c.name == e.name))
+            .map(|e| ReadmeEnvVarContext {
+                name: &e.name,
+                required: if e.required { "Yes" } else { "No" },
+                sensitive: if e.sensitive { "Yes" } else { "No" },
+                description: e.description.as_deref().unwrap_or(""),
+            })
+            .collect(),
+        dump_command: db_engine.dump_command,
+        dump_file: db_engine.dump_file,
+        restore_command: db_engine.restore_command.replace("{service}", &cluster.id),
+    };
+
+    engine.render(templates::MIGRATION, &context).map(Some)
+}
+
+/// Compose healthcheck test of a cluster: the engine's own check for
+/// databases (their images have no `nc`), a TCP check of the first port
+/// otherwise, with PowerShell in Windows containers.
+fn healthcheck_test(cluster: &AppCluster, platform: ContainerPlatform) -> Option<String> {
+    let test: Vec<String> = match cluster
+        .database
+        .as_ref()
+        .and_then(|d| databases::engine(&d.engine))
+    {
+        Some(engine) => engine.healthcheck.iter().map(|s| s.to_string()).collect(),
+        None => {
+            let port = cluster.ports.first()?.port;
+            match platform {
+                ContainerPlatform::Linux => ["CMD", "nc", "-z", "localhost", &port.to_string()]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                ContainerPlatform::Windows => ["CMD", "powershell", "-NoProfile", "-Command"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .chain(std::iter::once(windows_port_check(port)))
+                    .collect(),
             }
         }
-        readme.push('\n');
-    }
+    };
+    serde_json::to_string(&test).ok()
+}
 
-    // Ports
-    if !cluster.ports.is_empty() {
-        readme.push_str("## Ports\n\n");
-        readme.push_str("| Port | Protocol | Purpose |\n");
-        readme.push_str("|------|----------|--------|\n");
-        for port in &cluster.ports {
-            let purpose = port.purpose.as_deref().unwrap_or("Unknown");
-            readme.push_str(&format!(
-                "| {} | {} | {} |\n",
-                port.port, port.protocol, purpose
-            ));
-        }
-        readme.push('\n');
+/// Generate docker-compose.yaml for all clusters.
+pub fn generate_compose(
+    engine: &TemplateEngine,
+    plan: &PackPlan,
+    platform: ContainerPlatform,
+) -> Result<String> {
+    let services = plan
+        .clusters
+        .iter()
+        .map(|cluster| ComposeServiceContext {
+            id: &cluster.id,
+            image: cluster.database.as_ref().map(|d| d.image.as_str()),
+            ports: compose_ports(cluster),
+            has_deploy: cluster.replicas.is_some_and(|n| n > 1) || cluster.resources.is_some(),
+            replicas: cluster.replicas.filter(|&n| n > 1),
+            reservations: cluster
+                .resources
+                .as_ref()
+                .map(ComposeReservations::from_usage),
+            has_environment: !cluster.env_vars.is_empty(),
+            // Sensitive values are never written to the compose file; the
+            // credentials of database images must be set in the environment
+            environment: cluster
+                .env_vars
+                .iter()
+                .filter(|e| !e.sensitive || cluster.database.is_some())
+                .map(|e| ComposeEnvContext {
+                    name: &e.name,
+                    value: match e.default_value {
+                        Some(ref value) if !e.sensitive => value.clone(),
+                        _ if e.sensitive => format!("${{{0}:?{0} is required}}", e.name),
+                        _ => format!("${{{}:-}}", e.name),
+                    },
+                })
+                .collect(),
+            depends_on: &cluster.depends_on,
+            healthcheck_port: cluster.ports.first().map(|p| p.port),
+            healthcheck_test: healthcheck_test(cluster, platform),
+            job: compose_job(cluster),
+            volumes: cluster
+                .volumes
+                .iter()
+                .map(|v| ComposeVolumeContext {
+                    name: &v.name,
+                    container_path: &v.container_path,
+                })
+                .collect(),
+            mem_limit: cluster
+                .java
+                .as_ref()
+                .and_then(|j| j.max_heap.as_deref())
+                .and_then(crate::java::memory_limit),
+        })
+        .collect();
+    let batch_jobs = plan
+        .clusters
+        .iter()
+        .filter(|c| compose_job(c).is_some())
+        .map(|c| c.id.as_str())
+        .collect();
+    let volumes = plan
+        .clusters
+        .iter()
+        .flat_map(|c| c.volumes.iter().map(|v| v.name.as_str()))
+        .collect();
+
+    engine.render(
+        templates::COMPOSE,
+        &ComposeContext {
+            services,
+            batch_jobs,
+            volumes,
+        },
+    )
+}
+
+/// Ofelia job for a batch cluster. Ofelia schedules have a leading seconds
+/// field; shell syntax in the command needs an explicit shell since ofelia
+/// execs it directly.
+fn compose_job(cluster: &AppCluster) -> Option<ComposeJobContext> {
+    if cluster.app_type != "batch" {
+        return None;
     }
+    let schedule = cluster.schedule.as_deref()?;
+    let command = cluster.services.first()?.exec_start.as_deref()?;
+
+    let schedule = if schedule.starts_with('@') {
+        schedule.to_string()
+    } else {
+        format!("0 {}", schedule)
+    };
+    let command = if command.contains(|c: char| "&|;<>$`*".contains(c)) {
+        format!("sh -c '{}'", command.replace('\'', "'\\''"))
+    } else {
+        command.to_string()
+    };
 
-    // Environment Variables
-    if !cluster.env_vars.is_empty() {
-        readme.push_str("## Environment Variables\n\n");
-        readme.push_str("| Variable | Required | Sensitive | Description |\n");
-        readme.push_str("|----------|----------|-----------|-------------|\n");
-        for env in &cluster.env_vars {
-            let required = if env.required { "Yes" } else { "No" };
-            let sensitive = if env.sensitive { "Yes" } else { "No" };
-            let desc = env.description.as_deref().unwrap_or("");
-            readme.push_str(&format!(
-                "| {} | {} | {} | {} |\n",
-                env.name, required, sensitive, desc
-            ));
+    Some(ComposeJobContext {
+        schedule,
+        command: serde_json::to_string(&command).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{
+        AppFileSpec, ClusterPort, ClusterService, DatabaseInfo, EnvVarSpec, ReadinessCheck,
+        SupervisedProgram, VolumeSpec,
+    };
+
+    fn runtime(language: &str, version: Option<&str>) -> RuntimeInfo {
+        RuntimeInfo {
+            language: language.to_string(),
+            version: version.map(|v| v.to_string()),
+            evidence_refs: vec!["evidence/packages_1.txt".to_string()],
         }
-        readme.push('\n');
     }
 
-    // Config Files
-    if !cluster.config_files.is_empty() {
-        readme.push_str("## Configuration Files\n\n");
-        for config in &cluster.config_files {
-            readme.push_str(&format!(
-                "- `{}` -> `{}`",
-                config.source_path, config.container_path
-            ));
-            if config.templated {
-                readme.push_str(" (templated)");
-            }
-            readme.push('\n');
-        }
-        readme.push('\n');
+    #[test]
+    fn test_pinned_base_image() {
+        assert_eq!(
+            pinned_base_image(&runtime("python", Some("3.11.7"))).as_deref(),
+            Some("python:3.11-slim")
+        );
+        assert_eq!(
+            pinned_base_image(&runtime("java", Some("17"))).as_deref(),
+            Some("eclipse-temurin:17-jre")
+        );
+        assert_eq!(
+            pinned_base_image(&runtime("java", Some("1.8.0"))).as_deref(),
+            Some("eclipse-temurin:8-jre")
+        );
+        assert_eq!(
+            pinned_base_image(&runtime("node", Some("20.11.1"))).as_deref(),
+            Some("node:20-alpine")
+        );
+        // Not enough version information to pin
+        assert_eq!(pinned_base_image(&runtime("python", Some("3"))), None);
+        assert_eq!(pinned_base_image(&runtime("node", None)), None);
     }
 
-    // Dependencies
-    if !cluster.depends_on.is_empty() || !cluster.external_deps.is_empty() {
-        readme.push_str("## Dependencies\n\n");
-        if !cluster.depends_on.is_empty() {
-            readme.push_str("### Internal Dependencies\n\n");
-            for dep in &cluster.depends_on {
-                readme.push_str(&format!("- {}\n", dep));
-            }
-            readme.push('\n');
-        }
-        if !cluster.external_deps.is_empty() {
-            readme.push_str("### External Dependencies\n\n");
-            for dep in &cluster.external_deps {
-                readme.push_str(&format!("- {}\n", dep));
-            }
-            readme.push('\n');
-        }
+    #[test]
+    fn test_select_base_images_records_decision() {
+        let mut cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: "api".to_string(),
+            runtime: Some(runtime("java", Some("17"))),
+            confidence: 0.8,
+            ..Default::default()
+        };
+
+        select_base_images(std::slice::from_mut(&mut cluster));
+        assert_eq!(
+            cluster.base_image.as_deref(),
+            Some("eclipse-temurin:17-jre")
+        );
+        assert_eq!(cluster.decisions.len(), 1);
+        assert_eq!(
+            cluster.decisions[0].evidence_refs,
+            vec!["evidence/packages_1.txt"]
+        );
+
+        // Without a version the unpinned default is used, with no decision
+        cluster.runtime = Some(runtime("java", None));
+        cluster.base_image = None;
+        cluster.decisions.clear();
+        select_base_images(std::slice::from_mut(&mut cluster));
+        assert_eq!(
+            cluster.base_image.as_deref(),
+            Some("eclipse-temurin:17-jre-alpine")
+        );
+        assert!(cluster.decisions.is_empty());
     }
 
-    // Build & Run
-    readme.push_str("## Build & Run\n\n");
-    readme.push_str("```bash\n");
-    readme.push_str("# Build the image\n");
-    readme.push_str(&format!("docker build -t {} .\n\n", cluster.name));
-    readme.push_str("# Run the container\n");
-    readme.push_str("docker run -d");
-    for port in &cluster.ports {
-        readme.push_str(&format!(" -p {}:{}", port.port, port.port));
+    #[test]
+    fn test_compose_schedules_batch_jobs_with_ofelia() {
+        let cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "invoice-export".to_string(),
+            app_type: "batch".to_string(),
+            services: vec![ClusterService {
+                name: "invoice-export".to_string(),
+                exec_start: Some("cd /opt/billing && ./export > /var/log/export.log".to_string()),
+                user: Some("app".to_string()),
+                ..Default::default()
+            }],
+            schedule: Some("*/5 * * * *".to_string()),
+            resources: Some(ResourceUsage {
+                cpu_p95: 0.12,
+                memory_p95_bytes: 200 * 1024 * 1024 + 1,
+                samples: 13,
+                evidence_refs: vec![],
+            }),
+            confidence: 0.7,
+            ..Default::default()
+        };
+        let plan = PackPlan {
+            schema_version: "1.0.0".to_string(),
+            generated_at: chrono::Utc::now(),
+            source_bundle_id: "test".to_string(),
+            source_os: None,
+            clusters: vec![cluster],
+            external_dependencies: vec![],
+            startup_dag: vec![],
+            artifacts: vec![],
+            overall_confidence: 0.7,
+            warnings: vec![],
+            rejected_clusters: vec![],
+        };
+
+        let compose = generate_compose(
+            &TemplateEngine::new().unwrap(),
+            &plan,
+            ContainerPlatform::Linux,
+        )
+        .unwrap();
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&compose).unwrap();
+        let labels = &yaml["services"]["app-0"]["labels"];
+        assert_eq!(
+            labels["ofelia.job-exec.app-0.schedule"].as_str(),
+            Some("0 */5 * * * *")
+        );
+        assert_eq!(
+            labels["ofelia.job-exec.app-0.command"].as_str(),
+            Some("sh -c 'cd /opt/billing && ./export > /var/log/export.log'")
+        );
+        assert_eq!(
+            yaml["services"]["ofelia"]["image"].as_str(),
+            Some("mcuadros/ofelia:latest")
+        );
+        let reservations = &yaml["services"]["app-0"]["deploy"]["resources"]["reservations"];
+        assert_eq!(reservations["cpus"].as_str(), Some("0.15"));
+        assert_eq!(reservations["memory"].as_str(), Some("201M"));
+        assert!(yaml["services"]["app-0"]["deploy"]["replicas"].is_null());
     }
-    for env in &cluster.env_vars {
-        if env.required && !env.sensitive {
-            readme.push_str(&format!(" -e {}=<value>", env.name));
-        }
+
+    #[test]
+    fn test_compose_ports_for_replicas() {
+        let mut cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "worker".to_string(),
+            app_type: "worker".to_string(),
+            ports: vec![ClusterPort {
+                port: 8001,
+                protocol: "tcp".to_string(),
+                ..Default::default()
+            }],
+            confidence: 0.9,
+            ..Default::default()
+        };
+        assert_eq!(compose_ports(&cluster), vec!["8001:8001"]);
+        cluster.replicas = Some(3);
+        assert_eq!(compose_ports(&cluster), vec!["8001-8003:8001"]);
+        // Remapped host ports, published once for IPv4 and IPv6 listeners
+        cluster.ports[0].host_port = Some(8004);
+        cluster.ports.push(cluster.ports[0].clone());
+        assert_eq!(compose_ports(&cluster), vec!["8004-8006:8001"]);
+
+        let readme = generate_readme(&TemplateEngine::new().unwrap(), &cluster).unwrap();
+        assert!(readme.contains("- 8001 on host port 8004\n"));
+        assert!(readme.contains(" -p 8004:8001 "));
     }
-    readme.push_str(&format!(" {}\n", cluster.name));
-    readme.push_str("```\n\n");
 
-    // Notes
-    readme.push_str("## Notes\n\n");
-    readme.push_str("This Dockerfile was auto-generated by xcprobe analyzer.\n");
-    readme.push_str("Review the following before production use:\n\n");
-    readme.push_str("- [ ] Verify base image is appropriate\n");
-    readme.push_str("- [ ] Add application files to the image\n");
-    readme.push_str("- [ ] Configure environment variables\n");
-    readme.push_str("- [ ] Review and adjust config templates\n");
-    readme.push_str("- [ ] Set up proper health checks\n");
-    readme.push_str("- [ ] Configure logging\n");
+    #[test]
+    fn test_database_cluster_uses_official_image() {
+        let cluster = AppCluster {
+            id: "app-1".to_string(),
+            name: "postgresql".to_string(),
+            app_type: "database".to_string(),
+            ports: vec![ClusterPort {
+                port: 5432,
+                protocol: "tcp".to_string(),
+                ..Default::default()
+            }],
+            env_vars: vec![
+                EnvVarSpec {
+                    name: "POSTGRES_USER".to_string(),
+                    default_value: Some("billing".to_string()),
+                    description: Some("Superuser name".to_string()),
+                    ..Default::default()
+                },
+                EnvVarSpec {
+                    name: "POSTGRES_PASSWORD".to_string(),
+                    required: true,
+                    description: Some("Superuser password".to_string()),
+                    sensitive: true,
+                    ..Default::default()
+                },
+            ],
+            volumes: vec![VolumeSpec {
+                name: "app-1-pgdata".to_string(),
+                container_path: "/var/lib/postgresql/data".to_string(),
+                kind: "database".to_string(),
+                evidence_ref: None,
+            }],
+            database: Some(DatabaseInfo {
+                engine: "postgres".to_string(),
+                version: Some("15".to_string()),
+                image: "postgres:15".to_string(),
+                source_data_dir: Some("/srv/pgdata".to_string()),
+                data_dir: "/var/lib/postgresql/data".to_string(),
+                evidence_refs: vec![],
+            }),
+            confidence: 0.9,
+            ..Default::default()
+        };
+        let engine = TemplateEngine::new().unwrap();
+        let plan = PackPlan {
+            schema_version: "1.0.0".to_string(),
+            generated_at: chrono::Utc::now(),
+            source_bundle_id: "test".to_string(),
+            source_os: None,
+            clusters: vec![cluster.clone()],
+            external_dependencies: vec![],
+            startup_dag: vec![],
+            artifacts: vec![],
+            overall_confidence: 0.9,
+            warnings: vec![],
+            rejected_clusters: vec![],
+        };
 
-    Ok(readme)
-}
+        let compose = generate_compose(&engine, &plan, ContainerPlatform::Linux).unwrap();
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&compose).unwrap();
+        let service = &yaml["services"]["app-1"];
+        assert_eq!(service["image"].as_str(), Some("postgres:15"));
+        assert!(service["build"].is_null());
+        assert_eq!(
+            service["healthcheck"]["test"][1].as_str(),
+            Some("pg_isready")
+        );
+        assert_eq!(
+            service["environment"]["POSTGRES_PASSWORD"].as_str(),
+            Some("${POSTGRES_PASSWORD:?POSTGRES_PASSWORD is required}")
+        );
+        assert_eq!(
+            service["environment"]["POSTGRES_USER"].as_str(),
+            Some("billing")
+        );
 
-/// Generate docker-compose.yaml for all clusters.
-pub fn generate_compose(plan: &PackPlan) -> Result<String> {
-    let mut compose = String::new();
+        let migration = generate_migration(&engine, &cluster).unwrap().unwrap();
+        assert!(migration.contains("`/srv/pgdata`"));
+        assert!(migration.contains("sudo -u postgres pg_dumpall > dump.sql"));
+        assert!(migration.contains("docker compose exec -T app-1 sh -c"));
+        assert!(!migration.contains("version is unknown"));
+    }
 
-    compose.push_str("# Auto-generated docker-compose.yaml\n");
-    compose.push_str("# Generated by xcprobe analyzer\n\n");
+    #[test]
+    fn test_rewritten_config_template() {
+        let mut cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "web".to_string(),
+            app_type: "web".to_string(),
+            config_files: vec![ConfigFileSpec {
+                source_path: "/etc/nginx/conf.d/app.conf".to_string(),
+                container_path: "/etc/nginx/conf.d/app.conf".to_string(),
+                templated: true,
+                template_vars: vec!["APP_1_ADDR".to_string()],
+                content: Some(
+                    "proxy_pass http://${APP_1_ADDR};\nproxy_set_header Host $host;\n".to_string(),
+                ),
+                evidence_ref: None,
+            }],
+            confidence: 0.9,
+            ..Default::default()
+        };
+        let engine = TemplateEngine::new().unwrap();
 
-    compose.push_str("services:\n");
+        let template = generate_config_template(&engine, &cluster.config_files[0]).unwrap();
+        assert_eq!(
+            template,
+            "proxy_pass http://${APP_1_ADDR};\nproxy_set_header Host $host;\n"
+        );
+        let entrypoint = generate_entrypoint(&engine, &cluster).unwrap();
+        assert!(entrypoint.contains(
+            "render_template '/templates/etc/nginx/conf.d/app.conf.tmpl' '/etc/nginx/conf.d/app.conf' '${APP_1_ADDR}'"
+        ));
 
-    for cluster in &plan.clusters {
-        compose.push_str(&format!("  {}:\n", cluster.id));
-        compose.push_str("    build:\n");
-        compose.push_str(&format!("      context: ./{}\n", cluster.id));
-        compose.push_str("      dockerfile: Dockerfile\n");
+        cluster.config_files[0].content = None;
+        let template = generate_config_template(&engine, &cluster.config_files[0]).unwrap();
+        assert!(template.starts_with("# Auto-generated template from"));
+        assert!(template.contains("# APP_1_ADDR=${APP_1_ADDR}"));
+    }
 
-        // Ports
-        if !cluster.ports.is_empty() {
-            compose.push_str("    ports:\n");
-            for port in &cluster.ports {
-                compose.push_str(&format!("      - \"{}:{}\"\n", port.port, port.port));
+    #[test]
+    fn test_supervised_cluster() {
+        let program = |name: &str, command: &str, user: Option<&str>, directory: Option<&str>| {
+            SupervisedProgram {
+                name: name.to_string(),
+                command: command.to_string(),
+                user: user.map(str::to_string),
+                directory: directory.map(str::to_string),
+                evidence_ref: None,
             }
-        }
+        };
+        let cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "web".to_string(),
+            app_type: "web".to_string(),
+            services: vec![ClusterService {
+                name: "nginx.service".to_string(),
+                exec_start: Some("/usr/sbin/nginx -g 'daemon off;'".to_string()),
+                user: Some("www-data".to_string()),
+                ..Default::default()
+            }],
+            programs: vec![
+                program("nginx", "/usr/sbin/nginx -g 'daemon off;'", None, None),
+                program(
+                    "php-fpm",
+                    "-/usr/sbin/php-fpm8.2 --nodaemonize",
+                    Some("www-data"),
+                    Some("/var/www"),
+                ),
+                program("sync", "/bin/sh -c 'date +%s'", Some("app"), None),
+            ],
+            confidence: 0.9,
+            ..Default::default()
+        };
+        let engine = TemplateEngine::new().unwrap();
 
-        // Environment
-        if !cluster.env_vars.is_empty() {
-            compose.push_str("    environment:\n");
-            for env in &cluster.env_vars {
-                if !env.sensitive {
-                    if let Some(ref default) = env.default_value {
-                        compose.push_str(&format!("      {}: \"{}\"\n", env.name, default));
-                    } else {
-                        compose.push_str(&format!("      {}: \"${{{}:-}}\"\n", env.name, env.name));
-                    }
-                }
-            }
-        }
+        let dockerfile = generate_dockerfile(&engine, &cluster).unwrap();
+        assert!(dockerfile.contains("COPY supervisord.conf /etc/supervisord.conf\n"));
+        assert!(dockerfile.contains("RUN adduser --disabled-password --gecos '' app || true\n"));
+        assert!(
+            dockerfile.contains("RUN adduser --disabled-password --gecos '' www-data || true\n")
+        );
+        assert!(!dockerfile.contains("\nUSER "));
+        assert!(dockerfile.contains(r#"CMD ["supervisord", "-c", "/etc/supervisord.conf"]"#));
+        assert!(crate::lint::lint_dockerfile(&dockerfile).is_empty());
 
-        // Dependencies
-        if !cluster.depends_on.is_empty() {
-            compose.push_str("    depends_on:\n");
-            for dep in &cluster.depends_on {
-                compose.push_str(&format!("      {}:\n", dep));
-                compose.push_str("        condition: service_healthy\n");
-            }
-        }
+        let conf = generate_supervisord_conf(&engine, &cluster)
+            .unwrap()
+            .unwrap();
+        assert!(conf.contains("[supervisord]\nnodaemon=true\n"));
+        assert!(conf.contains(
+            "[program:php-fpm]\ncommand=/usr/sbin/php-fpm8.2 --nodaemonize\n\
+             directory=/var/www\nuser=www-data\nautorestart=true\n"
+        ));
+        assert!(conf.contains(
+            "[program:nginx]\ncommand=/usr/sbin/nginx -g 'daemon off;'\nautorestart=true\n"
+        ));
+        assert!(conf.contains("command=/bin/sh -c 'date +%%s'\n"));
 
-        // Healthcheck
-        if !cluster.ports.is_empty() {
-            let port = cluster.ports[0].port;
-            compose.push_str("    healthcheck:\n");
-            compose.push_str(&format!(
-                "      test: [\"CMD\", \"nc\", \"-z\", \"localhost\", \"{}\"]\n",
-                port
-            ));
-            compose.push_str("      interval: 10s\n");
-            compose.push_str("      timeout: 5s\n");
-            compose.push_str("      retries: 3\n");
-        }
+        let single = AppCluster {
+            programs: Vec::new(),
+            ..cluster
+        };
+        assert!(generate_supervisord_conf(&engine, &single)
+            .unwrap()
+            .is_none());
+        let dockerfile = generate_dockerfile(&engine, &single).unwrap();
+        assert!(dockerfile.contains("\nUSER www-data\n"));
+        assert!(!dockerfile.contains("supervisord"));
+    }
+
+    #[test]
+    fn test_dockerfile_restores_file_modes() {
+        let file = |path: &str, kind: &str, owner: Option<&str>, mode: &str| AppFileSpec {
+            source_path: path.to_string(),
+            container_path: path.to_string(),
+            kind: kind.to_string(),
+            owner: owner.map(str::to_string),
+            mode: Some(mode.to_string()),
+            collected: true,
+            ..Default::default()
+        };
+        let cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: "api".to_string(),
+            app_files: vec![
+                file("/opt/app", "working_directory", Some("app"), "0750"),
+                file("/etc/app/key.pem", "config", Some("root:app"), "0640"),
+                file("/etc/app/app.yaml", "config", None, "0644"),
+            ],
+            confidence: 0.9,
+            ..Default::default()
+        };
 
-        compose.push('\n');
+        let dockerfile = generate_dockerfile(&TemplateEngine::new().unwrap(), &cluster).unwrap();
+        assert!(dockerfile.contains("COPY --chown=root:app pack/etc/app/key.pem /etc/app/key.pem"));
+        assert!(dockerfile.contains("RUN chmod 0750 /opt/app/\n"));
+        assert!(dockerfile.contains("RUN chmod 0640 /etc/app/key.pem\n"));
+        assert!(!dockerfile.contains("chmod 0644"));
     }
 
-    Ok(compose)
+    #[test]
+    fn test_windows_container_artifacts() {
+        let cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "billing".to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: "BillingSvc".to_string(),
+                exec_start: Some(
+                    "\"C:\\Program Files\\Contoso\\Billing\\billing.exe\" -service".to_string(),
+                ),
+                user: Some("CORP\\svc_billing".to_string()),
+                ..Default::default()
+            }],
+            ports: vec![ClusterPort {
+                port: 8080,
+                protocol: "tcp".to_string(),
+                ..Default::default()
+            }],
+            env_vars: vec![EnvVarSpec {
+                name: "INSTALL_DIR".to_string(),
+                required: true,
+                default_value: Some("C:\\Program Files\\Contoso".to_string()),
+                ..Default::default()
+            }],
+            config_files: vec![ConfigFileSpec {
+                source_path: "C:\\ProgramData\\Contoso\\billing.config".to_string(),
+                container_path: "C:\\ProgramData\\Contoso\\billing.config".to_string(),
+                templated: true,
+                template_vars: vec!["APP_1_ADDR".to_string(), "DB_HOST".to_string()],
+                ..Default::default()
+            }],
+            depends_on: vec!["app-1".to_string()],
+            readiness: Some(ReadinessCheck {
+                check_type: "tcp".to_string(),
+                target: None,
+                port: Some(8080),
+                path: None,
+                command: None,
+                timeout_seconds: 5,
+                interval_seconds: 10,
+                retries: 3,
+            }),
+            base_image: Some("debian:bookworm-slim".to_string()),
+            confidence: 0.9,
+            ..Default::default()
+        };
+        let engine = TemplateEngine::new().unwrap();
+
+        let dockerfile = generate_windows_dockerfile(&engine, &cluster).unwrap();
+        assert!(dockerfile.contains("FROM mcr.microsoft.com/windows/servercore:ltsc2022\n"));
+        assert!(dockerfile.contains("WORKDIR C:/Program Files/Contoso/Billing\n"));
+        assert!(dockerfile.contains(r#"ENV INSTALL_DIR="C:\\Program Files\\Contoso""#));
+        assert!(dockerfile
+            .contains(r#"CMD ["C:\\Program Files\\Contoso\\Billing\\billing.exe", "-service"]"#));
+        assert!(dockerfile.contains("# The service ran as svc_billing"));
+        assert!(dockerfile.contains(
+            r#"CMD ["powershell","-NoProfile","-Command","if (-not (Test-NetConnection localhost -Port 8080 -InformationLevel Quiet)) { exit 1 }"]"#
+        ));
+        assert!(crate::lint::lint_dockerfile(&dockerfile).is_empty());
+
+        let entrypoint = generate_windows_entrypoint(&engine, &cluster).unwrap();
+        assert!(entrypoint.contains(
+            r"Render-Template 'C:/templates/C/ProgramData/Contoso/billing.config.tmpl' 'C:\ProgramData\Contoso\billing.config' @('APP_1_ADDR', 'DB_HOST')"
+        ));
+        assert!(entrypoint.contains("# Wait-ForPort app-1 <port>"));
+
+        let plan = PackPlan {
+            clusters: vec![cluster],
+            ..Default::default()
+        };
+        let compose = generate_compose(&engine, &plan, ContainerPlatform::Windows).unwrap();
+        assert!(compose.contains("Test-NetConnection localhost -Port 8080"));
+    }
 }
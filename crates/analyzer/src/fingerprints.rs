@@ -0,0 +1,535 @@
+//! Well-known application fingerprints.
+//!
+//! Off-the-shelf servers (Tomcat, WildFly, Jenkins, Keycloak, databases...)
+//! have documented configuration paths, data directories, ports and
+//! official images. A cluster whose services or processes match a
+//! fingerprint gets its configuration files, data volumes, readiness check
+//! and base image from the fingerprint instead of generic heuristics.
+
+use crate::readiness::{INTERVAL_SECONDS, RETRIES, TIMEOUT_SECONDS};
+use crate::volumes::volume_name;
+use xcprobe_bundle_schema::{
+    AppCluster, Bundle, ConfigFileSpec, Decision, ReadinessCheck, VolumeSpec,
+};
+
+/// Confidence of the decisions taken from a fingerprint.
+const FINGERPRINT_CONFIDENCE: f64 = 0.9;
+
+/// How a fingerprinted application reports readiness.
+#[derive(Debug, Clone, Copy)]
+pub enum Probe {
+    /// TCP connect on the first default port the cluster listens on.
+    Tcp,
+    /// HTTP GET of `path` on `port`, when the cluster listens on it.
+    Http { port: u16, path: &'static str },
+    /// Command run in the container.
+    Command(&'static str),
+}
+
+/// What is known about a well-known application.
+#[derive(Debug)]
+pub struct Fingerprint {
+    pub name: &'static str,
+    pub app_type: &'static str,
+    /// Substrings of the service name, command line or process command.
+    pub markers: &'static [&'static str],
+    /// File name that must be among the collected configuration files,
+    /// for markers shared with other applications (`php-fpm`).
+    pub required_file: Option<&'static str>,
+    /// Substrings of configuration file paths.
+    pub config_paths: &'static [&'static str],
+    pub data_dirs: &'static [&'static str],
+    pub default_ports: &'static [u16],
+    /// Official container image.
+    pub image: &'static str,
+    pub probe: Probe,
+    /// Environment variables the application or its image reads.
+    pub env_vars: &'static [&'static str],
+}
+
+/// Known applications, most specific markers first.
+pub static FINGERPRINTS: &[Fingerprint] = &[
+    Fingerprint {
+        name: "Keycloak",
+        app_type: "web",
+        markers: &["keycloak", "kc.sh"],
+        required_file: None,
+        config_paths: &["/opt/keycloak/conf/"],
+        data_dirs: &["/opt/keycloak/data"],
+        default_ports: &[8080, 8443, 9000],
+        image: "quay.io/keycloak/keycloak:24.0",
+        probe: Probe::Http {
+            port: 9000,
+            path: "/health/ready",
+        },
+        env_vars: &[
+            "KC_DB",
+            "KC_DB_URL",
+            "KC_DB_USERNAME",
+            "KC_DB_PASSWORD",
+            "KC_HOSTNAME",
+            "KEYCLOAK_ADMIN",
+            "KEYCLOAK_ADMIN_PASSWORD",
+        ],
+    },
+    Fingerprint {
+        name: "Jenkins",
+        app_type: "web",
+        markers: &["jenkins"],
+        required_file: None,
+        config_paths: &[
+            "/etc/default/jenkins",
+            "/etc/sysconfig/jenkins",
+            "/var/lib/jenkins/config.xml",
+        ],
+        data_dirs: &["/var/lib/jenkins"],
+        default_ports: &[8080, 50000],
+        image: "jenkins/jenkins:lts-jdk17",
+        probe: Probe::Http {
+            port: 8080,
+            path: "/login",
+        },
+        env_vars: &["JENKINS_HOME", "JENKINS_OPTS", "JAVA_OPTS"],
+    },
+    Fingerprint {
+        name: "WildFly",
+        app_type: "web",
+        markers: &["wildfly", "jboss-modules"],
+        required_file: None,
+        config_paths: &["/standalone/configuration/"],
+        data_dirs: &["/opt/wildfly/standalone/data"],
+        default_ports: &[8080, 9990],
+        image: "quay.io/wildfly/wildfly:31.0.1.Final-jdk17",
+        probe: Probe::Http {
+            port: 9990,
+            path: "/health/ready",
+        },
+        env_vars: &["JBOSS_HOME", "JAVA_OPTS"],
+    },
+    Fingerprint {
+        name: "Tomcat",
+        app_type: "web",
+        markers: &["catalina", "tomcat"],
+        required_file: None,
+        config_paths: &[
+            "/etc/tomcat",
+            "/tomcat/conf/",
+            "/tomcat9/conf/",
+            "/tomcat10/conf/",
+        ],
+        data_dirs: &[],
+        default_ports: &[8080, 8009],
+        image: "tomcat:10.1-jre17",
+        probe: Probe::Tcp,
+        env_vars: &[
+            "CATALINA_HOME",
+            "CATALINA_BASE",
+            "CATALINA_OPTS",
+            "JAVA_OPTS",
+        ],
+    },
+    Fingerprint {
+        name: "Odoo",
+        app_type: "web",
+        markers: &["odoo", "openerp-server"],
+        required_file: None,
+        config_paths: &["/etc/odoo/"],
+        data_dirs: &["/var/lib/odoo"],
+        default_ports: &[8069, 8072],
+        image: "odoo:17",
+        probe: Probe::Http {
+            port: 8069,
+            path: "/web/health",
+        },
+        env_vars: &["HOST", "PORT", "USER", "PASSWORD"],
+    },
+    Fingerprint {
+        name: "WordPress",
+        app_type: "web",
+        markers: &["wordpress", "php-fpm"],
+        required_file: Some("wp-config.php"),
+        config_paths: &["wp-config.php", "/etc/php/"],
+        data_dirs: &["/var/www/html/wp-content/uploads"],
+        default_ports: &[9000],
+        image: "wordpress:6-php8.2-fpm",
+        probe: Probe::Tcp,
+        env_vars: &[
+            "WORDPRESS_DB_HOST",
+            "WORDPRESS_DB_USER",
+            "WORDPRESS_DB_PASSWORD",
+            "WORDPRESS_DB_NAME",
+            "WORDPRESS_TABLE_PREFIX",
+        ],
+    },
+    Fingerprint {
+        name: "SQL Server",
+        app_type: "database",
+        markers: &["sqlservr", "mssql-server"],
+        required_file: None,
+        config_paths: &["/var/opt/mssql/mssql.conf"],
+        data_dirs: &["/var/opt/mssql"],
+        default_ports: &[1433],
+        image: "mcr.microsoft.com/mssql/server:2022-latest",
+        probe: Probe::Tcp,
+        env_vars: &[
+            "ACCEPT_EULA",
+            "MSSQL_SA_PASSWORD",
+            "MSSQL_PID",
+            "MSSQL_TCP_PORT",
+        ],
+    },
+    Fingerprint {
+        name: "Oracle Database",
+        app_type: "database",
+        markers: &["ora_pmon_", "tnslsnr", "oracle-database"],
+        required_file: None,
+        config_paths: &["/network/admin/", "/etc/oratab"],
+        data_dirs: &["/opt/oracle/oradata"],
+        default_ports: &[1521, 5500],
+        image: "container-registry.oracle.com/database/free:latest",
+        probe: Probe::Command("/opt/oracle/checkDBStatus.sh"),
+        env_vars: &[
+            "ORACLE_PWD",
+            "ORACLE_SID",
+            "ORACLE_PDB",
+            "ORACLE_CHARACTERSET",
+        ],
+    },
+];
+
+/// Match each cluster against the fingerprints and fill in what the
+/// matching fingerprint knows.
+pub fn apply_fingerprints(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        let Some((fingerprint, evidence_refs)) = match_fingerprint(bundle, cluster) else {
+            continue;
+        };
+        apply_fingerprint(bundle, cluster, fingerprint, evidence_refs);
+    }
+}
+
+/// The fingerprint matching the cluster, with the evidence of the services
+/// and processes carrying its marker.
+fn match_fingerprint(
+    bundle: &Bundle,
+    cluster: &AppCluster,
+) -> Option<(&'static Fingerprint, Vec<String>)> {
+    let mut sources: Vec<(String, Option<&String>)> = cluster
+        .services
+        .iter()
+        .map(|s| {
+            let text = format!("{} {}", s.name, s.exec_start.as_deref().unwrap_or(""));
+            (text.to_lowercase(), s.evidence_ref.as_ref())
+        })
+        .collect();
+    sources.extend(cluster.processes.iter().map(|p| {
+        let text = format!("{} {}", p.command, p.args.join(" "));
+        (text.to_lowercase(), p.evidence_ref.as_ref())
+    }));
+
+    FINGERPRINTS.iter().find_map(|fingerprint| {
+        if let Some(file) = fingerprint.required_file {
+            if !bundle
+                .manifest
+                .config_files
+                .iter()
+                .any(|f| f.path.ends_with(file))
+            {
+                return None;
+            }
+        }
+        let matching: Vec<_> = sources
+            .iter()
+            .filter(|(text, _)| fingerprint.markers.iter().any(|m| text.contains(m)))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        let evidence_refs = matching.iter().filter_map(|(_, r)| r.cloned()).collect();
+        Some((fingerprint, evidence_refs))
+    })
+}
+
+fn apply_fingerprint(
+    bundle: &Bundle,
+    cluster: &mut AppCluster,
+    fingerprint: &Fingerprint,
+    evidence_refs: Vec<String>,
+) {
+    let decision = |what: String, why: String, refs: Vec<String>| {
+        Decision::new(what, why, refs, FINGERPRINT_CONFIDENCE)
+    };
+
+    cluster.decisions.push(decision(
+        format!("Recognize {} as {}", cluster.name, fingerprint.name),
+        format!(
+            "Service or process matches {}",
+            fingerprint.markers.join(", ")
+        ),
+        evidence_refs.clone(),
+    ));
+
+    if cluster.app_type != fingerprint.app_type {
+        cluster.app_type = fingerprint.app_type.to_string();
+        cluster.decisions.push(decision(
+            format!("App type: {}", fingerprint.app_type),
+            format!(
+                "{} is a {} application",
+                fingerprint.name, fingerprint.app_type
+            ),
+            evidence_refs.clone(),
+        ));
+    }
+
+    for config in &bundle.manifest.config_files {
+        if !fingerprint
+            .config_paths
+            .iter()
+            .any(|p| config.path.contains(p))
+            || cluster
+                .config_files
+                .iter()
+                .any(|c| c.source_path == config.path)
+        {
+            continue;
+        }
+        cluster.decisions.push(decision(
+            format!("Include configuration file {}", config.path),
+            format!(
+                "{} reads its configuration from this path",
+                fingerprint.name
+            ),
+            config.attachment_ref.iter().cloned().collect(),
+        ));
+        cluster.config_files.push(ConfigFileSpec {
+            source_path: config.path.clone(),
+            container_path: config.path.clone(),
+            templated: false,
+            template_vars: vec![],
+            content: None,
+            evidence_ref: config.attachment_ref.clone(),
+        });
+    }
+
+    for dir in fingerprint.data_dirs {
+        if cluster.volumes.iter().any(|v| v.container_path == *dir) {
+            continue;
+        }
+        let name = volume_name(cluster, dir);
+        cluster.decisions.push(decision(
+            format!("Persist {} in volume {}", dir, name),
+            format!("Default data directory of {}", fingerprint.name),
+            evidence_refs.clone(),
+        ));
+        cluster.volumes.push(VolumeSpec {
+            name,
+            container_path: dir.to_string(),
+            kind: if fingerprint.app_type == "database" {
+                "database"
+            } else {
+                "data"
+            }
+            .to_string(),
+            evidence_ref: evidence_refs.first().cloned(),
+        });
+    }
+
+    if cluster.readiness.is_none() {
+        if let Some((check, port_ref)) = fingerprint_check(cluster, fingerprint) {
+            cluster.decisions.push(decision(
+                format!("Readiness check: {}", describe_check(&check)),
+                format!("Documented readiness check of {}", fingerprint.name),
+                port_ref.into_iter().chain(evidence_refs.clone()).collect(),
+            ));
+            cluster.readiness = Some(check);
+        }
+    }
+
+    for env_var in cluster.env_vars.iter_mut() {
+        if env_var.description.is_none() && fingerprint.env_vars.contains(&env_var.name.as_str()) {
+            env_var.description = Some(format!("{} setting", fingerprint.name));
+        }
+    }
+
+    cluster.base_image = Some(fingerprint.image.to_string());
+    cluster.decisions.push(decision(
+        format!("Base image: {}", fingerprint.image),
+        format!("Official image of {}", fingerprint.name),
+        evidence_refs,
+    ));
+}
+
+/// The fingerprint's readiness check, with the evidence of the port it
+/// probes. HTTP checks fall back to TCP when the cluster does not listen on
+/// the health port.
+fn fingerprint_check(
+    cluster: &AppCluster,
+    fingerprint: &Fingerprint,
+) -> Option<(ReadinessCheck, Option<String>)> {
+    let listening = |port: u16| cluster.ports.iter().find(|p| p.port == port);
+    let check = |check_type: &str, port: Option<u16>, path: Option<&str>, command: Option<&str>| {
+        ReadinessCheck {
+            check_type: check_type.to_string(),
+            target: port.map(|_| "localhost".to_string()),
+            port,
+            path: path.map(str::to_string),
+            command: command.map(str::to_string),
+            timeout_seconds: TIMEOUT_SECONDS,
+            interval_seconds: INTERVAL_SECONDS,
+            retries: RETRIES,
+        }
+    };
+
+    match fingerprint.probe {
+        Probe::Command(command) => Some((check("command", None, None, Some(command)), None)),
+        Probe::Http { port, path } if listening(port).is_some() => Some((
+            check("http", Some(port), Some(path), None),
+            listening(port).and_then(|p| p.evidence_ref.clone()),
+        )),
+        _ => {
+            let port = fingerprint
+                .default_ports
+                .iter()
+                .find_map(|p| listening(*p))?;
+            Some((
+                check("tcp", Some(port.port), None, None),
+                port.evidence_ref.clone(),
+            ))
+        }
+    }
+}
+
+fn describe_check(check: &ReadinessCheck) -> String {
+    match check.check_type.as_str() {
+        "http" => format!(
+            "HTTP GET {} on port {}",
+            check.path.as_deref().unwrap_or("/"),
+            check.port.unwrap_or_default()
+        ),
+        "tcp" => format!("TCP connect on port {}", check.port.unwrap_or_default()),
+        _ => format!("run {}", check.command.as_deref().unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use xcprobe_bundle_schema::{ClusterPort, ClusterService, EnvVarSpec, FileInfo, Manifest};
+
+    fn config_file(path: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            size_bytes: 100,
+            modified_at: None,
+            owner: None,
+            permissions: None,
+            content_hash: None,
+            attachment_ref: Some(format!("attachments/{}", path.trim_start_matches('/'))),
+            discovery_method: "known_path".to_string(),
+            discovery_evidence_ref: None,
+            opened_by: Vec::new(),
+        }
+    }
+
+    fn cluster(service: &str, exec_start: &str, ports: &[u16]) -> AppCluster {
+        AppCluster {
+            id: "app-0".to_string(),
+            name: service.trim_end_matches(".service").to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: service.to_string(),
+                exec_start: Some(exec_start.to_string()),
+                evidence_ref: Some("evidence/services_1.txt".to_string()),
+                ..Default::default()
+            }],
+            ports: ports
+                .iter()
+                .map(|&port| ClusterPort {
+                    port,
+                    protocol: "tcp".to_string(),
+                    evidence_ref: Some("evidence/ports_1.txt".to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            env_vars: vec![EnvVarSpec {
+                name: "JENKINS_HOME".to_string(),
+                required: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn bundle(config_files: &[&str]) -> Bundle {
+        Bundle {
+            manifest: Manifest {
+                config_files: config_files.iter().map(|p| config_file(p)).collect(),
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_jenkins_fingerprint_fills_cluster() {
+        let bundle = bundle(&["/etc/default/jenkins", "/etc/nginx/nginx.conf"]);
+        let mut clusters = vec![cluster(
+            "jenkins.service",
+            "/usr/bin/java -jar /usr/share/java/jenkins.war --httpPort=8080",
+            &[8080, 50000],
+        )];
+
+        apply_fingerprints(&bundle, &mut clusters);
+
+        let cluster = &clusters[0];
+        assert_eq!(cluster.app_type, "web");
+        assert_eq!(
+            cluster.base_image.as_deref(),
+            Some("jenkins/jenkins:lts-jdk17")
+        );
+        assert_eq!(cluster.config_files.len(), 1);
+        assert_eq!(cluster.config_files[0].source_path, "/etc/default/jenkins");
+        assert_eq!(cluster.volumes[0].container_path, "/var/lib/jenkins");
+        assert_eq!(cluster.volumes[0].name, "app-0-jenkins");
+        let readiness = cluster.readiness.as_ref().unwrap();
+        assert_eq!(readiness.check_type, "http");
+        assert_eq!(readiness.path.as_deref(), Some("/login"));
+        assert_eq!(
+            cluster.env_vars[0].description.as_deref(),
+            Some("Jenkins setting")
+        );
+        assert!(cluster.decisions.iter().all(|d| d.confidence >= 0.9));
+        assert_eq!(
+            cluster.decisions[0].decision,
+            "Recognize jenkins as Jenkins"
+        );
+    }
+
+    #[test]
+    fn test_php_fpm_needs_wordpress_config() {
+        let mut clusters = vec![cluster(
+            "php8.2-fpm.service",
+            "/usr/sbin/php-fpm8.2",
+            &[9000],
+        )];
+        apply_fingerprints(&bundle(&["/etc/php/8.2/fpm/php.ini"]), &mut clusters);
+        assert!(clusters[0].base_image.is_none());
+        assert!(clusters[0].decisions.is_empty());
+
+        let bundle = bundle(&["/etc/php/8.2/fpm/php.ini", "/var/www/html/wp-config.php"]);
+        apply_fingerprints(&bundle, &mut clusters);
+        assert_eq!(
+            clusters[0].base_image.as_deref(),
+            Some("wordpress:6-php8.2-fpm")
+        );
+        assert_eq!(clusters[0].config_files.len(), 2);
+        let readiness = clusters[0].readiness.as_ref().unwrap();
+        assert_eq!(
+            (readiness.check_type.as_str(), readiness.port),
+            ("tcp", Some(9000))
+        );
+    }
+}
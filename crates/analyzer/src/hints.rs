@@ -0,0 +1,266 @@
+//! User-provided clustering hints.
+//!
+//! A `hints.yaml` file lets users correct the clustering heuristics:
+//!
+//! ```yaml
+//! exclude:
+//!   - legacy-reports.service
+//! clusters:
+//!   - name: billing
+//!     services: [billing-api.service, billing-worker]
+//!     processes: [invoice-daemon]
+//!     app_type: api
+//!     depends_on: [postgresql]
+//! ```
+//!
+//! Hinted assignments are recorded as decisions with the hint file as
+//! evidence and full confidence.
+
+use crate::clustering::{absorb_cluster, renumber_clusters};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use xcprobe_bundle_schema::{AnalysisWarning, AppCluster, Decision};
+
+/// Clustering hints loaded from a hints file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClusteringHints {
+    /// Services, process executables or cluster names to leave out.
+    pub exclude: Vec<String>,
+    /// Named clusters with pre-assigned members.
+    pub clusters: Vec<ClusterHint>,
+    /// Hint file path, cited as decision evidence.
+    #[serde(skip)]
+    pub source: String,
+}
+
+/// A named cluster and the services and processes assigned to it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClusterHint {
+    pub name: String,
+    /// Service names, with or without the `.service` suffix.
+    pub services: Vec<String>,
+    /// Process executables, by name or full path.
+    pub processes: Vec<String>,
+    /// Application type overriding the detected one.
+    pub app_type: Option<String>,
+    /// Names of the clusters this cluster depends on.
+    pub depends_on: Vec<String>,
+}
+
+/// Load a hints file.
+pub fn load_hints(path: &Path) -> Result<ClusteringHints> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read hints file {}", path.display()))?;
+    let mut hints: ClusteringHints = serde_yaml::from_str(&content)
+        .with_context(|| format!("Invalid hints file {}", path.display()))?;
+    hints.source = path.display().to_string();
+    Ok(hints)
+}
+
+/// Apply exclusions and cluster assignments, then renumber cluster IDs.
+/// Hints matching nothing are returned as warnings.
+pub fn apply_cluster_hints(
+    clusters: &mut Vec<AppCluster>,
+    hints: &ClusteringHints,
+    prefix: &str,
+) -> Vec<AnalysisWarning> {
+    let mut warnings = Vec::new();
+
+    for pattern in &hints.exclude {
+        let mut matched = false;
+        clusters.retain_mut(|cluster| {
+            if cluster.name == *pattern {
+                matched = true;
+                return false;
+            }
+            let services = cluster.services.len();
+            let processes = cluster.processes.len();
+            cluster
+                .services
+                .retain(|s| !service_matches(&s.name, pattern));
+            cluster
+                .processes
+                .retain(|p| !process_matches(&p.command, pattern));
+            matched |= cluster.services.len() < services || cluster.processes.len() < processes;
+            !cluster.services.is_empty() || !cluster.processes.is_empty()
+        });
+        if !matched {
+            warnings.push(unmatched_hint(hints, format!("exclude entry {}", pattern)));
+        }
+    }
+
+    for hint in &hints.clusters {
+        let members: Vec<usize> = clusters
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.services
+                    .iter()
+                    .any(|s| hint.services.iter().any(|h| service_matches(&s.name, h)))
+                    || c.processes.iter().any(|p| {
+                        hint.processes
+                            .iter()
+                            .any(|h| process_matches(&p.command, h))
+                    })
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let Some((&target, others)) = members.split_first() else {
+            warnings.push(unmatched_hint(hints, format!("cluster {}", hint.name)));
+            continue;
+        };
+
+        // Remove from the back so earlier indices stay valid
+        for &index in others.iter().rev() {
+            let other = clusters.remove(index);
+            absorb_cluster(&mut clusters[target], other);
+        }
+        let cluster = &mut clusters[target];
+        let mut assigned: Vec<&str> = cluster
+            .services
+            .iter()
+            .map(|s| s.name.as_str())
+            .chain(cluster.processes.iter().map(|p| p.command.as_str()))
+            .collect();
+        assigned.dedup();
+        cluster.decisions.push(hint_decision(
+            hints,
+            format!("Assign {} to cluster {}", assigned.join(", "), hint.name),
+        ));
+        cluster.name = hint.name.clone();
+        if let Some(ref app_type) = hint.app_type {
+            cluster.app_type = app_type.clone();
+            cluster
+                .decisions
+                .push(hint_decision(hints, format!("Set app type {}", app_type)));
+        }
+    }
+
+    renumber_clusters(clusters, prefix);
+    warnings
+}
+
+/// Add the dependencies declared in hints.
+pub fn apply_dependency_hints(
+    clusters: &mut [AppCluster],
+    hints: &ClusteringHints,
+) -> Vec<AnalysisWarning> {
+    let mut warnings = Vec::new();
+    for hint in hints.clusters.iter().filter(|h| !h.depends_on.is_empty()) {
+        let Some(index) = clusters.iter().position(|c| c.name == hint.name) else {
+            continue;
+        };
+        for dependency in &hint.depends_on {
+            let Some(dep_id) = clusters
+                .iter()
+                .find(|c| c.name == *dependency || c.id == *dependency)
+                .map(|c| c.id.clone())
+            else {
+                warnings.push(unmatched_hint(
+                    hints,
+                    format!("dependency {} of cluster {}", dependency, hint.name),
+                ));
+                continue;
+            };
+            let cluster = &mut clusters[index];
+            if dep_id != cluster.id && !cluster.depends_on.contains(&dep_id) {
+                cluster.depends_on.push(dep_id);
+                cluster
+                    .decisions
+                    .push(hint_decision(hints, format!("Depends on {}", dependency)));
+            }
+        }
+    }
+    warnings
+}
+
+fn service_matches(name: &str, pattern: &str) -> bool {
+    name == pattern || name.trim_end_matches(".service") == pattern
+}
+
+fn process_matches(command: &str, pattern: &str) -> bool {
+    command == pattern || command.rsplit(['/', '\\']).next() == Some(pattern)
+}
+
+fn hint_decision(hints: &ClusteringHints, decision: String) -> Decision {
+    Decision::new(
+        decision,
+        format!("Clustering hint in {}", hints.source),
+        vec![hints.source.clone()],
+        1.0,
+    )
+}
+
+fn unmatched_hint(hints: &ClusteringHints, what: String) -> AnalysisWarning {
+    AnalysisWarning::new(
+        "hint_unmatched",
+        "warning",
+        format!("Hint {} in {} matches nothing", what, hints.source),
+        vec![],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::ClusterService;
+
+    fn cluster(id: &str, service: &str) -> AppCluster {
+        AppCluster {
+            id: id.to_string(),
+            name: service.trim_end_matches(".service").to_string(),
+            app_type: "unknown".to_string(),
+            services: vec![ClusterService {
+                name: service.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_hints() {
+        let mut hints: ClusteringHints = serde_yaml::from_str(
+            r#"
+exclude: [legacy-reports.service, telnetd]
+clusters:
+  - name: billing
+    services: [billing-api.service, billing-worker]
+    app_type: api
+    depends_on: [postgresql, mongodb]
+"#,
+        )
+        .unwrap();
+        hints.source = "hints.yaml".to_string();
+        let mut clusters = vec![
+            cluster("app-0", "postgresql.service"),
+            cluster("app-1", "billing-api.service"),
+            cluster("app-2", "legacy-reports.service"),
+            cluster("app-3", "billing-worker.service"),
+        ];
+
+        let warnings = apply_cluster_hints(&mut clusters, &hints, "app");
+        let names: Vec<(&str, &str)> = clusters
+            .iter()
+            .map(|c| (c.id.as_str(), c.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("app-0", "postgresql"), ("app-1", "billing")]);
+        let billing = &clusters[1];
+        assert_eq!(billing.services.len(), 2);
+        assert_eq!(billing.app_type, "api");
+        assert!(billing
+            .decisions
+            .iter()
+            .all(|d| d.evidence_refs == vec!["hints.yaml"] && d.confidence == 1.0));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("telnetd"));
+
+        let warnings = apply_dependency_hints(&mut clusters, &hints);
+        assert_eq!(clusters[1].depends_on, vec!["app-0"]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("mongodb"));
+    }
+}
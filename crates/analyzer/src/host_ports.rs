@@ -0,0 +1,249 @@
+//! Host ports of the generated compose file.
+//!
+//! Each cluster publishes its listening ports on the same host port, and
+//! its replicas on a range of host ports starting there. Clusters of
+//! different hosts, or several instances of an application on one host,
+//! often listen on the same port, and compose would then fail to start the
+//! second service. Clusters keep their container ports, but those after the
+//! first, in plan order, publish a colliding port on the next host ports
+//! that no cluster asks for. The replicas of one port can also run into the
+//! next port of the same cluster, which moves the same way. A port with no
+//! free host ports left keeps its own, with a warning.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use xcprobe_bundle_schema::{AnalysisWarning, AppCluster, Decision};
+
+/// Move the host ports of clusters publishing a port another cluster
+/// already publishes, recording each move as a decision and a warning.
+pub fn resolve_port_conflicts(clusters: &mut [AppCluster]) -> Vec<AnalysisWarning> {
+    let requested: HashSet<u16> = clusters
+        .iter()
+        .flat_map(|c| {
+            let replicas = c.replicas.unwrap_or(1);
+            c.ports
+                .iter()
+                .flat_map(move |p| published_range(p.port, replicas))
+        })
+        .collect();
+    // Host port -> (cluster, container port) publishing it
+    let mut published: HashMap<u16, (String, u16)> = HashMap::new();
+    let mut warnings = Vec::new();
+
+    for cluster in clusters.iter_mut() {
+        let replicas = cluster.replicas.unwrap_or(1);
+        let mut own = HashSet::new();
+        for port in cluster.ports.iter_mut() {
+            port.host_port = None;
+            // A port listed twice, for IPv4 and IPv6, is published once
+            if !own.insert(port.port) {
+                continue;
+            }
+            let collision = published_range(port.port, replicas)
+                .find_map(|p| published.get(&p).map(|owner| (p, owner.clone())));
+            let Some((taken, (owner, owner_port))) = collision else {
+                for p in published_range(port.port, replicas) {
+                    published.insert(p, (cluster.id.clone(), port.port));
+                }
+                continue;
+            };
+            // The replicas of another port of this cluster may take it too
+            let same_cluster = owner == cluster.id;
+            let free = (port.port.saturating_add(1)..=u16::MAX).find_map(|start| {
+                let range = host_range(start, replicas)?;
+                range
+                    .clone()
+                    .all(|p| !published.contains_key(&p) && !requested.contains(&p))
+                    .then_some(range)
+            });
+            let Some(range) = free else {
+                let (message, affected) = if same_cluster {
+                    (
+                        format!(
+                            "Replicas of ports {} and {} of cluster {} share host port {} and no free host ports are left to move port {} to",
+                            owner_port, port.port, cluster.id, taken, port.port
+                        ),
+                        vec![cluster.id.clone()],
+                    )
+                } else {
+                    (
+                        format!(
+                            "Clusters {} and {} both publish host port {} and no free host ports are left to move port {} of {} to",
+                            owner, cluster.id, taken, port.port, cluster.id
+                        ),
+                        vec![owner, cluster.id.clone()],
+                    )
+                };
+                warnings.push(AnalysisWarning::new(
+                    "host_port_conflict",
+                    "warning",
+                    message,
+                    affected,
+                ));
+                continue;
+            };
+            let host_port = *range.start();
+            for p in range {
+                published.insert(p, (cluster.id.clone(), port.port));
+            }
+            port.host_port = Some(host_port);
+            let decision = format!("Publish port {} on host port {}", port.port, host_port);
+            let (reason, message, affected) = if same_cluster {
+                (
+                    format!(
+                        "The replicas of port {} already publish host port {}",
+                        owner_port, taken
+                    ),
+                    format!(
+                        "Replicas of ports {} and {} of cluster {} share host port {}; port {} is published from host port {}",
+                        owner_port, port.port, cluster.id, taken, port.port, host_port
+                    ),
+                    vec![cluster.id.clone()],
+                )
+            } else {
+                (
+                    format!("Cluster {} already publishes host port {}", owner, taken),
+                    format!(
+                        "Clusters {} and {} both listen on port {}; {} is published on host port {}",
+                        owner, cluster.id, port.port, cluster.id, host_port
+                    ),
+                    vec![owner, cluster.id.clone()],
+                )
+            };
+            if !cluster.decisions.iter().any(|d| d.decision == decision) {
+                cluster.decisions.push(Decision::new(
+                    decision,
+                    reason,
+                    port.evidence_ref.iter().cloned().collect(),
+                    0.9,
+                ));
+            }
+            warnings.push(AnalysisWarning::new(
+                "host_port_remapped",
+                "warning",
+                message,
+                affected,
+            ));
+        }
+    }
+    warnings
+}
+
+/// Host ports of `replicas` replicas starting at `start`, `None` when they
+/// run past the last port.
+pub(crate) fn host_range(start: u16, replicas: u32) -> Option<RangeInclusive<u16>> {
+    let extra = u16::try_from(replicas.saturating_sub(1)).ok()?;
+    Some(start..=start.checked_add(extra)?)
+}
+
+/// Host ports a container port is published on without remapping: a range
+/// for replicas, or the port alone when the range would not fit.
+fn published_range(port: u16, replicas: u32) -> RangeInclusive<u16> {
+    host_range(port, replicas).unwrap_or(port..=port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::ClusterPort;
+
+    fn cluster(id: &str, ports: &[u16], replicas: Option<u32>) -> AppCluster {
+        AppCluster {
+            id: id.to_string(),
+            name: id.to_string(),
+            app_type: "api".to_string(),
+            ports: ports
+                .iter()
+                .map(|&port| ClusterPort {
+                    port,
+                    protocol: "tcp".to_string(),
+                    evidence_ref: Some("evidence/ss_1.txt".to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            replicas,
+            confidence: 0.8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_port_conflicts() {
+        let mut clusters = vec![
+            cluster("app-0", &[8080, 9090], None),
+            cluster("app-1", &[8080, 8080], None),
+            cluster("app-2", &[8000], Some(2)),
+            cluster("app-3", &[8001, 8081], None),
+        ];
+        let warnings = resolve_port_conflicts(&mut clusters);
+
+        let host_ports: Vec<Vec<Option<u16>>> = clusters
+            .iter()
+            .map(|c| c.ports.iter().map(|p| p.host_port).collect())
+            .collect();
+        // app-3 asks for 8081, and app-2's second replica takes 8001
+        assert_eq!(
+            host_ports,
+            vec![
+                vec![None, None],
+                vec![Some(8082), None],
+                vec![None],
+                vec![Some(8002), None],
+            ]
+        );
+        let affected: Vec<&[String]> = warnings
+            .iter()
+            .map(|w| w.affected_clusters.as_slice())
+            .collect();
+        assert_eq!(affected, [["app-0", "app-1"], ["app-2", "app-3"]]);
+        assert_eq!(
+            warnings[0].message,
+            "Clusters app-0 and app-1 both listen on port 8080; app-1 is published on host port 8082"
+        );
+        assert!(clusters[1]
+            .decisions
+            .iter()
+            .any(|d| d.decision == "Publish port 8080 on host port 8082"));
+
+        // The pass is deterministic and can run again on its own output
+        let again = resolve_port_conflicts(&mut clusters);
+        assert_eq!(again.len(), 2);
+        assert_eq!(clusters[1].ports[0].host_port, Some(8082));
+        assert_eq!(clusters[1].decisions.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_port_conflicts_within_cluster() {
+        let mut clusters = vec![cluster("app-0", &[8080, 8081], Some(2))];
+        let warnings = resolve_port_conflicts(&mut clusters);
+
+        // 8080-8081 and 8081-8082 are asked for
+        assert_eq!(clusters[0].ports[0].host_port, None);
+        assert_eq!(clusters[0].ports[1].host_port, Some(8083));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].affected_clusters, ["app-0"]);
+        assert_eq!(
+            warnings[0].message,
+            "Replicas of ports 8080 and 8081 of cluster app-0 share host port 8081; port 8081 is published from host port 8083"
+        );
+        assert_eq!(
+            clusters[0].decisions[0].reason,
+            "The replicas of port 8080 already publish host port 8081"
+        );
+    }
+
+    #[test]
+    fn test_resolve_port_conflicts_without_free_ports() {
+        let mut clusters = vec![
+            cluster("app-0", &[65535], None),
+            cluster("app-1", &[65535], None),
+        ];
+        let warnings = resolve_port_conflicts(&mut clusters);
+
+        assert_eq!(clusters[1].ports[0].host_port, None);
+        assert!(clusters[1].decisions.is_empty());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "host_port_conflict");
+        assert_eq!(warnings[0].affected_clusters, ["app-0", "app-1"]);
+    }
+}
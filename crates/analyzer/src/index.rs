@@ -0,0 +1,240 @@
+//! Lookup tables over a manifest.
+//!
+//! Scoring and clustering look up processes, ports, services and files by
+//! PID, name or path for every process and service of the manifest. On
+//! hosts with tens of thousands of processes, linear scans for each lookup
+//! dominate the analysis time, so the tables are built once per analysis
+//! and shared. Lookups return entries in manifest order, as the scans did.
+
+use std::collections::HashMap;
+use xcprobe_bundle_schema::{
+    EnvironmentFile, FileInfo, Manifest, PackageOwner, PortInfo, ProcessInfo, ServiceInfo,
+};
+
+/// Indexes of the entries of a manifest.
+pub struct ManifestIndex<'a> {
+    pub manifest: &'a Manifest,
+    processes: HashMap<u32, &'a ProcessInfo>,
+    ports: HashMap<u32, Vec<&'a PortInfo>>,
+    unix_sockets: HashMap<u32, Vec<&'a PortInfo>>,
+    services: HashMap<&'a str, &'a ServiceInfo>,
+    services_by_pid: HashMap<u32, Vec<&'a ServiceInfo>>,
+    environment_files: HashMap<&'a str, &'a EnvironmentFile>,
+    file_stats: HashMap<&'a str, &'a FileInfo>,
+    package_owners: HashMap<&'a str, &'a PackageOwner>,
+    /// Indexes of the configuration files, sorted by path.
+    config_paths: Vec<usize>,
+    configs_opened_by: HashMap<u32, Vec<usize>>,
+    logs_opened_by: HashMap<u32, Vec<usize>>,
+}
+
+impl<'a> ManifestIndex<'a> {
+    pub fn new(manifest: &'a Manifest) -> Self {
+        let mut index = Self {
+            manifest,
+            processes: HashMap::with_capacity(manifest.processes.len()),
+            ports: HashMap::new(),
+            unix_sockets: HashMap::new(),
+            services: HashMap::with_capacity(manifest.services.len()),
+            services_by_pid: HashMap::new(),
+            environment_files: HashMap::new(),
+            file_stats: HashMap::new(),
+            package_owners: HashMap::new(),
+            config_paths: (0..manifest.config_files.len()).collect(),
+            configs_opened_by: opened_by(&manifest.config_files),
+            logs_opened_by: opened_by(&manifest.log_files),
+        };
+        // The first entry wins, as with a search from the start
+        for process in &manifest.processes {
+            index.processes.entry(process.pid).or_insert(process);
+        }
+        for port in &manifest.ports {
+            if let Some(pid) = port.pid {
+                let listeners = if port.is_unix() {
+                    &mut index.unix_sockets
+                } else {
+                    &mut index.ports
+                };
+                listeners.entry(pid).or_default().push(port);
+            }
+        }
+        for service in &manifest.services {
+            index.services.entry(&service.name).or_insert(service);
+            if let Some(pid) = service.main_pid {
+                index.services_by_pid.entry(pid).or_default().push(service);
+            }
+        }
+        for file in &manifest.environment_files {
+            index.environment_files.entry(&file.path).or_insert(file);
+        }
+        for file in manifest.config_files.iter().chain(&manifest.directories) {
+            index.file_stats.entry(&file.path).or_insert(file);
+        }
+        for owner in &manifest.package_owners {
+            index.package_owners.entry(&owner.path).or_insert(owner);
+        }
+        index
+            .config_paths
+            .sort_by_key(|&i| (&manifest.config_files[i].path, i));
+        index
+    }
+
+    /// The process of a PID.
+    pub fn process(&self, pid: u32) -> Option<&'a ProcessInfo> {
+        self.processes.get(&pid).copied()
+    }
+
+    /// The network ports a process listens on.
+    pub fn ports(&self, pid: u32) -> &[&'a PortInfo] {
+        self.ports.get(&pid).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The Unix domain sockets a process listens on.
+    pub fn unix_sockets(&self, pid: u32) -> &[&'a PortInfo] {
+        self.unix_sockets
+            .get(&pid)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The service of a name.
+    pub fn service(&self, name: &str) -> Option<&'a ServiceInfo> {
+        self.services.get(name).copied()
+    }
+
+    /// The services whose main process is `pid`.
+    pub fn services_of_pid(&self, pid: u32) -> &[&'a ServiceInfo] {
+        self.services_by_pid
+            .get(&pid)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The environment file at `path`.
+    pub fn environment_file(&self, path: &str) -> Option<&'a EnvironmentFile> {
+        self.environment_files.get(path).copied()
+    }
+
+    /// Owner and mode of a configuration file or directory.
+    pub fn file_stat(&self, path: &str) -> Option<&'a FileInfo> {
+        self.file_stats.get(path).copied()
+    }
+
+    /// The packages owning the file at `path`, when they were queried.
+    pub fn package_owner(&self, path: &str) -> Option<&'a PackageOwner> {
+        self.package_owners.get(path).copied()
+    }
+
+    /// The configuration files whose path starts with `prefix`.
+    pub fn configs_under(&self, prefix: &str) -> Vec<&'a FileInfo> {
+        let configs = &self.manifest.config_files;
+        let start = self
+            .config_paths
+            .partition_point(|&i| configs[i].path.as_str() < prefix);
+        let mut matching: Vec<usize> = self.config_paths[start..]
+            .iter()
+            .copied()
+            .take_while(|&i| configs[i].path.starts_with(prefix))
+            .collect();
+        matching.sort_unstable();
+        matching.into_iter().map(|i| &configs[i]).collect()
+    }
+
+    /// The configuration files held open by any of `pids`.
+    pub fn configs_opened_by(&self, pids: &[u32]) -> Vec<&'a FileInfo> {
+        held_by(&self.configs_opened_by, pids)
+            .map(|i| &self.manifest.config_files[i])
+            .collect()
+    }
+
+    /// The log files held open by any of `pids`.
+    pub fn logs_opened_by(&self, pids: &[u32]) -> Vec<&'a FileInfo> {
+        held_by(&self.logs_opened_by, pids)
+            .map(|i| &self.manifest.log_files[i])
+            .collect()
+    }
+}
+
+/// Indexes of the files each PID holds open.
+fn opened_by(files: &[FileInfo]) -> HashMap<u32, Vec<usize>> {
+    let mut by_pid: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        for &pid in &file.opened_by {
+            by_pid.entry(pid).or_default().push(i);
+        }
+    }
+    by_pid
+}
+
+/// Indexes of the files held open by any of `pids`, in manifest order.
+fn held_by(by_pid: &HashMap<u32, Vec<usize>>, pids: &[u32]) -> impl Iterator<Item = usize> {
+    let mut indexes: Vec<usize> = pids
+        .iter()
+        .filter_map(|pid| by_pid.get(pid))
+        .flatten()
+        .copied()
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, opened_by: Vec<u32>) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            size_bytes: 0,
+            modified_at: None,
+            owner: None,
+            permissions: None,
+            content_hash: None,
+            attachment_ref: None,
+            discovery_method: "cmdline".to_string(),
+            discovery_evidence_ref: None,
+            opened_by,
+        }
+    }
+
+    #[test]
+    fn test_file_lookups_keep_manifest_order() {
+        let manifest = Manifest {
+            config_files: vec![
+                file("/opt/app/z.yaml", vec![10]),
+                file("/etc/app.yaml", vec![11]),
+                file("/opt/app/a.yaml", vec![10, 11]),
+                file("/opt/application/b.yaml", vec![]),
+            ],
+            log_files: vec![file("/var/log/app.log", vec![11])],
+            ..Default::default()
+        };
+        let index = ManifestIndex::new(&manifest);
+
+        let paths = |files: Vec<&FileInfo>| -> Vec<String> {
+            files.into_iter().map(|f| f.path.clone()).collect()
+        };
+        // A plain string prefix, as `str::starts_with`
+        assert_eq!(
+            paths(index.configs_under("/opt/app")),
+            vec![
+                "/opt/app/z.yaml",
+                "/opt/app/a.yaml",
+                "/opt/application/b.yaml"
+            ]
+        );
+        assert!(index.configs_under("/srv").is_empty());
+        assert_eq!(
+            paths(index.configs_opened_by(&[11, 10])),
+            vec!["/opt/app/z.yaml", "/etc/app.yaml", "/opt/app/a.yaml"]
+        );
+        assert_eq!(paths(index.logs_opened_by(&[11])), vec!["/var/log/app.log"]);
+        assert_eq!(
+            index
+                .file_stat("/etc/app.yaml")
+                .map(|f| f.opened_by.clone()),
+            Some(vec![11])
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! JVM application introspection.
+//!
+//! Parses the command line of Java services and processes for the main jar
+//! or class, classpath, system properties, Spring profiles and heap sizing.
+//! Heap flags size the compose memory limit; jar and classpath locations
+//! are copied into the image.
+
+use xcprobe_bundle_schema::{AppCluster, Decision, JavaAppInfo};
+
+/// JVM memory beyond the heap (metaspace, threads, code cache), as a
+/// fraction of the heap.
+const NON_HEAP_OVERHEAD: f64 = 0.5;
+/// Memory limits are rounded up to this many MiB.
+const MEMORY_LIMIT_STEP_MB: u64 = 64;
+
+/// Parse the command lines of Java clusters into [`JavaAppInfo`].
+pub fn detect_java_apps(clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        let mut command_lines = cluster
+            .services
+            .iter()
+            .filter_map(|s| {
+                Some((
+                    s.exec_start.clone()?,
+                    s.working_directory.clone(),
+                    s.evidence_ref.clone(),
+                ))
+            })
+            .chain(cluster.processes.iter().map(|p| {
+                (
+                    format!("{} {}", p.command, p.args.join(" ")),
+                    p.working_directory.clone(),
+                    p.evidence_ref.clone(),
+                )
+            }));
+
+        let found = command_lines.find_map(|(line, workdir, evidence_ref)| {
+            let mut info = parse_java_command(&line)?;
+            info.evidence_ref = evidence_ref;
+            resolve_paths(&mut info, workdir.as_deref());
+            Some(info)
+        });
+        let Some(info) = found else {
+            continue;
+        };
+
+        let entry = info
+            .main_jar
+            .as_deref()
+            .map(|jar| format!("jar {}", jar))
+            .or_else(|| {
+                info.main_class
+                    .as_deref()
+                    .map(|c| format!("main class {}", c))
+            })
+            .unwrap_or_else(|| "no main jar or class".to_string());
+        let mut details = vec![entry];
+        if let Some(ref heap) = info.max_heap {
+            details.push(format!("heap -Xmx{}", heap));
+        }
+        if !info.spring_profiles.is_empty() {
+            details.push(format!(
+                "Spring profiles {}",
+                info.spring_profiles.join(",")
+            ));
+        }
+        cluster.decisions.push(Decision::new(
+            format!("Java application: {}", details.join(", ")),
+            "Parsed from the JVM command line",
+            info.evidence_ref.iter().cloned().collect(),
+            0.9,
+        ));
+        cluster.java = Some(info);
+    }
+}
+
+/// Parse a JVM command line. Returns `None` when it does not run `java`.
+pub fn parse_java_command(command_line: &str) -> Option<JavaAppInfo> {
+    let tokens: Vec<&str> = command_line.split_whitespace().collect();
+    let java_pos = tokens.iter().position(|t| {
+        let name = t.rsplit(['/', '\\']).next().unwrap_or(t);
+        name == "java" || name.eq_ignore_ascii_case("java.exe")
+    })?;
+
+    let mut info = JavaAppInfo::default();
+    let mut args = tokens[java_pos + 1..].iter();
+    while let Some(&arg) = args.next() {
+        if arg == "-jar" {
+            info.main_jar = args.next().map(|s| s.to_string());
+            break;
+        } else if arg == "-cp" || arg == "-classpath" || arg == "--class-path" {
+            if let Some(cp) = args.next() {
+                let separator = if cp.contains(';') { ';' } else { ':' };
+                info.classpath = cp
+                    .split(separator)
+                    .filter(|e| !e.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+        } else if let Some(property) = arg.strip_prefix("-D") {
+            let (key, value) = property.split_once('=').unwrap_or((property, ""));
+            info.system_properties
+                .insert(key.to_string(), value.to_string());
+        } else if let Some(size) = arg.strip_prefix("-Xmx") {
+            info.max_heap = Some(size.to_string());
+        } else if let Some(size) = arg.strip_prefix("-Xms") {
+            info.initial_heap = Some(size.to_string());
+        } else if !arg.starts_with('-') {
+            info.main_class = Some(arg.to_string());
+            break;
+        }
+    }
+
+    // Spring profiles from the system property or an application argument
+    let app_profiles = args.find_map(|a| a.strip_prefix("--spring.profiles.active="));
+    if let Some(profiles) = info
+        .system_properties
+        .get("spring.profiles.active")
+        .map(String::as_str)
+        .or(app_profiles)
+    {
+        info.spring_profiles = profiles
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+
+    Some(info)
+}
+
+/// Make a relative jar and classpath entries absolute against the working
+/// directory.
+fn resolve_paths(info: &mut JavaAppInfo, workdir: Option<&str>) {
+    let Some(workdir) = workdir.filter(|wd| wd.starts_with('/')) else {
+        return;
+    };
+    let resolve = |path: &mut String| {
+        if !path.starts_with('/') {
+            let relative = path.trim_start_matches("./");
+            *path = format!("{}/{}", workdir.trim_end_matches('/'), relative);
+        }
+    };
+    if let Some(ref mut jar) = info.main_jar {
+        resolve(jar);
+    }
+    info.classpath.iter_mut().for_each(resolve);
+}
+
+/// Parse a JVM memory size (`512m`, `2G`, `1048576`) into bytes.
+pub fn parse_memory_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (digits, multiplier) = match size.chars().last()?.to_ascii_lowercase() {
+        'k' => (&size[..size.len() - 1], 1u64 << 10),
+        'm' => (&size[..size.len() - 1], 1 << 20),
+        'g' => (&size[..size.len() - 1], 1 << 30),
+        't' => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Container memory limit for a JVM with the given maximum heap: the heap
+/// plus non-heap overhead, rounded up, in compose notation (`768m`).
+pub fn memory_limit(max_heap: &str) -> Option<String> {
+    let heap_mb = parse_memory_size(max_heap)? as f64 / (1u64 << 20) as f64;
+    let limit_mb = (heap_mb * (1.0 + NON_HEAP_OVERHEAD)).ceil() as u64;
+    let limit_mb = limit_mb.div_ceil(MEMORY_LIMIT_STEP_MB).max(1) * MEMORY_LIMIT_STEP_MB;
+    Some(format!("{}m", limit_mb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_java_command() {
+        let info = parse_java_command(
+            "/usr/lib/jvm/java-17/bin/java -Xms256m -Xmx1g -Dspring.profiles.active=prod,eu -Dfile.encoding=UTF-8 -jar app.jar --server.port=8080",
+        )
+        .unwrap();
+        assert_eq!(info.main_jar.as_deref(), Some("app.jar"));
+        assert_eq!(info.max_heap.as_deref(), Some("1g"));
+        assert_eq!(info.initial_heap.as_deref(), Some("256m"));
+        assert_eq!(info.spring_profiles, vec!["prod", "eu"]);
+        assert_eq!(info.system_properties["file.encoding"], "UTF-8");
+
+        let info = parse_java_command(
+            "java -cp /opt/app/lib/*:/opt/app/conf com.example.Main --spring.profiles.active=dev",
+        )
+        .unwrap();
+        assert_eq!(info.main_class.as_deref(), Some("com.example.Main"));
+        assert_eq!(info.classpath, vec!["/opt/app/lib/*", "/opt/app/conf"]);
+        assert_eq!(info.spring_profiles, vec!["dev"]);
+
+        assert!(parse_java_command("/usr/bin/node server.js").is_none());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        assert_eq!(parse_memory_size("512m"), Some(512 << 20));
+        assert_eq!(parse_memory_size("2G"), Some(2 << 30));
+        assert_eq!(parse_memory_size("1048576"), Some(1 << 20));
+        assert_eq!(parse_memory_size("lots"), None);
+
+        assert_eq!(memory_limit("512m").as_deref(), Some("768m"));
+        assert_eq!(memory_limit("1g").as_deref(), Some("1536m"));
+        assert_eq!(memory_limit("100m").as_deref(), Some("192m"));
+    }
+
+    #[test]
+    fn test_relative_jar_resolved_against_workdir() {
+        let mut info = parse_java_command("java -jar ./build/app.jar").unwrap();
+        resolve_paths(&mut info, Some("/opt/app/"));
+        assert_eq!(info.main_jar.as_deref(), Some("/opt/app/build/app.jar"));
+    }
+}
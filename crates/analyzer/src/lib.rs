@@ -1,102 +1,579 @@
 //! XCProbe Analyzer - Analyze bundles and generate Docker artifacts.
 
+pub mod aliases;
+pub mod artifacts;
+pub mod build_check;
 pub mod clustering;
 pub mod confidence;
+pub mod config_vars;
+pub mod databases;
 pub mod dependencies;
+pub mod detectors;
 pub mod docker;
+pub mod fingerprints;
+pub mod hints;
+pub mod host_ports;
+pub mod index;
+pub mod java;
+pub mod lint;
+pub mod packages;
+pub mod proxy;
+pub mod readiness;
+pub mod registry;
+pub mod report;
+pub mod resolution;
+pub mod resources;
+pub mod runtime;
+pub mod sbom;
 pub mod scoring;
+pub mod split;
+pub mod stubs;
+pub mod supervisor;
+pub mod templates;
+pub mod trace;
+pub mod volumes;
+pub mod windows;
 
-use anyhow::Result;
-use tracing::info;
-use xcprobe_bundle_schema::PackPlan;
+use anyhow::{Context, Result};
+use artifacts::ArtifactSet;
+use docker::ContainerPlatform;
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use templates::TemplateEngine;
+use tracing::{debug, info, warn};
+use xcprobe_bundle_schema::{migration, AnalysisWarning, AppCluster, GeneratedArtifact, PackPlan};
+
+/// Options controlling the analysis pipeline.
+#[derive(Debug, Clone)]
+pub struct AnalyzeOptions {
+    /// Prefix of cluster IDs (`<prefix>-<n>`).
+    pub cluster_prefix: String,
+    /// How clusters of the same application are merged.
+    pub clustering_strategy: clustering::ClusteringStrategy,
+    /// User-provided clustering hints.
+    pub hints: Option<hints::ClusteringHints>,
+    /// Clusters below this confidence are excluded from the plan.
+    pub min_confidence: f64,
+    /// Record excluded clusters in the plan's `rejected_clusters`.
+    pub include_rejected: bool,
+    /// Calibration of process scoring and cluster confidence.
+    pub scoring: scoring::ScoringConfig,
+    /// Redaction rules deciding which environment variables are secrets.
+    pub redaction: xcprobe_redaction::RedactorConfig,
+    /// Custom detectors, run after the built-in ones.
+    pub detectors: Vec<Arc<dyn detectors::Detector>>,
+    /// Date the plan with the bundle's collection time rather than the
+    /// current time, so that the same bundle always gives the same output.
+    pub reproducible: bool,
+    /// Worker threads for per-cluster work; one per CPU when `None`.
+    pub jobs: Option<usize>,
+}
+
+impl AnalyzeOptions {
+    /// Register a custom detector.
+    pub fn with_detector(mut self, detector: impl detectors::Detector + 'static) -> Self {
+        self.detectors.push(Arc::new(detector));
+        self
+    }
+}
+
+impl Default for AnalyzeOptions {
+    fn default() -> Self {
+        Self {
+            cluster_prefix: "app".to_string(),
+            clustering_strategy: clustering::ClusteringStrategy::default(),
+            hints: None,
+            min_confidence: 0.7,
+            include_rejected: false,
+            scoring: scoring::ScoringConfig::default(),
+            redaction: xcprobe_redaction::RedactorConfig::default(),
+            detectors: Vec::new(),
+            reproducible: false,
+            jobs: None,
+        }
+    }
+}
 
 /// Run the full analysis pipeline on a bundle.
 pub fn analyze_bundle(
     bundle: &xcprobe_bundle_schema::Bundle,
-    cluster_prefix: &str,
-    min_confidence: f64,
+    options: &AnalyzeOptions,
 ) -> Result<PackPlan> {
+    with_jobs(options.jobs, || analyze_clusters(bundle, options))
+}
+
+/// Run `f` on `jobs` worker threads, or on one per CPU when `None`.
+fn with_jobs<T: Send>(jobs: Option<usize>, f: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+    match jobs {
+        Some(jobs) => rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .context("Failed to start worker threads")?
+            .install(f),
+        None => f(),
+    }
+}
+
+fn analyze_clusters(
+    bundle: &xcprobe_bundle_schema::Bundle,
+    options: &AnalyzeOptions,
+) -> Result<PackPlan> {
+    let min_confidence = options.min_confidence;
+
     // Step 1: Score processes/services for business relevance
-    let scores = scoring::score_processes(&bundle.manifest);
+    let manifest_index = index::ManifestIndex::new(&bundle.manifest);
+    let scores = scoring::score_processes(&manifest_index, &options.scoring.processes);
 
-    // Step 2: Cluster into applications
-    let mut clusters = clustering::cluster_applications(bundle, &scores, cluster_prefix)?;
+    // Step 2: Cluster into applications and match well-known applications
+    let mut clusters =
+        clustering::cluster_applications(&manifest_index, &scores, &options.cluster_prefix)?;
+    clustering::merge_clusters(
+        &mut clusters,
+        options.clustering_strategy,
+        &options.cluster_prefix,
+    );
+    let mut hint_warnings = Vec::new();
+    if let Some(ref hints) = options.hints {
+        hint_warnings.extend(hints::apply_cluster_hints(
+            &mut clusters,
+            hints,
+            &options.cluster_prefix,
+        ));
+    }
+    fingerprints::apply_fingerprints(bundle, &mut clusters);
+    registry::attach_registry_settings(bundle, &mut clusters);
+    let redactor = xcprobe_redaction::Redactor::with_config(options.redaction.clone());
+    clustering::mark_sensitive_env_vars(&mut clusters, &redactor);
 
-    // Step 3: Detect dependencies
-    dependencies::detect_dependencies(bundle, &mut clusters)?;
+    // Step 3: Detect dependencies with the built-in and custom detectors,
+    // adding those declared in hints, and resolve their host names with
+    // the source host's hosts file and lookups
+    let mut external_dependencies =
+        detectors::run_detectors(bundle, &mut clusters, &options.detectors)?;
+    if let Some(ref hints) = options.hints {
+        hint_warnings.extend(hints::apply_dependency_hints(&mut clusters, hints));
+    }
+    resolution::resolve_dependencies(&bundle.manifest.name_resolution, &mut external_dependencies);
 
     // Step 4: Build startup DAG
     let dag = dependencies::build_startup_dag(&clusters);
+    let cycles = dependencies::find_dependency_cycles(&clusters);
 
-    // Step 5: Calculate confidence scores
-    for cluster in &mut clusters {
-        confidence::calculate_cluster_confidence(cluster);
-    }
+    // Step 5: Infer readiness checks from health endpoints and ports
+    readiness::infer_readiness(bundle, &mut clusters);
+
+    // Step 6: Detect stateful directories to persist in named volumes
+    volumes::detect_volumes(bundle, &mut clusters);
+
+    // Step 7: Calculate confidence scores
+    clusters.par_iter_mut().for_each(|cluster| {
+        confidence::calculate_cluster_confidence(cluster, &options.scoring.evidence)
+    });
 
     // Filter by minimum confidence
-    clusters.retain(|c| c.confidence >= min_confidence);
+    let (mut clusters, filtered): (Vec<_>, Vec<_>) = clusters
+        .into_iter()
+        .partition(|c| c.confidence >= min_confidence);
+    let mut warnings = hint_warnings;
+    warnings.extend(confidence::filtered_cluster_warnings(
+        &filtered,
+        min_confidence,
+    ));
+    warnings.extend(confidence::cycle_warnings(&cycles));
+    // External dependencies are kept for the retained clusters only
+    for dependency in &mut external_dependencies {
+        dependency
+            .used_by
+            .retain(|id| clusters.iter().any(|c| &c.id == id));
+    }
+    external_dependencies.retain(|d| !d.used_by.is_empty());
+    let rejected_clusters = if options.include_rejected {
+        filtered
+            .into_iter()
+            .map(|c| confidence::reject_cluster(c, min_confidence))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Step 8: Detect language runtimes for the retained clusters
+    runtime::detect_runtimes(bundle, &mut clusters);
+
+    // Step 9: Parse JVM command lines (jar, classpath, heap, profiles)
+    java::detect_java_apps(&mut clusters);
+
+    // Step 10: Run database servers from their version-matched official images
+    databases::detect_databases(bundle, &mut clusters);
+
+    // Step 11: Run the programs of multi-process clusters under supervisord
+    warnings.extend(supervisor::detect_multi_process(
+        &manifest_index,
+        &mut clusters,
+    ));
+
+    // Step 12: Suggest splitting clusters that look like several applications
+    warnings.extend(split::suggest_splits(&manifest_index, &clusters));
+
+    // Step 13: Rewrite the source host's addresses in configuration files
+    // as variables defaulting to compose service names
+    aliases::rewrite_host_addresses(bundle, &mut clusters);
+
+    // Step 14: Replace environment variable values, listening ports and
+    // dependency endpoints in configuration files with template variables
+    config_vars::discover_template_vars(bundle, &external_dependencies, &mut clusters);
+
+    // Step 15: Record the packages owning cluster files and select base
+    // images, pinned to the source distribution for packaged executables
+    // and to runtime versions where known, keeping the official images of
+    // fingerprinted applications and databases
+    packages::attach_package_files(&manifest_index, &mut clusters);
+    docker::select_base_images(&mut clusters);
+
+    // Step 16: Plan the application files to copy into each image
+    clusters
+        .par_iter_mut()
+        .filter(|c| c.database.is_none())
+        .for_each(|cluster| clustering::plan_app_files(&manifest_index, cluster));
+
+    // Step 17: Size resource requests from sampled CPU and memory usage
+    resources::detect_resource_usage(bundle, &mut clusters);
+
+    // Step 18: Give the ports several clusters listen on distinct host ports
+    warnings.extend(host_ports::resolve_port_conflicts(&mut clusters));
+
+    warnings.extend(confidence::missing_command_warnings(&clusters));
+    let overall_confidence = confidence::calculate_overall_confidence(&clusters);
 
     // Build pack plan
-    let plan = PackPlan {
-        schema_version: "1.0.0".to_string(),
-        generated_at: chrono::Utc::now(),
+    let manifest = &bundle.manifest;
+    let generated_at = if options.reproducible {
+        manifest.completed_at.unwrap_or(manifest.collected_at)
+    } else {
+        chrono::Utc::now()
+    };
+    let mut plan = PackPlan {
+        schema_version: xcprobe_bundle_schema::PACKPLAN_SCHEMA_VERSION.to_string(),
+        generated_at,
         source_bundle_id: bundle.manifest.collection_id.clone(),
+        source_os: Some(manifest.system.os_type.clone()).filter(|os| !os.is_empty()),
         clusters,
-        external_dependencies: vec![],
+        external_dependencies,
         startup_dag: dag,
         artifacts: vec![],
-        overall_confidence: 0.0,
-        warnings: vec![],
+        overall_confidence,
+        warnings,
+        rejected_clusters,
     };
+    trace::link_audit_entries(bundle, &mut plan);
 
     Ok(plan)
 }
 
-/// Generate Docker artifacts from a pack plan.
-pub fn generate_artifacts(plan: &PackPlan, output_dir: &std::path::Path) -> Result<()> {
-    for cluster in &plan.clusters {
-        let cluster_dir = output_dir.join(&cluster.id);
-        std::fs::create_dir_all(&cluster_dir)?;
+/// File name of the pack plan in an output directory.
+pub const PLAN_FILE: &str = "packplan.json";
 
-        // Generate Dockerfile
-        let dockerfile = docker::generate_dockerfile(cluster)?;
-        std::fs::write(cluster_dir.join("Dockerfile"), dockerfile)?;
+/// What to do with the output of a previous run in the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwriteMode {
+    /// Fail if the output directory holds generated artifacts.
+    #[default]
+    Refuse,
+    /// Replace them.
+    Force,
+    /// Replace those unchanged since they were generated, as recorded in
+    /// the previous plan's `artifacts`, and keep the others.
+    Merge,
+}
 
-        // Generate entrypoint.sh
-        let entrypoint = docker::generate_entrypoint(cluster)?;
-        std::fs::write(cluster_dir.join("entrypoint.sh"), entrypoint)?;
+/// Options controlling artifact generation.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    /// Directory with `.hbs` files overriding the built-in templates.
+    pub templates_dir: Option<PathBuf>,
+    /// Also write `docker-compose.dev.yaml`, running stand-ins for the
+    /// external dependencies.
+    pub dev_stubs: bool,
+    /// What to do with existing artifacts in the output directory.
+    pub overwrite: OverwriteMode,
+    /// For a plan of a Windows host, generate Linux artifacts and a
+    /// `COMPATIBILITY.md` report instead of Windows containers.
+    pub advise_rewrite: bool,
+    /// Worker threads rendering and writing the artifacts; one per CPU
+    /// when `None`.
+    pub jobs: Option<usize>,
+}
 
-        // Generate config templates
-        for config in &cluster.config_files {
-            if config.templated {
-                let template = docker::generate_config_template(config)?;
-                let template_name = format!(
-                    "{}.tmpl",
-                    std::path::Path::new(&config.source_path)
-                        .file_name()
-                        .map(|n| n.to_string_lossy().to_string())
-                        .unwrap_or_else(|| "config".to_string())
+/// Load a pack plan, as written by `analyze` or edited by hand.
+pub fn load_plan(path: &std::path::Path) -> Result<PackPlan> {
+    let plan_json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pack plan {}", path.display()))?;
+    let document = serde_json::from_str(&plan_json)
+        .with_context(|| format!("Invalid pack plan {}", path.display()))?;
+    let (plan, applied) = migration::migrate_pack_plan(document)
+        .with_context(|| format!("Invalid pack plan {}", path.display()))?;
+    for step in applied {
+        warn!("{}: {}", path.display(), step);
+    }
+    Ok(plan)
+}
+
+/// What [`generate_artifacts`] wrote.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    /// Every written file, for the plan's `artifacts`.
+    pub artifacts: Vec<GeneratedArtifact>,
+    /// Lint issues of the generated Dockerfiles, as plan warnings.
+    pub lint_warnings: Vec<AnalysisWarning>,
+}
+
+/// Generate Docker artifacts from a pack plan.
+///
+/// All artifacts are rendered in memory first (clusters in parallel),
+/// validated as a set, and then written atomically into `output_dir`.
+/// Existing artifacts are handled according to `options.overwrite`.
+pub fn generate_artifacts(
+    plan: &PackPlan,
+    output_dir: &std::path::Path,
+    options: &GenerateOptions,
+) -> Result<Generation> {
+    with_jobs(options.jobs, || write_artifacts(plan, output_dir, options))
+}
+
+fn write_artifacts(
+    plan: &PackPlan,
+    output_dir: &std::path::Path,
+    options: &GenerateOptions,
+) -> Result<Generation> {
+    let engine = TemplateEngine::with_overrides(options.templates_dir.as_deref())?;
+    let windows = windows::is_windows_plan(plan);
+    let platform = if windows && !options.advise_rewrite {
+        ContainerPlatform::Windows
+    } else {
+        ContainerPlatform::Linux
+    };
+    let mut artifacts = build_artifacts(&engine, plan, platform)?;
+    if options.advise_rewrite {
+        if windows {
+            let report = windows::generate_compatibility_report(&engine, plan)?;
+            artifacts.add(windows::COMPATIBILITY_FILE, report, artifacts::FILE_MODE)?;
+        } else {
+            warn!("--advise-rewrite applies to plans of Windows hosts only");
+        }
+    }
+    if options.dev_stubs {
+        if let Some(compose) = stubs::generate_dev_compose(&engine, plan)? {
+            artifacts.add("docker-compose.dev.yaml", compose, artifacts::FILE_MODE)?;
+        }
+    }
+    let mut generated = artifacts.describe();
+
+    let plan_path = output_dir.join(PLAN_FILE);
+    match options.overwrite {
+        OverwriteMode::Force => {}
+        OverwriteMode::Refuse => {
+            let mut existing = artifacts.existing_outputs(output_dir);
+            if plan_path.exists() {
+                existing.push(PLAN_FILE.into());
+            }
+            if !existing.is_empty() {
+                let names: Vec<String> = existing.iter().map(|p| p.display().to_string()).collect();
+                anyhow::bail!(
+                    "{} already holds generated artifacts ({}). \
+                     Use --force to replace them or --merge to keep the edited ones",
+                    output_dir.display(),
+                    names.join(", ")
                 );
-                let templates_dir = cluster_dir.join("templates");
-                std::fs::create_dir_all(&templates_dir)?;
-                std::fs::write(templates_dir.join(template_name), template)?;
             }
         }
+        OverwriteMode::Merge => {
+            // Without a previous plan, every existing file counts as edited
+            let previous = if plan_path.exists() {
+                load_plan(&plan_path)?.artifacts
+            } else {
+                Vec::new()
+            };
+            for path in artifacts.keep_edited(output_dir, &previous)? {
+                info!("Keeping {}, edited or added by hand", path.display());
+                let path = path.to_string_lossy().replace('\\', "/");
+                if let Some(artifact) = generated.iter_mut().find(|a| a.path == path) {
+                    artifact.preserved = true;
+                }
+            }
+        }
+    }
+
+    artifacts.validate()?;
+    artifacts.write_atomic(output_dir)?;
+
+    info!(
+        "Wrote {} artifacts for {} clusters",
+        artifacts.len(),
+        plan.clusters.len()
+    );
+
+    Ok(Generation {
+        artifacts: generated,
+        lint_warnings: lint_artifacts(&artifacts, plan),
+    })
+}
+
+/// Replace the artifacts recorded in a plan with a new generation. Build
+/// results are kept for the Dockerfiles that did not change.
+pub fn record_artifacts(plan: &mut PackPlan, mut artifacts: Vec<GeneratedArtifact>) {
+    for artifact in &mut artifacts {
+        artifact.build = plan
+            .artifacts
+            .iter()
+            .find(|a| a.path == artifact.path && a.content_hash == artifact.content_hash)
+            .and_then(|a| a.build.clone());
+    }
+    plan.artifacts = artifacts;
+}
+
+/// Lint the Dockerfile of every cluster in a rendered artifact set.
+pub fn lint_artifacts(artifacts: &ArtifactSet, plan: &PackPlan) -> Vec<AnalysisWarning> {
+    let per_cluster: Vec<Vec<AnalysisWarning>> = plan
+        .clusters
+        .par_iter()
+        .map(|cluster| {
+            let path = std::path::Path::new(&cluster.id).join("Dockerfile");
+            let Some(dockerfile) = artifacts.get(path) else {
+                return Vec::new();
+            };
+            lint::lint_dockerfile(&dockerfile.content)
+                .into_iter()
+                .map(|issue| issue.to_warning(&cluster.id))
+                .collect()
+        })
+        .collect();
+    per_cluster.into_iter().flatten().collect()
+}
 
-        // Generate README
-        let readme = docker::generate_readme(cluster)?;
-        std::fs::write(cluster_dir.join("README.md"), readme)?;
+/// Replace the Dockerfile lint warnings of a plan with a new set.
+pub fn replace_lint_warnings(plan: &mut PackPlan, lint_warnings: Vec<AnalysisWarning>) {
+    plan.warnings
+        .retain(|w| !w.code.starts_with(lint::LINT_CODE_PREFIX));
+    plan.warnings.extend(lint_warnings);
+}
 
-        // Generate confidence.json
-        let confidence_report = confidence::generate_confidence_report(cluster)?;
-        std::fs::write(cluster_dir.join("confidence.json"), confidence_report)?;
+/// Render all artifacts for a pack plan into an in-memory set, for Linux or
+/// Windows containers.
+pub fn build_artifacts(
+    engine: &TemplateEngine,
+    plan: &PackPlan,
+    platform: ContainerPlatform,
+) -> Result<ArtifactSet> {
+    // Collected in plan order, whatever order the clusters finish in
+    let cluster_sets: Vec<Result<ArtifactSet>> = plan
+        .clusters
+        .par_iter()
+        .map(|cluster| build_cluster_artifacts(engine, cluster, platform))
+        .collect();
 
-        info!("Generated artifacts for cluster: {}", cluster.id);
+    let mut artifacts = ArtifactSet::new();
+    for cluster_set in cluster_sets {
+        artifacts.merge(cluster_set?)?;
     }
 
     // Generate docker-compose.yaml
-    let compose = docker::generate_compose(plan)?;
-    std::fs::write(output_dir.join("docker-compose.yaml"), compose)?;
+    let compose = docker::generate_compose(engine, plan, platform)?;
+    artifacts.add("docker-compose.yaml", compose, artifacts::FILE_MODE)?;
+
+    // Generate the dependency graph, for Mermaid and Graphviz
+    artifacts.add(
+        "dependencies.mmd",
+        dependencies::mermaid_graph(plan),
+        artifacts::FILE_MODE,
+    )?;
+    artifacts.add(
+        "dependencies.dot",
+        dependencies::dot_graph(plan),
+        artifacts::FILE_MODE,
+    )?;
+
+    Ok(artifacts)
+}
+
+/// Render the artifacts belonging to a single cluster.
+fn build_cluster_artifacts(
+    engine: &TemplateEngine,
+    cluster: &AppCluster,
+    platform: ContainerPlatform,
+) -> Result<ArtifactSet> {
+    let mut artifacts = ArtifactSet::new();
+    let cluster_dir = std::path::Path::new(&cluster.id);
+
+    // Databases run their official image: no Dockerfile, a migration guide
+    let mut lint_issues = Vec::new();
+    if let Some(migration) = docker::generate_migration(engine, cluster)? {
+        artifacts.add(
+            cluster_dir.join("MIGRATION.md"),
+            migration,
+            artifacts::FILE_MODE,
+        )?;
+    } else {
+        // Generate the Dockerfile and its entrypoint script
+        let (dockerfile, entrypoint, entrypoint_name) = match platform {
+            ContainerPlatform::Linux => (
+                docker::generate_dockerfile(engine, cluster)?,
+                docker::generate_entrypoint(engine, cluster)?,
+                "entrypoint.sh",
+            ),
+            ContainerPlatform::Windows => (
+                docker::generate_windows_dockerfile(engine, cluster)?,
+                docker::generate_windows_entrypoint(engine, cluster)?,
+                "entrypoint.ps1",
+            ),
+        };
+        lint_issues = lint::lint_dockerfile(&dockerfile);
+        if platform == ContainerPlatform::Linux {
+            if let Some(conf) = docker::generate_supervisord_conf(engine, cluster)? {
+                artifacts.add(
+                    cluster_dir.join("supervisord.conf"),
+                    conf,
+                    artifacts::FILE_MODE,
+                )?;
+            }
+        }
+        artifacts.add(
+            cluster_dir.join("Dockerfile"),
+            dockerfile,
+            artifacts::FILE_MODE,
+        )?;
+        artifacts.add(
+            cluster_dir.join(entrypoint_name),
+            entrypoint,
+            artifacts::EXECUTABLE_MODE,
+        )?;
+
+        // Generate config templates
+        for config in &cluster.config_files {
+            if config.templated {
+                let template = docker::generate_config_template(engine, config)?;
+                let template_name = docker::config_template_name(config);
+                artifacts.add(
+                    cluster_dir.join("templates").join(template_name),
+                    template,
+                    artifacts::FILE_MODE,
+                )?;
+            }
+        }
+    }
+
+    // Generate README
+    let readme = docker::generate_readme(engine, cluster)?;
+    artifacts.add(cluster_dir.join("README.md"), readme, artifacts::FILE_MODE)?;
+
+    // Generate confidence.json, with the lint issues of the Dockerfile
+    let confidence_report = confidence::generate_confidence_report(cluster, &lint_issues)?;
+    artifacts.add(
+        cluster_dir.join("confidence.json"),
+        confidence_report,
+        artifacts::FILE_MODE,
+    )?;
+
+    debug!("Rendered artifacts for cluster: {}", cluster.id);
 
-    Ok(())
+    Ok(artifacts)
 }
@@ -0,0 +1,316 @@
+//! Linting of generated Dockerfiles.
+//!
+//! [`crate::artifacts::ArtifactSet::validate`] rejects Dockerfiles that are
+//! structurally broken. The lint rules here catch Dockerfiles that parse but
+//! would not build or run: unknown base image tags, privileged ports exposed
+//! to a non-root user, a missing `CMD` and malformed `HEALTHCHECK`s.
+
+use regex::Regex;
+use std::sync::LazyLock;
+use xcprobe_bundle_schema::AnalysisWarning;
+
+/// Prefix of the plan warning codes raised by lint rules.
+pub const LINT_CODE_PREFIX: &str = "dockerfile_";
+
+/// Tags published for the base images the analyzer selects.
+static KNOWN_TAGS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| {
+    [
+        (
+            "node",
+            r"^(\d+(\.\d+){0,2}|lts|current)(-(alpine|slim|bookworm|bullseye))?$",
+        ),
+        (
+            "python",
+            r"^[23](\.\d+){1,2}(-(slim|alpine|bookworm|bullseye))?$",
+        ),
+        // Temurin publishes Java 8, 11 and 16 onwards
+        (
+            "eclipse-temurin",
+            r"^(8|11|1[6-9]|[2-9]\d)(\.[\d.+_]+)?-(jre|jdk)(-(alpine|jammy|noble|focal))?$",
+        ),
+        (
+            "mcr.microsoft.com/dotnet/aspnet",
+            r"^\d+\.\d+(-(alpine|bookworm-slim|jammy|noble|(nanoserver|windowsservercore)-ltsc20(19|22|25)))?$",
+        ),
+        (
+            "mcr.microsoft.com/windows/servercore",
+            r"^ltsc20(19|22|25)(-amd64)?$",
+        ),
+        (
+            "mcr.microsoft.com/windows/nanoserver",
+            r"^ltsc20(19|22|25)(-amd64)?$",
+        ),
+        (
+            "ruby",
+            r"^\d+\.\d+(\.\d+)?(-(slim|alpine|bookworm|bullseye))?$",
+        ),
+        (
+            "php",
+            r"^\d+\.\d+(\.\d+)?-(cli|fpm|apache)(-(alpine|bookworm|bullseye))?$",
+        ),
+        ("debian", r"^(bookworm|bullseye|buster|trixie|\d+)(-slim)?$"),
+        (
+            "nginx",
+            r"^(\d+(\.\d+){0,2}|mainline|stable|alpine)(-alpine)?$",
+        ),
+    ]
+    .into_iter()
+    .map(|(image, tag)| (image, Regex::new(tag).unwrap()))
+    .collect()
+});
+
+/// A valid image reference: `[registry/]name[:tag]`.
+static IMAGE_REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[a-z0-9]+([._/-][a-z0-9]+)*(:\d+)?(/[a-z0-9]+([._-][a-z0-9]+)*)*(:[\w][\w.-]{0,127})?(@sha256:[0-9a-f]{64})?$").unwrap()
+});
+
+/// Docker durations (`30s`, `1m30s`, `500ms`).
+static DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d+(\.\d+)?(ns|us|ms|s|m|h))+$").unwrap());
+
+/// A problem found in a generated Dockerfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    /// Rule code, also used as the plan warning code.
+    pub code: &'static str,
+    /// Severity (warning, error).
+    pub severity: &'static str,
+    /// Line of the offending instruction.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl LintIssue {
+    fn new(
+        code: &'static str,
+        severity: &'static str,
+        line: Option<usize>,
+        message: String,
+    ) -> Self {
+        Self {
+            code,
+            severity,
+            line,
+            message,
+        }
+    }
+
+    /// The issue as `line N: message`.
+    pub fn describe(&self) -> String {
+        match self.line {
+            Some(line) => format!("line {}: {}", line, self.message),
+            None => self.message.clone(),
+        }
+    }
+
+    /// The issue as a plan warning for a cluster.
+    pub fn to_warning(&self, cluster_id: &str) -> AnalysisWarning {
+        AnalysisWarning::new(
+            self.code,
+            self.severity,
+            format!("{}/Dockerfile {}", cluster_id, self.describe()),
+            vec![cluster_id.to_string()],
+        )
+    }
+}
+
+/// A Dockerfile instruction with its continuation lines joined.
+struct Instruction {
+    line: usize,
+    keyword: String,
+    args: String,
+}
+
+fn instructions(content: &str) -> Vec<Instruction> {
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut continuation = false;
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line = raw_line.trim();
+        let was_continuation = continuation;
+        continuation = line.ends_with('\\');
+        let line = line.trim_end_matches('\\').trim();
+
+        if was_continuation {
+            if let Some(last) = instructions.last_mut() {
+                if !line.starts_with('#') {
+                    last.args = format!("{} {}", last.args, line).trim().to_string();
+                }
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        instructions.push(Instruction {
+            line: idx + 1,
+            keyword: keyword.to_uppercase(),
+            args: args.trim().to_string(),
+        });
+    }
+    instructions
+}
+
+/// Lint a generated Dockerfile.
+pub fn lint_dockerfile(content: &str) -> Vec<LintIssue> {
+    let instructions = instructions(content);
+    let mut issues = Vec::new();
+
+    for from in instructions.iter().filter(|i| i.keyword == "FROM") {
+        let image = from.args.split_whitespace().next().unwrap_or_default();
+        if let Some(message) = check_base_image(image) {
+            issues.push(LintIssue::new(
+                "dockerfile_base_image",
+                "warning",
+                Some(from.line),
+                message,
+            ));
+        }
+    }
+
+    // The last USER applies to the running container
+    let user = instructions
+        .iter()
+        .rev()
+        .find(|i| i.keyword == "USER")
+        .map(|i| i.args.split(':').next().unwrap_or_default().to_string());
+    if let Some(user) = user.filter(|u| u != "root" && u != "0") {
+        for expose in instructions.iter().filter(|i| i.keyword == "EXPOSE") {
+            for port in expose.args.split_whitespace() {
+                let number = port.split('/').next().unwrap_or(port);
+                if number.parse::<u16>().is_ok_and(|p| p < 1024) {
+                    issues.push(LintIssue::new(
+                        "dockerfile_privileged_port",
+                        "warning",
+                        Some(expose.line),
+                        format!(
+                            "port {} is privileged and cannot be bound by non-root user {}",
+                            number, user
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    if !instructions.iter().any(|i| i.keyword == "CMD") {
+        issues.push(LintIssue::new(
+            "dockerfile_missing_cmd",
+            "error",
+            None,
+            "no CMD: the entrypoint has no command to run".to_string(),
+        ));
+    }
+
+    for healthcheck in instructions.iter().filter(|i| i.keyword == "HEALTHCHECK") {
+        if let Err(message) = check_healthcheck(&healthcheck.args) {
+            issues.push(LintIssue::new(
+                "dockerfile_healthcheck",
+                "error",
+                Some(healthcheck.line),
+                format!("invalid HEALTHCHECK: {}", message),
+            ));
+        }
+    }
+
+    issues
+}
+
+fn check_base_image(image: &str) -> Option<String> {
+    if !IMAGE_REFERENCE.is_match(image) {
+        return Some(format!("invalid base image reference '{}'", image));
+    }
+    if image.contains('@') {
+        // Pinned to a digest
+        return None;
+    }
+    let (name, tag) = match image.rsplit_once(':').filter(|(_, tag)| !tag.contains('/')) {
+        Some((name, tag)) => (name, tag),
+        None => (image, "latest"),
+    };
+    if tag == "latest" {
+        return Some(format!("base image {} is not pinned to a tag", name));
+    }
+    let (_, pattern) = KNOWN_TAGS.iter().find(|(known, _)| *known == name)?;
+    if pattern.is_match(tag) {
+        None
+    } else {
+        Some(format!("unknown tag '{}' for base image {}", tag, name))
+    }
+}
+
+fn check_healthcheck(args: &str) -> Result<(), String> {
+    if args.eq_ignore_ascii_case("NONE") {
+        return Ok(());
+    }
+    let mut tokens = args.split_whitespace();
+    for token in tokens.by_ref() {
+        if token.eq_ignore_ascii_case("CMD") {
+            return match tokens.next() {
+                Some(_) => Ok(()),
+                None => Err("CMD has no command".to_string()),
+            };
+        }
+        let Some((option, value)) = token.strip_prefix("--").and_then(|o| o.split_once('=')) else {
+            return Err(format!("unexpected '{}' before CMD", token));
+        };
+        let valid = match option {
+            "interval" | "timeout" | "start-period" | "start-interval" => DURATION.is_match(value),
+            "retries" => value.parse::<u32>().is_ok(),
+            _ => return Err(format!("unknown option --{}", option)),
+        };
+        if !valid {
+            return Err(format!("invalid value '{}' for --{}", value, option));
+        }
+    }
+    Err("missing CMD".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_generated_dockerfile() {
+        let dockerfile = r#"FROM eclipse-temurin:17-jre
+WORKDIR /opt/app
+USER app
+EXPOSE 80
+EXPOSE 8080
+HEALTHCHECK --interval=10s --timeout=5s --retries=3 \
+  CMD curl -f http://localhost:8080/health || exit 1
+ENTRYPOINT ["/entrypoint.sh"]
+CMD ["/opt/app/bin/server"]
+"#;
+        let issues = lint_dockerfile(dockerfile);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].code, "dockerfile_privileged_port");
+        assert_eq!(issues[0].line, Some(4));
+
+        let dockerfile = "FROM eclipse-temurin:13-jre\nHEALTHCHECK --interval=ten CMD true\nENTRYPOINT [\"/entrypoint.sh\"]\n";
+        let codes: Vec<&str> = lint_dockerfile(dockerfile).iter().map(|i| i.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                "dockerfile_base_image",
+                "dockerfile_missing_cmd",
+                "dockerfile_healthcheck"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_base_image() {
+        assert_eq!(check_base_image("python:3.11-slim"), None);
+        assert_eq!(
+            check_base_image("mcr.microsoft.com/dotnet/aspnet:8.0"),
+            None
+        );
+        assert_eq!(check_base_image("registry.local:5000/team/app:1.2"), None);
+        assert!(check_base_image("debian").unwrap().contains("not pinned"));
+        assert!(check_base_image("node:twenty")
+            .unwrap()
+            .contains("unknown tag"));
+        assert!(check_base_image("Bad Image").unwrap().contains("invalid"));
+    }
+}
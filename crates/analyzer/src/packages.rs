@@ -0,0 +1,304 @@
+//! Package origin of cluster files.
+//!
+//! The collector asks dpkg or rpm which installed packages own the
+//! executables and configuration files of services and processes. A file
+//! no package owns was deployed by hand and is packed into the image. A
+//! native application whose executables come from distribution packages
+//! runs on the source host's distribution release, with those packages
+//! installed again, rather than on a generic base image.
+
+use crate::index::ManifestIndex;
+use xcprobe_bundle_schema::{AppCluster, Decision, PackageFile};
+
+/// Confidence that the source host's release runs the packaged executables.
+const DISTRIBUTION_IMAGE_CONFIDENCE: f64 = 0.8;
+
+/// Record the owning package of each executable and configuration file of
+/// the clusters, and pin the base image of native clusters running
+/// packaged executables to the source host's distribution release.
+pub fn attach_package_files(index: &ManifestIndex<'_>, clusters: &mut [AppCluster]) {
+    let distribution = index
+        .manifest
+        .system
+        .os_version
+        .as_deref()
+        .and_then(distribution_image);
+    for cluster in clusters.iter_mut() {
+        let executables = cluster
+            .services
+            .iter()
+            .filter_map(|s| s.exec_start.as_deref()?.split_whitespace().next())
+            .chain(cluster.processes.iter().map(|p| p.command.as_str()))
+            .map(|path| (path.to_string(), "executable"));
+        let configs = cluster
+            .config_files
+            .iter()
+            .map(|c| (c.source_path.clone(), "config"));
+        let files: Vec<(String, &str)> = executables.chain(configs).collect();
+
+        let mut package_files: Vec<PackageFile> = Vec::new();
+        for (path, kind) in files {
+            if package_files.iter().any(|f| f.path == path) {
+                continue;
+            }
+            let Some(owner) = index.package_owner(&path) else {
+                continue;
+            };
+            let package = owner.packages.first().map(|p| package_name(p).to_string());
+            let version = package.as_deref().and_then(|name| {
+                index
+                    .manifest
+                    .packages
+                    .iter()
+                    .find(|p| p.name == name)
+                    .map(|p| p.version.clone())
+            });
+            package_files.push(PackageFile {
+                path,
+                kind: kind.to_string(),
+                package,
+                version,
+                source: owner.source.clone(),
+                evidence_ref: owner.evidence_ref.clone(),
+            });
+        }
+
+        for file in package_files
+            .iter()
+            .filter(|f| f.kind == "executable" && f.package.is_none())
+        {
+            cluster.decisions.push(Decision::new(
+                format!("Custom-deployed executable {}", file.path),
+                format!(
+                    "No {} package owns it; it is packed into the image",
+                    file.source
+                ),
+                file.evidence_ref.iter().cloned().collect(),
+                0.9,
+            ));
+        }
+
+        let mut system_packages: Vec<String> = package_files
+            .iter()
+            .filter(|f| f.kind == "executable")
+            .filter_map(|f| f.package.clone())
+            .collect();
+        system_packages.sort();
+        system_packages.dedup();
+        let native = cluster
+            .runtime
+            .as_ref()
+            .is_none_or(|r| r.language == "native");
+        let pinnable = cluster.base_image.is_none()
+            && cluster.database.is_none()
+            && cluster.app_type != "proxy"
+            && native;
+        if let Some(image) = distribution
+            .as_ref()
+            .filter(|_| pinnable && !system_packages.is_empty())
+        {
+            cluster.decisions.push(Decision::new(
+                format!("Base image: {}", image),
+                format!(
+                    "Executables come from {} packages of the source distribution ({})",
+                    package_files[0].source,
+                    system_packages.join(", ")
+                ),
+                package_files
+                    .iter()
+                    .filter(|f| f.package.is_some())
+                    .filter_map(|f| f.evidence_ref.clone())
+                    .collect(),
+                DISTRIBUTION_IMAGE_CONFIDENCE,
+            ));
+            cluster.base_image = Some(image.clone());
+            cluster.system_packages = system_packages;
+        }
+        cluster.package_files = package_files;
+    }
+}
+
+/// Package name without the architecture qualifier dpkg gives
+/// multi-arch packages (`libc6:amd64`).
+fn package_name(package: &str) -> &str {
+    package.split(':').next().unwrap_or(package)
+}
+
+/// Official image of the distribution release described by `os_version`:
+/// the `/etc/os-release` fields, or a release line such as
+/// `Rocky Linux release 9.3 (Blue Onyx)`.
+pub fn distribution_image(os_version: &str) -> Option<String> {
+    let field = |name: &str| {
+        os_version.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(name)?.strip_prefix('=')?;
+            Some(value.trim_matches('"').to_string())
+        })
+    };
+    let (id, version) = match (field("ID"), field("VERSION_ID")) {
+        (Some(id), Some(version)) => (id.to_lowercase(), version),
+        _ => {
+            let id = os_version.split_whitespace().next()?.to_lowercase();
+            let version = os_version
+                .split_whitespace()
+                .find(|w| w.starts_with(|c: char| c.is_ascii_digit()))?;
+            (id, version.to_string())
+        }
+    };
+    let mut parts = version.split('.');
+    let major = parts.next().filter(|m| m.parse::<u32>().is_ok())?;
+    let image = match id.as_str() {
+        "debian" => format!("debian:{}-slim", major),
+        "ubuntu" => format!("ubuntu:{}.{}", major, parts.next()?),
+        "rocky" => format!("rockylinux:{}", major),
+        "almalinux" => format!("almalinux:{}", major),
+        "rhel" | "red" => format!("registry.access.redhat.com/ubi{}/ubi", major),
+        "centos" if major.parse::<u32>().ok()? >= 9 => {
+            format!("quay.io/centos/centos:stream{}", major)
+        }
+        "centos" => format!("centos:{}", major),
+        "fedora" => format!("fedora:{}", major),
+        "amzn" | "amazon" => format!("amazonlinux:{}", major),
+        "ol" | "oracle" => format!("oraclelinux:{}", major),
+        _ => return None,
+    };
+    Some(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{
+        ClusterService, ConfigFileSpec, Manifest, Package, PackageOwner, RuntimeInfo,
+    };
+
+    fn cluster(exec_starts: &[&str]) -> AppCluster {
+        AppCluster {
+            id: "app-0".to_string(),
+            name: "redis".to_string(),
+            app_type: "worker".to_string(),
+            services: exec_starts
+                .iter()
+                .map(|exec_start| ClusterService {
+                    name: "redis-server".to_string(),
+                    exec_start: Some(exec_start.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_distribution_image() {
+        let os_release = "PRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\nNAME=\"Ubuntu\"\n\
+                          VERSION_ID=\"22.04\"\nID=ubuntu\nID_LIKE=debian\n";
+        assert_eq!(distribution_image(os_release).unwrap(), "ubuntu:22.04");
+        assert_eq!(
+            distribution_image("ID=debian\nVERSION_ID=\"12\"\n").unwrap(),
+            "debian:12-slim"
+        );
+        assert_eq!(
+            distribution_image("Rocky Linux release 9.3 (Blue Onyx)").unwrap(),
+            "rockylinux:9"
+        );
+        assert_eq!(
+            distribution_image("Red Hat Enterprise Linux release 8.9 (Ootpa)").unwrap(),
+            "registry.access.redhat.com/ubi8/ubi"
+        );
+        assert!(distribution_image("12.5").is_none());
+        assert!(distribution_image("ID=arch\nVERSION_ID=rolling\n").is_none());
+    }
+
+    #[test]
+    fn test_attach_package_files() {
+        let mut manifest = Manifest::default();
+        manifest.system.os_version = Some("ID=debian\nVERSION_ID=\"12\"\n".to_string());
+        manifest.packages.push(Package {
+            name: "redis-server".to_string(),
+            version: "5:7.0.15-1".to_string(),
+            architecture: Some("amd64".to_string()),
+            description: None,
+            install_date: None,
+            source: "dpkg".to_string(),
+            evidence_ref: None,
+        });
+        for (path, packages) in [
+            ("/usr/bin/redis-server", vec!["redis-server:amd64"]),
+            ("/opt/tools/redis-exporter", vec![]),
+            ("/etc/redis/redis.conf", vec!["redis-server"]),
+        ] {
+            manifest.package_owners.push(PackageOwner {
+                path: path.to_string(),
+                packages: packages.into_iter().map(str::to_string).collect(),
+                source: "dpkg".to_string(),
+                services: vec!["redis-server".to_string()],
+                evidence_ref: Some("evidence/dpkg_S_1.txt".to_string()),
+            });
+        }
+        let index = ManifestIndex::new(&manifest);
+
+        let mut cluster = cluster(&[
+            "/usr/bin/redis-server /etc/redis/redis.conf",
+            "/opt/tools/redis-exporter --port 9121",
+        ]);
+        cluster.config_files.push(ConfigFileSpec {
+            source_path: "/etc/redis/redis.conf".to_string(),
+            container_path: "/etc/redis/redis.conf".to_string(),
+            templated: false,
+            template_vars: Vec::new(),
+            content: None,
+            evidence_ref: None,
+        });
+        let mut runtime_cluster = cluster.clone();
+        runtime_cluster.runtime = Some(RuntimeInfo {
+            language: "python".to_string(),
+            version: None,
+            evidence_refs: Vec::new(),
+        });
+        let mut clusters = vec![cluster, runtime_cluster];
+        attach_package_files(&index, &mut clusters);
+
+        let cluster = &clusters[0];
+        let files: Vec<(&str, &str, Option<&str>, Option<&str>)> = cluster
+            .package_files
+            .iter()
+            .map(|f| {
+                (
+                    f.path.as_str(),
+                    f.kind.as_str(),
+                    f.package.as_deref(),
+                    f.version.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                (
+                    "/usr/bin/redis-server",
+                    "executable",
+                    Some("redis-server"),
+                    Some("5:7.0.15-1")
+                ),
+                ("/opt/tools/redis-exporter", "executable", None, None),
+                (
+                    "/etc/redis/redis.conf",
+                    "config",
+                    Some("redis-server"),
+                    Some("5:7.0.15-1")
+                ),
+            ]
+        );
+        assert_eq!(cluster.base_image.as_deref(), Some("debian:12-slim"));
+        assert_eq!(cluster.system_packages, vec!["redis-server"]);
+        assert!(cluster
+            .decisions
+            .iter()
+            .any(|d| d.decision == "Custom-deployed executable /opt/tools/redis-exporter"));
+
+        // Language runtimes keep their runtime images
+        assert_eq!(clusters[1].package_files.len(), 3);
+        assert!(clusters[1].base_image.is_none());
+        assert!(clusters[1].system_packages.is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+//! Reverse-proxy configuration parsing.
+//!
+//! Extracts backend targets from nginx (`proxy_pass` and the other `*_pass`
+//! directives, expanded through `upstream` blocks) and Apache (`ProxyPass`,
+//! expanded through `balancer://` members) configuration.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// nginx `upstream name { ... }` blocks.
+static NGINX_UPSTREAM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)\bupstream\s+([\w.-]+)\s*\{(.*?)\}").unwrap());
+
+/// `server` entries of an nginx upstream block.
+static NGINX_UPSTREAM_SERVER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bserver\s+([^\s;]+)").unwrap());
+
+/// nginx directives forwarding requests to a backend.
+static NGINX_PASS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(proxy_pass|fastcgi_pass|grpc_pass|uwsgi_pass)\s+([^\s;]+)\s*;").unwrap()
+});
+
+/// Apache `<Proxy balancer://name>` sections.
+static APACHE_BALANCER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<Proxy\s+"?balancer://([\w.-]+)/?"?\s*>(.*?)</Proxy>"#).unwrap()
+});
+
+/// `BalancerMember` entries of an Apache balancer.
+static APACHE_BALANCER_MEMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\bBalancerMember\s+"?([^\s"]+)"#).unwrap());
+
+/// Apache `ProxyPass` and `ProxyPassMatch` directives.
+static APACHE_PROXY_PASS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?im)^\s*(ProxyPass(?:Match)?)\s+"?\S+?"?\s+"?([^\s"]+)"#).unwrap()
+});
+
+/// A backend a reverse proxy forwards requests to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyTarget {
+    /// Directive naming the target (`proxy_pass`, `ProxyPass`, ...).
+    pub directive: String,
+    /// Backend host or address.
+    pub host: String,
+    /// Backend port, when given or implied by the scheme.
+    pub port: Option<u16>,
+}
+
+impl ProxyTarget {
+    /// The target as `host:port` (or `host`).
+    pub fn endpoint(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
+        }
+    }
+
+    /// Whether the target is on the analyzed host itself.
+    pub fn is_local(&self, hostname: &str) -> bool {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        host == "localhost"
+            || host.starts_with("127.")
+            || host == "::1"
+            || host == "0.0.0.0"
+            || (!hostname.is_empty() && host.eq_ignore_ascii_case(hostname))
+    }
+}
+
+/// Extract the reverse-proxy targets of an nginx or Apache configuration.
+pub fn parse_proxy_targets(content: &str) -> Vec<ProxyTarget> {
+    let content = strip_comments(content);
+    let mut targets = Vec::new();
+
+    // Named groups of backends, from nginx upstreams and Apache balancers
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for caps in NGINX_UPSTREAM.captures_iter(&content) {
+        let servers = NGINX_UPSTREAM_SERVER
+            .captures_iter(&caps[2])
+            .map(|s| s[1].to_string())
+            .collect();
+        groups.insert(caps[1].to_string(), servers);
+    }
+    for caps in APACHE_BALANCER.captures_iter(&content) {
+        let members = APACHE_BALANCER_MEMBER
+            .captures_iter(&caps[2])
+            .map(|m| m[1].to_string())
+            .collect();
+        groups.insert(caps[1].to_string(), members);
+    }
+
+    let passes = NGINX_PASS
+        .captures_iter(&content)
+        .chain(APACHE_PROXY_PASS.captures_iter(&content));
+    for caps in passes {
+        let directive = &caps[1];
+        let Some((host, port)) = parse_target(&caps[2]) else {
+            continue;
+        };
+        match groups.get(&host) {
+            Some(members) => {
+                for member in members {
+                    if let Some((host, port)) = parse_target(member) {
+                        push_target(&mut targets, directive, host, port);
+                    }
+                }
+            }
+            None => push_target(&mut targets, directive, host, port),
+        }
+    }
+
+    targets
+}
+
+fn push_target(targets: &mut Vec<ProxyTarget>, directive: &str, host: String, port: Option<u16>) {
+    let target = ProxyTarget {
+        directive: directive.to_string(),
+        host,
+        port,
+    };
+    if !targets.contains(&target) {
+        targets.push(target);
+    }
+}
+
+/// Drop `#` comments, which both nginx and Apache use.
+fn strip_comments(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Split a backend URL or address into host and port. Unix sockets,
+/// backends named by a variable and `ProxyPass path !` exclusions have no
+/// network target.
+fn parse_target(target: &str) -> Option<(String, Option<u16>)> {
+    let target = target.trim_matches('"');
+    if target == "!" || target.starts_with("unix:") {
+        return None;
+    }
+    let (scheme, rest) = match target.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme.to_lowercase()), rest),
+        None => (None, target),
+    };
+    let authority = rest.split('/').next().unwrap_or(rest);
+    if authority.is_empty() || authority.contains('$') {
+        return None;
+    }
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()),
+        _ => (authority, None),
+    };
+    let port = port.or(match scheme.as_deref() {
+        Some("http") | Some("ws") | Some("grpc") => Some(80),
+        Some("https") | Some("wss") | Some("grpcs") => Some(443),
+        Some("ajp") => Some(8009),
+        _ => None,
+    });
+
+    Some((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nginx_upstreams_and_passes() {
+        let config = r#"
+upstream api_backend {
+    server 127.0.0.1:8080 weight=3;
+    server 10.0.0.12:8080 backup;
+}
+server {
+    listen 80;
+    location /api/ { proxy_pass http://api_backend/; }
+    location /static/ { proxy_pass http://cdn.example.com; }
+    location ~ \.php$ { fastcgi_pass unix:/run/php/php-fpm.sock; }
+    location /grpc { grpc_pass grpc://localhost:50051; }
+    # proxy_pass http://old-backend:9000;
+}
+"#;
+        let targets = parse_proxy_targets(config);
+        let endpoints: Vec<(String, &str)> = targets
+            .iter()
+            .map(|t| (t.endpoint(), t.directive.as_str()))
+            .collect();
+        assert_eq!(
+            endpoints,
+            vec![
+                ("127.0.0.1:8080".to_string(), "proxy_pass"),
+                ("10.0.0.12:8080".to_string(), "proxy_pass"),
+                ("cdn.example.com:80".to_string(), "proxy_pass"),
+                ("localhost:50051".to_string(), "grpc_pass"),
+            ]
+        );
+        assert!(targets[0].is_local("web01"));
+        assert!(!targets[1].is_local("web01"));
+    }
+
+    #[test]
+    fn test_apache_proxy_pass_and_balancers() {
+        let config = r#"
+<VirtualHost *:80>
+    ProxyPass /health !
+    ProxyPass "/app" "http://web01:3000/app"
+    ProxyPassReverse /app http://web01:3000/app
+    ProxyPass /api balancer://apicluster/
+    <Proxy "balancer://apicluster">
+        BalancerMember http://127.0.0.1:8081
+        BalancerMember https://api.internal
+    </Proxy>
+    ProxyPassMatch ^/legacy/(.*)$ ajp://127.0.0.1/$1
+</VirtualHost>
+"#;
+        let targets = parse_proxy_targets(config);
+        let endpoints: Vec<String> = targets.iter().map(|t| t.endpoint()).collect();
+        assert_eq!(
+            endpoints,
+            vec![
+                "web01:3000",
+                "127.0.0.1:8081",
+                "api.internal:443",
+                "127.0.0.1:8009"
+            ]
+        );
+        assert!(targets[0].is_local("WEB01"));
+    }
+}
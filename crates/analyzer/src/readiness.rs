@@ -0,0 +1,355 @@
+//! Readiness check inference.
+//!
+//! Clusters get an HTTP check when the collected evidence names a health
+//! endpoint (an nginx `location /health` block, a Spring Boot actuator) and
+//! fall back to a TCP check on their first exposed port. Every inference is
+//! recorded as a decision referencing the evidence behind it.
+
+use regex::Regex;
+use std::sync::LazyLock;
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, ReadinessCheck};
+
+/// nginx `location` blocks serving a health endpoint.
+static HEALTH_LOCATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"location\s+(?:=\s*)?(/(?:health|healthz|healthcheck|status|ping|ready)[\w/.-]*)")
+        .unwrap()
+});
+
+/// nginx `listen` directive, with an optional address.
+static NGINX_LISTEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"listen\s+(?:\S*:)?(\d+)").unwrap());
+
+/// Spring Boot management (actuator) port.
+static ACTUATOR_PORT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"management\.(?:server\.)?port\s*[=:]\s*(\d+)").unwrap());
+
+/// Spring Boot actuator base path.
+static ACTUATOR_BASE_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"management\.endpoints\.web\.base-path\s*[=:]\s*(/\S*)").unwrap());
+
+/// Seconds between checks.
+pub(crate) const INTERVAL_SECONDS: u32 = 10;
+/// Seconds before a check times out.
+pub(crate) const TIMEOUT_SECONDS: u32 = 5;
+/// Failed checks before the container is unhealthy.
+pub(crate) const RETRIES: u32 = 3;
+
+/// Infer a readiness check for each cluster that has none.
+pub fn infer_readiness(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        if cluster.readiness.is_some() || cluster.app_type == "batch" {
+            continue;
+        }
+        let configs = cluster_configs(bundle, cluster);
+        let inferred = nginx_health_check(cluster, &configs)
+            .or_else(|| actuator_check(cluster, &configs))
+            .or_else(|| port_check(cluster));
+
+        if let Some((check, decision)) = inferred {
+            cluster.readiness = Some(check);
+            cluster.decisions.push(decision);
+        }
+    }
+}
+
+/// A collected configuration file: path, evidence reference and content.
+pub(crate) struct ConfigContent<'a> {
+    pub path: &'a str,
+    pub evidence_ref: &'a str,
+    pub content: String,
+}
+
+/// Web server configuration directories, matched against config file paths.
+const WEB_SERVER_CONFIGS: &[&str] = &["nginx", "apache2", "httpd"];
+
+/// Contents of the cluster's configuration files, plus the host's web server
+/// (nginx, Apache) configuration for proxy and web server clusters.
+pub(crate) fn cluster_configs<'a>(
+    bundle: &'a Bundle,
+    cluster: &'a AppCluster,
+) -> Vec<ConfigContent<'a>> {
+    let exec = cluster
+        .services
+        .iter()
+        .filter_map(|s| s.exec_start.as_deref())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut servers: Vec<&str> = WEB_SERVER_CONFIGS
+        .iter()
+        .copied()
+        .filter(|name| cluster.name.contains(name) || exec.contains(name))
+        .collect();
+    if servers.is_empty() && cluster.app_type == "proxy" {
+        servers = WEB_SERVER_CONFIGS.to_vec();
+    }
+    let web_server_configs = bundle
+        .manifest
+        .config_files
+        .iter()
+        .filter(move |f| servers.iter().any(|name| f.path.contains(name)))
+        .filter_map(|f| Some((f.path.as_str(), f.attachment_ref.as_deref()?)));
+    let candidates = cluster
+        .config_files
+        .iter()
+        .filter_map(|c| Some((c.source_path.as_str(), c.evidence_ref.as_deref()?)))
+        .chain(web_server_configs);
+
+    let mut configs: Vec<ConfigContent<'a>> = Vec::new();
+    for (path, evidence_ref) in candidates {
+        if configs.iter().any(|c| c.evidence_ref == evidence_ref) {
+            continue;
+        }
+        let Some(content) = bundle
+            .evidence
+            .get(evidence_ref)
+            .and_then(|e| e.content.as_deref())
+        else {
+            continue;
+        };
+        configs.push(ConfigContent {
+            path,
+            evidence_ref,
+            content: String::from_utf8_lossy(content).into_owned(),
+        });
+    }
+    configs
+}
+
+fn http_check(port: u16, path: &str) -> ReadinessCheck {
+    ReadinessCheck {
+        check_type: "http".to_string(),
+        target: Some("localhost".to_string()),
+        port: Some(port),
+        path: Some(path.to_string()),
+        command: None,
+        timeout_seconds: TIMEOUT_SECONDS,
+        interval_seconds: INTERVAL_SECONDS,
+        retries: RETRIES,
+    }
+}
+
+/// HTTP check on a health `location` of an nginx configuration.
+fn nginx_health_check(
+    cluster: &AppCluster,
+    configs: &[ConfigContent<'_>],
+) -> Option<(ReadinessCheck, Decision)> {
+    configs.iter().find_map(|config| {
+        let location = HEALTH_LOCATION.captures(&config.content)?[1].to_string();
+        // Prefer the server's own listen port when the cluster exposes it
+        let port = NGINX_LISTEN
+            .captures_iter(&config.content)
+            .filter_map(|caps| caps[1].parse::<u16>().ok())
+            .find(|port| cluster.ports.iter().any(|p| p.port == *port))
+            .or_else(|| cluster.ports.first().map(|p| p.port))?;
+
+        Some((
+            http_check(port, &location),
+            Decision::new(
+                format!("Readiness check: HTTP GET {} on port {}", location, port),
+                format!("nginx config {} defines location {}", config.path, location),
+                vec![config.evidence_ref.to_string()],
+                0.85,
+            ),
+        ))
+    })
+}
+
+/// HTTP check on the Spring Boot actuator health endpoint of a Java service
+/// whose configuration sets actuator properties.
+fn actuator_check(
+    cluster: &AppCluster,
+    configs: &[ConfigContent<'_>],
+) -> Option<(ReadinessCheck, Decision)> {
+    let runs_java = cluster
+        .services
+        .iter()
+        .filter_map(|s| s.exec_start.as_deref())
+        .chain(cluster.processes.iter().map(|p| p.command.as_str()))
+        .any(|cmd| cmd.contains("java"));
+    if !runs_java {
+        return None;
+    }
+
+    configs.iter().find_map(|config| {
+        let port = ACTUATOR_PORT
+            .captures(&config.content)
+            .and_then(|caps| caps[1].parse::<u16>().ok());
+        let base_path = ACTUATOR_BASE_PATH
+            .captures(&config.content)
+            .map(|caps| caps[1].trim_end_matches('/').to_string());
+        if port.is_none() && base_path.is_none() {
+            return None;
+        }
+        let port = port.or_else(|| cluster.ports.first().map(|p| p.port))?;
+        let path = format!("{}/health", base_path.as_deref().unwrap_or("/actuator"));
+
+        Some((
+            http_check(port, &path),
+            Decision::new(
+                format!("Readiness check: HTTP GET {} on port {}", path, port),
+                format!(
+                    "Spring Boot actuator configured in {} for a Java service",
+                    config.path
+                ),
+                vec![config.evidence_ref.to_string()],
+                0.75,
+            ),
+        ))
+    })
+}
+
+/// TCP check on the first exposed port.
+fn port_check(cluster: &AppCluster) -> Option<(ReadinessCheck, Decision)> {
+    let port = cluster.ports.first()?;
+
+    Some((
+        ReadinessCheck {
+            check_type: "tcp".to_string(),
+            target: Some("localhost".to_string()),
+            port: Some(port.port),
+            path: None,
+            command: None,
+            timeout_seconds: TIMEOUT_SECONDS,
+            interval_seconds: INTERVAL_SECONDS,
+            retries: RETRIES,
+        },
+        Decision::new(
+            format!("Readiness check: TCP connect on port {}", port.port),
+            "No health endpoint found; the service listens on this port",
+            port.evidence_ref.iter().cloned().collect(),
+            0.6,
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use xcprobe_bundle_schema::{
+        ClusterPort, ClusterService, ConfigFileSpec, Evidence, FileInfo, Manifest,
+    };
+
+    fn cluster(app_type: &str, exec_start: &str, ports: &[u16]) -> AppCluster {
+        AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: app_type.to_string(),
+            services: vec![ClusterService {
+                name: "app.service".to_string(),
+                exec_start: Some(exec_start.to_string()),
+                ..Default::default()
+            }],
+            ports: ports
+                .iter()
+                .map(|port| ClusterPort {
+                    port: *port,
+                    protocol: "tcp".to_string(),
+                    evidence_ref: Some("evidence/ports_1.txt".to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn bundle(config_files: Vec<FileInfo>, contents: &[(&str, &str)]) -> Bundle {
+        Bundle {
+            manifest: Manifest {
+                config_files,
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: contents
+                .iter()
+                .map(|&(evidence_ref, content)| {
+                    (
+                        evidence_ref.to_string(),
+                        Evidence::from_command_output(
+                            evidence_ref,
+                            "cat",
+                            content.as_bytes().to_vec(),
+                            evidence_ref,
+                        ),
+                    )
+                })
+                .collect(),
+            checksums: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_nginx_health_location() {
+        let nginx = FileInfo {
+            path: "/etc/nginx/conf.d/app.conf".to_string(),
+            size_bytes: 0,
+            modified_at: None,
+            owner: None,
+            permissions: None,
+            content_hash: None,
+            attachment_ref: Some("evidence/config_1.txt".to_string()),
+            discovery_method: "standard_path".to_string(),
+            discovery_evidence_ref: None,
+            opened_by: Vec::new(),
+        };
+        let bundle = bundle(
+            vec![nginx],
+            &[(
+                "evidence/config_1.txt",
+                "server {\n  listen 443 ssl;\n  listen 80;\n  location = /healthz { return 200; }\n}\n",
+            )],
+        );
+        let mut clusters = vec![cluster("proxy", "/usr/sbin/nginx", &[80])];
+
+        infer_readiness(&bundle, &mut clusters);
+        let readiness = clusters[0].readiness.as_ref().unwrap();
+        assert_eq!(readiness.check_type, "http");
+        assert_eq!(readiness.path.as_deref(), Some("/healthz"));
+        assert_eq!(readiness.port, Some(80));
+        assert_eq!(
+            clusters[0].decisions[0].evidence_refs,
+            vec!["evidence/config_1.txt"]
+        );
+    }
+
+    #[test]
+    fn test_actuator_and_port_fallback() {
+        let bundle = bundle(
+            vec![],
+            &[(
+                "evidence/config_2.txt",
+                "server.port=8080\nmanagement.server.port=9090\n",
+            )],
+        );
+        let mut api = cluster("api", "/usr/bin/java -jar /opt/api/api.jar", &[8080]);
+        api.config_files.push(ConfigFileSpec {
+            source_path: "/opt/api/application.properties".to_string(),
+            container_path: "/opt/api/application.properties".to_string(),
+            templated: false,
+            template_vars: vec![],
+            content: None,
+            evidence_ref: Some("evidence/config_2.txt".to_string()),
+        });
+        let mut clusters = vec![
+            api,
+            cluster("api", "/opt/app/server", &[5000]),
+            cluster("batch", "/opt/app/export", &[]),
+        ];
+
+        infer_readiness(&bundle, &mut clusters);
+        let actuator = clusters[0].readiness.as_ref().unwrap();
+        assert_eq!(actuator.path.as_deref(), Some("/actuator/health"));
+        assert_eq!(actuator.port, Some(9090));
+
+        let tcp = clusters[1].readiness.as_ref().unwrap();
+        assert_eq!(tcp.check_type, "tcp");
+        assert_eq!(tcp.port, Some(5000));
+        assert_eq!(
+            clusters[1].decisions[0].evidence_refs,
+            vec!["evidence/ports_1.txt"]
+        );
+
+        assert!(clusters[2].readiness.is_none());
+        assert!(clusters[2].decisions.is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+//! Registry-derived settings.
+//!
+//! Windows applications often keep their settings in the registry rather
+//! than in files: `HKLM\SOFTWARE\Vendor\Product` holds install paths,
+//! connection strings and ports the container has to receive another way.
+//! Each value of the product keys collected for a cluster's services
+//! becomes an environment variable of the cluster, named after the value
+//! (`HKLM\SOFTWARE\Contoso\Billing\Queue` value `MaxRetries` becomes
+//! `QUEUE_MAX_RETRIES`).
+
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, EnvVarSpec, RegistryKey};
+use xcprobe_redaction::{patterns, HASH_PLACEHOLDER_PREFIX, REDACTED_PLACEHOLDER};
+
+/// Confidence that a registry value is configuration the application reads.
+const REGISTRY_CONFIDENCE: f64 = 0.7;
+
+/// Add the values of the registry keys collected for the services of each
+/// cluster as environment variables. Redacted values and values under
+/// sensitive names have no default.
+pub fn attach_registry_settings(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        let keys: Vec<&RegistryKey> = bundle
+            .manifest
+            .registry_keys
+            .iter()
+            .filter(|k| is_software_key(&k.path))
+            .filter(|k| cluster.services.iter().any(|s| s.name == k.service))
+            .collect();
+
+        for key in &keys {
+            let prefix = key_prefix(key, &keys);
+            let mut added = 0;
+            for (value_name, value) in &key.values {
+                let name = env_var_name(&prefix, value_name);
+                if name.is_empty() || cluster.env_vars.iter().any(|e| e.name == name) {
+                    continue;
+                }
+                let redacted =
+                    value.contains(REDACTED_PLACEHOLDER) || value.contains(HASH_PLACEHOLDER_PREFIX);
+                cluster.env_vars.push(EnvVarSpec {
+                    name,
+                    required: true,
+                    default_value: (!redacted && !patterns::is_sensitive_key(value_name))
+                        .then(|| value.clone()),
+                    description: Some(format!("From registry value {}\\{}", key.path, value_name)),
+                    sensitive: false,
+                    evidence_ref: key.evidence_ref.clone(),
+                });
+                added += 1;
+            }
+            if added > 0 {
+                cluster.decisions.push(Decision::new(
+                    format!("Set {} variables from registry key {}", added, key.path),
+                    "Settings of the service's product in HKLM\\SOFTWARE".to_string(),
+                    key.evidence_ref.iter().cloned().collect(),
+                    REGISTRY_CONFIDENCE,
+                ));
+            }
+        }
+    }
+}
+
+/// Product keys, as opposed to the service's own key.
+fn is_software_key(path: &str) -> bool {
+    path.split('\\')
+        .nth(1)
+        .is_some_and(|hive| hive.eq_ignore_ascii_case("SOFTWARE"))
+}
+
+/// Subkeys of the key's product key, the topmost key collected for the
+/// same service that contains it.
+fn key_prefix(key: &RegistryKey, keys: &[&RegistryKey]) -> Vec<String> {
+    // ASCII lowercase keeps byte offsets
+    let lower = key.path.to_ascii_lowercase();
+    let root = keys
+        .iter()
+        .filter(|k| k.service == key.service)
+        .map(|k| k.path.to_ascii_lowercase())
+        .filter(|root| lower == *root || lower.starts_with(&format!("{}\\", root)))
+        .min_by_key(|root| root.len())
+        .unwrap_or_else(|| lower.clone());
+    key.path[root.len()..]
+        .split('\\')
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `MaxRetries` under subkey `Queue` becomes `QUEUE_MAX_RETRIES`.
+fn env_var_name(prefix: &[String], value_name: &str) -> String {
+    if value_name.trim().is_empty() {
+        return String::new();
+    }
+    prefix
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once(value_name))
+        .map(screaming_snake)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `DBConnectionString` becomes `DB_CONNECTION_STRING`, `max.threads`
+/// becomes `MAX_THREADS`.
+fn screaming_snake(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            continue;
+        }
+        if c.is_ascii_uppercase() && i > 0 && !out.is_empty() && !out.ends_with('_') {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out.trim_end_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+    use xcprobe_bundle_schema::{ClusterService, Manifest};
+
+    fn registry_key(path: &str, values: &[(&str, &str)]) -> RegistryKey {
+        RegistryKey {
+            path: path.to_string(),
+            service: "BillingSvc".to_string(),
+            values: values
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            evidence_ref: Some("evidence/registry_1.txt".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_registry_values_become_env_vars() {
+        let manifest = Manifest {
+            registry_keys: vec![
+                registry_key(
+                    r"HKLM\SYSTEM\CurrentControlSet\Services\BillingSvc",
+                    &[("ImagePath", r"C:\Program Files\Contoso\Billing\billing.exe")],
+                ),
+                registry_key(
+                    r"HKLM\SOFTWARE\Contoso\Billing",
+                    &[
+                        ("DBConnectionString", "Server=db01;Database=billing"),
+                        ("DbPassword", "[REDACTED]"),
+                        ("", "default value"),
+                    ],
+                ),
+                registry_key(
+                    r"HKLM\SOFTWARE\Contoso\Billing\Queue",
+                    &[("MaxRetries", "5")],
+                ),
+            ],
+            ..Default::default()
+        };
+        let bundle = Bundle {
+            manifest,
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let mut clusters = vec![AppCluster {
+            id: "app-0".to_string(),
+            name: "billing".to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: "BillingSvc".to_string(),
+                ..Default::default()
+            }],
+            confidence: 0.9,
+            ..Default::default()
+        }];
+
+        attach_registry_settings(&bundle, &mut clusters);
+
+        let env_vars = &clusters[0].env_vars;
+        let names: Vec<&str> = env_vars.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["DB_CONNECTION_STRING", "DB_PASSWORD", "QUEUE_MAX_RETRIES"]
+        );
+        assert_eq!(
+            env_vars[0].default_value.as_deref(),
+            Some("Server=db01;Database=billing")
+        );
+        assert_eq!(env_vars[1].default_value, None);
+        assert_eq!(
+            env_vars[2].description.as_deref(),
+            Some(r"From registry value HKLM\SOFTWARE\Contoso\Billing\Queue\MaxRetries")
+        );
+        assert_eq!(
+            clusters[0].decisions[0].decision,
+            r"Set 2 variables from registry key HKLM\SOFTWARE\Contoso\Billing"
+        );
+    }
+
+    #[test]
+    fn test_screaming_snake() {
+        assert_eq!(screaming_snake("InstallDir"), "INSTALL_DIR");
+        assert_eq!(
+            screaming_snake("DBConnectionString"),
+            "DB_CONNECTION_STRING"
+        );
+        assert_eq!(screaming_snake("max.threads"), "MAX_THREADS");
+        assert_eq!(screaming_snake("Port2Listen"), "PORT2_LISTEN");
+    }
+}
@@ -0,0 +1,390 @@
+//! Migration assessment report.
+//!
+//! Renders a pack plan as a document for the people planning the
+//! migration: the clusters and how confident the analysis is about them,
+//! a Mermaid diagram of the dependencies, the questions the analysis could
+//! not answer, its warnings, and a checklist per cluster.
+
+use crate::dependencies;
+use crate::templates::{self, TemplateEngine};
+use anyhow::Result;
+use serde::Serialize;
+use std::str::FromStr;
+use xcprobe_bundle_schema::{AppCluster, DependencyInfo, PackPlan};
+
+/// Decisions below this confidence are listed as questions to confirm.
+const LOW_DECISION_CONFIDENCE: f64 = 0.6;
+
+/// Output format of the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    fn template(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => templates::REPORT_MARKDOWN,
+            ReportFormat::Html => templates::REPORT_HTML,
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(anyhow::anyhow!("Invalid report format: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReportContext<'a> {
+    source_bundle_id: &'a str,
+    generated_at: String,
+    confidence_percent: String,
+    clusters: Vec<ReportClusterContext<'a>>,
+    diagram: String,
+    external_dependencies: Vec<ReportDependencyContext<'a>>,
+    questions: Vec<String>,
+    warnings: Vec<ReportWarningContext<'a>>,
+    rejected: Vec<ReportRejectedContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct ReportClusterContext<'a> {
+    id: &'a str,
+    name: &'a str,
+    app_type: &'a str,
+    confidence_percent: String,
+    image: String,
+    checklist: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ReportDependencyContext<'a> {
+    id: &'a str,
+    dep_type: &'a str,
+    endpoint: &'a str,
+    network: String,
+    used_by: String,
+}
+
+#[derive(Serialize)]
+struct ReportWarningContext<'a> {
+    severity: &'a str,
+    code: &'a str,
+    message: &'a str,
+    clusters: String,
+}
+
+#[derive(Serialize)]
+struct ReportRejectedContext<'a> {
+    id: &'a str,
+    name: &'a str,
+    confidence_percent: String,
+    reason: &'a str,
+}
+
+/// Render the migration assessment of a pack plan.
+pub fn generate_report(
+    engine: &TemplateEngine,
+    plan: &PackPlan,
+    format: ReportFormat,
+) -> Result<String> {
+    let percent = |confidence: f64| format!("{:.0}", confidence * 100.0);
+
+    let context = ReportContext {
+        source_bundle_id: &plan.source_bundle_id,
+        generated_at: plan.generated_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        confidence_percent: percent(plan.overall_confidence),
+        clusters: plan
+            .clusters
+            .iter()
+            .map(|c| ReportClusterContext {
+                id: &c.id,
+                name: &c.name,
+                app_type: &c.app_type,
+                confidence_percent: percent(c.confidence),
+                image: match (&c.database, &c.base_image) {
+                    (Some(database), _) => database.image.clone(),
+                    (None, Some(base_image)) => base_image.clone(),
+                    (None, None) => "-".to_string(),
+                },
+                checklist: cluster_checklist(c),
+            })
+            .collect(),
+        diagram: dependencies::mermaid_graph(plan),
+        external_dependencies: plan
+            .external_dependencies
+            .iter()
+            .map(|d| ReportDependencyContext {
+                id: &d.id,
+                dep_type: &d.dep_type,
+                endpoint: &d.endpoint,
+                network: dependency_network(d),
+                used_by: d.used_by.join(", "),
+            })
+            .collect(),
+        questions: open_questions(plan),
+        warnings: plan
+            .warnings
+            .iter()
+            .map(|w| ReportWarningContext {
+                severity: &w.severity,
+                code: &w.code,
+                message: &w.message,
+                clusters: w.affected_clusters.join(", "),
+            })
+            .collect(),
+        rejected: plan
+            .rejected_clusters
+            .iter()
+            .map(|r| ReportRejectedContext {
+                id: &r.id,
+                name: &r.name,
+                confidence_percent: percent(r.confidence),
+                reason: &r.reason,
+            })
+            .collect(),
+    };
+
+    engine.render(format.template(), &context)
+}
+
+/// Questions the analysis leaves to the people migrating: excluded
+/// Network of a dependency, with the addresses its host resolves to on the
+/// source host.
+fn dependency_network(dependency: &DependencyInfo) -> String {
+    let network = match dependency.internal {
+        Some(true) => "source network",
+        Some(false) => "Internet",
+        None => "unknown",
+    };
+    if dependency.addresses.is_empty() {
+        network.to_string()
+    } else {
+        format!("{} ({})", network, dependency.addresses.join(", "))
+    }
+}
+
+/// clusters, external endpoints, values no source provides, and decisions
+/// taken on weak evidence.
+fn open_questions(plan: &PackPlan) -> Vec<String> {
+    let mut questions = Vec::new();
+    for rejected in &plan.rejected_clusters {
+        questions.push(format!(
+            "Should {} ({}) be migrated? It was excluded: {}",
+            rejected.name, rejected.id, rejected.reason
+        ));
+    }
+    for dependency in &plan.external_dependencies {
+        questions.push(format!(
+            "Will the {} endpoint {} stay reachable from the containers of {}, or move with them?",
+            dependency.dep_type,
+            dependency.endpoint,
+            dependency.used_by.join(", ")
+        ));
+    }
+    for cluster in &plan.clusters {
+        for env_var in cluster
+            .env_vars
+            .iter()
+            .filter(|e| e.required && e.default_value.is_none())
+        {
+            questions.push(if env_var.sensitive {
+                format!("Which secret provides {} to {}?", env_var.name, cluster.id)
+            } else {
+                format!("What value of {} does {} need?", env_var.name, cluster.id)
+            });
+        }
+        for decision in cluster
+            .decisions
+            .iter()
+            .filter(|d| d.confidence < LOW_DECISION_CONFIDENCE)
+        {
+            questions.push(format!(
+                "Confirm for {}: {} ({})",
+                cluster.id, decision.decision, decision.reason
+            ));
+        }
+    }
+    questions
+}
+
+/// Steps left to migrate a cluster once its artifacts are generated.
+fn cluster_checklist(cluster: &AppCluster) -> Vec<String> {
+    let id = &cluster.id;
+    let mut checklist = Vec::new();
+    if let Some(database) = &cluster.database {
+        checklist.push(format!(
+            "Check that `{}` matches the version on the source host",
+            database.image
+        ));
+        checklist.push(format!(
+            "Move the data with a dump and restore, as described in `{}/MIGRATION.md`",
+            id
+        ));
+    } else {
+        match &cluster.base_image {
+            Some(base_image) => checklist.push(format!(
+                "Review the base image `{}` in `{}/Dockerfile`",
+                base_image, id
+            )),
+            None => checklist.push(format!("Choose a base image in `{}/Dockerfile`", id)),
+        }
+        if !cluster.app_files.is_empty() {
+            checklist.push(format!(
+                "Fetch the {} application files with `xcprobe pack`",
+                cluster.app_files.len()
+            ));
+        }
+    }
+
+    let required: Vec<&str> = cluster
+        .env_vars
+        .iter()
+        .filter(|e| e.required && !e.sensitive)
+        .map(|e| e.name.as_str())
+        .collect();
+    if !required.is_empty() {
+        checklist.push(format!("Set {}", required.join(", ")));
+    }
+    let secrets: Vec<&str> = cluster
+        .env_vars
+        .iter()
+        .filter(|e| e.sensitive)
+        .map(|e| e.name.as_str())
+        .collect();
+    if !secrets.is_empty() {
+        checklist.push(format!("Provide {} as secrets", secrets.join(", ")));
+    }
+
+    if cluster.config_files.iter().any(|c| c.templated) {
+        checklist.push(format!(
+            "Review the config templates in `{}/templates/`",
+            id
+        ));
+    }
+    let copied: Vec<&str> = cluster
+        .config_files
+        .iter()
+        .filter(|c| !c.templated)
+        .map(|c| c.source_path.as_str())
+        .collect();
+    if !copied.is_empty() {
+        checklist.push(format!("Check the configuration in {}", copied.join(", ")));
+    }
+    if !cluster.volumes.is_empty() {
+        let paths: Vec<&str> = cluster
+            .volumes
+            .iter()
+            .map(|v| v.container_path.as_str())
+            .collect();
+        checklist.push(format!(
+            "Copy the data of {} into its volumes",
+            paths.join(", ")
+        ));
+    }
+    if !cluster.log_paths.is_empty() {
+        checklist.push(format!(
+            "Send the logs written to {} to stdout or a volume",
+            cluster.log_paths.join(", ")
+        ));
+    }
+
+    match &cluster.readiness {
+        Some(readiness) => checklist.push(format!(
+            "Check the {} readiness check",
+            readiness.check_type
+        )),
+        None if !cluster.ports.is_empty() => {
+            checklist.push("Add a health check".to_string());
+        }
+        None => {}
+    }
+    if !cluster.depends_on.is_empty() {
+        checklist.push(format!("Start after {}", cluster.depends_on.join(", ")));
+    }
+    if let Some(schedule) = &cluster.schedule {
+        checklist.push(format!("Schedule the job (`{}`)", schedule));
+    }
+    if let Some(replicas) = cluster.replicas {
+        checklist.push(format!(
+            "Check that {} replicas can share the same ports and data",
+            replicas
+        ));
+    }
+    checklist.push("Run the container and compare its behaviour with the source host".to_string());
+    checklist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> PackPlan {
+        serde_json::from_value(serde_json::json!({
+            "schema_version": "1.0.0",
+            "generated_at": "2026-01-05T10:00:00Z",
+            "source_bundle_id": "bundle-1",
+            "clusters": [{
+                "id": "app-0", "name": "billing", "app_type": "api",
+                "processes": [], "services": [],
+                "ports": [{ "port": 8080, "protocol": "tcp", "purpose": null,
+                            "evidence_ref": null }],
+                "env_vars": [{ "name": "DB_PASSWORD", "required": true,
+                               "default_value": null, "description": null,
+                               "sensitive": true, "evidence_ref": null }],
+                "config_files": [], "log_paths": [], "depends_on": [],
+                "external_deps": ["ext-0"], "base_image": "node:20-slim",
+                "confidence": 0.85, "evidence_refs": [],
+                "decisions": [{ "decision": "Detect runtime node",
+                                "reason": "Executable name", "evidence_refs": [],
+                                "confidence": 0.5 }]
+            }],
+            "external_dependencies": [{ "id": "ext-0", "dep_type": "database",
+                                        "endpoint": "db.internal:5432", "port": 5432,
+                                        "used_by": ["app-0"], "evidence_refs": [],
+                                        "addresses": ["10.0.0.9"], "internal": true }],
+            "startup_dag": [], "artifacts": [], "overall_confidence": 0.85,
+            "warnings": [{ "code": "NO_COMMAND", "message": "No command for <app-0>",
+                           "severity": "warning", "affected_clusters": ["app-0"] }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let engine = TemplateEngine::new().unwrap();
+        let report = generate_report(&engine, &plan(), ReportFormat::Markdown).unwrap();
+
+        assert!(report.contains("| app-0 | billing | api | 85% | node:20-slim |"));
+        assert!(report.contains("    app-0[\"billing (api)\"]"));
+        assert!(report.contains("    ext-0 -.-> app-0"));
+        assert!(report.contains(
+            "| ext-0 | database | `db.internal:5432` | source network (10.0.0.9) | app-0 |"
+        ));
+        assert!(report.contains("- Which secret provides DB_PASSWORD to app-0?"));
+        assert!(report.contains("- Confirm for app-0: Detect runtime node (Executable name)"));
+        assert!(report.contains("- [ ] Provide DB_PASSWORD as secrets"));
+        assert!(report.contains("- [ ] Add a health check"));
+    }
+
+    #[test]
+    fn test_html_report_is_escaped() {
+        let engine = TemplateEngine::new().unwrap();
+        let report = generate_report(&engine, &plan(), ReportFormat::Html).unwrap();
+
+        assert!(report.starts_with("<!DOCTYPE html>"));
+        assert!(report.contains("No command for &lt;app-0&gt;"));
+        assert!(report.contains("ext-0 -.-&gt; app-0"));
+        assert!("pdf".parse::<ReportFormat>().is_err());
+    }
+}
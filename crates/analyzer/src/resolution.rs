@@ -0,0 +1,200 @@
+//! Name resolution of dependency endpoints.
+//!
+//! The collector records the hosts file and resolver configuration of the
+//! source host and, with `collect --resolve-hosts`, the addresses the host
+//! names of its configuration resolve to there. A dependency whose host
+//! resolves to private addresses is a service of the source network, to be
+//! migrated along or reached over it; one resolving to public addresses is
+//! an Internet service. Unqualified names, and names under a search domain
+//! or a private suffix, are internal even when they did not resolve.
+
+use crate::dependencies::endpoint_host;
+use std::net::IpAddr;
+use xcprobe_bundle_schema::{DependencyInfo, NameResolution};
+
+/// Domain suffixes reserved or customary for private networks.
+const PRIVATE_SUFFIXES: &[&str] = &[".local", ".internal", ".lan", ".corp", ".home.arpa"];
+
+/// Record the addresses the host of each dependency resolves to, and
+/// whether the dependency is on the source network.
+pub fn resolve_dependencies(resolution: &NameResolution, dependencies: &mut [DependencyInfo]) {
+    for dependency in dependencies {
+        let Some(host) = endpoint_host(&dependency.endpoint) else {
+            continue;
+        };
+        dependency.addresses = host_addresses(resolution, &host);
+        dependency.internal = is_internal(resolution, &host, &dependency.addresses);
+    }
+}
+
+/// Addresses `host` resolves to on the source host: the address itself,
+/// the hosts file entries naming it, which take precedence as they do in
+/// `nsswitch.conf`, or the addresses it was looked up to.
+pub fn host_addresses(resolution: &NameResolution, host: &str) -> Vec<String> {
+    let host = normalize(host);
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return vec![ip.to_string()];
+    }
+    let mut addresses: Vec<String> = resolution
+        .hosts
+        .iter()
+        .filter(|e| e.names.iter().any(|n| n.eq_ignore_ascii_case(&host)))
+        .map(|e| e.address.clone())
+        .collect();
+    if addresses.is_empty() {
+        if let Some(lookup) = resolution.lookups.iter().find(|l| l.name == host) {
+            addresses = lookup.addresses.clone();
+        }
+    }
+    addresses.dedup();
+    addresses
+}
+
+/// Whether `host` resolves only to loopback addresses or to the addresses
+/// of the source host, named `hostname`: a backend on the host itself.
+pub fn resolves_to_host(resolution: &NameResolution, host: &str, hostname: &str) -> bool {
+    let addresses = host_addresses(resolution, host);
+    let own = if hostname.is_empty() {
+        Vec::new()
+    } else {
+        host_addresses(resolution, hostname)
+    };
+    !addresses.is_empty()
+        && addresses
+            .iter()
+            .all(|a| own.contains(a) || a.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()))
+}
+
+/// Whether `host`, resolving to `addresses`, is on the source network:
+/// `None` for a public name that did not resolve.
+fn is_internal(resolution: &NameResolution, host: &str, addresses: &[String]) -> Option<bool> {
+    if !addresses.is_empty() {
+        return Some(
+            addresses
+                .iter()
+                .filter_map(|a| a.parse::<IpAddr>().ok())
+                .all(is_private),
+        );
+    }
+    let host = normalize(host);
+    let private_name = !host.contains('.')
+        || PRIVATE_SUFFIXES.iter().any(|s| host.ends_with(s))
+        || resolution
+            .search_domains
+            .iter()
+            .any(|d| host.ends_with(&format!(".{}", d)));
+    private_name.then_some(true)
+}
+
+/// Whether an address is not routed on the Internet: private, shared
+/// (carrier-grade NAT), loopback, link-local or unique local.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// Lowercase host name without IPv6 brackets or the root label.
+fn normalize(host: &str) -> String {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{HostEntry, HostLookup};
+
+    fn resolution() -> NameResolution {
+        let entry = |address: &str, names: &[&str]| HostEntry {
+            address: address.to_string(),
+            names: names.iter().map(|n| n.to_string()).collect(),
+        };
+        let lookup = |name: &str, addresses: &[&str]| HostLookup {
+            name: name.to_string(),
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            evidence_ref: Some("evidence/getent_1.txt".to_string()),
+        };
+        NameResolution {
+            hosts: vec![
+                entry("127.0.0.1", &["localhost"]),
+                entry("10.0.0.5", &["web01.corp.example.com", "web01"]),
+                entry("10.0.0.5", &["api.example.com"]),
+                entry("10.0.0.9", &["db"]),
+            ],
+            nameservers: vec!["10.0.0.2".to_string()],
+            search_domains: vec!["corp.example.com".to_string()],
+            lookups: vec![
+                lookup("api.stripe.com", &["3.18.12.63", "52.15.183.149"]),
+                lookup("api.example.com", &["93.184.215.14"]),
+                lookup("queue.corp.example.com", &[]),
+            ],
+            evidence_refs: vec!["evidence/cat_hosts_1.txt".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_resolve_dependencies() {
+        let resolution = resolution();
+        let dependency = |endpoint: &str| DependencyInfo {
+            id: endpoint.to_string(),
+            dep_type: "unknown".to_string(),
+            endpoint: endpoint.to_string(),
+            port: None,
+            used_by: vec!["app-0".to_string()],
+            evidence_refs: vec![],
+            addresses: Vec::new(),
+            internal: None,
+        };
+        let mut dependencies: Vec<DependencyInfo> = [
+            "postgres://app@db:5432/billing",
+            "https://api.stripe.com/v1",
+            "api.example.com:443",
+            "queue.corp.example.com:5672",
+            "172.20.1.4:6379",
+            "smtp.mailgun.org:587",
+        ]
+        .into_iter()
+        .map(dependency)
+        .collect();
+        resolve_dependencies(&resolution, &mut dependencies);
+
+        let resolved: Vec<(Vec<&str>, Option<bool>)> = dependencies
+            .iter()
+            .map(|d| (d.addresses.iter().map(String::as_str).collect(), d.internal))
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                (vec!["10.0.0.9"], Some(true)),
+                (vec!["3.18.12.63", "52.15.183.149"], Some(false)),
+                // The hosts file overrides DNS
+                (vec!["10.0.0.5"], Some(true)),
+                (vec![], Some(true)),
+                (vec!["172.20.1.4"], Some(true)),
+                (vec![], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolves_to_host() {
+        let resolution = resolution();
+        assert!(resolves_to_host(&resolution, "api.example.com", "web01"));
+        assert!(resolves_to_host(&resolution, "LOCALHOST", ""));
+        assert!(!resolves_to_host(&resolution, "db", "web01"));
+        assert!(!resolves_to_host(&resolution, "unknown", "web01"));
+    }
+}
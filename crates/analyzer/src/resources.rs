@@ -0,0 +1,187 @@
+//! Resource request sizing.
+//!
+//! When a collection sampled CPU time and resident memory over a window,
+//! each cluster gets the 95th percentile of its processes' combined usage,
+//! used as the resource reservations of its container.
+
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, ResourceSample, ResourceUsage};
+
+/// Percentile used for resource requests.
+const PERCENTILE: f64 = 0.95;
+
+/// Compute resource usage for each cluster whose processes were sampled.
+pub fn detect_resource_usage(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    let samples = &bundle.manifest.resource_samples;
+    if samples.is_empty() {
+        return;
+    }
+    let main_pids: HashMap<&str, u32> = bundle
+        .manifest
+        .services
+        .iter()
+        .filter_map(|s| Some((s.name.as_str(), s.main_pid?)))
+        .collect();
+
+    for cluster in clusters.iter_mut() {
+        let pids: HashSet<u32> = cluster
+            .processes
+            .iter()
+            .map(|p| p.pid)
+            .chain(
+                cluster
+                    .services
+                    .iter()
+                    .filter_map(|s| main_pids.get(s.name.as_str()).copied()),
+            )
+            .collect();
+        let cluster_samples: Vec<&ResourceSample> =
+            samples.iter().filter(|s| pids.contains(&s.pid)).collect();
+        let Some(usage) = usage_percentiles(&cluster_samples) else {
+            continue;
+        };
+
+        cluster.decisions.push(Decision::new(
+            format!(
+                "Resource requests: {:.2} CPU, {} MiB memory",
+                usage.cpu_p95,
+                usage.memory_p95_bytes.div_ceil(1024 * 1024)
+            ),
+            format!(
+                "95th percentile of {} samples of {} processes",
+                usage.samples,
+                pids.len()
+            ),
+            usage.evidence_refs.clone(),
+            0.8,
+        ));
+        cluster.resources = Some(usage);
+    }
+}
+
+/// Percentiles of the summed CPU rate and RSS of the samples, per sampling
+/// instant. Needs two instants to derive a CPU rate.
+fn usage_percentiles(samples: &[&ResourceSample]) -> Option<ResourceUsage> {
+    let mut instants: BTreeMap<DateTime<Utc>, Vec<&ResourceSample>> = BTreeMap::new();
+    for sample in samples {
+        instants.entry(sample.timestamp).or_default().push(sample);
+    }
+    if instants.len() < 2 {
+        return None;
+    }
+
+    let memory: Vec<f64> = instants
+        .values()
+        .map(|s| s.iter().map(|s| s.rss_bytes as f64).sum())
+        .collect();
+
+    let instants: Vec<_> = instants.into_iter().collect();
+    let cpu: Vec<f64> = instants
+        .windows(2)
+        .filter_map(|pair| {
+            let (t0, before) = &pair[0];
+            let (t1, after) = &pair[1];
+            let elapsed = (*t1 - *t0).num_milliseconds() as f64 / 1000.0;
+            if elapsed <= 0.0 {
+                return None;
+            }
+            let busy: f64 = after
+                .iter()
+                .filter_map(|a| {
+                    let b = before.iter().find(|b| b.pid == a.pid)?;
+                    // A restarted process starts its CPU time over
+                    Some((a.cpu_seconds - b.cpu_seconds).max(0.0))
+                })
+                .sum();
+            Some(busy / elapsed)
+        })
+        .collect();
+
+    let evidence_refs: BTreeSet<String> = samples
+        .iter()
+        .filter_map(|s| s.evidence_ref.clone())
+        .collect();
+
+    Some(ResourceUsage {
+        cpu_p95: percentile(cpu),
+        memory_p95_bytes: percentile(memory) as u64,
+        samples: instants.len(),
+        evidence_refs: evidence_refs.into_iter().collect(),
+    })
+}
+
+/// Nearest-rank percentile; 0 for no values.
+fn percentile(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let rank = (PERCENTILE * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use xcprobe_bundle_schema::{ClusterProcess, Manifest};
+
+    fn sample(pid: u32, second: i64, cpu_seconds: f64, rss_mib: u64) -> ResourceSample {
+        ResourceSample {
+            pid,
+            timestamp: DateTime::<Utc>::UNIX_EPOCH + Duration::seconds(second),
+            cpu_seconds,
+            rss_bytes: rss_mib * 1024 * 1024,
+            evidence_ref: Some(format!("evidence/resources_{}.txt", second / 5 + 1)),
+        }
+    }
+
+    #[test]
+    fn test_percentiles_of_cluster_processes() {
+        let mut samples = Vec::new();
+        for i in 0..21 {
+            let t = i * 5;
+            // pid 10 uses half a core, pid 11 a quarter; pid 99 is elsewhere
+            samples.push(sample(10, t, 2.5 * i as f64, 100 + i as u64));
+            samples.push(sample(11, t, 1.25 * i as f64, 50));
+            samples.push(sample(99, t, 5.0 * i as f64, 4000));
+        }
+        let bundle = Bundle {
+            manifest: Manifest {
+                resource_samples: samples,
+                ..Default::default()
+            },
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let mut clusters = vec![AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: "web".to_string(),
+            processes: [10, 11]
+                .into_iter()
+                .map(|pid| ClusterProcess {
+                    pid,
+                    command: "app".to_string(),
+                    user: "app".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }];
+
+        detect_resource_usage(&bundle, &mut clusters);
+
+        let usage = clusters[0].resources.as_ref().unwrap();
+        assert_eq!(usage.samples, 21);
+        assert!((usage.cpu_p95 - 0.75).abs() < 1e-9);
+        // RSS grows from 150 to 170 MiB; the 95th percentile is 169 MiB
+        assert_eq!(usage.memory_p95_bytes, 169 * 1024 * 1024);
+        assert_eq!(usage.evidence_refs.len(), 21);
+        assert!(clusters[0].decisions[0]
+            .decision
+            .contains("0.75 CPU, 169 MiB"));
+    }
+}
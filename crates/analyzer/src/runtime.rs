@@ -0,0 +1,415 @@
+//! Language runtime detection.
+//!
+//! The runtime of a cluster is derived from the executables its services and
+//! processes start. The language comes from the executable name (`python3.11`,
+//! `java`) or, for anything else, from the collected executable type: a
+//! script's shebang interpreter or a native ELF binary. The version comes from
+//! the executable name, runtime version probes, or installed packages.
+
+use regex::Regex;
+use std::cmp::Ordering;
+use xcprobe_bundle_schema::{
+    AppCluster, Bundle, ClusterPackage, Decision, Manifest, Package, RuntimeInfo,
+};
+
+/// Executable name patterns mapped to languages. An optional version suffix
+/// (e.g. `python3.11`, `php8.2`) is captured separately.
+const EXECUTABLE_LANGUAGES: &[(&str, &str)] = &[
+    ("python", "python"),
+    ("gunicorn", "python"),
+    ("uvicorn", "python"),
+    ("celery", "python"),
+    ("nodejs", "node"),
+    ("node", "node"),
+    ("node.js", "node"),
+    ("npm", "node"),
+    ("java", "java"),
+    ("dotnet", "dotnet"),
+    ("ruby", "ruby"),
+    ("php-fpm", "php"),
+    ("php", "php"),
+];
+
+/// Package name patterns per language. When the pattern has a capture group
+/// the version is taken from the package name, otherwise from its version.
+const PACKAGE_PATTERNS: &[(&str, &str)] = &[
+    ("python", r"^python(\d+\.\d+)(?:-minimal)?$"),
+    ("python", r"^python3$"),
+    ("java", r"^(?:openjdk|temurin)-(\d+)-(?:jre|jdk)"),
+    ("java", r"^java-(\d+(?:\.\d+)*)-openjdk"),
+    ("node", r"^nodejs$"),
+    ("dotnet", r"^(?:dotnet|aspnetcore)-runtime-(\d+\.\d+)$"),
+    ("ruby", r"^ruby(\d+\.\d+)$"),
+    ("php", r"^php(\d+\.\d+)(?:-\w+)?$"),
+];
+
+/// How the language of a cluster was identified.
+struct LanguageMatch {
+    language: String,
+    version: Option<String>,
+    confidence: f64,
+    reason: String,
+    evidence_refs: Vec<String>,
+}
+
+/// Detect the runtime of every cluster and record it with a decision, along
+/// with the installed packages that provide it.
+pub fn detect_runtimes(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        if let Some((runtime, decision)) = detect_cluster_runtime(&bundle.manifest, cluster) {
+            cluster.packages = runtime_packages(&bundle.manifest, &runtime.language)
+                .into_iter()
+                .map(|(package, _)| ClusterPackage {
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                    evidence_ref: package.evidence_ref.clone(),
+                })
+                .collect();
+            cluster.runtime = Some(runtime);
+            cluster.decisions.push(decision);
+        }
+    }
+}
+
+/// Detect the runtime of a single cluster.
+fn detect_cluster_runtime(
+    manifest: &Manifest,
+    cluster: &AppCluster,
+) -> Option<(RuntimeInfo, Decision)> {
+    let mut found = find_language(manifest, cluster)?;
+    let mut reason = found.reason.clone();
+
+    // A bare major version (python3) is refined by probes or packages
+    let needs_version = found
+        .version
+        .as_deref()
+        .map(|v| !v.contains('.'))
+        .unwrap_or(true);
+
+    if needs_version && found.language != "native" {
+        let refined = version_from_probes(manifest, &found.language)
+            .or_else(|| version_from_packages(manifest, &found.language))
+            .filter(|(version, _, _)| {
+                found
+                    .version
+                    .as_deref()
+                    .map(|major| version.split('.').next() == Some(major))
+                    .unwrap_or(true)
+            });
+
+        if let Some((version, source, evidence_ref)) = refined {
+            reason.push_str(&format!("; version from {}", source));
+            found.version = Some(version);
+            found.evidence_refs.extend(evidence_ref);
+        }
+    }
+
+    found.evidence_refs.sort();
+    found.evidence_refs.dedup();
+
+    let decision = Decision::new(
+        format!(
+            "Runtime: {} {}",
+            found.language,
+            found.version.as_deref().unwrap_or("(version unknown)")
+        ),
+        reason,
+        found.evidence_refs.clone(),
+        found.confidence,
+    );
+
+    let runtime = RuntimeInfo {
+        language: found.language,
+        version: found.version,
+        evidence_refs: found.evidence_refs,
+    };
+
+    Some((runtime, decision))
+}
+
+/// Find the language from the executables started by the cluster.
+fn find_language(manifest: &Manifest, cluster: &AppCluster) -> Option<LanguageMatch> {
+    let commands = cluster
+        .services
+        .iter()
+        .filter_map(|s| s.exec_start.as_deref().map(|e| (e, s.evidence_ref.clone())))
+        .chain(
+            cluster
+                .processes
+                .iter()
+                .map(|p| (p.command.as_str(), p.evidence_ref.clone())),
+        );
+
+    let mut native: Option<LanguageMatch> = None;
+
+    for (command, command_ref) in commands {
+        let executable = match command.split_whitespace().next() {
+            Some(e) => e,
+            None => continue,
+        };
+
+        if let Some((language, version)) = language_from_executable(executable) {
+            return Some(LanguageMatch {
+                language,
+                version,
+                confidence: 0.9,
+                reason: format!("Executable {} is a known runtime", executable),
+                evidence_refs: command_ref.into_iter().collect(),
+            });
+        }
+
+        let binary = match manifest.binaries.iter().find(|b| b.path == executable) {
+            Some(b) => b,
+            None => continue,
+        };
+        let evidence_refs: Vec<String> = command_ref
+            .into_iter()
+            .chain(binary.evidence_ref.clone())
+            .collect();
+
+        match (binary.kind.as_str(), binary.interpreter.as_deref()) {
+            ("script", Some(interpreter)) => {
+                let interpreter_name = interpreter.split_whitespace().last().unwrap_or_default();
+                if let Some((language, version)) = language_from_executable(interpreter_name) {
+                    return Some(LanguageMatch {
+                        language,
+                        version,
+                        confidence: 0.85,
+                        reason: format!("{} is a script run by {}", executable, interpreter),
+                        evidence_refs,
+                    });
+                }
+            }
+            ("elf", interpreter) if native.is_none() => {
+                native = Some(LanguageMatch {
+                    language: "native".to_string(),
+                    version: None,
+                    confidence: 0.6,
+                    reason: match interpreter {
+                        Some(i) => format!("{} is an ELF binary (interpreter {})", executable, i),
+                        None => format!("{} is a static ELF binary", executable),
+                    },
+                    evidence_refs,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    native
+}
+
+/// Map an executable path to a language and optional version suffix.
+fn language_from_executable(executable: &str) -> Option<(String, Option<String>)> {
+    let name = executable
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(executable)
+        .to_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+
+    for (prefix, language) in EXECUTABLE_LANGUAGES {
+        if let Some(suffix) = name.strip_prefix(prefix) {
+            if suffix.is_empty() {
+                return Some((language.to_string(), None));
+            }
+            if suffix.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && suffix.starts_with(|c: char| c.is_ascii_digit())
+            {
+                return Some((language.to_string(), Some(suffix.to_string())));
+            }
+        }
+    }
+
+    None
+}
+
+/// Version reported by a runtime probe, with its source and evidence.
+fn version_from_probes(
+    manifest: &Manifest,
+    language: &str,
+) -> Option<(String, String, Option<String>)> {
+    manifest
+        .runtimes
+        .iter()
+        .find(|r| r.language == language)
+        .map(|r| {
+            (
+                r.version.clone(),
+                format!("{} version probe", language),
+                r.evidence_ref.clone(),
+            )
+        })
+}
+
+/// Installed packages providing a language runtime, each paired with the
+/// runtime version it provides.
+fn runtime_packages<'a>(manifest: &'a Manifest, language: &str) -> Vec<(&'a Package, String)> {
+    let patterns: Vec<Regex> = PACKAGE_PATTERNS
+        .iter()
+        .filter(|(lang, _)| *lang == language)
+        .map(|(_, pattern)| Regex::new(pattern).unwrap())
+        .collect();
+
+    manifest
+        .packages
+        .iter()
+        .filter_map(|package| {
+            let caps = patterns.iter().find_map(|re| re.captures(&package.name))?;
+            let version = match caps.get(1) {
+                Some(m) => m.as_str().to_string(),
+                None => leading_version(&package.version)?,
+            };
+            Some((package, version))
+        })
+        .collect()
+}
+
+/// Highest runtime version among installed packages.
+fn version_from_packages(
+    manifest: &Manifest,
+    language: &str,
+) -> Option<(String, String, Option<String>)> {
+    runtime_packages(manifest, language)
+        .into_iter()
+        .max_by(|a, b| compare_versions(&a.1, &b.1))
+        .map(|(package, version)| {
+            (
+                version,
+                format!("package {}", package.name),
+                package.evidence_ref.clone(),
+            )
+        })
+}
+
+/// Extract the leading dotted version from a package version string
+/// (`3.11.2-1+b1` -> `3.11.2`, `1:18.19.0` -> `18.19.0`).
+pub(crate) fn leading_version(version: &str) -> Option<String> {
+    let version = version.split_once(':').map(|(_, v)| v).unwrap_or(version);
+    let re = Regex::new(r"^(\d+(?:\.\d+)*)").unwrap();
+    re.captures(version).map(|caps| caps[1].to_string())
+}
+
+/// Compare dotted versions numerically.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |v: &str| -> Vec<u64> { v.split('.').filter_map(|p| p.parse().ok()).collect() };
+    parse(a).cmp(&parse(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{BinaryInfo, ClusterService, RuntimeProbe};
+
+    fn cluster_with_exec(exec_start: &str) -> AppCluster {
+        AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: "app.service".to_string(),
+                exec_start: Some(exec_start.to_string()),
+                evidence_ref: Some("evidence/services_1.txt".to_string()),
+                ..Default::default()
+            }],
+            confidence: 0.8,
+            ..Default::default()
+        }
+    }
+
+    fn package(name: &str, version: &str) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            architecture: None,
+            description: None,
+            install_date: None,
+            source: "dpkg".to_string(),
+            evidence_ref: Some("evidence/packages_1.txt".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_language_from_executable() {
+        assert_eq!(
+            language_from_executable("/usr/bin/python3.11"),
+            Some(("python".to_string(), Some("3.11".to_string())))
+        );
+        assert_eq!(
+            language_from_executable("/usr/bin/node"),
+            Some(("node".to_string(), None))
+        );
+        assert_eq!(
+            language_from_executable("C:\\Program Files\\dotnet\\dotnet.exe"),
+            Some(("dotnet".to_string(), None))
+        );
+        assert_eq!(language_from_executable("/usr/bin/nodeapp"), None);
+        assert_eq!(language_from_executable("/opt/app/server"), None);
+    }
+
+    #[test]
+    fn test_version_from_probe_and_packages() {
+        let mut manifest = Manifest {
+            packages: vec![
+                package("openjdk-11-jre-headless", "11.0.21+9-1"),
+                package("openjdk-17-jre-headless", "17.0.9+9-1"),
+                package("nodejs", "18.19.0+dfsg-6"),
+            ],
+            ..Default::default()
+        };
+
+        let (runtime, decision) =
+            detect_cluster_runtime(&manifest, &cluster_with_exec("/usr/bin/java -jar app.jar"))
+                .unwrap();
+        assert_eq!(runtime.language, "java");
+        assert_eq!(runtime.version.as_deref(), Some("17"));
+        assert!(runtime
+            .evidence_refs
+            .contains(&"evidence/packages_1.txt".to_string()));
+        assert!(decision.reason.contains("openjdk-17-jre-headless"));
+
+        // A version probe takes precedence over packages
+        manifest.runtimes.push(RuntimeProbe {
+            language: "node".to_string(),
+            version: "20.11.1".to_string(),
+            evidence_ref: Some("evidence/runtimes_1.txt".to_string()),
+        });
+        let (runtime, _) =
+            detect_cluster_runtime(&manifest, &cluster_with_exec("node server.js")).unwrap();
+        assert_eq!(runtime.version.as_deref(), Some("20.11.1"));
+    }
+
+    #[test]
+    fn test_script_shebang_and_native_binary() {
+        let manifest = Manifest {
+            binaries: vec![
+                BinaryInfo {
+                    path: "/opt/app/run".to_string(),
+                    kind: "script".to_string(),
+                    interpreter: Some("/usr/bin/env python3".to_string()),
+                    evidence_ref: Some("evidence/binaries_1.txt".to_string()),
+                },
+                BinaryInfo {
+                    path: "/opt/app/server".to_string(),
+                    kind: "elf".to_string(),
+                    interpreter: Some("/lib64/ld-linux-x86-64.so.2".to_string()),
+                    evidence_ref: Some("evidence/binaries_2.txt".to_string()),
+                },
+            ],
+            packages: vec![package("python3.11", "3.11.2-6")],
+            ..Default::default()
+        };
+
+        let (runtime, _) =
+            detect_cluster_runtime(&manifest, &cluster_with_exec("/opt/app/run --port 80"))
+                .unwrap();
+        assert_eq!(runtime.language, "python");
+        assert_eq!(runtime.version.as_deref(), Some("3.11"));
+
+        let (runtime, decision) =
+            detect_cluster_runtime(&manifest, &cluster_with_exec("/opt/app/server")).unwrap();
+        assert_eq!(runtime.language, "native");
+        assert_eq!(runtime.version, None);
+        assert!(decision.has_evidence());
+    }
+}
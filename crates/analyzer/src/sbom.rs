@@ -0,0 +1,369 @@
+//! Software bill of materials export.
+//!
+//! Converts the package inventory of a bundle (dpkg, rpm, pkg, pkgutil,
+//! Get-Package) into CycloneDX or SPDX JSON, for the whole host or scoped
+//! to the packages of a cluster, so vulnerability tooling can scan what a
+//! migration carries over. Packages are identified by package URLs.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use xcprobe_bundle_schema::{AppCluster, Manifest, Package};
+
+/// SBOM document format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON.
+    CycloneDx,
+    /// SPDX 2.3 JSON.
+    Spdx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cyclonedx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(anyhow::anyhow!("Invalid SBOM format: {}", s)),
+        }
+    }
+}
+
+impl SbomFormat {
+    /// Conventional file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SbomFormat::CycloneDx => "cdx.json",
+            SbomFormat::Spdx => "spdx.json",
+        }
+    }
+}
+
+/// SBOM of every package installed on the host.
+pub fn host_sbom(manifest: &Manifest, format: SbomFormat) -> Value {
+    let packages: Vec<&Package> = manifest.packages.iter().collect();
+    let subject = Subject {
+        kind: "operating-system",
+        name: &manifest.system.hostname,
+        version: manifest.system.os_version.as_deref(),
+    };
+    sbom(manifest, &subject, &packages, format)
+}
+
+/// SBOM of the packages the analysis attributed to a cluster.
+pub fn cluster_sbom(manifest: &Manifest, cluster: &AppCluster, format: SbomFormat) -> Value {
+    let packages: Vec<&Package> = manifest
+        .packages
+        .iter()
+        .filter(|p| {
+            cluster
+                .packages
+                .iter()
+                .any(|c| c.name == p.name && c.version == p.version)
+        })
+        .collect();
+    let subject = Subject {
+        kind: "application",
+        name: &cluster.name,
+        version: None,
+    };
+    sbom(manifest, &subject, &packages, format)
+}
+
+/// Write the host SBOM, and one per cluster, into `out_dir`. Returns the
+/// written paths.
+pub fn write_sboms(
+    manifest: &Manifest,
+    clusters: &[AppCluster],
+    format: SbomFormat,
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+
+    let host = file_name(&manifest.system.hostname, "host");
+    let mut documents = vec![(host, host_sbom(manifest, format))];
+    for cluster in clusters {
+        documents.push((cluster.id.clone(), cluster_sbom(manifest, cluster, format)));
+    }
+
+    let mut written = Vec::new();
+    for (name, document) in documents {
+        let path = out_dir.join(format!("{}.{}", name, format.extension()));
+        std::fs::write(&path, serde_json::to_string_pretty(&document)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// What an SBOM describes: the host or a cluster.
+struct Subject<'a> {
+    /// CycloneDX component type.
+    kind: &'static str,
+    name: &'a str,
+    version: Option<&'a str>,
+}
+
+fn sbom(
+    manifest: &Manifest,
+    subject: &Subject<'_>,
+    packages: &[&Package],
+    format: SbomFormat,
+) -> Value {
+    match format {
+        SbomFormat::CycloneDx => cyclonedx(manifest, subject, packages),
+        SbomFormat::Spdx => spdx(manifest, subject.name, packages),
+    }
+}
+
+fn cyclonedx(manifest: &Manifest, subject: &Subject<'_>, packages: &[&Package]) -> Value {
+    let components: Vec<Value> = packages
+        .iter()
+        .map(|package| {
+            let purl = purl(manifest, package);
+            let mut properties = vec![json!({"name": "xcprobe:source", "value": package.source})];
+            if let Some(ref evidence_ref) = package.evidence_ref {
+                properties.push(json!({"name": "xcprobe:evidence_ref", "value": evidence_ref}));
+            }
+            let mut component = json!({
+                "type": "library",
+                "bom-ref": purl,
+                "name": package.name,
+                "version": package.version,
+                "purl": purl,
+                "properties": properties,
+            });
+            if let Some(ref description) = package.description {
+                component["description"] = json!(description);
+            }
+            component
+        })
+        .collect();
+
+    let mut component = json!({
+        "type": subject.kind,
+        "name": subject.name,
+    });
+    if let Some(version) = subject.version {
+        component["version"] = json!(version);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid::Uuid::new_v4()),
+        "version": 1,
+        "metadata": {
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "xcprobe",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": component,
+            "properties": [
+                {"name": "xcprobe:collection_id", "value": manifest.collection_id},
+            ],
+        },
+        "components": components,
+    })
+}
+
+fn spdx(manifest: &Manifest, name: &str, packages: &[&Package]) -> Value {
+    let spdx_packages: Vec<Value> = packages
+        .iter()
+        .enumerate()
+        .map(|(i, package)| {
+            json!({
+                "name": package.name,
+                "SPDXID": format!("SPDXRef-Package-{}", i + 1),
+                "versionInfo": package.version,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+                "comment": format!("Installed via {}", package.source),
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": purl(manifest, package),
+                }],
+            })
+        })
+        .collect();
+    let relationships: Vec<Value> = (1..=packages.len())
+        .map(|i| {
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": format!("SPDXRef-Package-{}", i),
+            })
+        })
+        .collect();
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": name,
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/xcprobe-{}-{}",
+            file_name(name, "host"),
+            uuid::Uuid::new_v4()
+        ),
+        "creationInfo": {
+            "created": chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: xcprobe-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    })
+}
+
+/// Package URL of an installed package. dpkg and rpm packages are
+/// namespaced by the distribution; other sources are generic.
+fn purl(manifest: &Manifest, package: &Package) -> String {
+    let (purl_type, default_namespace) = match package.source.as_str() {
+        "dpkg" => ("deb", Some("debian")),
+        "rpm" => ("rpm", Some("redhat")),
+        _ => ("generic", None),
+    };
+    let namespace = default_namespace.map(|default| {
+        manifest
+            .system
+            .os_version
+            .as_deref()
+            .and_then(|v| v.split_whitespace().next())
+            .map(|distro| distro.to_lowercase())
+            .filter(|distro| distro.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or_else(|| default.to_string())
+    });
+
+    let mut purl = format!("pkg:{}/", purl_type);
+    if let Some(namespace) = namespace {
+        purl.push_str(&namespace);
+        purl.push('/');
+    }
+    purl.push_str(&purl_encode(&package.name));
+    purl.push('@');
+    purl.push_str(&purl_encode(&package.version));
+    if let Some(ref arch) = package.architecture {
+        purl.push_str("?arch=");
+        purl.push_str(&purl_encode(arch));
+    }
+    purl
+}
+
+/// Percent-encode a package URL component.
+fn purl_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'-' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// File-system safe version of a name.
+fn file_name(name: &str, fallback: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if cleaned.trim_matches('-').is_empty() {
+        fallback.to_string()
+    } else {
+        cleaned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::ClusterPackage;
+
+    fn package(name: &str, version: &str, source: &str) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            architecture: Some("amd64".to_string()),
+            description: None,
+            install_date: None,
+            source: source.to_string(),
+            evidence_ref: Some("evidence/packages_1.txt".to_string()),
+        }
+    }
+
+    fn manifest() -> Manifest {
+        let mut manifest = Manifest::default();
+        manifest.system.hostname = "web01".to_string();
+        manifest.system.os_version = Some("Ubuntu 22.04.3 LTS".to_string());
+        manifest.packages = vec![
+            package("openssl", "3.0.2-0ubuntu1.10", "dpkg"),
+            package("python3.10", "3.10.12-1~22.04", "dpkg"),
+            package("libc6", "2.35-0ubuntu3.4", "dpkg"),
+        ];
+        manifest
+    }
+
+    #[test]
+    fn test_cyclonedx_host_sbom() {
+        let sbom = host_sbom(&manifest(), SbomFormat::CycloneDx);
+        assert_eq!(sbom["bomFormat"], "CycloneDX");
+        assert_eq!(sbom["metadata"]["component"]["name"], "web01");
+        let components = sbom["components"].as_array().unwrap();
+        assert_eq!(components.len(), 3);
+        assert_eq!(
+            components[1]["purl"],
+            "pkg:deb/ubuntu/python3.10@3.10.12-1~22.04?arch=amd64"
+        );
+        assert_eq!(components[1]["properties"][0]["value"], "dpkg");
+    }
+
+    #[test]
+    fn test_spdx_cluster_sbom() {
+        let manifest = manifest();
+        let cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "api".to_string(),
+            app_type: "api".to_string(),
+            packages: vec![ClusterPackage {
+                name: "python3.10".to_string(),
+                version: "3.10.12-1~22.04".to_string(),
+                evidence_ref: None,
+            }],
+            confidence: 0.9,
+            ..Default::default()
+        };
+
+        let sbom = cluster_sbom(&manifest, &cluster, SbomFormat::Spdx);
+        assert_eq!(sbom["spdxVersion"], "SPDX-2.3");
+        let packages = sbom["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0]["name"], "python3.10");
+        assert_eq!(
+            packages[0]["externalRefs"][0]["referenceLocator"],
+            "pkg:deb/ubuntu/python3.10@3.10.12-1~22.04?arch=amd64"
+        );
+        assert_eq!(
+            sbom["relationships"][0]["relatedSpdxElement"],
+            "SPDXRef-Package-1"
+        );
+    }
+}
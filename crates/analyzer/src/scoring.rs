@@ -1,8 +1,167 @@
 //! Process/service scoring for business relevance.
 
+use crate::index::ManifestIndex;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 use xcprobe_bundle_schema::Manifest;
 
+/// Calibration of process scoring and cluster confidence, loaded from a
+/// `scoring.toml`. Missing keys keep their defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringConfig {
+    pub processes: ProcessScoring,
+    pub evidence: EvidenceWeighting,
+}
+
+/// Keywords and scores deciding which processes are business applications.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessScoring {
+    /// Score of a process matching no rule.
+    pub base_score: f64,
+    /// Processes scoring at least this are business processes.
+    pub business_threshold: f64,
+    /// Command prefixes of kernel threads.
+    pub kernel_thread_prefixes: Vec<String>,
+    pub kernel_thread_score: f64,
+    /// Launchers and wrappers, never business processes themselves.
+    pub launcher_commands: Vec<String>,
+    pub launcher_score: f64,
+    /// Keywords of container runtime processes.
+    pub container_keywords: Vec<String>,
+    pub container_score: f64,
+    /// Keywords of application servers, runtimes and databases.
+    pub service_keywords: Vec<String>,
+    pub service_score: f64,
+    /// Minimum score of a process listening on a port.
+    pub listening_port_score: f64,
+    /// Minimum score of the main process of a service.
+    pub managed_service_score: f64,
+    /// Users not counted as application users.
+    pub system_users: Vec<String>,
+    /// Added for processes running as an application user.
+    pub non_root_bonus: f64,
+    /// Command lines longer than this suggest configuration.
+    pub long_cmdline_length: usize,
+    pub long_cmdline_bonus: f64,
+}
+
+impl Default for ProcessScoring {
+    fn default() -> Self {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+        Self {
+            base_score: 0.5,
+            business_threshold: 0.6,
+            kernel_thread_prefixes: strings(&[
+                "kworker",
+                "migration",
+                "ksoftirqd",
+                "rcu_",
+                "watchdog",
+                "kthreadd",
+                "kswapd",
+                "khugepaged",
+                "kcompactd",
+            ]),
+            kernel_thread_score: 0.1,
+            launcher_commands: strings(&[
+                "su",
+                "sudo",
+                "sh",
+                "bash",
+                "dash",
+                "zsh",
+                "login",
+                "sshd",
+                "init",
+                "tini",
+                "dumb-init",
+                "runuser",
+                "start-stop-daemon",
+                "supervise",
+            ]),
+            launcher_score: 0.2,
+            container_keywords: strings(&["docker", "containerd", "kubelet", "crio"]),
+            container_score: 0.3,
+            service_keywords: strings(&[
+                "nginx", "apache", "httpd", "java", "python", "node", "ruby", "php", "dotnet",
+                "postgres", "mysql", "redis", "mongo", "rabbit", "kafka", "elastic",
+            ]),
+            service_score: 0.8,
+            listening_port_score: 0.7,
+            managed_service_score: 0.8,
+            system_users: strings(&["nobody", "daemon", "systemd-network"]),
+            non_root_bonus: 0.1,
+            long_cmdline_length: 100,
+            long_cmdline_bonus: 0.05,
+        }
+    }
+}
+
+/// How evidence weighs in cluster confidence.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvidenceWeighting {
+    /// Weight, in the average of decision confidences, of a decision
+    /// citing no evidence (decisions with evidence weigh 1).
+    pub unevidenced_weight: f64,
+    /// Share of the confidence kept by a cluster none of whose decisions
+    /// cite evidence; the rest grows with the share of decisions that do.
+    pub evidence_ratio_floor: f64,
+}
+
+impl Default for EvidenceWeighting {
+    fn default() -> Self {
+        Self {
+            unevidenced_weight: 0.5,
+            evidence_ratio_floor: 0.5,
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// Load a scoring configuration file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scoring config {}", path.display()))?;
+        let config: Self = toml::from_str(&content)
+            .with_context(|| format!("Invalid scoring config {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid scoring config {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Check that scores and weights lie between 0 and 1.
+    fn validate(&self) -> Result<()> {
+        let p = &self.processes;
+        let e = &self.evidence;
+        let values = [
+            ("processes.base_score", p.base_score),
+            ("processes.business_threshold", p.business_threshold),
+            ("processes.kernel_thread_score", p.kernel_thread_score),
+            ("processes.launcher_score", p.launcher_score),
+            ("processes.container_score", p.container_score),
+            ("processes.service_score", p.service_score),
+            ("processes.listening_port_score", p.listening_port_score),
+            ("processes.managed_service_score", p.managed_service_score),
+            ("processes.non_root_bonus", p.non_root_bonus),
+            ("processes.long_cmdline_bonus", p.long_cmdline_bonus),
+            ("evidence.unevidenced_weight", e.unevidenced_weight),
+            ("evidence.evidence_ratio_floor", e.evidence_ratio_floor),
+        ];
+        for (name, value) in values {
+            if !(0.0..=1.0).contains(&value) {
+                bail!("{} must be between 0 and 1, got {}", name, value);
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Score for a process or service.
 #[derive(Debug, Clone)]
 pub struct ProcessScore {
@@ -19,63 +178,42 @@ pub struct ProcessScore {
 ///
 /// Higher scores indicate more likely to be a business application.
 /// System processes, kernel threads, etc. get lower scores.
-pub fn score_processes(manifest: &Manifest) -> HashMap<u32, ProcessScore> {
+pub fn score_processes(
+    index: &ManifestIndex<'_>,
+    config: &ProcessScoring,
+) -> HashMap<u32, ProcessScore> {
+    let manifest = index.manifest;
     let mut scores = HashMap::new();
 
     // Score based on process characteristics
     for process in &manifest.processes {
-        let mut score: f64 = 0.5; // Base score
+        let mut score: f64 = config.base_score;
         let mut reasons = Vec::new();
 
         // System process detection (lower score)
-        let system_prefixes = [
-            "kworker",
-            "migration",
-            "ksoftirqd",
-            "rcu_",
-            "watchdog",
-            "kthreadd",
-            "kswapd",
-            "khugepaged",
-            "kcompactd",
-        ];
-        if system_prefixes
+        if config
+            .kernel_thread_prefixes
             .iter()
-            .any(|p| process.command.starts_with(p))
+            .any(|p| process.command.starts_with(p.as_str()))
         {
-            score = 0.1;
+            score = config.kernel_thread_score;
             reasons.push("System kernel thread".to_string());
         }
 
         // Launcher/wrapper commands: these invoke other processes but are not
         // themselves the business application. Skip even if their cmdline
         // contains framework keywords like "python" or "node".
-        let launcher_commands = [
-            "su",
-            "sudo",
-            "sh",
-            "bash",
-            "dash",
-            "zsh",
-            "login",
-            "sshd",
-            "init",
-            "tini",
-            "dumb-init",
-            "runuser",
-            "start-stop-daemon",
-            "supervise",
-        ];
         let cmd_basename = process
             .command
             .rsplit('/')
             .next()
             .unwrap_or(&process.command);
-        if launcher_commands
+        if config
+            .launcher_commands
             .iter()
             .any(|l| cmd_basename.eq_ignore_ascii_case(l))
         {
-            score = 0.2;
+            score = config.launcher_score;
             reasons.push("Launcher/wrapper process".to_string());
             // Skip further scoring — launchers should not be business processes
             scores.insert(
@@ -92,59 +230,51 @@ pub fn score_processes(manifest: &Manifest) -> HashMap<u32, ProcessScore> {
         }
 
         // Container/orchestration processes
-        let container_keywords = ["docker", "containerd", "kubelet", "crio"];
-        if container_keywords
+        if config
+            .container_keywords
             .iter()
-            .any(|k| process.command.contains(k))
+            .any(|k| process.command.contains(k.as_str()))
         {
-            score = 0.3;
+            score = config.container_score;
             reasons.push("Container runtime process".to_string());
         }
 
         // Common service processes (higher score)
-        let service_keywords = [
-            "nginx", "apache", "httpd", "java", "python", "node", "ruby", "php", "dotnet",
-            "postgres", "mysql", "redis", "mongo", "rabbit", "kafka", "elastic",
-        ];
-        if service_keywords.iter().any(|k| {
-            process.command.to_lowercase().contains(k)
-                || process.full_cmdline.to_lowercase().contains(k)
+        let command = process.command.to_lowercase();
+        let cmdline = process.full_cmdline.to_lowercase();
+        if config.service_keywords.iter().any(|k| {
+            let k = k.to_lowercase();
+            command.contains(&k) || cmdline.contains(&k)
         }) {
-            score = 0.8;
+            score = config.service_score;
             reasons.push("Known application framework".to_string());
         }
 
         // Listening on a port is a strong signal
-        if let Some(pid) = Some(process.pid) {
-            if manifest.ports.iter().any(|p| p.pid == Some(pid)) {
-                score = score.max(0.7);
-                reasons.push("Listening on network port".to_string());
-            }
+        if !index.ports(process.pid).is_empty() {
+            score = score.max(config.listening_port_score);
+            reasons.push("Listening on network port".to_string());
         }
 
         // Has a corresponding systemd service
-        for service in &manifest.services {
-            if service.main_pid == Some(process.pid) {
-                score = score.max(0.8);
-                reasons.push(format!("Managed by systemd service: {}", service.name));
-            }
+        for service in index.services_of_pid(process.pid) {
+            score = score.max(config.managed_service_score);
+            reasons.push(format!("Managed by systemd service: {}", service.name));
         }
 
         // User processes (not root) often indicate business apps
-        if process.user != "root"
-            && !["nobody", "daemon", "systemd-network"].contains(&process.user.as_str())
-        {
-            score += 0.1;
+        if process.user != "root" && !config.system_users.contains(&process.user) {
+            score += config.non_root_bonus;
             reasons.push(format!("Runs as user: {}", process.user));
         }
 
         // Long command lines suggest configuration
-        if process.full_cmdline.len() > 100 {
-            score += 0.05;
+        if process.full_cmdline.len() > config.long_cmdline_length {
+            score += config.long_cmdline_bonus;
             reasons.push("Has complex command line".to_string());
         }
 
-        let is_business = score >= 0.6;
+        let is_business = score >= config.business_threshold;
 
         scores.insert(
             process.pid,
@@ -234,9 +364,10 @@ mod tests {
             working_directory: None,
             environment: None,
             evidence_ref: None,
+            cmdline_evidence_ref: None,
         });
 
-        let scores = score_processes(&manifest);
+        let scores = score_processes(&ManifestIndex::new(&manifest), &ProcessScoring::default());
         let score = scores.get(&1).unwrap();
         assert!(score.score < 0.5);
         assert!(!score.is_business_process);
@@ -259,6 +390,7 @@ mod tests {
             working_directory: None,
             environment: None,
             evidence_ref: None,
+            cmdline_evidence_ref: None,
         });
         manifest.ports.push(xcprobe_bundle_schema::PortInfo {
             protocol: "tcp".to_string(),
@@ -268,11 +400,62 @@ mod tests {
             pid: Some(1234),
             process_name: Some("nginx".to_string()),
             evidence_ref: None,
+            pid_source: None,
+            pid_evidence_ref: None,
+            family: None,
+            socket_path: None,
         });
 
-        let scores = score_processes(&manifest);
+        let scores = score_processes(&ManifestIndex::new(&manifest), &ProcessScoring::default());
         let score = scores.get(&1234).unwrap();
         assert!(score.score >= 0.7);
         assert!(score.is_business_process);
     }
+
+    #[test]
+    fn test_scoring_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scoring.toml");
+        std::fs::write(
+            &path,
+            "[processes]\nbusiness_threshold = 0.9\n\n[evidence]\nunevidenced_weight = 0.2\n",
+        )
+        .unwrap();
+        let config = ScoringConfig::load(&path).unwrap();
+        assert_eq!(config.processes.business_threshold, 0.9);
+        assert_eq!(config.processes.base_score, 0.5);
+        assert_eq!(config.evidence.unevidenced_weight, 0.2);
+
+        // nginx listening on a port scores 0.8, below the raised threshold
+        let mut manifest = Manifest::default();
+        manifest.processes.push(xcprobe_bundle_schema::ProcessInfo {
+            pid: 1234,
+            ppid: 1,
+            user: "root".to_string(),
+            command: "nginx".to_string(),
+            args: vec![],
+            full_cmdline: "nginx".to_string(),
+            start_time: None,
+            elapsed_time: None,
+            cpu_percent: None,
+            memory_percent: None,
+            working_directory: None,
+            environment: None,
+            evidence_ref: None,
+            cmdline_evidence_ref: None,
+        });
+        assert!(
+            score_processes(&ManifestIndex::new(&manifest), &ProcessScoring::default())[&1234]
+                .is_business_process
+        );
+        assert!(
+            !score_processes(&ManifestIndex::new(&manifest), &config.processes)[&1234]
+                .is_business_process
+        );
+
+        std::fs::write(&path, "[processes]\nservice_score = 1.5\n").unwrap();
+        assert!(ScoringConfig::load(&path).is_err());
+        std::fs::write(&path, "[processes]\nbusines_threshold = 0.9\n").unwrap();
+        assert!(ScoringConfig::load(&path).is_err());
+    }
 }
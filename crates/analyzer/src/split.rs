@@ -0,0 +1,286 @@
+//! Cluster splitting suggestions.
+//!
+//! Merging and process grouping can leave several applications in one
+//! cluster, such as Java services started by a single unit manager, each in
+//! its own directory and on its own port. Such clusters are kept, but a
+//! warning proposes sub-cluster boundaries: the distinct working
+//! directories of the cluster's processes, and the unrelated process trees
+//! listening on ports.
+
+use crate::clustering;
+use crate::index::ManifestIndex;
+use xcprobe_bundle_schema::{AnalysisWarning, AppCluster};
+
+/// A proposed sub-cluster.
+#[derive(Debug, Default)]
+struct Boundary {
+    /// Working directory, or `PID <n>` of the process tree root.
+    label: String,
+    /// Whether the boundary is a working directory.
+    workdir: bool,
+    services: Vec<String>,
+    pids: Vec<u32>,
+    ports: Vec<u16>,
+    evidence_refs: Vec<String>,
+}
+
+impl Boundary {
+    /// Whether the boundary stands for an application of its own rather
+    /// than a helper of one.
+    fn is_application(&self) -> bool {
+        self.workdir || !self.ports.is_empty()
+    }
+
+    fn describe(&self) -> String {
+        let mut members: Vec<String> = self.services.clone();
+        if !self.pids.is_empty() {
+            let pids: Vec<String> = self.pids.iter().map(u32::to_string).collect();
+            let noun = if self.pids.len() == 1 { "PID" } else { "PIDs" };
+            members.push(format!("{} {}", noun, pids.join(", ")));
+        }
+        if !self.ports.is_empty() {
+            let ports: Vec<String> = self.ports.iter().map(u16::to_string).collect();
+            let noun = if self.ports.len() == 1 {
+                "port"
+            } else {
+                "ports"
+            };
+            members.push(format!("{} {}", noun, ports.join(", ")));
+        }
+        format!("{} ({})", self.label, members.join(", "))
+    }
+
+    fn add_evidence(&mut self, evidence_ref: Option<&String>) {
+        if let Some(evidence_ref) = evidence_ref {
+            if !self.evidence_refs.contains(evidence_ref) {
+                self.evidence_refs.push(evidence_ref.clone());
+            }
+        }
+    }
+}
+
+/// Warnings proposing to split clusters holding several applications.
+/// Database clusters and replicas of one templated unit are left alone.
+pub fn suggest_splits(index: &ManifestIndex<'_>, clusters: &[AppCluster]) -> Vec<AnalysisWarning> {
+    clusters
+        .iter()
+        .filter(|c| c.database.is_none() && c.replicas.is_none())
+        .filter_map(|cluster| {
+            let boundaries: Vec<Boundary> = boundaries(index, cluster)
+                .into_iter()
+                .filter(Boundary::is_application)
+                .collect();
+            if boundaries.len() < 2 {
+                return None;
+            }
+            let described: Vec<String> = boundaries.iter().map(Boundary::describe).collect();
+            let evidence_refs = boundaries
+                .iter()
+                .flat_map(|b| b.evidence_refs.iter().cloned())
+                .fold(Vec::new(), |mut refs: Vec<String>, r| {
+                    if !refs.contains(&r) {
+                        refs.push(r);
+                    }
+                    refs
+                });
+            Some(
+                AnalysisWarning::new(
+                    "cluster_split_suggested",
+                    "warning",
+                    format!(
+                        "Cluster {} ({}) may hold {} applications; consider splitting it into: {}",
+                        cluster.id,
+                        cluster.name,
+                        boundaries.len(),
+                        described.join("; ")
+                    ),
+                    vec![cluster.id.clone()],
+                )
+                .with_evidence(evidence_refs),
+            )
+        })
+        .collect()
+}
+
+/// The cluster's members grouped by working directory, or by process tree
+/// for processes in a shared directory, in cluster order.
+fn boundaries(index: &ManifestIndex<'_>, cluster: &AppCluster) -> Vec<Boundary> {
+    let mut boundaries: Vec<Boundary> = Vec::new();
+    let mut boundary_of = |label: String, workdir: bool| -> usize {
+        match boundaries.iter().position(|b| b.label == label) {
+            Some(position) => position,
+            None => {
+                boundaries.push(Boundary {
+                    label,
+                    workdir,
+                    ..Default::default()
+                });
+                boundaries.len() - 1
+            }
+        }
+    };
+
+    let pids: Vec<u32> = cluster.processes.iter().map(|p| p.pid).collect();
+    let mut assigned: Vec<(u32, usize)> = Vec::new();
+    for process in &cluster.processes {
+        let (label, workdir) = process_boundary(index, cluster, &pids, process.pid);
+        let position = boundary_of(label, workdir);
+        assigned.push((process.pid, position));
+    }
+
+    let mut assigned_services: Vec<(usize, usize)> = Vec::new();
+    for (n, service) in cluster.services.iter().enumerate() {
+        let main_pid = index.service(&service.name).and_then(|s| s.main_pid);
+        let position = match main_pid.and_then(|pid| assigned.iter().find(|(p, _)| *p == pid)) {
+            Some((_, position)) => *position,
+            None => match service
+                .working_directory
+                .as_deref()
+                .and_then(clustering::app_workdir)
+            {
+                Some(wd) => boundary_of(wd, true),
+                None => continue,
+            },
+        };
+        assigned_services.push((n, position));
+    }
+
+    for (n, position) in assigned_services {
+        let service = &cluster.services[n];
+        let boundary = &mut boundaries[position];
+        boundary.services.push(service.name.clone());
+        boundary.add_evidence(service.evidence_ref.as_ref());
+    }
+    for (process, (pid, position)) in cluster.processes.iter().zip(assigned) {
+        let boundary = &mut boundaries[position];
+        boundary.pids.push(pid);
+        boundary.add_evidence(process.evidence_ref.as_ref());
+        for port in index.ports(pid) {
+            if !boundary.ports.contains(&port.local_port) {
+                boundary.ports.push(port.local_port);
+                boundary.add_evidence(port.evidence_ref.as_ref());
+            }
+        }
+    }
+    boundaries
+}
+
+/// Boundary of a process: its own application working directory, else its
+/// parent's within the cluster, else its process tree root.
+fn process_boundary(
+    index: &ManifestIndex<'_>,
+    cluster: &AppCluster,
+    pids: &[u32],
+    pid: u32,
+) -> (String, bool) {
+    let mut current = pid;
+    // Bounded, in case of a ppid cycle in a malformed manifest
+    for _ in 0..64 {
+        let workdir = cluster
+            .processes
+            .iter()
+            .find(|p| p.pid == current)
+            .and_then(|p| p.working_directory.as_deref())
+            .and_then(clustering::app_workdir);
+        if let Some(workdir) = workdir {
+            return (workdir, true);
+        }
+        match index.process(current).map(|p| p.ppid) {
+            Some(parent) if parent != current && pids.contains(&parent) => current = parent,
+            _ => break,
+        }
+    }
+    (format!("PID {}", current), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{ClusterProcess, ClusterService, Manifest};
+
+    #[test]
+    fn test_unit_manager_with_several_applications() {
+        let processes = serde_json::from_value(serde_json::json!([
+            { "pid": 100, "ppid": 1, "user": "apps", "command": "/opt/apps/run-all.sh",
+              "args": [], "full_cmdline": "/opt/apps/run-all.sh", "working_directory": "/" },
+            { "pid": 101, "ppid": 100, "user": "apps", "command": "/usr/bin/java",
+              "args": ["-jar", "orders.jar"], "full_cmdline": "java -jar orders.jar",
+              "working_directory": "/opt/orders" },
+            { "pid": 102, "ppid": 100, "user": "apps", "command": "/usr/bin/java",
+              "args": ["-jar", "billing.jar"], "full_cmdline": "java -jar billing.jar",
+              "working_directory": "/opt/billing/" },
+            { "pid": 103, "ppid": 102, "user": "apps", "command": "/bin/sh",
+              "args": ["report.sh"], "full_cmdline": "sh report.sh", "working_directory": "/" }
+        ]))
+        .unwrap();
+        let ports = serde_json::from_value(serde_json::json!([
+            { "protocol": "tcp", "local_address": "0.0.0.0", "local_port": 8080,
+              "state": "LISTEN", "pid": 101, "evidence_ref": "evidence/ports/ss.txt" },
+            { "protocol": "tcp", "local_address": "0.0.0.0", "local_port": 8081,
+              "state": "LISTEN", "pid": 102, "evidence_ref": "evidence/ports/ss.txt" }
+        ]))
+        .unwrap();
+        let services = serde_json::from_value(serde_json::json!([
+            { "name": "apps.service", "state": "running", "main_pid": 100,
+              "exec_start": "/opt/apps/run-all.sh", "exec_start_pre": [],
+              "exec_start_post": [], "environment": {}, "environment_files": [],
+              "dependencies": [], "wanted_by": [], "evidence_ref": "evidence/service/apps.txt" }
+        ]))
+        .unwrap();
+        let manifest = Manifest {
+            processes,
+            ports,
+            services,
+            ..Default::default()
+        };
+        let index = ManifestIndex::new(&manifest);
+        let cluster = |pids: &[u32]| AppCluster {
+            id: "app-0".to_string(),
+            name: "apps".to_string(),
+            app_type: "api".to_string(),
+            processes: manifest
+                .processes
+                .iter()
+                .filter(|p| pids.contains(&p.pid))
+                .map(|p| ClusterProcess {
+                    pid: p.pid,
+                    command: p.command.clone(),
+                    args: p.args.clone(),
+                    user: p.user.clone(),
+                    working_directory: p.working_directory.clone(),
+                    evidence_ref: Some(format!("evidence/processes/{}.txt", p.pid)),
+                })
+                .collect(),
+            services: vec![ClusterService {
+                name: "apps.service".to_string(),
+                exec_start: Some("/opt/apps/run-all.sh".to_string()),
+                user: Some("apps".to_string()),
+                evidence_ref: Some("evidence/service/apps.txt".to_string()),
+                ..Default::default()
+            }],
+            confidence: 0.9,
+            ..Default::default()
+        };
+
+        let warnings = suggest_splits(&index, &[cluster(&[100, 101, 102, 103])]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "cluster_split_suggested");
+        assert_eq!(
+            warnings[0].message,
+            "Cluster app-0 (apps) may hold 2 applications; consider splitting it into: \
+             /opt/orders (PID 101, port 8080); /opt/billing (PIDs 102, 103, port 8081)"
+        );
+        assert_eq!(
+            warnings[0].evidence_refs,
+            vec![
+                "evidence/processes/101.txt",
+                "evidence/ports/ss.txt",
+                "evidence/processes/102.txt",
+                "evidence/processes/103.txt",
+            ]
+        );
+
+        // One application and its helper
+        assert!(suggest_splits(&index, &[cluster(&[100, 102, 103])]).is_empty());
+    }
+}
@@ -0,0 +1,512 @@
+//! Development stand-ins for external dependencies.
+//!
+//! `docker-compose.dev.yaml` is a compose override running a containerized
+//! stand-in (postgres, redis, mailhog, localstack...) for each external
+//! dependency of the plan, and pointing the services that use it at the
+//! stand-in through their own environment variables. Teams can run the
+//! migrated stack locally before connecting it to real infrastructure.
+
+use crate::dependencies::endpoint_host;
+use crate::templates::{self, TemplateEngine};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use xcprobe_bundle_schema::{AppCluster, DependencyInfo, PackPlan};
+
+/// Username, password and database name of stand-ins with credentials.
+const DEV_CREDENTIAL: &str = "dev";
+
+/// A container standing in for a kind of external dependency.
+#[derive(Debug)]
+pub struct StandIn {
+    pub name: &'static str,
+    pub image: &'static str,
+    /// Dependency type it stands in for.
+    dep_type: &'static str,
+    /// Endpoint substrings and ports selecting it among stand-ins of the
+    /// same type. The first stand-in of a type is its default.
+    markers: &'static [&'static str],
+    ports: &'static [u16],
+    /// Port the stand-in listens on.
+    pub port: u16,
+    /// Ports published on the host (web consoles).
+    published: &'static [u16],
+    environment: &'static [(&'static str, &'static str)],
+    /// Whether it takes the `dev` user, password and database.
+    credentials: bool,
+    /// Prefixes of the variables configuring this kind of dependency.
+    env_prefixes: &'static [&'static str],
+    /// Connection URL, `{host}` being the stand-in service.
+    url: &'static str,
+    /// Variables set on every service using the stand-in.
+    consumer_environment: &'static [(&'static str, &'static str)],
+}
+
+/// Known stand-ins.
+pub static STAND_INS: &[StandIn] = &[
+    StandIn {
+        name: "postgres",
+        image: "postgres:16-alpine",
+        dep_type: "database",
+        markers: &["postgres"],
+        ports: &[5432],
+        port: 5432,
+        published: &[],
+        environment: &[
+            ("POSTGRES_USER", DEV_CREDENTIAL),
+            ("POSTGRES_PASSWORD", DEV_CREDENTIAL),
+            ("POSTGRES_DB", DEV_CREDENTIAL),
+        ],
+        credentials: true,
+        env_prefixes: &["DB_", "DATABASE_", "POSTGRES_", "PG"],
+        url: "postgres://dev:dev@{host}:5432/dev",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "mysql",
+        image: "mysql:8.0",
+        dep_type: "database",
+        markers: &["mysql", "mariadb"],
+        ports: &[3306],
+        port: 3306,
+        published: &[],
+        environment: &[
+            ("MYSQL_ROOT_PASSWORD", DEV_CREDENTIAL),
+            ("MYSQL_DATABASE", DEV_CREDENTIAL),
+            ("MYSQL_USER", DEV_CREDENTIAL),
+            ("MYSQL_PASSWORD", DEV_CREDENTIAL),
+        ],
+        credentials: true,
+        env_prefixes: &["DB_", "DATABASE_", "MYSQL_"],
+        url: "mysql://dev:dev@{host}:3306/dev",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "mongo",
+        image: "mongo:7.0",
+        dep_type: "database",
+        markers: &["mongo"],
+        ports: &[27017],
+        port: 27017,
+        published: &[],
+        environment: &[
+            ("MONGO_INITDB_ROOT_USERNAME", DEV_CREDENTIAL),
+            ("MONGO_INITDB_ROOT_PASSWORD", DEV_CREDENTIAL),
+        ],
+        credentials: true,
+        env_prefixes: &["MONGO_", "MONGODB_"],
+        url: "mongodb://dev:dev@{host}:27017",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "redis",
+        image: "redis:7-alpine",
+        dep_type: "cache",
+        markers: &["redis"],
+        ports: &[6379],
+        port: 6379,
+        published: &[],
+        environment: &[],
+        credentials: false,
+        env_prefixes: &["REDIS_", "CACHE_"],
+        url: "redis://{host}:6379",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "memcached",
+        image: "memcached:1.6-alpine",
+        dep_type: "cache",
+        markers: &["memcached"],
+        ports: &[11211],
+        port: 11211,
+        published: &[],
+        environment: &[],
+        credentials: false,
+        env_prefixes: &["MEMCACHED_"],
+        url: "{host}:11211",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "rabbitmq",
+        image: "rabbitmq:3-management-alpine",
+        dep_type: "messagequeue",
+        markers: &["amqp", "rabbit"],
+        ports: &[5672],
+        port: 5672,
+        published: &[15672],
+        environment: &[
+            ("RABBITMQ_DEFAULT_USER", DEV_CREDENTIAL),
+            ("RABBITMQ_DEFAULT_PASS", DEV_CREDENTIAL),
+        ],
+        credentials: true,
+        env_prefixes: &["AMQP_", "RABBITMQ_", "BROKER_"],
+        url: "amqp://dev:dev@{host}:5672",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "mailhog",
+        image: "mailhog/mailhog:v1.0.1",
+        dep_type: "smtp",
+        markers: &["smtp", "mail"],
+        ports: &[25, 465, 587],
+        port: 1025,
+        published: &[8025],
+        environment: &[],
+        credentials: false,
+        env_prefixes: &["SMTP_", "MAIL_", "EMAIL_"],
+        url: "smtp://{host}:1025",
+        consumer_environment: &[],
+    },
+    StandIn {
+        name: "localstack",
+        image: "localstack/localstack:3",
+        dep_type: "aws",
+        markers: &["amazonaws"],
+        ports: &[],
+        port: 4566,
+        published: &[],
+        environment: &[],
+        credentials: false,
+        env_prefixes: &["AWS_", "S3_", "SQS_"],
+        url: "http://{host}:4566",
+        consumer_environment: &[
+            ("AWS_ENDPOINT_URL", "http://{host}:4566"),
+            ("AWS_ACCESS_KEY_ID", "test"),
+            ("AWS_SECRET_ACCESS_KEY", "test"),
+            ("AWS_DEFAULT_REGION", "us-east-1"),
+        ],
+    },
+];
+
+#[derive(Serialize)]
+struct DevComposeContext {
+    stand_ins: Vec<StandInContext>,
+    consumers: Vec<ConsumerContext>,
+    /// Dependencies without a stand-in, for a comment.
+    unsupported: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct StandInContext {
+    name: String,
+    image: &'static str,
+    ports: Vec<String>,
+    environment: Vec<EnvContext>,
+}
+
+#[derive(Serialize)]
+struct ConsumerContext {
+    id: String,
+    environment: Vec<EnvContext>,
+    depends_on: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct EnvContext {
+    name: String,
+    /// Value as a quoted YAML scalar.
+    value: String,
+}
+
+impl EnvContext {
+    fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: serde_json::to_string(value).unwrap_or_default(),
+        }
+    }
+}
+
+/// A stand-in service replacing one external endpoint.
+struct Instance<'a> {
+    service: String,
+    stand_in: &'static StandIn,
+    host: String,
+    dependency: &'a DependencyInfo,
+}
+
+/// The stand-in for a dependency: one of its type matching the endpoint or
+/// port, else the default of its type.
+pub fn stand_in(dependency: &DependencyInfo) -> Option<&'static StandIn> {
+    let endpoint = dependency.endpoint.to_lowercase();
+    let mut candidates = STAND_INS
+        .iter()
+        .filter(|s| s.dep_type == dependency.dep_type);
+    let default = candidates.clone().next();
+    candidates
+        .find(|s| {
+            s.markers.iter().any(|m| endpoint.contains(m))
+                || dependency.port.is_some_and(|p| s.ports.contains(&p))
+        })
+        .or(default)
+}
+
+/// Generate `docker-compose.dev.yaml`, or nothing when the plan has no
+/// external dependencies.
+pub fn generate_dev_compose(engine: &TemplateEngine, plan: &PackPlan) -> Result<Option<String>> {
+    if plan.external_dependencies.is_empty() {
+        return Ok(None);
+    }
+
+    let mut instances: Vec<Instance<'_>> = Vec::new();
+    let mut unsupported = Vec::new();
+    for dependency in &plan.external_dependencies {
+        let (Some(stand_in), Some(host)) =
+            (stand_in(dependency), endpoint_host(&dependency.endpoint))
+        else {
+            unsupported.push(format!(
+                "{} ({} {})",
+                dependency.id, dependency.dep_type, dependency.endpoint
+            ));
+            continue;
+        };
+        // Endpoints of the same host share a stand-in
+        let shared = instances
+            .iter()
+            .find(|i| std::ptr::eq(i.stand_in, stand_in) && i.host == host)
+            .map(|i| i.service.clone());
+        let service = shared.unwrap_or_else(|| {
+            let mut service = stand_in.name.to_string();
+            let mut n = 1;
+            while instances.iter().any(|i| i.service == service)
+                || plan.clusters.iter().any(|c| c.id == service)
+            {
+                n += 1;
+                service = format!("{}-{}", stand_in.name, n);
+            }
+            service
+        });
+        instances.push(Instance {
+            service,
+            stand_in,
+            host,
+            dependency,
+        });
+    }
+
+    let mut stand_ins: Vec<StandInContext> = Vec::new();
+    for instance in &instances {
+        if stand_ins.iter().any(|s| s.name == instance.service) {
+            continue;
+        }
+        stand_ins.push(StandInContext {
+            name: instance.service.clone(),
+            image: instance.stand_in.image,
+            ports: instance
+                .stand_in
+                .published
+                .iter()
+                .map(|p| format!("{}:{}", p, p))
+                .collect(),
+            environment: instance
+                .stand_in
+                .environment
+                .iter()
+                .map(|(name, value)| EnvContext::new(name, value))
+                .collect(),
+        });
+    }
+
+    let consumers = plan
+        .clusters
+        .iter()
+        .filter_map(|cluster| {
+            let used: Vec<&Instance<'_>> = instances
+                .iter()
+                .filter(|i| i.dependency.used_by.contains(&cluster.id))
+                .collect();
+            if used.is_empty() {
+                return None;
+            }
+            let mut depends_on: Vec<String> = Vec::new();
+            for instance in &used {
+                if !depends_on.contains(&instance.service) {
+                    depends_on.push(instance.service.clone());
+                }
+            }
+            Some(ConsumerContext {
+                id: cluster.id.clone(),
+                environment: consumer_environment(cluster, &used)
+                    .iter()
+                    .map(|(name, value)| EnvContext::new(name, value))
+                    .collect(),
+                depends_on,
+            })
+        })
+        .collect();
+
+    let context = DevComposeContext {
+        stand_ins,
+        consumers,
+        unsupported,
+    };
+    engine.render(templates::DEV_COMPOSE, &context).map(Some)
+}
+
+/// Variables of a service pointing it at its stand-ins. Values mentioning
+/// the original host get the stand-in instead; variables named after the
+/// kind of dependency (`DB_HOST`, `REDIS_URL`, `SMTP_PORT`) get the
+/// stand-in's address and `dev` credentials.
+fn consumer_environment(cluster: &AppCluster, used: &[&Instance<'_>]) -> BTreeMap<String, String> {
+    let mut environment = BTreeMap::new();
+    for instance in used {
+        for (name, value) in instance.stand_in.consumer_environment {
+            environment.insert(name.to_string(), value.replace("{host}", &instance.service));
+        }
+    }
+
+    for env_var in &cluster.env_vars {
+        let replaced = env_var.default_value.as_deref().and_then(|value| {
+            used.iter().find_map(|i| {
+                value.contains(&i.host).then(|| {
+                    let address = format!("{}:{}", i.service, i.stand_in.port);
+                    match i.dependency.port {
+                        Some(port) => value
+                            .replace(&format!("{}:{}", i.host, port), &address)
+                            .replace(&i.host, &i.service),
+                        None => value.replace(&i.host, &i.service),
+                    }
+                })
+            })
+        });
+        let value = replaced.or_else(|| {
+            let name = env_var.name.to_uppercase();
+            used.iter().find_map(|i| {
+                let prefix = i
+                    .stand_in
+                    .env_prefixes
+                    .iter()
+                    .find(|p| name.starts_with(*p))?;
+                setting_value(i, &name[prefix.len()..])
+            })
+        });
+        if let Some(value) = value {
+            environment.insert(env_var.name.clone(), value);
+        }
+    }
+    environment
+}
+
+/// Value for a setting named `setting` (`HOST`, `URL`, `PASSWORD`...) of a
+/// stand-in.
+fn setting_value(instance: &Instance<'_>, setting: &str) -> Option<String> {
+    let ends_with = |suffixes: &[&str]| suffixes.iter().any(|s| setting.ends_with(s));
+    let stand_in = instance.stand_in;
+    if ends_with(&["URL", "URI", "DSN"]) {
+        Some(stand_in.url.replace("{host}", &instance.service))
+    } else if ends_with(&["HOST", "HOSTNAME", "SERVER", "ADDR", "ADDRESS"]) {
+        Some(instance.service.clone())
+    } else if ends_with(&["PORT"]) {
+        Some(stand_in.port.to_string())
+    } else if !stand_in.credentials {
+        None
+    } else if ends_with(&[
+        "USER", "USERNAME", "PASSWORD", "PASS", "NAME", "DATABASE", "DB",
+    ]) {
+        Some(DEV_CREDENTIAL.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::EnvVarSpec;
+
+    fn dependency(id: &str, dep_type: &str, endpoint: &str, port: Option<u16>) -> DependencyInfo {
+        DependencyInfo {
+            id: id.to_string(),
+            dep_type: dep_type.to_string(),
+            endpoint: endpoint.to_string(),
+            port,
+            used_by: vec!["app-0".to_string()],
+            evidence_refs: vec![],
+            addresses: Vec::new(),
+            internal: None,
+        }
+    }
+
+    #[test]
+    fn test_dev_compose_wires_stand_ins() {
+        let env_var = |name: &str, default_value: Option<&str>, required, sensitive| EnvVarSpec {
+            name: name.to_string(),
+            required,
+            default_value: default_value.map(str::to_string),
+            sensitive,
+            ..Default::default()
+        };
+        let cluster = AppCluster {
+            id: "app-0".to_string(),
+            name: "billing".to_string(),
+            app_type: "api".to_string(),
+            env_vars: vec![
+                env_var(
+                    "DATABASE_URL",
+                    Some("postgres://billing@db.internal:5432/billing"),
+                    true,
+                    false,
+                ),
+                env_var("DB_PASSWORD", None, true, true),
+                env_var("SMTP_HOST", None, true, false),
+                env_var("SMTP_PORT", Some("587"), true, false),
+                env_var("LOG_LEVEL", Some("info"), false, false),
+            ],
+            confidence: 0.9,
+            ..Default::default()
+        };
+        let plan = PackPlan {
+            schema_version: "1.0.0".to_string(),
+            generated_at: chrono::Utc::now(),
+            source_bundle_id: "test".to_string(),
+            source_os: None,
+            clusters: vec![cluster],
+            external_dependencies: vec![
+                dependency(
+                    "ext-0",
+                    "database",
+                    "postgres://billing@db.internal:5432/billing",
+                    Some(5432),
+                ),
+                dependency("ext-1", "database", "db.internal", None),
+                dependency("ext-2", "smtp", "mail.example.com", None),
+                dependency("ext-3", "api", "https://payments.example.com", Some(443)),
+            ],
+            startup_dag: vec![],
+            artifacts: vec![],
+            overall_confidence: 0.9,
+            warnings: vec![],
+            rejected_clusters: vec![],
+        };
+
+        let compose = generate_dev_compose(&TemplateEngine::new().unwrap(), &plan)
+            .unwrap()
+            .unwrap();
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&compose).unwrap();
+        let services = yaml["services"].as_mapping().unwrap();
+        // Both endpoints of db.internal share one stand-in; the API has none
+        assert_eq!(services.len(), 3);
+        assert_eq!(
+            yaml["services"]["postgres"]["image"].as_str(),
+            Some("postgres:16-alpine")
+        );
+        assert_eq!(
+            yaml["services"]["mailhog"]["ports"][0].as_str(),
+            Some("8025:8025")
+        );
+        let env = &yaml["services"]["app-0"]["environment"];
+        assert_eq!(
+            env["DATABASE_URL"].as_str(),
+            Some("postgres://billing@postgres:5432/billing")
+        );
+        assert_eq!(env["DB_PASSWORD"].as_str(), Some("dev"));
+        assert_eq!(env["SMTP_HOST"].as_str(), Some("mailhog"));
+        assert_eq!(env["SMTP_PORT"].as_str(), Some("1025"));
+        assert!(env["LOG_LEVEL"].is_null());
+        let depends_on = yaml["services"]["app-0"]["depends_on"]
+            .as_mapping()
+            .unwrap();
+        assert_eq!(depends_on.len(), 2);
+        assert!(compose.contains("ext-3 (api https://payments.example.com)"));
+    }
+}
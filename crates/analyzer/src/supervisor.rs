@@ -0,0 +1,274 @@
+//! Multi-process clusters.
+//!
+//! A container runs one command, but a cluster can group programs started
+//! separately on the source host: services merged on a shared working
+//! directory (nginx and php-fpm), or a sidecar script next to the
+//! application. Such clusters run their programs under supervisord, and a
+//! warning suggests splitting them into one compose service each.
+
+use crate::clustering;
+use crate::index::ManifestIndex;
+use xcprobe_bundle_schema::{AnalysisWarning, AppCluster, Decision, SupervisedProgram};
+
+/// Confidence of running a cluster's programs under a supervisor.
+const SUPERVISOR_CONFIDENCE: f64 = 0.7;
+
+/// Record the programs of clusters grouping several programs started
+/// separately, with a warning for each such cluster. Database and batch
+/// clusters, and replicas of one templated unit, are left alone.
+pub fn detect_multi_process(
+    index: &ManifestIndex<'_>,
+    clusters: &mut [AppCluster],
+) -> Vec<AnalysisWarning> {
+    let mut warnings = Vec::new();
+    for cluster in clusters.iter_mut() {
+        if cluster.database.is_some() || cluster.schedule.is_some() || cluster.replicas.is_some() {
+            continue;
+        }
+        let programs = cluster_programs(index, cluster);
+        if programs.len() < 2 {
+            continue;
+        }
+
+        let names: Vec<&str> = programs.iter().map(|p| p.name.as_str()).collect();
+        cluster.decisions.push(Decision::new(
+            format!("Run {} under supervisord", names.join(", ")),
+            format!(
+                "The cluster groups {} programs started separately on the source host, \
+                 and a container runs a single command",
+                programs.len()
+            ),
+            programs
+                .iter()
+                .filter_map(|p| p.evidence_ref.clone())
+                .collect(),
+            SUPERVISOR_CONFIDENCE,
+        ));
+        warnings.push(AnalysisWarning::new(
+            "multi_process_cluster",
+            "warning",
+            format!(
+                "Cluster {} ({}) runs {} programs ({}) under supervisord; \
+                 consider splitting them into one service each",
+                cluster.id,
+                cluster.name,
+                programs.len(),
+                names.join(", ")
+            ),
+            vec![cluster.id.clone()],
+        ));
+        cluster.programs = programs;
+    }
+    warnings
+}
+
+/// Programs a cluster starts: its services' commands, and its processes
+/// that are neither a service's process nor started by another process of
+/// the cluster. Identical command lines (workers) count once.
+fn cluster_programs(index: &ManifestIndex<'_>, cluster: &AppCluster) -> Vec<SupervisedProgram> {
+    let mut programs: Vec<SupervisedProgram> = Vec::new();
+    let mut service_pids: Vec<u32> = Vec::new();
+    for service in &cluster.services {
+        service_pids.extend(index.service(&service.name).and_then(|s| s.main_pid));
+        let Some(command) = service.exec_start.as_deref().filter(|c| !c.is_empty()) else {
+            continue;
+        };
+        if programs.iter().any(|p| p.command == command) {
+            continue;
+        }
+        programs.push(SupervisedProgram {
+            name: program_name(service.name.trim_end_matches(".service"), &programs),
+            command: command.to_string(),
+            user: service
+                .user
+                .as_deref()
+                .and_then(clustering::image_user)
+                .map(str::to_string),
+            directory: service.working_directory.clone(),
+            evidence_ref: service.evidence_ref.clone(),
+        });
+    }
+
+    let pids: Vec<u32> = cluster.processes.iter().map(|p| p.pid).collect();
+    for process in &cluster.processes {
+        if service_pids.contains(&process.pid)
+            || has_ancestor(index, process.pid, &pids)
+            || has_ancestor(index, process.pid, &service_pids)
+        {
+            continue;
+        }
+        let command = std::iter::once(process.command.as_str())
+            .chain(process.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if programs.iter().any(|p| p.command == command) {
+            continue;
+        }
+        let executable = process.command.rsplit('/').next().unwrap_or_default();
+        programs.push(SupervisedProgram {
+            name: program_name(executable, &programs),
+            command,
+            user: clustering::image_user(&process.user).map(str::to_string),
+            directory: process.working_directory.clone(),
+            evidence_ref: process.evidence_ref.clone(),
+        });
+    }
+    programs
+}
+
+/// Whether a process descends from one of `pids`.
+fn has_ancestor(index: &ManifestIndex<'_>, pid: u32, pids: &[u32]) -> bool {
+    let mut current = pid;
+    // Bounded, in case of a ppid cycle in a malformed manifest
+    for _ in 0..64 {
+        let Some(parent) = index.process(current).map(|p| p.ppid) else {
+            return false;
+        };
+        if parent == 0 || parent == current {
+            return false;
+        }
+        if pids.contains(&parent) {
+            return true;
+        }
+        current = parent;
+    }
+    false
+}
+
+/// A supervisor program name for `base`: letters, digits, `-` and `_`,
+/// suffixed to differ from the names already taken.
+fn program_name(base: &str, programs: &[SupervisedProgram]) -> String {
+    let name: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = match name.trim_matches('-') {
+        "" => "program".to_string(),
+        trimmed => trimmed.to_string(),
+    };
+    let taken = |candidate: &str| programs.iter().any(|p| p.name == candidate);
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|candidate| !taken(candidate))
+        .expect("unbounded suffixes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{ClusterProcess, ClusterService, Manifest};
+
+    fn manifest() -> Manifest {
+        let processes = serde_json::from_value(serde_json::json!([
+            { "pid": 10, "ppid": 1, "user": "root", "command": "/usr/sbin/nginx",
+              "args": ["-g", "daemon off;"], "full_cmdline": "nginx" },
+            { "pid": 11, "ppid": 10, "user": "www-data", "command": "/usr/sbin/nginx",
+              "args": [], "full_cmdline": "nginx: worker process" },
+            { "pid": 20, "ppid": 1, "user": "www-data", "command": "/usr/sbin/php-fpm8.2",
+              "args": ["--nodaemonize"], "full_cmdline": "php-fpm: master process" },
+            { "pid": 30, "ppid": 1, "user": "app", "command": "/bin/sh",
+              "args": ["/var/www/sync.sh"], "full_cmdline": "/bin/sh /var/www/sync.sh" }
+        ]))
+        .unwrap();
+        let services = serde_json::from_value(serde_json::json!([
+            { "name": "nginx.service", "state": "running", "main_pid": 10,
+              "exec_start": "/usr/sbin/nginx -g 'daemon off;'",
+              "exec_start_pre": [], "exec_start_post": [], "environment": {},
+              "environment_files": [], "dependencies": [], "wanted_by": [] },
+            { "name": "php8.2-fpm.service", "state": "running", "main_pid": 20,
+              "exec_start": "/usr/sbin/php-fpm8.2 --nodaemonize", "user": "www-data",
+              "working_directory": "/var/www",
+              "exec_start_pre": [], "exec_start_post": [], "environment": {},
+              "environment_files": [], "dependencies": [], "wanted_by": [] }
+        ]))
+        .unwrap();
+        Manifest {
+            processes,
+            services,
+            ..Default::default()
+        }
+    }
+
+    fn cluster(services: &[&str], pids: &[u32]) -> AppCluster {
+        let manifest = manifest();
+        AppCluster {
+            id: "app-0".to_string(),
+            name: "web".to_string(),
+            app_type: "web".to_string(),
+            processes: manifest
+                .processes
+                .iter()
+                .filter(|p| pids.contains(&p.pid))
+                .map(|p| ClusterProcess {
+                    pid: p.pid,
+                    command: p.command.clone(),
+                    args: p.args.clone(),
+                    user: p.user.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            services: manifest
+                .services
+                .iter()
+                .filter(|s| services.contains(&s.name.as_str()))
+                .map(|s| ClusterService {
+                    name: s.name.clone(),
+                    exec_start: s.exec_start.clone(),
+                    user: s.user.clone(),
+                    working_directory: s.working_directory.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            confidence: 0.9,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merged_services_and_sidecar_are_supervised() {
+        let manifest = manifest();
+        let index = ManifestIndex::new(&manifest);
+        let mut clusters = vec![
+            cluster(&["nginx.service", "php8.2-fpm.service"], &[10, 11, 20, 30]),
+            // A service and the workers it starts are one program
+            cluster(&["nginx.service"], &[10, 11]),
+        ];
+
+        let warnings = detect_multi_process(&index, &mut clusters);
+
+        let programs: Vec<(&str, &str, Option<&str>)> = clusters[0]
+            .programs
+            .iter()
+            .map(|p| (p.name.as_str(), p.command.as_str(), p.user.as_deref()))
+            .collect();
+        assert_eq!(
+            programs,
+            vec![
+                ("nginx", "/usr/sbin/nginx -g 'daemon off;'", None),
+                (
+                    "php8-2-fpm",
+                    "/usr/sbin/php-fpm8.2 --nodaemonize",
+                    Some("www-data")
+                ),
+                ("sh", "/bin/sh /var/www/sync.sh", Some("app")),
+            ]
+        );
+        assert!(clusters[0]
+            .decisions
+            .iter()
+            .any(|d| d.decision == "Run nginx, php8-2-fpm, sh under supervisord"));
+        assert!(clusters[1].programs.is_empty());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "multi_process_cluster");
+        assert_eq!(warnings[0].affected_clusters, vec!["app-0"]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Template engine for generated artifacts.
+//!
+//! Every generated text file (Dockerfile, entrypoint, supervisord
+//! configuration, config templates, README, database migration guide,
+//! docker-compose, the Windows compatibility report and the migration
+//! report) is rendered from a Handlebars template. Defaults
+//! are embedded in the binary; a templates directory can override any of them
+//! by providing a file with the same name (e.g. `Dockerfile.hbs`). Any other
+//! `.hbs` file in that directory is registered as a partial.
+
+use anyhow::{Context, Result};
+use handlebars::{handlebars_helper, Handlebars};
+use serde::Serialize;
+use std::path::Path;
+use tracing::debug;
+
+/// Dockerfile template name.
+pub const DOCKERFILE: &str = "Dockerfile";
+/// Windows container Dockerfile template name.
+pub const DOCKERFILE_WINDOWS: &str = "Dockerfile.windows";
+/// Entrypoint script template name.
+pub const ENTRYPOINT: &str = "entrypoint.sh";
+/// Windows container entrypoint script template name.
+pub const ENTRYPOINT_WINDOWS: &str = "entrypoint.ps1";
+/// supervisord configuration template name, for multi-process clusters.
+pub const SUPERVISORD: &str = "supervisord.conf";
+/// Config file template name.
+pub const CONFIG_TEMPLATE: &str = "config.tmpl";
+/// README template name.
+pub const README: &str = "README.md";
+/// docker-compose template name.
+pub const COMPOSE: &str = "docker-compose.yaml";
+/// Development compose override template name.
+pub const DEV_COMPOSE: &str = "docker-compose.dev.yaml";
+/// Database migration guide template name.
+pub const MIGRATION: &str = "MIGRATION.md";
+/// Windows-to-Linux compatibility report template name.
+pub const COMPATIBILITY: &str = "COMPATIBILITY.md";
+/// Markdown migration report template name.
+pub const REPORT_MARKDOWN: &str = "report.md";
+/// HTML migration report template name.
+pub const REPORT_HTML: &str = "report.html";
+
+/// Embedded default templates.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    (DOCKERFILE, include_str!("../templates/Dockerfile.hbs")),
+    (
+        DOCKERFILE_WINDOWS,
+        include_str!("../templates/Dockerfile.windows.hbs"),
+    ),
+    (ENTRYPOINT, include_str!("../templates/entrypoint.sh.hbs")),
+    (
+        ENTRYPOINT_WINDOWS,
+        include_str!("../templates/entrypoint.ps1.hbs"),
+    ),
+    (
+        SUPERVISORD,
+        include_str!("../templates/supervisord.conf.hbs"),
+    ),
+    (
+        CONFIG_TEMPLATE,
+        include_str!("../templates/config.tmpl.hbs"),
+    ),
+    (README, include_str!("../templates/README.md.hbs")),
+    (
+        COMPOSE,
+        include_str!("../templates/docker-compose.yaml.hbs"),
+    ),
+    (
+        DEV_COMPOSE,
+        include_str!("../templates/docker-compose.dev.yaml.hbs"),
+    ),
+    (MIGRATION, include_str!("../templates/MIGRATION.md.hbs")),
+    (
+        COMPATIBILITY,
+        include_str!("../templates/COMPATIBILITY.md.hbs"),
+    ),
+    (REPORT_MARKDOWN, include_str!("../templates/report.md.hbs")),
+    (REPORT_HTML, include_str!("../templates/report.html.hbs")),
+];
+
+/// File extension of template files in an override directory.
+const TEMPLATE_EXTENSION: &str = "hbs";
+
+// Render a shell-style variable reference: {{env_ref "PORT"}} -> ${PORT}
+handlebars_helper!(env_ref: |name: str| format!("${{{}}}", name));
+
+// Escape text for the HTML report: {{html name}}
+handlebars_helper!(html: |text: str| handlebars::html_escape(text));
+
+/// Renders artifacts from embedded or user-provided templates.
+pub struct TemplateEngine {
+    registry: Handlebars<'static>,
+}
+
+impl TemplateEngine {
+    /// Create an engine with the embedded default templates only.
+    pub fn new() -> Result<Self> {
+        let mut registry = Handlebars::new();
+        // Output is HTML only for the report, which escapes with `html`
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_helper("env_ref", Box::new(env_ref));
+        registry.register_helper("html", Box::new(html));
+
+        for (name, source) in DEFAULT_TEMPLATES {
+            registry
+                .register_template_string(name, *source)
+                .with_context(|| format!("Invalid built-in template: {}", name))?;
+        }
+
+        Ok(Self { registry })
+    }
+
+    /// Create an engine, applying overrides from `templates_dir` if given.
+    pub fn with_overrides(templates_dir: Option<&Path>) -> Result<Self> {
+        let mut engine = Self::new()?;
+        if let Some(dir) = templates_dir {
+            engine.load_dir(dir)?;
+        }
+        Ok(engine)
+    }
+
+    /// Load `.hbs` files from a directory. Files named after a built-in
+    /// template replace it; all others are registered as partials.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<()> {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read templates directory {}", dir.display()))?;
+
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_file()
+                || path.extension().and_then(|e| e.to_str()) != Some(TEMPLATE_EXTENSION)
+            {
+                continue;
+            }
+
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read template {}", path.display()))?;
+
+            if DEFAULT_TEMPLATES.iter().any(|(n, _)| *n == name) {
+                self.registry
+                    .register_template_string(&name, source)
+                    .with_context(|| format!("Invalid template {}", path.display()))?;
+                debug!("Overriding template: {}", name);
+            } else {
+                self.registry
+                    .register_partial(&name, source)
+                    .with_context(|| format!("Invalid partial {}", path.display()))?;
+                debug!("Registered partial: {}", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Render a named template with the given context.
+    pub fn render<T: Serialize>(&self, name: &str, context: &T) -> Result<String> {
+        self.registry
+            .render(name, context)
+            .with_context(|| format!("Failed to render template {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_builtin_templates_compile() {
+        let engine = TemplateEngine::new().unwrap();
+        let out = engine
+            .render(
+                CONFIG_TEMPLATE,
+                &json!({"source_path": "/etc/app.conf", "template_vars": ["PORT"]}),
+            )
+            .unwrap();
+        assert!(out.contains("#   ${PORT}"));
+        assert!(out.contains("# PORT=${PORT}"));
+    }
+
+    #[test]
+    fn test_override_and_partials() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Dockerfile.hbs"),
+            "FROM {{base_image}}\n{{> extra}}",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("extra.hbs"), "RUN echo {{name}}\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let engine = TemplateEngine::with_overrides(Some(dir.path())).unwrap();
+        let out = engine
+            .render(DOCKERFILE, &json!({"base_image": "alpine", "name": "app"}))
+            .unwrap();
+        assert_eq!(out, "FROM alpine\nRUN echo app\n");
+    }
+}
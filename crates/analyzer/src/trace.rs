@@ -0,0 +1,324 @@
+//! Decision tracing.
+//!
+//! Follows a decision of a pack plan back to the collection that supports
+//! it: the evidence it cites, the audit entry of the command that produced
+//! each piece of evidence (command, timestamps, exit code), and an excerpt
+//! of the captured output.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use xcprobe_bundle_schema::{AuditEntry, Bundle, Decision, PackPlan};
+
+/// Lines of captured output shown per piece of evidence.
+const EXCERPT_LINES: usize = 15;
+
+/// A decision with the collection behind it.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionTrace {
+    /// Cluster (or rejected cluster) holding the decision.
+    pub cluster_id: String,
+    /// Position of the decision in the cluster's `decisions`.
+    pub index: usize,
+    pub decision: Decision,
+    pub evidence: Vec<EvidenceTrace>,
+}
+
+/// A piece of evidence cited by a decision.
+#[derive(Debug, Clone, Serialize)]
+pub struct EvidenceTrace {
+    pub evidence_ref: String,
+    /// The command that produced it, when the bundle's audit log has it.
+    pub audit: Option<AuditEntry>,
+    /// First lines of the captured output.
+    pub excerpt: Option<String>,
+}
+
+/// Record in every decision of the plan the audit entries of the commands
+/// that produced its evidence.
+pub fn link_audit_entries(bundle: &Bundle, plan: &mut PackPlan) {
+    let seqs: HashMap<&str, u64> = bundle
+        .audit
+        .iter()
+        .map(|e| (e.evidence_ref.as_str(), e.seq))
+        .collect();
+    let decisions = plan
+        .clusters
+        .iter_mut()
+        .flat_map(|c| c.decisions.iter_mut())
+        .chain(
+            plan.rejected_clusters
+                .iter_mut()
+                .flat_map(|c| c.decisions.iter_mut()),
+        );
+    for decision in decisions {
+        decision.audit_seqs = decision
+            .evidence_refs
+            .iter()
+            .filter_map(|r| seqs.get(r.as_str()).copied())
+            .collect();
+    }
+}
+
+/// Trace the decisions selected by `selector`: an index into the
+/// decisions of a cluster, or text found in the decision (case
+/// insensitive). An index needs `cluster` unless the plan has a single
+/// cluster.
+pub fn trace_decision(
+    plan: &PackPlan,
+    bundle: &Bundle,
+    selector: &str,
+    cluster: Option<&str>,
+) -> Result<Vec<DecisionTrace>> {
+    let clusters: Vec<(&str, &[Decision])> = plan
+        .clusters
+        .iter()
+        .map(|c| (c.id.as_str(), c.decisions.as_slice()))
+        .chain(
+            plan.rejected_clusters
+                .iter()
+                .map(|c| (c.id.as_str(), c.decisions.as_slice())),
+        )
+        .filter(|(id, _)| cluster.is_none_or(|c| c == *id))
+        .collect();
+    if let Some(cluster) = cluster {
+        if clusters.is_empty() {
+            bail!("No cluster {} in the pack plan", cluster);
+        }
+    }
+
+    let selected: Vec<(&str, usize, &Decision)> = match selector.parse::<usize>() {
+        Ok(index) => {
+            let [(id, decisions)] = clusters.as_slice() else {
+                bail!(
+                    "Decision index {} is ambiguous: choose a cluster with --cluster",
+                    index
+                );
+            };
+            match decisions.get(index) {
+                Some(decision) => vec![(*id, index, decision)],
+                None => bail!("{} has {} decisions, no #{}", id, decisions.len(), index),
+            }
+        }
+        Err(_) => {
+            let needle = selector.to_lowercase();
+            clusters
+                .iter()
+                .flat_map(|(id, decisions)| {
+                    decisions
+                        .iter()
+                        .enumerate()
+                        .map(move |(index, decision)| (*id, index, decision))
+                })
+                .filter(|(_, _, d)| d.decision.to_lowercase().contains(&needle))
+                .collect()
+        }
+    };
+    if selected.is_empty() {
+        bail!("No decision matches \"{}\"", selector);
+    }
+
+    Ok(selected
+        .into_iter()
+        .map(|(cluster_id, index, decision)| DecisionTrace {
+            cluster_id: cluster_id.to_string(),
+            index,
+            decision: decision.clone(),
+            evidence: decision
+                .evidence_refs
+                .iter()
+                .map(|r| EvidenceTrace {
+                    evidence_ref: r.clone(),
+                    audit: bundle.audit_entry(r).cloned(),
+                    excerpt: bundle
+                        .evidence
+                        .get(r)
+                        .and_then(|e| e.content.as_deref())
+                        .map(excerpt),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// The first lines of the standard output captured in an evidence file,
+/// or of the file itself for attachments.
+fn excerpt(content: &[u8]) -> String {
+    let content = String::from_utf8_lossy(content);
+    let stdout = match content.strip_prefix("=== STDOUT ===\n") {
+        Some(rest) => rest.split("\n\n=== STDERR ===").next().unwrap_or(rest),
+        None => &content,
+    };
+    let lines: Vec<&str> = stdout.lines().collect();
+    let mut excerpt = lines
+        .iter()
+        .take(EXCERPT_LINES)
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if lines.len() > EXCERPT_LINES {
+        let _ = write!(
+            excerpt,
+            "\n... ({} more lines)",
+            lines.len() - EXCERPT_LINES
+        );
+    }
+    excerpt
+}
+
+/// Render traces for a terminal.
+pub fn render_text(traces: &[DecisionTrace]) -> String {
+    let mut out = String::new();
+    for trace in traces {
+        let decision = &trace.decision;
+        let _ = writeln!(
+            out,
+            "{} decision #{}: {}",
+            trace.cluster_id, trace.index, decision.decision
+        );
+        let _ = writeln!(out, "  Reason: {}", decision.reason);
+        let _ = writeln!(out, "  Confidence: {:.0}%", decision.confidence * 100.0);
+        if trace.evidence.is_empty() {
+            let _ = writeln!(out, "  No evidence cited");
+        }
+        for evidence in &trace.evidence {
+            let _ = writeln!(out, "  Evidence: {}", evidence.evidence_ref);
+            match &evidence.audit {
+                Some(audit) => {
+                    let _ = writeln!(out, "    Command: {}", audit.command);
+                    let _ = writeln!(
+                        out,
+                        "    Ran: {} ({} ms), exit code {}, audit entry #{}",
+                        audit.started_at.to_rfc3339(),
+                        audit.duration_ms,
+                        audit
+                            .exit_code
+                            .map(|c| c.to_string())
+                            .unwrap_or_else(|| "none".to_string()),
+                        audit.seq
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "    Not in the audit log");
+                }
+            }
+            match &evidence.excerpt {
+                Some(excerpt) => {
+                    let _ = writeln!(out, "    Output:");
+                    for line in excerpt.lines() {
+                        let _ = writeln!(out, "      | {}", line);
+                    }
+                }
+                None => {
+                    let _ = writeln!(out, "    Not in the bundle");
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use xcprobe_bundle_schema::{AppCluster, Evidence, Manifest};
+
+    fn bundle() -> Bundle {
+        let audit = AuditEntry::new(
+            0,
+            "cat /etc/app/app.conf".to_string(),
+            "config".to_string(),
+            Utc::now(),
+            Utc::now(),
+            Some(0),
+            12,
+            0,
+            "evidence/config_1.txt".to_string(),
+            None,
+        );
+        let output = format!(
+            "=== STDOUT ===\n{}\n\n=== STDERR ===\n",
+            (1..=20)
+                .map(|n| format!("line {}", n))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        Bundle {
+            manifest: Manifest::default(),
+            audit: vec![audit],
+            evidence: HashMap::from([(
+                "evidence/config_1.txt".to_string(),
+                Evidence::from_command_output(
+                    "config_1",
+                    "cat /etc/app/app.conf",
+                    output.into_bytes(),
+                    "evidence/config_1.txt",
+                ),
+            )]),
+            checksums: HashMap::new(),
+        }
+    }
+
+    fn plan() -> PackPlan {
+        PackPlan {
+            source_bundle_id: "bundle-1".to_string(),
+            clusters: vec![AppCluster {
+                id: "app-0".to_string(),
+                name: "billing".to_string(),
+                app_type: "api".to_string(),
+                confidence: 0.85,
+                decisions: vec![
+                    Decision::new("Detect runtime node", "Executable name", vec![], 0.5),
+                    Decision::new(
+                        "Include config file /etc/app/app.conf",
+                        "Opened by the process",
+                        vec!["evidence/config_1.txt".to_string()],
+                        0.8,
+                    ),
+                ],
+                ..Default::default()
+            }],
+            overall_confidence: 0.85,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_trace_decision_to_command() {
+        let bundle = bundle();
+        let mut plan = plan();
+        link_audit_entries(&bundle, &mut plan);
+        assert_eq!(plan.clusters[0].decisions[1].audit_seqs, vec![0]);
+
+        let traces = trace_decision(&plan, &bundle, "CONFIG FILE", None).unwrap();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].index, 1);
+        let evidence = &traces[0].evidence[0];
+        assert_eq!(
+            evidence.audit.as_ref().map(|a| a.command.as_str()),
+            Some("cat /etc/app/app.conf")
+        );
+        let excerpt = evidence.excerpt.as_deref().unwrap();
+        assert!(excerpt.starts_with("line 1\n"));
+        assert!(excerpt.ends_with("line 15\n... (5 more lines)"));
+
+        let text = render_text(&traces);
+        assert!(text.contains("app-0 decision #1: Include config file /etc/app/app.conf"));
+        assert!(text.contains("    Command: cat /etc/app/app.conf"));
+        assert!(text.contains("      | line 15\n"));
+    }
+
+    #[test]
+    fn test_trace_decision_by_index() {
+        let traces = trace_decision(&plan(), &bundle(), "0", Some("app-0")).unwrap();
+        assert_eq!(traces[0].decision.decision, "Detect runtime node");
+        assert!(render_text(&traces).contains("  No evidence cited"));
+
+        assert!(trace_decision(&plan(), &bundle(), "7", None).is_err());
+        assert!(trace_decision(&plan(), &bundle(), "0", Some("app-9")).is_err());
+        assert!(trace_decision(&plan(), &bundle(), "unknown", None).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Stateful directory detection.
+//!
+//! Database data directories and application upload/data directories are
+//! found in service command lines, environment variables and configuration
+//! files, and become named volumes so generated containers keep their data.
+
+use crate::readiness::cluster_configs;
+use regex::Regex;
+use std::sync::LazyLock;
+use xcprobe_bundle_schema::{AppCluster, Bundle, Decision, VolumeSpec};
+
+/// Data directory flags on database server command lines.
+static COMMAND_DATA_DIR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:(?:postgres|postmaster)\S*\s.*-D\s*|--datadir=|--dbpath[= ])(/\S+)").unwrap()
+});
+
+/// Data directory settings in database configuration files
+/// (postgresql.conf, my.cnf, mongod.conf).
+static CONFIG_DATA_DIR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^\s*(?:data_directory|datadir|dbPath)\s*[=:]\s*["']?(/[^\s"']+)"#).unwrap()
+});
+
+/// Upload and data directory settings of applications.
+static APP_DATA_DIR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r#"(?i)\b((?:upload|media|attachment|storage|data)s?)[_.-]?"#,
+        r#"(?:dir|directory|path|root|folder|location)["']?\s*[=:]\s*["']?(/[^\s"',;]+)"#
+    ))
+    .unwrap()
+});
+
+/// Default data directories of database servers, by executable name.
+const DATABASE_DEFAULTS: &[(&str, &str)] = &[
+    ("postgres", "/var/lib/postgresql/data"),
+    ("mysql", "/var/lib/mysql"),
+    ("mariadb", "/var/lib/mysql"),
+    ("mongod", "/data/db"),
+    ("redis", "/data"),
+];
+
+/// Detect stateful directories for each cluster.
+pub fn detect_volumes(bundle: &Bundle, clusters: &mut [AppCluster]) {
+    for cluster in clusters.iter_mut() {
+        let found = find_data_dirs(bundle, cluster);
+        for dir in found {
+            if cluster.volumes.iter().any(|v| v.container_path == dir.path) {
+                continue;
+            }
+            let name = volume_name(cluster, &dir.path);
+            cluster.decisions.push(Decision::new(
+                format!("Persist {} in volume {}", dir.path, name),
+                dir.reason,
+                dir.evidence_ref.iter().cloned().collect(),
+                dir.confidence,
+            ));
+            cluster.volumes.push(VolumeSpec {
+                name,
+                container_path: dir.path,
+                kind: dir.kind.to_string(),
+                evidence_ref: dir.evidence_ref,
+            });
+        }
+    }
+}
+
+/// A stateful directory and why it is considered stateful.
+struct DataDir {
+    path: String,
+    kind: &'static str,
+    reason: String,
+    evidence_ref: Option<String>,
+    confidence: f64,
+}
+
+fn find_data_dirs(bundle: &Bundle, cluster: &AppCluster) -> Vec<DataDir> {
+    let mut dirs = Vec::new();
+
+    for service in &cluster.services {
+        let Some(exec_start) = service.exec_start.as_deref() else {
+            continue;
+        };
+        if let Some(caps) = COMMAND_DATA_DIR.captures(exec_start) {
+            dirs.push(DataDir {
+                path: clean_path(&caps[1]),
+                kind: "database",
+                reason: format!(
+                    "Database data directory on the command line of {}",
+                    service.name
+                ),
+                evidence_ref: service.evidence_ref.clone(),
+                confidence: 0.9,
+            });
+        }
+        for (key, value) in &service.environment {
+            let setting = format!("{}={}", key, value);
+            if key == "PGDATA" && value.starts_with('/') {
+                dirs.push(DataDir {
+                    path: clean_path(value),
+                    kind: "database",
+                    reason: format!("PGDATA set in the environment of {}", service.name),
+                    evidence_ref: service.evidence_ref.clone(),
+                    confidence: 0.9,
+                });
+            } else if let Some(caps) = APP_DATA_DIR.captures(&setting) {
+                dirs.push(app_data_dir(
+                    &caps,
+                    format!("environment of {}", service.name),
+                    service.evidence_ref.clone(),
+                ));
+            }
+        }
+    }
+    for process in &cluster.processes {
+        let command_line = format!("{} {}", process.command, process.args.join(" "));
+        if let Some(caps) = COMMAND_DATA_DIR.captures(&command_line) {
+            dirs.push(DataDir {
+                path: clean_path(&caps[1]),
+                kind: "database",
+                reason: format!(
+                    "Database data directory on the command line of PID {}",
+                    process.pid
+                ),
+                evidence_ref: process.evidence_ref.clone(),
+                confidence: 0.9,
+            });
+        }
+    }
+
+    for config in cluster_configs(bundle, cluster) {
+        for caps in CONFIG_DATA_DIR.captures_iter(&config.content) {
+            dirs.push(DataDir {
+                path: clean_path(&caps[1]),
+                kind: "database",
+                reason: format!("Database data directory set in {}", config.path),
+                evidence_ref: Some(config.evidence_ref.to_string()),
+                confidence: 0.85,
+            });
+        }
+        for caps in APP_DATA_DIR.captures_iter(&config.content) {
+            dirs.push(app_data_dir(
+                &caps,
+                config.path.to_string(),
+                Some(config.evidence_ref.to_string()),
+            ));
+        }
+    }
+
+    // Databases without an explicit setting use their default directory
+    if cluster.app_type == "database" && !dirs.iter().any(|d| d.kind == "database") {
+        let exec = cluster
+            .services
+            .iter()
+            .filter_map(|s| s.exec_start.as_deref())
+            .chain(cluster.processes.iter().map(|p| p.command.as_str()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let default = DATABASE_DEFAULTS
+            .iter()
+            .find(|(name, _)| exec.contains(name) || cluster.name.contains(name));
+        if let Some((name, path)) = default {
+            dirs.push(DataDir {
+                path: path.to_string(),
+                kind: "database",
+                reason: format!("Default data directory of {}", name),
+                evidence_ref: cluster
+                    .services
+                    .first()
+                    .and_then(|s| s.evidence_ref.clone()),
+                confidence: 0.6,
+            });
+        }
+    }
+
+    dirs
+}
+
+fn app_data_dir(
+    caps: &regex::Captures<'_>,
+    source: String,
+    evidence_ref: Option<String>,
+) -> DataDir {
+    let key = caps[1].to_lowercase();
+    let kind = if key.starts_with("data") || key.starts_with("storage") {
+        "data"
+    } else {
+        "uploads"
+    };
+    DataDir {
+        path: clean_path(&caps[2]),
+        kind,
+        reason: format!("Application {} directory set in {}", kind, source),
+        evidence_ref,
+        confidence: 0.75,
+    }
+}
+
+fn clean_path(path: &str) -> String {
+    let path = path.trim_matches(|c| c == '"' || c == '\'');
+    match path.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Name a volume after its cluster and the last path component.
+pub(crate) fn volume_name(cluster: &AppCluster, path: &str) -> String {
+    let last = path
+        .rsplit('/')
+        .find(|c| !c.is_empty())
+        .unwrap_or("data")
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+    let base = format!("{}-{}", cluster.id, last);
+    let mut name = base.clone();
+    let mut n = 1;
+    while cluster.volumes.iter().any(|v| v.name == name) {
+        n += 1;
+        name = format!("{}-{}", base, n);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use xcprobe_bundle_schema::{ClusterService, ConfigFileSpec, Evidence};
+
+    fn cluster(app_type: &str, exec_start: &str) -> AppCluster {
+        AppCluster {
+            id: "app-0".to_string(),
+            name: "app".to_string(),
+            app_type: app_type.to_string(),
+            services: vec![ClusterService {
+                name: "app.service".to_string(),
+                exec_start: Some(exec_start.to_string()),
+                evidence_ref: Some("evidence/services_1.txt".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_database_data_directories() {
+        let bundle = Bundle {
+            manifest: Default::default(),
+            audit: vec![],
+            evidence: HashMap::new(),
+            checksums: HashMap::new(),
+        };
+        let mut clusters = vec![
+            cluster(
+                "database",
+                "/usr/lib/postgresql/15/bin/postgres -D /srv/pgdata/ -c config_file=/etc/postgresql/15/main/postgresql.conf",
+            ),
+            cluster("database", "/usr/sbin/mysqld"),
+            cluster("api", "/opt/app/server"),
+        ];
+
+        detect_volumes(&bundle, &mut clusters);
+        assert_eq!(clusters[0].volumes.len(), 1);
+        assert_eq!(clusters[0].volumes[0].container_path, "/srv/pgdata");
+        assert_eq!(clusters[0].volumes[0].name, "app-0-pgdata");
+        assert!(clusters[0].decisions[0].has_evidence());
+
+        assert_eq!(clusters[1].volumes[0].container_path, "/var/lib/mysql");
+        assert!(clusters[2].volumes.is_empty());
+    }
+
+    #[test]
+    fn test_app_upload_directories() {
+        let content = "UPLOAD_FOLDER = '/var/lib/app/uploads'\nmedia_root: /srv/media\nlog_dir=/var/log/app\n";
+        let bundle = Bundle {
+            manifest: Default::default(),
+            audit: vec![],
+            evidence: HashMap::from([(
+                "evidence/config_1.txt".to_string(),
+                Evidence::from_command_output(
+                    "config_1",
+                    "cat",
+                    content.as_bytes().to_vec(),
+                    "evidence/config_1.txt",
+                ),
+            )]),
+            checksums: HashMap::new(),
+        };
+        let mut app = cluster("api", "/usr/bin/python3 /opt/app/app.py");
+        app.services[0]
+            .environment
+            .insert("DATA_DIR".to_string(), "/var/lib/app/data".to_string());
+        app.config_files.push(ConfigFileSpec {
+            source_path: "/opt/app/settings.ini".to_string(),
+            container_path: "/opt/app/settings.ini".to_string(),
+            templated: false,
+            template_vars: vec![],
+            content: None,
+            evidence_ref: Some("evidence/config_1.txt".to_string()),
+        });
+        let mut clusters = vec![app];
+
+        detect_volumes(&bundle, &mut clusters);
+        let volumes: Vec<(&str, &str)> = clusters[0]
+            .volumes
+            .iter()
+            .map(|v| (v.container_path.as_str(), v.kind.as_str()))
+            .collect();
+        assert_eq!(
+            volumes,
+            vec![
+                ("/var/lib/app/data", "data"),
+                ("/var/lib/app/uploads", "uploads"),
+                ("/srv/media", "uploads"),
+            ]
+        );
+        assert_eq!(clusters[0].decisions.len(), 3);
+    }
+}
@@ -0,0 +1,282 @@
+//! Windows-to-Linux compatibility.
+//!
+//! Applications collected from a Windows host are generated as Windows
+//! containers by default. When a move to Linux is considered instead, each
+//! cluster is assessed: whether it runs on Linux as is (a cross-platform
+//! runtime, an official image), needs checking first (.NET applications may
+//! use Windows-only APIs) or has to be rebuilt for Linux (native Windows
+//! executables), with what changes on the way (registry settings, domain
+//! accounts, Windows paths).
+
+use crate::clustering;
+use crate::templates::{self, TemplateEngine};
+use anyhow::Result;
+use serde::Serialize;
+use xcprobe_bundle_schema::{AppCluster, PackPlan};
+
+/// File name of the compatibility report in the output directory.
+pub const COMPATIBILITY_FILE: &str = "COMPATIBILITY.md";
+
+/// Runtimes with official Linux images.
+const CROSS_PLATFORM_RUNTIMES: &[&str] = &["java", "node", "python", "ruby", "php"];
+
+/// How a cluster maps to Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compatibility {
+    /// Runs on Linux from a cross-platform runtime or an official image.
+    Linux,
+    /// Can run on Linux once checked for Windows-only dependencies.
+    Review,
+    /// A native Windows application: needs a Linux build.
+    Rewrite,
+}
+
+impl Compatibility {
+    fn label(self) -> &'static str {
+        match self {
+            Compatibility::Linux => "Runs on Linux",
+            Compatibility::Review => "Review before moving",
+            Compatibility::Rewrite => "Needs a Linux build",
+        }
+    }
+}
+
+/// The assessment of a cluster.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterCompatibility {
+    pub cluster_id: String,
+    pub name: String,
+    pub compatibility: Compatibility,
+    /// Linux image the cluster maps to.
+    pub linux_image: Option<String>,
+    /// Why, then what changes on Linux.
+    pub notes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CompatibilityContext<'a> {
+    source_bundle_id: &'a str,
+    clusters: Vec<CompatibilityClusterContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct CompatibilityClusterContext<'a> {
+    cluster_id: &'a str,
+    name: &'a str,
+    label: &'static str,
+    linux_image: Option<&'a str>,
+    notes: &'a [String],
+}
+
+/// Whether the plan was analyzed from a bundle of a Windows host.
+pub fn is_windows_plan(plan: &PackPlan) -> bool {
+    plan.source_os.as_deref() == Some("windows")
+}
+
+/// Assess how a cluster of a Windows host maps to Linux.
+pub fn assess_cluster(cluster: &AppCluster) -> ClusterCompatibility {
+    let official = cluster
+        .decisions
+        .iter()
+        .find_map(|d| d.reason.strip_prefix("Official image of "));
+    let executable = cluster
+        .services
+        .first()
+        .and_then(|s| s.exec_start.as_deref())
+        .unwrap_or(&cluster.name);
+
+    let (compatibility, linux_image, mut notes) = match (&cluster.database, official) {
+        (Some(database), _) => (
+            Compatibility::Linux,
+            Some(database.image.clone()),
+            vec![format!(
+                "{} database: runs from its official Linux image; move the data as described in {}/MIGRATION.md",
+                database.engine, cluster.id
+            )],
+        ),
+        (None, Some(application)) => (
+            Compatibility::Linux,
+            cluster.base_image.clone(),
+            vec![format!("{} has an official Linux image", application)],
+        ),
+        (None, None) => match cluster.runtime.as_ref().map(|r| r.language.as_str()) {
+            Some("dotnet") => (
+                Compatibility::Review,
+                cluster.base_image.clone(),
+                vec![
+                    ".NET Core and .NET 5 or later run on Linux; .NET Framework does not".to_string(),
+                    "Check for Windows-only APIs (registry, WMI, COM, System.Drawing, \
+                     Windows authentication) and for .NET Framework assemblies"
+                        .to_string(),
+                ],
+            ),
+            Some(language) if CROSS_PLATFORM_RUNTIMES.contains(&language) => (
+                Compatibility::Linux,
+                cluster.base_image.clone(),
+                vec![format!("Runs on the cross-platform {} runtime", language)],
+            ),
+            _ => (
+                Compatibility::Rewrite,
+                None,
+                vec![format!(
+                    "Native Windows executable ({}): needs a Linux build of the application, \
+                     or a Windows container",
+                    executable
+                )],
+            ),
+        },
+    };
+
+    let registry_settings = cluster
+        .env_vars
+        .iter()
+        .filter(|e| {
+            e.description
+                .as_deref()
+                .is_some_and(|d| d.starts_with("From registry value"))
+        })
+        .count();
+    if registry_settings > 0 {
+        notes.push(format!(
+            "{} settings read from the registry are passed as environment variables: \
+             the application must read them from the environment on Linux",
+            registry_settings
+        ));
+    }
+    for service in &cluster.services {
+        let Some(account) = service.user.as_deref() else {
+            continue;
+        };
+        if account.contains('\\')
+            && !account.starts_with(".\\")
+            && clustering::image_user(account).is_some()
+        {
+            notes.push(format!(
+                "{} runs as the domain account {}: Windows authentication to other \
+                 services needs a keytab or explicit credentials on Linux",
+                service.name, account
+            ));
+        }
+    }
+    let windows_paths = cluster
+        .config_files
+        .iter()
+        .filter(|c| c.container_path.contains('\\'))
+        .count();
+    if windows_paths > 0 {
+        notes.push(format!(
+            "{} configuration files are at Windows paths: choose Linux paths and \
+             update the paths they reference",
+            windows_paths
+        ));
+    }
+
+    ClusterCompatibility {
+        cluster_id: cluster.id.clone(),
+        name: cluster.name.clone(),
+        compatibility,
+        linux_image,
+        notes,
+    }
+}
+
+/// Render the compatibility report of the clusters of a plan.
+pub fn generate_compatibility_report(engine: &TemplateEngine, plan: &PackPlan) -> Result<String> {
+    let assessments: Vec<ClusterCompatibility> = plan.clusters.iter().map(assess_cluster).collect();
+    let context = CompatibilityContext {
+        source_bundle_id: &plan.source_bundle_id,
+        clusters: assessments
+            .iter()
+            .map(|a| CompatibilityClusterContext {
+                cluster_id: &a.cluster_id,
+                name: &a.name,
+                label: a.compatibility.label(),
+                linux_image: a.linux_image.as_deref(),
+                notes: &a.notes,
+            })
+            .collect(),
+    };
+    engine.render(templates::COMPATIBILITY, &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{ClusterService, EnvVarSpec, RuntimeInfo};
+
+    fn cluster(id: &str, runtime: Option<&str>, exec_start: &str, user: &str) -> AppCluster {
+        AppCluster {
+            id: id.to_string(),
+            name: id.to_string(),
+            app_type: "api".to_string(),
+            services: vec![ClusterService {
+                name: format!("{}-svc", id),
+                exec_start: Some(exec_start.to_string()),
+                user: Some(user.to_string()),
+                ..Default::default()
+            }],
+            env_vars: vec![EnvVarSpec {
+                name: "INSTALL_DIR".to_string(),
+                required: true,
+                default_value: Some("C:\\Program Files\\Contoso".to_string()),
+                description: Some(
+                    "From registry value HKLM\\SOFTWARE\\Contoso\\InstallDir".to_string(),
+                ),
+                ..Default::default()
+            }],
+            runtime: runtime.map(|language| RuntimeInfo {
+                language: language.to_string(),
+                version: None,
+                evidence_refs: vec![],
+            }),
+            base_image: runtime.map(|_| "node:20-alpine".to_string()),
+            confidence: 0.9,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_assess_windows_clusters() {
+        let node = assess_cluster(&cluster(
+            "app-0",
+            Some("node"),
+            "C:\\Program Files\\nodejs\\node.exe server.js",
+            "LocalSystem",
+        ));
+        assert_eq!(node.compatibility, Compatibility::Linux);
+        assert_eq!(node.linux_image.as_deref(), Some("node:20-alpine"));
+        assert!(node.notes[1].starts_with("1 settings read from the registry"));
+
+        let native = assess_cluster(&cluster(
+            "app-1",
+            None,
+            "C:\\Program Files\\Contoso\\billing.exe",
+            "CORP\\svc_billing",
+        ));
+        assert_eq!(native.compatibility, Compatibility::Rewrite);
+        assert!(native
+            .notes
+            .iter()
+            .any(|n| n.contains("domain account CORP\\svc_billing")));
+    }
+
+    #[test]
+    fn test_compatibility_report() {
+        let plan = PackPlan {
+            source_bundle_id: "bundle-1".to_string(),
+            source_os: Some("windows".to_string()),
+            clusters: vec![cluster(
+                "app-0",
+                Some("dotnet"),
+                "C:\\app\\api.exe",
+                "LocalSystem",
+            )],
+            ..Default::default()
+        };
+        assert!(is_windows_plan(&plan));
+        let report = generate_compatibility_report(&TemplateEngine::new().unwrap(), &plan).unwrap();
+        assert!(report.contains("| app-0 | app-0 | Review before moving | `node:20-alpine` |"));
+        assert!(report.contains("- Check for Windows-only APIs"));
+    }
+}
@@ -3,6 +3,20 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Whether a command of the audit log was run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    /// The command was run on the target.
+    #[default]
+    Executed,
+    /// The allowlist refused to build the command from a value found on
+    /// the target, such as an unsafe path or service name.
+    Denied,
+    /// The command was not run because the collection deadline passed.
+    Skipped,
+}
+
 /// A single audit log entry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditEntry {
@@ -30,6 +44,15 @@ pub struct AuditEntry {
     pub error: Option<String>,
     /// Category of the command (process, service, port, etc.).
     pub category: String,
+    /// Number of times the command was retried after a transient failure.
+    #[serde(default)]
+    pub retries: u32,
+    /// Errors of the failed attempts that were retried.
+    #[serde(default)]
+    pub retry_errors: Vec<String>,
+    /// Whether the command was run; `error` holds the reason when not.
+    #[serde(default)]
+    pub status: CommandStatus,
 }
 
 impl AuditEntry {
@@ -64,8 +87,44 @@ impl AuditEntry {
             evidence_ref,
             error,
             category,
+            retries: 0,
+            retry_errors: Vec::new(),
+            status: CommandStatus::Executed,
         }
     }
+
+    /// Create an entry for a command that was not run, for `reason`. It
+    /// has no output and no evidence.
+    pub fn not_run(
+        command: String,
+        category: String,
+        status: CommandStatus,
+        reason: String,
+        at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            status,
+            ..Self::new(
+                0,
+                command,
+                category,
+                at,
+                at,
+                None,
+                0,
+                0,
+                String::new(),
+                Some(reason),
+            )
+        }
+    }
+
+    /// Record the failed attempts that preceded this one.
+    pub fn with_retries(mut self, retry_errors: Vec<String>) -> Self {
+        self.retries = retry_errors.len() as u32;
+        self.retry_errors = retry_errors;
+        self
+    }
 }
 
 /// Collection of audit entries (written as JSONL).
@@ -81,11 +140,13 @@ impl AuditLog {
         Self::default()
     }
 
-    /// Add an entry to the audit log.
-    pub fn add(&mut self, mut entry: AuditEntry) {
-        entry.seq = self.next_seq;
+    /// Add an entry to the audit log, returning its sequence number.
+    pub fn add(&mut self, mut entry: AuditEntry) -> u64 {
+        let seq = self.next_seq;
+        entry.seq = seq;
         self.next_seq += 1;
         self.entries.push(entry);
+        seq
     }
 
     /// Get all entries.
@@ -142,5 +203,31 @@ mod tests {
         let jsonl = log.to_jsonl();
         let parsed = AuditLog::from_jsonl(&jsonl).unwrap();
         assert_eq!(parsed.entries().len(), 1);
+        assert_eq!(parsed.entries()[0].retries, 0);
+    }
+
+    #[test]
+    fn test_audit_entry_retries() {
+        let now = Utc::now();
+        let entry = AuditEntry::new(
+            0,
+            "ss -tlnp".to_string(),
+            "ports".to_string(),
+            now,
+            now,
+            Some(0),
+            10,
+            0,
+            "evidence/ports.txt".to_string(),
+            None,
+        )
+        .with_retries(vec!["attempt 1: Failed to open SSH channel".to_string()]);
+
+        assert_eq!(entry.retries, 1);
+        // Entries written before retries existed still parse
+        let legacy = r#"{"seq":0,"started_at":"2024-01-01T00:00:00Z","completed_at":"2024-01-01T00:00:00Z","duration_ms":0,"command":"id","exit_code":0,"success":true,"stdout_bytes":0,"stderr_bytes":0,"evidence_ref":"e.txt","error":null,"category":"system"}"#;
+        let parsed = AuditLog::from_jsonl(legacy).unwrap();
+        assert!(parsed.entries()[0].retry_errors.is_empty());
+        assert_eq!(parsed.entries()[0].status, CommandStatus::Executed);
     }
 }
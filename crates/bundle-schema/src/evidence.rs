@@ -67,6 +67,9 @@ pub struct Evidence {
     pub bundle_path: String,
     /// Original path on the target system (if applicable).
     pub original_path: Option<String>,
+    /// Sequence number of the audit entry of the command that produced it.
+    #[serde(default)]
+    pub audit_seq: Option<u64>,
     /// The actual content (only present in memory, not serialized to metadata).
     #[serde(skip)]
     pub content: Option<Vec<u8>>,
@@ -94,6 +97,7 @@ impl Evidence {
             redacted: false,
             bundle_path: bundle_path.into(),
             original_path: None,
+            audit_seq: None,
             content: Some(content),
         }
     }
@@ -120,10 +124,24 @@ impl Evidence {
             redacted: false,
             bundle_path: bundle_path.into(),
             original_path: Some(original_path.into()),
+            audit_seq: None,
             content: Some(content),
         }
     }
 
+    /// The content as text: the standard output of a command, without the
+    /// standard error section, or the file itself.
+    pub fn output(&self) -> Option<String> {
+        let content = String::from_utf8_lossy(self.content.as_ref()?);
+        Some(match content.strip_prefix("=== STDOUT ===\n") {
+            Some(rest) => match rest.rsplit_once("\n\n=== STDERR ===\n") {
+                Some((stdout, _)) => stdout.to_string(),
+                None => rest.to_string(),
+            },
+            None => content.into_owned(),
+        })
+    }
+
     /// Mark this evidence as redacted.
     pub fn mark_redacted(&mut self) {
         self.redacted = true;
@@ -144,5 +162,13 @@ mod tests {
         );
         assert_eq!(evidence.evidence_type, EvidenceType::CommandOutput);
         assert!(!evidence.redacted);
+
+        let wrapped = Evidence::from_command_output(
+            "config/etc/app.conf",
+            "cat /etc/app.conf",
+            b"=== STDOUT ===\nport=80\n\n=== STDERR ===\n".to_vec(),
+            "evidence/config/etc/app.conf.txt",
+        );
+        assert_eq!(wrapped.output().as_deref(), Some("port=80"));
     }
 }
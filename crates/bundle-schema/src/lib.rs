@@ -6,18 +6,26 @@
 pub mod audit;
 pub mod evidence;
 pub mod manifest;
+pub mod migration;
 pub mod packplan;
 pub mod schema;
+pub mod truth;
 pub mod validation;
 
-pub use audit::{AuditEntry, AuditLog};
+pub use audit::{AuditEntry, AuditLog, CommandStatus};
 pub use evidence::{Evidence, EvidenceRef, EvidenceType};
 pub use manifest::{
-    Bundle, EnvironmentFile, FileInfo, Manifest, NetworkConnection, Package, PortInfo, ProcessInfo,
-    ScheduledTask, ServiceInfo, SystemInfo,
+    BinaryInfo, Bundle, CollectionError, EnvironmentFile, FileInfo, HostEntry, HostLookup,
+    Manifest, NameResolution, NetworkConnection, Package, PackageOwner, PortInfo, ProcessInfo,
+    RecoveryAction, RegistryKey, ResourceSample, RuntimeProbe, ScheduledTask, ServiceInfo,
+    ServiceRecovery, SystemInfo,
 };
+pub use migration::{MANIFEST_SCHEMA_VERSION, PACKPLAN_SCHEMA_VERSION};
 pub use packplan::{
-    AppCluster, ClusterPort, ClusterProcess, ClusterService, ConfigFileSpec, DagEdge, Decision,
-    DependencyInfo, EnvVarSpec, GeneratedArtifact, PackPlan, ReadinessCheck,
+    AnalysisWarning, AppCluster, AppFileSpec, ArtifactType, BuildCheck, ClusterPackage,
+    ClusterPort, ClusterProcess, ClusterService, ConfigFileSpec, DagEdge, DatabaseInfo, Decision,
+    DependencyInfo, EnvVarSpec, GeneratedArtifact, JavaAppInfo, PackManifest, PackPlan,
+    PackageFile, PackedFile, ReadinessCheck, RejectedCluster, ResourceUsage, RuntimeInfo,
+    SupervisedProgram, VolumeSpec, PACK_DIR, PACK_MANIFEST_FILE,
 };
 pub use validation::validate_bundle;
@@ -1,8 +1,9 @@
 //! Manifest types for the collection bundle.
 
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// The complete bundle structure (in-memory representation).
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,10 +14,19 @@ pub struct Bundle {
     pub checksums: HashMap<String, String>,
 }
 
+impl Bundle {
+    /// The audit entry of the command whose output is stored at
+    /// `evidence_ref`.
+    pub fn audit_entry(&self, evidence_ref: &str) -> Option<&super::AuditEntry> {
+        self.audit.iter().find(|e| e.evidence_ref == evidence_ref)
+    }
+}
+
 /// The manifest.json file - contains all collected facts.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Manifest {
     /// Schema version for forward compatibility.
+    #[schemars(regex(pattern = r"^\d+\.\d+\.\d+$"))]
     pub schema_version: String,
     /// Unique identifier for this collection.
     pub collection_id: String,
@@ -36,24 +46,52 @@ pub struct Manifest {
     pub connections: Vec<NetworkConnection>,
     /// Installed packages.
     pub packages: Vec<Package>,
+    /// Packages owning the executables and configuration files of services
+    /// and processes.
+    #[serde(default)]
+    pub package_owners: Vec<PackageOwner>,
     /// Scheduled tasks (cron, systemd timers, Windows scheduled tasks).
     pub scheduled_tasks: Vec<ScheduledTask>,
     /// Collected configuration files.
     pub config_files: Vec<FileInfo>,
+    /// Ownership and mode of service and process working directories.
+    #[serde(default)]
+    pub directories: Vec<FileInfo>,
     /// Collected log snippets.
     pub log_files: Vec<FileInfo>,
     /// Environment files found.
     pub environment_files: Vec<EnvironmentFile>,
+    /// Language runtime versions reported by the target.
+    #[serde(default)]
+    pub runtimes: Vec<RuntimeProbe>,
+    /// Executable type information for service and process binaries.
+    #[serde(default)]
+    pub binaries: Vec<BinaryInfo>,
+    /// CPU and memory readings of business processes, taken repeatedly
+    /// over a sampling window when one is requested.
+    #[serde(default)]
+    pub resource_samples: Vec<ResourceSample>,
+    /// Registry keys of Windows services, with their (redacted) values.
+    #[serde(default)]
+    pub registry_keys: Vec<RegistryKey>,
+    /// Hosts file, resolver configuration and lookups of the host names
+    /// configuration files name.
+    #[serde(default)]
+    pub name_resolution: NameResolution,
     /// Collection mode used.
     pub collection_mode: String,
     /// Any errors encountered during collection.
     pub errors: Vec<CollectionError>,
+    /// SHA256 of the content of each evidence path. Identical content is
+    /// stored once in the bundle, as `blobs/<hash>` (`blobs/<hash>.gz` in v1).
+    #[serde(default)]
+    pub evidence_blobs: BTreeMap<String, String>,
 }
 
 impl Default for Manifest {
     fn default() -> Self {
         Self {
-            schema_version: "1.0.0".to_string(),
+            schema_version: crate::MANIFEST_SCHEMA_VERSION.to_string(),
             collection_id: uuid::Uuid::new_v4().to_string(),
             collected_at: Utc::now(),
             completed_at: None,
@@ -63,18 +101,26 @@ impl Default for Manifest {
             ports: Vec::new(),
             connections: Vec::new(),
             packages: Vec::new(),
+            package_owners: Vec::new(),
             scheduled_tasks: Vec::new(),
             config_files: Vec::new(),
+            directories: Vec::new(),
             log_files: Vec::new(),
             environment_files: Vec::new(),
+            runtimes: Vec::new(),
+            binaries: Vec::new(),
+            resource_samples: Vec::new(),
+            registry_keys: Vec::new(),
+            name_resolution: NameResolution::default(),
             collection_mode: "unknown".to_string(),
             errors: Vec::new(),
+            evidence_blobs: BTreeMap::new(),
         }
     }
 }
 
 /// System information.
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 pub struct SystemInfo {
     pub hostname: String,
     pub os_type: String,
@@ -83,10 +129,17 @@ pub struct SystemInfo {
     pub architecture: Option<String>,
     pub uptime_seconds: Option<u64>,
     pub timezone: Option<String>,
+    /// Init system managing services (systemd, sysvinit, openrc), Linux only.
+    #[serde(default)]
+    pub init_system: Option<String>,
+    /// Whether collection ran as root or an Administrator. Unprivileged
+    /// collection misses the details of other users' processes.
+    #[serde(default)]
+    pub privileged: Option<bool>,
 }
 
 /// Process information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProcessInfo {
     pub pid: u32,
     pub ppid: u32,
@@ -102,10 +155,14 @@ pub struct ProcessInfo {
     pub environment: Option<HashMap<String, String>>,
     /// Evidence reference for the raw ps output.
     pub evidence_ref: Option<String>,
+    /// Evidence reference for the command line read from
+    /// `/proc/<pid>/cmdline`, when it replaced the one given by `ps`.
+    #[serde(default)]
+    pub cmdline_evidence_ref: Option<String>,
 }
 
 /// Service information (systemd or Windows service).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ServiceInfo {
     pub name: String,
     pub display_name: Option<String>,
@@ -126,12 +183,35 @@ pub struct ServiceInfo {
     pub dependencies: Vec<String>,
     pub wanted_by: Vec<String>,
     pub main_pid: Option<u32>,
+    /// What the service manager does when the service fails (Windows).
+    #[serde(default)]
+    pub recovery: Option<ServiceRecovery>,
     /// Evidence reference for the unit file content.
     pub evidence_ref: Option<String>,
 }
 
+/// Failure recovery settings of a service.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ServiceRecovery {
+    /// Seconds without failure after which the failure count is reset.
+    pub reset_period_secs: Option<u64>,
+    /// Actions on the first, second and subsequent failures.
+    pub actions: Vec<RecoveryAction>,
+    /// Command line of `run` actions.
+    pub command: Option<String>,
+}
+
+/// An action taken when a service fails.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RecoveryAction {
+    /// `restart`, `run` or `reboot`.
+    pub action: String,
+    /// Delay before the action, in milliseconds.
+    pub delay_ms: u64,
+}
+
 /// Port/listener information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PortInfo {
     pub protocol: String,
     pub local_address: String,
@@ -141,10 +221,33 @@ pub struct PortInfo {
     pub process_name: Option<String>,
     /// Evidence reference.
     pub evidence_ref: Option<String>,
+    /// How `pid` was found when the listing lacked it: `proc_net`, the
+    /// socket inode of `/proc/net/*` found among a process's file
+    /// descriptors, or `lsof`.
+    #[serde(default)]
+    pub pid_source: Option<String>,
+    /// Evidence of the `pid_source` attribution.
+    #[serde(default)]
+    pub pid_evidence_ref: Option<String>,
+    /// Address family: `ipv4`, `ipv6` or `unix`. Absent from bundles
+    /// collected before it was recorded.
+    #[serde(default)]
+    pub family: Option<String>,
+    /// Path of a Unix domain socket, whose `local_port` is 0.
+    #[serde(default)]
+    pub socket_path: Option<String>,
+}
+
+impl PortInfo {
+    /// Whether the listener is a Unix domain socket rather than a network
+    /// port.
+    pub fn is_unix(&self) -> bool {
+        self.family.as_deref() == Some("unix")
+    }
 }
 
 /// Network connection information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetworkConnection {
     pub protocol: String,
     pub local_address: String,
@@ -157,7 +260,7 @@ pub struct NetworkConnection {
 }
 
 /// Package information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Package {
     pub name: String,
     pub version: String,
@@ -165,10 +268,29 @@ pub struct Package {
     pub description: Option<String>,
     pub install_date: Option<DateTime<Utc>>,
     pub source: String, // dpkg, rpm, etc.
+    /// Evidence reference.
+    #[serde(default)]
+    pub evidence_ref: Option<String>,
+}
+
+/// The installed packages an executable or configuration file comes from.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PackageOwner {
+    /// Absolute path of the file.
+    pub path: String,
+    /// Owning packages; empty when no package owns the file, i.e. it was
+    /// deployed by hand.
+    pub packages: Vec<String>,
+    /// Package manager asked (dpkg, rpm).
+    pub source: String,
+    /// Services the file is the executable or a configuration file of.
+    pub services: Vec<String>,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
 }
 
 /// Scheduled task information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ScheduledTask {
     pub name: String,
     pub task_type: String, // cron, systemd-timer, windows-task
@@ -183,12 +305,14 @@ pub struct ScheduledTask {
 }
 
 /// File information for configs and logs.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FileInfo {
     pub path: String,
     pub size_bytes: u64,
     pub modified_at: Option<DateTime<Utc>>,
+    /// Owner as `user:group`.
     pub owner: Option<String>,
+    /// Octal mode (`0640`).
     pub permissions: Option<String>,
     pub content_hash: Option<String>,
     /// Reference to the attachment containing (redacted) content.
@@ -197,10 +321,14 @@ pub struct FileInfo {
     pub discovery_method: String,
     /// Evidence that led to discovering this file.
     pub discovery_evidence_ref: Option<String>,
+    /// PIDs of the processes holding the file open, when discovered from
+    /// open file descriptors.
+    #[serde(default)]
+    pub opened_by: Vec<u32>,
 }
 
 /// Environment file information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct EnvironmentFile {
     pub path: String,
     /// Variable names found (not values - those may be sensitive).
@@ -209,12 +337,101 @@ pub struct EnvironmentFile {
     pub evidence_ref: Option<String>,
 }
 
+/// A Windows registry key read for a service.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RegistryKey {
+    /// Key path (`HKLM\SOFTWARE\Acme\Billing`).
+    pub path: String,
+    /// Service the key was read for.
+    pub service: String,
+    /// Values by name, redacted; multi-string values are joined with
+    /// newlines.
+    pub values: BTreeMap<String, String>,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
+/// How the target resolves host names.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct NameResolution {
+    /// Entries of the hosts file.
+    pub hosts: Vec<HostEntry>,
+    /// Name servers of `/etc/resolv.conf`.
+    pub nameservers: Vec<String>,
+    /// Search domains of `/etc/resolv.conf`.
+    pub search_domains: Vec<String>,
+    /// Host names of configuration files looked up on the target, with
+    /// `collect --resolve-hosts`.
+    pub lookups: Vec<HostLookup>,
+    /// Evidence references of the hosts file and resolver configuration.
+    pub evidence_refs: Vec<String>,
+}
+
+/// An address of the hosts file and its names.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HostEntry {
+    pub address: String,
+    pub names: Vec<String>,
+}
+
+/// The addresses a host name resolved to on the target; none when it did
+/// not resolve.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HostLookup {
+    pub name: String,
+    pub addresses: Vec<String>,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
+/// A language runtime found on the target (e.g. from `node --version`).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RuntimeProbe {
+    /// Language (node, python, java, dotnet).
+    pub language: String,
+    /// Version as reported by the runtime.
+    pub version: String,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
+/// Executable type of a binary run by a service or process.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BinaryInfo {
+    /// Absolute path of the executable.
+    pub path: String,
+    /// Kind of executable (elf, macho, script, unknown).
+    pub kind: String,
+    /// ELF program interpreter or script shebang interpreter.
+    pub interpreter: Option<String>,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
+/// A CPU and memory reading of a process.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ResourceSample {
+    pub pid: u32,
+    /// When the reading was taken.
+    pub timestamp: DateTime<Utc>,
+    /// CPU time used by the process since it started, in seconds.
+    pub cpu_seconds: f64,
+    /// Resident memory in bytes.
+    pub rss_bytes: u64,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
 /// Collection error.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CollectionError {
     pub phase: String,
     pub command: Option<String>,
     pub error: String,
     pub timestamp: DateTime<Utc>,
     pub recoverable: bool,
+    /// The allowlist refused the command: nothing failed on the target.
+    #[serde(default)]
+    pub denied: bool,
 }
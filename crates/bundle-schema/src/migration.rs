@@ -0,0 +1,284 @@
+//! Schema migrations.
+//!
+//! Manifests and pack plans written by an older version are upgraded in
+//! memory before they are deserialized. Each registered [`Migration`] takes
+//! a document from one schema version to the next, filling new fields with
+//! defaults and moving renamed fields; readers report the steps applied as
+//! warnings rather than failing. Documents of a newer major version are
+//! refused, as their fields may not mean what this version expects; newer
+//! minor versions only add fields and are read as they are.
+
+use crate::{Manifest, PackPlan};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use thiserror::Error;
+
+/// Schema version of the manifests written by this version.
+pub const MANIFEST_SCHEMA_VERSION: &str = "1.0.0";
+
+/// Schema version of the pack plans written by this version.
+pub const PACKPLAN_SCHEMA_VERSION: &str = "1.0.0";
+
+/// Upgrades of manifests, oldest first.
+pub const MANIFEST_MIGRATIONS: &[Migration] = &[];
+
+/// Upgrades of pack plans, oldest first.
+pub const PACKPLAN_MIGRATIONS: &[Migration] = &[];
+
+/// Migration error type.
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Missing or invalid schema_version: {0}")]
+    InvalidVersion(String),
+
+    #[error("Schema version {found} is newer than the supported {supported}.x: upgrade xcprobe")]
+    NewerVersion { found: String, supported: u64 },
+
+    #[error("JSON parsing error: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// A change made by a migration step.
+#[derive(Debug, Clone, Copy)]
+pub enum Change {
+    /// Set the field at `field`, a JSON pointer whose `*` segments match
+    /// every element of an array, to the JSON `default` when absent.
+    Default {
+        field: &'static str,
+        default: &'static str,
+    },
+    /// Move the value at `from` to the sibling field `to`.
+    Rename {
+        from: &'static str,
+        to: &'static str,
+    },
+}
+
+/// Upgrade of documents from one schema version to the next.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub changes: &'static [Change],
+}
+
+/// Upgrade and deserialize a manifest, and return the steps applied.
+pub fn migrate_manifest(document: Value) -> Result<(Manifest, Vec<String>), MigrationError> {
+    migrate_into(
+        document,
+        "manifest",
+        MANIFEST_SCHEMA_VERSION,
+        MANIFEST_MIGRATIONS,
+    )
+}
+
+/// Upgrade and deserialize a pack plan, and return the steps applied.
+pub fn migrate_pack_plan(document: Value) -> Result<(PackPlan, Vec<String>), MigrationError> {
+    migrate_into(
+        document,
+        "pack plan",
+        PACKPLAN_SCHEMA_VERSION,
+        PACKPLAN_MIGRATIONS,
+    )
+}
+
+fn migrate_into<T: DeserializeOwned>(
+    mut document: Value,
+    kind: &str,
+    current: &str,
+    migrations: &[Migration],
+) -> Result<(T, Vec<String>), MigrationError> {
+    let applied = migrate(&mut document, kind, current, migrations)?;
+    Ok((serde_json::from_value(document)?, applied))
+}
+
+/// Upgrade `document` to the `current` schema version with the
+/// `migrations` from its version on, and describe the steps applied.
+pub fn migrate(
+    document: &mut Value,
+    kind: &str,
+    current: &str,
+    migrations: &[Migration],
+) -> Result<Vec<String>, MigrationError> {
+    let found = document
+        .get("schema_version")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    check_supported(&found, current)?;
+    let version = parse_version(&found)?;
+    if version >= parse_version(current)? {
+        return Ok(Vec::new());
+    }
+
+    let mut applied = Vec::new();
+    for migration in migrations {
+        if parse_version(migration.from)? < version {
+            continue;
+        }
+        for change in migration.changes {
+            apply(document, *change);
+        }
+        applied.push(format!(
+            "Upgraded {} from schema {} to {}",
+            kind, migration.from, migration.to
+        ));
+    }
+    if applied.is_empty() {
+        applied.push(format!("Read {} of schema {} as {}", kind, found, current));
+    }
+    document["schema_version"] = Value::String(current.to_string());
+    Ok(applied)
+}
+
+/// Refuse a schema version of a newer major version than `current`.
+pub fn check_supported(version: &str, current: &str) -> Result<(), MigrationError> {
+    let (major, _, _) = parse_version(version)?;
+    let (supported, _, _) = parse_version(current)?;
+    if major > supported {
+        return Err(MigrationError::NewerVersion {
+            found: version.to_string(),
+            supported,
+        });
+    }
+    Ok(())
+}
+
+/// `major.minor.patch`, missing parts being 0.
+fn parse_version(version: &str) -> Result<(u64, u64, u64), MigrationError> {
+    let invalid = || MigrationError::InvalidVersion(version.to_string());
+    let mut parts = version
+        .split('.')
+        .map(|p| p.parse::<u64>().map_err(|_| invalid()));
+    let major = parts.next().ok_or_else(invalid)??;
+    let minor = parts.next().transpose()?.unwrap_or(0);
+    let patch = parts.next().transpose()?.unwrap_or(0);
+    Ok((major, minor, patch))
+}
+
+fn apply(document: &mut Value, change: Change) {
+    match change {
+        Change::Default { field, default } => {
+            let (parent, name) = split_pointer(field);
+            let default: Value = serde_json::from_str(default).unwrap_or(Value::Null);
+            for object in select(document, &parent) {
+                if let Some(object) = object.as_object_mut() {
+                    object
+                        .entry(name.to_string())
+                        .or_insert_with(|| default.clone());
+                }
+            }
+        }
+        Change::Rename { from, to } => {
+            let (parent, name) = split_pointer(from);
+            for object in select(document, &parent) {
+                if let Some(object) = object.as_object_mut() {
+                    if let Some(value) = object.remove(name) {
+                        object.entry(to.to_string()).or_insert(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Segments of the parent of a pointer, and the last segment.
+fn split_pointer(pointer: &str) -> (Vec<&str>, &str) {
+    let mut segments: Vec<&str> = pointer.trim_start_matches('/').split('/').collect();
+    let name = segments.pop().unwrap_or_default();
+    (segments, name)
+}
+
+/// The values at the `segments` under `value`.
+fn select<'a>(value: &'a mut Value, segments: &[&str]) -> Vec<&'a mut Value> {
+    let Some((first, rest)) = segments.split_first() else {
+        return vec![value];
+    };
+    match (value, *first) {
+        (Value::Array(items), "*") => items
+            .iter_mut()
+            .flat_map(|item| select(item, rest))
+            .collect(),
+        (Value::Object(object), name) => match object.get_mut(name) {
+            Some(child) => select(child, rest),
+            None => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: "0.9.0",
+            to: "1.0.0",
+            changes: &[
+                Change::Rename {
+                    from: "/clusters/*/image",
+                    to: "base_image",
+                },
+                Change::Default {
+                    field: "/clusters/*/depends_on",
+                    default: "[]",
+                },
+            ],
+        },
+        Migration {
+            from: "1.0.0",
+            to: "1.1.0",
+            changes: &[Change::Default {
+                field: "/rejected_clusters",
+                default: "[]",
+            }],
+        },
+    ];
+
+    #[test]
+    fn test_migrate_older_document() {
+        let mut document = json!({
+            "schema_version": "0.9.0",
+            "clusters": [{ "id": "app-0", "image": "node:20" }, { "id": "app-1" }]
+        });
+
+        let applied = migrate(&mut document, "pack plan", "1.1.0", MIGRATIONS).unwrap();
+
+        assert_eq!(
+            applied,
+            vec![
+                "Upgraded pack plan from schema 0.9.0 to 1.0.0",
+                "Upgraded pack plan from schema 1.0.0 to 1.1.0"
+            ]
+        );
+        assert_eq!(
+            document,
+            json!({
+                "schema_version": "1.1.0",
+                "clusters": [
+                    { "id": "app-0", "base_image": "node:20", "depends_on": [] },
+                    { "id": "app-1", "depends_on": [] }
+                ],
+                "rejected_clusters": []
+            })
+        );
+    }
+
+    #[test]
+    fn test_newer_versions() {
+        let mut minor = json!({ "schema_version": "1.2.0", "new_field": true });
+        assert!(migrate(&mut minor, "manifest", "1.1.0", MIGRATIONS)
+            .unwrap()
+            .is_empty());
+        assert_eq!(minor["schema_version"], "1.2.0");
+
+        let mut major = json!({ "schema_version": "2.0.0" });
+        assert!(matches!(
+            migrate(&mut major, "manifest", "1.1.0", MIGRATIONS),
+            Err(MigrationError::NewerVersion { supported: 1, .. })
+        ));
+        assert!(migrate(&mut json!({}), "manifest", "1.1.0", MIGRATIONS).is_err());
+    }
+}
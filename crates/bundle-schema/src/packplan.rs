@@ -1,18 +1,23 @@
 //! Pack plan types - output of the analyzer.
 
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 /// The complete pack plan - describes how to containerize the discovered applications.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PackPlan {
     /// Schema version.
+    #[schemars(regex(pattern = r"^\d+\.\d+\.\d+$"))]
     pub schema_version: String,
     /// When this plan was generated.
     pub generated_at: DateTime<Utc>,
     /// Source bundle ID.
     pub source_bundle_id: String,
+    /// Operating system of the collected host (`linux`, `windows`, ...).
+    #[serde(default)]
+    pub source_os: Option<String>,
     /// Discovered application clusters.
     pub clusters: Vec<AppCluster>,
     /// Global dependencies (external endpoints).
@@ -22,29 +27,35 @@ pub struct PackPlan {
     /// Generated artifacts metadata.
     pub artifacts: Vec<GeneratedArtifact>,
     /// Overall confidence score (0.0 - 1.0).
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub overall_confidence: f64,
     /// Analysis warnings.
     pub warnings: Vec<AnalysisWarning>,
+    /// Clusters excluded for low confidence, when requested.
+    #[serde(default)]
+    pub rejected_clusters: Vec<RejectedCluster>,
 }
 
 impl Default for PackPlan {
     fn default() -> Self {
         Self {
-            schema_version: "1.0.0".to_string(),
+            schema_version: crate::PACKPLAN_SCHEMA_VERSION.to_string(),
             generated_at: chrono::Utc::now(),
             source_bundle_id: String::new(),
+            source_os: None,
             clusters: Vec::new(),
             external_dependencies: Vec::new(),
             startup_dag: Vec::new(),
             artifacts: Vec::new(),
             overall_confidence: 0.0,
             warnings: Vec::new(),
+            rejected_clusters: Vec::new(),
         }
     }
 }
 
 /// An application cluster - a logical grouping of related processes/services.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct AppCluster {
     /// Unique identifier for this cluster.
     pub id: String,
@@ -64,6 +75,9 @@ pub struct AppCluster {
     pub env_vars: Vec<EnvVarSpec>,
     /// Configuration files.
     pub config_files: Vec<ConfigFileSpec>,
+    /// Application files and directories to copy into the image.
+    #[serde(default)]
+    pub app_files: Vec<AppFileSpec>,
     /// Log files/paths.
     pub log_paths: Vec<String>,
     /// Dependencies on other clusters.
@@ -72,7 +86,47 @@ pub struct AppCluster {
     pub external_deps: Vec<String>,
     /// Readiness check configuration.
     pub readiness: Option<ReadinessCheck>,
+    /// Detected language runtime.
+    #[serde(default)]
+    pub runtime: Option<RuntimeInfo>,
+    /// Installed packages providing the runtime.
+    #[serde(default)]
+    pub packages: Vec<ClusterPackage>,
+    /// Executables and configuration files with the package they come from.
+    #[serde(default)]
+    pub package_files: Vec<PackageFile>,
+    /// Distribution packages installed in the image, for the executables
+    /// that come from them.
+    #[serde(default)]
+    pub system_packages: Vec<String>,
+    /// Selected container base image.
+    #[serde(default)]
+    pub base_image: Option<String>,
+    /// Cron schedule of a batch cluster's job.
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Stateful directories mounted as named volumes.
+    #[serde(default)]
+    pub volumes: Vec<VolumeSpec>,
+    /// JVM launch details of a Java application.
+    #[serde(default)]
+    pub java: Option<JavaAppInfo>,
+    /// Number of instances of a templated systemd unit (`worker@1.service`,
+    /// `worker@2.service`, ...) run as replicas.
+    #[serde(default)]
+    pub replicas: Option<u32>,
+    /// Resource usage measured over the collection sampling window.
+    #[serde(default)]
+    pub resources: Option<ResourceUsage>,
+    /// Database server run from its official image.
+    #[serde(default)]
+    pub database: Option<DatabaseInfo>,
+    /// Cooperating programs run under supervisord in one container, when
+    /// the cluster groups several programs started separately.
+    #[serde(default)]
+    pub programs: Vec<SupervisedProgram>,
     /// Confidence score for this cluster (0.0 - 1.0).
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub confidence: f64,
     /// Evidence references that support this cluster identification.
     pub evidence_refs: Vec<String>,
@@ -80,8 +134,25 @@ pub struct AppCluster {
     pub decisions: Vec<Decision>,
 }
 
+/// A cluster excluded from the plan, kept for review.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RejectedCluster {
+    pub id: String,
+    pub name: String,
+    pub app_type: String,
+    /// Confidence score the cluster reached.
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub confidence: f64,
+    /// Why the cluster was excluded.
+    pub reason: String,
+    /// Evidence references that support this cluster identification.
+    pub evidence_refs: Vec<String>,
+    /// Decisions that led to the confidence score.
+    pub decisions: Vec<Decision>,
+}
+
 /// A process within a cluster.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ClusterProcess {
     pub pid: u32,
     pub command: String,
@@ -92,28 +163,32 @@ pub struct ClusterProcess {
 }
 
 /// A service within a cluster.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ClusterService {
     pub name: String,
     pub exec_start: Option<String>,
     pub user: Option<String>,
     pub working_directory: Option<String>,
-    pub environment: HashMap<String, String>,
+    pub environment: BTreeMap<String, String>,
     pub environment_files: Vec<String>,
     pub evidence_ref: Option<String>,
 }
 
 /// Port exposed by a cluster.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ClusterPort {
     pub port: u16,
     pub protocol: String,
     pub purpose: Option<String>,
     pub evidence_ref: Option<String>,
+    /// Host port the compose file publishes the port on, when another
+    /// cluster already publishes the container port.
+    #[serde(default)]
+    pub host_port: Option<u16>,
 }
 
 /// Environment variable specification.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct EnvVarSpec {
     /// Variable name.
     pub name: String,
@@ -130,7 +205,7 @@ pub struct EnvVarSpec {
 }
 
 /// Configuration file specification.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ConfigFileSpec {
     /// Original path on the source system.
     pub source_path: String,
@@ -140,12 +215,206 @@ pub struct ConfigFileSpec {
     pub templated: bool,
     /// Template variables used.
     pub template_vars: Vec<String>,
+    /// Template body: the collected file with host addresses replaced by
+    /// `${VAR}` references. Without it the template lists the variables.
+    #[serde(default)]
+    pub content: Option<String>,
     /// Evidence reference.
     pub evidence_ref: Option<String>,
 }
 
+/// Language runtime detected for a cluster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RuntimeInfo {
+    /// Language (node, python, java, dotnet, ruby, php, native).
+    pub language: String,
+    /// Runtime version, if known.
+    pub version: Option<String>,
+    /// Evidence references supporting the detection.
+    pub evidence_refs: Vec<String>,
+}
+
+/// Launch details of a Java application, from its command line.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct JavaAppInfo {
+    /// Jar run with `-jar`.
+    pub main_jar: Option<String>,
+    /// Main class, when no jar is given.
+    pub main_class: Option<String>,
+    /// Classpath entries (`-cp`, `-classpath`).
+    pub classpath: Vec<String>,
+    /// System properties set with `-D`.
+    pub system_properties: BTreeMap<String, String>,
+    /// Active Spring profiles.
+    pub spring_profiles: Vec<String>,
+    /// Maximum heap size (`-Xmx`), as given.
+    pub max_heap: Option<String>,
+    /// Initial heap size (`-Xms`), as given.
+    pub initial_heap: Option<String>,
+    /// Evidence reference for the command line.
+    pub evidence_ref: Option<String>,
+}
+
+/// CPU and memory usage of a cluster's processes over a sampling window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResourceUsage {
+    /// 95th percentile of CPU usage, in cores.
+    pub cpu_p95: f64,
+    /// 95th percentile of resident memory, in bytes.
+    pub memory_p95_bytes: u64,
+    /// Number of sampling instants the percentiles are computed from.
+    pub samples: usize,
+    /// Evidence references of the samples.
+    pub evidence_refs: Vec<String>,
+}
+
+/// A database server migrated to its official image rather than rebuilt
+/// from the host's binaries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DatabaseInfo {
+    /// Engine (postgres, mysql, mariadb, mongodb, redis).
+    pub engine: String,
+    /// Server version on the source system, if known.
+    pub version: Option<String>,
+    /// Official image, tagged with the version.
+    pub image: String,
+    /// Data directory on the source system.
+    pub source_data_dir: Option<String>,
+    /// Data directory in the official image.
+    pub data_dir: String,
+    /// Evidence references of the engine and version.
+    pub evidence_refs: Vec<String>,
+}
+
+/// A program of a multi-process cluster, started by the container's
+/// process supervisor.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SupervisedProgram {
+    /// Program name in the supervisor configuration.
+    pub name: String,
+    /// Command line, from the service's `exec_start` or the process.
+    pub command: String,
+    /// Account the program runs as, `None` for root.
+    pub user: Option<String>,
+    /// Working directory.
+    pub directory: Option<String>,
+    /// Evidence reference of the service or process.
+    pub evidence_ref: Option<String>,
+}
+
+/// An installed package relevant to a cluster.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClusterPackage {
+    pub name: String,
+    pub version: String,
+    pub evidence_ref: Option<String>,
+}
+
+/// A file of a cluster and the installed package it comes from.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PackageFile {
+    /// Path on the source system.
+    pub path: String,
+    /// Kind of file (executable, config).
+    pub kind: String,
+    /// Owning package; `None` for a file deployed by hand, which is packed.
+    pub package: Option<String>,
+    /// Installed version of the owning package.
+    pub version: Option<String>,
+    /// Package manager asked (dpkg, rpm).
+    pub source: String,
+    pub evidence_ref: Option<String>,
+}
+
+/// Directory, relative to a cluster's artifact directory, holding files
+/// fetched by the pack step.
+pub const PACK_DIR: &str = "pack";
+
+/// File name of the pack step's manifest, in the artifacts directory.
+pub const PACK_MANIFEST_FILE: &str = "pack-manifest.json";
+
+/// Files fetched by the pack step into the clusters' `pack/` directories.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PackManifest {
+    /// Collection ID of the bundle the pack plan was analyzed from.
+    pub source_bundle_id: String,
+    /// Fetched files, in path order.
+    pub files: Vec<PackedFile>,
+}
+
+/// A file fetched by the pack step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PackedFile {
+    /// Cluster the file was fetched for.
+    pub cluster_id: String,
+    /// Application file or directory of the cluster it belongs to, on the
+    /// source system.
+    pub source_path: String,
+    /// Path relative to the artifacts directory
+    /// (`app-0/pack/opt/app/main.py`).
+    pub path: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// SHA-256 of the content.
+    pub sha256: String,
+    /// Permission bits in octal (`0755`), `None` where the file system has
+    /// none.
+    pub mode: Option<String>,
+}
+
+/// Application file or directory copied from the source system into the image.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AppFileSpec {
+    /// Path on the source system.
+    pub source_path: String,
+    /// Path in the container.
+    pub container_path: String,
+    /// Kind of path (working_directory, directory, binary, jar, config).
+    pub kind: String,
+    /// Owner of the copied files in the container.
+    pub owner: Option<String>,
+    /// Octal mode of the path on the source system, restored in the image.
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Size in bytes of a file, when collected; unknown for directories.
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Whether the pack step fetched this path.
+    #[serde(default)]
+    pub collected: bool,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
+/// Stateful directory persisted in a named volume.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct VolumeSpec {
+    /// Volume name, unique within the compose file.
+    pub name: String,
+    /// Mount path in the container.
+    pub container_path: String,
+    /// Kind of data (database, uploads, data).
+    pub kind: String,
+    /// Evidence reference.
+    pub evidence_ref: Option<String>,
+}
+
+impl AppFileSpec {
+    /// Whether this entry is a directory.
+    pub fn is_directory(&self) -> bool {
+        self.kind == "working_directory" || self.kind == "directory"
+    }
+
+    /// Path of the fetched copy, relative to the cluster's artifact directory.
+    pub fn pack_path(&self) -> String {
+        let normalized = self.source_path.replace('\\', "/").replace(':', "");
+        format!("{}/{}", PACK_DIR, normalized.trim_start_matches('/'))
+    }
+}
+
 /// Readiness check configuration.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ReadinessCheck {
     /// Type of check (tcp, http, command).
     pub check_type: String,
@@ -166,7 +435,7 @@ pub struct ReadinessCheck {
 }
 
 /// Dependency on an external endpoint.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DependencyInfo {
     /// Unique identifier.
     pub id: String,
@@ -180,10 +449,18 @@ pub struct DependencyInfo {
     pub used_by: Vec<String>,
     /// Evidence references.
     pub evidence_refs: Vec<String>,
+    /// Addresses the endpoint's host resolves to on the source host, from
+    /// its hosts file or lookups.
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Whether the endpoint is on the source host's network rather than an
+    /// Internet service; `None` when unknown.
+    #[serde(default)]
+    pub internal: Option<bool>,
 }
 
 /// DAG edge for startup order.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DagEdge {
     /// Cluster or dependency that must start first.
     pub from: String,
@@ -194,20 +471,44 @@ pub struct DagEdge {
 }
 
 /// Generated artifact metadata.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct GeneratedArtifact {
-    /// Cluster ID this artifact belongs to.
+    /// Cluster ID this artifact belongs to, empty for artifacts covering
+    /// the whole plan (compose files, dependency graphs).
     pub cluster_id: String,
     /// Type of artifact.
     pub artifact_type: ArtifactType,
-    /// Path where the artifact was written.
+    /// Path where the artifact was written, relative to the output directory.
     pub path: String,
     /// Description.
     pub description: Option<String>,
+    /// SHA-256 of the generated content.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Whether the file was edited by hand and kept instead of regenerated
+    /// (`--merge`). Its content then differs from `content_hash`.
+    #[serde(default)]
+    pub preserved: bool,
+    /// Result of the `docker build` smoke test, for Dockerfiles.
+    #[serde(default)]
+    pub build: Option<BuildCheck>,
+}
+
+/// Outcome of building a generated Dockerfile.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BuildCheck {
+    /// Whether the image built.
+    pub success: bool,
+    /// Tag of the built image.
+    pub image: String,
+    /// Build log, relative to the output directory.
+    pub log_path: String,
+    /// Build duration in milliseconds.
+    pub duration_ms: u64,
 }
 
 /// Type of generated artifact.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ArtifactType {
     Dockerfile,
@@ -216,10 +517,13 @@ pub enum ArtifactType {
     ComposeFile,
     Readme,
     ConfidenceReport,
+    /// Startup order and external dependencies (`dependencies.mmd`,
+    /// `dependencies.dot`).
+    DependencyGraph,
 }
 
 /// A decision made during analysis with justification.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Decision {
     /// What decision was made.
     pub decision: String,
@@ -228,7 +532,12 @@ pub struct Decision {
     /// Evidence references supporting this decision.
     pub evidence_refs: Vec<String>,
     /// Confidence level (0.0 - 1.0).
+    #[schemars(range(min = 0.0, max = 1.0))]
     pub confidence: f64,
+    /// Sequence numbers of the audit entries of the commands that produced
+    /// the evidence, in the bundle's `audit.jsonl`.
+    #[serde(default)]
+    pub audit_seqs: Vec<u64>,
 }
 
 impl Decision {
@@ -244,6 +553,7 @@ impl Decision {
             reason: reason.into(),
             evidence_refs,
             confidence,
+            audit_seqs: Vec::new(),
         }
     }
 
@@ -254,7 +564,7 @@ impl Decision {
 }
 
 /// Analysis warning.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AnalysisWarning {
     /// Warning code.
     pub code: String,
@@ -264,4 +574,31 @@ pub struct AnalysisWarning {
     pub severity: String,
     /// Affected cluster IDs.
     pub affected_clusters: Vec<String>,
+    /// Evidence references behind the warning.
+    #[serde(default)]
+    pub evidence_refs: Vec<String>,
+}
+
+impl AnalysisWarning {
+    /// Create a new warning.
+    pub fn new(
+        code: impl Into<String>,
+        severity: impl Into<String>,
+        message: impl Into<String>,
+        affected_clusters: Vec<String>,
+    ) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+            severity: severity.into(),
+            affected_clusters,
+            evidence_refs: Vec::new(),
+        }
+    }
+
+    /// Attach the evidence behind the warning.
+    pub fn with_evidence(mut self, evidence_refs: Vec<String>) -> Self {
+        self.evidence_refs = evidence_refs;
+        self
+    }
 }
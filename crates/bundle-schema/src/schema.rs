@@ -1,262 +1,409 @@
-//! JSON schema definitions for bundle validation.
+//! JSON schemas of the published documents, derived from their Rust types.
 
-/// JSON Schema for manifest.json.
-pub const MANIFEST_SCHEMA: &str = r#"{
-  "$schema": "https://json-schema.org/draft/2020-12/schema",
-  "$id": "https://xcprobe.dev/schemas/manifest.json",
-  "title": "XCProbe Bundle Manifest",
-  "type": "object",
-  "required": ["schema_version", "collection_id", "collected_at", "system", "processes", "services", "ports"],
-  "properties": {
-    "schema_version": {
-      "type": "string",
-      "pattern": "^\\d+\\.\\d+\\.\\d+$"
-    },
-    "collection_id": {
-      "type": "string",
-      "format": "uuid"
-    },
-    "collected_at": {
-      "type": "string",
-      "format": "date-time"
-    },
-    "completed_at": {
-      "type": ["string", "null"],
-      "format": "date-time"
-    },
-    "system": {
-      "type": "object",
-      "required": ["hostname", "os_type"],
-      "properties": {
-        "hostname": { "type": "string" },
-        "os_type": { "type": "string", "enum": ["linux", "windows"] },
-        "os_version": { "type": ["string", "null"] },
-        "kernel_version": { "type": ["string", "null"] },
-        "architecture": { "type": ["string", "null"] },
-        "uptime_seconds": { "type": ["integer", "null"] },
-        "timezone": { "type": ["string", "null"] }
-      }
-    },
-    "processes": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["pid", "ppid", "user", "command", "full_cmdline"],
-        "properties": {
-          "pid": { "type": "integer" },
-          "ppid": { "type": "integer" },
-          "user": { "type": "string" },
-          "command": { "type": "string" },
-          "args": { "type": "array", "items": { "type": "string" } },
-          "full_cmdline": { "type": "string" },
-          "start_time": { "type": ["string", "null"] },
-          "elapsed_time": { "type": ["string", "null"] },
-          "working_directory": { "type": ["string", "null"] },
-          "evidence_ref": { "type": ["string", "null"] }
-        }
-      }
-    },
-    "services": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["name", "state"],
-        "properties": {
-          "name": { "type": "string" },
-          "display_name": { "type": ["string", "null"] },
-          "state": { "type": "string" },
-          "sub_state": { "type": ["string", "null"] },
-          "start_mode": { "type": ["string", "null"] },
-          "exec_start": { "type": ["string", "null"] },
-          "working_directory": { "type": ["string", "null"] },
-          "user": { "type": ["string", "null"] },
-          "environment": { "type": "object" },
-          "environment_files": { "type": "array", "items": { "type": "string" } },
-          "unit_file_path": { "type": ["string", "null"] },
-          "evidence_ref": { "type": ["string", "null"] }
-        }
-      }
-    },
-    "ports": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["protocol", "local_address", "local_port", "state"],
-        "properties": {
-          "protocol": { "type": "string" },
-          "local_address": { "type": "string" },
-          "local_port": { "type": "integer" },
-          "state": { "type": "string" },
-          "pid": { "type": ["integer", "null"] },
-          "process_name": { "type": ["string", "null"] },
-          "evidence_ref": { "type": ["string", "null"] }
-        }
-      }
-    },
-    "connections": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "properties": {
-          "protocol": { "type": "string" },
-          "local_address": { "type": "string" },
-          "local_port": { "type": "integer" },
-          "remote_address": { "type": "string" },
-          "remote_port": { "type": "integer" },
-          "state": { "type": "string" },
-          "pid": { "type": ["integer", "null"] }
-        }
-      }
-    },
-    "packages": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["name", "version", "source"],
-        "properties": {
-          "name": { "type": "string" },
-          "version": { "type": "string" },
-          "architecture": { "type": ["string", "null"] },
-          "source": { "type": "string" }
-        }
-      }
-    },
-    "scheduled_tasks": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["name", "task_type", "enabled"],
-        "properties": {
-          "name": { "type": "string" },
-          "task_type": { "type": "string" },
-          "schedule": { "type": ["string", "null"] },
-          "command": { "type": ["string", "null"] },
-          "enabled": { "type": "boolean" }
-        }
-      }
-    },
-    "config_files": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["path", "size_bytes", "discovery_method"],
-        "properties": {
-          "path": { "type": "string" },
-          "size_bytes": { "type": "integer" },
-          "modified_at": { "type": ["string", "null"] },
-          "attachment_ref": { "type": ["string", "null"] },
-          "discovery_method": { "type": "string" }
-        }
-      }
-    },
-    "log_files": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["path", "size_bytes", "discovery_method"],
-        "properties": {
-          "path": { "type": "string" },
-          "size_bytes": { "type": "integer" },
-          "attachment_ref": { "type": ["string", "null"] },
-          "discovery_method": { "type": "string" }
-        }
-      }
-    },
-    "environment_files": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["path", "variable_names"],
-        "properties": {
-          "path": { "type": "string" },
-          "variable_names": { "type": "array", "items": { "type": "string" } }
-        }
-      }
-    },
-    "collection_mode": { "type": "string" },
-    "errors": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["phase", "error", "timestamp", "recoverable"],
-        "properties": {
-          "phase": { "type": "string" },
-          "command": { "type": ["string", "null"] },
-          "error": { "type": "string" },
-          "timestamp": { "type": "string" },
-          "recoverable": { "type": "boolean" }
+use crate::truth::Truth;
+use crate::{Manifest, PackPlan};
+use schemars::schema_for;
+
+/// A document with a published schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    Manifest,
+    Packplan,
+    Truth,
+}
+
+impl SchemaKind {
+    pub const ALL: [SchemaKind; 3] = [
+        SchemaKind::Manifest,
+        SchemaKind::Packplan,
+        SchemaKind::Truth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SchemaKind::Manifest => "manifest",
+            SchemaKind::Packplan => "packplan",
+            SchemaKind::Truth => "truth",
         }
-      }
     }
-  }
-}"#;
 
-/// JSON Schema for packplan.json.
-pub const PACKPLAN_SCHEMA: &str = r#"{
-  "$schema": "https://json-schema.org/draft/2020-12/schema",
-  "$id": "https://xcprobe.dev/schemas/packplan.json",
-  "title": "XCProbe Pack Plan",
-  "type": "object",
-  "required": ["schema_version", "generated_at", "source_bundle_id", "clusters", "overall_confidence"],
-  "properties": {
-    "schema_version": {
-      "type": "string",
-      "pattern": "^\\d+\\.\\d+\\.\\d+$"
-    },
-    "generated_at": {
-      "type": "string",
-      "format": "date-time"
-    },
-    "source_bundle_id": {
-      "type": "string"
-    },
-    "clusters": {
-      "type": "array",
-      "items": {
-        "type": "object",
-        "required": ["id", "name", "app_type", "confidence", "evidence_refs", "decisions"],
-        "properties": {
-          "id": { "type": "string" },
-          "name": { "type": "string" },
-          "app_type": { "type": "string" },
-          "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
-          "evidence_refs": {
-            "type": "array",
-            "items": { "type": "string" }
-          },
-          "decisions": {
-            "type": "array",
-            "items": {
-              "type": "object",
-              "required": ["decision", "reason", "evidence_refs", "confidence"],
-              "properties": {
-                "decision": { "type": "string" },
-                "reason": { "type": "string" },
-                "evidence_refs": {
-                  "type": "array",
-                  "items": { "type": "string" }
-                },
-                "confidence": { "type": "number" }
-              }
-            }
-          }
-        }
-      }
-    },
-    "overall_confidence": {
-      "type": "number",
-      "minimum": 0,
-      "maximum": 1
+    /// The JSON schema, pretty-printed.
+    pub fn schema_text(self) -> String {
+        serde_json::to_string_pretty(&self.schema()).expect("Unserializable schema")
+    }
+
+    /// The JSON schema as a JSON value.
+    pub fn schema(self) -> serde_json::Value {
+        let schema = match self {
+            SchemaKind::Manifest => schema_for!(Manifest),
+            SchemaKind::Packplan => schema_for!(PackPlan),
+            SchemaKind::Truth => schema_for!(Truth),
+        };
+        serde_json::to_value(schema).expect("Unserializable schema")
     }
-  }
-}"#;
+}
+
+impl std::str::FromStr for SchemaKind {
+    type Err = crate::validation::ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SchemaKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s.to_lowercase())
+            .ok_or_else(|| {
+                crate::validation::ValidationError::SchemaError(format!(
+                    "Unknown schema {} (expected manifest, packplan or truth)",
+                    s
+                ))
+            })
+    }
+}
 
-/// Get the manifest schema as a parsed JSON value.
+/// Get the manifest schema as a JSON value.
 pub fn manifest_schema() -> serde_json::Value {
-    serde_json::from_str(MANIFEST_SCHEMA).expect("Invalid manifest schema")
+    SchemaKind::Manifest.schema()
 }
 
-/// Get the packplan schema as a parsed JSON value.
+/// Get the packplan schema as a JSON value.
 pub fn packplan_schema() -> serde_json::Value {
-    serde_json::from_str(PACKPLAN_SCHEMA).expect("Invalid packplan schema")
+    SchemaKind::Packplan.schema()
+}
+
+#[cfg(test)]
+mod tests {
+    //! These tests serialize documents through the Rust types, with every
+    //! field set and with only the required ones, and fail when serde
+    //! attributes make the output disagree with the derived schema.
+
+    use super::*;
+    use crate::validation::validate_document;
+    use crate::{Manifest, PackPlan};
+    use serde_json::{json, Value};
+
+    fn assert_matches_schema(kind: SchemaKind, document: &Value) {
+        let result = validate_document(kind, document).unwrap();
+        let errors: Vec<String> = result
+            .errors
+            .iter()
+            .map(|e| e.to_string())
+            // Evidence is checked by analysis, not the schema
+            .filter(|e| !e.starts_with("Decision without evidence"))
+            .collect();
+        assert!(errors.is_empty(), "{} schema: {:#?}", kind.name(), errors);
+    }
+
+    fn full_manifest() -> Value {
+        json!({
+            "schema_version": "1.0.0",
+            "collection_id": "550e8400-e29b-41d4-a716-446655440000",
+            "collected_at": "2026-03-01T12:00:00Z",
+            "completed_at": "2026-03-01T12:05:00Z",
+            "system": {
+                "hostname": "web01", "os_type": "linux", "os_version": "Ubuntu 22.04",
+                "kernel_version": "5.15.0", "architecture": "x86_64",
+                "uptime_seconds": 3600, "timezone": "UTC", "init_system": "systemd",
+                "privileged": true
+            },
+            "processes": [{
+                "pid": 100, "ppid": 1, "user": "app", "command": "node",
+                "args": ["server.js"], "full_cmdline": "node server.js",
+                "start_time": "2026-03-01T11:00:00Z", "elapsed_time": "01:00:00",
+                "cpu_percent": 1.5, "memory_percent": 2.5, "working_directory": "/opt/app",
+                "environment": { "PORT": "8080" }, "evidence_ref": "evidence/ps_1.txt",
+                "cmdline_evidence_ref": "evidence/cmdline_1.txt"
+            }],
+            "services": [{
+                "name": "app.service", "display_name": "App", "description": "The app",
+                "state": "active", "sub_state": "running", "start_mode": "auto",
+                "exec_start": "/usr/bin/node server.js", "exec_start_pre": ["/bin/true"],
+                "exec_start_post": ["/bin/true"], "exec_stop": "/bin/kill",
+                "working_directory": "/opt/app", "user": "app", "group": "app",
+                "environment": { "PORT": "8080" }, "environment_files": ["/etc/default/app"],
+                "unit_file_path": "/etc/systemd/system/app.service",
+                "dependencies": ["network.target"], "wanted_by": ["multi-user.target"],
+                "main_pid": 100,
+                "recovery": {
+                    "reset_period_secs": 86400,
+                    "actions": [{ "action": "restart", "delay_ms": 60000 }],
+                    "command": "notify.cmd"
+                },
+                "evidence_ref": "evidence/service_1.txt"
+            }],
+            "ports": [{
+                "protocol": "tcp", "local_address": "0.0.0.0", "local_port": 8080,
+                "state": "LISTEN", "pid": 100, "process_name": "node",
+                "evidence_ref": "evidence/ss_1.txt", "pid_source": "proc_net",
+                "pid_evidence_ref": "evidence/proc_net_1.txt", "family": "ipv4",
+                "socket_path": null
+            }],
+            "connections": [{
+                "protocol": "tcp", "local_address": "10.0.0.5", "local_port": 40000,
+                "remote_address": "10.0.0.9", "remote_port": 5432, "state": "ESTABLISHED",
+                "pid": 100, "process_name": "node"
+            }],
+            "packages": [{
+                "name": "nodejs", "version": "20.1.0", "architecture": "amd64",
+                "description": "Node.js", "install_date": "2026-01-01T00:00:00Z",
+                "source": "dpkg", "evidence_ref": "evidence/dpkg_1.txt"
+            }],
+            "package_owners": [{
+                "path": "/usr/bin/node", "packages": ["nodejs"], "source": "dpkg",
+                "services": ["app.service"], "evidence_ref": "evidence/dpkg_S_1.txt"
+            }],
+            "scheduled_tasks": [{
+                "name": "backup", "task_type": "cron", "schedule": "0 2 * * *",
+                "command": "/usr/local/bin/backup", "user": "root", "enabled": true,
+                "last_run": "2026-03-01T02:00:00Z", "next_run": "2026-03-02T02:00:00Z",
+                "evidence_ref": "evidence/cron_1.txt"
+            }],
+            "config_files": [{
+                "path": "/etc/app/config.yaml", "size_bytes": 120,
+                "modified_at": "2026-02-01T00:00:00Z", "owner": "app:app",
+                "permissions": "0640", "content_hash": "abc",
+                "attachment_ref": "attachments/config.yaml", "discovery_method": "cmdline",
+                "discovery_evidence_ref": "evidence/ps_1.txt", "opened_by": [100]
+            }],
+            "directories": [{
+                "path": "/opt/app", "size_bytes": 0, "modified_at": null, "owner": "app:app",
+                "permissions": "0755", "content_hash": null, "attachment_ref": null,
+                "discovery_method": "working_directory", "discovery_evidence_ref": null,
+                "opened_by": []
+            }],
+            "log_files": [],
+            "environment_files": [{
+                "path": "/etc/default/app", "variable_names": ["PORT"],
+                "evidence_ref": "attachments/app.env"
+            }],
+            "runtimes": [{ "language": "node", "version": "v20.1.0", "evidence_ref": "evidence/node_1.txt" }],
+            "binaries": [{
+                "path": "/usr/bin/node", "kind": "elf",
+                "interpreter": "/lib64/ld-linux-x86-64.so.2", "evidence_ref": "evidence/file_1.txt"
+            }],
+            "resource_samples": [{
+                "pid": 100, "timestamp": "2026-03-01T12:01:00Z", "cpu_seconds": 12.5,
+                "rss_bytes": 104857600, "evidence_ref": "evidence/sample_1.txt"
+            }],
+            "registry_keys": [{
+                "path": "HKLM\\SOFTWARE\\Contoso", "service": "Billing",
+                "values": { "Port": "8080" }, "evidence_ref": "evidence/registry_1.txt"
+            }],
+            "name_resolution": {
+                "hosts": [{ "address": "10.0.0.9", "names": ["db.internal", "db"] }],
+                "nameservers": ["10.0.0.2"],
+                "search_domains": ["corp.example.com"],
+                "lookups": [{
+                    "name": "api.stripe.com", "addresses": ["198.51.100.7"],
+                    "evidence_ref": "evidence/getent_1.txt"
+                }],
+                "evidence_refs": ["evidence/hosts_1.txt", "evidence/resolv_1.txt"]
+            },
+            "collection_mode": "remote",
+            "errors": [{
+                "phase": "logs", "command": "journalctl", "error": "timeout",
+                "timestamp": "2026-03-01T12:04:00Z", "recoverable": true,
+                "denied": false
+            }],
+            "evidence_blobs": { "evidence/ps_1.txt": "0".repeat(64) }
+        })
+    }
+
+    fn full_cluster() -> Value {
+        json!({
+            "id": "app-0", "name": "api", "description": "The API", "app_type": "api",
+            "processes": [{
+                "pid": 100, "command": "node", "args": ["server.js"], "user": "app",
+                "working_directory": "/opt/app", "evidence_ref": "evidence/ps_1.txt"
+            }],
+            "services": [{
+                "name": "app.service", "exec_start": "/usr/bin/node server.js", "user": "app",
+                "working_directory": "/opt/app", "environment": { "PORT": "8080" },
+                "environment_files": ["/etc/default/app"], "evidence_ref": "evidence/service_1.txt"
+            }],
+            "ports": [{ "port": 8080, "protocol": "tcp", "purpose": "http", "evidence_ref": "evidence/ss_1.txt", "host_port": 18080 }],
+            "env_vars": [{
+                "name": "PORT", "required": true, "default_value": "8080",
+                "description": "Listen port", "sensitive": false, "evidence_ref": "evidence/ps_1.txt"
+            }],
+            "config_files": [{
+                "source_path": "/etc/app/config.yaml", "container_path": "/etc/app/config.yaml",
+                "templated": true, "template_vars": ["PORT"], "content": "port: 8080",
+                "evidence_ref": "attachments/config.yaml"
+            }],
+            "app_files": [{
+                "source_path": "/opt/app", "container_path": "/opt/app",
+                "kind": "working_directory", "owner": "app:app", "mode": "0755",
+                "size": null, "collected": true, "evidence_ref": "evidence/ps_1.txt"
+            }],
+            "log_paths": ["/var/log/app"],
+            "depends_on": ["app-1"],
+            "external_deps": ["dep-0"],
+            "readiness": {
+                "check_type": "http", "target": "localhost", "port": 8080, "path": "/health",
+                "command": "curl -f localhost:8080/health", "timeout_seconds": 5,
+                "interval_seconds": 10, "retries": 3
+            },
+            "runtime": { "language": "node", "version": "20", "evidence_refs": ["evidence/node_1.txt"] },
+            "packages": [{ "name": "nodejs", "version": "20.1.0", "evidence_ref": "evidence/dpkg_1.txt" }],
+            "package_files": [{
+                "path": "/usr/bin/node", "kind": "executable", "package": "nodejs",
+                "version": "20.1.0", "source": "dpkg", "evidence_ref": "evidence/dpkg_S_1.txt"
+            }],
+            "system_packages": ["nodejs"],
+            "base_image": "node:20-alpine",
+            "schedule": "0 2 * * *",
+            "volumes": [{
+                "name": "data", "container_path": "/var/lib/app", "kind": "state",
+                "evidence_ref": "evidence/ps_1.txt"
+            }],
+            "java": {
+                "main_jar": "app.jar", "main_class": "com.example.Main", "classpath": ["lib/*"],
+                "system_properties": { "spring.profiles.active": "prod" },
+                "spring_profiles": ["prod"], "max_heap": "512m", "initial_heap": "256m",
+                "evidence_ref": "evidence/ps_1.txt"
+            },
+            "replicas": 2,
+            "resources": {
+                "cpu_p95": 0.5, "memory_p95_bytes": 104857600, "samples": 12,
+                "evidence_refs": ["evidence/sample_1.txt"]
+            },
+            "database": {
+                "engine": "postgresql", "version": "15", "image": "postgres:15",
+                "source_data_dir": "/var/lib/postgresql/15/main",
+                "data_dir": "/var/lib/postgresql/data", "evidence_refs": ["evidence/ps_1.txt"]
+            },
+            "programs": [{
+                "name": "php-fpm", "command": "/usr/sbin/php-fpm8.2 --nodaemonize",
+                "user": "www-data", "directory": "/var/www", "evidence_ref": "evidence/ps_1.txt"
+            }],
+            "confidence": 0.9,
+            "evidence_refs": ["evidence/ps_1.txt"],
+            "decisions": [{
+                "decision": "Expose port 8080", "reason": "Listening socket",
+                "evidence_refs": ["evidence/ss_1.txt"], "confidence": 0.9, "audit_seqs": [3]
+            }]
+        })
+    }
+
+    fn full_plan() -> Value {
+        json!({
+            "schema_version": "1.0.0",
+            "generated_at": "2026-03-01T13:00:00Z",
+            "source_bundle_id": "550e8400-e29b-41d4-a716-446655440000",
+            "source_os": "linux",
+            "clusters": [full_cluster()],
+            "external_dependencies": [{
+                "id": "dep-0", "dep_type": "database", "endpoint": "10.0.0.9", "port": 5432,
+                "used_by": ["app-0"], "evidence_refs": ["evidence/ss_1.txt"],
+                "addresses": ["10.0.0.9"], "internal": true
+            }],
+            "startup_dag": [{ "from": "app-1", "to": "app-0", "reason": "Connects to it" }],
+            "artifacts": [{
+                "cluster_id": "app-0", "artifact_type": "dockerfile", "path": "app-0/Dockerfile",
+                "description": "Dockerfile", "content_hash": "abc", "preserved": false,
+                "build": {
+                    "success": true, "image": "xcprobe/app-0", "log_path": "app-0/build.log",
+                    "duration_ms": 1200
+                }
+            }],
+            "overall_confidence": 0.9,
+            "warnings": [{
+                "code": "LOW_CONFIDENCE", "message": "Low confidence",
+                "severity": "warning", "affected_clusters": ["app-0"],
+                "evidence_refs": ["evidence/ps_1.txt"]
+            }],
+            "rejected_clusters": [{
+                "id": "app-2", "name": "helper", "app_type": "worker", "confidence": 0.2,
+                "reason": "Below the minimum confidence", "evidence_refs": [], "decisions": []
+            }]
+        })
+    }
+
+    /// Remove every field but `keep` from the objects of `array`.
+    fn sparse(document: &mut Value, array: &str, keep: &[&str]) {
+        if let Some(items) = document.get_mut(array).and_then(Value::as_array_mut) {
+            for item in items.iter_mut().filter_map(Value::as_object_mut) {
+                item.retain(|name, _| keep.contains(&name.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_manifest_schema_matches_types() {
+        let full: Manifest = serde_json::from_value(full_manifest()).unwrap();
+        assert_matches_schema(SchemaKind::Manifest, &serde_json::to_value(&full).unwrap());
+
+        // Unset optional fields serialize as null
+        let mut minimal = full_manifest();
+        minimal["system"] = json!({ "hostname": "web01", "os_type": "windows" });
+        sparse(
+            &mut minimal,
+            "processes",
+            &["pid", "ppid", "user", "command", "args", "full_cmdline"],
+        );
+        sparse(
+            &mut minimal,
+            "services",
+            &[
+                "name",
+                "state",
+                "exec_start_pre",
+                "exec_start_post",
+                "environment",
+                "environment_files",
+                "dependencies",
+                "wanted_by",
+            ],
+        );
+        sparse(
+            &mut minimal,
+            "ports",
+            &["protocol", "local_address", "local_port", "state"],
+        );
+        sparse(&mut minimal, "packages", &["name", "version", "source"]);
+        let minimal: Manifest = serde_json::from_value(minimal).unwrap();
+        assert_matches_schema(
+            SchemaKind::Manifest,
+            &serde_json::to_value(&minimal).unwrap(),
+        );
+
+        let mut default = Manifest::default();
+        default.system.os_type = "linux".to_string();
+        assert_matches_schema(
+            SchemaKind::Manifest,
+            &serde_json::to_value(&default).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_packplan_schema_matches_types() {
+        let full: PackPlan = serde_json::from_value(full_plan()).unwrap();
+        assert_matches_schema(SchemaKind::Packplan, &serde_json::to_value(&full).unwrap());
+
+        let mut minimal = full_plan();
+        minimal["source_os"] = Value::Null;
+        let cluster = &mut minimal["clusters"][0];
+        for optional in [
+            "description",
+            "readiness",
+            "runtime",
+            "base_image",
+            "schedule",
+            "java",
+            "replicas",
+            "resources",
+            "database",
+        ] {
+            cluster.as_object_mut().unwrap().remove(optional);
+        }
+        let minimal: PackPlan = serde_json::from_value(minimal).unwrap();
+        assert_matches_schema(
+            SchemaKind::Packplan,
+            &serde_json::to_value(&minimal).unwrap(),
+        );
+
+        assert_matches_schema(
+            SchemaKind::Packplan,
+            &serde_json::to_value(PackPlan::default()).unwrap(),
+        );
+    }
 }
@@ -0,0 +1,150 @@
+//! Ground truth of an E2E scenario (`truth.json`): the applications, ports
+//! and dependencies analysis is expected to find.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Ground truth for a test scenario.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Truth {
+    /// Schema version
+    pub version: String,
+    /// Scenario name
+    #[schemars(length(min = 1))]
+    pub name: String,
+    /// Description
+    pub description: Option<String>,
+    /// Expected applications
+    pub applications: Vec<ExpectedApp>,
+    /// Expected ports
+    pub ports: Vec<ExpectedPort>,
+    /// Expected environment variable names
+    pub env_names: Vec<String>,
+    /// Expected dependencies
+    pub dependencies: Vec<ExpectedDependency>,
+    /// Expected config files
+    pub config_files: Vec<String>,
+    /// Thresholds for pass/fail
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    /// Items that must not be detected
+    #[serde(default)]
+    pub must_not_contain: ForbiddenItems,
+}
+
+/// Items whose detection is a false positive.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ForbiddenItems {
+    /// Ports no cluster may expose
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Names or IDs no cluster may have
+    #[serde(default)]
+    pub clusters: Vec<String>,
+    /// Environment variable names no cluster may declare
+    #[serde(default)]
+    pub env_names: Vec<String>,
+}
+
+/// Expected application.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ExpectedApp {
+    /// Application name
+    pub name: String,
+    /// Application type
+    pub app_type: String,
+    /// Expected command patterns
+    pub command_patterns: Vec<String>,
+    /// Expected user
+    pub user: Option<String>,
+    /// Expected working directory
+    pub working_directory: Option<String>,
+}
+
+/// Expected port.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ExpectedPort {
+    pub port: u16,
+    pub protocol: String,
+    pub process_name: Option<String>,
+}
+
+/// Expected dependency.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ExpectedDependency {
+    pub from: String,
+    pub to: String,
+    pub dep_type: String,
+}
+
+/// Thresholds for pass/fail determination.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Thresholds {
+    /// Minimum process/cmdline recall
+    #[serde(default = "default_process_recall")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub process_cmdline_recall: f64,
+    /// Minimum ports recall
+    #[serde(default = "default_ports_recall")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub ports_recall: f64,
+    /// Minimum env names recall
+    #[serde(default = "default_env_recall")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub env_names_recall: f64,
+    /// Minimum dependencies recall
+    #[serde(default = "default_deps_recall")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub deps_recall: f64,
+    /// All decisions must have evidence
+    #[serde(default = "default_require_evidence")]
+    pub require_all_evidence: bool,
+    /// Minimum process/cmdline precision, if checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub process_cmdline_precision: Option<f64>,
+    /// Minimum ports precision, if checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub ports_precision: Option<f64>,
+    /// Minimum env names precision, if checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub env_names_precision: Option<f64>,
+    /// Minimum dependencies precision, if checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub deps_precision: Option<f64>,
+}
+
+fn default_process_recall() -> f64 {
+    0.9
+}
+fn default_ports_recall() -> f64 {
+    0.95
+}
+fn default_env_recall() -> f64 {
+    0.8
+}
+fn default_deps_recall() -> f64 {
+    0.8
+}
+fn default_require_evidence() -> bool {
+    true
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            process_cmdline_recall: default_process_recall(),
+            ports_recall: default_ports_recall(),
+            env_names_recall: default_env_recall(),
+            deps_recall: default_deps_recall(),
+            require_all_evidence: default_require_evidence(),
+            process_cmdline_precision: None,
+            ports_precision: None,
+            env_names_precision: None,
+            deps_precision: None,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 //! Bundle validation utilities.
 
-use crate::schema;
+use crate::schema::{self, SchemaKind};
 use jsonschema::JSONSchema;
 use serde_json::Value;
 use std::collections::HashSet;
@@ -25,6 +25,15 @@ pub enum ValidationError {
     #[error("Invalid evidence reference: {0}")]
     InvalidEvidenceRef(String),
 
+    #[error("File not listed in checksums.json: {0}")]
+    UnlistedFile(String),
+
+    #[error("Corrupt file {file}: {reason}")]
+    CorruptFile { file: String, reason: String },
+
+    #[error("Audit entry {seq} references missing evidence {evidence_ref}")]
+    InvalidAuditRef { seq: u64, evidence_ref: String },
+
     #[error("Decision without evidence: {0}")]
     DecisionWithoutEvidence(String),
 
@@ -70,13 +79,29 @@ impl Default for ValidationResult {
 
 /// Validate a manifest against the JSON schema.
 pub fn validate_manifest(manifest: &Value) -> Result<ValidationResult, ValidationError> {
+    validate_against(&schema::manifest_schema(), manifest)
+}
+
+/// Validate a document of any published schema: the schema checks, plus
+/// the pack plan's evidence checks.
+pub fn validate_document(
+    kind: SchemaKind,
+    document: &Value,
+) -> Result<ValidationResult, ValidationError> {
+    match kind {
+        SchemaKind::Manifest => validate_manifest(document),
+        SchemaKind::Packplan => validate_packplan(document),
+        SchemaKind::Truth => validate_against(&kind.schema(), document),
+    }
+}
+
+fn validate_against(schema: &Value, document: &Value) -> Result<ValidationResult, ValidationError> {
     let mut result = ValidationResult::new();
 
-    let schema_value = schema::manifest_schema();
-    let compiled = JSONSchema::compile(&schema_value)
-        .map_err(|e| ValidationError::SchemaError(e.to_string()))?;
+    let compiled =
+        JSONSchema::compile(schema).map_err(|e| ValidationError::SchemaError(e.to_string()))?;
 
-    let validation = compiled.validate(manifest);
+    let validation = compiled.validate(document);
     if let Err(errors) = validation {
         for error in errors {
             result.add_error(ValidationError::SchemaError(format!(
@@ -91,21 +116,7 @@ pub fn validate_manifest(manifest: &Value) -> Result<ValidationResult, Validatio
 
 /// Validate a packplan against the JSON schema.
 pub fn validate_packplan(packplan: &Value) -> Result<ValidationResult, ValidationError> {
-    let mut result = ValidationResult::new();
-
-    let schema_value = schema::packplan_schema();
-    let compiled = JSONSchema::compile(&schema_value)
-        .map_err(|e| ValidationError::SchemaError(e.to_string()))?;
-
-    let validation = compiled.validate(packplan);
-    if let Err(errors) = validation {
-        for error in errors {
-            result.add_error(ValidationError::SchemaError(format!(
-                "{} at {}",
-                error, error.instance_path
-            )));
-        }
-    }
+    let mut result = validate_against(&schema::packplan_schema(), packplan)?;
 
     // Check that all decisions have evidence
     if let Some(clusters) = packplan.get("clusters").and_then(|c| c.as_array()) {
@@ -184,6 +195,22 @@ pub fn validate_bundle(
         }
     }
 
+    let runtime_refs = manifest
+        .runtimes
+        .iter()
+        .filter_map(|r| r.evidence_ref.as_ref())
+        .chain(
+            manifest
+                .binaries
+                .iter()
+                .filter_map(|b| b.evidence_ref.as_ref()),
+        );
+    for evidence_ref in runtime_refs {
+        if !evidence_files.contains(evidence_ref) {
+            result.add_error(ValidationError::InvalidEvidenceRef(evidence_ref.clone()));
+        }
+    }
+
     Ok(result)
 }
 
@@ -203,11 +230,20 @@ mod tests {
             },
             "processes": [],
             "services": [],
-            "ports": []
+            "ports": [],
+            "connections": [],
+            "packages": [],
+            "scheduled_tasks": [],
+            "config_files": [],
+            "log_files": [],
+            "environment_files": [],
+            "collection_mode": "local",
+            "errors": []
         });
 
         let result = validate_manifest(&manifest).unwrap();
         assert!(result.valid, "Errors: {:?}", result.errors);
+        serde_json::from_value::<crate::Manifest>(manifest).unwrap();
     }
 
     #[test]
@@ -220,4 +256,23 @@ mod tests {
         let result = validate_manifest(&manifest).unwrap();
         assert!(!result.valid);
     }
+
+    #[test]
+    fn test_validate_truth_documents() {
+        for truth in [
+            include_str!("../../../tests/scenarios/scenario_a_basic_multi_proc_host/truth.json"),
+            include_str!("../../../tests/scenarios/scenario_b_wrapper_and_env_files/truth.json"),
+            include_str!("../../../tests/scenarios/scenario_c_batch_only_no_ports/truth.json"),
+            include_str!("../../../tests/scenarios/scenario_d_logs_stdout_only/truth.json"),
+        ] {
+            let document: Value = serde_json::from_str(truth).unwrap();
+            let result = validate_document(SchemaKind::Truth, &document).unwrap();
+            assert!(result.valid, "Errors: {:?}", result.errors);
+        }
+
+        let kind: SchemaKind = "truth".parse().unwrap();
+        let result = validate_document(kind, &serde_json::json!({ "version": "1.0" })).unwrap();
+        assert!(!result.valid);
+        assert!("bundle".parse::<SchemaKind>().is_err());
+    }
 }
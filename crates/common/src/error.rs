@@ -1,5 +1,11 @@
 //! Common error types for xcprobe.
+//!
+//! Errors fall into [`ErrorCategory`]s, each with its own process exit code,
+//! so scripts driving the CLI can tell a host that could not be reached from
+//! a bundle that failed validation.
 
+use serde::Serialize;
+use std::fmt;
 use thiserror::Error;
 
 /// Common error type for xcprobe operations.
@@ -38,6 +44,18 @@ pub enum Error {
     #[error("WinRM connection failed: {0}")]
     WinRmConnection(String),
 
+    #[error("WinRM authentication failed: {0}")]
+    WinRmAuth(String),
+
+    #[error("Partial collection: {errors} commands failed")]
+    PartialCollection { errors: usize },
+
+    #[error("Invalid pack plan: {0}")]
+    InvalidPlan(String),
+
+    #[error("Overall confidence {confidence:.2} is below the minimum {minimum:.2}")]
+    LowConfidence { confidence: f64, minimum: f64 },
+
     #[error("Configuration error: {0}")]
     Config(String),
 
@@ -62,3 +80,119 @@ impl From<anyhow::Error> for Error {
         Error::Other(e.to_string())
     }
 }
+
+impl Error {
+    /// Category of the error, which sets the exit code.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::SshConnection(_) | Error::WinRmConnection(_) => ErrorCategory::Connection,
+            Error::SshAuth(_) | Error::WinRmAuth(_) => ErrorCategory::Auth,
+            Error::PartialCollection { .. } => ErrorCategory::PartialCollection,
+            Error::InvalidBundle(_)
+            | Error::InvalidPlan(_)
+            | Error::SchemaValidation(_)
+            | Error::MissingEvidence { .. } => ErrorCategory::Validation,
+            Error::LowConfidence { .. } => ErrorCategory::LowConfidence,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
+/// Failure category reported by the CLI.
+///
+/// Exit code 2 is left to command line usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Any other failure.
+    Other,
+    /// The target could not be reached.
+    Connection,
+    /// The target rejected the credentials.
+    Auth,
+    /// The bundle was written, but some commands failed.
+    PartialCollection,
+    /// A bundle or pack plan failed validation.
+    Validation,
+    /// No application reached the minimum confidence.
+    LowConfidence,
+}
+
+impl ErrorCategory {
+    /// Process exit code of the category.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorCategory::Other => 1,
+            ErrorCategory::Connection => 3,
+            ErrorCategory::Auth => 4,
+            ErrorCategory::PartialCollection => 5,
+            ErrorCategory::Validation => 6,
+            ErrorCategory::LowConfidence => 7,
+        }
+    }
+
+    /// Category of an error: that of the first [`Error`] in its chain of
+    /// causes, so context added on the way up does not hide it.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Error>())
+            .map_or(ErrorCategory::Other, Error::category)
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCategory::Other => write!(f, "other"),
+            ErrorCategory::Connection => write!(f, "connection"),
+            ErrorCategory::Auth => write!(f, "auth"),
+            ErrorCategory::PartialCollection => write!(f, "partial_collection"),
+            ErrorCategory::Validation => write!(f, "validation"),
+            ErrorCategory::LowConfidence => write!(f, "low_confidence"),
+        }
+    }
+}
+
+/// Machine-readable summary of a failed command, written as one JSON line
+/// on stderr.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorSummary {
+    pub error: ErrorCategory,
+    pub exit_code: u8,
+    pub message: String,
+}
+
+impl ErrorSummary {
+    pub fn new(error: &anyhow::Error) -> Self {
+        let category = ErrorCategory::of(error);
+        Self {
+            error: category,
+            exit_code: category.exit_code(),
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_category_survives_context() {
+        let error = Err::<(), _>(Error::SshAuth("root@web01".to_string()))
+            .context("Failed to connect to web01")
+            .unwrap_err();
+        let summary = ErrorSummary::new(&error);
+        assert_eq!(summary.error, ErrorCategory::Auth);
+        assert_eq!(summary.exit_code, 4);
+        assert_eq!(
+            summary.message,
+            "Failed to connect to web01: SSH authentication failed: root@web01"
+        );
+
+        let error = anyhow::anyhow!("disk full");
+        assert_eq!(ErrorCategory::of(&error).exit_code(), 1);
+    }
+}
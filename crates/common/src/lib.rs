@@ -5,6 +5,6 @@ pub mod hash;
 pub mod os;
 pub mod timestamp;
 
-pub use error::{Error, Result};
+pub use error::{Error, ErrorCategory, ErrorSummary, Result};
 pub use os::OsType;
 pub use timestamp::Timestamp;
@@ -10,6 +10,9 @@ use std::str::FromStr;
 pub enum OsType {
     Linux,
     Windows,
+    MacOs,
+    /// Other Unix-like systems (FreeBSD, Linux without systemd).
+    Unix,
 }
 
 impl fmt::Display for OsType {
@@ -17,6 +20,8 @@ impl fmt::Display for OsType {
         match self {
             OsType::Linux => write!(f, "linux"),
             OsType::Windows => write!(f, "windows"),
+            OsType::MacOs => write!(f, "macos"),
+            OsType::Unix => write!(f, "unix"),
         }
     }
 }
@@ -28,6 +33,8 @@ impl FromStr for OsType {
         match s.to_lowercase().as_str() {
             "linux" => Ok(OsType::Linux),
             "windows" => Ok(OsType::Windows),
+            "macos" | "darwin" => Ok(OsType::MacOs),
+            "unix" | "freebsd" | "bsd" => Ok(OsType::Unix),
             _ => Err(crate::Error::UnsupportedOs(s.to_string())),
         }
     }
@@ -43,6 +50,28 @@ impl OsType {
     pub fn is_windows(&self) -> bool {
         matches!(self, OsType::Windows)
     }
+
+    /// Check if the OS is macOS.
+    pub fn is_macos(&self) -> bool {
+        matches!(self, OsType::MacOs)
+    }
+
+    /// The operating system this binary runs on.
+    pub fn host() -> Self {
+        if cfg!(target_os = "windows") {
+            OsType::Windows
+        } else if cfg!(target_os = "macos") {
+            OsType::MacOs
+        } else if cfg!(any(
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd"
+        )) {
+            OsType::Unix
+        } else {
+            OsType::Linux
+        }
+    }
 }
 
 #[cfg(test)]
@@ -56,11 +85,16 @@ mod tests {
         assert_eq!(OsType::from_str("LINUX").unwrap(), OsType::Linux);
         assert_eq!(OsType::from_str("windows").unwrap(), OsType::Windows);
         assert_eq!(OsType::from_str("Windows").unwrap(), OsType::Windows);
+        assert_eq!(OsType::from_str("macOS").unwrap(), OsType::MacOs);
+        assert_eq!(OsType::from_str("darwin").unwrap(), OsType::MacOs);
+        assert_eq!(OsType::from_str("freebsd").unwrap(), OsType::Unix);
     }
 
     #[test]
     fn test_display_os_type() {
         assert_eq!(OsType::Linux.to_string(), "linux");
         assert_eq!(OsType::Windows.to_string(), "windows");
+        assert_eq!(OsType::MacOs.to_string(), "macos");
+        assert_eq!(OsType::Unix.to_string(), "unix");
     }
 }
@@ -0,0 +1,407 @@
+//! Draft truth files from compose scenarios.
+//!
+//! Services running well-known infrastructure images (databases, caches,
+//! brokers) are dependencies; every other service is an expected
+//! application, with its command, user, working directory, ports and
+//! environment. The draft keeps the default thresholds and is meant to be
+//! refined by the scenario author, for instance to list the programs a
+//! simulated host runs under a supervisor.
+
+use crate::truth::{
+    ExpectedApp, ExpectedDependency, ExpectedPort, ForbiddenItems, Thresholds, Truth,
+};
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::path::Path;
+
+/// Image names of infrastructure services, with the dependency type of an
+/// application using them.
+const INFRASTRUCTURE_IMAGES: &[(&str, &str)] = &[
+    ("postgres", "database"),
+    ("mysql", "database"),
+    ("mariadb", "database"),
+    ("mongo", "database"),
+    ("mssql", "database"),
+    ("oracle", "database"),
+    ("redis", "cache"),
+    ("memcached", "cache"),
+    ("rabbitmq", "queue"),
+    ("kafka", "queue"),
+    ("nats", "queue"),
+    ("activemq", "queue"),
+    ("elasticsearch", "search"),
+    ("opensearch", "search"),
+];
+
+/// Draft a truth file from the compose file at `path`, named after its
+/// directory.
+pub fn draft_truth_file(path: &Path) -> Result<Truth> {
+    let content = std::fs::read_to_string(path).context("Failed to read compose file")?;
+    let compose: Value = serde_yaml::from_str(&content).context("Failed to parse compose file")?;
+    let name = path
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.parent()?.file_name()?.to_str().map(str::to_string))
+        .unwrap_or_else(|| "scenario".to_string());
+    Ok(draft_truth(&compose, &name))
+}
+
+/// Draft a truth file from a parsed compose file.
+pub fn draft_truth(compose: &Value, name: &str) -> Truth {
+    let services: Vec<(&str, &Value)> = compose
+        .get("services")
+        .and_then(Value::as_mapping)
+        .map(|services| {
+            services
+                .iter()
+                .filter_map(|(name, service)| Some((name.as_str()?, service)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let dep_type = |service: &str| {
+        services
+            .iter()
+            .find(|(name, _)| *name == service)
+            .and_then(|(_, s)| infrastructure_type(s))
+            .unwrap_or("service")
+    };
+
+    let mut truth = Truth {
+        version: "1.0".to_string(),
+        name: name.to_string(),
+        description: Some(format!(
+            "Draft generated from the compose file of {}; refine before use",
+            name
+        )),
+        applications: Vec::new(),
+        ports: Vec::new(),
+        env_names: Vec::new(),
+        dependencies: Vec::new(),
+        config_files: Vec::new(),
+        thresholds: Thresholds::default(),
+        must_not_contain: ForbiddenItems::default(),
+    };
+
+    for (service_name, service) in &services {
+        if infrastructure_type(service).is_some() {
+            continue;
+        }
+        let command = command_patterns(service);
+        let ports = service_ports(service);
+        truth.applications.push(ExpectedApp {
+            name: service_name.to_string(),
+            app_type: if ports.is_empty() { "worker" } else { "api" }.to_string(),
+            user: string(service, "user"),
+            working_directory: string(service, "working_dir"),
+            command_patterns: command.clone(),
+        });
+        for (port, protocol) in ports {
+            if !truth.ports.iter().any(|p| p.port == port) {
+                truth.ports.push(ExpectedPort {
+                    port,
+                    protocol,
+                    process_name: command.first().cloned(),
+                });
+            }
+        }
+
+        let environment = environment(service);
+        for (key, _) in &environment {
+            if !truth.env_names.contains(key) {
+                truth.env_names.push(key.clone());
+            }
+        }
+
+        // Explicit dependencies, then services named as hosts in the
+        // environment, such as DATABASE_URL=postgres://db:5432/app
+        let mut targets = depends_on(service);
+        for (other, _) in &services {
+            let referenced = environment
+                .iter()
+                .any(|(_, value)| names_host(value, other));
+            if other != service_name && referenced && !targets.iter().any(|t| t == other) {
+                targets.push(other.to_string());
+            }
+        }
+        for target in targets {
+            truth.dependencies.push(ExpectedDependency {
+                from: service_name.to_string(),
+                dep_type: dep_type(&target).to_string(),
+                to: target,
+            });
+        }
+
+        for target in mounted_files(service) {
+            if !truth.config_files.contains(&target) {
+                truth.config_files.push(target);
+            }
+        }
+    }
+    truth
+}
+
+/// The dependency type of a service running an infrastructure image.
+fn infrastructure_type(service: &Value) -> Option<&'static str> {
+    let image = service.get("image")?.as_str()?;
+    // registry/namespace/name:tag
+    let name = image
+        .rsplit('/')
+        .next()?
+        .split([':', '@'])
+        .next()?
+        .to_ascii_lowercase();
+    INFRASTRUCTURE_IMAGES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, dep_type)| *dep_type)
+}
+
+fn string(service: &Value, key: &str) -> Option<String> {
+    service.get(key)?.as_str().map(str::to_string)
+}
+
+/// The executable name and the script or module arguments of the service
+/// command, e.g. `python3 app.py` from `["/usr/bin/python3", "app.py"]`.
+fn command_patterns(service: &Value) -> Vec<String> {
+    let words = |key: &str| -> Vec<String> {
+        match service.get(key) {
+            Some(Value::String(command)) => {
+                command.split_whitespace().map(str::to_string).collect()
+            }
+            Some(Value::Sequence(words)) => words
+                .iter()
+                .filter_map(|w| w.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let mut words = words("entrypoint").into_iter().chain(words("command"));
+    let Some(executable) = words.next() else {
+        return Vec::new();
+    };
+    std::iter::once(
+        executable
+            .rsplit('/')
+            .next()
+            .unwrap_or(&executable)
+            .to_string(),
+    )
+    .chain(words.filter(|w| !w.starts_with('-')).take(1))
+    .collect()
+}
+
+/// Container ports of `ports` (short or long syntax) and `expose`, with
+/// their protocol.
+fn service_ports(service: &Value) -> Vec<(u16, String)> {
+    let mut ports = Vec::new();
+    let entries = ["ports", "expose"]
+        .iter()
+        .filter_map(|key| service.get(*key)?.as_sequence())
+        .flatten();
+    for entry in entries {
+        let (spec, protocol) = match entry {
+            Value::Mapping(_) => (
+                entry.get("target").map(scalar).unwrap_or_default(),
+                entry
+                    .get("protocol")
+                    .and_then(Value::as_str)
+                    .unwrap_or("tcp")
+                    .to_string(),
+            ),
+            _ => {
+                let spec = scalar(entry);
+                match spec.split_once('/') {
+                    Some((spec, protocol)) => (spec.to_string(), protocol.to_string()),
+                    None => (spec, "tcp".to_string()),
+                }
+            }
+        };
+        // [host_ip:][host_port:]container_port, which may be a range
+        let container = spec.rsplit(':').next().unwrap_or_default();
+        let container = container.split('-').next().unwrap_or_default();
+        if let Ok(port) = container.parse::<u16>() {
+            if !ports.iter().any(|(p, _)| *p == port) {
+                ports.push((port, protocol));
+            }
+        }
+    }
+    ports
+}
+
+/// `environment` entries, from a mapping or `KEY=value` list.
+fn environment(service: &Value) -> Vec<(String, String)> {
+    match service.get("environment") {
+        Some(Value::Mapping(map)) => map
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = if value.is_null() {
+                    String::new()
+                } else {
+                    scalar(value)
+                };
+                Some((key.as_str()?.to_string(), value))
+            })
+            .collect(),
+        Some(Value::Sequence(entries)) => entries
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|entry| match entry.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => (entry.to_string(), String::new()),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Services of `depends_on`, a list or a mapping with conditions.
+fn depends_on(service: &Value) -> Vec<String> {
+    match service.get("depends_on") {
+        Some(Value::Sequence(names)) => names
+            .iter()
+            .filter_map(|n| n.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::Mapping(map)) => map
+            .keys()
+            .filter_map(|n| n.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `value` names `host` as a host: `//host`, `@host` or `host:`
+/// in a URL, or the whole value.
+fn names_host(value: &str, host: &str) -> bool {
+    value == host
+        || value.match_indices(host).any(|(i, _)| {
+            let before = value[..i].chars().next_back();
+            let after = value[i + host.len()..].chars().next();
+            matches!(before, Some('/' | '@')) && matches!(after, None | Some(':' | '/'))
+        })
+}
+
+/// Container paths of files bind-mounted from the scenario directory.
+fn mounted_files(service: &Value) -> Vec<String> {
+    let Some(volumes) = service.get("volumes").and_then(Value::as_sequence) else {
+        return Vec::new();
+    };
+    volumes
+        .iter()
+        .filter_map(|volume| match volume {
+            Value::Mapping(_) => Some((
+                volume.get("source")?.as_str()?.to_string(),
+                volume.get("target")?.as_str()?.to_string(),
+            )),
+            _ => {
+                let mut parts = volume.as_str()?.split(':');
+                Some((parts.next()?.to_string(), parts.next()?.to_string()))
+            }
+        })
+        .filter(|(source, target)| {
+            let bind = source.starts_with('.') || source.starts_with('/');
+            let file = target
+                .rsplit('/')
+                .next()
+                .is_some_and(|name| name.contains('.'));
+            bind && file
+        })
+        .map(|(_, target)| target)
+        .collect()
+}
+
+/// A scalar as a string, e.g. a port given as a number.
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draft_truth() {
+        let compose: Value = serde_yaml::from_str(
+            r#"
+services:
+  api:
+    build: ./api
+    command: ["/usr/bin/python3", "-u", "app.py"]
+    user: api-user
+    working_dir: /opt/api
+    ports: ["8080:80", "127.0.0.1:9090:9090/udp"]
+    environment:
+      DATABASE_URL: postgres://app@db:5432/app
+      LOG_LEVEL: info
+    volumes:
+      - ./api.conf:/etc/api/api.conf:ro
+      - data:/var/lib/api
+    depends_on:
+      cache:
+        condition: service_healthy
+  worker:
+    image: example/worker:1.2
+    entrypoint: /opt/worker/run.sh
+    environment:
+      - QUEUE=jobs
+      - REDIS_HOST=cache
+  db:
+    image: postgres:15-alpine
+  cache:
+    image: docker.io/library/redis:7
+"#,
+        )
+        .unwrap();
+
+        let truth = draft_truth(&compose, "scenario_x");
+
+        let apps: Vec<(&str, &str, Vec<&str>)> = truth
+            .applications
+            .iter()
+            .map(|a| {
+                (
+                    a.name.as_str(),
+                    a.app_type.as_str(),
+                    a.command_patterns.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            apps,
+            vec![
+                ("api", "api", vec!["python3", "app.py"]),
+                ("worker", "worker", vec!["run.sh"]),
+            ]
+        );
+        assert_eq!(truth.applications[0].user.as_deref(), Some("api-user"));
+        let ports: Vec<(u16, &str)> = truth
+            .ports
+            .iter()
+            .map(|p| (p.port, p.protocol.as_str()))
+            .collect();
+        assert_eq!(ports, vec![(80, "tcp"), (9090, "udp")]);
+        assert_eq!(
+            truth.env_names,
+            vec!["DATABASE_URL", "LOG_LEVEL", "QUEUE", "REDIS_HOST"]
+        );
+        let deps: Vec<(&str, &str, &str)> = truth
+            .dependencies
+            .iter()
+            .map(|d| (d.from.as_str(), d.to.as_str(), d.dep_type.as_str()))
+            .collect();
+        assert_eq!(
+            deps,
+            vec![
+                ("api", "cache", "cache"),
+                ("api", "db", "database"),
+                ("worker", "cache", "cache"),
+            ]
+        );
+        assert_eq!(truth.config_files, vec!["/etc/api/api.conf"]);
+        assert!(crate::truth::validate_truth(&truth).errors.is_empty());
+    }
+}
@@ -2,15 +2,18 @@
 //!
 //! Runs end-to-end test scenarios and validates results against truth files.
 
+mod compose;
 mod metrics;
 mod report;
 mod runner;
+mod scenario;
 mod truth;
+mod validate;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 #[derive(Parser)]
@@ -44,6 +47,24 @@ enum Commands {
         /// Timeout in seconds
         #[arg(long, default_value = "300")]
         timeout: u64,
+
+        /// Write a JUnit XML report to this file
+        #[arg(long)]
+        junit: Option<PathBuf>,
+
+        /// Print GitHub Actions annotations for the failures
+        #[arg(long)]
+        github_annotations: bool,
+
+        /// Build the generated Dockerfiles and start the generated compose
+        /// file, failing unless every service becomes healthy
+        #[arg(long)]
+        validate_artifacts: bool,
+
+        /// Time allowed to build and start the generated artifacts of a
+        /// target, in seconds
+        #[arg(long, default_value = "600")]
+        validate_timeout: u64,
     },
 
     /// Run all test scenarios in a directory
@@ -59,6 +80,28 @@ enum Commands {
         /// Continue on failure
         #[arg(long)]
         continue_on_failure: bool,
+
+        /// Scenarios run at the same time, each as its own compose project
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: u16,
+
+        /// Write a JUnit XML report to this file
+        #[arg(long)]
+        junit: Option<PathBuf>,
+
+        /// Print GitHub Actions annotations for the failures
+        #[arg(long)]
+        github_annotations: bool,
+
+        /// Build the generated Dockerfiles and start the generated compose
+        /// file, failing unless every service becomes healthy
+        #[arg(long)]
+        validate_artifacts: bool,
+
+        /// Time allowed to build and start the generated artifacts of a
+        /// target, in seconds
+        #[arg(long, default_value = "600")]
+        validate_timeout: u64,
     },
 
     /// Validate a truth.json file
@@ -68,13 +111,24 @@ enum Commands {
         truth: PathBuf,
     },
 
+    /// Draft a truth.json from a scenario's compose file
+    GenerateTruth {
+        /// Path to compose.yaml
+        #[arg(long)]
+        compose: PathBuf,
+
+        /// Output file (default: stdout)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
     /// Generate a report from test results
     Report {
         /// Directory containing test results
         #[arg(long)]
         results: PathBuf,
 
-        /// Output format (text, json, html)
+        /// Output format (text, json, html, junit, github)
         #[arg(long, default_value = "text")]
         format: String,
     },
@@ -102,17 +156,28 @@ async fn main() -> Result<()> {
             artifacts,
             keep_running,
             timeout,
+            junit,
+            github_annotations,
+            validate_artifacts,
+            validate_timeout,
         } => {
             info!("Running scenario: {:?}", scenario);
 
             let config = runner::RunConfig {
+                project_name: runner::project_name(&scenario),
                 scenario_path: scenario,
                 artifacts_path: artifacts,
                 keep_running,
                 timeout_seconds: timeout,
+                validate_timeout: validate_artifacts.then_some(validate_timeout),
             };
 
             let result = runner::run_scenario(&config).await?;
+            write_ci_reports(
+                std::slice::from_ref(&result),
+                junit.as_deref(),
+                github_annotations,
+            )?;
 
             // Print summary
             println!("\n=== Test Results ===");
@@ -141,10 +206,47 @@ async fn main() -> Result<()> {
                 "  Dependencies Recall: {:.2}%",
                 result.metrics.deps_recall * 100.0
             );
+            println!(
+                "  Process/Cmdline Precision: {:.2}%",
+                result.metrics.process_cmdline_precision * 100.0
+            );
+            println!(
+                "  Ports Precision: {:.2}%",
+                result.metrics.ports_precision * 100.0
+            );
+            println!(
+                "  Env Names Precision: {:.2}%",
+                result.metrics.env_names_precision * 100.0
+            );
+            println!(
+                "  Dependencies Precision: {:.2}%",
+                result.metrics.deps_precision * 100.0
+            );
             println!(
                 "  Decisions with Evidence: {:.2}%",
                 result.metrics.decisions_with_evidence_ratio * 100.0
             );
+            let metrics = &result.metrics;
+            let forbidden: Vec<String> = metrics
+                .forbidden_ports
+                .iter()
+                .map(|port| format!("port {}", port))
+                .chain(
+                    metrics
+                        .forbidden_clusters
+                        .iter()
+                        .map(|c| format!("cluster {}", c)),
+                )
+                .chain(
+                    metrics
+                        .forbidden_env_names
+                        .iter()
+                        .map(|e| format!("env {}", e)),
+                )
+                .collect();
+            if !forbidden.is_empty() {
+                println!("  Forbidden Items Detected: {}", forbidden.join(", "));
+            }
 
             if !result.passed {
                 println!("\nFailures:");
@@ -159,49 +261,87 @@ async fn main() -> Result<()> {
             scenarios_dir,
             artifacts,
             continue_on_failure,
+            jobs,
+            junit,
+            github_annotations,
+            validate_artifacts,
+            validate_timeout,
         } => {
             info!("Running all scenarios in: {:?}", scenarios_dir);
 
+            // Scenarios in name order, for a stable summary
+            let mut scenario_paths = Vec::new();
+            for entry in std::fs::read_dir(&scenarios_dir)? {
+                let path = entry?.path();
+                if path.is_dir() && path.join("truth.json").exists() {
+                    scenario_paths.push(path);
+                }
+            }
+            scenario_paths.sort();
+
+            // Windows scenarios run only against a configured host
+            scenario_paths.retain(|path| match scenario::load_scenario(path) {
+                Ok(scenario) if scenario.winrm.as_ref().is_some_and(|w| w.host.is_none()) => {
+                    warn!(
+                        "Skipping {:?}: no Windows host configured (XCPROBE_E2E_WINRM_HOST)",
+                        path
+                    );
+                    false
+                }
+                _ => true,
+            });
+
+            let configs = scenario_paths
+                .iter()
+                .map(|scenario_path| runner::RunConfig {
+                    project_name: runner::project_name(scenario_path),
+                    scenario_path: scenario_path.clone(),
+                    artifacts_path: artifacts.join(scenario_path.file_name().unwrap_or_default()),
+                    keep_running: false,
+                    timeout_seconds: 300,
+                    validate_timeout: validate_artifacts.then_some(validate_timeout),
+                })
+                .collect();
+            let outcomes =
+                runner::run_scenarios(configs, usize::from(jobs), continue_on_failure).await?;
+
             let mut results = Vec::new();
             let mut all_passed = true;
-
-            for entry in std::fs::read_dir(&scenarios_dir)? {
-                let entry = entry?;
-                if entry.path().is_dir() {
-                    let scenario_path = entry.path();
-                    let truth_path = scenario_path.join("truth.json");
-
-                    if truth_path.exists() {
-                        info!("Running scenario: {:?}", scenario_path);
-
-                        let config = runner::RunConfig {
-                            scenario_path: scenario_path.clone(),
-                            artifacts_path: artifacts.join(entry.file_name()),
-                            keep_running: false,
-                            timeout_seconds: 300,
-                        };
-
-                        match runner::run_scenario(&config).await {
-                            Ok(result) => {
-                                if !result.passed {
-                                    all_passed = false;
-                                }
-                                results.push(result);
-                            }
-                            Err(e) => {
-                                tracing::error!("Scenario {:?} failed: {}", scenario_path, e);
-                                all_passed = false;
-                                if !continue_on_failure {
-                                    return Err(e);
-                                }
-                            }
+            let mut first_error = None;
+            for (scenario_path, outcome) in outcomes {
+                match outcome {
+                    Ok(result) => {
+                        if !result.passed {
+                            all_passed = false;
                         }
+                        results.push(result);
+                    }
+                    Err(e) => {
+                        tracing::error!("Scenario {:?} failed: {}", scenario_path, e);
+                        all_passed = false;
+                        let name = scenario_path.file_name().unwrap_or_default();
+                        results.push(runner::RunResult::failed_to_run(
+                            &name.to_string_lossy(),
+                            &e,
+                        ));
+                        first_error.get_or_insert(e);
                     }
                 }
             }
+            write_ci_reports(&results, junit.as_deref(), github_annotations)?;
+            if let Some(e) = first_error.filter(|_| !continue_on_failure) {
+                return Err(e);
+            }
 
             // Print summary
             println!("\n=== All Scenarios Summary ===");
+            for result in &results {
+                println!(
+                    "  {:<40} {}",
+                    result.scenario_name,
+                    if result.passed { "PASSED" } else { "FAILED" }
+                );
+            }
             let passed_count = results.iter().filter(|r| r.passed).count();
             println!("Passed: {}/{}", passed_count, results.len());
 
@@ -227,6 +367,22 @@ async fn main() -> Result<()> {
             }
         }
 
+        Commands::GenerateTruth { compose, out } => {
+            let truth = compose::draft_truth_file(&compose)?;
+            let json = serde_json::to_string_pretty(&truth)?;
+            match out {
+                Some(out) => {
+                    std::fs::write(&out, json + "\n")?;
+                    info!(
+                        "Wrote draft truth file {:?} ({} applications)",
+                        out,
+                        truth.applications.len()
+                    );
+                }
+                None => println!("{}", json),
+            }
+        }
+
         Commands::Report { results, format } => {
             info!("Generating report from: {:?}", results);
             report::generate_report(&results, &format)?;
@@ -235,3 +391,22 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Write the JUnit XML report and print the GitHub Actions annotations of
+/// `results`, as requested.
+fn write_ci_reports(
+    results: &[runner::RunResult],
+    junit: Option<&std::path::Path>,
+    github_annotations: bool,
+) -> Result<()> {
+    if let Some(junit) = junit {
+        std::fs::write(junit, report::junit_xml(results))?;
+        info!("Wrote JUnit report {:?}", junit);
+    }
+    if github_annotations {
+        for annotation in report::github_annotations(results) {
+            println!("{}", annotation);
+        }
+    }
+    Ok(())
+}
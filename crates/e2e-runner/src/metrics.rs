@@ -30,6 +30,15 @@ pub struct TestMetrics {
     pub total_decisions: usize,
     /// Decisions with evidence
     pub decisions_with_evidence: usize,
+    /// Forbidden ports detected
+    #[serde(default)]
+    pub forbidden_ports: Vec<u16>,
+    /// Forbidden clusters detected
+    #[serde(default)]
+    pub forbidden_clusters: Vec<String>,
+    /// Forbidden environment variable names detected
+    #[serde(default)]
+    pub forbidden_env_names: Vec<String>,
 }
 
 impl Default for TestMetrics {
@@ -46,6 +55,9 @@ impl Default for TestMetrics {
             decisions_with_evidence_ratio: 0.0,
             total_decisions: 0,
             decisions_with_evidence: 0,
+            forbidden_ports: Vec::new(),
+            forbidden_clusters: Vec::new(),
+            forbidden_env_names: Vec::new(),
         }
     }
 }
@@ -79,6 +91,20 @@ fn calculate_recall_precision<T: std::hash::Hash + Eq + Clone>(
     (recall, precision)
 }
 
+/// Host of a dependency endpoint: `db` in `postgres://app@db:5432/app`,
+/// `db:5432` or `db`.
+fn endpoint_host(endpoint: &str) -> Option<&str> {
+    let rest = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    let authority = rest.split('/').next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next()?;
+    (!host.is_empty()).then_some(host)
+}
+
 /// Calculate metrics by comparing pack plan to truth.
 pub fn calculate_metrics(plan: &PackPlan, truth: &Truth) -> TestMetrics {
     let mut metrics = TestMetrics::default();
@@ -154,6 +180,31 @@ pub fn calculate_metrics(plan: &PackPlan, truth: &Truth) -> TestMetrics {
     metrics.env_names_recall = env_recall;
     metrics.env_names_precision = env_precision;
 
+    // False positives named by the truth file
+    let forbidden = &truth.must_not_contain;
+    metrics.forbidden_ports = forbidden
+        .ports
+        .iter()
+        .copied()
+        .filter(|port| actual_ports.contains(port))
+        .collect();
+    metrics.forbidden_clusters = forbidden
+        .clusters
+        .iter()
+        .filter(|name| {
+            plan.clusters
+                .iter()
+                .any(|c| &&c.name == name || &&c.id == name)
+        })
+        .cloned()
+        .collect();
+    metrics.forbidden_env_names = forbidden
+        .env_names
+        .iter()
+        .filter(|name| actual_env.contains(name))
+        .cloned()
+        .collect();
+
     // Dependency metrics
     let expected_deps: Vec<(String, String)> = truth
         .dependencies
@@ -161,10 +212,20 @@ pub fn calculate_metrics(plan: &PackPlan, truth: &Truth) -> TestMetrics {
         .map(|d| (d.from.clone(), d.to.clone()))
         .collect();
 
+    // Startup edges, and the hosts of external dependencies by the name of
+    // the clusters using them, which identify the other targets of
+    // multi-host scenarios
     let actual_deps: Vec<(String, String)> = plan
         .startup_dag
         .iter()
         .map(|e| (e.from.clone(), e.to.clone()))
+        .chain(plan.external_dependencies.iter().flat_map(|dep| {
+            let host = endpoint_host(&dep.endpoint);
+            dep.used_by.iter().filter_map(move |id| {
+                let cluster = plan.clusters.iter().find(|c| &c.id == id)?;
+                Some((cluster.name.clone(), host?.to_string()))
+            })
+        }))
         .collect();
 
     let (deps_recall, deps_precision) = calculate_recall_precision(&expected_deps, &actual_deps);
@@ -195,51 +256,142 @@ pub fn calculate_metrics(plan: &PackPlan, truth: &Truth) -> TestMetrics {
     metrics
 }
 
-/// Check if metrics pass thresholds.
-pub fn check_thresholds(metrics: &TestMetrics, truth: &Truth) -> Vec<String> {
-    let mut failures = Vec::new();
-
-    if metrics.process_cmdline_recall < truth.thresholds.process_cmdline_recall {
-        failures.push(format!(
-            "Process/cmdline recall {:.2}% < {:.2}%",
-            metrics.process_cmdline_recall * 100.0,
-            truth.thresholds.process_cmdline_recall * 100.0
-        ));
-    }
-
-    if metrics.ports_recall < truth.thresholds.ports_recall {
-        failures.push(format!(
-            "Ports recall {:.2}% < {:.2}%",
-            metrics.ports_recall * 100.0,
-            truth.thresholds.ports_recall * 100.0
-        ));
-    }
+/// A threshold of the truth file checked against a metric.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Assertion {
+    /// Threshold name, as in truth.json
+    pub name: String,
+    pub passed: bool,
+    /// Metric and threshold, e.g. `Ports recall 50.00% < 95.00%`
+    pub message: String,
+}
 
-    if metrics.env_names_recall < truth.thresholds.env_names_recall {
-        failures.push(format!(
-            "Env names recall {:.2}% < {:.2}%",
-            metrics.env_names_recall * 100.0,
-            truth.thresholds.env_names_recall * 100.0
-        ));
+/// The thresholds of `truth` checked against `metrics`, in truth.json order.
+pub fn assertions(metrics: &TestMetrics, truth: &Truth) -> Vec<Assertion> {
+    let thresholds = &truth.thresholds;
+    let recall = |name: &str, label: &str, actual: f64, threshold: f64| {
+        let passed = actual >= threshold;
+        Assertion {
+            name: name.to_string(),
+            passed,
+            message: format!(
+                "{} {:.2}% {} {:.2}%",
+                label,
+                actual * 100.0,
+                if passed { ">=" } else { "<" },
+                threshold * 100.0
+            ),
+        }
+    };
+    let mut assertions = vec![
+        recall(
+            "process_cmdline_recall",
+            "Process/cmdline recall",
+            metrics.process_cmdline_recall,
+            thresholds.process_cmdline_recall,
+        ),
+        recall(
+            "ports_recall",
+            "Ports recall",
+            metrics.ports_recall,
+            thresholds.ports_recall,
+        ),
+        recall(
+            "env_names_recall",
+            "Env names recall",
+            metrics.env_names_recall,
+            thresholds.env_names_recall,
+        ),
+        recall(
+            "deps_recall",
+            "Dependencies recall",
+            metrics.deps_recall,
+            thresholds.deps_recall,
+        ),
+    ];
+    let precisions = [
+        (
+            "process_cmdline_precision",
+            "Process/cmdline precision",
+            metrics.process_cmdline_precision,
+            thresholds.process_cmdline_precision,
+        ),
+        (
+            "ports_precision",
+            "Ports precision",
+            metrics.ports_precision,
+            thresholds.ports_precision,
+        ),
+        (
+            "env_names_precision",
+            "Env names precision",
+            metrics.env_names_precision,
+            thresholds.env_names_precision,
+        ),
+        (
+            "deps_precision",
+            "Dependencies precision",
+            metrics.deps_precision,
+            thresholds.deps_precision,
+        ),
+    ];
+    for (name, label, actual, threshold) in precisions {
+        if let Some(threshold) = threshold {
+            assertions.push(recall(name, label, actual, threshold));
+        }
     }
-
-    if metrics.deps_recall < truth.thresholds.deps_recall {
-        failures.push(format!(
-            "Dependencies recall {:.2}% < {:.2}%",
-            metrics.deps_recall * 100.0,
-            truth.thresholds.deps_recall * 100.0
-        ));
+    if thresholds.require_all_evidence {
+        assertions.push(Assertion {
+            name: "require_all_evidence".to_string(),
+            passed: metrics.decisions_with_evidence_ratio >= 1.0,
+            message: format!(
+                "Decisions without evidence: {} of {}",
+                metrics.total_decisions - metrics.decisions_with_evidence,
+                metrics.total_decisions
+            ),
+        });
     }
 
-    if truth.thresholds.require_all_evidence && metrics.decisions_with_evidence_ratio < 1.0 {
-        failures.push(format!(
-            "Decisions without evidence: {} of {}",
-            metrics.total_decisions - metrics.decisions_with_evidence,
-            metrics.total_decisions
-        ));
-    }
+    let forbidden = |name: &str, label: &str, listed: bool, found: Vec<String>| {
+        listed.then(|| Assertion {
+            name: format!("must_not_contain.{}", name),
+            passed: found.is_empty(),
+            message: if found.is_empty() {
+                format!("No forbidden {} detected", label)
+            } else {
+                format!("Forbidden {} detected: {}", label, found.join(", "))
+            },
+        })
+    };
+    let must_not_contain = &truth.must_not_contain;
+    assertions.extend(forbidden(
+        "ports",
+        "ports",
+        !must_not_contain.ports.is_empty(),
+        metrics.forbidden_ports.iter().map(u16::to_string).collect(),
+    ));
+    assertions.extend(forbidden(
+        "clusters",
+        "clusters",
+        !must_not_contain.clusters.is_empty(),
+        metrics.forbidden_clusters.clone(),
+    ));
+    assertions.extend(forbidden(
+        "env_names",
+        "env names",
+        !must_not_contain.env_names.is_empty(),
+        metrics.forbidden_env_names.clone(),
+    ));
+    assertions
+}
 
-    failures
+/// Check if metrics pass thresholds: the messages of the failed assertions.
+pub fn check_thresholds(metrics: &TestMetrics, truth: &Truth) -> Vec<String> {
+    assertions(metrics, truth)
+        .into_iter()
+        .filter(|a| !a.passed)
+        .map(|a| a.message)
+        .collect()
 }
 
 #[cfg(test)]
@@ -259,6 +411,17 @@ mod tests {
         assert!((precision - 0.6).abs() < 0.01);
     }
 
+    #[test]
+    fn test_endpoint_host() {
+        assert_eq!(
+            endpoint_host("postgres://app@db-host:5432/app"),
+            Some("db-host")
+        );
+        assert_eq!(endpoint_host("db-host:6379"), Some("db-host"));
+        assert_eq!(endpoint_host("db-host"), Some("db-host"));
+        assert_eq!(endpoint_host("file:///tmp/x"), None);
+    }
+
     #[test]
     fn test_empty_sets() {
         let expected: Vec<i32> = vec![];
@@ -269,4 +432,46 @@ mod tests {
         assert_eq!(recall, 1.0);
         assert_eq!(precision, 1.0);
     }
+
+    #[test]
+    fn test_precision_and_forbidden_assertions() {
+        let truth: Truth = serde_json::from_value(serde_json::json!({
+            "version": "1.0", "name": "s", "applications": [], "ports": [],
+            "env_names": [], "dependencies": [], "config_files": [],
+            "thresholds": { "ports_precision": 0.9 },
+            "must_not_contain": { "ports": [22, 25], "env_names": ["HOME"] }
+        }))
+        .unwrap();
+        let metrics = TestMetrics {
+            process_cmdline_recall: 1.0,
+            ports_recall: 1.0,
+            env_names_recall: 1.0,
+            deps_recall: 1.0,
+            ports_precision: 0.5,
+            decisions_with_evidence_ratio: 1.0,
+            forbidden_ports: vec![22],
+            ..Default::default()
+        };
+
+        let failed: Vec<(String, String)> = assertions(&metrics, &truth)
+            .into_iter()
+            .filter(|a| !a.passed)
+            .map(|a| (a.name, a.message))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                (
+                    "ports_precision".to_string(),
+                    "Ports precision 50.00% < 90.00%".to_string()
+                ),
+                (
+                    "must_not_contain.ports".to_string(),
+                    "Forbidden ports detected: 22".to_string()
+                ),
+            ]
+        );
+        assert!(check_thresholds(&metrics, &truth)
+            .contains(&"Forbidden ports detected: 22".to_string()));
+    }
 }
@@ -23,9 +23,18 @@ pub fn generate_report(results_dir: &Path, format: &str) -> Result<()> {
         }
     }
 
+    // Directory listing order varies between runs and systems
+    results.sort_by(|a, b| a.scenario_name.cmp(&b.scenario_name));
+
     match format {
         "json" => print_json_report(&results),
         "html" => print_html_report(&results),
+        "junit" => print!("{}", junit_xml(&results)),
+        "github" => {
+            for annotation in github_annotations(&results) {
+                println!("{}", annotation);
+            }
+        }
         _ => print_text_report(&results),
     }
 
@@ -83,6 +92,24 @@ fn print_text_report(results: &[RunResult]) {
         println!("  Ports Recall:           {:.1}%", avg_ports_recall * 100.0);
         println!("  Env Names Recall:       {:.1}%", avg_env_recall * 100.0);
         println!("  Dependencies Recall:    {:.1}%", avg_deps_recall * 100.0);
+        let avg_ports_precision: f64 = results
+            .iter()
+            .map(|r| r.metrics.ports_precision)
+            .sum::<f64>()
+            / results.len() as f64;
+        let avg_deps_precision: f64 = results
+            .iter()
+            .map(|r| r.metrics.deps_precision)
+            .sum::<f64>()
+            / results.len() as f64;
+        println!(
+            "  Ports Precision:        {:.1}%",
+            avg_ports_precision * 100.0
+        );
+        println!(
+            "  Dependencies Precision: {:.1}%",
+            avg_deps_precision * 100.0
+        );
         println!("  Evidence Coverage:      {:.1}%", avg_evidence * 100.0);
         println!();
     }
@@ -137,6 +164,127 @@ fn print_json_report(results: &[RunResult]) {
     println!("{}", serde_json::to_string_pretty(&report).unwrap());
 }
 
+/// Failures of a result that no threshold assertion accounts for, such as a
+/// failed collection.
+fn run_failures(result: &RunResult) -> Vec<&String> {
+    result
+        .failures
+        .iter()
+        .filter(|f| !result.assertions.iter().any(|a| &a.message == *f))
+        .collect()
+}
+
+/// A JUnit XML report: one test suite per scenario, with a `run` test case
+/// for the scenario as a whole and one test case per threshold.
+pub fn junit_xml(results: &[RunResult]) -> String {
+    let mut suites = String::new();
+    let mut total_tests = 0;
+    let mut total_failures = 0;
+    for result in results {
+        let name = xml_escape(&result.scenario_name);
+        let mut cases = String::new();
+        let mut failures = 0;
+
+        let run_failures = run_failures(result);
+        if run_failures.is_empty() {
+            cases.push_str(&format!(
+                "    <testcase name=\"run\" classname=\"{}\" time=\"{:.3}\"/>\n",
+                name, result.duration_seconds
+            ));
+        } else {
+            failures += 1;
+            let messages: Vec<&str> = run_failures.iter().map(|f| f.as_str()).collect();
+            cases.push_str(&format!(
+                "    <testcase name=\"run\" classname=\"{}\" time=\"{:.3}\">\n      \
+                 <failure message=\"{}\" type=\"run\">{}</failure>\n    </testcase>\n",
+                name,
+                result.duration_seconds,
+                xml_escape(messages[0]),
+                xml_escape(&messages.join("\n"))
+            ));
+        }
+        for assertion in &result.assertions {
+            if assertion.passed {
+                cases.push_str(&format!(
+                    "    <testcase name=\"{}\" classname=\"{}\" time=\"0\"/>\n",
+                    xml_escape(&assertion.name),
+                    name
+                ));
+            } else {
+                failures += 1;
+                cases.push_str(&format!(
+                    "    <testcase name=\"{}\" classname=\"{}\" time=\"0\">\n      \
+                     <failure message=\"{}\" type=\"threshold\"/>\n    </testcase>\n",
+                    xml_escape(&assertion.name),
+                    name,
+                    xml_escape(&assertion.message)
+                ));
+            }
+        }
+
+        let tests = 1 + result.assertions.len();
+        total_tests += tests;
+        total_failures += failures;
+        suites.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n{}  </testsuite>\n",
+            name, tests, failures, result.duration_seconds, cases
+        ));
+    }
+
+    let time: f64 = results.iter().map(|r| r.duration_seconds).sum();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites name=\"xcprobe-e2e\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n\
+         {}</testsuites>\n",
+        total_tests, total_failures, time, suites
+    )
+}
+
+/// GitHub Actions `::error` workflow commands, one per failure, so that the
+/// failed thresholds show as annotations of the run.
+pub fn github_annotations(results: &[RunResult]) -> Vec<String> {
+    let mut annotations = Vec::new();
+    for result in results {
+        for failure in run_failures(result) {
+            annotations.push(format!(
+                "::error title={}::{}",
+                annotation_property(&result.scenario_name),
+                annotation_data(failure)
+            ));
+        }
+        for assertion in result.assertions.iter().filter(|a| !a.passed) {
+            annotations.push(format!(
+                "::error title={}::{}",
+                annotation_property(&format!("{}: {}", result.scenario_name, assertion.name)),
+                annotation_data(&assertion.message)
+            ));
+        }
+    }
+    annotations
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Message of a workflow command, with the characters GitHub decodes escaped.
+fn annotation_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Property value of a workflow command, such as `title`.
+fn annotation_property(text: &str) -> String {
+    annotation_data(text)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
 fn print_html_report(results: &[RunResult]) {
     let total = results.len();
     let passed = results.iter().filter(|r| r.passed).count();
@@ -213,3 +361,62 @@ fn print_html_report(results: &[RunResult]) {
 </html>"#
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{Assertion, TestMetrics};
+
+    fn result() -> RunResult {
+        RunResult {
+            scenario_name: "scenario_a".to_string(),
+            passed: false,
+            metrics: TestMetrics::default(),
+            failures: vec![
+                "Failed to generate pack plan".to_string(),
+                "Ports recall 50.00% < 95.00%".to_string(),
+            ],
+            assertions: vec![
+                Assertion {
+                    name: "process_cmdline_recall".to_string(),
+                    passed: true,
+                    message: "Process/cmdline recall 100.00% >= 90.00%".to_string(),
+                },
+                Assertion {
+                    name: "ports_recall".to_string(),
+                    passed: false,
+                    message: "Ports recall 50.00% < 95.00%".to_string(),
+                },
+            ],
+            bundle_path: None,
+            plan_path: None,
+            targets: Vec::new(),
+            duration_seconds: 1.5,
+        }
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        let xml = junit_xml(&[result()]);
+        assert!(
+            xml.contains(r#"<testsuites name="xcprobe-e2e" tests="3" failures="2" time="1.500">"#)
+        );
+        assert!(xml.contains(r#"<failure message="Failed to generate pack plan" type="run">"#));
+        assert!(xml.contains(
+            r#"<testcase name="process_cmdline_recall" classname="scenario_a" time="0"/>"#
+        ));
+        assert!(xml
+            .contains(r#"<failure message="Ports recall 50.00% &lt; 95.00%" type="threshold"/>"#));
+    }
+
+    #[test]
+    fn test_github_annotations() {
+        assert_eq!(
+            github_annotations(&[result()]),
+            vec![
+                "::error title=scenario_a::Failed to generate pack plan",
+                "::error title=scenario_a%3A ports_recall::Ports recall 50.00%25 < 95.00%25",
+            ]
+        );
+    }
+}
@@ -1,22 +1,37 @@
 //! Test scenario runner.
 
-use crate::metrics::{calculate_metrics, check_thresholds, TestMetrics};
+use crate::metrics::{assertions, calculate_metrics, check_thresholds, Assertion, TestMetrics};
+use crate::scenario::{load_scenario, WinRmTarget};
 use crate::truth::load_truth;
+use crate::validate::validate_artifacts;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tokio::time::Duration;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, info_span, warn};
+use xcprobe_bundle_schema::PackPlan;
+use xcprobe_common::ErrorCategory;
 
 /// Configuration for running a scenario.
 #[derive(Debug, Clone)]
 pub struct RunConfig {
     pub scenario_path: PathBuf,
     pub artifacts_path: PathBuf,
+    /// Compose project name, unique to the run so that scenarios running
+    /// at the same time do not share containers or networks.
+    pub project_name: String,
     pub keep_running: bool,
     #[allow(dead_code)]
     pub timeout_seconds: u64,
+    /// Time allowed to build and start the generated artifacts of each
+    /// target, in seconds; `None` skips the validation.
+    pub validate_timeout: Option<u64>,
 }
 
 /// Result of running a scenario.
@@ -26,11 +41,47 @@ pub struct RunResult {
     pub passed: bool,
     pub metrics: TestMetrics,
     pub failures: Vec<String>,
+    /// Thresholds checked against the metrics, and generated artifacts
+    /// started, failed or not
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    /// Bundle of the first target
     pub bundle_path: Option<PathBuf>,
+    /// Pack plan of the first target
     pub plan_path: Option<PathBuf>,
+    /// Bundle and pack plan of each target
+    #[serde(default)]
+    pub targets: Vec<TargetArtifacts>,
     pub duration_seconds: f64,
 }
 
+/// Bundle and pack plan collected from one target of a scenario.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetArtifacts {
+    /// Compose service
+    pub service: String,
+    pub bundle_path: Option<PathBuf>,
+    pub plan_path: Option<PathBuf>,
+}
+
+impl RunResult {
+    /// Result of a scenario that could not be run, such as when its
+    /// containers failed to start.
+    pub fn failed_to_run(scenario_name: &str, error: &anyhow::Error) -> Self {
+        Self {
+            scenario_name: scenario_name.to_string(),
+            passed: false,
+            metrics: TestMetrics::default(),
+            failures: vec![format!("{:#}", error)],
+            assertions: Vec::new(),
+            bundle_path: None,
+            plan_path: None,
+            targets: Vec::new(),
+            duration_seconds: 0.0,
+        }
+    }
+}
+
 /// Resolve a path to absolute (relative to cwd).
 fn to_absolute(path: &Path) -> Result<PathBuf> {
     if path.is_absolute() {
@@ -41,6 +92,50 @@ fn to_absolute(path: &Path) -> Result<PathBuf> {
     }
 }
 
+/// A compose project name for a run of the scenario at `scenario_path`:
+/// the directory name, lowercased and restricted to the characters compose
+/// accepts, and the runner's process ID.
+pub fn project_name(scenario_path: &Path) -> String {
+    let scenario: String = scenario_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("xcprobe-e2e-{}-{}", scenario, std::process::id())
+}
+
+/// `docker compose` for the scenario's compose file and project.
+fn compose_command(compose_file: &Path, project: &str) -> Command {
+    let mut command = Command::new("docker");
+    command
+        .args(["compose", "-p", project, "-f"])
+        .arg(compose_file);
+    command
+}
+
+/// A scenario's compose project, stopped with its volumes when dropped.
+struct ComposeProject<'a> {
+    compose_file: &'a Path,
+    project: &'a str,
+}
+
+impl Drop for ComposeProject<'_> {
+    fn drop(&mut self) {
+        info!("Stopping docker-compose...");
+        let _ = compose_command(self.compose_file, self.project)
+            .args(["down", "-v"])
+            .output();
+    }
+}
+
 /// Find the compose file in a scenario directory and return its absolute path.
 fn find_compose_file(scenario_path: &Path) -> Result<PathBuf> {
     let yaml = scenario_path.join("compose.yaml");
@@ -54,6 +149,14 @@ fn find_compose_file(scenario_path: &Path) -> Result<PathBuf> {
     anyhow::bail!("No compose.yaml or docker-compose.yaml found in scenario");
 }
 
+/// Where a scenario's targets run.
+enum Environment {
+    /// Services of the scenario's compose file
+    Compose(PathBuf),
+    /// An external Windows host, reached over WinRM
+    WinRm(WinRmTarget),
+}
+
 /// Run a test scenario.
 pub async fn run_scenario(config: &RunConfig) -> Result<RunResult> {
     let start = std::time::Instant::now();
@@ -62,7 +165,19 @@ pub async fn run_scenario(config: &RunConfig) -> Result<RunResult> {
     // (which may change working directory) always see correct paths.
     let scenario_path = to_absolute(&config.scenario_path)?;
     let artifacts_path = to_absolute(&config.artifacts_path)?;
-    let compose_file = find_compose_file(&scenario_path)?;
+    let scenario = load_scenario(&scenario_path)?;
+    let (environment, target_names) = match scenario.winrm {
+        Some(winrm) => {
+            let host = winrm.host.clone().context(
+                "No Windows host configured: set XCPROBE_E2E_WINRM_HOST or winrm.host in scenario.yaml",
+            )?;
+            (Environment::WinRm(winrm), vec![host])
+        }
+        None => (
+            Environment::Compose(find_compose_file(&scenario_path)?),
+            scenario.targets.into_iter().map(|t| t.service).collect(),
+        ),
+    };
 
     // Load truth file
     let truth_path = scenario_path.join("truth.json");
@@ -74,97 +189,88 @@ pub async fn run_scenario(config: &RunConfig) -> Result<RunResult> {
     // Create artifacts directory
     std::fs::create_dir_all(&artifacts_path)?;
 
-    // Step 1: Build images first (separate from up to get clearer errors)
-    info!("Building docker images...");
-    let compose_build = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(&compose_file)
-        .args(["build"])
-        .output()
-        .context("Failed to run docker compose build")?;
-
-    if !compose_build.status.success() {
-        let stdout = String::from_utf8_lossy(&compose_build.stdout);
-        let stderr = String::from_utf8_lossy(&compose_build.stderr);
-        anyhow::bail!(
-            "docker compose build failed:\nstdout: {}\nstderr: {}",
-            stdout,
-            stderr
-        );
-    }
-
-    // Step 1b: Start services
-    info!("Starting docker-compose services...");
-    let compose_up = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(&compose_file)
-        .args(["up", "-d", "--wait"])
-        .output()
-        .context("Failed to run docker compose up")?;
-
-    if !compose_up.status.success() {
-        let stdout = String::from_utf8_lossy(&compose_up.stdout);
-        let stderr = String::from_utf8_lossy(&compose_up.stderr);
-        // Grab container logs for debugging
-        let logs = Command::new("docker")
-            .args(["compose", "-f"])
-            .arg(&compose_file)
-            .args(["logs", "--tail", "50"])
-            .output();
-        let container_logs = logs
-            .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-            .unwrap_or_default();
-        anyhow::bail!(
-            "docker compose up failed:\nstdout: {}\nstderr: {}\ncontainer logs:\n{}",
-            stdout,
-            stderr,
-            container_logs
-        );
+    // Steps 1 and 2: Start the scenario's services. They are stopped on
+    // every way out, including errors, unless kept running
+    let mut services = None;
+    if let Environment::Compose(ref compose_file) = environment {
+        services = (!config.keep_running).then(|| ComposeProject {
+            compose_file,
+            project: &config.project_name,
+        });
+        start_services(compose_file, &config.project_name).await?;
     }
 
-    // Step 2: Wait for services to be ready (--wait flag handles healthchecks,
-    // add a small grace period for processes to fully initialize)
-    info!("Waiting for services to stabilize...");
-    tokio::time::sleep(Duration::from_secs(3)).await;
-
-    // Step 3: Run xcprobe collect
-    info!("Running xcprobe collect...");
-    let bundle_path = artifacts_path.join("bundle.tgz");
-
-    let collect_result = run_collect(&compose_file, &bundle_path).await;
-
-    let bundle_path = match collect_result {
-        Ok(path) => Some(path),
-        Err(e) => {
-            warn!("xcprobe collect failed: {}", e);
-            None
-        }
-    };
-
-    // Step 4: Run xcprobe analyze
-    let plan_path = if let Some(ref bundle) = bundle_path {
-        info!("Running xcprobe analyze...");
-        let plan_path = artifacts_path.join("packplan.json");
-        let analyze_result = run_analyze(bundle, &plan_path).await;
-
-        match analyze_result {
+    // Step 3: Run xcprobe collect on each target, then Step 4: xcprobe
+    // analyze on each bundle. A single target writes to the artifacts
+    // directory itself, several each to a subdirectory.
+    let several = target_names.len() > 1;
+    let mut targets = Vec::new();
+    for name in target_names {
+        let target_path = if several {
+            artifacts_path.join(&name)
+        } else {
+            artifacts_path.clone()
+        };
+        std::fs::create_dir_all(&target_path)?;
+
+        info!("Running xcprobe collect on {}...", name);
+        let bundle_path = target_path.join("bundle.tgz");
+        let collected = match environment {
+            Environment::Compose(ref compose_file) => {
+                run_collect(compose_file, &config.project_name, &name, &bundle_path).await
+            }
+            Environment::WinRm(ref winrm) => run_collect_winrm(winrm, &bundle_path).await,
+        };
+        let bundle_path = match collected {
             Ok(path) => Some(path),
             Err(e) => {
-                warn!("xcprobe analyze failed: {}", e);
+                warn!("xcprobe collect failed on {}: {}", name, e);
                 None
             }
-        }
-    } else {
-        None
-    };
+        };
+
+        let plan_path = if let Some(ref bundle) = bundle_path {
+            info!("Running xcprobe analyze on the bundle of {}...", name);
+            let plan_path = target_path.join("packplan.json");
+            match run_analyze(bundle, &plan_path, config.validate_timeout.is_some()).await {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn!("xcprobe analyze failed on {}: {}", name, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        targets.push(TargetArtifacts {
+            service: name,
+            bundle_path,
+            plan_path,
+        });
+    }
 
     // Step 5: Compare results to truth
-    let (metrics, failures) = if let Some(ref plan) = plan_path {
+    let mut plans = Vec::new();
+    let mut failures = Vec::new();
+    for target in &targets {
+        let Some(ref plan) = target.plan_path else {
+            failures.push(if several {
+                format!("Failed to generate pack plan for {}", target.service)
+            } else {
+                "Failed to generate pack plan".to_string()
+            });
+            continue;
+        };
         let plan_content = std::fs::read_to_string(plan)?;
-        let pack_plan: xcprobe_bundle_schema::PackPlan = serde_json::from_str(&plan_content)?;
+        let pack_plan: PackPlan = serde_json::from_str(&plan_content)?;
 
         // Debug: log what the plan contains
-        info!("Pack plan has {} clusters", pack_plan.clusters.len());
+        info!(
+            "Pack plan of {} has {} clusters",
+            target.service,
+            pack_plan.clusters.len()
+        );
         for cluster in &pack_plan.clusters {
             info!(
                 "  Cluster '{}': {} processes, {} ports, {} env_vars, confidence={:.2}",
@@ -181,34 +287,56 @@ pub async fn run_scenario(config: &RunConfig) -> Result<RunResult> {
                 info!("    Port: {}/{}", port.port, port.protocol);
             }
         }
+        plans.push((target.service.clone(), pack_plan));
+    }
 
-        let metrics = calculate_metrics(&pack_plan, &truth);
-        let failures = check_thresholds(&metrics, &truth);
-
-        (metrics, failures)
+    // No plan = all failures
+    let metrics = if plans.is_empty() {
+        TestMetrics::default()
     } else {
-        // No plan = all failures
-        let mut failures = vec!["Failed to generate pack plan".to_string()];
-        let metrics = TestMetrics::default();
-        failures.extend(check_thresholds(&metrics, &truth));
-        (metrics, failures)
+        calculate_metrics(&merge_plans(plans), &truth)
     };
+    failures.extend(check_thresholds(&metrics, &truth));
+
+    let mut assertions = assertions(&metrics, &truth);
+
+    // Step 5b: Build and start the generated artifacts. Those of a Windows
+    // host need Windows containers
+    if let (Some(_), Environment::WinRm(_)) = (config.validate_timeout, &environment) {
+        warn!("Artifacts generated for a Windows host are not validated");
+    } else if let Some(timeout) = config.validate_timeout {
+        for target in targets.iter().filter(|t| t.plan_path.is_some()) {
+            let assertion = validate_target(
+                target,
+                &config.project_name,
+                Duration::from_secs(timeout),
+                several,
+            )
+            .await;
+            if !assertion.passed {
+                failures.push(assertion.message.clone());
+            }
+            assertions.push(assertion);
+        }
+    }
 
     // Step 6: Cleanup (unless keep_running)
-    if !config.keep_running {
-        info!("Stopping docker-compose...");
-        let _ = Command::new("docker")
-            .args(["compose", "-f"])
-            .arg(&compose_file)
-            .args(["down", "-v"])
-            .output();
-    }
+    drop(services);
 
     // Step 7: Archive artifacts if failed
     let passed = failures.is_empty();
     if !passed {
         info!("Test failed, archiving artifacts...");
-        archive_artifacts(&artifacts_path, &bundle_path, &plan_path)?;
+        for target in &targets {
+            let archive_path = if several {
+                artifacts_path
+                    .join("failed_artifacts")
+                    .join(&target.service)
+            } else {
+                artifacts_path.join("failed_artifacts")
+            };
+            archive_artifacts(&archive_path, &target.bundle_path, &target.plan_path)?;
+        }
     }
 
     let duration = start.elapsed();
@@ -219,8 +347,10 @@ pub async fn run_scenario(config: &RunConfig) -> Result<RunResult> {
         passed,
         metrics,
         failures,
-        bundle_path,
-        plan_path,
+        assertions,
+        bundle_path: targets.first().and_then(|t| t.bundle_path.clone()),
+        plan_path: targets.first().and_then(|t| t.plan_path.clone()),
+        targets,
         duration_seconds: duration.as_secs_f64(),
     };
 
@@ -231,10 +361,161 @@ pub async fn run_scenario(config: &RunConfig) -> Result<RunResult> {
     Ok(result)
 }
 
-/// Run xcprobe collect inside the host-sim container.
+/// Build and start the services of a scenario's compose file, and wait for
+/// them to be ready.
+async fn start_services(compose_file: &Path, project: &str) -> Result<()> {
+    // Step 1: Build images first (separate from up to get clearer errors)
+    info!("Building docker images...");
+    let compose_build = compose_command(compose_file, project)
+        .args(["build"])
+        .output()
+        .context("Failed to run docker compose build")?;
+
+    if !compose_build.status.success() {
+        let stdout = String::from_utf8_lossy(&compose_build.stdout);
+        let stderr = String::from_utf8_lossy(&compose_build.stderr);
+        anyhow::bail!(
+            "docker compose build failed:\nstdout: {}\nstderr: {}",
+            stdout,
+            stderr
+        );
+    }
+
+    // Step 1b: Start services
+    info!("Starting docker-compose services...");
+    let compose_up = compose_command(compose_file, project)
+        .args(["up", "-d", "--wait"])
+        .output()
+        .context("Failed to run docker compose up")?;
+
+    if !compose_up.status.success() {
+        let stdout = String::from_utf8_lossy(&compose_up.stdout);
+        let stderr = String::from_utf8_lossy(&compose_up.stderr);
+        // Grab container logs for debugging
+        let logs = compose_command(compose_file, project)
+            .args(["logs", "--tail", "50"])
+            .output();
+        let container_logs = logs
+            .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+            .unwrap_or_default();
+        anyhow::bail!(
+            "docker compose up failed:\nstdout: {}\nstderr: {}\ncontainer logs:\n{}",
+            stdout,
+            stderr,
+            container_logs
+        );
+    }
+
+    // Step 2: Wait for services to be ready (--wait flag handles healthchecks,
+    // add a small grace period for processes to fully initialize)
+    info!("Waiting for services to stabilize...");
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    Ok(())
+}
+
+/// Run scenarios, up to `jobs` at a time, and return their outcomes in the
+/// order of `configs`. Unless `continue_on_failure`, no scenario is started
+/// after one fails to run; those already running complete.
+pub async fn run_scenarios(
+    configs: Vec<RunConfig>,
+    jobs: usize,
+    continue_on_failure: bool,
+) -> Result<Vec<(PathBuf, Result<RunResult>)>> {
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut tasks = JoinSet::new();
+    for (n, config) in configs.into_iter().enumerate() {
+        let permit = semaphore.clone().acquire_owned().await?;
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let stop = stop.clone();
+        // Scenarios run docker synchronously, each on a thread of its own
+        tasks.spawn_blocking(move || {
+            let _permit = permit;
+            let scenario = config
+                .scenario_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let _span = info_span!("scenario", name = %scenario).entered();
+            let result = Handle::current().block_on(run_scenario(&config));
+            if result.is_err() && !continue_on_failure {
+                stop.store(true, Ordering::SeqCst);
+            }
+            (n, config.scenario_path, result)
+        });
+    }
+
+    let mut outcomes = Vec::new();
+    while let Some(outcome) = tasks.join_next().await {
+        outcomes.push(outcome?);
+    }
+    outcomes.sort_by_key(|(n, _, _)| *n);
+    Ok(outcomes
+        .into_iter()
+        .map(|(_, path, result)| (path, result))
+        .collect())
+}
+
+/// Build and start the artifacts generated for `target`, as a compose
+/// project named after the scenario's.
+async fn validate_target(
+    target: &TargetArtifacts,
+    project: &str,
+    timeout: Duration,
+    several: bool,
+) -> Assertion {
+    let (name, subject) = if several {
+        (
+            format!("artifacts_start.{}", target.service),
+            format!("Generated artifacts of {}", target.service),
+        )
+    } else {
+        (
+            "artifacts_start".to_string(),
+            "Generated artifacts".to_string(),
+        )
+    };
+    let output_dir = target
+        .plan_path
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new("."));
+    let project = format!(
+        "{}-artifacts-{}",
+        project,
+        target.service.to_lowercase().replace('.', "-")
+    );
+    info!(
+        "Validating the artifacts generated for {}...",
+        target.service
+    );
+    let failures = match validate_artifacts(output_dir, &project, timeout).await {
+        Ok(failures) => failures,
+        Err(e) => vec![format!("{:#}", e)],
+    };
+    Assertion {
+        name,
+        passed: failures.is_empty(),
+        message: if failures.is_empty() {
+            format!("{} started", subject)
+        } else {
+            format!("{} failed to start: {}", subject, failures.join("; "))
+        },
+    }
+}
+
+/// Run xcprobe collect inside the container of compose `service` in
+/// `project`.
 /// `compose_file` must be an absolute path to the compose file.
 /// `bundle_path` must be an absolute path for the output bundle.
-async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf> {
+async fn run_collect(
+    compose_file: &Path,
+    project: &str,
+    service: &str,
+    bundle_path: &Path,
+) -> Result<PathBuf> {
     // Find xcprobe binary: check PATH, then common build output paths
     let xcprobe_path =
         find_binary("xcprobe").context("xcprobe binary not found in PATH or target/ directory")?;
@@ -242,12 +523,10 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
     info!("Copying xcprobe to container from {:?}", xcprobe_path);
 
     // Copy xcprobe into the container
-    let copy_binary = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(compose_file)
+    let copy_binary = compose_command(compose_file, project)
         .arg("cp")
         .arg(&xcprobe_path)
-        .arg("host-sim:/xcprobe")
+        .arg(format!("{}:/xcprobe", service))
         .output()
         .context("Failed to copy xcprobe to container")?;
 
@@ -257,11 +536,9 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
     }
 
     // Make it executable
-    let chmod = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(compose_file)
+    let chmod = compose_command(compose_file, project)
         .args([
-            "exec", "-T", "--user", "root", "host-sim", "chmod", "+x", "/xcprobe",
+            "exec", "-T", "--user", "root", service, "chmod", "+x", "/xcprobe",
         ])
         .output()
         .context("Failed to chmod xcprobe")?;
@@ -272,15 +549,13 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
     }
 
     // Verify the binary can execute (check for missing shared libraries)
-    let ldd_check = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(compose_file)
+    let ldd_check = compose_command(compose_file, project)
         .args([
             "exec",
             "-T",
             "--user",
             "root",
-            "host-sim",
+            service,
             "sh",
             "-c",
             "ldd /xcprobe 2>&1 || echo 'ldd not available'",
@@ -296,15 +571,13 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
     }
 
     // Debug: check what ss sees inside the container
-    let ss_check = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(compose_file)
+    let ss_check = compose_command(compose_file, project)
         .args([
             "exec",
             "-T",
             "--user",
             "root",
-            "host-sim",
+            service,
             "sh",
             "-c",
             "echo '=== ss -lntup ===' && ss -lntup 2>&1 && echo '=== ps auxww ===' && ps auxww 2>&1",
@@ -315,16 +588,14 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
         info!("Container state before collect:\n{}", stdout);
     }
 
-    // Run xcprobe collect inside the host-sim container
-    let output = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(compose_file)
+    // Run xcprobe collect inside the target container
+    let output = compose_command(compose_file, project)
         .args([
             "exec",
             "-T",
             "--user",
             "root",
-            "host-sim",
+            service,
             "/xcprobe",
             "collect",
             "--verbose",
@@ -343,7 +614,12 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
         if !stderr.is_empty() {
             info!("xcprobe collect stderr:\n{}", stderr);
         }
-        if !output.status.success() {
+        // A partial collection still writes the bundle
+        let partial =
+            output.status.code() == Some(ErrorCategory::PartialCollection.exit_code().into());
+        if partial {
+            warn!("xcprobe collect was partial, some commands failed");
+        } else if !output.status.success() {
             anyhow::bail!(
                 "xcprobe collect failed (exit {}): {}",
                 output.status,
@@ -353,10 +629,9 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
     }
 
     // Copy bundle out of container to local filesystem
-    let copy_output = Command::new("docker")
-        .args(["compose", "-f"])
-        .arg(compose_file)
-        .args(["cp", "host-sim:/tmp/bundle.tgz"])
+    let copy_output = compose_command(compose_file, project)
+        .arg("cp")
+        .arg(format!("{}:/tmp/bundle.tgz", service))
         .arg(bundle_path)
         .output()
         .context("Failed to copy bundle from container")?;
@@ -369,21 +644,88 @@ async fn run_collect(compose_file: &Path, bundle_path: &Path) -> Result<PathBuf>
     Ok(bundle_path.to_path_buf())
 }
 
-async fn run_analyze(bundle_path: &Path, plan_path: &Path) -> Result<PathBuf> {
+/// Run xcprobe collect from this machine on the Windows host `winrm`.
+/// The WinRM password is passed on by `$XCPROBE_WINRM_PASSWORD`.
+async fn run_collect_winrm(winrm: &WinRmTarget, bundle_path: &Path) -> Result<PathBuf> {
+    let xcprobe_path =
+        find_binary("xcprobe").context("xcprobe binary not found in PATH or target/ directory")?;
+    let host = winrm
+        .host
+        .as_deref()
+        .context("No Windows host configured")?;
+
+    let mut command = Command::new(&xcprobe_path);
+    command.args([
+        "collect",
+        "--verbose",
+        "--mode",
+        "remote",
+        "--os",
+        "windows",
+        "--target",
+        host,
+    ]);
+    if let Some(port) = winrm.port {
+        command.args(["--winrm-port", &port.to_string()]);
+    }
+    if let Some(ref user) = winrm.user {
+        command.args(["--winrm-user", user]);
+    }
+    if winrm.https {
+        command.arg("--winrm-https");
+    } else {
+        // A scenario over plain HTTP targets a host set up to accept it
+        command.arg("--winrm-allow-unencrypted");
+    }
+    if winrm.insecure {
+        command.arg("--winrm-insecure");
+    }
+    if let Some(ref auth) = winrm.auth {
+        command.args(["--winrm-auth", auth]);
+    }
+    let output = command
+        .arg("--out")
+        .arg(bundle_path)
+        .output()
+        .context("Failed to run xcprobe collect")?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.is_empty() {
+        info!("xcprobe collect stderr:\n{}", stderr);
+    }
+    // A partial collection still writes the bundle
+    if output.status.code() == Some(ErrorCategory::PartialCollection.exit_code().into()) {
+        warn!("xcprobe collect was partial, some commands failed");
+    } else if !output.status.success() {
+        anyhow::bail!(
+            "xcprobe collect failed (exit {}): {}",
+            output.status,
+            stderr
+        );
+    }
+    Ok(bundle_path.to_path_buf())
+}
+
+/// Run xcprobe analyze on a bundle, with stand-ins for the external
+/// dependencies when the generated artifacts are to be started.
+async fn run_analyze(bundle_path: &Path, plan_path: &Path, dev_stubs: bool) -> Result<PathBuf> {
     let output_dir = plan_path.parent().unwrap();
 
     // Find xcprobe binary: check PATH, then common build output paths
     let xcprobe_path =
         find_binary("xcprobe").context("xcprobe binary not found in PATH or target/ directory")?;
 
-    let output = Command::new(&xcprobe_path)
+    let mut command = Command::new(&xcprobe_path);
+    command
         .args(["analyze", "--verbose", "--bundle"])
         .arg(bundle_path)
         .args(["--out"])
         .arg(output_dir)
-        .args(["--min-confidence", "0.3"])
-        .output()
-        .context("Failed to run xcprobe analyze")?;
+        .args(["--min-confidence", "0.3", "--force"]);
+    if dev_stubs {
+        command.arg("--dev-stubs");
+    }
+    let output = command.output().context("Failed to run xcprobe analyze")?;
 
     if !output.status.success() {
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -401,6 +743,36 @@ async fn run_analyze(bundle_path: &Path, plan_path: &Path) -> Result<PathBuf> {
     Ok(plan_path.to_path_buf())
 }
 
+/// Combine the pack plans of several targets into one for the metrics.
+/// Cluster and dependency IDs, unique within a plan only, are prefixed
+/// with the target's service name; cluster names are kept.
+fn merge_plans(plans: Vec<(String, PackPlan)>) -> PackPlan {
+    if plans.len() == 1 {
+        return plans.into_iter().next().map(|(_, plan)| plan).unwrap();
+    }
+    let mut merged = PackPlan::default();
+    for (service, plan) in plans {
+        let prefixed = |id: &String| format!("{}/{}", service, id);
+        for mut cluster in plan.clusters {
+            cluster.id = prefixed(&cluster.id);
+            cluster.depends_on = cluster.depends_on.iter().map(prefixed).collect();
+            cluster.external_deps = cluster.external_deps.iter().map(prefixed).collect();
+            merged.clusters.push(cluster);
+        }
+        for mut dependency in plan.external_dependencies {
+            dependency.id = prefixed(&dependency.id);
+            dependency.used_by = dependency.used_by.iter().map(prefixed).collect();
+            merged.external_dependencies.push(dependency);
+        }
+        for mut edge in plan.startup_dag {
+            edge.from = prefixed(&edge.from);
+            edge.to = prefixed(&edge.to);
+            merged.startup_dag.push(edge);
+        }
+    }
+    merged
+}
+
 /// Find a binary by name, checking PATH first, then target/release and target/debug.
 fn find_binary(name: &str) -> Result<PathBuf> {
     // Check PATH
@@ -425,12 +797,11 @@ fn find_binary(name: &str) -> Result<PathBuf> {
 }
 
 fn archive_artifacts(
-    artifacts_path: &Path,
+    archive_dir: &Path,
     bundle_path: &Option<PathBuf>,
     plan_path: &Option<PathBuf>,
 ) -> Result<()> {
-    let archive_dir = artifacts_path.join("failed_artifacts");
-    std::fs::create_dir_all(&archive_dir)?;
+    std::fs::create_dir_all(archive_dir)?;
 
     if let Some(bundle) = bundle_path {
         if bundle.exists() {
@@ -446,3 +817,66 @@ fn archive_artifacts(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcprobe_bundle_schema::{AppCluster, DependencyInfo};
+
+    #[test]
+    fn test_project_name() {
+        let name = project_name(Path::new("tests/scenarios/Scenario_A.v2"));
+        assert_eq!(
+            name,
+            format!("xcprobe-e2e-scenario_a-v2-{}", std::process::id())
+        );
+    }
+
+    #[test]
+    fn test_merge_plans() {
+        let plan = |name: &str| -> PackPlan {
+            let cluster = AppCluster {
+                id: "app-0".to_string(),
+                name: name.to_string(),
+                app_type: "api".to_string(),
+                external_deps: vec!["dep-0".to_string()],
+                confidence: 0.9,
+                ..Default::default()
+            };
+            let dependency = DependencyInfo {
+                id: "dep-0".to_string(),
+                dep_type: "database".to_string(),
+                endpoint: "db-host:5432".to_string(),
+                port: Some(5432),
+                used_by: vec!["app-0".to_string()],
+                evidence_refs: vec![],
+                addresses: vec![],
+                internal: None,
+            };
+            PackPlan {
+                clusters: vec![cluster],
+                external_dependencies: vec![dependency],
+                ..Default::default()
+            }
+        };
+
+        let merged = merge_plans(vec![
+            ("web-host".to_string(), plan("web")),
+            ("api-host".to_string(), plan("api")),
+        ]);
+        let ids: Vec<(&str, &str)> = merged
+            .clusters
+            .iter()
+            .map(|c| (c.id.as_str(), c.name.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![("web-host/app-0", "web"), ("api-host/app-0", "api")]
+        );
+        assert_eq!(merged.clusters[1].external_deps, vec!["api-host/dep-0"]);
+        assert_eq!(
+            merged.external_dependencies[1].used_by,
+            vec!["api-host/app-0"]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Scenario settings.
+//!
+//! `scenario.yaml`, next to the compose file, names the compose services
+//! the runner collects from, for scenarios spreading applications over
+//! several hosts. Without it, the runner collects from `host-sim`.
+//!
+//! A scenario with a `winrm` section has no compose file: it targets an
+//! external Windows host or VM, collected over WinRM from the runner's
+//! machine. The `XCPROBE_E2E_WINRM_*` environment variables override its
+//! settings, so that CI can point it at its own host.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Compose service probed when a scenario has no `scenario.yaml`.
+pub const DEFAULT_TARGET: &str = "host-sim";
+
+/// Settings of a scenario.
+#[derive(Debug, Deserialize)]
+pub struct Scenario {
+    /// Compose services to collect from, each analyzed on its own
+    #[serde(default)]
+    pub targets: Vec<Target>,
+    /// Windows host to collect from instead of compose services
+    #[serde(default)]
+    pub winrm: Option<WinRmTarget>,
+}
+
+/// A compose service the runner collects from.
+#[derive(Debug, Deserialize)]
+pub struct Target {
+    /// Compose service name
+    pub service: String,
+}
+
+/// A Windows host the runner collects from over WinRM. The password is
+/// read by xcprobe from `$XCPROBE_WINRM_PASSWORD`.
+#[derive(Debug, Default, Deserialize)]
+pub struct WinRmTarget {
+    /// Host name or IP; `$XCPROBE_E2E_WINRM_HOST`
+    pub host: Option<String>,
+    /// WinRM port; `$XCPROBE_E2E_WINRM_PORT`
+    pub port: Option<u16>,
+    /// WinRM user; `$XCPROBE_E2E_WINRM_USER`
+    pub user: Option<String>,
+    /// Use HTTPS; `$XCPROBE_E2E_WINRM_HTTPS=1`
+    #[serde(default)]
+    pub https: bool,
+    /// Accept any HTTPS certificate; `$XCPROBE_E2E_WINRM_INSECURE=1`
+    #[serde(default)]
+    pub insecure: bool,
+    /// Authentication scheme (negotiate, ntlm, basic); `$XCPROBE_E2E_WINRM_AUTH`
+    pub auth: Option<String>,
+}
+
+impl WinRmTarget {
+    /// Override the settings with the `XCPROBE_E2E_WINRM_*` variables
+    /// found by `var`.
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let flag = |name: &str| var(name).map(|v| matches!(v.as_str(), "1" | "true" | "yes"));
+        if let Some(host) = var("XCPROBE_E2E_WINRM_HOST") {
+            self.host = Some(host);
+        }
+        if let Some(port) = var("XCPROBE_E2E_WINRM_PORT") {
+            self.port = Some(
+                port.parse()
+                    .with_context(|| format!("Invalid XCPROBE_E2E_WINRM_PORT: {}", port))?,
+            );
+        }
+        if let Some(user) = var("XCPROBE_E2E_WINRM_USER") {
+            self.user = Some(user);
+        }
+        if let Some(https) = flag("XCPROBE_E2E_WINRM_HTTPS") {
+            self.https = https;
+        }
+        if let Some(insecure) = flag("XCPROBE_E2E_WINRM_INSECURE") {
+            self.insecure = insecure;
+        }
+        if let Some(auth) = var("XCPROBE_E2E_WINRM_AUTH") {
+            self.auth = Some(auth);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            targets: vec![Target {
+                service: DEFAULT_TARGET.to_string(),
+            }],
+            winrm: None,
+        }
+    }
+}
+
+/// Load `scenario.yaml` from a scenario directory, or the default settings
+/// when there is none.
+pub fn load_scenario(scenario_path: &Path) -> Result<Scenario> {
+    let path = scenario_path.join("scenario.yaml");
+    if !path.exists() {
+        return Ok(Scenario::default());
+    }
+    let content = std::fs::read_to_string(&path).context("Failed to read scenario.yaml")?;
+    let mut scenario: Scenario =
+        serde_yaml::from_str(&content).context("Failed to parse scenario.yaml")?;
+
+    if let Some(ref mut winrm) = scenario.winrm {
+        if !scenario.targets.is_empty() {
+            anyhow::bail!("scenario.yaml lists both targets and a winrm host");
+        }
+        // CI sets unconfigured secrets to empty strings
+        winrm.apply_env(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))?;
+        return Ok(scenario);
+    }
+    if scenario.targets.is_empty() {
+        anyhow::bail!("scenario.yaml lists no targets");
+    }
+    for (n, target) in scenario.targets.iter().enumerate() {
+        let valid = !target.service.is_empty()
+            && target
+                .service
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            anyhow::bail!("Invalid target service name: {:?}", target.service);
+        }
+        if scenario.targets[..n]
+            .iter()
+            .any(|t| t.service == target.service)
+        {
+            anyhow::bail!("Target {} is listed twice", target.service);
+        }
+    }
+    Ok(scenario)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_scenario() {
+        let dir = std::env::temp_dir().join(format!("e2e-scenario-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let scenario = load_scenario(&dir).unwrap();
+        assert_eq!(scenario.targets[0].service, DEFAULT_TARGET);
+
+        std::fs::write(
+            dir.join("scenario.yaml"),
+            "targets:\n  - service: web-host\n  - service: db-host\n",
+        )
+        .unwrap();
+        let services: Vec<String> = load_scenario(&dir)
+            .unwrap()
+            .targets
+            .into_iter()
+            .map(|t| t.service)
+            .collect();
+        assert_eq!(services, vec!["web-host", "db-host"]);
+
+        std::fs::write(
+            dir.join("scenario.yaml"),
+            "targets:\n  - service: web-host\n  - service: web-host\n",
+        )
+        .unwrap();
+        assert!(load_scenario(&dir).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_winrm_env_overrides() {
+        let scenario: Scenario =
+            serde_yaml::from_str("winrm:\n  host: win-vm\n  port: 5985\n  auth: basic\n").unwrap();
+        let mut winrm = scenario.winrm.unwrap();
+        let env = |name: &str| match name {
+            "XCPROBE_E2E_WINRM_HOST" => Some("10.0.0.5".to_string()),
+            "XCPROBE_E2E_WINRM_PORT" => Some("5986".to_string()),
+            "XCPROBE_E2E_WINRM_HTTPS" => Some("1".to_string()),
+            _ => None,
+        };
+        winrm.apply_env(env).unwrap();
+        assert_eq!(winrm.host.as_deref(), Some("10.0.0.5"));
+        assert_eq!(winrm.port, Some(5986));
+        assert!(winrm.https);
+        assert!(!winrm.insecure);
+        assert_eq!(winrm.auth.as_deref(), Some("basic"));
+
+        let bad_port = |name: &str| (name == "XCPROBE_E2E_WINRM_PORT").then(|| "x".to_string());
+        assert!(winrm.apply_env(bad_port).is_err());
+    }
+}
@@ -1,111 +1,11 @@
 //! Truth file handling.
 
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
 use std::path::Path;
 
-/// Ground truth for a test scenario.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Truth {
-    /// Schema version
-    pub version: String,
-    /// Scenario name
-    pub name: String,
-    /// Description
-    pub description: Option<String>,
-    /// Expected applications
-    pub applications: Vec<ExpectedApp>,
-    /// Expected ports
-    pub ports: Vec<ExpectedPort>,
-    /// Expected environment variable names
-    pub env_names: Vec<String>,
-    /// Expected dependencies
-    pub dependencies: Vec<ExpectedDependency>,
-    /// Expected config files
-    pub config_files: Vec<String>,
-    /// Thresholds for pass/fail
-    #[serde(default)]
-    pub thresholds: Thresholds,
-}
-
-/// Expected application.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExpectedApp {
-    /// Application name
-    pub name: String,
-    /// Application type
-    pub app_type: String,
-    /// Expected command patterns
-    pub command_patterns: Vec<String>,
-    /// Expected user
-    pub user: Option<String>,
-    /// Expected working directory
-    pub working_directory: Option<String>,
-}
-
-/// Expected port.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExpectedPort {
-    pub port: u16,
-    pub protocol: String,
-    pub process_name: Option<String>,
-}
-
-/// Expected dependency.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExpectedDependency {
-    pub from: String,
-    pub to: String,
-    pub dep_type: String,
-}
-
-/// Thresholds for pass/fail determination.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Thresholds {
-    /// Minimum process/cmdline recall
-    #[serde(default = "default_process_recall")]
-    pub process_cmdline_recall: f64,
-    /// Minimum ports recall
-    #[serde(default = "default_ports_recall")]
-    pub ports_recall: f64,
-    /// Minimum env names recall
-    #[serde(default = "default_env_recall")]
-    pub env_names_recall: f64,
-    /// Minimum dependencies recall
-    #[serde(default = "default_deps_recall")]
-    pub deps_recall: f64,
-    /// All decisions must have evidence
-    #[serde(default = "default_require_evidence")]
-    pub require_all_evidence: bool,
-}
-
-fn default_process_recall() -> f64 {
-    0.9
-}
-fn default_ports_recall() -> f64 {
-    0.95
-}
-fn default_env_recall() -> f64 {
-    0.8
-}
-fn default_deps_recall() -> f64 {
-    0.8
-}
-fn default_require_evidence() -> bool {
-    true
-}
-
-impl Default for Thresholds {
-    fn default() -> Self {
-        Self {
-            process_cmdline_recall: default_process_recall(),
-            ports_recall: default_ports_recall(),
-            env_names_recall: default_env_recall(),
-            deps_recall: default_deps_recall(),
-            require_all_evidence: default_require_evidence(),
-        }
-    }
-}
+pub use xcprobe_bundle_schema::truth::{
+    ExpectedApp, ExpectedDependency, ExpectedPort, ForbiddenItems, Thresholds, Truth,
+};
 
 /// Load truth from file.
 pub fn load_truth(path: &Path) -> Result<Truth> {
@@ -145,6 +45,33 @@ pub fn validate_truth(truth: &Truth) -> TruthValidation {
         errors.push("ports_recall threshold cannot exceed 1.0".to_string());
     }
 
+    let precisions = [
+        (
+            "process_cmdline_precision",
+            truth.thresholds.process_cmdline_precision,
+        ),
+        ("ports_precision", truth.thresholds.ports_precision),
+        ("env_names_precision", truth.thresholds.env_names_precision),
+        ("deps_precision", truth.thresholds.deps_precision),
+    ];
+    for (name, threshold) in precisions {
+        if threshold.is_some_and(|t| t > 1.0) {
+            errors.push(format!("{} threshold cannot exceed 1.0", name));
+        }
+    }
+
+    // An item both expected and forbidden can never pass
+    for port in &truth.must_not_contain.ports {
+        if truth.ports.iter().any(|p| p.port == *port) {
+            errors.push(format!("Port {} is both expected and forbidden", port));
+        }
+    }
+    for name in &truth.must_not_contain.env_names {
+        if truth.env_names.contains(name) {
+            errors.push(format!("Env name {} is both expected and forbidden", name));
+        }
+    }
+
     // Check for duplicate ports
     let mut seen_ports = std::collections::HashSet::new();
     for port in &truth.ports {
@@ -184,6 +111,8 @@ mod tests {
         assert_eq!(truth.name, "test-scenario");
         assert_eq!(truth.applications.len(), 1);
         assert_eq!(truth.ports[0].port, 8080);
+        assert_eq!(truth.thresholds.ports_precision, None);
+        assert!(truth.must_not_contain.ports.is_empty());
     }
 
     #[test]
@@ -198,6 +127,7 @@ mod tests {
             dependencies: vec![],
             config_files: vec![],
             thresholds: Thresholds::default(),
+            must_not_contain: ForbiddenItems::default(),
         };
 
         let result = validate_truth(&truth);
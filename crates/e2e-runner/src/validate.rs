@@ -0,0 +1,209 @@
+//! Build-and-boot validation of generated artifacts.
+//!
+//! The generated Dockerfiles are built and the generated compose file is
+//! started as a compose project of its own, with its own network and
+//! without published ports, so that it neither reaches the scenario's
+//! containers nor collides with other runs. Every service must then be
+//! running, and healthy when the generated readiness check gives it a
+//! healthcheck.
+
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::path::Path;
+use std::process::Output;
+use tokio::process::Command;
+use tokio::time::{Duration, Instant};
+use tracing::info;
+
+/// Compose override written next to the generated compose file.
+const OVERRIDE_FILE: &str = "docker-compose.validate.yaml";
+
+/// Build and start the artifacts generated in `output_dir` as compose
+/// `project`, within `timeout`, and return the reasons they fail to start.
+pub async fn validate_artifacts(
+    output_dir: &Path,
+    project: &str,
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    let deadline = Instant::now() + timeout;
+    let mut files = vec![output_dir.join("docker-compose.yaml")];
+    let dev = output_dir.join("docker-compose.dev.yaml");
+    if dev.exists() {
+        files.push(dev);
+    }
+    let mut services = Vec::new();
+    for file in &files {
+        let content =
+            std::fs::read_to_string(file).with_context(|| format!("Failed to read {:?}", file))?;
+        let compose: Value = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse {:?}", file))?;
+        for service in compose_services(&compose) {
+            if !services.contains(&service) {
+                services.push(service);
+            }
+        }
+    }
+    let override_path = output_dir.join(OVERRIDE_FILE);
+    std::fs::write(&override_path, unpublish_override(&services))?;
+    files.push(override_path);
+
+    let compose = |args: &[&str]| {
+        let mut command = Command::new("docker");
+        command.args(["compose", "-p", project]);
+        for file in &files {
+            command.arg("-f").arg(file);
+        }
+        command.args(args).kill_on_drop(true);
+        command
+    };
+
+    let mut failures = Vec::new();
+    info!("Building the generated images...");
+    let build = run_until(compose(&["build"]), deadline).await?;
+    if !build.status.success() {
+        failures.push(format!(
+            "Generated images failed to build: {}",
+            tail(&build.stderr)
+        ));
+    } else {
+        info!("Starting the generated services...");
+        let wait_timeout = deadline
+            .saturating_duration_since(Instant::now())
+            .as_secs()
+            .max(1)
+            .to_string();
+        let up = run_until(
+            compose(&["up", "-d", "--wait", "--wait-timeout", &wait_timeout]),
+            deadline,
+        )
+        .await;
+        let ps = run_until(compose(&["ps", "--all", "--format", "json"]), deadline).await;
+        match (up, ps) {
+            (Ok(up), Ok(ps)) => {
+                failures.extend(service_failures(&String::from_utf8_lossy(&ps.stdout)));
+                if !up.status.success() && failures.is_empty() {
+                    failures.push(format!(
+                        "Generated services failed to start: {}",
+                        tail(&up.stderr)
+                    ));
+                }
+            }
+            (Err(e), _) | (_, Err(e)) => failures.push(format!("{:#}", e)),
+        }
+        if !failures.is_empty() {
+            if let Ok(logs) = compose(&["logs", "--tail", "50"]).output().await {
+                info!(
+                    "Generated services logs:\n{}",
+                    String::from_utf8_lossy(&logs.stdout)
+                );
+            }
+        }
+    }
+
+    // Cleanup runs whatever time is left
+    let _ = compose(&["down", "-v", "--rmi", "local"]).output().await;
+    Ok(failures)
+}
+
+/// Run a command, failing once `deadline` is reached.
+async fn run_until(mut command: Command, deadline: Instant) -> Result<Output> {
+    match tokio::time::timeout_at(deadline, command.output()).await {
+        Ok(output) => output.context("Failed to run docker compose"),
+        Err(_) => anyhow::bail!("Artifact validation timed out"),
+    }
+}
+
+/// Service names of a compose file.
+fn compose_services(compose: &Value) -> Vec<String> {
+    compose
+        .get("services")
+        .and_then(Value::as_mapping)
+        .map(|services| {
+            services
+                .keys()
+                .filter_map(|name| name.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A compose override removing the published ports of `services`.
+fn unpublish_override(services: &[String]) -> String {
+    let mut content = String::from("# Written by e2e-runner --validate-artifacts\nservices:\n");
+    for service in services {
+        content.push_str(&format!("  {}:\n    ports: !reset []\n", service));
+    }
+    content
+}
+
+/// Services of `docker compose ps --format json` output that are not
+/// running, or running but not healthy. Compose prints a JSON array or one
+/// object per line depending on its version.
+fn service_failures(ps: &str) -> Vec<String> {
+    let entries: Vec<serde_json::Value> = match serde_json::from_str(ps.trim()) {
+        Ok(serde_json::Value::Array(entries)) => entries,
+        _ => ps
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+    };
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let field = |name: &str| entry.get(name).and_then(|v| v.as_str()).unwrap_or("");
+            let service = field("Service");
+            match (field("State"), field("Health")) {
+                ("running", "" | "healthy") => None,
+                ("running", health) => Some(format!("Generated service {} is {}", service, health)),
+                // One-off containers, such as migrations, may have completed
+                ("exited", _) if field("ExitCode") == "0" || entry["ExitCode"] == 0 => None,
+                (state, _) => Some(format!("Generated service {} is {}", service, state)),
+            }
+        })
+        .collect()
+}
+
+/// The last lines of a command's output.
+fn tail(output: &[u8]) -> String {
+    let output = String::from_utf8_lossy(output);
+    let lines: Vec<&str> = output.lines().collect();
+    lines[lines.len().saturating_sub(20)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unpublish_override() {
+        let compose: Value = serde_yaml::from_str(
+            "services:\n  app-0:\n    build: ./app-0\n    ports: [\"8080:8080\"]\n  app-1:\n    image: x\n",
+        )
+        .unwrap();
+        assert_eq!(
+            unpublish_override(&compose_services(&compose)),
+            "# Written by e2e-runner --validate-artifacts\nservices:\n  \
+             app-0:\n    ports: !reset []\n  app-1:\n    ports: !reset []\n"
+        );
+    }
+
+    #[test]
+    fn test_service_failures() {
+        let lines = r#"{"Service":"app-0","State":"running","Health":"healthy"}
+{"Service":"app-1","State":"running","Health":"unhealthy"}
+{"Service":"app-2","State":"exited","ExitCode":1}
+{"Service":"migrate","State":"exited","ExitCode":0}"#;
+        let expected = vec![
+            "Generated service app-1 is unhealthy",
+            "Generated service app-2 is exited",
+        ];
+        assert_eq!(service_failures(lines), expected);
+
+        let array = r#"[{"Service":"app-0","State":"running","Health":""},
+                        {"Service":"app-1","State":"running","Health":"starting"}]"#;
+        assert_eq!(
+            service_failures(array),
+            vec!["Generated service app-1 is starting"]
+        );
+    }
+}
@@ -1,53 +1,272 @@
 //! Bundle reading and writing utilities.
+//!
+//! Bundles are written in one of two archive layouts:
+//!
+//! - v1, a gzip-compressed tarball of `manifest.json`, `audit.jsonl`,
+//!   `checksums.json` and the evidence blobs;
+//! - v2, a zstd-compressed tarball starting with `bundle.json` (format
+//!   version, schema version and compression) and the evidence index
+//!   `index.json`, then the manifest split by category under `manifest/`,
+//!   the audit log, the checksums and the evidence blobs.
+//!
+//! Readers detect the layout from the compression magic, and refuse format
+//! and schema versions newer than the ones they know.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::Path;
+use std::str::FromStr;
 use tar::{Archive, Builder};
-use tracing::info;
-use xcprobe_bundle_schema::{validation, Bundle, Evidence, Manifest};
+use tracing::{info, warn};
+use xcprobe_bundle_schema::{
+    migration, validation, AuditEntry, Bundle, Evidence, Manifest, MANIFEST_SCHEMA_VERSION,
+};
+use xcprobe_common::hash::sha256_bytes;
+
+/// Directory of the content-addressed evidence blobs in a bundle.
+pub const BLOB_DIR: &str = "blobs";
+
+/// Evidence index of a v2 bundle.
+pub const INDEX_FILE: &str = "index.json";
+
+/// Newest archive layout this version reads.
+pub const LATEST_FORMAT_VERSION: u32 = 2;
+
+/// First file of a v2 bundle, describing its layout.
+const INFO_FILE: &str = "bundle.json";
+
+/// Directory of the manifest sections of a v2 bundle.
+const MANIFEST_DIR: &str = "manifest";
+
+/// Manifest fields stored in a file of their own in a v2 bundle; the other
+/// fields are in `manifest/core.json`.
+const MANIFEST_SECTIONS: &[&str] = &[
+    "processes",
+    "services",
+    "ports",
+    "connections",
+    "packages",
+    "scheduled_tasks",
+    "config_files",
+    "directories",
+    "log_files",
+    "environment_files",
+    "runtimes",
+    "binaries",
+    "resource_samples",
+    "registry_keys",
+];
+
+/// Compression of a v2 bundle.
+const COMPRESSION: &str = "zstd";
+
+/// First bytes of a gzip stream (v1).
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// First bytes of a zstd frame (v2).
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Archive layout of a bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BundleFormat {
+    /// A gzip-compressed tarball with a single manifest.
+    #[default]
+    V1,
+    /// A zstd-compressed tarball with the manifest split by category and
+    /// an evidence index.
+    V2,
+}
+
+impl FromStr for BundleFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "v1" | "1" => Ok(BundleFormat::V1),
+            "v2" | "2" => Ok(BundleFormat::V2),
+            _ => Err(anyhow::anyhow!("Invalid bundle format: {}", s)),
+        }
+    }
+}
+
+impl BundleFormat {
+    /// File name extension of the bundles, without the leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            BundleFormat::V1 => "tgz",
+            BundleFormat::V2 => "tar.zst",
+        }
+    }
+}
+
+/// Content of `bundle.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleInfo {
+    format_version: u32,
+    schema_version: String,
+    compression: String,
+}
+
+/// An evidence file in the index of a v2 bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// SHA256 of the content, naming its blob.
+    pub sha256: String,
+    /// Size of the content in bytes.
+    pub size: u64,
+}
 
 /// Write a bundle to a compressed tarball.
 pub fn write_bundle(bundle: &Bundle, path: &Path) -> Result<()> {
     let file = File::create(path).context("Failed to create bundle file")?;
-    let encoder = GzEncoder::new(file, Compression::default());
-    let mut archive = Builder::new(encoder);
-
-    // Write manifest.json
-    let manifest_json = serde_json::to_string_pretty(&bundle.manifest)?;
-    add_file_to_archive(&mut archive, "manifest.json", manifest_json.as_bytes())?;
+    write_bundle_to(bundle, file)
+}
 
-    // Write audit.jsonl
-    let audit_content: Vec<String> = bundle
-        .audit
-        .iter()
-        .filter_map(|e| serde_json::to_string(e).ok())
-        .collect();
-    let audit_jsonl = audit_content.join("\n");
-    add_file_to_archive(&mut archive, "audit.jsonl", audit_jsonl.as_bytes())?;
+/// Write a bundle as a compressed tarball to `writer`, such as stdout when
+/// nothing may be written on the target.
+pub fn write_bundle_to<W: Write>(bundle: &Bundle, writer: W) -> Result<()> {
+    write_bundle_as(bundle, writer, BundleFormat::V1)
+}
 
-    // Write evidence files
+/// Write a bundle to `writer` in the given archive layout.
+pub fn write_bundle_as<W: Write>(bundle: &Bundle, writer: W, format: BundleFormat) -> Result<()> {
+    // Evidence is stored once per distinct content; the manifest maps each
+    // evidence path to its blob
+    let mut manifest = bundle.manifest.clone();
+    let mut blobs: BTreeMap<String, &[u8]> = BTreeMap::new();
+    let mut index: BTreeMap<String, IndexEntry> = BTreeMap::new();
     for (path, evidence) in &bundle.evidence {
         if let Some(ref content) = evidence.content {
-            add_file_to_archive(&mut archive, path, content)?;
+            let hash = sha256_bytes(content);
+            manifest.evidence_blobs.insert(path.clone(), hash.clone());
+            index.insert(
+                path.clone(),
+                IndexEntry {
+                    sha256: hash.clone(),
+                    size: content.len() as u64,
+                },
+            );
+            blobs.entry(hash).or_insert(content);
         }
     }
 
-    // Write checksums.json
+    let audit_jsonl = bundle
+        .audit
+        .iter()
+        .filter_map(|e| serde_json::to_string(e).ok())
+        .collect::<Vec<_>>()
+        .join("\n");
     let checksums_json = serde_json::to_string_pretty(&bundle.checksums)?;
-    add_file_to_archive(&mut archive, "checksums.json", checksums_json.as_bytes())?;
 
-    archive.finish()?;
+    match format {
+        BundleFormat::V1 => {
+            let encoder = GzEncoder::new(writer, Compression::default());
+            let mut archive = Builder::new(encoder);
+            let manifest_json = serde_json::to_string_pretty(&manifest)?;
+            add_file_to_archive(&mut archive, "manifest.json", manifest_json.as_bytes())?;
+            add_file_to_archive(&mut archive, "audit.jsonl", audit_jsonl.as_bytes())?;
+            for (hash, content) in &blobs {
+                let compressed = gzip(content, Compression::best())?;
+                add_file_to_archive(&mut archive, &blob_path(format, hash), &compressed)?;
+            }
+            add_file_to_archive(&mut archive, "checksums.json", checksums_json.as_bytes())?;
+            archive.into_inner()?.finish()?.flush()?;
+        }
+        BundleFormat::V2 => {
+            let encoder = zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            let mut archive = Builder::new(encoder);
+            let info = BundleInfo {
+                format_version: LATEST_FORMAT_VERSION,
+                schema_version: manifest.schema_version.clone(),
+                compression: COMPRESSION.to_string(),
+            };
+            add_file_to_archive(&mut archive, INFO_FILE, &serde_json::to_vec_pretty(&info)?)?;
+            add_file_to_archive(
+                &mut archive,
+                INDEX_FILE,
+                &serde_json::to_vec_pretty(&index)?,
+            )?;
+
+            let Value::Object(mut core) = serde_json::to_value(&manifest)? else {
+                bail!("The manifest does not serialize to an object");
+            };
+            // In the index
+            core.remove("evidence_blobs");
+            let sections: Vec<(&str, Value)> = MANIFEST_SECTIONS
+                .iter()
+                .filter_map(|section| Some((*section, core.remove(*section)?)))
+                .collect();
+            add_file_to_archive(
+                &mut archive,
+                &format!("{}/core.json", MANIFEST_DIR),
+                &serde_json::to_vec(&core)?,
+            )?;
+            for (section, value) in sections {
+                let path = format!("{}/{}.json", MANIFEST_DIR, section);
+                add_file_to_archive(&mut archive, &path, &serde_json::to_vec(&value)?)?;
+            }
+            add_file_to_archive(&mut archive, "audit.jsonl", audit_jsonl.as_bytes())?;
+            add_file_to_archive(&mut archive, "checksums.json", checksums_json.as_bytes())?;
+            for (hash, content) in &blobs {
+                add_file_to_archive(&mut archive, &blob_path(format, hash), content)?;
+            }
+            archive.into_inner()?.finish()?.flush()?;
+        }
+    }
+    info!(
+        "{} evidence files stored as {} blobs",
+        manifest.evidence_blobs.len(),
+        blobs.len()
+    );
     info!("Bundle written successfully");
 
     Ok(())
 }
 
+/// Bundle path of the blob holding content with the given SHA256: each
+/// blob is gzip-compressed on its own in v1, and stored as is in the zstd
+/// stream of v2.
+fn blob_path(format: BundleFormat, hash: &str) -> String {
+    match format {
+        BundleFormat::V1 => format!("{}/{}.gz", BLOB_DIR, hash),
+        BundleFormat::V2 => format!("{}/{}", BLOB_DIR, hash),
+    }
+}
+
+/// The SHA256 a blob path names, and whether the blob is gzip-compressed.
+fn blob_hash(path: &str) -> Option<(&str, bool)> {
+    let name = path.strip_prefix(BLOB_DIR)?.strip_prefix('/')?;
+    Some(match name.strip_suffix(".gz") {
+        Some(hash) => (hash, true),
+        None => (name, false),
+    })
+}
+
+/// Content of a blob, decompressed if it is gzip-compressed.
+fn blob_content(path: &str, content: &[u8]) -> Result<Vec<u8>> {
+    if !blob_hash(path).is_some_and(|(_, gzipped)| gzipped) {
+        return Ok(content.to_vec());
+    }
+    let mut data = Vec::new();
+    GzDecoder::new(content)
+        .read_to_end(&mut data)
+        .with_context(|| format!("Failed to decompress {}", path))?;
+    Ok(data)
+}
+
+fn gzip(content: &[u8], level: Compression) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), level);
+    encoder.write_all(content)?;
+    Ok(encoder.finish()?)
+}
+
 fn add_file_to_archive<W: Write>(
     archive: &mut Builder<W>,
     path: &str,
@@ -62,27 +281,134 @@ fn add_file_to_archive<W: Write>(
     Ok(())
 }
 
+/// Pass each file of a bundle archive of either layout to `visit`, with
+/// blobs as stored. Returns the layout.
+fn for_each_file<R: Read>(
+    reader: R,
+    mut visit: impl FnMut(String, Vec<u8>) -> Result<()>,
+) -> Result<BundleFormat> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        let mut archive = Archive::new(GzDecoder::new(reader));
+        for entry in archive.entries()? {
+            let (path, content) = read_entry(entry?)?;
+            visit(path, content)?;
+        }
+        return Ok(BundleFormat::V1);
+    }
+    if !magic.starts_with(&ZSTD_MAGIC) {
+        bail!("Not a bundle: neither gzip- nor zstd-compressed");
+    }
+
+    let mut archive = Archive::new(zstd::Decoder::with_buffer(reader)?);
+    let mut entries = archive.entries()?;
+    let info = match entries.next() {
+        Some(entry) => read_entry(entry?)?,
+        None => bail!("Empty bundle"),
+    };
+    if info.0 != INFO_FILE {
+        bail!("Not a bundle: {} does not come first", INFO_FILE);
+    }
+    let info: BundleInfo = serde_json::from_slice(&info.1)?;
+    if info.format_version > LATEST_FORMAT_VERSION {
+        bail!(
+            "Bundle format {} is newer than this version of xcprobe reads (up to {}): upgrade xcprobe",
+            info.format_version,
+            LATEST_FORMAT_VERSION
+        );
+    }
+    if info.compression != COMPRESSION {
+        bail!("Unsupported bundle compression: {}", info.compression);
+    }
+    migration::check_supported(&info.schema_version, MANIFEST_SCHEMA_VERSION)?;
+
+    for entry in entries {
+        let (path, content) = read_entry(entry?)?;
+        visit(path, content)?;
+    }
+    Ok(BundleFormat::V2)
+}
+
+fn read_entry<R: Read>(mut entry: tar::Entry<R>) -> Result<(String, Vec<u8>)> {
+    let path = entry.path()?.to_string_lossy().to_string();
+    let mut content = Vec::new();
+    entry.read_to_end(&mut content)?;
+    Ok((path, content))
+}
+
+/// The manifest of a bundle, read whole (v1) or by section with the
+/// evidence index (v2).
+#[derive(Default)]
+struct ManifestParts {
+    fields: serde_json::Map<String, Value>,
+    index: Option<BTreeMap<String, IndexEntry>>,
+    found: bool,
+}
+
+impl ManifestParts {
+    /// Take the file at `path` if it is part of the manifest.
+    fn add(&mut self, path: &str, content: &[u8]) -> Result<bool> {
+        let core = format!("{}/core.json", MANIFEST_DIR);
+        if path == "manifest.json" || path == core {
+            let Value::Object(fields) = serde_json::from_slice(content)? else {
+                bail!("{} is not an object", path);
+            };
+            self.fields.extend(fields);
+            self.found = true;
+        } else if let Some(section) = path
+            .strip_prefix(&format!("{}/", MANIFEST_DIR))
+            .and_then(|p| p.strip_suffix(".json"))
+        {
+            self.fields
+                .insert(section.to_string(), serde_json::from_slice(content)?);
+        } else if path == INDEX_FILE {
+            self.index = Some(serde_json::from_slice(content)?);
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn build(self) -> Result<Manifest> {
+        if !self.found {
+            bail!("Missing manifest.json in bundle");
+        }
+        // Manifests of older versions are upgraded
+        let (mut manifest, applied) = migration::migrate_manifest(Value::Object(self.fields))?;
+        for step in applied {
+            warn!("{}", step);
+        }
+        if let Some(index) = self.index {
+            manifest.evidence_blobs = index
+                .into_iter()
+                .map(|(path, entry)| (path, entry.sha256))
+                .collect();
+        }
+        Ok(manifest)
+    }
+}
+
 /// Read a bundle from a compressed tarball.
 pub fn read_bundle(path: &Path) -> Result<Bundle> {
     let file = File::open(path).context("Failed to open bundle file")?;
-    let decoder = GzDecoder::new(file);
-    let mut archive = Archive::new(decoder);
+    read_bundle_from(file)
+}
 
-    let mut manifest: Option<Manifest> = None;
-    let mut audit = Vec::new();
+/// Read a bundle of either layout from `reader`.
+pub fn read_bundle_from<R: Read>(reader: R) -> Result<Bundle> {
+    let mut manifest = ManifestParts::default();
+    let mut audit: Vec<AuditEntry> = Vec::new();
     let mut evidence: HashMap<String, Evidence> = HashMap::new();
     let mut checksums: HashMap<String, String> = HashMap::new();
+    let mut blobs: Vec<(String, Vec<u8>)> = Vec::new();
+    let blob_prefix = format!("{}/", BLOB_DIR);
 
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let path = entry.path()?.to_string_lossy().to_string();
-
-        let mut content = Vec::new();
-        entry.read_to_end(&mut content)?;
-
-        if path == "manifest.json" {
-            manifest = Some(serde_json::from_slice(&content)?);
-        } else if path == "audit.jsonl" {
+    for_each_file(reader, |path, content| {
+        if manifest.add(&path, &content)? {
+            return Ok(());
+        }
+        if path == "audit.jsonl" {
             let content_str = String::from_utf8_lossy(&content);
             for line in content_str.lines() {
                 if !line.trim().is_empty() {
@@ -93,66 +419,253 @@ pub fn read_bundle(path: &Path) -> Result<Bundle> {
             }
         } else if path == "checksums.json" {
             checksums = serde_json::from_slice(&content)?;
+        } else if path.starts_with(&blob_prefix) {
+            blobs.push((path, content));
         } else if path.starts_with("evidence/") || path.starts_with("attachments/") {
-            let hash = xcprobe_common::hash::sha256_bytes(&content);
-            let ev = Evidence {
-                id: path.clone(),
-                evidence_type: xcprobe_bundle_schema::EvidenceType::CommandOutput,
-                collected_at: chrono::Utc::now(),
-                source_command: None,
-                size_bytes: content.len() as u64,
-                content_hash: hash,
-                redacted: false,
-                bundle_path: path.clone(),
-                original_path: None,
-                content: Some(content),
-            };
-            evidence.insert(path, ev);
+            // Bundles written before content-addressed storage
+            evidence.insert(path.clone(), bundle_evidence(path, content));
+        }
+        Ok(())
+    })?;
+
+    let manifest = manifest.build()?;
+    let blobs = decompress_blobs(blobs)?;
+    for (path, hash) in &manifest.evidence_blobs {
+        let content = blobs
+            .get(hash)
+            .with_context(|| format!("Missing blob {} for {}", hash, path))?;
+        evidence.insert(path.clone(), bundle_evidence(path.clone(), content.clone()));
+    }
+
+    // Evidence metadata is not stored; the audit log knows its command
+    for entry in &audit {
+        if let Some(evidence) = evidence.get_mut(&entry.evidence_ref) {
+            evidence.source_command = Some(entry.command.clone());
+            evidence.collected_at = entry.completed_at;
+            evidence.audit_seq = Some(entry.seq);
         }
     }
 
     Ok(Bundle {
-        manifest: manifest.context("Missing manifest.json in bundle")?,
+        manifest,
         audit,
         evidence,
         checksums,
     })
 }
 
+/// Blob contents by SHA256, the gzip-compressed blobs of v1 decompressed
+/// on all cores: a large host has thousands.
+fn decompress_blobs(blobs: Vec<(String, Vec<u8>)>) -> Result<HashMap<String, Vec<u8>>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = blobs.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = blobs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|(path, content)| {
+                            let (hash, _) = blob_hash(path)?;
+                            Some(blob_content(path, content).map(|data| (hash.to_string(), data)))
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut decompressed = HashMap::new();
+        for worker in workers {
+            decompressed.extend(worker.join().expect("blob decompression panicked")?);
+        }
+        Ok(decompressed)
+    })
+}
+
+/// Evidence read back from a bundle.
+fn bundle_evidence(path: String, content: Vec<u8>) -> Evidence {
+    Evidence {
+        id: path.clone(),
+        evidence_type: xcprobe_bundle_schema::EvidenceType::CommandOutput,
+        collected_at: chrono::Utc::now(),
+        source_command: None,
+        size_bytes: content.len() as u64,
+        content_hash: sha256_bytes(&content),
+        redacted: false,
+        bundle_path: path,
+        original_path: None,
+        audit_seq: None,
+        content: Some(content),
+    }
+}
+
 /// Validate a bundle file.
+///
+/// With `verify_checksums`, every evidence file is hashed again from the
+/// archive entries and compared with `checksums.json`, and audit entries
+/// must reference evidence present in the bundle.
 pub fn validate_bundle_file(
     path: &Path,
     check_evidence: bool,
     verify_checksums: bool,
 ) -> Result<validation::ValidationResult> {
-    let bundle = read_bundle(path)?;
+    let mut result = validation::ValidationResult::new();
+    if verify_checksums {
+        verify_archive(path, &mut result)?;
+    }
+
+    let bundle = match read_bundle(path) {
+        Ok(bundle) => bundle,
+        // Corruption already reported by the archive checks
+        Err(e) if !result.valid => {
+            result.add_error(validation::ValidationError::CorruptFile {
+                file: path.display().to_string(),
+                reason: format!("{:#}", e),
+            });
+            return Ok(result);
+        }
+        Err(e) => return Err(e),
+    };
 
     let evidence_files: HashSet<String> = bundle.evidence.keys().cloned().collect();
 
-    let mut result =
+    let bundle_result =
         validation::validate_bundle(&bundle.manifest, &evidence_files, &bundle.checksums)?;
+    for error in bundle_result.errors {
+        result.add_error(error);
+    }
+    for warning in bundle_result.warnings {
+        result.add_warning(warning);
+    }
 
-    // Verify checksums
-    if verify_checksums {
-        for (path, expected_hash) in &bundle.checksums {
-            if let Some(evidence) = bundle.evidence.get(path) {
-                if evidence.content_hash != *expected_hash {
-                    result.add_error(validation::ValidationError::ChecksumMismatch {
-                        file: path.clone(),
-                        expected: expected_hash.clone(),
-                        actual: evidence.content_hash.clone(),
+    // Check evidence references exist
+    if check_evidence {
+        // Already done in validate_bundle
+    }
+
+    Ok(result)
+}
+
+/// Hash the evidence stored in the archive and check it against
+/// `checksums.json` and the audit log.
+fn verify_archive(path: &Path, result: &mut validation::ValidationResult) -> Result<()> {
+    use validation::ValidationError;
+
+    let mut manifest = ManifestParts::default();
+    let mut checksums: Option<HashMap<String, String>> = None;
+    let mut audit_refs: Vec<(u64, String)> = Vec::new();
+    // Path and hash of the decompressed content of each blob, by the hash
+    // naming it
+    let mut blobs: BTreeMap<String, (String, String)> = BTreeMap::new();
+    let mut corrupt_blobs: HashSet<String> = HashSet::new();
+    // Hash of the content of each evidence file, by evidence path
+    let mut hashes: BTreeMap<String, String> = BTreeMap::new();
+
+    let file = File::open(path).context("Failed to open bundle file")?;
+    let format = for_each_file(file, |entry_path, content| {
+        if manifest.add(&entry_path, &content)? {
+            return Ok(());
+        }
+        if let Some((hash, _)) = blob_hash(&entry_path) {
+            let hash = hash.to_string();
+            match blob_content(&entry_path, &content) {
+                Ok(data) => {
+                    blobs.insert(hash, (entry_path, sha256_bytes(&data)));
+                }
+                Err(e) => {
+                    result.add_error(ValidationError::CorruptFile {
+                        file: entry_path,
+                        reason: format!("{:#}", e),
                     });
+                    corrupt_blobs.insert(hash);
                 }
             }
+            return Ok(());
         }
+        match entry_path.as_str() {
+            "checksums.json" => checksums = Some(serde_json::from_slice(&content)?),
+            "audit.jsonl" => {
+                let content = String::from_utf8_lossy(&content);
+                for (n, line) in content.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<xcprobe_bundle_schema::AuditEntry>(line) {
+                        Ok(entry) => audit_refs.push((entry.seq, entry.evidence_ref)),
+                        Err(e) => result.add_error(ValidationError::CorruptFile {
+                            file: format!("audit.jsonl line {}", n + 1),
+                            reason: e.to_string(),
+                        }),
+                    }
+                }
+            }
+            p if p.starts_with("evidence/") || p.starts_with("attachments/") => {
+                hashes.insert(entry_path, sha256_bytes(&content));
+            }
+            _ => result.add_error(ValidationError::UnlistedFile(entry_path)),
+        }
+        Ok(())
+    })?;
+
+    let manifest = manifest.build()?;
+    let Some(checksums) = checksums else {
+        result.add_error(ValidationError::MissingFile("checksums.json".to_string()));
+        return Ok(());
+    };
+
+    // Blobs are named after the hash of their content
+    let mut referenced = HashSet::new();
+    for (evidence_path, hash) in &manifest.evidence_blobs {
+        match blobs.get(hash) {
+            Some((blob, actual)) => {
+                if actual != hash {
+                    result.add_error(ValidationError::ChecksumMismatch {
+                        file: blob.clone(),
+                        expected: hash.clone(),
+                        actual: actual.clone(),
+                    });
+                }
+                hashes.insert(evidence_path.clone(), actual.clone());
+            }
+            None if corrupt_blobs.contains(hash) => {}
+            None => result.add_error(ValidationError::MissingFile(blob_path(format, hash))),
+        }
+        referenced.insert(hash);
+    }
+    for (blob, _) in blobs
+        .iter()
+        .filter(|(hash, _)| !referenced.contains(hash))
+        .map(|(_, blob)| blob)
+    {
+        result.add_error(ValidationError::UnlistedFile(blob.clone()));
     }
 
-    // Check evidence references exist
-    if check_evidence {
-        // Already done in validate_bundle
+    let mut expected: Vec<_> = checksums.iter().collect();
+    expected.sort();
+    for (evidence_path, expected) in expected {
+        match hashes.get(evidence_path) {
+            Some(actual) if actual != expected => {
+                result.add_error(ValidationError::ChecksumMismatch {
+                    file: evidence_path.clone(),
+                    expected: expected.clone(),
+                    actual: actual.clone(),
+                });
+            }
+            Some(_) => {}
+            None => result.add_error(ValidationError::MissingFile(evidence_path.clone())),
+        }
+    }
+    for evidence_path in hashes.keys().filter(|p| !checksums.contains_key(*p)) {
+        result.add_error(ValidationError::UnlistedFile(evidence_path.clone()));
     }
 
-    Ok(result)
+    for (seq, evidence_ref) in audit_refs {
+        if !hashes.contains_key(&evidence_ref) {
+            result.add_error(ValidationError::InvalidAuditRef { seq, evidence_ref });
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -166,16 +679,273 @@ mod tests {
         let bundle_path = dir.path().join("test.tgz");
 
         let manifest = Manifest::default();
+        let now = chrono::Utc::now();
         let bundle = Bundle {
             manifest,
-            audit: vec![],
-            evidence: HashMap::new(),
+            audit: vec![AuditEntry::new(
+                3,
+                "ps aux".to_string(),
+                "processes".to_string(),
+                now,
+                now,
+                Some(0),
+                11,
+                0,
+                "evidence/ps_1.txt".to_string(),
+                None,
+            )],
+            evidence: HashMap::from([(
+                "evidence/ps_1.txt".to_string(),
+                Evidence::from_command_output(
+                    "ps_1",
+                    "ps aux",
+                    b"PID COMMAND".to_vec(),
+                    "evidence/ps_1.txt",
+                ),
+            )]),
             checksums: HashMap::new(),
         };
 
-        write_bundle(&bundle, &bundle_path).unwrap();
+        let mut stream = Vec::new();
+        write_bundle_to(&bundle, &mut stream).unwrap();
+        std::fs::write(&bundle_path, &stream).unwrap();
         let read_bundle = read_bundle(&bundle_path).unwrap();
 
         assert_eq!(read_bundle.manifest.schema_version, "1.0.0");
+        assert_eq!(
+            read_bundle_from(stream.as_slice()).unwrap().evidence.len(),
+            1
+        );
+        // Evidence is linked back to the command that produced it
+        let evidence = &read_bundle.evidence["evidence/ps_1.txt"];
+        assert_eq!(evidence.audit_seq, Some(3));
+        assert_eq!(evidence.source_command.as_deref(), Some("ps aux"));
+        assert_eq!(
+            read_bundle.audit_entry("evidence/ps_1.txt").map(|e| e.seq),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_identical_evidence_stored_once() {
+        let dir = tempdir().unwrap();
+        let bundle_path = dir.path().join("test.tgz");
+
+        let show = b"Id=app.service\nActiveState=active\n".repeat(50);
+        let evidence: HashMap<String, Evidence> = [
+            ("evidence/service_1.txt", show.clone()),
+            ("evidence/service_2.txt", show.clone()),
+            ("evidence/ps_1.txt", b"PID COMMAND".to_vec()),
+        ]
+        .into_iter()
+        .map(|(path, content)| {
+            (
+                path.to_string(),
+                Evidence::from_command_output(path, "cmd", content, path),
+            )
+        })
+        .collect();
+        let bundle = Bundle {
+            manifest: Manifest::default(),
+            audit: vec![],
+            evidence,
+            checksums: HashMap::new(),
+        };
+
+        write_bundle(&bundle, &bundle_path).unwrap();
+        let mut archive = Archive::new(GzDecoder::new(File::open(&bundle_path).unwrap()));
+        let blobs = archive
+            .entries()
+            .unwrap()
+            .filter(|e| {
+                let entry = e.as_ref().unwrap();
+                entry.path().unwrap().starts_with(BLOB_DIR)
+            })
+            .count();
+        assert_eq!(blobs, 2);
+
+        let read = read_bundle(&bundle_path).unwrap();
+        assert_eq!(read.manifest.evidence_blobs.len(), 3);
+        assert_eq!(read.evidence.len(), 3);
+        assert_eq!(
+            read.evidence["evidence/service_2.txt"].content.as_deref(),
+            Some(show.as_slice())
+        );
+        assert_eq!(
+            read.evidence["evidence/ps_1.txt"].content_hash,
+            sha256_bytes(b"PID COMMAND")
+        );
+    }
+
+    #[test]
+    fn test_write_read_bundle_v2() {
+        use xcprobe_bundle_schema::PortInfo;
+
+        let dir = tempdir().unwrap();
+        let bundle_path = dir.path().join("test.tgz");
+        let content = b"PID COMMAND".to_vec();
+        let evidence =
+            Evidence::from_command_output("ps_1", "ps", content.clone(), "evidence/ps_1.txt");
+        let bundle = Bundle {
+            manifest: Manifest {
+                ports: vec![PortInfo {
+                    protocol: "tcp".to_string(),
+                    local_address: "0.0.0.0".to_string(),
+                    local_port: 8080,
+                    state: "LISTEN".to_string(),
+                    pid: None,
+                    process_name: None,
+                    evidence_ref: None,
+                    pid_source: None,
+                    pid_evidence_ref: None,
+                    family: None,
+                    socket_path: None,
+                }],
+                system: xcprobe_bundle_schema::SystemInfo {
+                    hostname: "web01".to_string(),
+                    os_type: "linux".to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            audit: vec![],
+            checksums: HashMap::from([(
+                "evidence/ps_1.txt".to_string(),
+                evidence.content_hash.clone(),
+            )]),
+            evidence: HashMap::from([("evidence/ps_1.txt".to_string(), evidence)]),
+        };
+
+        write_bundle_as(
+            &bundle,
+            File::create(&bundle_path).unwrap(),
+            BundleFormat::V2,
+        )
+        .unwrap();
+        let bytes = std::fs::read(&bundle_path).unwrap();
+        assert!(bytes.starts_with(&ZSTD_MAGIC));
+        let names: Vec<String> = Archive::new(zstd::Decoder::new(bytes.as_slice()).unwrap())
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names[..3],
+            ["bundle.json", "index.json", "manifest/core.json"]
+        );
+        assert!(names.contains(&"manifest/ports.json".to_string()));
+        assert!(names.contains(&format!("blobs/{}", sha256_bytes(&content))));
+
+        let read = read_bundle(&bundle_path).unwrap();
+        assert_eq!(read.manifest.ports[0].local_port, 8080);
+        assert_eq!(read.evidence["evidence/ps_1.txt"].content, Some(content));
+        let result = validate_bundle_file(&bundle_path, true, true).unwrap();
+        assert!(result.valid, "{:?}", result.errors);
+
+        // A newer layout is refused rather than misread
+        let mut archive = Builder::new(zstd::Encoder::new(Vec::new(), 0).unwrap());
+        let info = r#"{"format_version": 3, "schema_version": "1.0.0", "compression": "zstd"}"#;
+        add_file_to_archive(&mut archive, INFO_FILE, info.as_bytes()).unwrap();
+        let newer = archive.into_inner().unwrap().finish().unwrap();
+        let error = read_bundle_from(newer.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("Bundle format 3 is newer"));
+    }
+
+    #[test]
+    fn test_verify_checksums_detects_tampering() {
+        use xcprobe_bundle_schema::AuditEntry;
+
+        let dir = tempdir().unwrap();
+        let bundle_path = dir.path().join("test.tgz");
+        let evidence: HashMap<String, Evidence> = [
+            ("evidence/ps_1.txt", b"PID COMMAND".to_vec()),
+            ("evidence/ss_1.txt", b"LISTEN 0.0.0.0:80".to_vec()),
+        ]
+        .into_iter()
+        .map(|(path, content)| {
+            (
+                path.to_string(),
+                Evidence::from_command_output(path, "cmd", content, path),
+            )
+        })
+        .collect();
+        let audit = ["evidence/ps_1.txt", "evidence/missing.txt"]
+            .iter()
+            .enumerate()
+            .map(|(seq, evidence_ref)| {
+                let now = chrono::Utc::now();
+                AuditEntry::new(
+                    seq as u64,
+                    "ps".to_string(),
+                    "process".to_string(),
+                    now,
+                    now,
+                    Some(0),
+                    0,
+                    0,
+                    evidence_ref.to_string(),
+                    None,
+                )
+            })
+            .collect();
+        let checksums = evidence
+            .iter()
+            .map(|(path, ev)| (path.clone(), ev.content_hash.clone()))
+            .collect();
+        let bundle = Bundle {
+            manifest: Manifest::default(),
+            audit,
+            evidence,
+            checksums,
+        };
+        write_bundle(&bundle, &bundle_path).unwrap();
+
+        let errors = |path: &Path| -> Vec<String> {
+            validate_bundle_file(path, false, true)
+                .unwrap()
+                .errors
+                .iter()
+                .filter(|e| !matches!(e, validation::ValidationError::SchemaError(_)))
+                .map(|e| e.to_string())
+                .collect()
+        };
+        assert_eq!(
+            errors(&bundle_path),
+            vec!["Audit entry 1 references missing evidence evidence/missing.txt"]
+        );
+
+        // Rewrite the archive with one blob altered and an extra file
+        let tampered_path = dir.path().join("tampered.tgz");
+        let ps_blob = blob_path(BundleFormat::V1, &sha256_bytes(b"PID COMMAND"));
+        let mut archive = Archive::new(GzDecoder::new(File::open(&bundle_path).unwrap()));
+        let mut builder = Builder::new(GzEncoder::new(
+            File::create(&tampered_path).unwrap(),
+            Compression::default(),
+        ));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            if path == ps_blob {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(b"PID COMMAND\n1 evil").unwrap();
+                content = encoder.finish().unwrap();
+            }
+            add_file_to_archive(&mut builder, &path, &content).unwrap();
+        }
+        add_file_to_archive(&mut builder, "evidence/extra.txt", b"extra").unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let errors = errors(&tampered_path);
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with(&format!("Checksum mismatch for {}", ps_blob))));
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("Checksum mismatch for evidence/ps_1.txt")));
+        assert!(
+            errors.contains(&"File not listed in checksums.json: evidence/extra.txt".to_string())
+        );
     }
 }
@@ -1,24 +1,37 @@
 //! System information collector.
 
-use crate::commands::{CommandSet, LinuxCommands, WindowsCommands};
-use crate::executor::{Executor, LocalExecutor, SshExecutor, WinRmExecutor};
+use crate::commands::{command_set, CommandSet, Denied};
+use crate::dry_run::{command_plan, PlannedCommand, HOST_PLACEHOLDER};
+use crate::executor::{
+    CommandTimeout, Executor, HostKeyCheck, HostKeyInfo, LocalExecutor, OpenSshExecutor,
+    SshExecutor, SshTransport, WinRmExecutor, WinRmTls,
+};
 use crate::parsers;
+use crate::phases::{default_phases, CollectionPhase, PhaseContext, PhaseSelection};
+use crate::progress::{NoProgress, ProgressEvent, ProgressReporter};
+use crate::winrm::WinRmAuth;
 use anyhow::Result;
 use chrono::Utc;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::str::FromStr;
-use tracing::{debug, info};
-use xcprobe_bundle_schema::{AuditEntry, AuditLog, Bundle, Evidence, FileInfo, Manifest};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::time::Instant;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+use xcprobe_bundle_schema::{
+    AuditEntry, AuditLog, Bundle, CollectionError, CommandStatus, Evidence, Manifest,
+};
 use xcprobe_common::OsType;
-use xcprobe_redaction::Redactor;
+use xcprobe_redaction::{Redactor, RedactorConfig};
 
 /// Collection mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CollectionMode {
-    /// Remote collection via SSH (Linux) or WinRM (Windows).
+    /// Remote collection via SSH (Linux, macOS, Unix) or WinRM (Windows).
     Remote,
-    /// Local ephemeral collection (for testing).
+    /// Collection on the machine running the probe, without a connection.
     LocalEphemeral,
 }
 
@@ -44,29 +57,394 @@ pub struct CollectorConfig {
     pub ssh_user: Option<String>,
     pub ssh_key: Option<PathBuf>,
     pub ssh_password: Option<String>,
+    pub ssh_host_key_check: HostKeyCheck,
+    /// SSH client: built-in libssh2 or the system `ssh` binary.
+    pub ssh_transport: SshTransport,
     pub winrm_port: u16,
     pub winrm_user: Option<String>,
     pub winrm_password: Option<String>,
     pub winrm_https: bool,
-    #[allow(dead_code)]
+    /// Allow WinRM over plain HTTP. Messages are not encrypted, which only
+    /// hosts configured with `AllowUnencrypted` accept.
+    pub winrm_allow_unencrypted: bool,
+    pub winrm_auth: WinRmAuth,
+    /// Certificate verification of WinRM over HTTPS.
+    pub winrm_tls: WinRmTls,
+    /// Overall collection deadline.
     pub timeout_seconds: u64,
+    /// Time limit for a single command.
+    pub command_timeout_seconds: u64,
+    pub retry: RetryPolicy,
+    pub phases: PhaseSelection,
+    /// Redaction of evidence and collected files.
+    pub redaction: RedactorConfig,
+    /// Repeated CPU/memory sampling of processes; disabled when `None`.
+    pub sampling: Option<SamplingConfig>,
+    /// Look up on the target the host names configuration files name.
+    pub resolve_hosts: bool,
+    /// Abort the collection on the first failed phase instead of recording
+    /// the failure and moving on.
+    pub strict: bool,
+}
+
+impl CollectorConfig {
+    /// The mode collection runs in: a remote collection of the machine
+    /// running the probe (`localhost`, a loopback address) runs locally.
+    pub fn effective_mode(&self) -> CollectionMode {
+        match self.mode {
+            CollectionMode::Remote
+                if is_local_target(&self.target) && self.os_type == OsType::host() =>
+            {
+                CollectionMode::LocalEphemeral
+            }
+            mode => mode,
+        }
+    }
+}
+
+/// Whether `target` names the machine running the probe.
+pub fn is_local_target(target: &str) -> bool {
+    let host = target.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+impl Default for CollectorConfig {
+    /// Local ephemeral collection of a Linux host, with the CLI defaults.
+    fn default() -> Self {
+        Self {
+            target: "localhost".to_string(),
+            os_type: OsType::Linux,
+            mode: CollectionMode::LocalEphemeral,
+            ssh_port: 22,
+            ssh_user: None,
+            ssh_key: None,
+            ssh_password: None,
+            ssh_host_key_check: HostKeyCheck::default(),
+            ssh_transport: SshTransport::default(),
+            winrm_port: 5985,
+            winrm_user: None,
+            winrm_password: None,
+            winrm_https: false,
+            winrm_allow_unencrypted: false,
+            winrm_auth: WinRmAuth::Ntlm,
+            winrm_tls: WinRmTls::default(),
+            timeout_seconds: 300,
+            command_timeout_seconds: 30,
+            retry: RetryPolicy::default(),
+            phases: PhaseSelection::default(),
+            redaction: RedactorConfig::default(),
+            sampling: None,
+            resolve_hosts: false,
+            strict: false,
+        }
+    }
+}
+
+/// Resource sampling window, for right-sizing containers.
+#[derive(Debug, Clone)]
+pub struct SamplingConfig {
+    /// How long to sample for.
+    pub duration: Duration,
+    /// Delay between two samples.
+    pub interval: Duration,
+}
+
+impl SamplingConfig {
+    /// Number of samples taken, including one at the start of the window.
+    pub fn sample_count(&self) -> usize {
+        let interval = self.interval.as_millis().max(1);
+        (self.duration.as_millis() / interval) as usize + 1
+    }
+}
+
+/// Retry policy for transient executor (connection/transport) failures.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts per command; 1 disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each failed attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay after the given (1-based) failed attempt.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
 }
 
 /// The main collector.
 pub struct Collector {
     config: CollectorConfig,
     redactor: Redactor,
+    /// State of the current collection run.
+    run: Mutex<RunState>,
+    progress: Box<dyn ProgressReporter>,
+    /// Enabled phases, in execution order.
+    phases: Vec<Box<dyn CollectionPhase>>,
+    /// Executor used instead of connecting to the target.
+    executor: Option<Arc<dyn Executor>>,
 }
 
+/// Builds a [`Collector`] for embedding collection in other tools.
+///
+/// Starts from [`CollectorConfig::default`]. A custom [`Executor`] replaces
+/// the SSH, WinRM or local transport chosen from the mode and OS type.
+pub struct CollectorBuilder {
+    config: CollectorConfig,
+    executor: Option<Arc<dyn Executor>>,
+    progress: Box<dyn ProgressReporter>,
+}
+
+impl CollectorBuilder {
+    /// Start from a configuration.
+    pub fn from_config(config: CollectorConfig) -> Self {
+        Self {
+            config,
+            executor: None,
+            progress: Box::new(NoProgress),
+        }
+    }
+
+    /// Host to collect from.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.config.target = target.into();
+        self
+    }
+
+    pub fn os_type(mut self, os_type: OsType) -> Self {
+        self.config.os_type = os_type;
+        self
+    }
+
+    pub fn mode(mut self, mode: CollectionMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// SSH port and user.
+    pub fn ssh(mut self, port: u16, user: Option<String>) -> Self {
+        self.config.ssh_port = port;
+        self.config.ssh_user = user;
+        self
+    }
+
+    /// Authenticate over SSH with a private key.
+    pub fn ssh_key(mut self, key: impl Into<PathBuf>) -> Self {
+        self.config.ssh_key = Some(key.into());
+        self
+    }
+
+    /// Authenticate over SSH with a password.
+    pub fn ssh_password(mut self, password: impl Into<String>) -> Self {
+        self.config.ssh_password = Some(password.into());
+        self
+    }
+
+    pub fn host_key_check(mut self, check: HostKeyCheck) -> Self {
+        self.config.ssh_host_key_check = check;
+        self
+    }
+
+    /// Connect with the system `ssh` binary instead of libssh2.
+    pub fn ssh_transport(mut self, transport: SshTransport) -> Self {
+        self.config.ssh_transport = transport;
+        self
+    }
+
+    /// WinRM port, transport and authentication scheme.
+    pub fn winrm(mut self, port: u16, https: bool, auth: WinRmAuth) -> Self {
+        self.config.winrm_port = port;
+        self.config.winrm_https = https;
+        self.config.winrm_auth = auth;
+        self
+    }
+
+    /// Allow WinRM over plain HTTP, for hosts accepting unencrypted messages.
+    pub fn winrm_allow_unencrypted(mut self, allow: bool) -> Self {
+        self.config.winrm_allow_unencrypted = allow;
+        self
+    }
+
+    /// Certificate verification of WinRM over HTTPS.
+    pub fn winrm_tls(mut self, tls: WinRmTls) -> Self {
+        self.config.winrm_tls = tls;
+        self
+    }
+
+    /// WinRM credentials.
+    pub fn winrm_credentials(
+        mut self,
+        user: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.config.winrm_user = Some(user.into());
+        self.config.winrm_password = Some(password.into());
+        self
+    }
+
+    /// Overall collection deadline, in whole seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout_seconds = timeout.as_secs();
+        self
+    }
+
+    /// Time limit for a single command, in whole seconds.
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.config.command_timeout_seconds = timeout.as_secs();
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Run only the named phases.
+    pub fn only_phases<I, S>(mut self, phases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.phases.only = phases.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Never run the named phases.
+    pub fn skip_phases<I, S>(mut self, phases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.phases.skip = phases.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Redaction rules applied to evidence.
+    pub fn redaction(mut self, redaction: RedactorConfig) -> Self {
+        self.config.redaction = redaction;
+        self
+    }
+
+    /// Sample CPU and memory of processes every `interval` for `duration`.
+    pub fn sampling(mut self, duration: Duration, interval: Duration) -> Self {
+        self.config.sampling = Some(SamplingConfig { duration, interval });
+        self
+    }
+
+    /// Look up the host names of configuration files on the target.
+    pub fn resolve_hosts(mut self, resolve_hosts: bool) -> Self {
+        self.config.resolve_hosts = resolve_hosts;
+        self
+    }
+
+    /// Abort on the first failed phase.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// Run commands through `executor` instead of connecting to the target.
+    pub fn executor(mut self, executor: impl Executor + 'static) -> Self {
+        self.executor = Some(Arc::new(executor));
+        self
+    }
+
+    /// Report progress events to `progress`.
+    pub fn progress(mut self, progress: impl ProgressReporter + 'static) -> Self {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    /// Build the collector. Fails on unknown phase names.
+    pub fn build(self) -> Result<Collector> {
+        let mut collector = Collector::new(self.config)?;
+        collector.executor = self.executor;
+        collector.progress = self.progress;
+        Ok(collector)
+    }
+}
+
+impl Default for CollectorBuilder {
+    fn default() -> Self {
+        Self::from_config(CollectorConfig::default())
+    }
+}
+
+/// Config locations read on every target, in addition to discovered ones.
+pub(crate) fn standard_config_paths(os_type: OsType) -> &'static [&'static str] {
+    match os_type {
+        OsType::Linux => &["/etc/default", "/etc/sysconfig"],
+        OsType::Windows => &["C:\\ProgramData"],
+        OsType::MacOs => &["/usr/local/etc", "/opt/homebrew/etc"],
+        OsType::Unix => &["/etc/default", "/etc/sysconfig", "/usr/local/etc"],
+    }
+}
+
+/// Per-run state shared by the collection phases.
+#[derive(Debug, Default)]
+struct RunState {
+    deadline: Option<Instant>,
+    deadline_exceeded: bool,
+    errors: Vec<CollectionError>,
+}
+
+/// Error returned for commands skipped after the collection deadline.
+#[derive(Debug, thiserror::Error)]
+#[error("Collection deadline exceeded")]
+struct DeadlineExceeded;
+
 impl Collector {
     /// Create a new collector.
-    pub fn new(config: CollectorConfig) -> Result<Self> {
+    pub fn new(mut config: CollectorConfig) -> Result<Self> {
+        let mode = config.effective_mode();
+        if mode != config.mode {
+            info!("{} is this machine: collecting locally", config.target);
+            config.mode = mode;
+        }
+        let mut phases = default_phases();
+        config.phases.validate(&phases)?;
+        phases.retain(|p| config.phases.is_enabled(p.name()));
+
         Ok(Self {
+            redactor: Redactor::with_config(config.redaction.clone()),
             config,
-            redactor: Redactor::new(),
+            run: Mutex::new(RunState::default()),
+            progress: Box::new(NoProgress),
+            phases,
+            executor: None,
         })
     }
 
+    /// Build a collector step by step.
+    pub fn builder() -> CollectorBuilder {
+        CollectorBuilder::default()
+    }
+
+    /// Report progress events to `progress`.
+    pub fn with_progress(mut self, progress: Box<dyn ProgressReporter>) -> Self {
+        self.progress = progress;
+        self
+    }
+
     /// Run the collection.
     pub async fn collect(&self) -> Result<Bundle> {
         info!(
@@ -78,6 +456,7 @@ impl Collector {
             collection_mode: format!("{:?}", self.config.mode).to_lowercase(),
             ..Default::default()
         };
+        manifest.system.os_type = self.config.os_type.to_string();
 
         let mut audit_log = AuditLog::new();
         let mut evidence: HashMap<String, Evidence> = HashMap::new();
@@ -85,93 +464,21 @@ impl Collector {
 
         // Create executor
         let executor = self.create_executor().await?;
+        if let Some(host_key) = executor.host_key() {
+            self.record_host_key(host_key, &mut audit_log, &mut evidence);
+        }
 
         // Get command set based on OS
-        let commands: Box<dyn CommandSet> = match self.config.os_type {
-            OsType::Linux => Box::new(LinuxCommands::new()),
-            OsType::Windows => Box::new(WindowsCommands::new()),
-        };
+        let commands = command_set(self.config.os_type);
 
-        // Collect system info
-        info!("Collecting system information...");
-        self.collect_system_info(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
-
-        // Collect processes
-        info!("Collecting process information...");
-        self.collect_processes(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
-
-        // Collect services
-        info!("Collecting service information...");
-        self.collect_services(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
-
-        // Collect ports
-        info!("Collecting port information...");
-        self.collect_ports(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
-
-        // Collect packages
-        info!("Collecting package information...");
-        self.collect_packages(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
-
-        // Collect scheduled tasks
-        info!("Collecting scheduled tasks...");
-        self.collect_scheduled_tasks(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
-
-        // Collect config files based on discovered services
-        info!("Collecting configuration files...");
-        self.collect_config_files(
-            &*executor,
-            commands.as_ref(),
-            &mut manifest,
-            &mut audit_log,
-            &mut evidence,
-        )
-        .await?;
+        // Commands past this deadline are skipped; the sampling window
+        // comes on top of the collection timeout
+        let sampling = self.config.sampling.as_ref().map(|s| s.duration);
+        self.start_run(
+            Duration::from_secs(self.config.timeout_seconds) + sampling.unwrap_or_default(),
+        );
 
-        // Collect log snippets
-        info!("Collecting log snippets...");
-        self.collect_logs(
+        self.run_phases(
             &*executor,
             commands.as_ref(),
             &mut manifest,
@@ -180,6 +487,18 @@ impl Collector {
         )
         .await?;
 
+        manifest.errors = self.take_errors();
+        self.progress.report(ProgressEvent::Completed {
+            duration_ms: (Utc::now() - manifest.collected_at)
+                .num_milliseconds()
+                .max(0) as u64,
+            commands: audit_log
+                .entries()
+                .iter()
+                .filter(|e| e.status == CommandStatus::Executed)
+                .count(),
+            errors: manifest.errors.len(),
+        });
         manifest.completed_at = Some(Utc::now());
 
         // Compute checksums for all evidence
@@ -195,36 +514,113 @@ impl Collector {
         })
     }
 
-    async fn create_executor(&self) -> Result<Box<dyn Executor>> {
+    /// List the commands a collection would run, without running them.
+    ///
+    /// Unless `offline`, this connects to the target and runs only the
+    /// service list command, so per-service commands can be expanded.
+    pub async fn dry_run(&self, offline: bool) -> Result<Vec<PlannedCommand>> {
+        let commands = command_set(self.config.os_type);
+
+        // Only the services and logs phases expand per-service commands
+        let per_service = ["services", "logs"]
+            .iter()
+            .any(|p| self.config.phases.is_enabled(p));
+        let queries_services = matches!(self.config.os_type, OsType::Linux | OsType::MacOs);
+        let services = if offline || !per_service || !queries_services {
+            None
+        } else {
+            let executor = self.create_executor().await?;
+            let (_, stdout, _) = executor.execute(commands.service_list_cmd()).await?;
+            Some(parsers::parse_service_list(&stdout, self.config.os_type)?)
+        };
+
+        let mut plan = command_plan(commands.as_ref(), self.config.os_type, services.as_deref());
+        plan.retain(|c| {
+            self.config.phases.is_enabled(&c.phase)
+                && (c.phase != "resources" || self.config.sampling.is_some())
+                && (self.config.resolve_hosts || !c.command.contains(HOST_PLACEHOLDER))
+        });
+        Ok(plan)
+    }
+
+    /// The target's service list, to notice services starting, stopping,
+    /// appearing or disappearing between two collections. The command is
+    /// not recorded in any bundle.
+    pub async fn service_state(&self) -> Result<String> {
+        let executor = self.create_executor().await?;
+        let commands = command_set(self.config.os_type);
+        let (_, stdout, _) = executor.execute(commands.service_list_cmd()).await?;
+        Ok(stdout)
+    }
+
+    async fn create_executor(&self) -> Result<Arc<dyn Executor>> {
+        if let Some(ref executor) = self.executor {
+            return Ok(executor.clone());
+        }
+        let command_timeout = Duration::from_secs(self.config.command_timeout_seconds);
         match self.config.mode {
-            CollectionMode::LocalEphemeral => Ok(Box::new(LocalExecutor::new())),
+            CollectionMode::LocalEphemeral => Ok(Arc::new(
+                LocalExecutor::new().with_command_timeout(command_timeout),
+            )),
             CollectionMode::Remote => match self.config.os_type {
-                OsType::Linux => {
+                OsType::Linux | OsType::MacOs | OsType::Unix
+                    if self.config.ssh_transport == SshTransport::OpenSsh =>
+                {
+                    let executor = OpenSshExecutor::connect(
+                        &self.config.target,
+                        self.config.ssh_port,
+                        self.config.ssh_user.as_deref(),
+                        self.config.ssh_key.as_deref(),
+                        self.config.ssh_password.as_deref(),
+                        &self.config.ssh_host_key_check,
+                    )
+                    .await?
+                    .with_command_timeout(command_timeout);
+                    Ok(Arc::new(executor))
+                }
+                OsType::Linux | OsType::MacOs | OsType::Unix => {
                     let executor = SshExecutor::connect(
                         &self.config.target,
                         self.config.ssh_port,
                         self.config.ssh_user.as_deref(),
                         self.config.ssh_key.as_deref(),
                         self.config.ssh_password.as_deref(),
-                    )?;
-                    Ok(Box::new(executor))
+                        &self.config.ssh_host_key_check,
+                    )?
+                    .with_command_timeout(command_timeout);
+                    Ok(Arc::new(executor))
                 }
                 OsType::Windows => {
+                    // Stock hosts reject unencrypted messages over HTTP
+                    if !self.config.winrm_https && !self.config.winrm_allow_unencrypted {
+                        anyhow::bail!(
+                            "WinRM over HTTP sends unencrypted messages, which Windows rejects \
+                             unless AllowUnencrypted is set: use --winrm-https (port 5986), or \
+                             --winrm-allow-unencrypted for hosts that allow it"
+                        );
+                    }
                     let executor = WinRmExecutor::connect(
                         &self.config.target,
                         self.config.winrm_port,
                         self.config.winrm_https,
+                        self.config.winrm_auth,
+                        &self.config.winrm_tls,
                         self.config.winrm_user.as_deref(),
                         self.config.winrm_password.as_deref(),
                     )
-                    .await?;
-                    Ok(Box::new(executor))
+                    .await?
+                    .with_command_timeout(command_timeout);
+                    Ok(Arc::new(executor))
                 }
             },
         }
     }
 
-    async fn collect_system_info(
+    /// Run every enabled collection phase in order. A phase interrupted by a
+    /// command timeout or the collection deadline does not stop the
+    /// following ones, nor does a failed phase unless the collection is
+    /// strict. Fails when every phase failed.
+    async fn run_phases(
         &self,
         executor: &dyn Executor,
         commands: &dyn CommandSet,
@@ -232,331 +628,296 @@ impl Collector {
         audit_log: &mut AuditLog,
         evidence: &mut HashMap<String, Evidence>,
     ) -> Result<()> {
-        let cmd = commands.hostname_cmd();
-        let result = self
-            .execute_and_record(executor, cmd, "system", audit_log, evidence)
-            .await?;
-        manifest.system.hostname = result.stdout.trim().to_string();
-        manifest.system.os_type = self.config.os_type.to_string();
-
-        if let Some(cmd) = commands.os_version_cmd() {
-            if let Ok(result) = self
-                .execute_and_record(executor, cmd, "system", audit_log, evidence)
-                .await
-            {
-                manifest.system.os_version = Some(result.stdout.trim().to_string());
-            }
-        }
-
-        if let Some(cmd) = commands.kernel_version_cmd() {
-            if let Ok(result) = self
-                .execute_and_record(executor, cmd, "system", audit_log, evidence)
-                .await
-            {
-                manifest.system.kernel_version = Some(result.stdout.trim().to_string());
+        let mut failures = Vec::new();
+        for phase in &self.phases {
+            info!("Collecting {}...", phase.description());
+            let started = self.start_phase(phase.name());
+            let mut ctx = PhaseContext {
+                collector: self,
+                phase: phase.name(),
+                executor,
+                commands,
+                manifest: &mut *manifest,
+                audit_log: &mut *audit_log,
+                evidence: &mut *evidence,
+            };
+            let result = phase.collect(&mut ctx).await;
+            if let Err(e) = self.finish_phase(phase.name(), started, result) {
+                if self.config.strict {
+                    return Err(e);
+                }
+                warn!("Phase {} failed: {:#}", phase.name(), e);
+                self.run.lock().unwrap().errors.push(CollectionError {
+                    phase: phase.name().to_string(),
+                    command: None,
+                    error: format!("{:#}", e),
+                    timestamp: Utc::now(),
+                    recoverable: false,
+                    denied: false,
+                });
+                failures.push(e);
             }
         }
 
-        if let Some(cmd) = commands.architecture_cmd() {
-            if let Ok(result) = self
-                .execute_and_record(executor, cmd, "system", audit_log, evidence)
-                .await
-            {
-                manifest.system.architecture = Some(result.stdout.trim().to_string());
-            }
+        if !failures.is_empty() && failures.len() == self.phases.len() {
+            return Err(failures
+                .swap_remove(0)
+                .context("Nothing could be collected: every phase failed"));
         }
-
         Ok(())
     }
 
-    async fn collect_processes(
-        &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
-        audit_log: &mut AuditLog,
-        evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        for cmd in commands.process_cmds() {
-            let result = self
-                .execute_and_record(executor, cmd, "process", audit_log, evidence)
-                .await;
-            if let Ok(result) = result {
-                let processes = parsers::parse_processes(&result.stdout, self.config.os_type)?;
-                for mut proc in processes {
-                    proc.evidence_ref = Some(result.evidence_ref.clone());
-                    manifest.processes.push(proc);
-                }
-            }
-        }
-        Ok(())
+    /// Reset per-run state and arm the collection deadline.
+    fn start_run(&self, budget: Duration) {
+        let mut run = self.run.lock().unwrap();
+        *run = RunState {
+            deadline: Some(Instant::now() + budget),
+            ..Default::default()
+        };
     }
 
-    async fn collect_services(
-        &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
-        audit_log: &mut AuditLog,
-        evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        let list_cmd = commands.service_list_cmd();
-        let result = self
-            .execute_and_record(executor, list_cmd, "service", audit_log, evidence)
-            .await?;
-
-        if self.config.os_type.is_windows() {
-            // Windows: parse full details directly from the list output (single query)
-            let mut services = parsers::parse_windows_services_from_list(&result.stdout)?;
-            for service in &mut services {
-                service.evidence_ref = Some(result.evidence_ref.clone());
-            }
-            manifest.services.extend(services);
-        } else {
-            // Linux: list names then query each service for details + unit files
-            let service_names = parsers::parse_service_list(&result.stdout, self.config.os_type)?;
-
-            for name in service_names {
-                if let Some(show_cmd) = commands.service_show_cmd(&name) {
-                    if let Ok(show_result) = self
-                        .execute_and_record(executor, &show_cmd, "service", audit_log, evidence)
-                        .await
-                    {
-                        if let Ok(mut service) =
-                            parsers::parse_service_details(&show_result.stdout, self.config.os_type)
-                        {
-                            service.evidence_ref = Some(show_result.evidence_ref.clone());
-
-                            if let Some(cat_cmd) = commands.service_cat_cmd(&name) {
-                                if let Ok(cat_result) = self
-                                    .execute_and_record(
-                                        executor, &cat_cmd, "service", audit_log, evidence,
-                                    )
-                                    .await
-                                {
-                                    let unit_info = parsers::parse_systemd_unit(&cat_result.stdout);
-                                    if let Some(exec) = unit_info.exec_start {
-                                        service.exec_start = Some(exec);
-                                    }
-                                    if let Some(wd) = unit_info.working_directory {
-                                        service.working_directory = Some(wd);
-                                    }
-                                    service
-                                        .environment_files
-                                        .extend(unit_info.environment_files);
-                                }
-                            }
-
-                            manifest.services.push(service);
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(())
+    /// Recoverable errors recorded during the run.
+    fn take_errors(&self) -> Vec<CollectionError> {
+        std::mem::take(&mut self.run.lock().unwrap().errors)
     }
 
-    async fn collect_ports(
-        &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
-        audit_log: &mut AuditLog,
-        evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        let cmd = commands.ports_cmd();
-        let result = self
-            .execute_and_record(executor, cmd, "ports", audit_log, evidence)
-            .await?;
-        let ports = parsers::parse_ports(&result.stdout, self.config.os_type)?;
-
-        for mut port in ports {
-            port.evidence_ref = Some(result.evidence_ref.clone());
-            manifest.ports.push(port);
-        }
+    fn record_error(&self, phase: &str, command: Option<&str>, error: String) {
+        self.run.lock().unwrap().errors.push(CollectionError {
+            phase: phase.to_string(),
+            command: command.map(str::to_string),
+            error,
+            timestamp: Utc::now(),
+            recoverable: true,
+            denied: false,
+        });
+    }
 
-        Ok(())
+    /// Record a command the allowlist refused in the audit log and the
+    /// manifest errors.
+    pub(crate) fn record_denied(&self, denied: &Denied, category: &str, audit_log: &mut AuditLog) {
+        let command = format!("{} {}", denied.action, denied.subject);
+        debug!("Denied: {}", denied);
+        audit_log.add(AuditEntry::not_run(
+            command.clone(),
+            category.to_string(),
+            CommandStatus::Denied,
+            denied.reason.to_string(),
+            Utc::now(),
+        ));
+        self.run.lock().unwrap().errors.push(CollectionError {
+            phase: category.to_string(),
+            command: Some(command),
+            error: denied.to_string(),
+            timestamp: Utc::now(),
+            recoverable: true,
+            denied: true,
+        });
     }
 
-    async fn collect_packages(
-        &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
-        audit_log: &mut AuditLog,
-        evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        for cmd in commands.package_cmds() {
-            if let Ok(result) = self
-                .execute_and_record(executor, cmd, "packages", audit_log, evidence)
-                .await
-            {
-                let packages = parsers::parse_packages(&result.stdout, self.config.os_type, cmd)?;
-                manifest.packages.extend(packages);
-                break; // Only use first successful package manager
+    /// Time left for the next command, or an error once the deadline passed.
+    /// The first command refused is recorded in the manifest errors.
+    fn command_budget(&self, phase: &str, command: &str) -> Result<Duration> {
+        let command_timeout = Duration::from_secs(self.config.command_timeout_seconds);
+        let mut run = self.run.lock().unwrap();
+        let Some(deadline) = run.deadline else {
+            return Ok(command_timeout);
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            if !run.deadline_exceeded {
+                run.deadline_exceeded = true;
+                drop(run);
+                warn!(
+                    "Collection deadline of {}s exceeded, skipping remaining commands",
+                    self.config.timeout_seconds
+                );
+                self.record_error(
+                    phase,
+                    Some(command),
+                    format!(
+                        "Collection deadline of {}s exceeded; remaining commands skipped",
+                        self.config.timeout_seconds
+                    ),
+                );
             }
+            return Err(DeadlineExceeded.into());
         }
-        Ok(())
+
+        Ok(command_timeout.min(remaining))
     }
 
-    async fn collect_scheduled_tasks(
-        &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
-        audit_log: &mut AuditLog,
-        evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        for cmd in commands.scheduled_task_cmds() {
-            if let Ok(result) = self
-                .execute_and_record(executor, cmd, "scheduled_tasks", audit_log, evidence)
-                .await
-            {
-                let tasks = parsers::parse_scheduled_tasks(&result.stdout, self.config.os_type)?;
-                for mut task in tasks {
-                    task.evidence_ref = Some(result.evidence_ref.clone());
-                    manifest.scheduled_tasks.push(task);
-                }
-            }
-        }
-        Ok(())
+    fn start_phase(&self, phase: &str) -> Instant {
+        self.progress.report(ProgressEvent::PhaseStarted {
+            phase: phase.to_string(),
+        });
+        Instant::now()
     }
 
-    async fn collect_config_files(
-        &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
-        audit_log: &mut AuditLog,
-        evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        // Collect config files from known service paths
-        let mut config_paths: Vec<String> = Vec::new();
+    pub(crate) fn os_type(&self) -> OsType {
+        self.config.os_type
+    }
 
-        // Add paths from services
-        for service in &manifest.services {
-            if let Some(ref wd) = service.working_directory {
-                config_paths.push(wd.clone());
-            }
-            for env_file in &service.environment_files {
-                config_paths.push(env_file.clone());
-            }
-        }
+    pub(crate) fn sampling(&self) -> Option<&SamplingConfig> {
+        self.config.sampling.as_ref()
+    }
 
-        // Add paths from process working directories
-        for process in &manifest.processes {
-            if let Some(ref wd) = process.working_directory {
-                config_paths.push(wd.clone());
-            }
-        }
+    pub(crate) fn resolve_hosts(&self) -> bool {
+        self.config.resolve_hosts
+    }
 
-        // Standard config paths
-        let standard_paths = if self.config.os_type.is_linux() {
-            vec!["/etc/default", "/etc/sysconfig"]
-        } else {
-            vec!["C:\\ProgramData"]
-        };
+    pub(crate) fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
 
-        for path in standard_paths {
-            config_paths.push(path.to_string());
-        }
+    /// Take the originals of the values redacted during collection, by hash
+    /// placeholder. Empty unless the redaction config records originals.
+    pub fn take_redacted_originals(&self) -> std::collections::BTreeMap<String, String> {
+        self.redactor.take_originals()
+    }
 
-        // Deduplicate and collect
-        config_paths.sort();
-        config_paths.dedup();
+    /// Report a step of a phase with a known number of steps.
+    pub(crate) fn step(&self, phase: &str, current: usize, total: usize) {
+        self.progress.report(ProgressEvent::Step {
+            phase: phase.to_string(),
+            current,
+            total,
+        });
+    }
 
-        for path in &config_paths {
-            if let Some(cmd) = commands.read_file_cmd(path) {
-                if let Ok(result) = self
-                    .execute_and_record(executor, &cmd, "config", audit_log, evidence)
-                    .await
-                {
-                    // Redact content before storing
-                    let redacted = self.redactor.redact(&result.stdout);
-                    let file_info = FileInfo {
-                        path: path.clone(),
-                        size_bytes: result.stdout.len() as u64,
-                        modified_at: None,
-                        owner: None,
-                        permissions: None,
-                        content_hash: Some(xcprobe_common::hash::sha256_str(&redacted.content)),
-                        attachment_ref: Some(result.evidence_ref.clone()),
-                        discovery_method: "service_path".to_string(),
-                        discovery_evidence_ref: None,
-                    };
-                    manifest.config_files.push(file_info);
-                }
+    /// Report the end of a phase. Timeout errors (already recorded) are
+    /// swallowed so later phases still run.
+    fn finish_phase(&self, phase: &str, started: Instant, result: Result<()>) -> Result<()> {
+        self.progress.report(ProgressEvent::PhaseFinished {
+            phase: phase.to_string(),
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        match result {
+            Err(e) if e.is::<CommandTimeout>() || e.is::<DeadlineExceeded>() => {
+                debug!("Phase {} interrupted: {}", phase, e);
+                Ok(())
             }
+            other => other,
         }
-
-        Ok(())
     }
 
-    async fn collect_logs(
+    /// Record the verified SSH host key as evidence and in the audit log, so
+    /// the bundle proves which machine it was collected from.
+    fn record_host_key(
         &self,
-        executor: &dyn Executor,
-        commands: &dyn CommandSet,
-        manifest: &mut Manifest,
+        host_key: &HostKeyInfo,
         audit_log: &mut AuditLog,
         evidence: &mut HashMap<String, Evidence>,
-    ) -> Result<()> {
-        // Collect journal logs for each service (Linux)
-        if self.config.os_type.is_linux() {
-            for service in &manifest.services {
-                if let Some(cmd) = commands.journal_cmd(&service.name, "1 hour ago") {
-                    if let Ok(result) = self
-                        .execute_and_record(executor, &cmd, "logs", audit_log, evidence)
-                        .await
-                    {
-                        let redacted = self.redactor.redact(&result.stdout);
-                        let file_info = FileInfo {
-                            path: format!("journal:{}", service.name),
-                            size_bytes: result.stdout.len() as u64,
-                            modified_at: None,
-                            owner: None,
-                            permissions: None,
-                            content_hash: Some(xcprobe_common::hash::sha256_str(&redacted.content)),
-                            attachment_ref: Some(result.evidence_ref.clone()),
-                            discovery_method: "journalctl".to_string(),
-                            discovery_evidence_ref: None,
-                        };
-                        manifest.log_files.push(file_info);
-                    }
-                }
-            }
-        }
+    ) {
+        let now = Utc::now();
+        let command = format!("ssh host key {}", host_key.host);
+        let evidence_ref = evidence_path("host_key", Subject::None, &command, evidence);
+        let evidence_id = evidence_id(&evidence_ref);
+        let content = format!(
+            "host: {}\nkey_type: {}\nfingerprint: {}\nverification: {}\n",
+            host_key.host, host_key.key_type, host_key.fingerprint, host_key.status
+        );
 
-        Ok(())
+        let mut ev = Evidence::from_command_output(
+            &evidence_id,
+            &command,
+            content.clone().into_bytes(),
+            &evidence_ref,
+        );
+        ev.audit_seq = Some(audit_log.add(AuditEntry::new(
+            0,
+            command,
+            "host_key".to_string(),
+            now,
+            now,
+            Some(0),
+            content.len() as u64,
+            0,
+            evidence_ref.clone(),
+            None,
+        )));
+        evidence.insert(evidence_ref, ev);
     }
 
-    async fn execute_and_record(
+    pub(crate) async fn execute_and_record(
         &self,
         executor: &dyn Executor,
         command: &str,
         category: &str,
+        subject: Subject<'_>,
         audit_log: &mut AuditLog,
         evidence: &mut HashMap<String, Evidence>,
     ) -> Result<ExecutionResult> {
         let started_at = Utc::now();
-        debug!("Executing: {}", command);
+        let mut retry_errors = Vec::new();
+
+        let outcome = loop {
+            let limit = match self.command_budget(category, command) {
+                Ok(limit) => limit,
+                Err(e) => {
+                    audit_log.add(AuditEntry::not_run(
+                        command.to_string(),
+                        category.to_string(),
+                        CommandStatus::Skipped,
+                        format!("{:#}", e),
+                        Utc::now(),
+                    ));
+                    return Err(e);
+                }
+            };
+            debug!("Executing: {}", command);
 
-        let (exit_code, stdout, stderr) = executor.execute(command).await?;
+            let result = match timeout(limit, executor.execute(command)).await {
+                Ok(result) => result,
+                Err(_) => Err(CommandTimeout(limit).into()),
+            };
+            let attempt = retry_errors.len() as u32 + 1;
+            match result {
+                // Only transport failures are retried: timeouts and non-zero
+                // exit codes are results, not blips
+                Err(e) if !e.is::<CommandTimeout>() && attempt < self.config.retry.max_attempts => {
+                    let delay = self.config.retry.backoff(attempt);
+                    warn!(
+                        "Attempt {}/{} failed for {}: {:#}; retrying in {:?}",
+                        attempt, self.config.retry.max_attempts, command, e, delay
+                    );
+                    retry_errors.push(format!("attempt {}: {:#}", attempt, e));
+                    tokio::time::sleep(delay).await;
+                }
+                other => break other,
+            }
+        };
+
+        let (output, failure) = match outcome {
+            Ok(output) => (output, None),
+            Err(e) => {
+                warn!("{:#}: {}", e, command);
+                self.record_error(category, Some(command), format!("{:#}", e));
+                ((None, String::new(), format!("{:#}", e)), Some(e))
+            }
+        };
+        let (exit_code, stdout, stderr) = output;
 
         let completed_at = Utc::now();
-        let evidence_id = format!("{}_{}", category, uuid::Uuid::new_v4());
-        let evidence_ref = format!("evidence/{}.txt", evidence_id);
+        let evidence_ref = evidence_path(category, subject, command, evidence);
+        let evidence_id = evidence_id(&evidence_ref);
 
-        // Create evidence
-        let content = format!("=== STDOUT ===\n{}\n\n=== STDERR ===\n{}", stdout, stderr);
-        let redacted = self.redactor.redact(&content);
-        let ev = Evidence::from_command_output(
+        // Create evidence; file contents are redacted according to their format
+        let stdout_redacted = match subject {
+            Subject::File(path) => self.redactor.redact_file(&stdout, path).content,
+            _ => self.redactor.redact(&stdout).content,
+        };
+        let content = format!(
+            "=== STDOUT ===\n{}\n\n=== STDERR ===\n{}",
+            stdout_redacted,
+            self.redactor.redact(&stderr).content
+        );
+        let mut ev = Evidence::from_command_output(
             &evidence_id,
             command,
-            redacted.content.into_bytes(),
+            content.into_bytes(),
             &evidence_ref,
         );
-        evidence.insert(evidence_ref.clone(), ev);
 
         // Create audit entry
         let audit_entry = AuditEntry::new(
@@ -574,8 +935,14 @@ impl Collector {
             } else {
                 None
             },
-        );
-        audit_log.add(audit_entry);
+        )
+        .with_retries(retry_errors);
+        ev.audit_seq = Some(audit_log.add(audit_entry));
+        evidence.insert(evidence_ref.clone(), ev);
+
+        if let Some(e) = failure {
+            return Err(e);
+        }
 
         Ok(ExecutionResult {
             exit_code,
@@ -586,11 +953,559 @@ impl Collector {
     }
 }
 
-struct ExecutionResult {
-    #[allow(dead_code)]
-    exit_code: Option<i32>,
-    stdout: String,
-    #[allow(dead_code)]
-    stderr: String,
-    evidence_ref: String,
+/// Output of a recorded command.
+pub struct ExecutionResult {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Bundle path of the command's evidence file.
+    pub evidence_ref: String,
+}
+
+/// What a command's output is about, which names its evidence file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Subject<'a> {
+    /// The host as a whole.
+    None,
+    /// A service, whose evidence files share a directory.
+    Service(&'a str),
+    /// A file or registry key, whose content the command prints.
+    File(&'a str),
+}
+
+/// Longest file name derived from a command, before the extension.
+const MAX_EVIDENCE_NAME: usize = 48;
+
+/// Bundle path of the output of `command`, so the same command on the same
+/// host always lands in the same file: `evidence/<category>/<command>.txt`,
+/// under a directory named after a service, or mirroring the path of a
+/// file. A path already in `taken` gets a `_2`, `_3`... suffix.
+pub(crate) fn evidence_path(
+    category: &str,
+    subject: Subject<'_>,
+    command: &str,
+    taken: &HashMap<String, Evidence>,
+) -> String {
+    let stem = match subject {
+        Subject::None => format!("{}/{}", category, command_name(command, None)),
+        Subject::Service(name) => format!(
+            "{}/{}/{}",
+            category,
+            path_component(name),
+            command_name(command, Some(name))
+        ),
+        Subject::File(path) => {
+            let components: Vec<String> = path
+                .split(['/', '\\'])
+                .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+                .map(path_component)
+                .collect();
+            format!("{}/{}", category, components.join("/"))
+        }
+    };
+    let mut path = format!("evidence/{}.txt", stem);
+    let mut n = 2;
+    while taken.contains_key(&path) {
+        path = format!("evidence/{}_{}.txt", stem, n);
+        n += 1;
+    }
+    path
+}
+
+/// Evidence ID of a bundle path: the path under `evidence/`, without the
+/// extension.
+fn evidence_id(evidence_ref: &str) -> String {
+    evidence_ref
+        .strip_prefix("evidence/")
+        .and_then(|p| p.strip_suffix(".txt"))
+        .unwrap_or(evidence_ref)
+        .to_string()
+}
+
+/// File name describing a command: the words of its first pipeline stage,
+/// without environment assignments, options dashes, redirections and the
+/// `subject` it is about.
+fn command_name(command: &str, subject: Option<&str>) -> String {
+    let mut stage = command.split('|').next().unwrap_or_default().trim_start();
+    while let Some((word, rest)) = stage.split_once(' ') {
+        if !word.contains('=') || word.starts_with('-') {
+            break;
+        }
+        stage = rest.trim_start();
+    }
+    let mut stage = stage.to_string();
+    for noise in ["2>/dev/null", "2>&1"] {
+        stage = stage.replace(noise, " ");
+    }
+    if let Some(subject) = subject.filter(|s| !s.is_empty()) {
+        stage = stage.replace(subject, " ");
+    }
+    let mut name = String::new();
+    for word in stage
+        .split(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '@')))
+        .map(|w| w.trim_start_matches('-').trim_end_matches('.'))
+        .filter(|w| !w.is_empty())
+    {
+        if name.len() + word.len() + 1 > MAX_EVIDENCE_NAME && !name.is_empty() {
+            break;
+        }
+        if !name.is_empty() {
+            name.push('_');
+        }
+        name.push_str(word);
+    }
+    name.truncate(MAX_EVIDENCE_NAME);
+    if name.is_empty() {
+        name.push_str("output");
+    }
+    name
+}
+
+/// A file name made of the safe characters of `name`.
+fn path_component(name: &str) -> String {
+    let component: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '@') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match component.trim_start_matches('.') {
+        "" => "_".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_config(timeout_seconds: u64) -> CollectorConfig {
+        CollectorConfig {
+            timeout_seconds,
+            retry: RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Fails with a transport error a fixed number of times, then succeeds.
+    struct FlakyExecutor {
+        failures: std::sync::atomic::AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for FlakyExecutor {
+        async fn execute(&self, _command: &str) -> Result<(Option<i32>, String, String)> {
+            use std::sync::atomic::Ordering;
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                anyhow::bail!("Failed to open SSH channel");
+            }
+            Ok((Some(1), "out".to_string(), "not found".to_string()))
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried_and_audited() {
+        let collector = Collector::new(local_config(300)).unwrap();
+        let mut audit_log = AuditLog::new();
+        let mut evidence = HashMap::new();
+
+        // Two blips then a non-zero exit, which is not retried
+        let executor = FlakyExecutor { failures: 2.into() };
+        let result = collector
+            .execute_and_record(
+                &executor,
+                "id",
+                "system",
+                Subject::None,
+                &mut audit_log,
+                &mut evidence,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, Some(1));
+        let entry = &audit_log.entries()[0];
+        assert_eq!(entry.retries, 2);
+        assert!(entry.retry_errors[0].contains("Failed to open SSH channel"));
+
+        // Failures beyond the attempt limit surface as recoverable errors
+        let executor = FlakyExecutor { failures: 5.into() };
+        assert!(collector
+            .execute_and_record(
+                &executor,
+                "id",
+                "system",
+                Subject::None,
+                &mut audit_log,
+                &mut evidence
+            )
+            .await
+            .is_err());
+        assert_eq!(audit_log.entries()[1].retries, 2);
+        assert_eq!(collector.take_errors().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+    }
+
+    /// Records every progress event.
+    #[derive(Clone, Default)]
+    struct RecordingProgress(std::sync::Arc<Mutex<Vec<ProgressEvent>>>);
+
+    impl ProgressReporter for RecordingProgress {
+        fn report(&self, event: ProgressEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deadline_recorded_as_recoverable_error() {
+        let progress = RecordingProgress::default();
+        let collector = Collector::new(local_config(0))
+            .unwrap()
+            .with_progress(Box::new(progress.clone()));
+        let bundle = collector.collect().await.unwrap();
+
+        // Every phase is still reported, followed by the summary
+        let events = progress.0.lock().unwrap();
+        let started = events
+            .iter()
+            .filter(|e| matches!(e, ProgressEvent::PhaseStarted { .. }))
+            .count();
+        assert_eq!(started, 16);
+        assert!(matches!(
+            events.last(),
+            Some(ProgressEvent::Completed { errors: 1, .. })
+        ));
+
+        // Nothing runs past the deadline, and it is reported exactly once
+        assert!(!bundle.audit.is_empty());
+        assert!(bundle
+            .audit
+            .iter()
+            .all(|e| e.status == CommandStatus::Skipped && e.exit_code.is_none()));
+        assert_eq!(bundle.manifest.errors.len(), 1);
+        let error = &bundle.manifest.errors[0];
+        assert_eq!(error.phase, "system");
+        assert!(error.recoverable);
+        assert!(error.error.contains("deadline"));
+    }
+
+    #[test]
+    fn test_evidence_paths() {
+        let mut taken = HashMap::new();
+        let path = |category, subject, command, taken: &HashMap<String, Evidence>| {
+            evidence_path(category, subject, command, taken)
+        };
+
+        assert_eq!(
+            path("ports", Subject::None, "ss -lntup", &taken),
+            "evidence/ports/ss_lntup.txt"
+        );
+        assert_eq!(
+            path(
+                "service",
+                Subject::Service("nginx.service"),
+                "systemctl show nginx.service --no-pager",
+                &taken
+            ),
+            "evidence/service/nginx.service/systemctl_show_no-pager.txt"
+        );
+        assert_eq!(
+            path(
+                "config",
+                Subject::File("/etc/nginx/nginx.conf"),
+                "cat '/etc/nginx/nginx.conf' 2>/dev/null | head -c 1048576",
+                &taken
+            ),
+            "evidence/config/etc/nginx/nginx.conf.txt"
+        );
+        assert_eq!(
+            path(
+                "registry",
+                Subject::File("SOFTWARE\\Contoso Ltd\\Billing"),
+                "$k = ...",
+                &taken
+            ),
+            "evidence/registry/SOFTWARE/Contoso_Ltd/Billing.txt"
+        );
+
+        // The same command again gets a numbered file
+        let first = path("resources", Subject::None, "ps -o pid,pcpu", &taken);
+        taken.insert(
+            first.clone(),
+            Evidence::from_command_output("", "", Vec::new(), &first),
+        );
+        assert_eq!(
+            path("resources", Subject::None, "ps -o pid,pcpu", &taken),
+            "evidence/resources/ps_o_pid_pcpu_2.txt"
+        );
+        assert_eq!(evidence_id(&first), "resources/ps_o_pid_pcpu");
+
+        // Environment assignments are left out
+        assert_eq!(
+            path(
+                "process",
+                Subject::None,
+                "LC_ALL=C TZ=UTC ps -ww -eo pid=",
+                &taken
+            ),
+            "evidence/process/ps_ww_eo_pid.txt"
+        );
+    }
+
+    #[test]
+    fn test_denied_command_is_audited() {
+        let collector = Collector::new(local_config(300)).unwrap();
+        let mut audit_log = AuditLog::new();
+        let denied = crate::commands::LinuxCommands::new()
+            .read_file_cmd("/tmp/app.conf")
+            .unwrap_err();
+
+        collector.record_denied(&denied, "config", &mut audit_log);
+
+        let entry = &audit_log.entries()[0];
+        assert_eq!(entry.status, CommandStatus::Denied);
+        assert_eq!(entry.command, "read file /tmp/app.conf");
+        assert_eq!(
+            entry.error.as_deref(),
+            Some("outside the readable directories")
+        );
+        let errors = collector.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].denied && errors[0].recoverable);
+        assert_eq!(errors[0].phase, "config");
+    }
+
+    /// Answers every command with the same output and counts them.
+    #[derive(Default)]
+    struct ScriptedExecutor {
+        commands: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for ScriptedExecutor {
+        async fn execute(&self, _command: &str) -> Result<(Option<i32>, String, String)> {
+            self.commands
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok((Some(0), "web01".to_string(), String::new()))
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+    }
+
+    /// Fails every command containing `failing`, answers the others.
+    struct PartialExecutor {
+        failing: &'static str,
+    }
+
+    #[async_trait::async_trait]
+    impl Executor for PartialExecutor {
+        async fn execute(&self, command: &str) -> Result<(Option<i32>, String, String)> {
+            if command.contains(self.failing) {
+                anyhow::bail!("Failed to open SSH channel");
+            }
+            Ok((Some(0), "web01".to_string(), String::new()))
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_phase_is_recorded_unless_strict() {
+        let builder = |failing, strict| {
+            Collector::builder()
+                .only_phases(["system", "ports"])
+                .retry(RetryPolicy {
+                    max_attempts: 1,
+                    ..Default::default()
+                })
+                .executor(PartialExecutor { failing })
+                .strict(strict)
+                .build()
+                .unwrap()
+        };
+
+        let bundle = builder("ss ", false).collect().await.unwrap();
+        assert_eq!(bundle.manifest.system.hostname, "web01");
+        let phase_error = bundle
+            .manifest
+            .errors
+            .iter()
+            .find(|e| e.command.is_none())
+            .unwrap();
+        assert_eq!(phase_error.phase, "ports");
+        assert!(!phase_error.recoverable);
+
+        assert!(builder("ss ", true).collect().await.is_err());
+        // Nothing gathered at all
+        assert!(builder("", false).collect().await.is_err());
+    }
+
+    /// Answers `ps` and the working directory lookups of a small host.
+    struct CwdExecutor;
+
+    #[async_trait::async_trait]
+    impl Executor for CwdExecutor {
+        async fn execute(&self, command: &str) -> Result<(Option<i32>, String, String)> {
+            let stdout = if command.contains(" ps ") {
+                let row = |pid: u32, user: &str, comm: &str, args: &str| {
+                    format!(
+                        "{:>5}     1  0.1  1.0       01:00 Fri Oct 16 12:00:00 2026 {:<64} {:<16} {}\n",
+                        pid, user, comm, args
+                    )
+                };
+                let ps = row(812, "app", "java", "java -jar app.jar")
+                    + &row(913, "root", "cron", "/usr/sbin/cron")
+                    + &row(1020, "app", "node", "node server.js");
+                return Ok((Some(0), ps, String::new()));
+            } else if command.contains("readlink") {
+                "p812\nn/opt/app\np913\nn/\np1020\nn\n"
+            } else if command.starts_with("lsof ") {
+                assert!(command.contains("-p 1020 "));
+                "p1020\nfcwd\nn/srv/api\n"
+            } else {
+                ""
+            };
+            Ok((Some(0), stdout.to_string(), String::new()))
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_working_directories() {
+        let collector = Collector::builder()
+            .only_phases(["processes", "working_directories"])
+            .executor(CwdExecutor)
+            .build()
+            .unwrap();
+        let bundle = collector.collect().await.unwrap();
+
+        let cwd = |pid: u32| {
+            bundle
+                .manifest
+                .processes
+                .iter()
+                .find(|p| p.pid == pid)
+                .and_then(|p| p.working_directory.as_deref())
+        };
+        assert_eq!(cwd(812), Some("/opt/app"));
+        assert_eq!(cwd(913), None);
+        assert_eq!(cwd(1020), Some("/srv/api"));
+    }
+
+    #[test]
+    fn test_remote_collection_of_localhost_runs_locally() {
+        let config = |target: &str, os_type| CollectorConfig {
+            target: target.to_string(),
+            os_type,
+            mode: CollectionMode::Remote,
+            ..Default::default()
+        };
+        for target in ["localhost", "127.0.0.1", "[::1]"] {
+            assert_eq!(
+                config(target, OsType::host()).effective_mode(),
+                CollectionMode::LocalEphemeral
+            );
+        }
+        assert_eq!(
+            config("web01.example.com", OsType::host()).effective_mode(),
+            CollectionMode::Remote
+        );
+        let other_os = if OsType::host() == OsType::Windows {
+            OsType::Linux
+        } else {
+            OsType::Windows
+        };
+        assert_eq!(
+            config("localhost", other_os).effective_mode(),
+            CollectionMode::Remote
+        );
+    }
+
+    #[tokio::test]
+    async fn test_winrm_over_http_needs_opt_in() {
+        let collector = Collector::new(CollectorConfig {
+            target: "win01.example.com".to_string(),
+            os_type: OsType::Windows,
+            mode: CollectionMode::Remote,
+            ..Default::default()
+        })
+        .unwrap();
+        let err = collector.create_executor().await.err().unwrap();
+        assert!(format!("{:#}", err).contains("--winrm-https"));
+    }
+
+    #[tokio::test]
+    async fn test_builder_with_custom_executor() {
+        // A remote target is never connected to when an executor is supplied
+        let collector = Collector::builder()
+            .target("web01.example.com")
+            .mode(CollectionMode::Remote)
+            .only_phases(["system"])
+            .executor(ScriptedExecutor::default())
+            .build()
+            .unwrap();
+        let bundle = collector.collect().await.unwrap();
+
+        assert!(!bundle.audit.is_empty());
+        assert!(bundle.manifest.errors.is_empty());
+        assert_eq!(bundle.manifest.system.hostname, "web01");
+        assert!(Collector::builder()
+            .only_phases(["nonexistent"])
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_only_selected_phases_run() {
+        let mut config = local_config(300);
+        config.phases = PhaseSelection {
+            only: vec!["system".to_string(), "ports".to_string()],
+            skip: vec!["ports".to_string()],
+        };
+        let progress = RecordingProgress::default();
+        let collector = Collector::new(config)
+            .unwrap()
+            .with_progress(Box::new(progress.clone()));
+        let bundle = collector.collect().await.unwrap();
+
+        let phases: Vec<_> = progress
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::PhaseStarted { phase } => Some(phase.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(phases, vec!["system"]);
+        assert!(bundle.audit.iter().all(|e| e.category == "system"));
+        assert_eq!(bundle.manifest.system.os_type, "linux");
+    }
 }
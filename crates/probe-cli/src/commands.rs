@@ -3,6 +3,45 @@
 //! All commands that can be executed on targets are defined here.
 //! This is the single source of truth for what the collector can run.
 
+use std::fmt;
+use xcprobe_common::OsType;
+
+/// A command built from a value found on the target, such as a service name
+/// or a path: `Ok(None)` when the OS has no such command, `Err` when the
+/// allowlist refuses the value.
+pub type Allowed = Result<Option<String>, Denied>;
+
+/// A command the allowlist refused to build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Denied {
+    /// What the command would have done, e.g. "read file".
+    pub action: &'static str,
+    /// The refused service name, path or key.
+    pub subject: String,
+    /// Why it was refused.
+    pub reason: &'static str,
+}
+
+impl Denied {
+    pub fn new(action: &'static str, subject: &str, reason: &'static str) -> Self {
+        Self {
+            action,
+            subject: subject.to_string(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} denied: {}",
+            self.action, self.subject, self.reason
+        )
+    }
+}
+
 /// Trait for command sets.
 pub trait CommandSet: Send + Sync {
     /// Get hostname command.
@@ -17,34 +56,233 @@ pub trait CommandSet: Send + Sync {
     /// Get architecture command.
     fn architecture_cmd(&self) -> Option<&str>;
 
+    /// Get command telling whether collection runs as root or an
+    /// Administrator.
+    fn privilege_cmd(&self) -> &str;
+
     /// Get process listing commands.
     fn process_cmds(&self) -> Vec<&str>;
 
+    /// Get command sampling cumulative CPU time and resident memory of processes.
+    fn resource_sample_cmd(&self, pids: &[u32]) -> Option<String>;
+
+    /// Get command printing the working directory of processes as `lsof -F`
+    /// records (`p<pid>` then `n<path>` lines).
+    fn process_cwd_cmd(&self, pids: &[u32]) -> Option<String>;
+
+    /// Get fallback command for the processes `process_cwd_cmd` could not
+    /// resolve, in the same format.
+    fn process_cwd_fallback_cmd(&self, pids: &[u32]) -> Option<String>;
+
+    /// Get command printing the untruncated arguments of processes as
+    /// records of a `p<pid>` line then one `a<arg>` line per argument.
+    fn process_cmdline_cmd(&self, pids: &[u32]) -> Option<String>;
+
+    /// Get command listing the files opened by processes as `lsof -F`
+    /// records.
+    fn open_files_cmd(&self, pids: &[u32]) -> Option<String>;
+
+    /// Get fallback command for the processes `open_files_cmd` listed no
+    /// files for, in the same format.
+    fn open_files_fallback_cmd(&self, pids: &[u32]) -> Option<String>;
+
+    /// Get command detecting the init system, if the OS has several.
+    fn init_system_cmd(&self) -> Option<&str>;
+
     /// Get service listing command.
     fn service_list_cmd(&self) -> &str;
 
+    /// Get service listing command for a non-default init system.
+    fn init_service_list_cmd(&self, init: InitSystem) -> Option<&str>;
+
+    /// Get command to read a service's init script.
+    fn init_script_cmd(&self, name: &str) -> Allowed;
+
     /// Get service show command for a specific service.
-    fn service_show_cmd(&self, name: &str) -> Option<String>;
+    fn service_show_cmd(&self, name: &str) -> Allowed;
 
     /// Get service cat command (for unit file content).
-    fn service_cat_cmd(&self, name: &str) -> Option<String>;
+    fn service_cat_cmd(&self, name: &str) -> Allowed;
+
+    /// Get command listing the dependencies between all services, when the
+    /// service details lack them.
+    fn service_dependencies_cmd(&self) -> Option<&str>;
+
+    /// Get command printing a service's failure recovery settings.
+    fn service_recovery_cmd(&self, name: &str) -> Allowed;
+
+    /// Get command exporting a registry key under `HKLM` and its subkeys as
+    /// JSON records of `Key` and `Values`.
+    fn registry_key_cmd(&self, key: &str) -> Allowed;
 
     /// Get ports/listeners command.
     fn ports_cmd(&self) -> &str;
 
+    /// Get command finding the processes of the listeners `ports_cmd`
+    /// gives none for, such as when collecting unprivileged: the sockets
+    /// of `/proc/net/*` after `# <file>` lines, then the socket file
+    /// descriptors of processes after `# fd`.
+    fn port_owner_cmd(&self) -> Option<&str>;
+
+    /// Get fallback command for the listeners `port_owner_cmd` could not
+    /// attribute, listing listeners and their process as `lsof` does.
+    fn port_owner_fallback_cmd(&self) -> Option<&str>;
+
     /// Get package listing commands.
     fn package_cmds(&self) -> Vec<&str>;
 
+    /// Get command printing the packages of package manager `source`
+    /// (`dpkg`, `rpm`) that own files, as `<path>\t<packages>` lines with
+    /// comma-separated packages, none for a file no package owns.
+    fn package_owner_cmd(&self, source: &str, paths: &[&str]) -> Option<String>;
+
+    /// Get command printing the static host table (`/etc/hosts`).
+    fn hosts_file_cmd(&self) -> Option<&str>;
+
+    /// Get command printing the resolver configuration (`/etc/resolv.conf`).
+    fn resolver_config_cmd(&self) -> Option<&str>;
+
+    /// Get command printing the addresses host names resolve to, as
+    /// `<name>\t<addresses>` lines with comma-separated addresses, none for
+    /// a name that does not resolve.
+    fn host_lookup_cmd(&self, names: &[&str]) -> Option<String>;
+
     /// Get scheduled task listing commands.
     fn scheduled_task_cmds(&self) -> Vec<&str>;
 
     /// Get command to read a file.
-    fn read_file_cmd(&self, path: &str) -> Option<String>;
+    fn read_file_cmd(&self, path: &str) -> Allowed;
+
+    /// Get runtime version commands as (language, command) pairs.
+    fn runtime_version_cmds(&self) -> Vec<(&str, &str)>;
+
+    /// Get command to identify an executable (ELF interpreter or script shebang).
+    fn binary_info_cmd(&self, path: &str) -> Allowed;
+
+    /// Get command printing `mode|user|group|path` for each path.
+    fn file_stat_cmd(&self, paths: &[&str]) -> Option<String>;
+
+    /// Get command to fetch a file as base64 (pack step).
+    fn fetch_file_cmd(&self, path: &str) -> Allowed;
+
+    /// Get command to fetch a directory as a base64 tar.gz of at most
+    /// `max_bytes`, leaving out files matching `excludes` (pack step).
+    fn fetch_dir_cmd(&self, path: &str, excludes: &[String], max_bytes: u64) -> Allowed;
+
+    /// Get command printing `sha256  ./relative/path` for each file of a
+    /// directory not matching `excludes` (pack step).
+    fn checksum_dir_cmd(&self, path: &str, excludes: &[String]) -> Allowed;
 
     /// Get journal/event log command.
-    fn journal_cmd(&self, unit: &str, since: &str) -> Option<String>;
+    fn journal_cmd(&self, unit: &str, since: &str) -> Allowed;
+}
+
+/// Linux init system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitSystem {
+    Systemd,
+    SysVinit,
+    OpenRc,
+}
+
+impl fmt::Display for InitSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitSystem::Systemd => write!(f, "systemd"),
+            InitSystem::SysVinit => write!(f, "sysvinit"),
+            InitSystem::OpenRc => write!(f, "openrc"),
+        }
+    }
+}
+
+/// Get the command set for a target OS.
+pub fn command_set(os_type: OsType) -> Box<dyn CommandSet> {
+    match os_type {
+        OsType::Linux => Box::new(LinuxCommands::new()),
+        OsType::Windows => Box::new(WindowsCommands::new()),
+        OsType::MacOs => Box::new(MacCommands::new()),
+        OsType::Unix => Box::new(GenericUnixCommands::new()),
+    }
 }
 
+/// Process listing of BSD and macOS `ps`, whose columns have no widths:
+/// `-e` prints environments there, `-A` lists every process.
+const BSD_PS: &str =
+    "LC_ALL=C TZ=UTC ps -ww -A -o pid=,ppid=,pcpu=,pmem=,etime=,lstart=,user=,args=";
+
+/// `ps` sample of cumulative CPU time and RSS (KiB), for BSD and macOS.
+fn ps_resource_sample_cmd(pids: &[u32]) -> Option<String> {
+    if pids.is_empty() {
+        return None;
+    }
+    let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
+    Some(format!("ps -o pid=,time=,rss= -p {}", ids.join(",")))
+}
+
+/// Arguments of processes from `/proc/<pid>/cmdline`, one per line. Each
+/// record starts on a new line: retitled processes have no trailing NUL.
+fn proc_cmdline_cmd(pids: &[u32]) -> Option<String> {
+    if pids.is_empty() {
+        return None;
+    }
+    let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
+    Some(format!(
+        "for pid in {}; do printf '\\np%s\\n' \"$pid\"; tr '\\0' '\\n' 2>/dev/null < /proc/$pid/cmdline | sed 's/^/a/'; done",
+        ids.join(" ")
+    ))
+}
+
+/// Packages owning files, for `dpkg` and `rpm`. On merged-`/usr` systems
+/// dpkg knows a file under the path it was packaged with, `/bin/sh` or
+/// `/usr/bin/sh`, so both are asked.
+fn package_owner_cmd(source: &str, paths: &[&str]) -> Option<String> {
+    let owner = match source {
+        "dpkg" => "{ dpkg -S \"$f\" || dpkg -S \"${f#/usr}\" || dpkg -S \"/usr$f\"; } 2>/dev/null | grep -v '^diversion' | head -n 1 | sed 's/: .*//'",
+        // "file ... is not owned by any package" has spaces, names do not
+        "rpm" => "rpm -qf --queryformat '%{NAME}\\n' \"$f\" 2>/dev/null | grep -v ' ' | paste -sd, -",
+        _ => return None,
+    };
+    Some(format!(
+        "for f in {}; do printf '%s\\t%s\\n' \"$f\" \"$({})\"; done",
+        quoted_paths(paths)?,
+        owner
+    ))
+}
+
+/// Addresses of host names from `getent`, which reads the hosts file and
+/// DNS in the order applications do. `ahosts` gives IPv4 and IPv6
+/// addresses, BSD `getent` only knows `hosts`.
+fn getent_hosts_cmd(names: &[&str]) -> Option<String> {
+    Some(format!(
+        "for n in {}; do printf '%s\\t%s\\n' \"$n\" \"$({{ getent ahosts \"$n\" || getent hosts \"$n\"; }} 2>/dev/null | awk '{{print $1}}' | sort -u | paste -sd, -)\"; done",
+        quoted_host_names(names)?
+    ))
+}
+
+/// `lsof` listing of the working directory of processes.
+fn lsof_cwd_cmd(pids: &[u32]) -> Option<String> {
+    if pids.is_empty() {
+        return None;
+    }
+    let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
+    Some(format!(
+        "lsof -a -d cwd -p {} -F pn 2>/dev/null",
+        ids.join(",")
+    ))
+}
+
+/// `lsof` listing of the files opened by processes.
+fn lsof_open_files_cmd(pids: &[u32]) -> Option<String> {
+    if pids.is_empty() {
+        return None;
+    }
+    let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
+    Some(format!("lsof -p {} -F pn 2>/dev/null", ids.join(",")))
+}
+
+/// `lsof` listing of TCP listeners and UDP sockets with their process.
+const LSOF_LISTENERS: &str = "lsof -nP -iTCP -sTCP:LISTEN -iUDP 2>/dev/null";
+
 /// Linux commands using standard tools.
 pub struct LinuxCommands;
 
@@ -77,33 +315,123 @@ impl CommandSet for LinuxCommands {
         Some("uname -m")
     }
 
+    fn privilege_cmd(&self) -> &str {
+        "id -u"
+    }
+
     fn process_cmds(&self) -> Vec<&str> {
-        // Only use ps auxww; the ps -eo format is not handled by the parser
-        // and produces garbage entries when parsed as ps aux format.
-        vec!["ps auxww"]
+        // Whitespace-free columns first, then fixed-width user and process
+        // name columns, so that user names and executables may contain spaces
+        vec![
+            "LC_ALL=C TZ=UTC ps -ww -eo pid=,ppid=,pcpu=,pmem=,etime=,lstart=,user:64=,comm:16=,args=",
+        ]
     }
 
-    fn service_list_cmd(&self) -> &str {
-        "systemctl list-units --type=service --all --no-pager --no-legend"
+    fn resource_sample_cmd(&self, pids: &[u32]) -> Option<String> {
+        if pids.is_empty() {
+            return None;
+        }
+        let stats: Vec<String> = pids.iter().map(|p| format!("/proc/{}/stat", p)).collect();
+        // Clock ticks and page size convert stat fields to seconds and bytes
+        Some(format!(
+            "cat {} 2>/dev/null; echo \"# $(getconf CLK_TCK) $(getconf PAGESIZE)\"",
+            stats.join(" ")
+        ))
     }
 
-    fn service_show_cmd(&self, name: &str) -> Option<String> {
-        // Validate service name to prevent injection
-        if !is_safe_service_name(name) {
+    fn process_cwd_cmd(&self, pids: &[u32]) -> Option<String> {
+        if pids.is_empty() {
             return None;
         }
-        Some(format!("systemctl show {} --no-pager", name))
+        let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
+        Some(format!(
+            "for pid in {}; do printf 'p%s\\nn%s\\n' \"$pid\" \"$(readlink /proc/$pid/cwd 2>/dev/null)\"; done",
+            ids.join(" ")
+        ))
+    }
+
+    fn process_cwd_fallback_cmd(&self, pids: &[u32]) -> Option<String> {
+        lsof_cwd_cmd(pids)
+    }
+
+    fn process_cmdline_cmd(&self, pids: &[u32]) -> Option<String> {
+        proc_cmdline_cmd(pids)
     }
 
-    fn service_cat_cmd(&self, name: &str) -> Option<String> {
-        if !is_safe_service_name(name) {
+    fn open_files_cmd(&self, pids: &[u32]) -> Option<String> {
+        if pids.is_empty() {
             return None;
         }
-        Some(format!("systemctl cat {} 2>/dev/null", name))
+        let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
+        Some(format!(
+            "for pid in {}; do printf 'p%s\\n' \"$pid\"; ls -l /proc/$pid/fd 2>/dev/null | sed -n 's/.* -> /n/p'; done",
+            ids.join(" ")
+        ))
+    }
+
+    fn open_files_fallback_cmd(&self, pids: &[u32]) -> Option<String> {
+        lsof_open_files_cmd(pids)
+    }
+
+    fn init_system_cmd(&self) -> Option<&str> {
+        Some("if [ -d /run/systemd/system ]; then echo systemd; elif command -v rc-status >/dev/null 2>&1; then echo openrc; else echo sysvinit; fi")
+    }
+
+    fn service_list_cmd(&self) -> &str {
+        "systemctl list-units --type=service --all --no-pager --no-legend"
+    }
+
+    fn init_service_list_cmd(&self, init: InitSystem) -> Option<&str> {
+        match init {
+            InitSystem::Systemd => None,
+            InitSystem::SysVinit => {
+                Some("chkconfig --list 2>/dev/null || ls -1 /etc/init.d 2>/dev/null")
+            }
+            InitSystem::OpenRc => Some("rc-status --all --nocolor 2>/dev/null"),
+        }
+    }
+
+    fn init_script_cmd(&self, name: &str) -> Allowed {
+        check_service_name("read init script", name)?;
+        Ok(Some(format!(
+            "cat '/etc/init.d/{}' 2>/dev/null | head -c 65536",
+            name
+        )))
+    }
+
+    fn service_show_cmd(&self, name: &str) -> Allowed {
+        // Validate service name to prevent injection
+        check_service_name("show service", name)?;
+        Ok(Some(format!("systemctl show {} --no-pager", name)))
+    }
+
+    fn service_cat_cmd(&self, name: &str) -> Allowed {
+        check_service_name("read service definition", name)?;
+        Ok(Some(format!("systemctl cat {} 2>/dev/null", name)))
+    }
+
+    fn service_dependencies_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn service_recovery_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn registry_key_cmd(&self, _key: &str) -> Allowed {
+        Ok(None)
     }
 
     fn ports_cmd(&self) -> &str {
-        "ss -lntup"
+        "ss -lntupx"
+    }
+
+    fn port_owner_cmd(&self) -> Option<&str> {
+        Some("for f in tcp tcp6 udp udp6; do echo \"# $f\"; cat /proc/net/$f 2>/dev/null; done; echo '# fd'; ls -l /proc/[0-9]*/fd 2>/dev/null | grep -E '^/proc/|socket:'")
+    }
+
+    fn port_owner_fallback_cmd(&self) -> Option<&str> {
+        Some(LSOF_LISTENERS)
     }
 
     fn package_cmds(&self) -> Vec<&str> {
@@ -113,38 +441,92 @@ impl CommandSet for LinuxCommands {
         ]
     }
 
+    fn package_owner_cmd(&self, source: &str, paths: &[&str]) -> Option<String> {
+        package_owner_cmd(source, paths)
+    }
+
+    fn hosts_file_cmd(&self) -> Option<&str> {
+        Some("cat /etc/hosts 2>/dev/null")
+    }
+
+    fn resolver_config_cmd(&self) -> Option<&str> {
+        Some("cat /etc/resolv.conf 2>/dev/null")
+    }
+
+    fn host_lookup_cmd(&self, names: &[&str]) -> Option<String> {
+        getent_hosts_cmd(names)
+    }
+
     fn scheduled_task_cmds(&self) -> Vec<&str> {
         vec![
             "systemctl list-timers --all --no-pager --no-legend",
             "cat /etc/crontab 2>/dev/null",
-            "ls -la /etc/cron.d/ 2>/dev/null",
+            "cat /etc/cron.d/* 2>/dev/null",
+            "for f in /var/spool/cron/crontabs/* /var/spool/cron/*; do [ -f \"$f\" ] && echo \"# user: ${f##*/}\" && cat \"$f\"; done 2>/dev/null",
         ]
     }
 
-    fn read_file_cmd(&self, path: &str) -> Option<String> {
+    fn read_file_cmd(&self, path: &str) -> Allowed {
         // Validate path to prevent injection
-        if !is_safe_path(path) {
-            return None;
-        }
+        check_path("read file", path)?;
         // Only allow reading from specific directories
         let allowed_prefixes = ["/etc/", "/opt/", "/srv/", "/var/log/", "/home/"];
         if !allowed_prefixes
             .iter()
             .any(|prefix| path.starts_with(prefix))
         {
-            return None;
+            return Err(Denied::new(
+                "read file",
+                path,
+                "outside the readable directories",
+            ));
         }
-        Some(format!("cat '{}' 2>/dev/null | head -c 1048576", path)) // Max 1MB
+        Ok(Some(format!(
+            "cat '{}' 2>/dev/null | head -c 1048576",
+            path
+        ))) // Max 1MB
     }
 
-    fn journal_cmd(&self, unit: &str, since: &str) -> Option<String> {
-        if !is_safe_service_name(unit) {
-            return None;
-        }
+    fn runtime_version_cmds(&self) -> Vec<(&str, &str)> {
+        vec![
+            ("node", "node --version 2>/dev/null"),
+            ("python", "python3 --version 2>&1"),
+            ("java", "java -version 2>&1"),
+            ("dotnet", "dotnet --list-runtimes 2>/dev/null"),
+        ]
+    }
+
+    fn binary_info_cmd(&self, path: &str) -> Allowed {
+        check_absolute_path("inspect binary", path)?;
+        Ok(Some(format!("file -L '{}' 2>/dev/null", path)))
+    }
+
+    fn file_stat_cmd(&self, paths: &[&str]) -> Option<String> {
         Some(format!(
+            "stat -c '%a|%U|%G|%n' -- {} 2>/dev/null",
+            quoted_paths(paths)?
+        ))
+    }
+
+    fn fetch_file_cmd(&self, path: &str) -> Allowed {
+        check_absolute_path("fetch file", path)?;
+        Ok(Some(format!("base64 '{}'", path)))
+    }
+
+    fn fetch_dir_cmd(&self, path: &str, excludes: &[String], max_bytes: u64) -> Allowed {
+        tar_dir_cmd(path, excludes, max_bytes)
+    }
+
+    fn checksum_dir_cmd(&self, path: &str, excludes: &[String]) -> Allowed {
+        find_checksum_cmd(path, excludes, "sha256sum")
+    }
+
+    fn journal_cmd(&self, unit: &str, since: &str) -> Allowed {
+        check_service_name("read service journal", unit)?;
+        Ok(Some(format!(
             "journalctl --since '{}' -u {} --no-pager | tail -n 2000",
             since, unit
-        ))
+        )))
     }
 }
 
@@ -180,46 +562,140 @@ impl CommandSet for WindowsCommands {
         Some("(Get-CimInstance Win32_OperatingSystem).OSArchitecture")
     }
 
+    fn privilege_cmd(&self) -> &str {
+        "([Security.Principal.WindowsPrincipal][Security.Principal.WindowsIdentity]::GetCurrent()).IsInRole([Security.Principal.WindowsBuiltInRole]::Administrator)"
+    }
+
     fn process_cmds(&self) -> Vec<&str> {
         vec![
             "Get-CimInstance Win32_Process | Select-Object ProcessId,ParentProcessId,Name,CommandLine,CreationDate | ConvertTo-Json -Depth 3",
         ]
     }
 
-    fn service_list_cmd(&self) -> &str {
-        "Get-CimInstance Win32_Service | Select-Object Name,State,StartMode,PathName,DisplayName,Description | ConvertTo-Json -Depth 3"
-    }
-
-    fn service_show_cmd(&self, name: &str) -> Option<String> {
-        if !is_safe_service_name(name) {
+    fn resource_sample_cmd(&self, pids: &[u32]) -> Option<String> {
+        if pids.is_empty() {
             return None;
         }
+        let ids: Vec<String> = pids.iter().map(u32::to_string).collect();
         Some(format!(
+            "Get-Process -Id {} -ErrorAction SilentlyContinue | Select-Object Id,CPU,WorkingSet64 | ConvertTo-Json",
+            ids.join(",")
+        ))
+    }
+
+    fn process_cwd_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None // Not exposed by Windows without a debugger
+    }
+
+    fn process_cwd_fallback_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn process_cmdline_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None // Win32_Process gives the full command line
+    }
+
+    fn open_files_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn open_files_fallback_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn init_system_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn init_service_list_cmd(&self, _init: InitSystem) -> Option<&str> {
+        None
+    }
+
+    fn init_script_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn service_list_cmd(&self) -> &str {
+        "Get-CimInstance Win32_Service | Select-Object Name,State,StartMode,PathName,DisplayName,Description,StartName,ProcessId | ConvertTo-Json -Depth 3"
+    }
+
+    fn service_show_cmd(&self, name: &str) -> Allowed {
+        check_service_name("show service", name)?;
+        Ok(Some(format!(
             "Get-CimInstance Win32_Service -Filter \"Name='{}'\" | Select-Object * | ConvertTo-Json -Depth 3",
             name
-        ))
+        )))
+    }
+
+    fn service_cat_cmd(&self, _name: &str) -> Allowed {
+        Ok(None) // Windows doesn't have unit files
+    }
+
+    fn service_dependencies_cmd(&self) -> Option<&str> {
+        Some("Get-CimInstance Win32_DependentService | ForEach-Object { [pscustomobject]@{ Antecedent = $_.Antecedent.Name; Dependent = $_.Dependent.Name } } | ConvertTo-Json -Depth 3")
     }
 
-    fn service_cat_cmd(&self, _name: &str) -> Option<String> {
-        None // Windows doesn't have unit files
+    fn service_recovery_cmd(&self, name: &str) -> Allowed {
+        check_service_name("show service recovery", name)?;
+        Ok(Some(format!("sc.exe qfailure \"{}\"", name)))
+    }
+
+    fn registry_key_cmd(&self, key: &str) -> Allowed {
+        if !is_safe_registry_key(key) {
+            return Err(Denied::new(
+                "read registry key",
+                key,
+                "outside the readable registry keys",
+            ));
+        }
+        // Read-only: values are listed, binary values skipped
+        Ok(Some(format!(
+            "$k = 'HKLM:\\{}'; @(Get-Item -Path $k -ErrorAction SilentlyContinue) + @(Get-ChildItem -Path $k -Recurse -Depth 2 -ErrorAction SilentlyContinue) | ForEach-Object {{ $values = [ordered]@{{}}; foreach ($n in $_.GetValueNames()) {{ $v = $_.GetValue($n); if ($v -is [byte[]]) {{ continue }}; if ($v -is [array]) {{ $v = $v -join \"`n\" }}; $values[$n] = [string]$v }}; [pscustomobject]@{{ Key = $_.Name; Values = $values }} }} | ConvertTo-Json -Depth 4",
+            key
+        )))
     }
 
     fn ports_cmd(&self) -> &str {
         "Get-NetTCPConnection | Where-Object {$_.State -eq 'Listen'} | Select-Object LocalAddress,LocalPort,OwningProcess,State | ConvertTo-Json -Depth 3"
     }
 
+    fn port_owner_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn port_owner_fallback_cmd(&self) -> Option<&str> {
+        None
+    }
+
     fn package_cmds(&self) -> Vec<&str> {
         vec!["Get-Package | Select-Object Name,Version | ConvertTo-Json -Depth 3"]
     }
 
+    fn package_owner_cmd(&self, _source: &str, _paths: &[&str]) -> Option<String> {
+        None // Installers do not record the files they deploy
+    }
+
+    fn hosts_file_cmd(&self) -> Option<&str> {
+        Some("Get-Content -Path \"$env:SystemRoot\\System32\\drivers\\etc\\hosts\" -ErrorAction SilentlyContinue")
+    }
+
+    fn resolver_config_cmd(&self) -> Option<&str> {
+        None // DNS servers are set per network interface
+    }
+
+    fn host_lookup_cmd(&self, names: &[&str]) -> Option<String> {
+        Some(format!(
+            "foreach ($n in @({})) {{ $a = try {{ ([Net.Dns]::GetHostAddresses($n) | ForEach-Object {{ $_.IPAddressToString }} | Sort-Object -Unique) -join ',' }} catch {{ '' }}; \"$n`t$a\" }}",
+            quoted_host_names(names)?.replace(' ', ",")
+        ))
+    }
+
     fn scheduled_task_cmds(&self) -> Vec<&str> {
         vec!["Get-ScheduledTask | Select-Object TaskName,State,TaskPath | ConvertTo-Json -Depth 3"]
     }
 
-    fn read_file_cmd(&self, path: &str) -> Option<String> {
-        if !is_safe_path(path) {
-            return None;
-        }
+    fn read_file_cmd(&self, path: &str) -> Allowed {
+        check_path("read file", path)?;
         // Only allow reading from specific directories
         let allowed_prefixes = ["C:\\ProgramData\\", "C:\\Program Files\\", "C:\\inetpub\\"];
         let normalized = path.replace('/', "\\");
@@ -227,17 +703,481 @@ impl CommandSet for WindowsCommands {
             .iter()
             .any(|prefix| normalized.starts_with(prefix))
         {
-            return None;
+            return Err(Denied::new(
+                "read file",
+                path,
+                "outside the readable directories",
+            ));
         }
-        Some(format!(
+        Ok(Some(format!(
             "Get-Content -Path '{}' -TotalCount 10000 -ErrorAction SilentlyContinue",
             path
-        ))
+        )))
+    }
+
+    fn runtime_version_cmds(&self) -> Vec<(&str, &str)> {
+        vec![
+            ("node", "node --version"),
+            ("python", "python --version"),
+            ("java", "java -version 2>&1"),
+            ("dotnet", "dotnet --list-runtimes"),
+        ]
+    }
+
+    fn binary_info_cmd(&self, _path: &str) -> Allowed {
+        Ok(None) // No equivalent of file(1) on Windows
     }
 
-    fn journal_cmd(&self, _unit: &str, _since: &str) -> Option<String> {
+    fn file_stat_cmd(&self, _paths: &[&str]) -> Option<String> {
+        None // ACLs have no owner/group/mode equivalent in a Linux image
+    }
+
+    fn fetch_file_cmd(&self, path: &str) -> Allowed {
+        check_path("fetch file", path)?;
+        Ok(Some(format!(
+            "[Convert]::ToBase64String([IO.File]::ReadAllBytes('{}'))",
+            path
+        )))
+    }
+
+    fn fetch_dir_cmd(&self, _path: &str, _excludes: &[String], _max_bytes: u64) -> Allowed {
+        Ok(None) // Archiving would require writing to the target's disk
+    }
+
+    fn checksum_dir_cmd(&self, _path: &str, _excludes: &[String]) -> Allowed {
+        Ok(None)
+    }
+
+    fn journal_cmd(&self, _unit: &str, _since: &str) -> Allowed {
         // Windows event log for Service Control Manager
-        Some("Get-WinEvent -FilterHashtable @{LogName='System'; ProviderName='Service Control Manager'; StartTime=(Get-Date).AddHours(-1)} -MaxEvents 100 -ErrorAction SilentlyContinue | Select-Object TimeCreated,Message | ConvertTo-Json -Depth 3".to_string())
+        Ok(Some("Get-WinEvent -FilterHashtable @{LogName='System'; ProviderName='Service Control Manager'; StartTime=(Get-Date).AddHours(-1)} -MaxEvents 100 -ErrorAction SilentlyContinue | Select-Object TimeCreated,Message | ConvertTo-Json -Depth 3".to_string()))
+    }
+}
+
+/// macOS commands using launchd and BSD tools.
+pub struct MacCommands;
+
+impl MacCommands {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for MacCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Directories holding launchd job definitions, searched in order.
+const LAUNCHD_DIRS: [&str; 2] = ["/Library/LaunchDaemons", "/Library/LaunchAgents"];
+
+impl CommandSet for MacCommands {
+    fn hostname_cmd(&self) -> &str {
+        "hostname"
+    }
+
+    fn os_version_cmd(&self) -> Option<&str> {
+        Some("sw_vers")
+    }
+
+    fn kernel_version_cmd(&self) -> Option<&str> {
+        Some("uname -r")
+    }
+
+    fn architecture_cmd(&self) -> Option<&str> {
+        Some("uname -m")
+    }
+
+    fn privilege_cmd(&self) -> &str {
+        "id -u"
+    }
+
+    fn process_cmds(&self) -> Vec<&str> {
+        vec![BSD_PS]
+    }
+
+    fn resource_sample_cmd(&self, pids: &[u32]) -> Option<String> {
+        ps_resource_sample_cmd(pids)
+    }
+
+    fn process_cwd_cmd(&self, pids: &[u32]) -> Option<String> {
+        lsof_cwd_cmd(pids)
+    }
+
+    fn process_cwd_fallback_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn process_cmdline_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None // No procfs; ps -ww does not truncate arguments
+    }
+
+    fn open_files_cmd(&self, pids: &[u32]) -> Option<String> {
+        lsof_open_files_cmd(pids)
+    }
+
+    fn open_files_fallback_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn init_system_cmd(&self) -> Option<&str> {
+        None // Always launchd
+    }
+
+    fn service_list_cmd(&self) -> &str {
+        "launchctl list"
+    }
+
+    fn init_service_list_cmd(&self, _init: InitSystem) -> Option<&str> {
+        None
+    }
+
+    fn init_script_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn service_show_cmd(&self, name: &str) -> Allowed {
+        check_service_name("show service", name)?;
+        Ok(Some(format!("launchctl list {}", name)))
+    }
+
+    fn service_cat_cmd(&self, name: &str) -> Allowed {
+        check_service_name("read service definition", name)?;
+        // Job definition plist, converted to JSON
+        let cmds: Vec<String> = LAUNCHD_DIRS
+            .iter()
+            .map(|dir| {
+                format!(
+                    "plutil -convert json -o - '{}/{}.plist' 2>/dev/null",
+                    dir, name
+                )
+            })
+            .collect();
+        Ok(Some(cmds.join(" || ")))
+    }
+
+    fn service_dependencies_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn service_recovery_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn registry_key_cmd(&self, _key: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn ports_cmd(&self) -> &str {
+        "lsof -nP -iTCP -sTCP:LISTEN"
+    }
+
+    fn port_owner_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn port_owner_fallback_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn package_cmds(&self) -> Vec<&str> {
+        vec!["pkgutil --pkgs"]
+    }
+
+    fn package_owner_cmd(&self, _source: &str, _paths: &[&str]) -> Option<String> {
+        None // pkgutil packages carry no version to pin
+    }
+
+    fn hosts_file_cmd(&self) -> Option<&str> {
+        Some("cat /etc/hosts 2>/dev/null")
+    }
+
+    fn resolver_config_cmd(&self) -> Option<&str> {
+        Some("cat /etc/resolv.conf 2>/dev/null")
+    }
+
+    fn host_lookup_cmd(&self, _names: &[&str]) -> Option<String> {
+        None // No getent; dscacheutil prints one record per address family
+    }
+
+    fn scheduled_task_cmds(&self) -> Vec<&str> {
+        vec!["crontab -l 2>/dev/null", "cat /etc/crontab 2>/dev/null"]
+    }
+
+    fn read_file_cmd(&self, path: &str) -> Allowed {
+        check_path("read file", path)?;
+        // Only allow reading from specific directories
+        let allowed_prefixes = [
+            "/etc/",
+            "/private/etc/",
+            "/opt/",
+            "/usr/local/etc/",
+            "/Library/",
+            "/Users/",
+            "/var/log/",
+        ];
+        if !allowed_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+        {
+            return Err(Denied::new(
+                "read file",
+                path,
+                "outside the readable directories",
+            ));
+        }
+        Ok(Some(format!(
+            "cat '{}' 2>/dev/null | head -c 1048576",
+            path
+        ))) // Max 1MB
+    }
+
+    fn runtime_version_cmds(&self) -> Vec<(&str, &str)> {
+        vec![
+            ("node", "node --version 2>/dev/null"),
+            ("python", "python3 --version 2>&1"),
+            ("java", "java -version 2>&1"),
+            ("dotnet", "dotnet --list-runtimes 2>/dev/null"),
+        ]
+    }
+
+    fn binary_info_cmd(&self, path: &str) -> Allowed {
+        check_absolute_path("inspect binary", path)?;
+        Ok(Some(format!("file -L '{}' 2>/dev/null", path)))
+    }
+
+    fn file_stat_cmd(&self, paths: &[&str]) -> Option<String> {
+        Some(format!(
+            "stat -f '%Lp|%Su|%Sg|%N' -- {} 2>/dev/null",
+            quoted_paths(paths)?
+        ))
+    }
+
+    fn fetch_file_cmd(&self, path: &str) -> Allowed {
+        check_absolute_path("fetch file", path)?;
+        Ok(Some(format!("base64 -i '{}'", path)))
+    }
+
+    fn fetch_dir_cmd(&self, path: &str, excludes: &[String], max_bytes: u64) -> Allowed {
+        tar_dir_cmd(path, excludes, max_bytes)
+    }
+
+    fn checksum_dir_cmd(&self, path: &str, excludes: &[String]) -> Allowed {
+        find_checksum_cmd(path, excludes, "shasum -a 256")
+    }
+
+    fn journal_cmd(&self, _unit: &str, _since: &str) -> Allowed {
+        Ok(None) // Unified log queries are too slow to run per service
+    }
+}
+
+/// Portable Unix commands for hosts without systemd or `ss` (FreeBSD,
+/// SysV-init Linux).
+pub struct GenericUnixCommands;
+
+impl GenericUnixCommands {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for GenericUnixCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandSet for GenericUnixCommands {
+    fn hostname_cmd(&self) -> &str {
+        "hostname"
+    }
+
+    fn os_version_cmd(&self) -> Option<&str> {
+        Some("freebsd-version 2>/dev/null || cat /etc/redhat-release 2>/dev/null || cat /etc/os-release 2>/dev/null || uname -sr")
+    }
+
+    fn kernel_version_cmd(&self) -> Option<&str> {
+        Some("uname -r")
+    }
+
+    fn architecture_cmd(&self) -> Option<&str> {
+        Some("uname -m")
+    }
+
+    fn privilege_cmd(&self) -> &str {
+        "id -u"
+    }
+
+    fn process_cmds(&self) -> Vec<&str> {
+        vec![BSD_PS]
+    }
+
+    fn resource_sample_cmd(&self, pids: &[u32]) -> Option<String> {
+        ps_resource_sample_cmd(pids)
+    }
+
+    fn process_cwd_cmd(&self, pids: &[u32]) -> Option<String> {
+        // lsof is a package on BSD, procstat output differs per release
+        lsof_cwd_cmd(pids)
+    }
+
+    fn process_cwd_fallback_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn process_cmdline_cmd(&self, pids: &[u32]) -> Option<String> {
+        // Linux hosts without systemd have procfs, BSD leaves no output
+        proc_cmdline_cmd(pids)
+    }
+
+    fn open_files_cmd(&self, pids: &[u32]) -> Option<String> {
+        lsof_open_files_cmd(pids)
+    }
+
+    fn open_files_fallback_cmd(&self, _pids: &[u32]) -> Option<String> {
+        None
+    }
+
+    fn init_system_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn service_list_cmd(&self) -> &str {
+        // Enabled rc.d scripts on BSD, init scripts elsewhere
+        "service -e 2>/dev/null || ls -1 /etc/init.d 2>/dev/null"
+    }
+
+    fn init_service_list_cmd(&self, _init: InitSystem) -> Option<&str> {
+        None
+    }
+
+    fn init_script_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn service_show_cmd(&self, _name: &str) -> Allowed {
+        Ok(None) // The service list is all there is
+    }
+
+    fn service_cat_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn service_dependencies_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn service_recovery_cmd(&self, _name: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn registry_key_cmd(&self, _key: &str) -> Allowed {
+        Ok(None)
+    }
+
+    fn ports_cmd(&self) -> &str {
+        "netstat -an"
+    }
+
+    fn port_owner_cmd(&self) -> Option<&str> {
+        None
+    }
+
+    fn port_owner_fallback_cmd(&self) -> Option<&str> {
+        // netstat -an lists no processes; lsof is a package on BSD
+        Some(LSOF_LISTENERS)
+    }
+
+    fn package_cmds(&self) -> Vec<&str> {
+        vec![
+            "pkg info 2>/dev/null",
+            "dpkg -l 2>/dev/null",
+            "rpm -qa --queryformat '%{NAME} %{VERSION}-%{RELEASE} %{ARCH}\\n' 2>/dev/null",
+        ]
+    }
+
+    fn package_owner_cmd(&self, source: &str, paths: &[&str]) -> Option<String> {
+        package_owner_cmd(source, paths)
+    }
+
+    fn hosts_file_cmd(&self) -> Option<&str> {
+        Some("cat /etc/hosts 2>/dev/null")
+    }
+
+    fn resolver_config_cmd(&self) -> Option<&str> {
+        Some("cat /etc/resolv.conf 2>/dev/null")
+    }
+
+    fn host_lookup_cmd(&self, names: &[&str]) -> Option<String> {
+        getent_hosts_cmd(names)
+    }
+
+    fn scheduled_task_cmds(&self) -> Vec<&str> {
+        vec!["cat /etc/crontab 2>/dev/null"]
+    }
+
+    fn read_file_cmd(&self, path: &str) -> Allowed {
+        check_path("read file", path)?;
+        // Only allow reading from specific directories
+        let allowed_prefixes = [
+            "/etc/",
+            "/usr/local/etc/",
+            "/opt/",
+            "/srv/",
+            "/var/log/",
+            "/home/",
+            "/usr/home/",
+        ];
+        if !allowed_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+        {
+            return Err(Denied::new(
+                "read file",
+                path,
+                "outside the readable directories",
+            ));
+        }
+        Ok(Some(format!(
+            "cat '{}' 2>/dev/null | head -c 1048576",
+            path
+        ))) // Max 1MB
+    }
+
+    fn runtime_version_cmds(&self) -> Vec<(&str, &str)> {
+        vec![
+            ("node", "node --version 2>/dev/null"),
+            ("python", "python3 --version 2>&1"),
+            ("java", "java -version 2>&1"),
+            ("dotnet", "dotnet --list-runtimes 2>/dev/null"),
+        ]
+    }
+
+    fn binary_info_cmd(&self, path: &str) -> Allowed {
+        check_absolute_path("inspect binary", path)?;
+        Ok(Some(format!("file -L '{}' 2>/dev/null", path)))
+    }
+
+    fn file_stat_cmd(&self, _paths: &[&str]) -> Option<String> {
+        None // stat(1) is not POSIX and its options differ between systems
+    }
+
+    fn fetch_file_cmd(&self, path: &str) -> Allowed {
+        check_absolute_path("fetch file", path)?;
+        Ok(Some(format!("base64 '{}'", path)))
+    }
+
+    fn fetch_dir_cmd(&self, path: &str, excludes: &[String], max_bytes: u64) -> Allowed {
+        tar_dir_cmd(path, excludes, max_bytes)
+    }
+
+    fn checksum_dir_cmd(&self, path: &str, excludes: &[String]) -> Allowed {
+        find_checksum_cmd(path, excludes, UNIX_SHA256)
+    }
+
+    fn journal_cmd(&self, _unit: &str, _since: &str) -> Allowed {
+        Ok(None) // No common log system
     }
 }
 
@@ -250,6 +1190,62 @@ fn is_safe_service_name(name: &str) -> bool {
         && name.len() < 256
 }
 
+fn check_service_name(action: &'static str, name: &str) -> Result<(), Denied> {
+    if !is_safe_service_name(name) {
+        return Err(Denied::new(action, name, "unsafe service name"));
+    }
+    Ok(())
+}
+
+/// Registry keys the collector may read, relative to `HKLM`.
+const REGISTRY_KEY_PREFIXES: [&str; 2] = ["SOFTWARE\\", "SYSTEM\\CurrentControlSet\\Services\\"];
+
+fn is_safe_registry_key(key: &str) -> bool {
+    let allowed = REGISTRY_KEY_PREFIXES
+        .iter()
+        .any(|prefix| key.len() > prefix.len() && key[..prefix.len()].eq_ignore_ascii_case(prefix));
+    allowed
+        && key.len() < 256
+        && key.split('\\').all(|component| {
+            !component.is_empty()
+                && component
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | '(' | ')'))
+        })
+}
+
+/// Absolute, safe paths quoted for a shell command line, or `None` when
+/// there are none.
+fn quoted_paths(paths: &[&str]) -> Option<String> {
+    let quoted: Vec<String> = paths
+        .iter()
+        .filter(|p| is_safe_path(p) && p.starts_with('/') && !p.contains('\''))
+        .map(|p| format!("'{}'", p))
+        .collect();
+    (!quoted.is_empty()).then(|| quoted.join(" "))
+}
+
+/// Host names single-quoted for the shell, leaving out anything that is
+/// not a host name.
+fn quoted_host_names(names: &[&str]) -> Option<String> {
+    let quoted: Vec<String> = names
+        .iter()
+        .filter(|n| is_host_name(n))
+        .map(|n| format!("'{}'", n))
+        .collect();
+    (!quoted.is_empty()).then(|| quoted.join(" "))
+}
+
+/// Whether a name is made of host name labels only.
+fn is_host_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && !name.starts_with(['-', '.'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
 /// Validate that a path is safe (no injection).
 fn is_safe_path(path: &str) -> bool {
     // Disallow command injection characters
@@ -265,6 +1261,96 @@ fn is_safe_path(path: &str) -> bool {
         && path.len() < 1024
 }
 
+/// A path that can be single-quoted on a command line.
+fn check_path(action: &'static str, path: &str) -> Result<(), Denied> {
+    if !is_safe_path(path) || path.contains('\'') {
+        return Err(Denied::new(action, path, "unsafe path"));
+    }
+    Ok(())
+}
+
+/// An absolute path that can be single-quoted on a command line.
+fn check_absolute_path(action: &'static str, path: &str) -> Result<(), Denied> {
+    check_path(action, path)?;
+    if !path.starts_with('/') {
+        return Err(Denied::new(action, path, "relative path"));
+    }
+    Ok(())
+}
+
+/// Patterns of files that typically hold secrets, never archived.
+const SECRET_EXCLUDES: [&str; 3] = [".env", "*.pem", "*.key"];
+
+/// SHA-256 tool of a Unix host: GNU `sha256sum`, else Perl's `shasum`.
+const UNIX_SHA256: &str =
+    "$(command -v sha256sum >/dev/null && echo sha256sum || echo shasum -a 256)";
+
+/// A directory that may be archived: absolute, safe and not the root.
+fn check_archive_dir(action: &'static str, path: &str) -> Result<(), Denied> {
+    check_absolute_path(action, path)?;
+    if path == "/" {
+        return Err(Denied::new(
+            action,
+            path,
+            "the root directory is never archived",
+        ));
+    }
+    Ok(())
+}
+
+/// The secret patterns and `excludes`, each of which can be single-quoted
+/// on a command line.
+fn exclude_patterns<'a>(
+    action: &'static str,
+    excludes: &'a [String],
+) -> Result<Vec<&'a str>, Denied> {
+    for pattern in excludes {
+        if pattern.is_empty() || pattern.len() >= 256 || pattern.contains(['\'', '\n', '\r']) {
+            return Err(Denied::new(action, pattern, "unsafe exclusion pattern"));
+        }
+    }
+    Ok(SECRET_EXCLUDES
+        .into_iter()
+        .chain(excludes.iter().map(String::as_str))
+        .collect())
+}
+
+/// Archive a directory as a base64 tar.gz, cut after `max_bytes + 1` bytes
+/// so that the collector can tell an oversized archive.
+fn tar_dir_cmd(path: &str, excludes: &[String], max_bytes: u64) -> Allowed {
+    check_archive_dir("fetch directory", path)?;
+    let excludes: String = exclude_patterns("fetch directory", excludes)?
+        .iter()
+        .map(|pattern| format!(" --exclude='{}'", pattern))
+        .collect();
+    Ok(Some(format!(
+        "tar czf - -C '{}'{} . 2>/dev/null | head -c {} | base64",
+        path,
+        excludes,
+        max_bytes.saturating_add(1)
+    )))
+}
+
+/// Hash the files of a directory that `tar_dir_cmd` archives. A pattern
+/// excludes the files it names or matches and everything under the
+/// directories it names, as with tar.
+fn find_checksum_cmd(path: &str, excludes: &[String], hasher: &str) -> Allowed {
+    check_archive_dir("checksum directory", path)?;
+    let filters: String = exclude_patterns("checksum directory", excludes)?
+        .iter()
+        .map(|pattern| {
+            format!(
+                " ! -name '{0}' ! -path '{0}' ! -path '*/{0}' ! -path '*/{0}/*'",
+                pattern
+            )
+        })
+        .collect();
+    Ok(Some(format!(
+        "cd '{}' && find . -type f{} -exec {} {{}} + 2>/dev/null",
+        path, filters, hasher
+    )))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -299,20 +1385,182 @@ mod tests {
 
         assert_eq!(cmds.hostname_cmd(), "hostname");
         assert!(cmds.os_version_cmd().is_some());
-        assert!(cmds.service_show_cmd("nginx").is_some());
-        assert!(cmds.service_show_cmd("nginx; rm -rf /").is_none());
+        assert!(cmds.service_show_cmd("nginx").unwrap().is_some());
+        assert!(cmds.service_show_cmd("nginx; rm -rf /").is_err());
+    }
+
+    #[test]
+    fn test_host_lookup_cmd() {
+        let cmds = LinuxCommands::new();
+        let cmd = cmds
+            .host_lookup_cmd(&["db.internal", "api.example.com", "x; id", "-n"])
+            .unwrap();
+        assert!(cmd.starts_with("for n in 'db.internal' 'api.example.com'; do"));
+        assert!(!cmd.contains("id'"));
+        assert!(cmds.host_lookup_cmd(&["$(id)"]).is_none());
+        assert!(command_set(OsType::Windows)
+            .host_lookup_cmd(&["db.internal"])
+            .unwrap()
+            .starts_with("foreach ($n in @('db.internal'))"));
     }
 
     #[test]
     fn test_read_file_allowed_paths() {
         let cmds = LinuxCommands::new();
 
-        assert!(cmds.read_file_cmd("/etc/nginx/nginx.conf").is_some());
-        assert!(cmds.read_file_cmd("/opt/app/config.yaml").is_some());
-        assert!(cmds.read_file_cmd("/var/log/app.log").is_some());
+        assert!(cmds
+            .read_file_cmd("/etc/nginx/nginx.conf")
+            .unwrap()
+            .is_some());
+        assert!(cmds
+            .read_file_cmd("/opt/app/config.yaml")
+            .unwrap()
+            .is_some());
+        assert!(cmds.read_file_cmd("/var/log/app.log").unwrap().is_some());
 
         // Disallowed paths
-        assert!(cmds.read_file_cmd("/root/.bashrc").is_none());
-        assert!(cmds.read_file_cmd("/tmp/secret").is_none());
+        assert!(cmds.read_file_cmd("/root/.bashrc").is_err());
+        assert!(cmds.read_file_cmd("/tmp/secret").is_err());
+    }
+
+    #[test]
+    fn test_binary_info_cmd() {
+        let cmds = LinuxCommands::new();
+
+        assert!(cmds
+            .binary_info_cmd("/opt/app/bin/server")
+            .unwrap()
+            .is_some());
+        assert!(cmds.binary_info_cmd("server").is_err());
+        assert!(cmds.binary_info_cmd("/opt/it's").is_err());
+        assert!(cmds.binary_info_cmd("/opt/app; rm -rf /").is_err());
+    }
+
+    #[test]
+    fn test_file_stat_cmd() {
+        let cmds = LinuxCommands::new();
+
+        assert_eq!(
+            cmds.file_stat_cmd(&["/etc/app.conf", "/opt/it's", "/opt/app"])
+                .as_deref(),
+            Some("stat -c '%a|%U|%G|%n' -- '/etc/app.conf' '/opt/app' 2>/dev/null")
+        );
+        assert!(cmds.file_stat_cmd(&["relative", "/opt/`id`"]).is_none());
+    }
+
+    #[test]
+    fn test_fetch_cmds() {
+        let cmds = LinuxCommands::new();
+
+        assert!(cmds.fetch_file_cmd("/usr/local/bin/app").unwrap().is_some());
+        assert_eq!(
+            cmds.fetch_dir_cmd("/opt/app", &["logs".to_string()], 1024)
+                .unwrap()
+                .as_deref(),
+            Some(
+                "tar czf - -C '/opt/app' --exclude='.env' --exclude='*.pem' --exclude='*.key' \
+                 --exclude='logs' . 2>/dev/null | head -c 1025 | base64"
+            )
+        );
+        assert_eq!(
+            cmds.checksum_dir_cmd("/opt/app", &[]).unwrap().as_deref(),
+            Some(
+                "cd '/opt/app' && find . -type f \
+                 ! -name '.env' ! -path '.env' ! -path '*/.env' ! -path '*/.env/*' \
+                 ! -name '*.pem' ! -path '*.pem' ! -path '*/*.pem' ! -path '*/*.pem/*' \
+                 ! -name '*.key' ! -path '*.key' ! -path '*/*.key' ! -path '*/*.key/*' \
+                 -exec sha256sum {} + 2>/dev/null"
+            )
+        );
+        assert!(cmds.fetch_dir_cmd("/", &[], 1024).is_err());
+        assert!(cmds
+            .fetch_dir_cmd("/opt/app", &["x'; id; '".to_string()], 1024)
+            .is_err());
+        assert!(cmds.fetch_file_cmd("/opt/app/`id`").is_err());
+        assert!(WindowsCommands::new()
+            .fetch_dir_cmd("C:\\app", &[], 1024)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_init_system_commands() {
+        let cmds = LinuxCommands::new();
+
+        assert!(cmds.init_system_cmd().is_some());
+        assert!(cmds.init_service_list_cmd(InitSystem::Systemd).is_none());
+        assert!(cmds
+            .init_service_list_cmd(InitSystem::SysVinit)
+            .unwrap()
+            .starts_with("chkconfig --list"));
+        assert_eq!(
+            cmds.init_script_cmd("nginx").unwrap().unwrap(),
+            "cat '/etc/init.d/nginx' 2>/dev/null | head -c 65536"
+        );
+        assert!(cmds.init_script_cmd("../../etc/shadow").is_err());
+        assert_eq!(InitSystem::OpenRc.to_string(), "openrc");
+    }
+
+    #[test]
+    fn test_mac_commands() {
+        let cmds = command_set(OsType::MacOs);
+
+        assert_eq!(cmds.service_list_cmd(), "launchctl list");
+        assert_eq!(
+            cmds.service_show_cmd("homebrew.mxcl.nginx")
+                .unwrap()
+                .unwrap(),
+            "launchctl list homebrew.mxcl.nginx"
+        );
+        assert!(cmds
+            .service_cat_cmd("homebrew.mxcl.nginx")
+            .unwrap()
+            .unwrap()
+            .contains("'/Library/LaunchDaemons/homebrew.mxcl.nginx.plist'"));
+        assert!(cmds.service_cat_cmd("x'; id").is_err());
+        assert!(cmds
+            .read_file_cmd("/usr/local/etc/nginx/nginx.conf")
+            .unwrap()
+            .is_some());
+        assert!(cmds.read_file_cmd("/System/Library/foo").is_err());
+        assert!(cmds.journal_cmd("nginx", "1 hour ago").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_generic_unix_commands() {
+        let cmds = command_set(OsType::Unix);
+
+        assert_eq!(cmds.ports_cmd(), "netstat -an");
+        assert!(!cmds.service_list_cmd().contains("systemctl"));
+        assert!(cmds.service_show_cmd("nginx").unwrap().is_none());
+        assert_eq!(cmds.package_cmds()[0], "pkg info 2>/dev/null");
+        assert!(cmds
+            .read_file_cmd("/usr/local/etc/nginx/nginx.conf")
+            .unwrap()
+            .is_some());
+        assert!(cmds.read_file_cmd("/root/.profile").is_err());
+    }
+
+    #[test]
+    fn test_registry_key_allowlist() {
+        let cmds = WindowsCommands::new();
+        let cmd = cmds
+            .registry_key_cmd("SOFTWARE\\Contoso\\Billing")
+            .unwrap()
+            .unwrap();
+        assert!(cmd.starts_with("$k = 'HKLM:\\SOFTWARE\\Contoso\\Billing';"));
+        assert!(cmds
+            .registry_key_cmd("SYSTEM\\CurrentControlSet\\Services\\BillingSvc")
+            .unwrap()
+            .is_some());
+        assert!(cmds.registry_key_cmd("SOFTWARE\\").is_err());
+        assert!(cmds.registry_key_cmd("SAM\\SAM\\Domains").is_err());
+        assert!(cmds
+            .registry_key_cmd("SOFTWARE\\Contoso'; Remove-Item C:\\")
+            .is_err());
+        assert!(LinuxCommands::new()
+            .registry_key_cmd("SOFTWARE\\Contoso")
+            .unwrap()
+            .is_none());
     }
 }
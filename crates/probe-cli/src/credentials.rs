@@ -0,0 +1,206 @@
+//! Connection passwords.
+//!
+//! A password given with `--ssh-password` or `--winrm-password` ends up in
+//! the shell history and in the process list of the machine running the
+//! probe. It can instead come from the environment, from a file, or from
+//! the output of a command such as a secrets manager client. Passwords on
+//! the command line win, then the file or command, then the environment.
+
+use crate::collector::{CollectionMode, CollectorConfig};
+use crate::executor::SshTransport;
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::{debug, warn};
+use xcprobe_common::OsType;
+
+/// Environment variable holding the SSH password.
+pub const SSH_PASSWORD_ENV: &str = "XCPROBE_SSH_PASSWORD";
+
+/// Environment variable holding the WinRM password.
+pub const WINRM_PASSWORD_ENV: &str = "XCPROBE_WINRM_PASSWORD";
+
+/// Placeholder of the target host in credential commands.
+const HOST_PLACEHOLDER: &str = "{host}";
+
+/// Where connection passwords are read from, besides the command line and
+/// the environment.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialSources {
+    /// File whose first line is the password.
+    pub password_file: Option<PathBuf>,
+    /// Shell command printing the password, with `{host}` replaced by the
+    /// target host.
+    pub credential_command: Option<String>,
+}
+
+impl CredentialSources {
+    /// The password for `host` from the file or the command, if either is
+    /// configured.
+    pub fn password(&self, host: &str) -> Result<Option<String>> {
+        if let Some(ref path) = self.password_file {
+            return read_password_file(path).map(Some);
+        }
+        if let Some(ref command) = self.credential_command {
+            return run_credential_command(command, host).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// The password of a connection to `host`: `given` on the command
+    /// line, else from the sources, else from the `env` variable.
+    pub fn resolve(&self, given: Option<String>, env: &str, host: &str) -> Result<Option<String>> {
+        if given.is_some() {
+            return Ok(given);
+        }
+        if let Some(password) = self.password(host)? {
+            return Ok(Some(password));
+        }
+        Ok(std::env::var(env)
+            .ok()
+            .filter(|password| !password.is_empty()))
+    }
+
+    /// Fill in the password of the connection `config` will open: SSH or
+    /// WinRM depending on the target OS. A local collection has none, and
+    /// the system `ssh` client only authenticates with keys and the agent.
+    pub fn fill(&self, config: &mut CollectorConfig) -> Result<()> {
+        if config.effective_mode() == CollectionMode::LocalEphemeral {
+            return Ok(());
+        }
+        if config.os_type != OsType::Windows && config.ssh_transport == SshTransport::OpenSsh {
+            return Ok(());
+        }
+        let target = config.target.clone();
+        if config.os_type == OsType::Windows {
+            config.winrm_password =
+                self.resolve(config.winrm_password.take(), WINRM_PASSWORD_ENV, &target)?;
+        } else {
+            config.ssh_password =
+                self.resolve(config.ssh_password.take(), SSH_PASSWORD_ENV, &target)?;
+        }
+        Ok(())
+    }
+}
+
+/// The first line of a password file.
+fn read_password_file(path: &std::path::Path) -> Result<String> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read password file {:?}", path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                warn!(
+                    "Password file {:?} is readable by other users; restrict it to 0600",
+                    path
+                );
+            }
+        }
+    }
+    let password = contents.lines().next().unwrap_or_default();
+    if password.is_empty() {
+        bail!("Password file {:?} is empty", path);
+    }
+    Ok(password.to_string())
+}
+
+/// The output of a credential command, without its final line break.
+fn run_credential_command(command: &str, host: &str) -> Result<String> {
+    let command = expand_host(command, host)?;
+    debug!("Running credential command: {}", command);
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", &command]).output()
+    } else {
+        Command::new("sh").args(["-c", &command]).output()
+    }
+    .with_context(|| format!("Failed to run credential command {:?}", command))?;
+    if !output.status.success() {
+        bail!(
+            "Credential command {:?} failed ({}): {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("Credential command {:?} printed invalid UTF-8", command))?;
+    let password = stdout.strip_suffix('\n').unwrap_or(&stdout);
+    let password = password.strip_suffix('\r').unwrap_or(password);
+    if password.is_empty() {
+        bail!("Credential command {:?} printed no password", command);
+    }
+    Ok(password.to_string())
+}
+
+/// Replace the host placeholder of a command. Host names go to a shell, so
+/// only the characters of host names and addresses are accepted.
+fn expand_host(command: &str, host: &str) -> Result<String> {
+    if !command.contains(HOST_PLACEHOLDER) {
+        return Ok(command.to_string());
+    }
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':' | '[' | ']'));
+    if !valid {
+        bail!("Cannot pass host {:?} to a credential command", host);
+    }
+    Ok(command.replace(HOST_PLACEHOLDER, host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("password");
+        std::fs::write(&file, "s3cret pass\nignored\n").unwrap();
+        let from_file = CredentialSources {
+            password_file: Some(file),
+            ..Default::default()
+        };
+        assert_eq!(
+            from_file
+                .resolve(None, "XCPROBE_TEST_UNSET", "web01")
+                .unwrap(),
+            Some("s3cret pass".to_string())
+        );
+        assert_eq!(
+            from_file
+                .resolve(Some("given".to_string()), "XCPROBE_TEST_UNSET", "web01")
+                .unwrap(),
+            Some("given".to_string())
+        );
+        assert_eq!(
+            CredentialSources::default()
+                .resolve(None, "XCPROBE_TEST_UNSET", "web01")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_credential_command() {
+        let sources = CredentialSources {
+            credential_command: Some("printf 'pw-for-%s\\n' {host}".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            sources.password("db01.example.com").unwrap(),
+            Some("pw-for-db01.example.com".to_string())
+        );
+        assert!(sources.password("web01; rm -rf /").is_err());
+
+        let failing = CredentialSources {
+            credential_command: Some("echo denied >&2; exit 3".to_string()),
+            ..Default::default()
+        };
+        let error = failing.password("web01").unwrap_err().to_string();
+        assert!(error.contains("denied"));
+    }
+}
@@ -0,0 +1,381 @@
+//! Dry-run command plans.
+//!
+//! Lists every allowlisted command a collection would run against a target,
+//! in execution order, without running it. Commands that depend on what is
+//! discovered on the target are expanded when the service list is known and
+//! otherwise shown as templates with a `<service>` or `<path>` placeholder.
+
+use crate::collector::standard_config_paths;
+use crate::commands::{Allowed, CommandSet, InitSystem};
+use serde::Serialize;
+use xcprobe_common::OsType;
+
+/// Placeholder for a discovered service name.
+pub const SERVICE_PLACEHOLDER: &str = "<service>";
+/// Placeholder for a discovered file path.
+pub const PATH_PLACEHOLDER: &str = "<path>";
+/// Placeholder for the sampled process IDs.
+pub const PID_PLACEHOLDER: &str = "<pid>";
+/// Placeholder for a registry key under `HKLM`.
+pub const KEY_PLACEHOLDER: &str = "<key>";
+/// Placeholder for the host names looked up.
+pub const HOST_PLACEHOLDER: &str = "<host>";
+
+/// Sample service name substituted by [`SERVICE_PLACEHOLDER`].
+const SAMPLE_SERVICE: &str = "__service__";
+/// Sample process ID substituted by [`PID_PLACEHOLDER`].
+const SAMPLE_PID: u32 = 4_000_000_000;
+/// Sample registry key substituted by [`KEY_PLACEHOLDER`].
+const SAMPLE_KEY: &str = "SOFTWARE\\__key__";
+/// Sample host name substituted by [`HOST_PLACEHOLDER`].
+const SAMPLE_HOST: &str = "sample-host.invalid";
+
+/// A command the collector would execute.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedCommand {
+    /// Collection phase running the command.
+    pub phase: String,
+    pub command: String,
+    /// Why or when the command runs, if not unconditionally.
+    pub note: Option<String>,
+}
+
+impl PlannedCommand {
+    fn new(phase: &str, command: impl Into<String>, note: Option<&str>) -> Self {
+        Self {
+            phase: phase.to_string(),
+            command: command.into(),
+            note: note.map(str::to_string),
+        }
+    }
+}
+
+/// Build the command plan for a target. `services` are the service names
+/// listed on the target, if known; per-service commands are templated
+/// otherwise.
+pub fn command_plan(
+    commands: &dyn CommandSet,
+    os_type: OsType,
+    services: Option<&[String]>,
+) -> Vec<PlannedCommand> {
+    let mut plan = Vec::new();
+    let sample_path = if os_type.is_windows() {
+        "C:\\ProgramData\\__path__"
+    } else {
+        "/etc/__path__"
+    };
+
+    // System info
+    plan.push(PlannedCommand::new("system", commands.hostname_cmd(), None));
+    for cmd in [
+        commands.os_version_cmd(),
+        commands.kernel_version_cmd(),
+        commands.architecture_cmd(),
+        Some(commands.privilege_cmd()),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        plan.push(PlannedCommand::new("system", cmd, None));
+    }
+
+    for cmd in commands.process_cmds() {
+        plan.push(PlannedCommand::new("processes", cmd, None));
+    }
+
+    // Services: Linux picks the listing for its init system
+    if let Some(cmd) = commands.init_system_cmd() {
+        plan.push(PlannedCommand::new("services", cmd, None));
+        for init in [InitSystem::SysVinit, InitSystem::OpenRc] {
+            if let Some(cmd) = commands.init_service_list_cmd(init) {
+                plan.push(PlannedCommand::new(
+                    "services",
+                    cmd,
+                    Some(&format!(
+                        "replaces systemctl if the init system is {}",
+                        init
+                    )),
+                ));
+            }
+        }
+        if let Ok(Some(cmd)) = commands.init_script_cmd(SAMPLE_SERVICE) {
+            plan.push(PlannedCommand::new(
+                "services",
+                cmd.replace(SAMPLE_SERVICE, SERVICE_PLACEHOLDER),
+                Some("for each listed service, without systemd"),
+            ));
+        }
+    }
+
+    // Linux and macOS query each listed service for details and unit file
+    // (or launchd plist)
+    plan.push(PlannedCommand::new(
+        "services",
+        commands.service_list_cmd(),
+        None,
+    ));
+    if !os_type.is_windows() {
+        let per_service: [ServiceCommand; 2] = [
+            |c: &dyn CommandSet, name: &str| c.service_show_cmd(name),
+            |c: &dyn CommandSet, name: &str| c.service_cat_cmd(name),
+        ];
+        plan.extend(service_commands(
+            commands,
+            services,
+            "services",
+            &per_service,
+        ));
+    }
+    // Windows lists dependencies at once, and recovery settings per service
+    if let Some(cmd) = commands.service_dependencies_cmd() {
+        plan.push(PlannedCommand::new("services", cmd, None));
+    }
+    if let Ok(Some(cmd)) = commands.service_recovery_cmd(SAMPLE_SERVICE) {
+        plan.push(PlannedCommand::new(
+            "services",
+            cmd.replace(SAMPLE_SERVICE, SERVICE_PLACEHOLDER),
+            Some("for each service installed outside the Windows directory"),
+        ));
+    }
+
+    plan.push(PlannedCommand::new("ports", commands.ports_cmd(), None));
+    if let Some(cmd) = commands.port_owner_cmd() {
+        plan.push(PlannedCommand::new(
+            "ports",
+            cmd,
+            Some("when the listing gives no process for a listener"),
+        ));
+    }
+    if let Some(cmd) = commands.port_owner_fallback_cmd() {
+        plan.push(PlannedCommand::new(
+            "ports",
+            cmd,
+            Some("for the listeners still without a process"),
+        ));
+    }
+
+    if let Some(cmd) = commands.process_cwd_cmd(&[SAMPLE_PID]) {
+        plan.push(PlannedCommand::new(
+            "working_directories",
+            cmd.replace(&SAMPLE_PID.to_string(), PID_PLACEHOLDER),
+            Some("for service main processes, listeners and the largest processes"),
+        ));
+    }
+    if let Some(cmd) = commands.process_cwd_fallback_cmd(&[SAMPLE_PID]) {
+        plan.push(PlannedCommand::new(
+            "working_directories",
+            cmd.replace(&SAMPLE_PID.to_string(), PID_PLACEHOLDER),
+            Some("for the processes still without a working directory"),
+        ));
+    }
+
+    if let Some(cmd) = commands.process_cmdline_cmd(&[SAMPLE_PID]) {
+        plan.push(PlannedCommand::new(
+            "command_lines",
+            cmd.replace(&SAMPLE_PID.to_string(), PID_PLACEHOLDER),
+            Some("for service main processes, listeners and the largest processes"),
+        ));
+    }
+
+    for cmd in commands.package_cmds() {
+        plan.push(PlannedCommand::new(
+            "packages",
+            cmd,
+            Some("until one package manager succeeds"),
+        ));
+    }
+
+    for (language, cmd) in commands.runtime_version_cmds() {
+        plan.push(PlannedCommand::new(
+            "runtimes",
+            cmd,
+            Some(&format!("{} version probe", language)),
+        ));
+    }
+    if let Ok(Some(cmd)) = commands.binary_info_cmd(sample_path) {
+        plan.push(PlannedCommand::new(
+            "runtimes",
+            cmd.replace(sample_path, PATH_PLACEHOLDER),
+            Some("for each executable of a discovered service or process"),
+        ));
+    }
+
+    for cmd in commands.scheduled_task_cmds() {
+        plan.push(PlannedCommand::new("scheduled_tasks", cmd, None));
+    }
+
+    if let Some(cmd) = commands.open_files_cmd(&[SAMPLE_PID]) {
+        plan.push(PlannedCommand::new(
+            "open_files",
+            cmd.replace(&SAMPLE_PID.to_string(), PID_PLACEHOLDER),
+            Some("for service main processes, listeners and the largest processes"),
+        ));
+    }
+    if let Some(cmd) = commands.open_files_fallback_cmd(&[SAMPLE_PID]) {
+        plan.push(PlannedCommand::new(
+            "open_files",
+            cmd.replace(&SAMPLE_PID.to_string(), PID_PLACEHOLDER),
+            Some("for the processes whose open files could not be listed"),
+        ));
+    }
+    if let Ok(Some(cmd)) = commands.read_file_cmd(sample_path) {
+        plan.push(PlannedCommand::new(
+            "open_files",
+            cmd.replace(sample_path, PATH_PLACEHOLDER),
+            Some("for each configuration file held open"),
+        ));
+    }
+
+    for path in standard_config_paths(os_type) {
+        if let Ok(Some(cmd)) = commands.read_file_cmd(path) {
+            plan.push(PlannedCommand::new("config_files", cmd, None));
+        }
+    }
+    if let Ok(Some(cmd)) = commands.read_file_cmd(sample_path) {
+        plan.push(PlannedCommand::new(
+            "config_files",
+            cmd.replace(sample_path, PATH_PLACEHOLDER),
+            Some("for each discovered working directory and environment file"),
+        ));
+    }
+    if let Some(cmd) = commands.file_stat_cmd(&[sample_path]) {
+        plan.push(PlannedCommand::new(
+            "config_files",
+            cmd.replace(sample_path, PATH_PLACEHOLDER),
+            Some("for the collected files and working directories, 100 paths at a time"),
+        ));
+    }
+
+    for source in ["dpkg", "rpm"] {
+        if let Some(cmd) = commands.package_owner_cmd(source, &[sample_path]) {
+            plan.push(PlannedCommand::new(
+                "package_owners",
+                cmd.replace(sample_path, PATH_PLACEHOLDER),
+                Some(&format!(
+                    "when {} listed the packages, for service and process executables and the collected files, 100 paths at a time",
+                    source
+                )),
+            ));
+        }
+    }
+
+    if let Some(cmd) = commands.hosts_file_cmd() {
+        plan.push(PlannedCommand::new("name_resolution", cmd, None));
+    }
+    if let Some(cmd) = commands.resolver_config_cmd() {
+        plan.push(PlannedCommand::new("name_resolution", cmd, None));
+    }
+    if let Some(cmd) = commands.host_lookup_cmd(&[SAMPLE_HOST]) {
+        plan.push(PlannedCommand::new(
+            "name_resolution",
+            cmd.replace(&format!("'{}'", SAMPLE_HOST), HOST_PLACEHOLDER),
+            Some("with --resolve-hosts, for the host names of configuration files and service environments"),
+        ));
+    }
+
+    if let Ok(Some(cmd)) = commands.registry_key_cmd(SAMPLE_KEY) {
+        plan.push(PlannedCommand::new(
+            "registry",
+            cmd.replace(SAMPLE_KEY, KEY_PLACEHOLDER),
+            Some("for the service key and SOFTWARE product key of each service installed outside the Windows directory"),
+        ));
+    }
+
+    if os_type.is_linux() {
+        let journal: [ServiceCommand; 1] =
+            [|c: &dyn CommandSet, name: &str| c.journal_cmd(name, "1 hour ago")];
+        plan.extend(service_commands(commands, services, "logs", &journal));
+    }
+
+    if let Some(cmd) = commands.resource_sample_cmd(&[SAMPLE_PID]) {
+        plan.push(PlannedCommand::new(
+            "resources",
+            cmd.replace(&SAMPLE_PID.to_string(), PID_PLACEHOLDER),
+            Some("every sample interval, with --sample-duration"),
+        ));
+    }
+
+    plan
+}
+
+type ServiceCommand = fn(&dyn CommandSet, &str) -> Allowed;
+
+/// Expand per-service commands for each known service, or as templates.
+fn service_commands(
+    commands: &dyn CommandSet,
+    services: Option<&[String]>,
+    phase: &str,
+    builders: &[ServiceCommand],
+) -> Vec<PlannedCommand> {
+    match services {
+        Some(services) => services
+            .iter()
+            .flat_map(|name| {
+                builders
+                    .iter()
+                    .filter_map(move |b| b(commands, name).ok().flatten())
+            })
+            .map(|cmd| PlannedCommand::new(phase, cmd, None))
+            .collect(),
+        None => builders
+            .iter()
+            .filter_map(|b| b(commands, SAMPLE_SERVICE).ok().flatten())
+            .map(|cmd| {
+                PlannedCommand::new(
+                    phase,
+                    cmd.replace(SAMPLE_SERVICE, SERVICE_PLACEHOLDER),
+                    Some("for each listed service"),
+                )
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{LinuxCommands, WindowsCommands};
+
+    #[test]
+    fn test_offline_plan_uses_templates() {
+        let plan = command_plan(&LinuxCommands::new(), OsType::Linux, None);
+
+        assert_eq!(plan[0].phase, "system");
+        assert!(plan
+            .iter()
+            .any(|c| c.command == "systemctl show <service> --no-pager"));
+        assert!(plan
+            .iter()
+            .any(|c| c.phase == "config_files" && c.command.starts_with("cat '<path>'")));
+        assert!(plan
+            .iter()
+            .any(|c| c.command == "file -L '<path>' 2>/dev/null"));
+        assert!(!plan.iter().any(|c| c.command.contains("__")));
+    }
+
+    #[test]
+    fn test_plan_expands_known_services() {
+        let services = vec!["nginx.service".to_string(), "bad;name".to_string()];
+        let plan = command_plan(&LinuxCommands::new(), OsType::Linux, Some(&services));
+
+        let service_cmds: Vec<_> = plan
+            .iter()
+            .filter(|c| c.command.contains("nginx.service"))
+            .map(|c| c.command.as_str())
+            .collect();
+        assert_eq!(service_cmds.len(), 3); // show, cat, journal
+        assert!(!plan.iter().any(|c| c.command.contains("bad;name")));
+
+        let windows = command_plan(&WindowsCommands::new(), OsType::Windows, None);
+        assert!(!windows.iter().any(|c| c.phase == "logs"));
+        assert!(windows
+            .iter()
+            .any(|c| c.command.starts_with("Get-Content -Path '<path>'")));
+        assert!(windows
+            .iter()
+            .any(|c| c.command == "sc.exe qfailure \"<service>\""));
+        assert!(windows
+            .iter()
+            .any(|c| c.phase == "registry" && c.command.starts_with("$k = 'HKLM:\\<key>';")));
+    }
+}
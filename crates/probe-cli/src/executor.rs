@@ -1,15 +1,27 @@
 //! Command executors for different connection types.
 
+use crate::kerberos::Kerberos;
+use crate::ntlm;
+use crate::winrm::{self, Account, ReceiveOutput, WinRmAuth};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use ssh2::Session;
-use std::io::Read;
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD};
+use base64::Engine;
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, WWW_AUTHENTICATE};
+use sha2::{Digest, Sha256};
+use ssh2::{CheckResult, HashType, HostKeyType, KnownHostFileKind, Session};
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::path::Path;
-use std::process::Command;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::time::timeout;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
+use xcprobe_common::{Error as XcError, ErrorCategory};
 
 /// Trait for command execution.
 #[async_trait]
@@ -20,17 +32,37 @@ pub trait Executor: Send + Sync {
     /// Check if the connection is still alive.
     #[allow(dead_code)]
     fn is_connected(&self) -> bool;
+
+    /// Host key presented by the remote server, if the transport has one.
+    fn host_key(&self) -> Option<&HostKeyInfo> {
+        None
+    }
 }
 
-/// Per-command timeout.
-const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// Default per-command timeout.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Error returned when a command exceeds its time limit.
+#[derive(Debug, thiserror::Error)]
+#[error("Command timed out after {}s", .0.as_secs())]
+pub struct CommandTimeout(pub Duration);
 
 /// Local executor for ephemeral testing.
-pub struct LocalExecutor;
+pub struct LocalExecutor {
+    command_timeout: Duration,
+}
 
 impl LocalExecutor {
     pub fn new() -> Self {
-        Self
+        Self {
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+
+    /// Set the per-command timeout.
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.command_timeout = command_timeout;
+        self
     }
 }
 
@@ -45,28 +77,32 @@ impl Executor for LocalExecutor {
     async fn execute(&self, command: &str) -> Result<(Option<i32>, String, String)> {
         debug!("Local exec: {}", command);
 
-        let cmd = command.to_string();
-        let result = timeout(
-            COMMAND_TIMEOUT,
-            tokio::task::spawn_blocking(move || {
-                if cfg!(target_os = "windows") {
-                    Command::new("powershell")
-                        .args(["-NoProfile", "-NonInteractive", "-Command", &cmd])
-                        .output()
-                } else {
-                    Command::new("sh").args(["-c", &cmd]).output()
-                }
-            }),
-        )
-        .await;
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut cmd = tokio::process::Command::new("powershell");
+            cmd.args(["-NoProfile", "-NonInteractive", "-Command", command]);
+            cmd
+        } else {
+            let mut cmd = tokio::process::Command::new("sh");
+            cmd.args(["-c", command]);
+            cmd
+        };
+        // Dropping the timed-out future kills the child
+        let child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to execute command")?;
 
-        let output = match result {
-            Ok(join_result) => join_result
-                .context("Command task panicked")?
-                .context("Failed to execute command")?,
+        let output = match timeout(self.command_timeout, child.wait_with_output()).await {
+            Ok(output) => output.context("Failed to execute command")?,
             Err(_) => {
-                warn!("Command timed out after {:?}: {}", COMMAND_TIMEOUT, command);
-                anyhow::bail!("Command timed out after {:?}: {}", COMMAND_TIMEOUT, command);
+                warn!(
+                    "Command timed out after {:?}: {}",
+                    self.command_timeout, command
+                );
+                return Err(CommandTimeout(self.command_timeout).into());
             }
         };
 
@@ -82,9 +118,164 @@ impl Executor for LocalExecutor {
     }
 }
 
+/// How SSH host keys are checked against known_hosts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyPolicy {
+    /// Only connect to hosts whose key is already known.
+    Strict,
+    /// Trust on first use: record unknown keys, reject changed ones.
+    AcceptNew,
+    /// Skip verification (the fingerprint is still recorded).
+    Off,
+}
+
+impl FromStr for HostKeyPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "yes" | "strict" => Ok(HostKeyPolicy::Strict),
+            "accept-new" | "tofu" => Ok(HostKeyPolicy::AcceptNew),
+            "no" | "off" => Ok(HostKeyPolicy::Off),
+            _ => Err(anyhow::anyhow!("Invalid host key checking mode: {}", s)),
+        }
+    }
+}
+
+/// SSH host key verification settings.
+#[derive(Debug, Clone)]
+pub struct HostKeyCheck {
+    pub policy: HostKeyPolicy,
+    /// known_hosts file; defaults to `~/.ssh/known_hosts`.
+    pub known_hosts: Option<PathBuf>,
+}
+
+impl Default for HostKeyCheck {
+    fn default() -> Self {
+        Self {
+            policy: HostKeyPolicy::AcceptNew,
+            known_hosts: None,
+        }
+    }
+}
+
+impl HostKeyCheck {
+    fn known_hosts_path(&self) -> Option<PathBuf> {
+        self.known_hosts.clone().or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh/known_hosts"))
+        })
+    }
+}
+
+/// Verification of the certificate of a WinRM HTTPS listener.
+///
+/// By default the certificate must chain to a built-in root. WinRM
+/// listeners usually present a certificate of an internal CA, or a
+/// self-signed one: trust the CA with `ca_cert`, or pin the certificate
+/// itself with `cert_fingerprint`.
+#[derive(Debug, Clone, Default)]
+pub struct WinRmTls {
+    /// PEM file of CA certificates, trusted in addition to the built-in roots.
+    pub ca_cert: Option<PathBuf>,
+    /// SHA-256 fingerprint of the server certificate, in hex (colons
+    /// allowed). The certificate is then accepted whoever issued it.
+    pub cert_fingerprint: Option<String>,
+    /// Accept any certificate.
+    pub insecure: bool,
+}
+
+impl WinRmTls {
+    /// Configure certificate verification on an HTTP client.
+    fn configure(&self, builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder> {
+        if self.insecure {
+            warn!("WinRM server certificate is not verified");
+            return Ok(builder.danger_accept_invalid_certs(true));
+        }
+        if let Some(ref fingerprint) = self.cert_fingerprint {
+            let verifier = PinnedCertVerifier {
+                fingerprint: parse_cert_fingerprint(fingerprint)?,
+            };
+            let config = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(verifier))
+                .with_no_client_auth();
+            return Ok(builder.use_preconfigured_tls(config));
+        }
+        if let Some(ref path) = self.ca_cert {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA certificate {:?}", path))?;
+            let certificate = reqwest::Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid CA certificate {:?}", path))?;
+            return Ok(builder.add_root_certificate(certificate));
+        }
+        Ok(builder)
+    }
+}
+
+/// SHA-256 certificate fingerprint from hex, with or without colons.
+pub fn parse_cert_fingerprint(fingerprint: &str) -> Result<[u8; 32]> {
+    let digits: String = fingerprint
+        .trim()
+        .trim_start_matches("SHA256:")
+        .chars()
+        .filter(|c| *c != ':')
+        .collect();
+    hex::decode(&digits)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| {
+            format!(
+                "Invalid certificate fingerprint {:?}: expected 64 hex digits",
+                fingerprint
+            )
+        })
+}
+
+/// Accepts the server certificate whose SHA-256 fingerprint was pinned.
+struct PinnedCertVerifier {
+    fingerprint: [u8; 32],
+}
+
+impl rustls::client::ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let fingerprint: [u8; 32] = Sha256::digest(&end_entity.0).into();
+        if fingerprint == self.fingerprint {
+            Ok(rustls::client::ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "certificate fingerprint {} does not match the pinned {}",
+                hex::encode(fingerprint),
+                hex::encode(self.fingerprint)
+            )))
+        }
+    }
+}
+
+/// Host key presented by an SSH server and the outcome of its verification.
+#[derive(Debug, Clone)]
+pub struct HostKeyInfo {
+    /// Host as written in known_hosts (`host` or `[host]:port`).
+    pub host: String,
+    pub key_type: String,
+    /// OpenSSH-style fingerprint (`SHA256:...`).
+    pub fingerprint: String,
+    /// `verified`, `added` (first use) or `unverified`.
+    pub status: String,
+}
+
 /// SSH executor for remote Linux systems.
 pub struct SshExecutor {
     session: Session,
+    host_key: HostKeyInfo,
+    command_timeout: Duration,
 }
 
 impl SshExecutor {
@@ -95,13 +286,19 @@ impl SshExecutor {
         user: Option<&str>,
         key_path: Option<&Path>,
         password: Option<&str>,
+        host_key_check: &HostKeyCheck,
     ) -> Result<Self> {
         let tcp = TcpStream::connect(format!("{}:{}", host, port))
-            .context("Failed to connect to SSH host")?;
+            .map_err(|e| XcError::SshConnection(format!("{}:{}: {}", host, port, e)))?;
 
         let mut session = Session::new().context("Failed to create SSH session")?;
         session.set_tcp_stream(tcp);
-        session.handshake().context("SSH handshake failed")?;
+        session.handshake().map_err(|e| {
+            XcError::SshConnection(format!("{}:{}: handshake failed: {}", host, port, e))
+        })?;
+
+        // Verify the server before sending any credentials
+        let host_key = verify_host_key(&session, host, port, host_key_check)?;
 
         let username = user.unwrap_or("root");
 
@@ -109,11 +306,19 @@ impl SshExecutor {
         if let Some(key) = key_path {
             session
                 .userauth_pubkey_file(username, None, key, None)
-                .context("SSH key authentication failed")?;
+                .map_err(|e| {
+                    XcError::SshAuth(format!(
+                        "{}@{} with key {}: {}",
+                        username,
+                        host,
+                        key.display(),
+                        e
+                    ))
+                })?;
         } else if let Some(pwd) = password {
-            session
-                .userauth_password(username, pwd)
-                .context("SSH password authentication failed")?;
+            session.userauth_password(username, pwd).map_err(|e| {
+                XcError::SshAuth(format!("{}@{} with password: {}", username, host, e))
+            })?;
         } else {
             // Try agent
             let mut agent = session.agent().context("Failed to connect to SSH agent")?;
@@ -133,18 +338,140 @@ impl SshExecutor {
             }
 
             if !authenticated {
-                anyhow::bail!("No valid SSH authentication method available");
+                return Err(XcError::SshAuth(format!(
+                    "{}@{}: no SSH agent identity accepted",
+                    username, host
+                ))
+                .into());
             }
         }
 
         if !session.authenticated() {
-            anyhow::bail!("SSH authentication failed");
+            return Err(XcError::SshAuth(format!("{}@{}", username, host)).into());
         }
 
-        Ok(Self { session })
+        let executor = Self {
+            session,
+            host_key,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+        };
+        Ok(executor.with_command_timeout(DEFAULT_COMMAND_TIMEOUT))
+    }
+
+    /// Set the per-command timeout. Blocking libssh2 calls are bounded by it
+    /// too, so a silent remote command cannot hang the session.
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.session
+            .set_timeout(command_timeout.as_millis().min(u32::MAX as u128) as u32);
+        self.command_timeout = command_timeout;
+        self
     }
 }
 
+/// Check the server host key against known_hosts according to `check`.
+fn verify_host_key(
+    session: &Session,
+    host: &str,
+    port: u16,
+    check: &HostKeyCheck,
+) -> Result<HostKeyInfo> {
+    let (key, key_type) = session
+        .host_key()
+        .context("SSH server presented no host key")?;
+    let fingerprint = session
+        .host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", BASE64_NO_PAD.encode(hash)))
+        .context("Failed to compute SSH host key fingerprint")?;
+    let key_type_name = host_key_type_name(key_type);
+    let entry = known_hosts_entry(host, port);
+    let path = check.known_hosts_path();
+
+    let mut known_hosts = session
+        .known_hosts()
+        .context("Failed to initialize known_hosts")?;
+    if let Some(path) = path.as_deref().filter(|p| p.exists()) {
+        known_hosts
+            .read_file(path, KnownHostFileKind::OpenSSH)
+            .with_context(|| format!("Failed to read known_hosts {}", path.display()))?;
+    }
+
+    let status = match (known_hosts.check_port(host, port, key), check.policy) {
+        (CheckResult::Match, _) => "verified",
+        (_, HostKeyPolicy::Off) => {
+            warn!(
+                "Host key for {} not verified ({} {})",
+                entry, key_type_name, fingerprint
+            );
+            "unverified"
+        }
+        (CheckResult::Mismatch, _) => anyhow::bail!(
+            "Host key for {} does not match known_hosts ({} {}); possible man-in-the-middle",
+            entry,
+            key_type_name,
+            fingerprint
+        ),
+        (_, HostKeyPolicy::Strict) => anyhow::bail!(
+            "Host key for {} ({} {}) is not in known_hosts",
+            entry,
+            key_type_name,
+            fingerprint
+        ),
+        (_, HostKeyPolicy::AcceptNew) => {
+            if let Some(path) = &path {
+                append_known_host(path, &entry, key_type_name, key)?;
+            }
+            info!(
+                "Added host key for {} to known_hosts ({} {})",
+                entry, key_type_name, fingerprint
+            );
+            "added"
+        }
+    };
+
+    Ok(HostKeyInfo {
+        host: entry,
+        key_type: key_type_name.to_string(),
+        fingerprint,
+        status: status.to_string(),
+    })
+}
+
+/// Host name as written in known_hosts: non-default ports use `[host]:port`.
+fn known_hosts_entry(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn host_key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+/// Append a host key line without rewriting existing known_hosts entries.
+fn append_known_host(path: &Path, entry: &str, key_type: &str, key: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open known_hosts {}", path.display()))?;
+    writeln!(file, "{} {} {}", entry, key_type, BASE64.encode(key))
+        .with_context(|| format!("Failed to write known_hosts {}", path.display()))?;
+    Ok(())
+}
+
 #[async_trait]
 impl Executor for SshExecutor {
     async fn execute(&self, command: &str) -> Result<(Option<i32>, String, String)> {
@@ -158,16 +485,27 @@ impl Executor for SshExecutor {
             .exec(command)
             .context("Failed to execute SSH command")?;
 
-        let mut stdout = String::new();
-        channel
-            .read_to_string(&mut stdout)
-            .context("Failed to read stdout")?;
-
-        let mut stderr = String::new();
-        channel
-            .stderr()
-            .read_to_string(&mut stderr)
-            .context("Failed to read stderr")?;
+        let deadline = Instant::now() + self.command_timeout;
+        let output = read_until(&mut channel, deadline)
+            .context("Failed to read stdout")
+            .and_then(|stdout| {
+                read_until(&mut channel.stderr(), deadline)
+                    .context("Failed to read stderr")
+                    .map(|stderr| (stdout, stderr))
+            });
+        let (stdout, stderr) = match output {
+            Ok(output) => output,
+            Err(e) if is_timeout(&e) => {
+                warn!(
+                    "Command timed out after {:?}: {}",
+                    self.command_timeout, command
+                );
+                // Cancel the remote command
+                channel.close().ok();
+                return Err(CommandTimeout(self.command_timeout).into());
+            }
+            Err(e) => return Err(e),
+        };
 
         channel.wait_close().ok();
         let exit_code = channel.exit_status().ok();
@@ -178,112 +516,612 @@ impl Executor for SshExecutor {
     fn is_connected(&self) -> bool {
         self.session.authenticated()
     }
+
+    fn host_key(&self) -> Option<&HostKeyInfo> {
+        Some(&self.host_key)
+    }
+}
+
+/// Read a stream to the end, failing with `TimedOut` past `deadline`.
+fn read_until<R: Read>(reader: &mut R, deadline: Instant) -> std::io::Result<String> {
+    let mut output = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        if Instant::now() >= deadline {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => output.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+}
+
+/// SSH client used for remote Unix targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SshTransport {
+    /// Built-in libssh2 client.
+    #[default]
+    Libssh2,
+    /// System `ssh` binary, honoring `~/.ssh/config`.
+    OpenSsh,
+}
+
+impl FromStr for SshTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "libssh2" | "ssh2" => Ok(SshTransport::Libssh2),
+            "openssh" => Ok(SshTransport::OpenSsh),
+            _ => Err(anyhow::anyhow!("Invalid SSH transport: {}", s)),
+        }
+    }
+}
+
+/// Exit status of the `ssh` client itself failing (connection, auth).
+const OPENSSH_ERROR_EXIT: i32 = 255;
+
+/// SSH executor running commands through the system `ssh` binary.
+///
+/// Authentication, proxies (`ProxyCommand`, `ProxyJump`), GSSAPI and FIDO
+/// keys are left to OpenSSH and `~/.ssh/config`; only the options given on
+/// the command line are passed. On Unix, a control master keeps a single
+/// connection open for all commands.
+pub struct OpenSshExecutor {
+    destination: String,
+    options: Vec<String>,
+    /// Directory of the control master socket, removed on drop.
+    control_dir: Option<PathBuf>,
+    command_timeout: Duration,
+}
+
+impl OpenSshExecutor {
+    /// Connect to a remote host with the `ssh` binary. Host keys are checked
+    /// by OpenSSH according to `host_key_check`.
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        user: Option<&str>,
+        key_path: Option<&Path>,
+        password: Option<&str>,
+        host_key_check: &HostKeyCheck,
+    ) -> Result<Self> {
+        if password.is_some() {
+            anyhow::bail!(
+                "SSH password authentication is not supported with the openssh transport; use a key or the agent"
+            );
+        }
+
+        let control_dir = if cfg!(unix) {
+            let dir = std::env::temp_dir().join(format!("xcprobe-ssh-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).context("Failed to create SSH control directory")?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+            }
+            Some(dir)
+        } else {
+            None
+        };
+
+        let executor = Self {
+            destination: host.to_string(),
+            options: openssh_options(port, user, key_path, host_key_check, control_dir.as_deref()),
+            control_dir,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+        };
+
+        // Fail early on connection and authentication errors
+        if let Err(e) = executor.execute("true").await {
+            let message = format!("{}:{}: {:#}", host, port, e);
+            return Err(if message.contains("Permission denied") {
+                XcError::SshAuth(message)
+            } else {
+                XcError::SshConnection(message)
+            }
+            .into());
+        }
+        Ok(executor)
+    }
+
+    /// Set the per-command timeout.
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.command_timeout = command_timeout;
+        self
+    }
+}
+
+/// Command line options of the `ssh` client. Settings left at their
+/// defaults are not passed, so `~/.ssh/config` applies.
+fn openssh_options(
+    port: u16,
+    user: Option<&str>,
+    key_path: Option<&Path>,
+    host_key_check: &HostKeyCheck,
+    control_dir: Option<&Path>,
+) -> Vec<String> {
+    let strict = match host_key_check.policy {
+        HostKeyPolicy::Strict => "yes",
+        HostKeyPolicy::AcceptNew => "accept-new",
+        HostKeyPolicy::Off => "no",
+    };
+    // Never prompt: there is no terminal to answer on
+    let mut options = vec![
+        "-T".to_string(),
+        "-o".to_string(),
+        "BatchMode=yes".to_string(),
+        "-o".to_string(),
+        format!("StrictHostKeyChecking={}", strict),
+    ];
+    if port != 22 {
+        options.extend(["-p".to_string(), port.to_string()]);
+    }
+    if let Some(user) = user {
+        options.extend(["-l".to_string(), user.to_string()]);
+    }
+    if let Some(key) = key_path {
+        options.extend(["-i".to_string(), key.display().to_string()]);
+    }
+    if let Some(known_hosts) = &host_key_check.known_hosts {
+        options.extend([
+            "-o".to_string(),
+            format!("UserKnownHostsFile={}", known_hosts.display()),
+        ]);
+    }
+    if let Some(dir) = control_dir {
+        options.extend([
+            "-o".to_string(),
+            "ControlMaster=auto".to_string(),
+            "-o".to_string(),
+            format!("ControlPath={}", dir.join("%C").display()),
+            "-o".to_string(),
+            "ControlPersist=60".to_string(),
+        ]);
+    }
+    options
+}
+
+#[async_trait]
+impl Executor for OpenSshExecutor {
+    async fn execute(&self, command: &str) -> Result<(Option<i32>, String, String)> {
+        debug!("OpenSSH exec: {}", command);
+
+        // Dropping the timed-out future kills the client, closing the channel
+        let child = tokio::process::Command::new("ssh")
+            .args(&self.options)
+            .arg("--")
+            .arg(&self.destination)
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to run ssh")?;
+
+        let output = match timeout(self.command_timeout, child.wait_with_output()).await {
+            Ok(output) => output.context("Failed to run ssh")?,
+            Err(_) => {
+                warn!(
+                    "Command timed out after {:?}: {}",
+                    self.command_timeout, command
+                );
+                return Err(CommandTimeout(self.command_timeout).into());
+            }
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let exit_code = output.status.code();
+        if exit_code == Some(OPENSSH_ERROR_EXIT) {
+            anyhow::bail!("ssh failed: {}", stderr.trim());
+        }
+
+        Ok((exit_code, stdout, stderr))
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for OpenSshExecutor {
+    fn drop(&mut self) {
+        if let Some(dir) = self.control_dir.take() {
+            // Stop the control master, then remove its socket directory
+            std::process::Command::new("ssh")
+                .args(&self.options)
+                .args(["-O", "exit", "--", &self.destination])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .ok();
+            std::fs::remove_dir_all(dir).ok();
+        }
+    }
 }
 
 /// WinRM executor for remote Windows systems.
+///
+/// Each command runs in its own remote shell (Create, Command, Receive,
+/// Signal, Delete) so a failed command cannot leave state behind.
 pub struct WinRmExecutor {
-    endpoint: String,
-    username: String,
-    password: String,
-    client: reqwest::Client,
+    transport: Arc<WinRmTransport>,
+    command_timeout: Duration,
 }
 
+/// HTTP timeout of a single WinRM request, above the server-side operation
+/// timeout after which a Receive without output returns.
+const WINRM_REQUEST_TIMEOUT: Duration = Duration::from_secs(90);
+
 impl WinRmExecutor {
     /// Connect to a remote host via WinRM.
     pub async fn connect(
         host: &str,
         port: u16,
         https: bool,
+        auth: WinRmAuth,
+        tls: &WinRmTls,
         user: Option<&str>,
         password: Option<&str>,
     ) -> Result<Self> {
         let scheme = if https { "https" } else { "http" };
         let endpoint = format!("{}://{}:{}/wsman", scheme, host, port);
 
-        let username = user.unwrap_or("Administrator").to_string();
-        let password = password.unwrap_or("").to_string();
+        // NTLM authenticates the connection, so keep a single one alive
+        let mut builder = reqwest::Client::builder()
+            .pool_max_idle_per_host(1)
+            .timeout(WINRM_REQUEST_TIMEOUT);
+        if https {
+            builder = tls.configure(builder)?;
+        }
+        let client = builder.build().context("Failed to create HTTP client")?;
+
+        let kerberos = match auth {
+            WinRmAuth::Negotiate => negotiate_kerberos(host, user, password),
+            WinRmAuth::Basic | WinRmAuth::Ntlm => None,
+        };
 
-        let client = reqwest::Client::builder()
-            .danger_accept_invalid_certs(true) // For testing only
-            .build()
-            .context("Failed to create HTTP client")?;
+        let executor = Self {
+            transport: Arc::new(WinRmTransport {
+                endpoint,
+                host: host.to_string(),
+                auth,
+                username: user.unwrap_or("Administrator").to_string(),
+                password: password.unwrap_or("").to_string(),
+                kerberos,
+                client,
+                ntlm_authenticated: AtomicBool::new(false),
+            }),
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+        };
 
         // Test connection
-        let test_result =
-            Self::execute_winrm_command(&client, &endpoint, &username, &password, "hostname").await;
-        if test_result.is_err() {
-            warn!("WinRM connection test failed, continuing anyway");
-        }
+        executor.run_command("hostname").await.map_err(|e| {
+            let endpoint = &executor.transport.endpoint;
+            if ErrorCategory::of(&e) == ErrorCategory::Auth {
+                e.context(format!("WinRM connection to {} failed", endpoint))
+            } else {
+                XcError::WinRmConnection(format!("{}: {:#}", endpoint, e)).into()
+            }
+        })?;
 
-        Ok(Self {
-            endpoint,
-            username,
-            password,
-            client,
-        })
+        Ok(executor)
     }
 
-    async fn execute_winrm_command(
-        client: &reqwest::Client,
-        endpoint: &str,
-        username: &str,
-        password: &str,
-        command: &str,
-    ) -> Result<(Option<i32>, String, String)> {
-        // Encode command as UTF-16LE base64 for PowerShell
-        let utf16_bytes: Vec<u8> = command
-            .encode_utf16()
-            .flat_map(|c| c.to_le_bytes())
-            .collect();
-        let encoded_command = base64_encode(&utf16_bytes);
-
-        // WinRM SOAP envelope for PowerShell command execution
-        // This is a simplified implementation - real WinRM requires proper shell management
-        let soap_body = format!(
-            r#"<?xml version="1.0" encoding="UTF-8"?>
-<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope"
-            xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing"
-            xmlns:w="http://schemas.dmtf.org/wbem/wsman/1/wsman.xsd">
-  <s:Header>
-    <a:To>{}</a:To>
-    <w:ResourceURI s:mustUnderstand="true">http://schemas.microsoft.com/wbem/wsman/1/windows/shell/cmd</w:ResourceURI>
-    <a:Action s:mustUnderstand="true">http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Command</a:Action>
-  </s:Header>
-  <s:Body>
-    <rsp:CommandLine xmlns:rsp="http://schemas.microsoft.com/wbem/wsman/1/windows/shell">
-      <rsp:Command>powershell.exe</rsp:Command>
-      <rsp:Arguments>-NoProfile -NonInteractive -EncodedCommand {}</rsp:Arguments>
-    </rsp:CommandLine>
-  </s:Body>
-</s:Envelope>"#,
-            endpoint, encoded_command
-        );
+    /// Set the per-command timeout. A command still running when it expires
+    /// is terminated and its shell deleted.
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.command_timeout = command_timeout;
+        self
+    }
 
-        let response = client
-            .post(endpoint)
-            .basic_auth(username, Some(password))
-            .header("Content-Type", "application/soap+xml;charset=UTF-8")
-            .body(soap_body)
-            .send()
+    /// Run a PowerShell command through a dedicated remote shell.
+    async fn run_command(&self, command: &str) -> Result<(Option<i32>, String, String)> {
+        let transport = &self.transport;
+        let response = transport
+            .call(winrm::create_shell_envelope(&transport.endpoint))
+            .await?;
+        let mut shell = RemoteShell {
+            transport: transport.clone(),
+            shell_id: winrm::parse_shell_id(&response)?,
+            command_id: None,
+            closed: false,
+        };
+
+        // The server keeps answering Receive with operation timeouts while
+        // the command runs, so the command timeout is the only way out
+        let result = match timeout(self.command_timeout, shell.run(command)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!(
+                    "Command timed out after {:?}: {}",
+                    self.command_timeout, command
+                );
+                Err(CommandTimeout(self.command_timeout).into())
+            }
+        };
+
+        shell.close().await;
+        result
+    }
+}
+
+/// Endpoint, credentials and HTTP client of a WinRM host, shared with the
+/// cleanup of shells whose command was abandoned.
+struct WinRmTransport {
+    endpoint: String,
+    host: String,
+    auth: WinRmAuth,
+    username: String,
+    password: String,
+    /// Kerberos credentials of Negotiate auth; NTLM is used without them.
+    kerberos: Option<Kerberos>,
+    client: reqwest::Client,
+    /// Whether the kept-alive connection went through the NTLM handshake.
+    ntlm_authenticated: AtomicBool,
+}
+
+impl WinRmTransport {
+    /// Terminate the command, if one was started, and delete the shell.
+    async fn close_shell(&self, shell_id: &str, command_id: Option<&str>) {
+        if let Some(command_id) = command_id {
+            if let Err(e) = self
+                .call(winrm::signal_envelope(&self.endpoint, shell_id, command_id))
+                .await
+            {
+                debug!("WinRM terminate signal failed: {}", e);
+            }
+        }
+
+        if let Err(e) = self
+            .call(winrm::delete_shell_envelope(&self.endpoint, shell_id))
             .await
-            .context("WinRM request failed")?;
+        {
+            warn!("Failed to delete WinRM shell {}: {}", shell_id, e);
+        }
+    }
 
-        let status = response.status();
+    /// Send a request and fail on SOAP faults.
+    async fn call(&self, envelope: String) -> Result<String> {
+        let (status, response) = self.post(envelope).await?;
+        winrm::check_fault(status, &response)?;
+        Ok(response)
+    }
+
+    /// Send a request with the configured authentication.
+    async fn post(&self, envelope: String) -> Result<(u16, String)> {
+        let response = match self.auth {
+            WinRmAuth::Basic => self
+                .request()
+                .basic_auth(&self.username, Some(&self.password))
+                .body(envelope)
+                .send()
+                .await
+                .context("WinRM request failed")?,
+            WinRmAuth::Negotiate if self.kerberos.is_some() => self.post_kerberos(envelope).await?,
+            WinRmAuth::Ntlm | WinRmAuth::Negotiate => self.post_ntlm(envelope).await?,
+        };
+
+        let status = response.status().as_u16();
+        if status == 401 {
+            return Err(XcError::WinRmAuth(format!("user {}", self.username)).into());
+        }
         let body = response
             .text()
             .await
             .context("Failed to read WinRM response")?;
+        Ok((status, body))
+    }
 
-        if status.is_success() {
-            // Parse SOAP response to extract output
-            // This is a simplified implementation
-            Ok((Some(0), body, String::new()))
-        } else {
-            Ok((
-                Some(1),
-                String::new(),
-                format!("WinRM error: {} - {}", status, body),
+    /// Send the envelope with a Kerberos token for the HTTP service of the
+    /// host; a single leg, as mutual authentication is not requested.
+    async fn post_kerberos(&self, envelope: String) -> Result<reqwest::Response> {
+        let kerberos = self.kerberos.as_ref().context("No Kerberos credentials")?;
+        let token = BASE64.encode(kerberos.token(&self.host)?);
+        self.request()
+            .header(AUTHORIZATION, format!("Negotiate {}", token))
+            .body(envelope)
+            .send()
+            .await
+            .context("WinRM request failed")
+    }
+
+    /// Send the envelope over the connection authenticated by an earlier
+    /// NTLM handshake, as the server authenticates connections rather than
+    /// requests. Without one, or when the server closed it, handshake:
+    /// NEGOTIATE, read the CHALLENGE from the 401, then send the envelope
+    /// with the AUTHENTICATE message on the same connection.
+    async fn post_ntlm(&self, envelope: String) -> Result<reqwest::Response> {
+        if self.ntlm_authenticated.load(Ordering::Acquire) {
+            let response = self
+                .request()
+                .body(envelope.clone())
+                .send()
+                .await
+                .context("WinRM request failed")?;
+            if response.status().as_u16() != 401 {
+                return Ok(response);
+            }
+            debug!("WinRM connection no longer authenticated, authenticating again");
+            self.ntlm_authenticated.store(false, Ordering::Release);
+            response.bytes().await.ok();
+        }
+
+        let negotiate = BASE64.encode(ntlm::negotiate_message());
+        let response = self
+            .request()
+            .header(AUTHORIZATION, format!("Negotiate {}", negotiate))
+            .header(CONTENT_LENGTH, 0)
+            .send()
+            .await
+            .context("WinRM request failed")?;
+
+        if response.status().as_u16() != 401 {
+            anyhow::bail!(
+                "WinRM server did not answer NTLM negotiation (HTTP {})",
+                response.status()
+            );
+        }
+
+        let (scheme, token) = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(|v| {
+                let (scheme, token) = v.split_once(' ')?;
+                (scheme.eq_ignore_ascii_case("Negotiate") || scheme.eq_ignore_ascii_case("NTLM"))
+                    .then(|| (scheme.to_string(), token.trim().to_string()))
+            })
+            .context("WinRM server offered no NTLM challenge")?;
+        // Drain the body so the connection is reused for the next leg
+        response.bytes().await.ok();
+
+        let challenge = ntlm::Challenge::parse(
+            &BASE64
+                .decode(token)
+                .context("Invalid NTLM challenge encoding")?,
+        )?;
+        let credentials = ntlm::NtlmCredentials::new(&self.username, &self.password);
+        let authenticate = ntlm::authenticate_message(&credentials, &challenge)?;
+
+        let response = self
+            .request()
+            .header(
+                AUTHORIZATION,
+                format!("{} {}", scheme, BASE64.encode(authenticate)),
+            )
+            .body(envelope)
+            .send()
+            .await
+            .context("WinRM request failed")?;
+        if response.status().as_u16() != 401 {
+            self.ntlm_authenticated.store(true, Ordering::Release);
+        }
+        Ok(response)
+    }
+
+    fn request(&self) -> reqwest::RequestBuilder {
+        self.client
+            .post(&self.endpoint)
+            .header(CONTENT_TYPE, "application/soap+xml;charset=UTF-8")
+    }
+}
+
+/// A remote shell and the command running in it.
+///
+/// Closing it terminates the command and deletes the shell. When it is
+/// dropped unclosed, because the caller gave up on the command, the cleanup
+/// runs in the background so the shell does not count against the
+/// MaxShellsPerUser quota of the host.
+struct RemoteShell {
+    transport: Arc<WinRmTransport>,
+    shell_id: String,
+    command_id: Option<String>,
+    closed: bool,
+}
+
+impl RemoteShell {
+    /// Start `command` and receive its output until it is done.
+    async fn run(&mut self, command: &str) -> Result<(Option<i32>, String, String)> {
+        let transport = &self.transport;
+        let response = transport
+            .call(winrm::command_envelope(
+                &transport.endpoint,
+                &self.shell_id,
+                command,
             ))
+            .await?;
+        let command_id = winrm::parse_command_id(&response)?;
+        self.command_id = Some(command_id.clone());
+
+        let mut output = ReceiveOutput::default();
+        while !output.done {
+            let (status, response) = transport
+                .post(winrm::receive_envelope(
+                    &transport.endpoint,
+                    &self.shell_id,
+                    &command_id,
+                ))
+                .await?;
+            // Long-running commands time out server-side without output
+            if status >= 400 && winrm::is_operation_timeout(&response) {
+                continue;
+            }
+            winrm::check_fault(status, &response)?;
+            winrm::parse_receive(&response, &mut output)?;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = winrm::decode_clixml(&String::from_utf8_lossy(&output.stderr));
+        Ok((output.exit_code, stdout, stderr))
+    }
+
+    async fn close(mut self) {
+        self.transport
+            .close_shell(&self.shell_id, self.command_id.as_deref())
+            .await;
+        self.closed = true;
+    }
+}
+
+impl Drop for RemoteShell {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            warn!("WinRM shell {} left open", self.shell_id);
+            return;
+        };
+        let transport = self.transport.clone();
+        let shell_id = std::mem::take(&mut self.shell_id);
+        let command_id = self.command_id.take();
+        runtime.spawn(async move {
+            transport
+                .close_shell(&shell_id, command_id.as_deref())
+                .await;
+        });
+    }
+}
+
+/// Kerberos credentials for Negotiate auth with `host`, or `None` to fall
+/// back to NTLM: for local accounts, or when the GSSAPI library, the
+/// credentials or a service ticket for the host cannot be had. Without a
+/// user, the credential cache is used.
+fn negotiate_kerberos(host: &str, user: Option<&str>, password: Option<&str>) -> Option<Kerberos> {
+    let principal = match user {
+        Some(user) => match Account::parse(user).kerberos_principal() {
+            Some(principal) => Some(principal),
+            None => {
+                debug!("{} is a local account, authenticating with NTLM", user);
+                return None;
+            }
+        },
+        None => None,
+    };
+    let kerberos = Kerberos::new(principal.as_deref(), password).and_then(|kerberos| {
+        kerberos.token(host)?;
+        Ok(kerberos)
+    });
+    match kerberos {
+        Ok(kerberos) => Some(kerberos),
+        Err(e) => {
+            warn!("Kerberos unavailable, falling back to NTLM: {:#}", e);
+            None
         }
     }
 }
@@ -292,59 +1130,297 @@ impl WinRmExecutor {
 impl Executor for WinRmExecutor {
     async fn execute(&self, command: &str) -> Result<(Option<i32>, String, String)> {
         debug!("WinRM exec: {}", command);
-        Self::execute_winrm_command(
-            &self.client,
-            &self.endpoint,
-            &self.username,
-            &self.password,
-            command,
-        )
-        .await
+        self.run_command(command).await
     }
 
     fn is_connected(&self) -> bool {
-        true // WinRM is stateless
+        true // Each command opens its own shell
     }
 }
 
-/// Simple base64 encoding (to avoid adding another dependency).
-fn base64_encode(data: &[u8]) -> String {
-    // Just use a simple base64 implementation
-    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
 
-    let mut result = String::new();
-    let mut i = 0;
+    #[test]
+    fn test_host_key_policy_and_entries() {
+        assert_eq!(
+            "yes".parse::<HostKeyPolicy>().unwrap(),
+            HostKeyPolicy::Strict
+        );
+        assert_eq!(
+            "accept-new".parse::<HostKeyPolicy>().unwrap(),
+            HostKeyPolicy::AcceptNew
+        );
+        assert_eq!("no".parse::<HostKeyPolicy>().unwrap(), HostKeyPolicy::Off);
+        assert!("maybe".parse::<HostKeyPolicy>().is_err());
 
-    while i < data.len() {
-        let b0 = data[i] as usize;
-        let b1 = if i + 1 < data.len() {
-            data[i + 1] as usize
-        } else {
-            0
+        assert_eq!(known_hosts_entry("web01", 22), "web01");
+        assert_eq!(known_hosts_entry("web01", 2222), "[web01]:2222");
+    }
+
+    #[test]
+    fn test_pinned_certificate() {
+        use rustls::client::ServerCertVerifier;
+
+        let certificate = rustls::Certificate(b"server certificate".to_vec());
+        let fingerprint = hex::encode(Sha256::digest(&certificate.0));
+        let with_colons = fingerprint
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap().to_uppercase())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(
+            parse_cert_fingerprint(&with_colons).unwrap(),
+            parse_cert_fingerprint(&fingerprint).unwrap()
+        );
+        assert!(parse_cert_fingerprint("ab:cd").is_err());
+
+        let verify = |fingerprint: &str| {
+            let verifier = PinnedCertVerifier {
+                fingerprint: parse_cert_fingerprint(fingerprint).unwrap(),
+            };
+            verifier.verify_server_cert(
+                &certificate,
+                &[],
+                &rustls::ServerName::try_from("win01").unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                std::time::SystemTime::now(),
+            )
         };
-        let b2 = if i + 2 < data.len() {
-            data[i + 2] as usize
-        } else {
-            0
+        assert!(verify(&fingerprint).is_ok());
+        assert!(verify(&"0".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_openssh_options_leave_defaults_to_ssh_config() {
+        let defaults = openssh_options(22, None, None, &HostKeyCheck::default(), None);
+        assert_eq!(
+            defaults,
+            vec![
+                "-T",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "StrictHostKeyChecking=accept-new"
+            ]
+        );
+
+        let check = HostKeyCheck {
+            policy: HostKeyPolicy::Strict,
+            known_hosts: Some(PathBuf::from("/tmp/known_hosts")),
         };
+        let options = openssh_options(
+            2222,
+            Some("deploy"),
+            Some(Path::new("/keys/id_ed25519_sk")),
+            &check,
+            Some(Path::new("/tmp/xcprobe-ssh-1")),
+        )
+        .join(" ");
+        assert!(options.contains("StrictHostKeyChecking=yes"));
+        assert!(options.contains("-p 2222 -l deploy -i /keys/id_ed25519_sk"));
+        assert!(options.contains("UserKnownHostsFile=/tmp/known_hosts"));
+        assert!(options.contains("ControlPath=/tmp/xcprobe-ssh-1/%C"));
 
-        result.push(ALPHABET[b0 >> 2] as char);
-        result.push(ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)] as char);
+        assert_eq!(
+            "openssh".parse::<SshTransport>().unwrap(),
+            SshTransport::OpenSsh
+        );
+        assert!("putty".parse::<SshTransport>().is_err());
+    }
 
-        if i + 1 < data.len() {
-            result.push(ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)] as char);
-        } else {
-            result.push('=');
-        }
+    #[tokio::test]
+    async fn test_local_command_timeout() {
+        let executor = LocalExecutor::new().with_command_timeout(Duration::from_millis(200));
 
-        if i + 2 < data.len() {
-            result.push(ALPHABET[b2 & 0x3f] as char);
-        } else {
-            result.push('=');
-        }
+        let err = executor.execute("sleep 5").await.unwrap_err();
+        assert!(err.is::<CommandTimeout>());
+
+        let (exit_code, stdout, _) = executor.execute("echo ok").await.unwrap();
+        assert_eq!(exit_code, Some(0));
+        assert_eq!(stdout.trim(), "ok");
+    }
+
+    /// Minimal WinRM server recording the action of each request. Receive
+    /// completes the first `completed` commands and never answers for the
+    /// others. Connections are kept alive, and NTLM authenticates one until
+    /// it is closed; the handshakes are counted in `handshakes`.
+    async fn serve_winrm(
+        actions: Arc<std::sync::Mutex<Vec<String>>>,
+        handshakes: Arc<std::sync::atomic::AtomicUsize>,
+        completed: usize,
+    ) -> u16 {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-        i += 3;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let actions = actions.clone();
+                let handshakes = handshakes.clone();
+                tokio::spawn(async move {
+                    let mut authenticated = false;
+                    loop {
+                        let mut request = Vec::new();
+                        let mut buffer = [0u8; 4096];
+                        // Read the head, then the body it announces
+                        let head_end = loop {
+                            let Ok(n) = socket.read(&mut buffer).await else {
+                                return;
+                            };
+                            if n == 0 {
+                                return;
+                            }
+                            request.extend_from_slice(&buffer[..n]);
+                            if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                                break i + 4;
+                            }
+                        };
+                        let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+                        let header = |name: &str| {
+                            head.lines().find_map(|l| {
+                                let (key, value) = l.split_once(": ")?;
+                                key.eq_ignore_ascii_case(name).then(|| value.to_string())
+                            })
+                        };
+                        let length: usize = header("content-length")
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(0);
+                        while request.len() < head_end + length {
+                            let n = socket.read(&mut buffer).await.unwrap();
+                            request.extend_from_slice(&buffer[..n]);
+                        }
+
+                        // NTLM message type of a Negotiate header
+                        let ntlm = header("authorization")
+                            .and_then(|v| v.strip_prefix("Negotiate ").map(str::to_string))
+                            .and_then(|token| BASE64.decode(token).ok())
+                            .map(|message| message[8]);
+                        let basic =
+                            header("authorization").is_some_and(|v| v.starts_with("Basic "));
+                        if ntlm == Some(1) {
+                            handshakes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                            let mut challenge = b"NTLMSSP\0".to_vec();
+                            challenge.extend_from_slice(&2u32.to_le_bytes());
+                            challenge.extend_from_slice(&[0u8; 8]);
+                            challenge.extend_from_slice(&0x0008_8207u32.to_le_bytes());
+                            challenge.extend_from_slice(&[7u8; 8]);
+                            let response = format!(
+                                "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate {}\r\nContent-Length: 0\r\n\r\n",
+                                BASE64.encode(challenge)
+                            );
+                            socket.write_all(response.as_bytes()).await.unwrap();
+                            continue;
+                        }
+                        authenticated |= ntlm == Some(3);
+                        if !authenticated && !basic {
+                            let response = "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate\r\nContent-Length: 0\r\n\r\n";
+                            socket.write_all(response.as_bytes()).await.unwrap();
+                            continue;
+                        }
+
+                        let body = String::from_utf8_lossy(&request[head_end..]).into_owned();
+                        let action = body
+                            .split("</a:Action>")
+                            .next()
+                            .and_then(|s| s.rsplit('/').next())
+                            .unwrap()
+                            .to_string();
+                        let commands = {
+                            let mut actions = actions.lock().unwrap();
+                            actions.push(action.clone());
+                            actions.iter().filter(|a| *a == "Command").count()
+                        };
+
+                        let response = match action.as_str() {
+                            "Create" => "<rsp:ShellId>S1</rsp:ShellId>".to_string(),
+                            "Command" => format!("<rsp:CommandId>C{}</rsp:CommandId>", commands),
+                            "Receive" if commands <= completed => format!(
+                                r#"<rsp:CommandState CommandId="C{}" State="http://schemas.microsoft.com/wbem/wsman/1/windows/shell/CommandState/Done"><rsp:ExitCode>0</rsp:ExitCode></rsp:CommandState>"#,
+                                commands
+                            ),
+                            "Receive" => {
+                                tokio::time::sleep(Duration::from_secs(30)).await;
+                                return;
+                            }
+                            _ => String::new(),
+                        };
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                            response.len(),
+                            response
+                        );
+                        socket.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        port
     }
 
-    result
+    async fn connect_winrm(port: u16, auth: WinRmAuth) -> WinRmExecutor {
+        WinRmExecutor::connect(
+            "127.0.0.1",
+            port,
+            false,
+            auth,
+            &WinRmTls::default(),
+            Some("admin"),
+            Some("secret"),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_winrm_command_timeout_closes_shell() {
+        let actions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let port = serve_winrm(actions.clone(), Default::default(), 1).await;
+        let executor = connect_winrm(port, WinRmAuth::Basic)
+            .await
+            .with_command_timeout(Duration::from_millis(200));
+
+        let err = executor.execute("Start-Sleep 60").await.unwrap_err();
+        assert!(err.is::<CommandTimeout>());
+        // The connection test ran to completion before
+        assert_eq!(
+            actions.lock().unwrap()[5..],
+            ["Create", "Command", "Receive", "Signal", "Delete"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_winrm_ntlm_authenticates_connection_once() {
+        let actions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handshakes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let port = serve_winrm(actions.clone(), handshakes.clone(), 3).await;
+        let executor = connect_winrm(port, WinRmAuth::Ntlm).await;
+
+        executor.execute("hostname").await.unwrap();
+        executor.execute("hostname").await.unwrap();
+
+        assert_eq!(actions.lock().unwrap().len(), 15);
+        assert_eq!(handshakes.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_append_known_host_keeps_existing_entries() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ssh/known_hosts");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "# managed\nold ssh-ed25519 AAAA\n").unwrap();
+
+        append_known_host(&path, "[web01]:2222", "ssh-ed25519", b"key").unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "# managed\nold ssh-ed25519 AAAA\n[web01]:2222 ssh-ed25519 a2V5\n"
+        );
+    }
 }
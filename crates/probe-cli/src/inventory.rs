@@ -0,0 +1,371 @@
+//! Ansible inventory ingestion.
+//!
+//! Reads targets and connection settings from an existing Ansible inventory
+//! in INI or YAML form, so hosts, groups and credentials are not repeated on
+//! the command line. Only connection variables are used: `ansible_host`,
+//! `ansible_port`, `ansible_user`, `ansible_ssh_private_key_file` and
+//! `ansible_connection`. Group variables apply from the outermost group in,
+//! and host variables override them.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Implicit group containing every host.
+const ALL_GROUP: &str = "all";
+
+/// A parsed Ansible inventory.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    groups: BTreeMap<String, Group>,
+    /// Variables of each host, in inventory order of declaration.
+    host_vars: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    hosts: Vec<String>,
+    children: Vec<String>,
+    vars: BTreeMap<String, String>,
+}
+
+/// Connection settings of an inventory host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryHost {
+    /// Inventory host name.
+    pub name: String,
+    /// Address to connect to: `ansible_host`, or the host name.
+    pub address: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub private_key: Option<PathBuf>,
+    /// `ansible_connection` (`ssh`, `winrm`, `local`, ...).
+    pub connection: Option<String>,
+}
+
+impl Inventory {
+    /// Load an inventory file; `.yaml`/`.yml` files are read as YAML, other
+    /// files as INI.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read inventory {}", path.display()))?;
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        if yaml {
+            Self::parse_yaml(&content)
+        } else {
+            Self::parse_ini(&content)
+        }
+        .with_context(|| format!("Invalid inventory {}", path.display()))
+    }
+
+    /// Parse an INI inventory: `[group]` host lines with `key=value`
+    /// variables, `[group:vars]` and `[group:children]` sections.
+    pub fn parse_ini(content: &str) -> Result<Self> {
+        enum Section {
+            Hosts(String),
+            Vars(String),
+            Children(String),
+        }
+
+        let mut inventory = Self::default();
+        let mut section = Section::Hosts("ungrouped".to_string());
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match header.split_once(':') {
+                    Some((group, "vars")) => Section::Vars(group.to_string()),
+                    Some((group, "children")) => Section::Children(group.to_string()),
+                    Some(_) => anyhow::bail!("line {}: unknown section [{}]", number + 1, header),
+                    None => Section::Hosts(header.to_string()),
+                };
+                continue;
+            }
+
+            match &section {
+                Section::Hosts(group) => {
+                    let mut parts = line.split_whitespace();
+                    let host = parts.next().unwrap_or_default();
+                    let vars = parts
+                        .map(|var| {
+                            parse_assignment(var).with_context(|| {
+                                format!("line {}: expected key=value, got {}", number + 1, var)
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    inventory.add_host(group, host, vars);
+                }
+                Section::Vars(group) => {
+                    let (key, value) = parse_assignment(line).with_context(|| {
+                        format!("line {}: expected key=value, got {}", number + 1, line)
+                    })?;
+                    inventory.group(group).vars.insert(key, value);
+                }
+                Section::Children(group) => {
+                    inventory.group(group).children.push(line.to_string());
+                    inventory.group(line);
+                }
+            }
+        }
+        Ok(inventory)
+    }
+
+    /// Parse a YAML inventory: nested groups with `hosts`, `vars` and
+    /// `children` keys, starting from `all`.
+    pub fn parse_yaml(content: &str) -> Result<Self> {
+        let root: serde_yaml::Value = serde_yaml::from_str(content)?;
+        let root = root
+            .as_mapping()
+            .context("expected a mapping of groups at the top level")?;
+        let mut inventory = Self::default();
+        for (name, group) in root {
+            let name = name.as_str().context("group names must be strings")?;
+            inventory.add_yaml_group(name, group)?;
+        }
+        Ok(inventory)
+    }
+
+    fn add_yaml_group(&mut self, name: &str, value: &serde_yaml::Value) -> Result<()> {
+        self.group(name);
+        if let Some(hosts) = value.get("hosts").and_then(|h| h.as_mapping()) {
+            for (host, vars) in hosts {
+                let host = host.as_str().context("host names must be strings")?;
+                let vars = vars
+                    .as_mapping()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(k, v)| Some((k.as_str()?.to_string(), yaml_scalar(v)?)))
+                    .collect();
+                self.add_host(name, host, vars);
+            }
+        }
+        if let Some(vars) = value.get("vars").and_then(|v| v.as_mapping()) {
+            for (key, value) in vars {
+                if let (Some(key), Some(value)) = (key.as_str(), yaml_scalar(value)) {
+                    self.group(name).vars.insert(key.to_string(), value);
+                }
+            }
+        }
+        if let Some(children) = value.get("children").and_then(|c| c.as_mapping()) {
+            for (child, group) in children {
+                let child = child.as_str().context("group names must be strings")?;
+                self.group(name).children.push(child.to_string());
+                self.add_yaml_group(child, group)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn group(&mut self, name: &str) -> &mut Group {
+        self.groups.entry(name.to_string()).or_default()
+    }
+
+    fn add_host(&mut self, group: &str, host: &str, vars: Vec<(String, String)>) {
+        let group = self.group(group);
+        if !group.hosts.iter().any(|h| h == host) {
+            group.hosts.push(host.to_string());
+        }
+        self.host_vars
+            .entry(host.to_string())
+            .or_default()
+            .extend(vars);
+    }
+
+    /// Hosts matching a host or group name (`all` for every host).
+    pub fn resolve(&self, pattern: &str) -> Result<Vec<InventoryHost>> {
+        let names: Vec<String> = if self.host_vars.contains_key(pattern) {
+            vec![pattern.to_string()]
+        } else if pattern == ALL_GROUP {
+            self.host_vars.keys().cloned().collect()
+        } else if self.groups.contains_key(pattern) {
+            let mut hosts = BTreeSet::new();
+            self.collect_hosts(pattern, &mut hosts, &mut BTreeSet::new());
+            hosts.into_iter().collect()
+        } else {
+            anyhow::bail!("No host or group named {} in the inventory", pattern);
+        };
+        if names.is_empty() {
+            anyhow::bail!("Inventory group {} has no hosts", pattern);
+        }
+        Ok(names.iter().map(|name| self.host(name)).collect())
+    }
+
+    fn collect_hosts<'a>(
+        &'a self,
+        group: &'a str,
+        hosts: &mut BTreeSet<String>,
+        visited: &mut BTreeSet<&'a str>,
+    ) {
+        if !visited.insert(group) {
+            return;
+        }
+        if let Some(g) = self.groups.get(group) {
+            hosts.extend(g.hosts.iter().cloned());
+            for child in &g.children {
+                self.collect_hosts(child, hosts, visited);
+            }
+        }
+    }
+
+    /// Connection settings of a host, merging group and host variables.
+    fn host(&self, name: &str) -> InventoryHost {
+        let mut groups: Vec<(usize, &str)> = self
+            .groups
+            .keys()
+            .filter(|group| group.as_str() != ALL_GROUP)
+            .filter(|group| {
+                let mut hosts = BTreeSet::new();
+                self.collect_hosts(group, &mut hosts, &mut BTreeSet::new());
+                hosts.contains(name)
+            })
+            .map(|group| (self.depth(group, &mut BTreeSet::new()), group.as_str()))
+            .collect();
+        groups.sort();
+
+        let mut vars = BTreeMap::new();
+        let all = self.groups.get(ALL_GROUP).map(|g| &g.vars);
+        for group_vars in all
+            .into_iter()
+            .chain(groups.iter().map(|(_, g)| &self.groups[*g].vars))
+            .chain(self.host_vars.get(name))
+        {
+            vars.extend(group_vars.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        }
+
+        let var = |keys: &[&str]| keys.iter().find_map(|k| vars.get(k).map(|v| v.to_string()));
+        InventoryHost {
+            name: name.to_string(),
+            address: var(&["ansible_host", "ansible_ssh_host"]).unwrap_or_else(|| name.to_string()),
+            port: var(&["ansible_port", "ansible_ssh_port"]).and_then(|p| p.parse().ok()),
+            user: var(&["ansible_user", "ansible_ssh_user"]),
+            private_key: var(&["ansible_ssh_private_key_file", "ansible_private_key_file"])
+                .map(|path| expand_home(&path)),
+            connection: var(&["ansible_connection"]),
+        }
+    }
+
+    /// Nesting depth of a group: 1 for top-level groups.
+    fn depth<'a>(&'a self, group: &'a str, visited: &mut BTreeSet<&'a str>) -> usize {
+        if !visited.insert(group) {
+            return 0;
+        }
+        self.groups
+            .iter()
+            .filter(|(name, g)| name.as_str() != ALL_GROUP && g.children.iter().any(|c| c == group))
+            .map(|(name, _)| self.depth(name, visited) + 1)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+/// Split `key=value`, unquoting the value.
+fn parse_assignment(var: &str) -> Option<(String, String)> {
+    let (key, value) = var.split_once('=')?;
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value);
+    Some((key.trim().to_string(), value.to_string()))
+}
+
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Expand a leading `~/` to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ini_group_and_host_vars() {
+        let ini = r#"
+# production web tier
+[web]
+web01 ansible_host=10.0.0.5
+web02 ansible_host=10.0.0.6 ansible_user=admin ansible_port=2222
+
+[web:vars]
+ansible_user=deploy
+ansible_ssh_private_key_file=/keys/deploy.pem
+
+[prod:children]
+web
+
+[prod:vars]
+ansible_user=root
+ansible_port=22
+
+[windows]
+win01 ansible_connection=winrm
+"#;
+        let inventory = Inventory::parse_ini(ini).unwrap();
+
+        let hosts = inventory.resolve("prod").unwrap();
+        assert_eq!(hosts.len(), 2);
+        // Child group variables override the parent's, host variables both
+        assert_eq!(hosts[0].address, "10.0.0.5");
+        assert_eq!(hosts[0].user.as_deref(), Some("deploy"));
+        assert_eq!(hosts[0].port, Some(22));
+        assert_eq!(
+            hosts[0].private_key.as_deref(),
+            Some(Path::new("/keys/deploy.pem"))
+        );
+        assert_eq!(hosts[1].user.as_deref(), Some("admin"));
+        assert_eq!(hosts[1].port, Some(2222));
+
+        let win = inventory.resolve("win01").unwrap();
+        assert_eq!(win[0].address, "win01");
+        assert_eq!(win[0].connection.as_deref(), Some("winrm"));
+        assert_eq!(inventory.resolve("all").unwrap().len(), 3);
+        assert!(inventory.resolve("db").is_err());
+    }
+
+    #[test]
+    fn test_yaml_inventory() {
+        let yaml = r#"
+all:
+  vars:
+    ansible_user: ops
+  children:
+    db:
+      hosts:
+        db01:
+          ansible_host: 10.0.1.10
+          ansible_port: 2200
+      vars:
+        ansible_ssh_private_key_file: /keys/db.pem
+"#;
+        let inventory = Inventory::parse_yaml(yaml).unwrap();
+        let hosts = inventory.resolve("db").unwrap();
+        assert_eq!(
+            hosts,
+            vec![InventoryHost {
+                name: "db01".to_string(),
+                address: "10.0.1.10".to_string(),
+                port: Some(2200),
+                user: Some("ops".to_string()),
+                private_key: Some(PathBuf::from("/keys/db.pem")),
+                connection: None,
+            }]
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Kerberos authentication through SPNEGO for WinRM Negotiate auth.
+//!
+//! The system GSSAPI library (MIT krb5 or Heimdal) is loaded at runtime
+//! rather than linked, so the probe still runs where it is missing, such as
+//! the static musl agent build; Negotiate auth then falls back to NTLM.
+//! Credentials come from the user's credential cache (`kinit`), or are
+//! acquired with the WinRM password when one is given. Only the initial
+//! token is produced: mutual authentication and message sealing are not
+//! requested, as with NTLM, so WinRM over plain HTTP still requires
+//! `AllowUnencrypted` on the target; HTTPS is recommended.
+
+use anyhow::{bail, Context, Result};
+use libloading::Library;
+use std::ffi::c_void;
+use std::ptr;
+
+/// Shared object names of the GSSAPI library, tried in order.
+const LIBRARIES: &[&str] = &[
+    "libgssapi_krb5.so.2",
+    "libgssapi.so.3",
+    "/System/Library/Frameworks/GSS.framework/GSS",
+];
+
+/// GSS_C_NT_HOSTBASED_SERVICE (1.2.840.113554.1.2.1.4).
+const NT_HOSTBASED_SERVICE: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x04";
+/// GSS_C_NT_USER_NAME (1.2.840.113554.1.2.1.1).
+const NT_USER_NAME: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x01";
+/// SPNEGO mechanism (1.3.6.1.5.5.2).
+const MECH_SPNEGO: &[u8] = b"\x2b\x06\x01\x05\x05\x02";
+
+const GSS_C_INITIATE: i32 = 1;
+const GSS_C_GSS_CODE: i32 = 1;
+const GSS_C_MECH_CODE: i32 = 2;
+
+type OmUint32 = u32;
+
+// Apple's GSS.framework declares `gss_OID_desc` and `gss_buffer_desc` under
+// `#pragma pack(2)`: fields are copied out rather than borrowed.
+#[cfg_attr(target_os = "macos", repr(C, packed(2)))]
+#[cfg_attr(not(target_os = "macos"), repr(C))]
+struct OidDesc {
+    length: OmUint32,
+    elements: *const c_void,
+}
+
+#[cfg_attr(target_os = "macos", repr(C, packed(2)))]
+#[cfg_attr(not(target_os = "macos"), repr(C))]
+struct BufferDesc {
+    length: usize,
+    value: *mut c_void,
+}
+
+impl BufferDesc {
+    fn empty() -> Self {
+        Self {
+            length: 0,
+            value: ptr::null_mut(),
+        }
+    }
+
+    fn borrowed(bytes: &[u8]) -> Self {
+        Self {
+            length: bytes.len(),
+            value: bytes.as_ptr() as *mut c_void,
+        }
+    }
+}
+
+fn oid(bytes: &'static [u8]) -> OidDesc {
+    OidDesc {
+        length: bytes.len() as OmUint32,
+        elements: bytes.as_ptr() as *const c_void,
+    }
+}
+
+type ImportName = unsafe extern "C" fn(
+    *mut OmUint32,
+    *const BufferDesc,
+    *const OidDesc,
+    *mut *mut c_void,
+) -> OmUint32;
+type ReleaseName = unsafe extern "C" fn(*mut OmUint32, *mut *mut c_void) -> OmUint32;
+type InitSecContext = unsafe extern "C" fn(
+    *mut OmUint32,
+    *mut c_void,
+    *mut *mut c_void,
+    *mut c_void,
+    *const OidDesc,
+    OmUint32,
+    OmUint32,
+    *const c_void,
+    *const BufferDesc,
+    *mut *const OidDesc,
+    *mut BufferDesc,
+    *mut OmUint32,
+    *mut OmUint32,
+) -> OmUint32;
+type DeleteSecContext =
+    unsafe extern "C" fn(*mut OmUint32, *mut *mut c_void, *mut BufferDesc) -> OmUint32;
+type ReleaseBuffer = unsafe extern "C" fn(*mut OmUint32, *mut BufferDesc) -> OmUint32;
+type AcquireCredWithPassword = unsafe extern "C" fn(
+    *mut OmUint32,
+    *mut c_void,
+    *const BufferDesc,
+    OmUint32,
+    *const c_void,
+    i32,
+    *mut *mut c_void,
+    *mut *mut c_void,
+    *mut OmUint32,
+) -> OmUint32;
+type ReleaseCred = unsafe extern "C" fn(*mut OmUint32, *mut *mut c_void) -> OmUint32;
+type DisplayStatus = unsafe extern "C" fn(
+    *mut OmUint32,
+    OmUint32,
+    i32,
+    *const OidDesc,
+    *mut OmUint32,
+    *mut BufferDesc,
+) -> OmUint32;
+
+/// Entry points of the GSSAPI library.
+struct Gssapi {
+    import_name: ImportName,
+    release_name: ReleaseName,
+    init_sec_context: InitSecContext,
+    delete_sec_context: DeleteSecContext,
+    release_buffer: ReleaseBuffer,
+    acquire_cred_with_password: Option<AcquireCredWithPassword>,
+    release_cred: ReleaseCred,
+    display_status: DisplayStatus,
+    // Keeps the entry points above valid
+    _library: Library,
+}
+
+impl Gssapi {
+    /// Load the first of `candidates` that can be opened.
+    fn load(candidates: &[&str]) -> Result<Self> {
+        let library = candidates
+            .iter()
+            // SAFETY: GSSAPI libraries have no initialization side effects
+            .find_map(|name| unsafe { Library::new(name) }.ok())
+            .with_context(|| {
+                format!("GSSAPI library not found (tried {})", candidates.join(", "))
+            })?;
+        // SAFETY: the types match the GSSAPI C bindings (RFC 2744)
+        unsafe {
+            Ok(Self {
+                import_name: *library.get(b"gss_import_name\0")?,
+                release_name: *library.get(b"gss_release_name\0")?,
+                init_sec_context: *library.get(b"gss_init_sec_context\0")?,
+                delete_sec_context: *library.get(b"gss_delete_sec_context\0")?,
+                release_buffer: *library.get(b"gss_release_buffer\0")?,
+                acquire_cred_with_password: library
+                    .get(b"gss_acquire_cred_with_password\0")
+                    .ok()
+                    .map(|f| *f),
+                release_cred: *library.get(b"gss_release_cred\0")?,
+                display_status: *library.get(b"gss_display_status\0")?,
+                _library: library,
+            })
+        }
+    }
+
+    /// Fail with the messages of a GSSAPI status.
+    fn check(&self, major: OmUint32, minor: OmUint32, operation: &str) -> Result<()> {
+        // Calling and routine errors; the low bits are supplementary info
+        if major & 0xffff_0000 == 0 {
+            return Ok(());
+        }
+        let mut messages = self.status_messages(major, GSS_C_GSS_CODE);
+        messages.extend(self.status_messages(minor, GSS_C_MECH_CODE));
+        bail!("{} failed: {}", operation, messages.join(": "))
+    }
+
+    fn status_messages(&self, status: OmUint32, kind: i32) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut context = 0;
+        loop {
+            let (mut minor, mut buffer) = (0, BufferDesc::empty());
+            // SAFETY: the buffer is released below
+            let major = unsafe {
+                (self.display_status)(
+                    &mut minor,
+                    status,
+                    kind,
+                    ptr::null(),
+                    &mut context,
+                    &mut buffer,
+                )
+            };
+            if major != 0 {
+                break;
+            }
+            let message = unsafe { buffer_bytes(&buffer) };
+            messages.push(String::from_utf8_lossy(&message).into_owned());
+            unsafe { (self.release_buffer)(&mut minor, &mut buffer) };
+            if context == 0 {
+                break;
+            }
+        }
+        messages
+    }
+
+    fn import_name(&self, name: &str, name_type: &'static [u8]) -> Result<*mut c_void> {
+        let (mut minor, mut output) = (0, ptr::null_mut());
+        let buffer = BufferDesc::borrowed(name.as_bytes());
+        let major =
+            unsafe { (self.import_name)(&mut minor, &buffer, &oid(name_type), &mut output) };
+        self.check(major, minor, &format!("Importing name {}", name))?;
+        Ok(output)
+    }
+}
+
+/// Copy of the bytes of a GSSAPI buffer.
+///
+/// # Safety
+/// `buffer` must have been filled by the GSSAPI library.
+unsafe fn buffer_bytes(buffer: &BufferDesc) -> Vec<u8> {
+    let (value, length) = (buffer.value, buffer.length);
+    if value.is_null() {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(value as *const u8, length).to_vec()
+}
+
+/// Kerberos credentials of a WinRM user.
+pub struct Kerberos {
+    gssapi: Gssapi,
+    /// Credential handle; null for the default credentials of the cache.
+    credential: *mut c_void,
+}
+
+// SAFETY: GSSAPI credential handles may be used from any thread, and are
+// only read after they are acquired
+unsafe impl Send for Kerberos {}
+unsafe impl Sync for Kerberos {}
+
+impl Kerberos {
+    /// Load the GSSAPI library and acquire the credentials of `principal`
+    /// with `password`; the credential cache is used when either is missing.
+    pub fn new(principal: Option<&str>, password: Option<&str>) -> Result<Self> {
+        let mut kerberos = Self {
+            gssapi: Gssapi::load(LIBRARIES)?,
+            credential: ptr::null_mut(),
+        };
+        if let (Some(principal), Some(password)) = (principal, password) {
+            kerberos.credential = kerberos.acquire_with_password(principal, password)?;
+        }
+        Ok(kerberos)
+    }
+
+    fn acquire_with_password(&self, principal: &str, password: &str) -> Result<*mut c_void> {
+        let gssapi = &self.gssapi;
+        let acquire = gssapi
+            .acquire_cred_with_password
+            .context("GSSAPI library cannot acquire credentials with a password")?;
+        let mut name = gssapi.import_name(principal, NT_USER_NAME)?;
+        let (mut minor, mut credential) = (0, ptr::null_mut());
+        let password = BufferDesc::borrowed(password.as_bytes());
+        let major = unsafe {
+            acquire(
+                &mut minor,
+                name,
+                &password,
+                0,
+                ptr::null(),
+                GSS_C_INITIATE,
+                &mut credential,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        unsafe { (gssapi.release_name)(&mut 0, &mut name) };
+        gssapi.check(
+            major,
+            minor,
+            &format!("Acquiring Kerberos credentials of {}", principal),
+        )?;
+        Ok(credential)
+    }
+
+    /// SPNEGO token authenticating to the HTTP service of `host`.
+    pub fn token(&self, host: &str) -> Result<Vec<u8>> {
+        let gssapi = &self.gssapi;
+        let mut target = gssapi.import_name(&format!("HTTP@{}", host), NT_HOSTBASED_SERVICE)?;
+        let (mut minor, mut context, mut output) = (0, ptr::null_mut(), BufferDesc::empty());
+        let major = unsafe {
+            (gssapi.init_sec_context)(
+                &mut minor,
+                self.credential,
+                &mut context,
+                target,
+                &oid(MECH_SPNEGO),
+                0,
+                0,
+                ptr::null(),
+                ptr::null(),
+                ptr::null_mut(),
+                &mut output,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        let token = unsafe { buffer_bytes(&output) };
+        unsafe {
+            (gssapi.release_buffer)(&mut 0, &mut output);
+            (gssapi.delete_sec_context)(&mut 0, &mut context, ptr::null_mut());
+            (gssapi.release_name)(&mut 0, &mut target);
+        }
+        gssapi.check(
+            major,
+            minor,
+            &format!("Kerberos authentication to HTTP/{}", host),
+        )?;
+        if token.is_empty() {
+            bail!("GSSAPI produced no token for HTTP/{}", host);
+        }
+        Ok(token)
+    }
+}
+
+impl Drop for Kerberos {
+    fn drop(&mut self) {
+        if !self.credential.is_null() {
+            unsafe { (self.gssapi.release_cred)(&mut 0, &mut self.credential) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_library() {
+        let error = Gssapi::load(&["/nonexistent/libgssapi.so"])
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("GSSAPI library not found"));
+        assert!(error.contains("/nonexistent/libgssapi.so"));
+    }
+}
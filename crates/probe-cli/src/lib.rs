@@ -1,8 +1,51 @@
 //! XCProbe Collector - Non-intrusive system discovery and collection.
+//!
+//! The collector can be embedded in other tools. Build a [`Collector`],
+//! optionally with a custom [`Executor`] (implemented with
+//! [`async_trait`]), and collect a typed [`Bundle`]:
+//!
+//! ```no_run
+//! use xcprobe_collector::{bundle, CollectionMode, Collector};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let collector = Collector::builder()
+//!     .target("web01.example.com")
+//!     .mode(CollectionMode::Remote)
+//!     .ssh(22, Some("probe".to_string()))
+//!     .skip_phases(["logs"])
+//!     .build()?;
+//! let bundle = collector.collect().await?;
+//! println!("{} services", bundle.manifest.services.len());
+//! bundle::write_bundle(&bundle, std::path::Path::new("bundle.tgz"))?;
+//! # Ok(())
+//! # }
+//! ```
 
 pub mod bundle;
 pub mod collector;
 pub mod commands;
+pub mod credentials;
+pub mod dry_run;
 pub mod executor;
+pub mod inventory;
+pub mod kerberos;
+pub mod ntlm;
 pub mod pack;
 pub mod parsers;
+pub mod phases;
+pub mod progress;
+pub mod s3;
+pub mod storage;
+pub mod upload;
+pub mod vault;
+pub mod watch;
+pub mod winrm;
+
+pub use async_trait::async_trait;
+pub use collector::{
+    CollectionMode, Collector, CollectorBuilder, CollectorConfig, RetryPolicy, SamplingConfig,
+};
+pub use executor::{Executor, HostKeyCheck, HostKeyPolicy, SshTransport, WinRmTls};
+pub use progress::{ProgressEvent, ProgressReporter};
+pub use winrm::WinRmAuth;
+pub use xcprobe_bundle_schema::Bundle;
@@ -0,0 +1,416 @@
+//! NTLMv2 authentication messages (MS-NLMP) for WinRM Negotiate auth.
+//!
+//! Only the connection-oriented authentication handshake is implemented:
+//! NEGOTIATE (type 1), CHALLENGE (type 2) parsing and AUTHENTICATE (type 3).
+//! Message signing and sealing are not negotiated, so WinRM over plain HTTP
+//! requires `AllowUnencrypted`; HTTPS is recommended.
+
+use crate::winrm::Account;
+use anyhow::{bail, Context, Result};
+use openssl::hash::{hash, MessageDigest};
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+
+/// Flags sent in the NEGOTIATE message.
+const CLIENT_FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NEGOTIATE_TARGET_INFO
+    | NEGOTIATE_128
+    | NEGOTIATE_56;
+
+/// AV pair id carrying the server timestamp.
+const MSV_AV_TIMESTAMP: u16 = 7;
+
+/// Seconds between 1601-01-01 (FILETIME epoch) and 1970-01-01.
+const FILETIME_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// Credentials used for NTLM authentication.
+#[derive(Debug, Clone)]
+pub struct NtlmCredentials {
+    pub domain: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl NtlmCredentials {
+    /// Build credentials from a `DOMAIN\user`, `user@domain` or bare user name.
+    pub fn new(user: &str, password: &str) -> Self {
+        let (domain, username) = match Account::parse(user) {
+            Account::DownLevel { domain, user } => (domain, user),
+            // UPN form is sent as-is with an empty domain
+            Account::Principal { .. } | Account::Local(_) => (String::new(), user.to_string()),
+        };
+        Self {
+            domain,
+            username,
+            password: password.to_string(),
+        }
+    }
+}
+
+/// Parsed CHALLENGE message.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub flags: u32,
+    pub server_challenge: [u8; 8],
+    pub target_info: Vec<u8>,
+}
+
+impl Challenge {
+    /// Parse a type 2 message.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 32 || &data[..8] != SIGNATURE || read_u32(data, 8) != 2 {
+            bail!("Not an NTLM CHALLENGE message");
+        }
+
+        let flags = read_u32(data, 20);
+        let mut server_challenge = [0u8; 8];
+        server_challenge.copy_from_slice(&data[24..32]);
+
+        let target_info = if data.len() >= 48 {
+            let len = read_u16(data, 40) as usize;
+            let offset = read_u32(data, 44) as usize;
+            data.get(offset..offset + len)
+                .context("NTLM target info out of bounds")?
+                .to_vec()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            flags,
+            server_challenge,
+            target_info,
+        })
+    }
+
+    /// Server timestamp from the target info, if present.
+    fn timestamp(&self) -> Option<u64> {
+        let info = &self.target_info;
+        let mut pos = 0;
+        while pos + 4 <= info.len() {
+            let id = read_u16(info, pos);
+            let len = read_u16(info, pos + 2) as usize;
+            let value = info.get(pos + 4..pos + 4 + len)?;
+            if id == 0 {
+                break;
+            }
+            if id == MSV_AV_TIMESTAMP && len == 8 {
+                return Some(u64::from_le_bytes(value.try_into().ok()?));
+            }
+            pos += 4 + len;
+        }
+        None
+    }
+}
+
+/// Build the NEGOTIATE (type 1) message.
+pub fn negotiate_message() -> Vec<u8> {
+    let mut msg = Vec::with_capacity(32);
+    msg.extend_from_slice(SIGNATURE);
+    msg.extend_from_slice(&1u32.to_le_bytes());
+    msg.extend_from_slice(&CLIENT_FLAGS.to_le_bytes());
+    // Empty domain and workstation buffers
+    msg.extend_from_slice(&[0u8; 16]);
+    msg
+}
+
+/// Build the AUTHENTICATE (type 3) message answering `challenge`.
+pub fn authenticate_message(creds: &NtlmCredentials, challenge: &Challenge) -> Result<Vec<u8>> {
+    let mut client_challenge = [0u8; 8];
+    openssl::rand::rand_bytes(&mut client_challenge).context("Failed to generate NTLM nonce")?;
+
+    let server_timestamp = challenge.timestamp();
+    let timestamp = server_timestamp.unwrap_or_else(current_filetime);
+
+    let (lm_response, nt_response) =
+        ntlmv2_responses(creds, challenge, &client_challenge, timestamp)?;
+    // The LM response must be zeroed when the server supplied a timestamp
+    let lm_response = if server_timestamp.is_some() {
+        vec![0u8; 24]
+    } else {
+        lm_response
+    };
+
+    let domain = utf16le(&creds.domain);
+    let user = utf16le(&creds.username);
+    let workstation: Vec<u8> = Vec::new();
+    let session_key: Vec<u8> = Vec::new();
+
+    let flags = CLIENT_FLAGS & challenge.flags | NEGOTIATE_UNICODE;
+
+    const HEADER_LEN: usize = 64;
+    let mut payload = Vec::new();
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(SIGNATURE);
+    header.extend_from_slice(&3u32.to_le_bytes());
+    for field in [
+        &lm_response,
+        &nt_response,
+        &domain,
+        &user,
+        &workstation,
+        &session_key,
+    ] {
+        let offset = (HEADER_LEN + payload.len()) as u32;
+        header.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+        payload.extend_from_slice(field);
+    }
+    header.extend_from_slice(&flags.to_le_bytes());
+
+    header.extend_from_slice(&payload);
+    Ok(header)
+}
+
+/// Compute the (LMv2, NTLMv2) challenge responses.
+fn ntlmv2_responses(
+    creds: &NtlmCredentials,
+    challenge: &Challenge,
+    client_challenge: &[u8; 8],
+    timestamp: u64,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let key = ntowf_v2(creds)?;
+
+    let mut temp = vec![0x01, 0x01, 0, 0, 0, 0, 0, 0];
+    temp.extend_from_slice(&timestamp.to_le_bytes());
+    temp.extend_from_slice(client_challenge);
+    temp.extend_from_slice(&[0u8; 4]);
+    temp.extend_from_slice(&challenge.target_info);
+    temp.extend_from_slice(&[0u8; 4]);
+
+    let nt_proof = hmac_md5(&key, &[&challenge.server_challenge[..], &temp].concat())?;
+    let nt_response = [&nt_proof[..], &temp].concat();
+
+    let lm_proof = hmac_md5(
+        &key,
+        &[&challenge.server_challenge[..], &client_challenge[..]].concat(),
+    )?;
+    let lm_response = [&lm_proof[..], &client_challenge[..]].concat();
+
+    Ok((lm_response, nt_response))
+}
+
+/// NTOWFv2: HMAC-MD5 of the upper-cased user and domain keyed by the NT hash.
+fn ntowf_v2(creds: &NtlmCredentials) -> Result<[u8; 16]> {
+    let nt_hash = md4(&utf16le(&creds.password));
+    let identity = format!("{}{}", creds.username.to_uppercase(), creds.domain);
+    hmac_md5(&nt_hash, &utf16le(&identity))
+}
+
+fn hmac_md5(key: &[u8], data: &[u8]) -> Result<[u8; 16]> {
+    const BLOCK: usize = 64;
+    let mut block_key = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block_key[..16].copy_from_slice(&hash(MessageDigest::md5(), key)?);
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let ipad: Vec<u8> = block_key.iter().map(|b| b ^ 0x36).collect();
+    let opad: Vec<u8> = block_key.iter().map(|b| b ^ 0x5c).collect();
+
+    let inner = hash(MessageDigest::md5(), &[&ipad[..], data].concat())?;
+    let outer = hash(MessageDigest::md5(), &[&opad[..], &inner[..]].concat())?;
+
+    let mut out = [0u8; 16];
+    out.copy_from_slice(&outer);
+    Ok(out)
+}
+
+/// MD4 (RFC 1320). OpenSSL 3 only ships it in the legacy provider, so it is
+/// implemented here.
+fn md4(input: &[u8]) -> [u8; 16] {
+    let mut msg = input.to_vec();
+    let bit_len = (input.len() as u64).wrapping_mul(8);
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&bit_len.to_le_bytes());
+
+    let f = |x: u32, y: u32, z: u32| (x & y) | (!x & z);
+    let g = |x: u32, y: u32, z: u32| (x & y) | (x & z) | (y & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    for block in msg.chunks(64) {
+        let x: Vec<u32> = block
+            .chunks(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in [0, 4, 8, 12] {
+            a = a.wrapping_add(f(b, c, d)).wrapping_add(x[i]).rotate_left(3);
+            d = d
+                .wrapping_add(f(a, b, c))
+                .wrapping_add(x[i + 1])
+                .rotate_left(7);
+            c = c
+                .wrapping_add(f(d, a, b))
+                .wrapping_add(x[i + 2])
+                .rotate_left(11);
+            b = b
+                .wrapping_add(f(c, d, a))
+                .wrapping_add(x[i + 3])
+                .rotate_left(19);
+        }
+        for i in 0..4 {
+            let k = 0x5a82_7999u32;
+            a = a
+                .wrapping_add(g(b, c, d))
+                .wrapping_add(x[i])
+                .wrapping_add(k)
+                .rotate_left(3);
+            d = d
+                .wrapping_add(g(a, b, c))
+                .wrapping_add(x[i + 4])
+                .wrapping_add(k)
+                .rotate_left(5);
+            c = c
+                .wrapping_add(g(d, a, b))
+                .wrapping_add(x[i + 8])
+                .wrapping_add(k)
+                .rotate_left(9);
+            b = b
+                .wrapping_add(g(c, d, a))
+                .wrapping_add(x[i + 12])
+                .wrapping_add(k)
+                .rotate_left(13);
+        }
+        for i in [0, 2, 1, 3] {
+            let k = 0x6ed9_eba1u32;
+            a = a
+                .wrapping_add(h(b, c, d))
+                .wrapping_add(x[i])
+                .wrapping_add(k)
+                .rotate_left(3);
+            d = d
+                .wrapping_add(h(a, b, c))
+                .wrapping_add(x[i + 8])
+                .wrapping_add(k)
+                .rotate_left(9);
+            c = c
+                .wrapping_add(h(d, a, b))
+                .wrapping_add(x[i + 4])
+                .wrapping_add(k)
+                .rotate_left(11);
+            b = b
+                .wrapping_add(h(c, d, a))
+                .wrapping_add(x[i + 12])
+                .wrapping_add(k)
+                .rotate_left(15);
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut out = [0u8; 16];
+    for (chunk, word) in out.chunks_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn current_filetime() -> u64 {
+    let unix = chrono::Utc::now().timestamp().max(0) as u64;
+    (unix + FILETIME_EPOCH_OFFSET) * 10_000_000
+}
+
+fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md4_vectors() {
+        assert_eq!(hex::encode(md4(b"")), "31d6cfe0d16ae931b73c59d7e0c089c0");
+        assert_eq!(hex::encode(md4(b"abc")), "a448017aaf21d8525fc10ae87aa6729d");
+        assert_eq!(
+            hex::encode(md4(&utf16le("Password"))),
+            "a4f49c406510bdcab6824ee7c30fd852"
+        );
+    }
+
+    #[test]
+    fn test_ntlmv2_spec_vector() {
+        // MS-NLMP 4.2.4
+        let creds = NtlmCredentials::new("Domain\\User", "Password");
+        assert_eq!(
+            hex::encode(ntowf_v2(&creds).unwrap()),
+            "0c868a403bfd7a93a3001ef22ef02e3f"
+        );
+
+        let challenge = Challenge {
+            flags: CLIENT_FLAGS,
+            server_challenge: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
+            target_info: [
+                &[0x02, 0x00, 0x0c, 0x00][..],
+                &utf16le("Domain"),
+                &[0x01, 0x00, 0x0c, 0x00],
+                &utf16le("Server"),
+                &[0x00, 0x00, 0x00, 0x00],
+            ]
+            .concat(),
+        };
+        let (lm, nt) = ntlmv2_responses(&creds, &challenge, &[0xaa; 8], 0).unwrap();
+        assert_eq!(hex::encode(&nt[..16]), "68cd0ab851e51c96aabc927bebef6a1c");
+        assert_eq!(hex::encode(&lm[..16]), "86c35097ac9cec102554764a57cccc19");
+    }
+
+    #[test]
+    fn test_challenge_round_trip() {
+        let info = [&[0x07, 0x00, 0x08, 0x00][..], &42u64.to_le_bytes(), &[0; 4]].concat();
+        let mut msg = Vec::new();
+        msg.extend_from_slice(SIGNATURE);
+        msg.extend_from_slice(&2u32.to_le_bytes());
+        msg.extend_from_slice(&[0u8; 8]);
+        msg.extend_from_slice(&CLIENT_FLAGS.to_le_bytes());
+        msg.extend_from_slice(&[7u8; 8]);
+        msg.extend_from_slice(&[0u8; 8]);
+        msg.extend_from_slice(&(info.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(info.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&48u32.to_le_bytes());
+        msg.extend_from_slice(&info);
+
+        let challenge = Challenge::parse(&msg).unwrap();
+        assert_eq!(challenge.server_challenge, [7u8; 8]);
+        assert_eq!(challenge.timestamp(), Some(42));
+
+        let creds = NtlmCredentials::new("user@corp.example", "secret");
+        let auth = authenticate_message(&creds, &challenge).unwrap();
+        assert_eq!(&auth[..8], SIGNATURE);
+        assert_eq!(read_u32(&auth, 8), 3);
+        // Zeroed LM response when the server sent a timestamp
+        assert_eq!(read_u16(&auth, 12), 24);
+        assert!(auth[64..88].iter().all(|b| *b == 0));
+    }
+}
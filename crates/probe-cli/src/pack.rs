@@ -1,10 +1,194 @@
 //! Pack plan generation and execution.
 
-use anyhow::Result;
+use crate::commands::{command_set, CommandSet};
+use crate::executor::{HostKeyCheck, SshTransport};
+use anyhow::{Context, Result};
+use base64::Engine;
+use globset::{GlobBuilder, GlobMatcher};
+use serde::Serialize;
 use std::path::{Path, PathBuf};
-use tracing::info;
-use xcprobe_bundle_schema::{Bundle, ConfigFileSpec, PackPlan};
+use std::time::Duration;
+use tracing::{info, warn};
+use xcprobe_bundle_schema::{
+    AppFileSpec, Bundle, ConfigFileSpec, PackManifest, PackPlan, PackedFile, PACK_MANIFEST_FILE,
+};
 use xcprobe_common::OsType;
+use xcprobe_redaction::{Redactor, RedactorConfig};
+
+/// Time limit for fetching one application file or directory.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Default size cap of a directory archive, in MiB.
+pub const DEFAULT_MAX_ARCHIVE_MB: u64 = 512;
+
+/// Extensions of the configuration files redacted in fetched directories.
+const CONFIG_EXTENSIONS: &[&str] = &[
+    "conf",
+    "cfg",
+    "cnf",
+    "ini",
+    "properties",
+    "env",
+    "json",
+    "yaml",
+    "yml",
+    "toml",
+    "xml",
+];
+
+/// Which plan entries are fetched, and how directories are archived on the
+/// target.
+#[derive(Debug, Clone)]
+pub struct PackOptions {
+    /// Globs of the entries to fetch; every entry when empty.
+    pub includes: Vec<String>,
+    /// Globs of the entries left out, also left out of directory archives
+    /// in addition to the files that typically hold secrets.
+    pub excludes: Vec<String>,
+    /// Largest file entry fetched, in bytes, when its size is known.
+    pub max_file_bytes: Option<u64>,
+    /// Largest compressed archive fetched for one directory, in bytes.
+    pub max_archive_bytes: u64,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            max_file_bytes: None,
+            max_archive_bytes: DEFAULT_MAX_ARCHIVE_MB * 1024 * 1024,
+        }
+    }
+}
+
+/// A plan entry as the pack step would handle it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedFetch {
+    pub cluster_id: String,
+    pub source_path: String,
+    pub kind: String,
+    /// Size of a file entry, when collected.
+    pub size: Option<u64>,
+    /// Why the entry is left out, if it is.
+    pub skipped: Option<String>,
+}
+
+/// The plan entries with the reason each one is left out under `options`,
+/// in plan order. Computed without connecting to the target.
+pub fn plan_fetches(plan: &PackPlan, options: &PackOptions) -> Result<Vec<PlannedFetch>> {
+    let filter = PackFilter::new(options)?;
+    Ok(plan
+        .clusters
+        .iter()
+        .flat_map(|cluster| {
+            cluster.app_files.iter().map(|app_file| PlannedFetch {
+                cluster_id: cluster.id.clone(),
+                source_path: app_file.source_path.clone(),
+                kind: app_file.kind.clone(),
+                size: app_file.size,
+                skipped: filter.skip_reason(app_file),
+            })
+        })
+        .collect())
+}
+
+/// Compiled `--include`/`--exclude` globs and file size limit.
+struct PackFilter<'a> {
+    includes: Vec<(&'a str, GlobMatcher)>,
+    excludes: Vec<(&'a str, GlobMatcher)>,
+    max_file_bytes: Option<u64>,
+}
+
+impl<'a> PackFilter<'a> {
+    fn new(options: &'a PackOptions) -> Result<Self> {
+        let compile = |patterns: &'a [String]| -> Result<Vec<(&'a str, GlobMatcher)>> {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    let glob = GlobBuilder::new(pattern)
+                        .literal_separator(true)
+                        .build()
+                        .with_context(|| format!("Invalid glob: {}", pattern))?;
+                    Ok((pattern.as_str(), glob.compile_matcher()))
+                })
+                .collect()
+        };
+        Ok(Self {
+            includes: compile(&options.includes)?,
+            excludes: compile(&options.excludes)?,
+            max_file_bytes: options.max_file_bytes,
+        })
+    }
+
+    /// Why an entry is left out: an exclude glob matching its path, no
+    /// include glob matching it, or a size over the limit. A directory also
+    /// matches the globs matching its path with a trailing `/`.
+    fn skip_reason(&self, app_file: &AppFileSpec) -> Option<String> {
+        let path = app_file.source_path.replace('\\', "/");
+        let mut candidates = vec![path.clone()];
+        if app_file.is_directory() {
+            candidates.push(format!("{}/", path.trim_end_matches('/')));
+        }
+        let matching = |globs: &[(&'a str, GlobMatcher)]| {
+            globs
+                .iter()
+                .find(|(_, glob)| candidates.iter().any(|c| glob.is_match(c)))
+                .map(|(pattern, _)| *pattern)
+        };
+
+        if let Some(pattern) = matching(&self.excludes) {
+            return Some(format!("excluded by {}", pattern));
+        }
+        if !self.includes.is_empty() && matching(&self.includes).is_none() {
+            return Some("matches no --include glob".to_string());
+        }
+        match (app_file.size, self.max_file_bytes) {
+            (Some(size), Some(max)) if size > max => {
+                Some(format!("{} exceeds --max-file-size", format_size(size)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parse a size such as `10M`: bytes, or KiB, MiB or GiB with a `K`, `M`
+/// or `G` suffix.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (digits, unit) = match size.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => size.split_at(i),
+        None => (size, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => anyhow::bail!("Invalid size {:?}: expected e.g. 512K, 10M or 1G", size),
+    };
+    let value: u64 = digits
+        .parse()
+        .with_context(|| format!("Invalid size {:?}", size))?;
+    value
+        .checked_mul(1 << shift)
+        .with_context(|| format!("Size too large: {}", size))
+}
+
+/// A byte count in the largest binary unit it reaches, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
 
 /// Generate a pack plan from a bundle.
 ///
@@ -26,6 +210,7 @@ pub fn generate_pack_plan(bundle: &Bundle) -> Result<PackPlan> {
             container_path: config.path.clone(),
             templated: false,
             template_vars: vec![],
+            content: None,
             evidence_ref: config.attachment_ref.clone(),
         });
     }
@@ -37,6 +222,7 @@ pub fn generate_pack_plan(bundle: &Bundle) -> Result<PackPlan> {
             container_path: env_file.path.clone(),
             templated: true,
             template_vars: env_file.variable_names.clone(),
+            content: None,
             evidence_ref: env_file.evidence_ref.clone(),
         });
     }
@@ -49,6 +235,7 @@ pub fn generate_pack_plan(bundle: &Bundle) -> Result<PackPlan> {
                 container_path: wd.clone(),
                 templated: false,
                 template_vars: vec![],
+                content: None,
                 evidence_ref: service.evidence_ref.clone(),
             });
         }
@@ -60,67 +247,469 @@ pub fn generate_pack_plan(bundle: &Bundle) -> Result<PackPlan> {
     Ok(plan)
 }
 
-/// Execute a pack plan to collect files from target.
+/// Execute a pack plan to collect application files from the target.
+///
+/// Each cluster's `app_files` are fetched into `<output_dir>/<cluster-id>/pack/`
+/// and marked as collected in the plan, so that regenerated Dockerfiles copy
+/// them into the image. Entries left out by the globs and size limit of
+/// `options` are skipped, as listed by [`plan_fetches`]. Configuration files
+/// are redacted with `redaction` before being written. Directories are
+/// streamed as one tar archive each, as `options` allows, checked against
+/// checksums computed on the target, then the configuration files in them
+/// are redacted. The fetched files are listed with their size, hash and
+/// mode in `<output_dir>/pack-manifest.json`. Returns the number of fetched
+/// files that differ from the target's checksums.
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_pack(
-    plan: &PackPlan,
+    plan: &mut PackPlan,
     target: &str,
     os_type: OsType,
     ssh_port: u16,
     ssh_user: Option<String>,
     ssh_key: Option<PathBuf>,
+    ssh_password: Option<String>,
+    host_key_check: &HostKeyCheck,
+    ssh_transport: SshTransport,
+    redaction: &RedactorConfig,
+    options: &PackOptions,
     output_dir: &Path,
-) -> Result<()> {
-    use crate::executor::{Executor, LocalExecutor, SshExecutor};
+) -> Result<usize> {
+    use crate::executor::{Executor, LocalExecutor, OpenSshExecutor, SshExecutor};
 
     std::fs::create_dir_all(output_dir)?;
 
     // Create executor
-    let executor: Box<dyn Executor> = if target == "localhost" || target == "127.0.0.1" {
-        Box::new(LocalExecutor::new())
+    let executor: Box<dyn Executor> = if crate::collector::is_local_target(target) {
+        Box::new(LocalExecutor::new().with_command_timeout(FETCH_TIMEOUT))
+    } else if ssh_transport == SshTransport::OpenSsh {
+        let ssh = OpenSshExecutor::connect(
+            target,
+            ssh_port,
+            ssh_user.as_deref(),
+            ssh_key.as_deref(),
+            ssh_password.as_deref(),
+            host_key_check,
+        )
+        .await?
+        .with_command_timeout(FETCH_TIMEOUT);
+        Box::new(ssh)
     } else {
         let ssh = SshExecutor::connect(
             target,
             ssh_port,
             ssh_user.as_deref(),
             ssh_key.as_deref(),
-            None,
-        )?;
+            ssh_password.as_deref(),
+            host_key_check,
+        )?
+        .with_command_timeout(FETCH_TIMEOUT);
         Box::new(ssh)
     };
 
-    // Collect files for each cluster
-    for cluster in &plan.clusters {
-        let cluster_dir = output_dir.join(&cluster.id);
-        std::fs::create_dir_all(&cluster_dir)?;
-
-        for config in &cluster.config_files {
-            info!("Collecting: {}", config.source_path);
+    let commands = command_set(os_type);
+    let redactor = Redactor::with_config(redaction.clone());
+    let filter = PackFilter::new(options)?;
 
-            let cmd = if os_type.is_linux() {
-                format!("cat '{}'", config.source_path)
-            } else {
-                format!("Get-Content -Path '{}'", config.source_path)
-            };
+    let mut manifest = PackManifest {
+        source_bundle_id: plan.source_bundle_id.clone(),
+        files: Vec::new(),
+    };
+    let mut mismatches = 0;
 
-            match executor.execute(&cmd).await {
-                Ok((_, content, _)) => {
-                    // Determine output path
-                    let relative_path = config.source_path.trim_start_matches('/');
-                    let output_path = cluster_dir.join(relative_path);
+    // Collect files for each cluster
+    for cluster in &mut plan.clusters {
+        let cluster_dir = output_dir.join(&cluster.id);
 
-                    if let Some(parent) = output_path.parent() {
-                        std::fs::create_dir_all(parent)?;
-                    }
+        for app_file in &mut cluster.app_files {
+            if let Some(reason) = filter.skip_reason(app_file) {
+                info!("Skipping {}: {}", app_file.source_path, reason);
+                continue;
+            }
+            info!("Collecting: {}", app_file.source_path);
 
-                    std::fs::write(&output_path, content)?;
-                    info!("Wrote: {:?}", output_path);
+            match fetch_app_file(
+                executor.as_ref(),
+                commands.as_ref(),
+                &redactor,
+                options,
+                app_file,
+                &cluster_dir,
+            )
+            .await
+            {
+                Ok((path, differing)) => {
+                    app_file.collected = true;
+                    mismatches += differing;
+                    info!("Wrote: {:?}", path);
+                    manifest.files.extend(packed_files(
+                        output_dir,
+                        &path,
+                        &cluster.id,
+                        &app_file.source_path,
+                    )?);
                 }
                 Err(e) => {
-                    tracing::warn!("Failed to collect {}: {}", config.source_path, e);
+                    warn!("Failed to collect {}: {}", app_file.source_path, e);
                 }
             }
         }
     }
 
-    Ok(())
+    manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+    std::fs::write(
+        output_dir.join(PACK_MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    Ok(mismatches)
+}
+
+/// Manifest entries of the files written at `path`, a fetched file or
+/// directory, with paths relative to `output_dir`.
+fn packed_files(
+    output_dir: &Path,
+    path: &Path,
+    cluster_id: &str,
+    source_path: &str,
+) -> Result<Vec<PackedFile>> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            files.extend(packed_files(
+                output_dir,
+                &entry?.path(),
+                cluster_id,
+                source_path,
+            )?);
+        }
+        return Ok(files);
+    }
+    if !metadata.is_file() {
+        // Symbolic links and special files unpacked from archives
+        return Ok(Vec::new());
+    }
+
+    let relative = path.strip_prefix(output_dir).unwrap_or(path);
+    Ok(vec![PackedFile {
+        cluster_id: cluster_id.to_string(),
+        source_path: source_path.to_string(),
+        path: relative.to_string_lossy().replace('\\', "/"),
+        size: metadata.len(),
+        sha256: xcprobe_common::hash::sha256_reader(std::fs::File::open(path)?)?,
+        mode: file_mode(&metadata),
+    }])
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(format!("{:04o}", metadata.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &std::fs::Metadata) -> Option<String> {
+    None
+}
+
+/// Fetch a single application file or directory into the cluster directory.
+/// Returns where it was written, and the number of files of a directory
+/// that differ from the target's checksums.
+async fn fetch_app_file(
+    executor: &dyn crate::executor::Executor,
+    commands: &dyn CommandSet,
+    redactor: &Redactor,
+    options: &PackOptions,
+    app_file: &AppFileSpec,
+    cluster_dir: &Path,
+) -> Result<(PathBuf, usize)> {
+    let cmd = if app_file.is_directory() {
+        commands.fetch_dir_cmd(
+            &app_file.source_path,
+            &options.excludes,
+            options.max_archive_bytes,
+        )
+    } else {
+        commands.fetch_file_cmd(&app_file.source_path)
+    }
+    .map_err(|denied| anyhow::anyhow!("{}", denied))?
+    .ok_or_else(|| anyhow::anyhow!("Path cannot be fetched: {}", app_file.source_path))?;
+
+    let (exit_code, stdout, stderr) = executor.execute(&cmd).await?;
+    if exit_code != Some(0) {
+        anyhow::bail!("{}", stderr.trim());
+    }
+
+    let encoded: String = stdout.split_whitespace().collect();
+    let data = base64::engine::general_purpose::STANDARD.decode(encoded)?;
+    let output_path = cluster_dir.join(app_file.pack_path());
+
+    if app_file.is_directory() {
+        if data.len() as u64 > options.max_archive_bytes {
+            anyhow::bail!(
+                "archive exceeds {} bytes; leave out large subdirectories with --exclude \
+                 or raise --max-archive-size",
+                options.max_archive_bytes
+            );
+        }
+        std::fs::create_dir_all(&output_path)?;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data.as_slice()));
+        // Modes are kept, but without setuid, setgid or sticky bits
+        archive.set_preserve_permissions(false);
+        archive.unpack(&output_path)?;
+        // Checksums are those of the files before redaction
+        let mismatches = verify_dir(executor, commands, options, app_file, &output_path).await?;
+        let redacted = redact_config_files(redactor, &output_path)?;
+        if redacted > 0 {
+            info!(
+                "Redacted {} configuration files in {}",
+                redacted, app_file.source_path
+            );
+        }
+        return Ok((output_path, mismatches));
+    }
+
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if app_file.kind == "config" {
+        let content = String::from_utf8_lossy(&data);
+        std::fs::write(
+            &output_path,
+            redactor
+                .redact_file(&content, &app_file.source_path)
+                .content,
+        )?;
+    } else {
+        std::fs::write(&output_path, &data)?;
+    }
+
+    #[cfg(unix)]
+    if app_file.kind == "binary" {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&output_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok((output_path, 0))
+}
+
+/// Redact in place the configuration files under `dir`, recognized by
+/// their extension. Files that are not UTF-8 text are left alone. Returns
+/// the number of files changed.
+fn redact_config_files(redactor: &Redactor, dir: &Path) -> Result<usize> {
+    let mut redacted = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            redacted += redact_config_files(redactor, &path)?;
+            continue;
+        }
+        let is_config = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .or_else(|| path.file_name()?.to_str()?.strip_prefix('.'))
+            .is_some_and(|ext| CONFIG_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if !file_type.is_file() || !is_config {
+            continue;
+        }
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let result = redactor.redact_file(&content, &path.to_string_lossy());
+        if result.content != content {
+            std::fs::write(&path, result.content)?;
+            redacted += 1;
+        }
+    }
+    Ok(redacted)
+}
+
+/// Compare an unpacked directory with the checksums of its source on the
+/// target, returning the number of files that differ. Files changed between
+/// hashing and archiving, such as logs, are kept.
+async fn verify_dir(
+    executor: &dyn crate::executor::Executor,
+    commands: &dyn CommandSet,
+    options: &PackOptions,
+    app_file: &AppFileSpec,
+    output_path: &Path,
+) -> Result<usize> {
+    let Some(cmd) = commands
+        .checksum_dir_cmd(&app_file.source_path, &options.excludes)
+        .map_err(|denied| anyhow::anyhow!("{}", denied))?
+    else {
+        return Ok(0);
+    };
+    let (_, stdout, _) = executor.execute(&cmd).await?;
+    let mismatches = checksum_mismatches(&stdout, output_path)?;
+    if !mismatches.is_empty() {
+        warn!(
+            "{} files of {} differ from the target's checksums: {}",
+            mismatches.len(),
+            app_file.source_path,
+            mismatches.join(", ")
+        );
+    }
+    Ok(mismatches.len())
+}
+
+/// Files listed in `sha256sum` output (`<hash>  ./<path>` lines) that are
+/// missing from `dir` or have another hash there.
+fn checksum_mismatches(listing: &str, dir: &Path) -> Result<Vec<String>> {
+    let mut mismatches = Vec::new();
+    for line in listing.lines() {
+        // sha256sum escapes names holding a backslash or newline
+        let Some((hash, path)) = line.split_once("  ").filter(|_| !line.starts_with('\\')) else {
+            continue;
+        };
+        let relative = path.trim_start_matches("./");
+        let matches = match std::fs::File::open(dir.join(relative)) {
+            Ok(file) => xcprobe_common::hash::sha256_reader(file)? == hash,
+            Err(_) => false,
+        };
+        if !matches {
+            mismatches.push(relative.to_string());
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app-0/pack/opt/app");
+        std::fs::create_dir_all(app.join("lib")).unwrap();
+        std::fs::write(app.join("main.py"), "print()").unwrap();
+        std::fs::write(app.join("lib/util.py"), "").unwrap();
+
+        let mut files = packed_files(dir.path(), &app, "app-0", "/opt/app").unwrap();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let listed: Vec<(&str, u64)> = files.iter().map(|f| (f.path.as_str(), f.size)).collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("app-0/pack/opt/app/lib/util.py", 0),
+                ("app-0/pack/opt/app/main.py", 7)
+            ]
+        );
+        assert!(files.iter().all(|f| f.source_path == "/opt/app"));
+        assert_eq!(files[1].sha256, xcprobe_common::hash::sha256_str("print()"));
+        #[cfg(unix)]
+        assert!(files[1].mode.as_deref().is_some_and(|m| m.len() == 4));
+    }
+
+    #[test]
+    fn test_redact_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("config")).unwrap();
+        std::fs::write(
+            dir.path().join("config/application.yml"),
+            "db:\n  password: hunter2-s3cret\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("main.py"), "password = 'hunter2-s3cret'\n").unwrap();
+        std::fs::write(dir.path().join("app.ini"), [0xff, 0xfe, b'=']).unwrap();
+
+        let redacted = redact_config_files(&Redactor::new(), dir.path()).unwrap();
+
+        assert_eq!(redacted, 1);
+        let config = std::fs::read_to_string(dir.path().join("config/application.yml")).unwrap();
+        assert!(config.starts_with("db:\n  password: "));
+        assert!(!config.contains("hunter2"));
+        // Source files and binary content are not configuration
+        assert!(std::fs::read_to_string(dir.path().join("main.py"))
+            .unwrap()
+            .contains("hunter2"));
+        assert_eq!(
+            std::fs::read(dir.path().join("app.ini")).unwrap(),
+            [0xff, 0xfe, b'=']
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatches() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("lib")).unwrap();
+        std::fs::write(dir.path().join("main.py"), "print()").unwrap();
+        std::fs::write(dir.path().join("lib/util.py"), "").unwrap();
+
+        let listing = format!(
+            "{}  ./main.py\n{}  ./lib/util.py\n{}  ./app.log\n",
+            xcprobe_common::hash::sha256_str("print()"),
+            xcprobe_common::hash::sha256_str("changed"),
+            xcprobe_common::hash::sha256_str("")
+        );
+        assert_eq!(
+            checksum_mismatches(&listing, dir.path()).unwrap(),
+            vec!["lib/util.py", "app.log"]
+        );
+    }
+
+    #[test]
+    fn test_plan_fetches() {
+        let app_file = |path: &str, kind: &str, size: Option<u64>| AppFileSpec {
+            source_path: path.to_string(),
+            container_path: path.to_string(),
+            kind: kind.to_string(),
+            size,
+            ..Default::default()
+        };
+        let cluster = xcprobe_bundle_schema::AppCluster {
+            id: "app-0".to_string(),
+            name: "web".to_string(),
+            app_type: "web".to_string(),
+            app_files: vec![
+                app_file("/opt/app", "working_directory", None),
+                app_file("/etc/app/app.conf", "config", Some(2048)),
+                app_file("/etc/app/big.conf", "config", Some(20 * 1024 * 1024)),
+                app_file("/opt/app/logs", "directory", None),
+            ],
+            confidence: 0.9,
+            ..Default::default()
+        };
+        let plan = PackPlan {
+            clusters: vec![cluster],
+            ..Default::default()
+        };
+
+        let options = PackOptions {
+            includes: vec!["**/*.conf".to_string(), "/opt/**".to_string()],
+            excludes: vec!["**/logs/**".to_string()],
+            max_file_bytes: Some(parse_size("10M").unwrap()),
+            ..Default::default()
+        };
+        let skipped: Vec<Option<String>> = plan_fetches(&plan, &options)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.skipped)
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                None,
+                None,
+                Some("20.0 MiB exceeds --max-file-size".to_string()),
+                Some("excluded by **/logs/**".to_string()),
+            ]
+        );
+
+        let options = PackOptions {
+            includes: vec!["**/*.conf".to_string()],
+            ..Default::default()
+        };
+        let fetches = plan_fetches(&plan, &options).unwrap();
+        assert_eq!(
+            fetches[0].skipped.as_deref(),
+            Some("matches no --include glob")
+        );
+        assert_eq!(parse_size("512k").unwrap(), 512 * 1024);
+        assert!(parse_size("10X").is_err());
+    }
 }
@@ -1,60 +1,139 @@
 //! Parsers for command outputs.
 
+use crate::commands::InitSystem;
 use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use regex::Regex;
-use std::collections::HashMap;
-use xcprobe_bundle_schema::{Package, PortInfo, ProcessInfo, ScheduledTask, ServiceInfo};
+use std::collections::{BTreeMap, HashMap};
+use xcprobe_bundle_schema::{
+    BinaryInfo, HostEntry, Package, PortInfo, ProcessInfo, RecoveryAction, ResourceSample,
+    ScheduledTask, ServiceInfo, ServiceRecovery,
+};
 use xcprobe_common::OsType;
 
+/// Width of the `user` column of the Linux process listing.
+const PS_USER_WIDTH: usize = 64;
+
+/// Width of the `comm` column of the Linux process listing.
+const PS_COMM_WIDTH: usize = 16;
+
+/// Longest process name kept by the kernel (`TASK_COMM_LEN` - 1); longer
+/// executable names are truncated to it.
+const COMM_MAX_LEN: usize = 15;
+
 /// Parse process list output.
 pub fn parse_processes(output: &str, os_type: OsType) -> Result<Vec<ProcessInfo>> {
     match os_type {
-        OsType::Linux => parse_linux_processes(output),
+        OsType::Linux => parse_ps_processes(output, true),
+        // BSD ps has no column widths: the user name is a single word
+        OsType::MacOs | OsType::Unix => parse_ps_processes(output, false),
         OsType::Windows => parse_windows_processes(output),
     }
 }
 
-fn parse_linux_processes(output: &str) -> Result<Vec<ProcessInfo>> {
+/// Parse `ps -o pid=,ppid=,pcpu=,pmem=,etime=,lstart=,user=,args=` output,
+/// with `lstart` printed in UTC. With `fixed_columns`, the user is a
+/// `user:64` column followed by a `comm:16` column, so that user names and
+/// executables may contain spaces.
+fn parse_ps_processes(output: &str, fixed_columns: bool) -> Result<Vec<ProcessInfo>> {
     let mut processes = Vec::new();
 
-    for line in output.lines().skip(1) {
-        // Skip header
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 11 {
-            continue;
+    for line in output.lines() {
+        // pid ppid pcpu pmem etime, then lstart as five words
+        let mut rest = line;
+        let mut fields = Vec::with_capacity(10);
+        for _ in 0..10 {
+            let trimmed = rest.trim_start();
+            let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+            fields.push(&trimmed[..end]);
+            rest = &trimmed[end..];
         }
-
-        // ps auxww format: USER PID %CPU %MEM VSZ RSS TTY STAT START TIME COMMAND...
-        let user = parts[0].to_string();
-        let pid: u32 = parts[1].parse().unwrap_or(0);
-        let cpu_percent: f32 = parts[2].parse().unwrap_or(0.0);
-        let memory_percent: f32 = parts[3].parse().unwrap_or(0.0);
-
-        // Command and args are from position 10 onwards
-        let full_cmdline = parts[10..].join(" ");
-        let command = parts.get(10).map(|s| s.to_string()).unwrap_or_default();
-        let args: Vec<String> = parts[11..].iter().map(|s| s.to_string()).collect();
+        let (Ok(pid), Ok(ppid)) = (fields[0].parse::<u32>(), fields[1].parse::<u32>()) else {
+            continue;
+        };
+        let start_time =
+            NaiveDateTime::parse_from_str(&fields[5..10].join(" "), "%a %b %e %H:%M:%S %Y")
+                .ok()
+                .map(|time| time.and_utc());
+
+        // Columns are separated by a single space
+        let rest = rest.strip_prefix(' ').unwrap_or(rest);
+        let (user, comm, cmdline) = if fixed_columns {
+            let (user, rest) = split_column(rest, PS_USER_WIDTH);
+            let (comm, rest) = split_column(rest, PS_COMM_WIDTH);
+            (user, Some(comm), rest)
+        } else {
+            let rest = rest.trim_start();
+            let end = rest.find(' ').unwrap_or(rest.len());
+            (&rest[..end], None, &rest[end..])
+        };
+        let full_cmdline = cmdline.trim().to_string();
+        let (command, args) = split_command(&full_cmdline, comm);
 
         processes.push(ProcessInfo {
             pid,
-            ppid: 0, // Not available in ps aux
-            user,
+            ppid,
+            user: user.to_string(),
             command,
             args,
             full_cmdline,
-            start_time: None,
-            elapsed_time: None,
-            cpu_percent: Some(cpu_percent),
-            memory_percent: Some(memory_percent),
+            start_time,
+            elapsed_time: Some(fields[4].to_string()).filter(|t| !t.is_empty()),
+            cpu_percent: fields[2].parse().ok(),
+            memory_percent: fields[3].parse().ok(),
             working_directory: None,
             environment: None,
             evidence_ref: None,
+            cmdline_evidence_ref: None,
         });
     }
 
     Ok(processes)
 }
 
+/// Split a left-aligned column of `width` characters, and the space after
+/// it, off `line`.
+fn split_column(line: &str, width: usize) -> (&str, &str) {
+    match line.char_indices().nth(width) {
+        Some((end, _)) => (
+            line[..end].trim_end(),
+            line[end..].strip_prefix(' ').unwrap_or(&line[end..]),
+        ),
+        None => (line.trim_end(), ""),
+    }
+}
+
+/// Split a command line into its executable and arguments. The executable
+/// is the shortest prefix whose file name is the process name `comm`
+/// (or starts with it, when the kernel truncated it), so that its path may
+/// contain spaces; failing
+/// that, the first word without the `:` of retitled processes such as
+/// `nginx: worker process`.
+fn split_command(cmdline: &str, comm: Option<&str>) -> (String, Vec<String>) {
+    let words = |rest: &str| rest.split_whitespace().map(str::to_string).collect();
+    if let Some(comm) = comm.filter(|c| !c.is_empty()) {
+        let ends = cmdline
+            .match_indices(' ')
+            .map(|(i, _)| i)
+            .chain(std::iter::once(cmdline.len()));
+        for end in ends {
+            let name = cmdline[..end].rsplit('/').next().unwrap_or_default();
+            let matches = if comm.chars().count() >= COMM_MAX_LEN {
+                name.starts_with(comm)
+            } else {
+                name == comm
+            };
+            if matches {
+                return (cmdline[..end].to_string(), words(&cmdline[end..]));
+            }
+        }
+    }
+    let end = cmdline.find(char::is_whitespace).unwrap_or(cmdline.len());
+    let command = &cmdline[..end];
+    let command = command.strip_suffix(':').unwrap_or(command);
+    (command.to_string(), words(&cmdline[end..]))
+}
+
 fn parse_windows_processes(output: &str) -> Result<Vec<ProcessInfo>> {
     // Windows output is JSON from PowerShell
     let json: serde_json::Value =
@@ -83,6 +162,7 @@ fn parse_windows_processes(output: &str) -> Result<Vec<ProcessInfo>> {
                 working_directory: None,
                 environment: None,
                 evidence_ref: None,
+                cmdline_evidence_ref: None,
             });
         }
     }
@@ -95,6 +175,11 @@ pub fn parse_service_list(output: &str, os_type: OsType) -> Result<Vec<String>>
     match os_type {
         OsType::Linux => parse_linux_service_list(output),
         OsType::Windows => parse_windows_service_list(output),
+        OsType::MacOs => parse_macos_service_list(output),
+        OsType::Unix => Ok(parse_rc_services(output)
+            .into_iter()
+            .map(|s| s.name)
+            .collect()),
     }
 }
 
@@ -114,6 +199,23 @@ fn parse_linux_service_list(output: &str) -> Result<Vec<String>> {
     Ok(services)
 }
 
+fn parse_macos_service_list(output: &str) -> Result<Vec<String>> {
+    let mut services = Vec::new();
+
+    for line in output.lines().skip(1) {
+        // Format: PID Status Label (PID is "-" when not running)
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if let Some(label) = parts.get(2) {
+            // Apple's own jobs are part of the OS, not workloads
+            if !label.starts_with("com.apple.") {
+                services.push(label.to_string());
+            }
+        }
+    }
+
+    Ok(services)
+}
+
 fn parse_windows_service_list(output: &str) -> Result<Vec<String>> {
     let json: serde_json::Value =
         serde_json::from_str(output).unwrap_or(serde_json::Value::Array(vec![]));
@@ -131,6 +233,219 @@ fn parse_windows_service_list(output: &str) -> Result<Vec<String>> {
     Ok(services)
 }
 
+/// Parse rc script listings (`service -e` paths or `/etc/init.d` names) into
+/// services. Only the script is known; services are assumed enabled.
+pub fn parse_rc_services(output: &str) -> Vec<ServiceInfo> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let name = line.rsplit('/').next()?;
+            // Helpers shipped alongside init scripts
+            if matches!(
+                name,
+                "functions" | "README" | "rc" | "rcS" | "halt" | "killall"
+            ) {
+                return None;
+            }
+            let mut service = init_service(name, "enabled");
+            if line.starts_with('/') {
+                service.unit_file_path = Some(line.to_string());
+            }
+            Some(service)
+        })
+        .collect()
+}
+
+/// A service known only by its `/etc/init.d` script.
+fn init_service(name: &str, state: &str) -> ServiceInfo {
+    ServiceInfo {
+        name: name.to_string(),
+        display_name: None,
+        description: None,
+        state: state.to_string(),
+        sub_state: None,
+        start_mode: None,
+        exec_start: None,
+        exec_start_pre: vec![],
+        exec_start_post: vec![],
+        exec_stop: None,
+        working_directory: None,
+        user: None,
+        group: None,
+        environment: HashMap::new(),
+        environment_files: vec![],
+        unit_file_path: Some(format!("/etc/init.d/{}", name)),
+        dependencies: vec![],
+        wanted_by: vec![],
+        main_pid: None,
+        recovery: None,
+        evidence_ref: None,
+    }
+}
+
+/// Parse the output of the init system detection command.
+pub fn parse_init_system(output: &str) -> InitSystem {
+    match output.trim() {
+        "sysvinit" => InitSystem::SysVinit,
+        "openrc" => InitSystem::OpenRc,
+        _ => InitSystem::Systemd,
+    }
+}
+
+/// Parse the output of the privilege command: the user id on Unix, `True`
+/// or `False` on Windows.
+pub fn parse_privileged(output: &str, os_type: OsType) -> Option<bool> {
+    let output = output.trim();
+    match os_type {
+        OsType::Windows => match output {
+            "True" => Some(true),
+            "False" => Some(false),
+            _ => None,
+        },
+        _ => output.parse::<u32>().ok().map(|uid| uid == 0),
+    }
+}
+
+/// Parse a SysVinit (`chkconfig --list` or `/etc/init.d` listing) or OpenRC
+/// (`rc-status --all`) service list.
+pub fn parse_init_services(output: &str, init: InitSystem) -> Vec<ServiceInfo> {
+    let mut services: Vec<ServiceInfo> = Vec::new();
+
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let Some(name) = parts.first() else {
+            continue;
+        };
+        let service = match init {
+            InitSystem::OpenRc => {
+                // " nginx    [  started  ]"; runlevel headers have no brackets
+                if !line.starts_with(' ') || !line.contains('[') {
+                    continue;
+                }
+                let state = parts.get(2).copied().unwrap_or("unknown");
+                init_service(name, state)
+            }
+            // "sshd  0:off 1:off 2:on 3:on 4:on 5:on 6:off"
+            _ if parts.len() > 1 => {
+                // Skip the "xinetd based services:" section
+                if !parts[1].contains(':') || name.ends_with(':') {
+                    continue;
+                }
+                let enabled = parts[1..].iter().any(|p| p.ends_with(":on"));
+                init_service(name, if enabled { "enabled" } else { "disabled" })
+            }
+            _ => match parse_rc_services(name).pop() {
+                Some(service) => service,
+                None => continue,
+            },
+        };
+        if !services.iter().any(|s| s.name == service.name) {
+            services.push(service);
+        }
+    }
+
+    services
+}
+
+/// Glean the daemon command line, working directory and environment files
+/// from a SysVinit or OpenRC init script.
+pub fn parse_init_script(name: &str, content: &str) -> UnitFileInfo {
+    let mut info = UnitFileInfo::default();
+    let assignment = Regex::new(r"^(?:export\s+)?([A-Za-z_][A-Za-z0-9_]*)=(.*)$").unwrap();
+    let sourced = Regex::new(r"(?:^|[;&|]\s*)(?:\.|source)\s+(/\S+)").unwrap();
+    let mut vars: HashMap<String, String> = HashMap::new();
+    let mut start_stop_exec = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("#!") && line.contains("openrc-run") {
+            // OpenRC sources /etc/conf.d/<name> implicitly
+            info.environment_files.push(format!("/etc/conf.d/{}", name));
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(caps) = assignment.captures(line) {
+            let value = expand_shell_vars(unquote(&caps[2]), &vars);
+            vars.insert(caps[1].to_string(), value);
+            continue;
+        }
+        for caps in sourced.captures_iter(line) {
+            let path = expand_shell_vars(caps[1].trim_end_matches(['"', '\'', ';']), &vars);
+            // Function libraries are not configuration
+            if !path.contains("functions") && !info.environment_files.contains(&path) {
+                info.environment_files.push(path);
+            }
+        }
+        // start-stop-daemon --start ... --exec /usr/sbin/app -- args
+        if line.contains("start-stop-daemon") && start_stop_exec.is_none() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            for pair in words.windows(2) {
+                let value = expand_shell_vars(unquote(pair[1]), &vars);
+                match pair[0] {
+                    "--exec" | "-x" | "--startas" => start_stop_exec = Some(value),
+                    "--chdir" | "-d" => info.working_directory = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // OpenRC `command`, then the usual SysVinit variable names
+    let daemon = ["command", "DAEMON", "exec", "EXEC", "BIN", "BINARY"]
+        .iter()
+        .filter_map(|key| vars.get(*key))
+        .find(|value| value.starts_with('/'))
+        .cloned()
+        .or(start_stop_exec.filter(|value| value.starts_with('/')));
+    if let Some(daemon) = daemon {
+        let args = ["command_args", "DAEMON_ARGS", "DAEMON_OPTS", "OPTIONS"]
+            .iter()
+            .filter_map(|key| vars.get(*key))
+            .find(|value| !value.is_empty());
+        info.exec_start = Some(match args {
+            Some(args) => format!("{} {}", daemon, args),
+            None => daemon,
+        });
+    }
+    if let Some(dir) = vars.get("directory") {
+        info.working_directory = Some(dir.clone());
+    }
+
+    info
+}
+
+/// Strip one level of shell quotes.
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+/// Expand `$VAR`, `${VAR}` and `${VAR-default}` from already assigned
+/// variables; unknown variables expand to their default or stay as is.
+fn expand_shell_vars(value: &str, vars: &HashMap<String, String>) -> String {
+    let re = Regex::new(r"\$\{(\w+)(?::?-([^}]*))?\}|\$(\w+)").unwrap();
+    re.replace_all(value, |caps: &regex::Captures| {
+        let name = caps
+            .get(1)
+            .or(caps.get(3))
+            .map(|m| m.as_str())
+            .unwrap_or("");
+        match (vars.get(name), caps.get(2)) {
+            (Some(value), _) => value.clone(),
+            (None, Some(default)) => unquote(default.as_str()).to_string(),
+            (None, None) => caps[0].to_string(),
+        }
+    })
+    .into_owned()
+}
+
 /// Parse full service list with details (Windows only).
 /// The Windows list command already returns all fields we need, so we can
 /// skip the per-service queries entirely.
@@ -158,14 +473,15 @@ pub fn parse_windows_services_from_list(output: &str) -> Result<Vec<ServiceInfo>
                 exec_start_post: vec![],
                 exec_stop: None,
                 working_directory: None,
-                user: None,
+                user: windows_service_account(item),
                 group: None,
                 environment: HashMap::new(),
                 environment_files: vec![],
                 unit_file_path: None,
                 dependencies: vec![],
                 wanted_by: vec![],
-                main_pid: None,
+                main_pid: windows_service_pid(item),
+                recovery: None,
                 evidence_ref: None,
             });
         }
@@ -174,11 +490,131 @@ pub fn parse_windows_services_from_list(output: &str) -> Result<Vec<ServiceInfo>
     Ok(services)
 }
 
+/// Account a Windows service logs on as (`StartName`: `LocalSystem`,
+/// `NT AUTHORITY\LocalService`, `CORP\svc_app`...).
+fn windows_service_account(item: &serde_json::Value) -> Option<String> {
+    item["StartName"]
+        .as_str()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Process of a running Windows service (0 when stopped).
+fn windows_service_pid(item: &serde_json::Value) -> Option<u32> {
+    item["ProcessId"]
+        .as_u64()
+        .filter(|&pid| pid > 0)
+        .and_then(|pid| u32::try_from(pid).ok())
+}
+
+/// Add the dependencies listed by `Win32_DependentService` (`Antecedent`
+/// and `Dependent` names) to the services depending on them.
+pub fn apply_windows_service_dependencies(services: &mut [ServiceInfo], output: &str) {
+    let json: serde_json::Value = serde_json::from_str(output).unwrap_or_default();
+    // ConvertTo-Json writes a single object rather than an array of one
+    let pairs = match json {
+        serde_json::Value::Array(items) => items,
+        item @ serde_json::Value::Object(_) => vec![item],
+        _ => return,
+    };
+    for pair in &pairs {
+        let (Some(antecedent), Some(dependent)) =
+            (pair["Antecedent"].as_str(), pair["Dependent"].as_str())
+        else {
+            continue;
+        };
+        let Some(service) = services
+            .iter_mut()
+            .find(|s| s.name.eq_ignore_ascii_case(dependent))
+        else {
+            continue;
+        };
+        if !service
+            .dependencies
+            .iter()
+            .any(|d| d.eq_ignore_ascii_case(antecedent))
+        {
+            service.dependencies.push(antecedent.to_string());
+        }
+    }
+}
+
+/// Parse `sc.exe qfailure` output. `None` when the service has no
+/// failure actions.
+pub fn parse_sc_qfailure(output: &str) -> Option<ServiceRecovery> {
+    let action_re =
+        Regex::new(r"(RESTART|RUN PROCESS|REBOOT) -- Delay = (\d+) milliseconds").unwrap();
+    let mut recovery = ServiceRecovery::default();
+
+    for line in output.lines() {
+        if let Some(caps) = action_re.captures(line) {
+            recovery.actions.push(RecoveryAction {
+                action: match &caps[1] {
+                    "RESTART" => "restart",
+                    "RUN PROCESS" => "run",
+                    _ => "reboot",
+                }
+                .to_string(),
+                delay_ms: caps[2].parse().unwrap_or(0),
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            k if k.starts_with("RESET_PERIOD") => recovery.reset_period_secs = value.parse().ok(),
+            "COMMAND_LINE" if !value.is_empty() => recovery.command = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    (!recovery.actions.is_empty()).then_some(recovery)
+}
+
+/// Parse the registry export of `registry_key_cmd` into key paths (under
+/// `HKLM\\`) and their values.
+pub fn parse_registry_keys(output: &str) -> Vec<(String, BTreeMap<String, String>)> {
+    let json: serde_json::Value = serde_json::from_str(output).unwrap_or_default();
+    let keys = match json {
+        serde_json::Value::Array(items) => items,
+        item @ serde_json::Value::Object(_) => vec![item],
+        _ => return Vec::new(),
+    };
+    keys.iter()
+        .filter_map(|key| {
+            let name = key["Key"].as_str()?;
+            let path = match name.split_once('\\') {
+                Some((hive, rest)) if hive.eq_ignore_ascii_case("HKEY_LOCAL_MACHINE") => {
+                    format!("HKLM\\{}", rest)
+                }
+                _ => name.to_string(),
+            };
+            let values = key["Values"]
+                .as_object()
+                .map(|values| {
+                    values
+                        .iter()
+                        .filter_map(|(name, value)| {
+                            Some((name.clone(), value.as_str()?.to_string()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some((path, values))
+        })
+        .collect()
+}
+
 /// Parse service details.
 pub fn parse_service_details(output: &str, os_type: OsType) -> Result<ServiceInfo> {
     match os_type {
         OsType::Linux => parse_linux_service_details(output),
         OsType::Windows => parse_windows_service_details(output),
+        OsType::MacOs => parse_macos_service_details(output),
+        OsType::Unix => anyhow::bail!("No per-service details on generic Unix targets"),
     }
 }
 
@@ -203,6 +639,7 @@ fn parse_linux_service_details(output: &str) -> Result<ServiceInfo> {
         dependencies: vec![],
         wanted_by: vec![],
         main_pid: None,
+        recovery: None,
         evidence_ref: None,
     };
 
@@ -221,10 +658,8 @@ fn parse_linux_service_details(output: &str) -> Result<ServiceInfo> {
             "ActiveState" => service.state = value,
             "SubState" => service.sub_state = Some(value),
             "ExecStart" => service.exec_start = Some(value),
-            "WorkingDirectory" => {
-                if !value.is_empty() {
-                    service.working_directory = Some(value);
-                }
+            "WorkingDirectory" if !value.is_empty() => {
+                service.working_directory = Some(value);
             }
             "User" => service.user = Some(value),
             "Group" => service.group = Some(value),
@@ -252,6 +687,33 @@ fn parse_windows_service_details(output: &str) -> Result<ServiceInfo> {
         exec_start_post: vec![],
         exec_stop: None,
         working_directory: None,
+        user: windows_service_account(&json),
+        group: None,
+        environment: HashMap::new(),
+        environment_files: vec![],
+        unit_file_path: None,
+        dependencies: vec![],
+        wanted_by: vec![],
+        main_pid: windows_service_pid(&json),
+        recovery: None,
+        evidence_ref: None,
+    })
+}
+
+/// Parse `launchctl list <label>` output (a NeXTSTEP-style plist dictionary).
+fn parse_macos_service_details(output: &str) -> Result<ServiceInfo> {
+    let mut service = ServiceInfo {
+        name: String::new(),
+        display_name: None,
+        description: None,
+        state: "stopped".to_string(),
+        sub_state: None,
+        start_mode: None,
+        exec_start: None,
+        exec_start_pre: vec![],
+        exec_start_post: vec![],
+        exec_stop: None,
+        working_directory: None,
         user: None,
         group: None,
         environment: HashMap::new(),
@@ -260,42 +722,174 @@ fn parse_windows_service_details(output: &str) -> Result<ServiceInfo> {
         dependencies: vec![],
         wanted_by: vec![],
         main_pid: None,
+        recovery: None,
         evidence_ref: None,
-    })
+    };
+    let mut program = None;
+    let mut arguments: Option<Vec<String>> = None;
+    let mut in_arguments = false;
+
+    for line in output.lines() {
+        let line = line.trim().trim_end_matches(';');
+        if in_arguments {
+            if line == ")" {
+                in_arguments = false;
+            } else if let Some(args) = arguments.as_mut() {
+                args.push(line.trim_matches('"').to_string());
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        let value = value.trim_matches('"');
+        match key.trim_matches('"') {
+            "Label" => service.name = value.to_string(),
+            "PID" => {
+                service.main_pid = value.parse().ok();
+                service.state = "running".to_string();
+            }
+            "LastExitStatus" => service.sub_state = Some(format!("exit {}", value)),
+            "Program" => program = Some(value.to_string()),
+            "ProgramArguments" if value == "(" => {
+                arguments = Some(Vec::new());
+                in_arguments = true;
+            }
+            _ => {}
+        }
+    }
+
+    service.exec_start = arguments
+        .filter(|args| !args.is_empty())
+        .map(|args| args.join(" "))
+        .or(program);
+
+    Ok(service)
 }
 
 /// Parse systemd unit file content.
+#[derive(Debug, Default)]
 pub struct UnitFileInfo {
     pub exec_start: Option<String>,
     pub working_directory: Option<String>,
     pub environment_files: Vec<String>,
     pub environment: HashMap<String, String>,
+    /// Units required, wanted or ordered before this one.
+    pub dependencies: Vec<String>,
+    /// Units ordered after this one (`Before=`).
+    pub before: Vec<String>,
+    /// Targets and units pulling this one in (`WantedBy=`, `RequiredBy=`).
+    pub wanted_by: Vec<String>,
+    /// Drop-in files applied on top of the unit file.
+    pub drop_ins: Vec<String>,
 }
 
+/// Dependency directives, in the order they are merged into
+/// [`UnitFileInfo::dependencies`].
+const UNIT_DEPENDENCY_KEYS: &[&str] = &["Requires", "Requisite", "BindsTo", "Wants", "After"];
+
+/// Parse `systemctl cat` output: the unit file followed by its drop-ins,
+/// each introduced by a `# /path` comment. Later assignments override
+/// earlier ones and an empty assignment resets a list.
 pub fn parse_systemd_unit(content: &str) -> UnitFileInfo {
-    let mut info = UnitFileInfo {
-        exec_start: None,
-        working_directory: None,
-        environment_files: vec![],
-        environment: HashMap::new(),
-    };
+    let mut info = UnitFileInfo::default();
+    let mut lists: HashMap<&str, Vec<String>> = HashMap::new();
 
     for line in content.lines() {
         let line = line.trim();
-        if line.starts_with("ExecStart=") {
-            info.exec_start = Some(line.trim_start_matches("ExecStart=").to_string());
-        } else if line.starts_with("WorkingDirectory=") {
-            info.working_directory = Some(line.trim_start_matches("WorkingDirectory=").to_string());
-        } else if line.starts_with("EnvironmentFile=") {
-            let path = line
-                .trim_start_matches("EnvironmentFile=")
-                .trim_start_matches('-');
-            info.environment_files.push(path.to_string());
-        } else if line.starts_with("Environment=") {
-            let env = line.trim_start_matches("Environment=");
-            if let Some((key, value)) = env.split_once('=') {
-                info.environment
-                    .insert(key.to_string(), value.trim_matches('"').to_string());
+        if let Some(path) = line.strip_prefix("# /") {
+            if path.contains(".d/") && path.ends_with(".conf") {
+                info.drop_ins.push(format!("/{}", path));
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "ExecStart" => info.exec_start = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "WorkingDirectory" => {
+                info.working_directory = Some(value.to_string()).filter(|v| !v.is_empty());
+            }
+            "EnvironmentFile" if value.is_empty() => info.environment_files.clear(),
+            "EnvironmentFile" => {
+                info.environment_files
+                    .push(value.trim_start_matches('-').to_string());
+            }
+            "Environment" => {
+                if let Some((key, value)) = value.split_once('=') {
+                    info.environment
+                        .insert(key.to_string(), value.trim_matches('"').to_string());
+                }
+            }
+            key @ ("Requires" | "Requisite" | "BindsTo" | "Wants" | "After" | "Before"
+            | "WantedBy" | "RequiredBy") => {
+                let list = lists.entry(key).or_default();
+                if value.is_empty() {
+                    list.clear();
+                }
+                list.extend(value.split_whitespace().map(str::to_string));
+            }
+            _ => {}
+        }
+    }
+
+    info.dependencies = merge_units(&mut lists, UNIT_DEPENDENCY_KEYS);
+    info.before = merge_units(&mut lists, &["Before"]);
+    info.wanted_by = merge_units(&mut lists, &["WantedBy", "RequiredBy"]);
+
+    info
+}
+
+/// The units listed under `keys`, without duplicates.
+fn merge_units(lists: &mut HashMap<&str, Vec<String>>, keys: &[&str]) -> Vec<String> {
+    let mut units: Vec<String> = Vec::new();
+    for key in keys {
+        for unit in lists.remove(*key).unwrap_or_default() {
+            if !units.contains(&unit) {
+                units.push(unit);
+            }
+        }
+    }
+    units
+}
+
+/// Add `Before=` ordering to the dependencies of the units it names, so
+/// every service lists the services it starts after.
+pub fn apply_unit_ordering(services: &mut [ServiceInfo], before: &[(String, Vec<String>)]) {
+    for (name, units) in before {
+        for service in services.iter_mut() {
+            if units.contains(&service.name) && !service.dependencies.contains(name) {
+                service.dependencies.push(name.clone());
+            }
+        }
+    }
+}
+
+/// Parse a launchd job plist, converted to JSON by `plutil`.
+pub fn parse_launchd_plist(content: &str) -> UnitFileInfo {
+    let mut info = UnitFileInfo::default();
+
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(content) else {
+        return info;
+    };
+
+    let arguments: Vec<&str> = json["ProgramArguments"]
+        .as_array()
+        .map(|args| args.iter().filter_map(|a| a.as_str()).collect())
+        .unwrap_or_default();
+    info.exec_start = if arguments.is_empty() {
+        json["Program"].as_str().map(|s| s.to_string())
+    } else {
+        Some(arguments.join(" "))
+    };
+    info.working_directory = json["WorkingDirectory"].as_str().map(|s| s.to_string());
+    if let Some(vars) = json["EnvironmentVariables"].as_object() {
+        for (key, value) in vars {
+            if let Some(value) = value.as_str() {
+                info.environment.insert(key.clone(), value.to_string());
             }
         }
     }
@@ -308,21 +902,52 @@ pub fn parse_ports(output: &str, os_type: OsType) -> Result<Vec<PortInfo>> {
     match os_type {
         OsType::Linux => parse_linux_ports(output),
         OsType::Windows => parse_windows_ports(output),
+        OsType::MacOs => parse_lsof_ports(output),
+        OsType::Unix => parse_netstat_ports(output),
     }
 }
 
 fn parse_linux_ports(output: &str) -> Result<Vec<PortInfo>> {
     let mut ports = Vec::new();
-    // Pattern for ss -lntup output:
+    // Pattern for ss -lntupx output:
     //   Netid  State  Recv-Q  Send-Q  Local Address:Port  Peer Address:Port  Process
     //   tcp    LISTEN 0       128     0.0.0.0:8080        0.0.0.0:*          users:(("python3",pid=7,fd=3))
+    //   tcp    LISTEN 0       511     [::]:8080           [::]:*
     let re = Regex::new(concat!(
-        r"(?P<proto>tcp|udp)\s+(?P<state>\w+)\s+\d+\s+\d+\s+",
+        r"^(?P<proto>tcp|udp)\s+(?P<state>\w+)\s+\d+\s+\d+\s+",
         r"(?P<local>\S+):(?P<port>\d+)\s+\S+:\S+\s*",
         r#"(?:users:\(\("(?P<name>[^"]+)",pid=(?P<pid>\d+))?"#,
     ))?;
+    // Unix sockets give their path and inode instead of address and port:
+    //   u_str  LISTEN 0       511     /run/php/php8.2-fpm.sock 23456  * 0  users:(("php-fpm8.2",pid=20,fd=8))
+    let unix_re = Regex::new(concat!(
+        r"^u_(?:str|seq|dgr)\s+(?P<state>\w+)\s+\d+\s+\d+\s+",
+        r"(?P<path>\S+)\s+\d+\s+\S+\s+\d+\s*",
+        r#"(?:users:\(\("(?P<name>[^"]+)",pid=(?P<pid>\d+))?"#,
+    ))?;
 
     for line in output.lines().skip(1) {
+        if let Some(caps) = unix_re.captures(line) {
+            // Unnamed and abstract (`@name`) sockets have no path to share
+            let path = &caps["path"];
+            if !path.starts_with('/') {
+                continue;
+            }
+            ports.push(PortInfo {
+                protocol: "unix".to_string(),
+                local_address: path.to_string(),
+                local_port: 0,
+                state: caps["state"].to_string(),
+                pid: caps.name("pid").and_then(|m| m.as_str().parse().ok()),
+                process_name: caps.name("name").map(|m| m.as_str().to_string()),
+                evidence_ref: None,
+                pid_source: None,
+                pid_evidence_ref: None,
+                family: Some("unix".to_string()),
+                socket_path: Some(path.to_string()),
+            });
+            continue;
+        }
         if let Some(caps) = re.captures(line) {
             let port: u16 = caps
                 .name("port")
@@ -349,6 +974,10 @@ fn parse_linux_ports(output: &str) -> Result<Vec<PortInfo>> {
                 pid: caps.name("pid").and_then(|m| m.as_str().parse().ok()),
                 process_name: caps.name("name").map(|m| m.as_str().to_string()),
                 evidence_ref: None,
+                pid_source: None,
+                pid_evidence_ref: None,
+                family: Some(ip_family(&caps["local"]).to_string()),
+                socket_path: None,
             });
         }
     }
@@ -356,40 +985,182 @@ fn parse_linux_ports(output: &str) -> Result<Vec<PortInfo>> {
     Ok(ports)
 }
 
-fn parse_windows_ports(output: &str) -> Result<Vec<PortInfo>> {
-    let json: serde_json::Value =
-        serde_json::from_str(output).unwrap_or(serde_json::Value::Array(vec![]));
+/// Address family of a listener address.
+fn ip_family(address: &str) -> &'static str {
+    if is_ipv6_address(address) {
+        "ipv6"
+    } else {
+        "ipv4"
+    }
+}
 
-    let mut ports = Vec::new();
+fn parse_lsof_ports(output: &str) -> Result<Vec<PortInfo>> {
+    let mut ports: Vec<PortInfo> = Vec::new();
 
-    if let Some(array) = json.as_array() {
-        for item in array {
-            ports.push(PortInfo {
-                protocol: "tcp".to_string(),
-                local_address: item["LocalAddress"].as_str().unwrap_or("").to_string(),
-                local_port: item["LocalPort"].as_u64().unwrap_or(0) as u16,
-                state: item["State"].as_str().unwrap_or("").to_string(),
-                pid: item["OwningProcess"].as_u64().map(|p| p as u32),
-                process_name: None,
-                evidence_ref: None,
-            });
+    for line in output.lines().skip(1) {
+        // Format: COMMAND PID USER FD TYPE DEVICE SIZE/OFF NODE NAME (STATE)
+        //   nginx  612 www  6u  IPv4 0x1a2b   0t0      TCP  *:8080 (LISTEN)
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let Some(node) = parts.iter().position(|p| *p == "TCP" || *p == "UDP") else {
+            continue;
+        };
+        let Some((address, port)) = parts.get(node + 1).and_then(|n| n.rsplit_once(':')) else {
+            continue;
+        };
+        let Ok(port) = port.parse::<u16>() else {
+            continue;
+        };
+        let pid = parts.get(1).and_then(|p| p.parse().ok());
+
+        // lsof lists a socket once per file descriptor
+        if ports
+            .iter()
+            .any(|p| p.pid == pid && p.local_port == port && p.local_address == address)
+        {
+            continue;
         }
+
+        ports.push(PortInfo {
+            protocol: parts[node].to_lowercase(),
+            local_address: address.to_string(),
+            local_port: port,
+            state: parts
+                .get(node + 2)
+                .map(|s| s.trim_matches(|c| c == '(' || c == ')').to_string())
+                .unwrap_or("LISTEN".to_string()),
+            pid,
+            process_name: parts.first().map(|s| s.to_string()),
+            evidence_ref: None,
+            pid_source: None,
+            pid_evidence_ref: None,
+            // `*` stands for either family, which the TYPE column tells
+            family: Some(
+                match parts.get(4) {
+                    Some(&"IPv6") => "ipv6",
+                    Some(&"IPv4") => "ipv4",
+                    _ => ip_family(address),
+                }
+                .to_string(),
+            ),
+            socket_path: None,
+        });
     }
 
     Ok(ports)
 }
 
-/// Parse package list output.
-pub fn parse_packages(output: &str, os_type: OsType, command: &str) -> Result<Vec<Package>> {
-    match os_type {
-        OsType::Linux => {
-            if command.contains("dpkg") {
+fn parse_netstat_ports(output: &str) -> Result<Vec<PortInfo>> {
+    let mut ports: Vec<PortInfo> = Vec::new();
+
+    for line in output.lines() {
+        // Format: Proto Recv-Q Send-Q Local-Address Foreign-Address (state)
+        //   BSD:   tcp4  0 0 *.22          *.*        LISTEN
+        //   Linux: tcp   0 0 0.0.0.0:22    0.0.0.0:*  LISTEN
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 5 {
+            continue;
+        }
+        let protocol = parts[0].trim_end_matches(['4', '6']);
+        // BSD suffixes the protocol with the family (tcp46 is dual-stack)
+        let family = match &parts[0][protocol.len()..] {
+            "" => None,
+            "4" => Some("ipv4"),
+            _ => Some("ipv6"),
+        };
+        let listening = match protocol {
+            "tcp" => parts.get(5) == Some(&"LISTEN"),
+            // Unconnected UDP sockets are the listeners
+            "udp" => parts[4].ends_with('*'),
+            _ => false,
+        };
+        if !listening {
+            continue;
+        }
+
+        // BSD separates the port with a dot, Linux with a colon
+        let Some((address, port)) = split_port(parts[3], '.').or_else(|| split_port(parts[3], ':'))
+        else {
+            continue;
+        };
+        if ports
+            .iter()
+            .any(|p| p.protocol == protocol && p.local_port == port && p.local_address == address)
+        {
+            continue;
+        }
+
+        ports.push(PortInfo {
+            protocol: protocol.to_string(),
+            local_address: address.to_string(),
+            local_port: port,
+            state: parts.get(5).unwrap_or(&"LISTEN").to_string(),
+            pid: None, // netstat -an does not map sockets to processes
+            process_name: None,
+            evidence_ref: None,
+            pid_source: None,
+            pid_evidence_ref: None,
+            family: Some(family.unwrap_or_else(|| ip_family(address)).to_string()),
+            socket_path: None,
+        });
+    }
+
+    Ok(ports)
+}
+
+/// Split `address<sep>port`, if what follows the last separator is a port.
+fn split_port(local: &str, separator: char) -> Option<(&str, u16)> {
+    let (address, port) = local.rsplit_once(separator)?;
+    Some((address, port.parse().ok()?))
+}
+
+fn parse_windows_ports(output: &str) -> Result<Vec<PortInfo>> {
+    let json: serde_json::Value =
+        serde_json::from_str(output).unwrap_or(serde_json::Value::Array(vec![]));
+
+    let mut ports = Vec::new();
+
+    if let Some(array) = json.as_array() {
+        for item in array {
+            ports.push(PortInfo {
+                protocol: "tcp".to_string(),
+                local_address: item["LocalAddress"].as_str().unwrap_or("").to_string(),
+                local_port: item["LocalPort"].as_u64().unwrap_or(0) as u16,
+                state: item["State"].as_str().unwrap_or("").to_string(),
+                pid: item["OwningProcess"].as_u64().map(|p| p as u32),
+                process_name: None,
+                evidence_ref: None,
+                pid_source: None,
+                pid_evidence_ref: None,
+                family: Some(ip_family(item["LocalAddress"].as_str().unwrap_or("")).to_string()),
+                socket_path: None,
+            });
+        }
+    }
+
+    Ok(ports)
+}
+
+/// Parse package list output.
+pub fn parse_packages(output: &str, os_type: OsType, command: &str) -> Result<Vec<Package>> {
+    match os_type {
+        OsType::Linux => {
+            if command.contains("dpkg") {
                 parse_dpkg_packages(output)
             } else {
                 parse_rpm_packages(output)
             }
         }
         OsType::Windows => parse_windows_packages(output),
+        OsType::MacOs => parse_pkgutil_packages(output),
+        OsType::Unix => {
+            if command.starts_with("pkg ") {
+                parse_pkg_packages(output)
+            } else if command.contains("dpkg") {
+                parse_dpkg_packages(output)
+            } else {
+                parse_rpm_packages(output)
+            }
+        }
     }
 }
 
@@ -410,6 +1181,7 @@ fn parse_dpkg_packages(output: &str) -> Result<Vec<Package>> {
                 description: None,
                 install_date: None,
                 source: "dpkg".to_string(),
+                evidence_ref: None,
             });
         }
     }
@@ -430,6 +1202,7 @@ fn parse_rpm_packages(output: &str) -> Result<Vec<Package>> {
                 description: None,
                 install_date: None,
                 source: "rpm".to_string(),
+                evidence_ref: None,
             });
         }
     }
@@ -437,6 +1210,50 @@ fn parse_rpm_packages(output: &str) -> Result<Vec<Package>> {
     Ok(packages)
 }
 
+fn parse_pkg_packages(output: &str) -> Result<Vec<Package>> {
+    let mut packages = Vec::new();
+
+    for line in output.lines() {
+        // Format: name-version  comment (e.g. "nginx-1.24.0_12,3  Robust and small WWW server")
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let Some((name, version)) = parts.next().and_then(|p| p.rsplit_once('-')) else {
+            continue;
+        };
+        packages.push(Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            architecture: None,
+            description: parts
+                .next()
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty()),
+            install_date: None,
+            source: "pkg".to_string(),
+            evidence_ref: None,
+        });
+    }
+
+    Ok(packages)
+}
+
+fn parse_pkgutil_packages(output: &str) -> Result<Vec<Package>> {
+    // `pkgutil --pkgs` lists receipt identifiers only, without versions
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|id| Package {
+            name: id.to_string(),
+            version: String::new(),
+            architecture: None,
+            description: None,
+            install_date: None,
+            source: "pkgutil".to_string(),
+            evidence_ref: None,
+        })
+        .collect())
+}
+
 fn parse_windows_packages(output: &str) -> Result<Vec<Package>> {
     let json: serde_json::Value =
         serde_json::from_str(output).unwrap_or(serde_json::Value::Array(vec![]));
@@ -452,6 +1269,7 @@ fn parse_windows_packages(output: &str) -> Result<Vec<Package>> {
                 description: None,
                 install_date: None,
                 source: "windows".to_string(),
+                evidence_ref: None,
             });
         }
     }
@@ -459,37 +1277,108 @@ fn parse_windows_packages(output: &str) -> Result<Vec<Package>> {
     Ok(packages)
 }
 
-/// Parse scheduled tasks output.
-pub fn parse_scheduled_tasks(output: &str, os_type: OsType) -> Result<Vec<ScheduledTask>> {
+/// Parse scheduled tasks output. `command` is the command that produced the
+/// output: system crontabs (`/etc/crontab`, `/etc/cron.d`) carry a user
+/// column, systemd timer listings are told apart from crontabs by it too.
+pub fn parse_scheduled_tasks(
+    output: &str,
+    os_type: OsType,
+    command: &str,
+) -> Result<Vec<ScheduledTask>> {
     match os_type {
-        OsType::Linux => parse_linux_scheduled_tasks(output),
         OsType::Windows => parse_windows_scheduled_tasks(output),
+        _ if command.contains("list-timers") => parse_systemd_timers(output),
+        _ => parse_crontab(
+            output,
+            command.contains("/etc/crontab") || command.contains("/etc/cron.d"),
+        ),
     }
 }
 
-fn parse_linux_scheduled_tasks(output: &str) -> Result<Vec<ScheduledTask>> {
+/// Parse `systemctl list-timers --no-legend` output. The columns before the
+/// unit are timestamps of varying width, so the timer is located by its
+/// suffix and the activated unit follows it.
+fn parse_systemd_timers(output: &str) -> Result<Vec<ScheduledTask>> {
     let mut tasks = Vec::new();
 
     for line in output.lines() {
         let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            tasks.push(ScheduledTask {
-                name: parts[0].to_string(),
-                task_type: "systemd-timer".to_string(),
-                schedule: parts.get(1).map(|s| s.to_string()),
-                command: None,
-                user: None,
-                enabled: true,
-                last_run: None,
-                next_run: None,
-                evidence_ref: None,
-            });
+        let Some(pos) = parts.iter().position(|p| p.ends_with(".timer")) else {
+            continue;
+        };
+        tasks.push(ScheduledTask {
+            name: parts[pos].to_string(),
+            task_type: "systemd-timer".to_string(),
+            schedule: None,
+            command: parts.get(pos + 1).map(|s| s.to_string()),
+            user: None,
+            enabled: true,
+            last_run: None,
+            next_run: None,
+            evidence_ref: None,
+        });
+    }
+
+    Ok(tasks)
+}
+
+/// Parse crontab entries. System crontabs have a user column between the
+/// schedule and the command; per-user crontabs are concatenated by the
+/// collector with a `# user: <name>` line before each one.
+fn parse_crontab(output: &str, system_format: bool) -> Result<Vec<ScheduledTask>> {
+    let mut tasks = Vec::new();
+    let mut current_user = None;
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(user) = line.strip_prefix("# user:") {
+            current_user = Some(user.trim().to_string());
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        // "@daily cmd" or five schedule fields then the command;
+        // skip variable assignments such as SHELL=/bin/sh
+        let fields = if parts[0].starts_with('@') { 1 } else { 5 };
+        let command_start = if system_format { fields + 1 } else { fields };
+        if parts.len() <= command_start || parts[0].contains('=') {
+            continue;
         }
+        let user = if system_format {
+            Some(parts[fields].to_string())
+        } else {
+            current_user.clone()
+        };
+        let command = parts[command_start..].join(" ");
+        tasks.push(ScheduledTask {
+            name: cron_task_name(&command),
+            task_type: "cron".to_string(),
+            schedule: Some(parts[..fields].join(" ")),
+            command: Some(command),
+            user,
+            enabled: true,
+            last_run: None,
+            next_run: None,
+            evidence_ref: None,
+        });
     }
 
     Ok(tasks)
 }
 
+/// Name a cron job after the basename of the executable it runs.
+fn cron_task_name(command: &str) -> String {
+    let executable = command.split_whitespace().next().unwrap_or(command);
+    executable
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(executable)
+        .to_string()
+}
+
 fn parse_windows_scheduled_tasks(output: &str) -> Result<Vec<ScheduledTask>> {
     let json: serde_json::Value =
         serde_json::from_str(output).unwrap_or(serde_json::Value::Array(vec![]));
@@ -518,27 +1407,559 @@ fn parse_windows_scheduled_tasks(output: &str) -> Result<Vec<ScheduledTask>> {
     Ok(tasks)
 }
 
+/// Parse one resource sample taken at `timestamp`.
+pub fn parse_resource_samples(
+    output: &str,
+    os_type: OsType,
+    timestamp: DateTime<Utc>,
+) -> Vec<ResourceSample> {
+    let usage = match os_type {
+        OsType::Linux => parse_proc_stat(output),
+        OsType::Windows => parse_windows_resources(output),
+        OsType::MacOs | OsType::Unix => parse_ps_resources(output),
+    };
+    usage
+        .into_iter()
+        .map(|(pid, cpu_seconds, rss_bytes)| ResourceSample {
+            pid,
+            timestamp,
+            cpu_seconds,
+            rss_bytes,
+            evidence_ref: None,
+        })
+        .collect()
+}
+
+/// `/proc/<pid>/stat` lines followed by `# <CLK_TCK> <PAGESIZE>`.
+fn parse_proc_stat(output: &str) -> Vec<(u32, f64, u64)> {
+    let (ticks, page_size) = output
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .and_then(|units| {
+            let mut parts = units.split_whitespace();
+            Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+        })
+        .unwrap_or((100.0, 4096u64));
+
+    output
+        .lines()
+        .filter_map(|line| {
+            // The command name may contain spaces; fields resume after ')'
+            let (head, rest) = line.rsplit_once(')')?;
+            let pid = head.split_whitespace().next()?.parse().ok()?;
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            // state is field 3, utime/stime 14-15 and rss 24 of stat(5)
+            let utime: f64 = fields.get(11)?.parse().ok()?;
+            let stime: f64 = fields.get(12)?.parse().ok()?;
+            let rss: u64 = fields.get(21)?.parse().ok()?;
+            Some((pid, (utime + stime) / ticks, rss * page_size))
+        })
+        .collect()
+}
+
+/// `Get-Process | Select-Object Id,CPU,WorkingSet64` as JSON.
+fn parse_windows_resources(output: &str) -> Vec<(u32, f64, u64)> {
+    let json: serde_json::Value =
+        serde_json::from_str(output).unwrap_or(serde_json::Value::Array(vec![]));
+    // A single process is not wrapped in an array
+    let items = match json {
+        serde_json::Value::Array(items) => items,
+        item @ serde_json::Value::Object(_) => vec![item],
+        _ => vec![],
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            Some((
+                item["Id"].as_u64()? as u32,
+                item["CPU"].as_f64().unwrap_or(0.0),
+                item["WorkingSet64"].as_u64().unwrap_or(0),
+            ))
+        })
+        .collect()
+}
+
+/// `ps -o pid=,time=,rss=` output: cumulative CPU time and RSS in KiB.
+fn parse_ps_resources(output: &str) -> Vec<(u32, f64, u64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let pid = parts.next()?.parse().ok()?;
+            let cpu_seconds = parse_cpu_time(parts.next()?)?;
+            let rss_kib: u64 = parts.next()?.parse().ok()?;
+            Some((pid, cpu_seconds, rss_kib * 1024))
+        })
+        .collect()
+}
+
+/// Parse a `[[dd-]hh:]mm:ss[.cc]` CPU time into seconds.
+fn parse_cpu_time(value: &str) -> Option<f64> {
+    let (days, clock) = match value.split_once('-') {
+        Some((days, clock)) => (days.parse::<f64>().ok()?, clock),
+        None => (0.0, value),
+    };
+    let mut seconds = 0.0;
+    for part in clock.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(days * 86400.0 + seconds)
+}
+
+/// Parse the output of a runtime version command (`node --version`,
+/// `java -version`, ...). Returns the first version number found.
+pub fn parse_runtime_version(output: &str) -> Option<String> {
+    let re = Regex::new(r"(\d+(?:\.\d+)+)").unwrap();
+    re.captures(output).map(|caps| caps[1].to_string())
+}
+
+/// Parse `file -L` output for an executable.
+pub fn parse_binary_info(path: &str, output: &str) -> BinaryInfo {
+    let description = output
+        .trim()
+        .strip_prefix(&format!("{}:", path))
+        .unwrap_or(output.trim())
+        .trim();
+
+    let (kind, interpreter) = if description.contains("ELF") {
+        let interpreter = description
+            .split(", ")
+            .find_map(|part| part.strip_prefix("interpreter "))
+            .map(|s| s.to_string());
+        ("elf", interpreter)
+    } else if description.contains("Mach-O") {
+        ("macho", None)
+    } else if let Some(head) = description
+        .split(',')
+        .next()
+        .filter(|h| h.ends_with(" script"))
+    {
+        // "a /usr/bin/env python3 script" or "Python script"
+        let head = head.trim_end_matches(" script");
+        let interpreter = head.strip_prefix("a ").unwrap_or(head).to_lowercase();
+        ("script", Some(interpreter))
+    } else {
+        ("unknown", None)
+    };
+
+    BinaryInfo {
+        path: path.to_string(),
+        kind: kind.to_string(),
+        interpreter,
+        evidence_ref: None,
+    }
+}
+
+/// Parse `mode|user|group|path` lines printed by `stat` into the owner
+/// (`user:group`) and four-digit octal mode of each path.
+pub fn parse_file_stats(output: &str) -> HashMap<String, (String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim_end().splitn(4, '|');
+            let mode = fields.next()?;
+            let (user, group, path) = (fields.next()?, fields.next()?, fields.next()?);
+            if mode.is_empty() || mode.len() > 4 || !mode.chars().all(|c| ('0'..='7').contains(&c))
+            {
+                return None;
+            }
+            Some((
+                path.to_string(),
+                (format!("{}:{}", user, group), format!("{:0>4}", mode)),
+            ))
+        })
+        .collect()
+}
+
+/// Parse `lsof -F pn` records (`p<pid>` then `n<path>` lines) into
+/// (pid, path) pairs, in order. Empty names are left out.
+pub fn parse_lsof_names(output: &str) -> Vec<(u32, String)> {
+    let mut names = Vec::new();
+    let mut pid = None;
+    for line in output.lines() {
+        let line = line.trim_end();
+        if let Some(value) = line.strip_prefix('p') {
+            pid = value.parse::<u32>().ok();
+        } else if let (Some(name), Some(pid)) = (line.strip_prefix('n'), pid) {
+            if !name.is_empty() {
+                names.push((pid, name.to_string()));
+            }
+        }
+    }
+    names
+}
+
+/// Parse `lsof -F pn` records into the working directory of each process.
+/// Deleted directories are left out.
+pub fn parse_process_cwds(output: &str) -> HashMap<u32, String> {
+    let mut cwds = HashMap::new();
+    for (pid, path) in parse_lsof_names(output) {
+        if path.starts_with('/') && !path.ends_with(" (deleted)") {
+            cwds.entry(pid).or_insert(path);
+        }
+    }
+    cwds
+}
+
+/// Parse the `p<pid>` and `a<arg>` records of `process_cmdline_cmd` into
+/// the arguments of each process. Trailing empty arguments, left by
+/// processes that overwrite their arguments, are dropped, and kernel
+/// threads, which have none, are left out.
+pub fn parse_process_cmdlines(output: &str) -> HashMap<u32, Vec<String>> {
+    let mut cmdlines: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut pid = None;
+    for line in output.lines() {
+        if let Some(value) = line.strip_prefix('p') {
+            pid = value.trim_end().parse::<u32>().ok();
+        } else if let (Some(arg), Some(pid)) = (line.strip_prefix('a'), pid) {
+            cmdlines.entry(pid).or_default().push(arg.to_string());
+        }
+    }
+    for args in cmdlines.values_mut() {
+        while args.last().is_some_and(|a| a.is_empty()) {
+            args.pop();
+        }
+    }
+    cmdlines.retain(|_, args| !args.is_empty());
+    cmdlines
+}
+
+/// Parse the `<path>\t<packages>` lines of `package_owner_cmd` into each
+/// file and the packages owning it, none for a file no package owns.
+pub fn parse_package_owners(output: &str) -> Vec<(String, Vec<String>)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (path, packages) = line.split_once('\t')?;
+            let packages = packages
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_string)
+                .collect();
+            path.starts_with('/').then(|| (path.to_string(), packages))
+        })
+        .collect()
+}
+
+/// Parse a hosts file into its entries, leaving out comments.
+pub fn parse_hosts_file(output: &str) -> Vec<HostEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next()?;
+            let mut fields = line.split_whitespace();
+            let address = fields.next()?;
+            let names: Vec<String> = fields.map(str::to_lowercase).collect();
+            (address.parse::<std::net::IpAddr>().is_ok() && !names.is_empty()).then(|| HostEntry {
+                address: address.to_string(),
+                names,
+            })
+        })
+        .collect()
+}
+
+/// Parse `/etc/resolv.conf` into its name servers and search domains.
+/// `domain` is the search list of one domain, and the last of `domain`
+/// and `search` wins.
+pub fn parse_resolv_conf(output: &str) -> (Vec<String>, Vec<String>) {
+    let mut nameservers = Vec::new();
+    let mut search = Vec::new();
+    for line in output.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => nameservers.extend(fields.next().map(str::to_string)),
+            Some("search") | Some("domain") => {
+                search = fields
+                    .take_while(|f| !f.starts_with(['#', ';']))
+                    .map(|f| f.trim_end_matches('.').to_lowercase())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    (nameservers, search)
+}
+
+/// Parse the `<name>\t<addresses>` lines of `host_lookup_cmd` into each
+/// host name and its addresses, none for a name that did not resolve.
+pub fn parse_host_lookups(output: &str) -> Vec<(String, Vec<String>)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, addresses) = line.split_once('\t')?;
+            let addresses = addresses
+                .split(',')
+                .map(str::trim)
+                .filter(|a| a.parse::<std::net::IpAddr>().is_ok())
+                .map(str::to_string)
+                .collect();
+            (!name.is_empty()).then(|| (name.to_lowercase(), addresses))
+        })
+        .collect()
+}
+
+/// Host names a configuration names: the hosts of URLs and the values of
+/// `host`, `hostname` and `server` settings (`DB_HOST=db`, `host: db`).
+/// Addresses and `localhost` are left out.
+pub fn config_host_names(content: &str) -> Vec<String> {
+    let url = Regex::new(r"[A-Za-z][A-Za-z0-9+.-]*://(?:[^@/\s]*@)?([A-Za-z0-9.-]+)").unwrap();
+    let setting = Regex::new(
+        r#"(?i)(?:host|hostname|server)\s*["']?\s*[=:]\s*["']?([A-Za-z0-9][A-Za-z0-9.-]*)"#,
+    )
+    .unwrap();
+    let mut names: Vec<String> = Vec::new();
+    for capture in url
+        .captures_iter(content)
+        .chain(setting.captures_iter(content))
+    {
+        let name = capture[1].trim_end_matches('.').to_lowercase();
+        let is_name = name.chars().any(|c| c.is_ascii_alphabetic())
+            && name.parse::<std::net::IpAddr>().is_err()
+            && name != "localhost"
+            && !name.starts_with(['-', '.']);
+        if is_name && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// A listening socket and the process holding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketOwner {
+    /// `tcp` or `udp`.
+    pub protocol: String,
+    pub ipv6: bool,
+    pub port: u16,
+    pub pid: u32,
+}
+
+/// Parse the `/proc/net/*` socket tables and the process socket
+/// descriptors listed by `port_owner_cmd` into the listening sockets whose
+/// holder was found. A socket shared by a process and its children goes to
+/// the lowest PID, usually the parent.
+pub fn parse_socket_owners(output: &str) -> Vec<SocketOwner> {
+    // (protocol, IPv6, port, inode)
+    let mut sockets: Vec<(&str, bool, u16, u64)> = Vec::new();
+    let mut inode_pids: HashMap<u64, u32> = HashMap::new();
+    let mut section = "";
+    let mut pid: Option<u32> = None;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("# ") {
+            section = name;
+            continue;
+        }
+        match section {
+            "tcp" | "tcp6" | "udp" | "udp6" => {
+                // sl local_address rem_address st tx:rx tr:when retrnsmt uid timeout inode
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 10 {
+                    continue;
+                }
+                let protocol = &section[..3];
+                // 0A is TCP_LISTEN; unconnected UDP sockets are 07 (TCP_CLOSE)
+                let listening = fields[3] == if protocol == "tcp" { "0A" } else { "07" };
+                let port = fields[1]
+                    .rsplit_once(':')
+                    .and_then(|(_, port)| u16::from_str_radix(port, 16).ok());
+                let inode = fields[9].parse::<u64>().ok();
+                if let (true, Some(port), Some(inode)) = (listening, port, inode) {
+                    if port != 0 && inode != 0 {
+                        sockets.push((protocol, section.ends_with('6'), port, inode));
+                    }
+                }
+            }
+            "fd" => {
+                // `ls -l` heads each directory with `/proc/<pid>/fd:`
+                if let Some(dir) = line.strip_suffix("/fd:") {
+                    pid = dir.strip_prefix("/proc/").and_then(|p| p.parse().ok());
+                    continue;
+                }
+                let inode = line
+                    .split("socket:[")
+                    .nth(1)
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .and_then(|inode| inode.parse::<u64>().ok());
+                if let (Some(pid), Some(inode)) = (pid, inode) {
+                    inode_pids
+                        .entry(inode)
+                        .and_modify(|owner| *owner = (*owner).min(pid))
+                        .or_insert(pid);
+                }
+            }
+            _ => {}
+        }
+    }
+    sockets
+        .into_iter()
+        .filter_map(|(protocol, ipv6, port, inode)| {
+            Some(SocketOwner {
+                protocol: protocol.to_string(),
+                ipv6,
+                port,
+                pid: *inode_pids.get(&inode)?,
+            })
+        })
+        .collect()
+}
+
+/// Parse the listeners of an `lsof -i` listing into their socket owners.
+pub fn parse_lsof_socket_owners(output: &str) -> Vec<SocketOwner> {
+    parse_lsof_ports(output)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|port| {
+            Some(SocketOwner {
+                ipv6: port.family.as_deref() == Some("ipv6"),
+                protocol: port.protocol,
+                port: port.local_port,
+                pid: port.pid?,
+            })
+        })
+        .collect()
+}
+
+/// Whether a listener address is an IPv6 one: `[::]`, `::1`, or `*` for
+/// the dual-stack sockets of `ss`.
+pub fn is_ipv6_address(address: &str) -> bool {
+    address.contains(':') || address == "*"
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_parse_linux_processes() {
-        let output = r#"USER       PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND
-root         1  0.0  0.1 169936 11892 ?        Ss   Jan01   0:05 /sbin/init
-www-data  1234  0.5  1.2 123456 12345 ?        Sl   Jan01   1:23 nginx: worker process
-"#;
-        let procs = parse_linux_processes(output).unwrap();
-        assert_eq!(procs.len(), 2);
+        let user = |name: &str| format!("{:<64}", name);
+        let output = format!(
+            "    1     0  0.0  0.1    05:04:03 Fri Oct 16 12:23:05 2026 {} systemd          /sbin/init splash\n\
+             812     1  0.5  1.2 1-02:03:04 Thu Oct  1 08:00:00 2026 {} nginx            nginx: worker process\n\
+             900     1  0.0  2.0       01:02 Fri Oct 16 12:22:00 2026 {} app              /opt/My App/bin/app --port 8080\n\
+             2     0  0.0  0.0    05:04:03 Fri Oct 16 12:23:05 2026 {} kthreadd         [kthreadd]\n",
+            user("root"),
+            user("www-data"),
+            user("CORP\\John Smith"),
+            user("root"),
+        );
+        let procs = parse_ps_processes(&output, true).unwrap();
+        assert_eq!(procs.len(), 4);
+        assert_eq!((procs[0].pid, procs[0].ppid), (1, 0));
         assert_eq!(procs[0].user, "root");
-        assert_eq!(procs[0].pid, 1);
-        assert_eq!(procs[1].command, "nginx:");
+        assert_eq!(procs[0].command, "/sbin/init");
+        assert_eq!(procs[0].args, vec!["splash"]);
+        assert_eq!(
+            procs[0].start_time.unwrap().to_rfc3339(),
+            "2026-10-16T12:23:05+00:00"
+        );
+        assert_eq!(procs[1].ppid, 1);
+        assert_eq!(procs[1].command, "nginx");
+        assert_eq!(procs[1].args, vec!["worker", "process"]);
+        assert_eq!(procs[1].full_cmdline, "nginx: worker process");
+        assert_eq!(procs[1].elapsed_time.as_deref(), Some("1-02:03:04"));
+        assert_eq!(procs[1].cpu_percent, Some(0.5));
+        assert_eq!(
+            procs[1].start_time.unwrap().to_rfc3339(),
+            "2026-10-01T08:00:00+00:00"
+        );
+        assert_eq!(procs[2].user, "CORP\\John Smith");
+        assert_eq!(procs[2].command, "/opt/My App/bin/app");
+        assert_eq!(procs[2].args, vec!["--port", "8080"]);
+        assert_eq!(procs[3].command, "[kthreadd]");
+
+        // BSD: no column widths
+        let output = "  310     1   0.0  0.3 02:10 Fri Oct 16 12:21:00 2026 _www /usr/sbin/httpd -D FOREGROUND\n";
+        let procs = parse_ps_processes(output, false).unwrap();
+        assert_eq!(procs[0].user, "_www");
+        assert_eq!(procs[0].command, "/usr/sbin/httpd");
+        assert_eq!(procs[0].args, vec!["-D", "FOREGROUND"]);
+        assert_eq!(procs[0].memory_percent, Some(0.3));
+    }
+
+    #[test]
+    fn test_parse_linux_process_with_truncated_name() {
+        // billing-exporter is truncated to 15 characters by the kernel
+        let output = format!(
+            "  950     1  0.0  0.4       00:10 Fri Oct 16 12:22:30 2026 {:<64} billing-exporte  /opt/Billing Tools/bin/billing-exporter --listen :9100\n",
+            "billing"
+        );
+        let procs = parse_ps_processes(&output, true).unwrap();
+        assert_eq!(procs[0].command, "/opt/Billing Tools/bin/billing-exporter");
+        assert_eq!(procs[0].args, vec!["--listen", ":9100"]);
+    }
+
+    #[test]
+    fn test_parse_process_cmdlines() {
+        let output = "\np812\na/usr/bin/java\na-Dconfig.file=/etc/app/app conf.xml\na-jar\na\na/opt/app.jar\n\
+                      \np900\nanginx: worker process\na\na\n\
+                      \np2\n";
+        let cmdlines = parse_process_cmdlines(output);
+        assert_eq!(
+            cmdlines[&812],
+            vec![
+                "/usr/bin/java",
+                "-Dconfig.file=/etc/app/app conf.xml",
+                "-jar",
+                "",
+                "/opt/app.jar"
+            ]
+        );
+        assert_eq!(cmdlines[&900], vec!["nginx: worker process"]);
+        assert!(!cmdlines.contains_key(&2));
+    }
+
+    #[test]
+    fn test_parse_package_owners() {
+        let owners = parse_package_owners(
+            "/usr/sbin/nginx\tnginx-core\n/usr/lib/x86_64-linux-gnu/libc.so.6\tlibc6:amd64\n\
+             /usr/bin/python3.11\tpython3.11, python3.11-minimal\n/usr/local/bin/app\t\n",
+        );
+        assert_eq!(owners.len(), 4);
+        assert_eq!(
+            owners[0],
+            (
+                "/usr/sbin/nginx".to_string(),
+                vec!["nginx-core".to_string()]
+            )
+        );
+        assert_eq!(owners[1].1, vec!["libc6:amd64"]);
+        assert_eq!(owners[2].1, vec!["python3.11", "python3.11-minimal"]);
+        assert_eq!(owners[3], ("/usr/local/bin/app".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn test_parse_name_resolution() {
+        let hosts = parse_hosts_file(
+            "127.0.0.1\tlocalhost\n# database\n10.0.0.9  DB.internal db # primary\nbad line\n",
+        );
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[1].address, "10.0.0.9");
+        assert_eq!(hosts[1].names, vec!["db.internal", "db"]);
+
+        let (nameservers, search) = parse_resolv_conf(
+            "# generated\nnameserver 10.0.0.2\nnameserver 10.0.0.3\ndomain corp\nsearch corp.example.com. example.com\n",
+        );
+        assert_eq!(nameservers, vec!["10.0.0.2", "10.0.0.3"]);
+        assert_eq!(search, vec!["corp.example.com", "example.com"]);
+
+        let lookups = parse_host_lookups("api.stripe.com\t3.18.12.63,::1\nqueue\t\n");
+        assert_eq!(lookups[0].1, vec!["3.18.12.63", "::1"]);
+        assert_eq!(lookups[1], ("queue".to_string(), Vec::new()));
+
+        let names = config_host_names(
+            "DB_HOST=db.internal\nurl: https://user:pw@API.stripe.com/v1\nredis_host: 127.0.0.1\n\
+             server = localhost\nbroker=amqp://queue:5672\nserver.port: 8080\n",
+        );
+        assert_eq!(names, vec!["api.stripe.com", "queue", "db.internal"]);
     }
 
     #[test]
     fn test_parse_linux_ports() {
         let output = r#"Netid State  Recv-Q Send-Q   Local Address:Port   Peer Address:Port  Process
-tcp   LISTEN 0      128        0.0.0.0:8080        0.0.0.0:*      users:(("python3",pid=7,fd=3))
+tcp   LISTEN 0      128        0.0.0.0:8080        0.0.0.0:*      users:(("python3",pid=7,fd
+=3))
 tcp   LISTEN 0      128        0.0.0.0:8081        0.0.0.0:*      users:(("python3",pid=12,fd=4))
 udp   UNCONN 0      0          0.0.0.0:5353        0.0.0.0:*
 "#;
@@ -553,6 +1974,41 @@ udp   UNCONN 0      0          0.0.0.0:5353        0.0.0.0:*
         assert_eq!(ports[2].local_port, 5353);
         assert_eq!(ports[2].protocol, "udp");
         assert_eq!(ports[2].pid, None);
+        assert_eq!(ports[2].family.as_deref(), Some("ipv4"));
+
+        let output = r#"Netid State  Recv-Q Send-Q   Local Address:Port   Peer Address:Port  Process
+tcp   LISTEN 0      511           [::]:8080           [::]:*     users:(("nginx",pid=10,fd=7))
+tcp   LISTEN 0      4096             *:9090              *:*     users:(("prometheus",pid=30,fd=9))
+u_str LISTEN 0      511   /run/php/php8.2-fpm.sock 23456 * 0     users:(("php-fpm8.2",pid=20,fd=8))
+u_str LISTEN 0      4096  @/tmp/.X11-unix/X0 11111        * 0
+u_dgr UNCONN 0      0     /run/systemd/notify 12222          * 0
+"#;
+        let ports = parse_linux_ports(output).unwrap();
+        let listeners: Vec<(&str, &str, u16, Option<u32>)> = ports
+            .iter()
+            .map(|p| {
+                (
+                    p.family.as_deref().unwrap(),
+                    p.local_address.as_str(),
+                    p.local_port,
+                    p.pid,
+                )
+            })
+            .collect();
+        assert_eq!(
+            listeners,
+            vec![
+                ("ipv6", "[::]", 8080, Some(10)),
+                ("ipv6", "*", 9090, Some(30)),
+                ("unix", "/run/php/php8.2-fpm.sock", 0, Some(20)),
+                ("unix", "/run/systemd/notify", 0, None),
+            ]
+        );
+        assert_eq!(
+            ports[2].socket_path.as_deref(),
+            Some("/run/php/php8.2-fpm.sock")
+        );
+        assert!(ports[2].is_unix());
     }
 
     #[test]
@@ -579,4 +2035,509 @@ Environment=NODE_ENV=production
             Some(&"production".to_string())
         );
     }
+
+    #[test]
+    fn test_parse_systemd_unit_drop_ins() {
+        let content = r#"# /lib/systemd/system/billing.service
+[Unit]
+Requires=postgresql.service
+Wants=redis-server.service
+After=network.target postgresql.service
+Before=nginx.service
+
+[Service]
+ExecStart=/opt/billing/bin/server
+
+[Install]
+WantedBy=multi-user.target
+
+# /etc/systemd/system/billing.service.d/override.conf
+[Unit]
+After=
+After=rabbitmq-server.service
+
+[Service]
+ExecStart=
+ExecStart=/opt/billing/bin/server --workers 4
+"#;
+        let info = parse_systemd_unit(content);
+        assert_eq!(
+            info.exec_start.as_deref(),
+            Some("/opt/billing/bin/server --workers 4")
+        );
+        assert_eq!(
+            info.dependencies,
+            vec![
+                "postgresql.service",
+                "redis-server.service",
+                "rabbitmq-server.service"
+            ]
+        );
+        assert_eq!(info.before, vec!["nginx.service"]);
+        assert_eq!(info.wanted_by, vec!["multi-user.target"]);
+        assert_eq!(
+            info.drop_ins,
+            vec!["/etc/systemd/system/billing.service.d/override.conf"]
+        );
+    }
+
+    #[test]
+    fn test_parse_runtime_version() {
+        assert_eq!(
+            parse_runtime_version("v20.11.1\n"),
+            Some("20.11.1".to_string())
+        );
+        assert_eq!(
+            parse_runtime_version("openjdk version \"17.0.9\" 2023-10-17\n"),
+            Some("17.0.9".to_string())
+        );
+        assert_eq!(
+            parse_runtime_version("Python 3.11.2"),
+            Some("3.11.2".to_string())
+        );
+        assert_eq!(parse_runtime_version("command not found"), None);
+    }
+
+    #[test]
+    fn test_parse_binary_info() {
+        let elf = parse_binary_info(
+            "/opt/app/server",
+            "/opt/app/server: ELF 64-bit LSB pie executable, x86-64, version 1 (SYSV), dynamically linked, interpreter /lib/ld-musl-x86_64.so.1, stripped\n",
+        );
+        assert_eq!(elf.kind, "elf");
+        assert_eq!(elf.interpreter.as_deref(), Some("/lib/ld-musl-x86_64.so.1"));
+
+        let script = parse_binary_info(
+            "/opt/app/run",
+            "/opt/app/run: a /usr/bin/env python3 script, ASCII text executable\n",
+        );
+        assert_eq!(script.kind, "script");
+        assert_eq!(script.interpreter.as_deref(), Some("/usr/bin/env python3"));
+
+        let unknown = parse_binary_info("/opt/app/data", "/opt/app/data: data\n");
+        assert_eq!(unknown.kind, "unknown");
+    }
+
+    #[test]
+    fn test_parse_privileged() {
+        assert_eq!(parse_privileged("0\n", OsType::Linux), Some(true));
+        assert_eq!(parse_privileged("1000\n", OsType::MacOs), Some(false));
+        assert_eq!(parse_privileged("True\r\n", OsType::Windows), Some(true));
+        assert_eq!(parse_privileged("", OsType::Windows), None);
+    }
+
+    #[test]
+    fn test_parse_init_services() {
+        assert_eq!(parse_init_system("openrc\n"), InitSystem::OpenRc);
+        assert_eq!(parse_init_system(""), InitSystem::Systemd);
+
+        let chkconfig = "httpd          \t0:off\t1:off\t2:on\t3:on\t4:on\t5:on\t6:off\nnetfs          \t0:off\t1:off\t2:off\t3:off\t4:off\t5:off\t6:off\n\nxinetd based services:\n\tchargen-dgram:\toff\n";
+        let services = parse_init_services(chkconfig, InitSystem::SysVinit);
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].name, "httpd");
+        assert_eq!(services[0].state, "enabled");
+        assert_eq!(services[1].state, "disabled");
+        assert_eq!(
+            services[0].unit_file_path.as_deref(),
+            Some("/etc/init.d/httpd")
+        );
+        let listed = parse_init_services("functions\nmysqld\n", InitSystem::SysVinit);
+        assert_eq!(listed.len(), 1);
+
+        let rc_status = "Runlevel: default\n sshd                                    [  started  ]\n redis                                   [  stopped  ]\nDynamic Runlevel: hotplugged\nRunlevel: boot\n sshd                                    [  started  ]\n";
+        let services = parse_init_services(rc_status, InitSystem::OpenRc);
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[1].name, "redis");
+        assert_eq!(services[1].state, "stopped");
+    }
+
+    #[test]
+    fn test_parse_init_script() {
+        let rhel = r#"#!/bin/sh
+# chkconfig: - 85 15
+. /etc/rc.d/init.d/functions
+if [ -f /etc/sysconfig/httpd ]; then
+        . /etc/sysconfig/httpd
+fi
+HTTPD_LANG=${HTTPD_LANG-"C"}
+httpd=${HTTPD-/usr/sbin/httpd}
+exec=$httpd
+OPTIONS="-DFOREGROUND"
+"#;
+        let info = parse_init_script("httpd", rhel);
+        assert_eq!(
+            info.exec_start.as_deref(),
+            Some("/usr/sbin/httpd -DFOREGROUND")
+        );
+        assert_eq!(info.environment_files, vec!["/etc/sysconfig/httpd"]);
+
+        let openrc = r#"#!/sbin/openrc-run
+command="/usr/bin/redis-server"
+command_args="/etc/redis.conf"
+directory="/var/lib/redis"
+"#;
+        let info = parse_init_script("redis", openrc);
+        assert_eq!(
+            info.exec_start.as_deref(),
+            Some("/usr/bin/redis-server /etc/redis.conf")
+        );
+        assert_eq!(info.working_directory.as_deref(), Some("/var/lib/redis"));
+        assert_eq!(info.environment_files, vec!["/etc/conf.d/redis"]);
+
+        let debian = r#"#!/bin/sh
+NAME=myapp
+[ -r /etc/default/$NAME ] && . /etc/default/$NAME
+start-stop-daemon --start --quiet --chdir /opt/myapp --exec /opt/myapp/bin/server -- --port 8080
+"#;
+        let info = parse_init_script("myapp", debian);
+        assert_eq!(info.exec_start.as_deref(), Some("/opt/myapp/bin/server"));
+        assert_eq!(info.working_directory.as_deref(), Some("/opt/myapp"));
+        assert_eq!(info.environment_files, vec!["/etc/default/myapp"]);
+    }
+
+    #[test]
+    fn test_parse_generic_unix() {
+        let services = parse_rc_services("/etc/rc.d/sshd\n/usr/local/etc/rc.d/nginx\n");
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[1].name, "nginx");
+        assert_eq!(
+            services[1].unit_file_path.as_deref(),
+            Some("/usr/local/etc/rc.d/nginx")
+        );
+        assert_eq!(
+            parse_service_list("functions\nhttpd\n", OsType::Unix).unwrap(),
+            vec!["httpd"]
+        );
+
+        let bsd = r#"Active Internet connections (including servers)
+Proto Recv-Q Send-Q Local Address          Foreign Address        (state)
+tcp4       0      0 *.22                   *.*                    LISTEN
+tcp6       0      0 *.22                   *.*                    LISTEN
+tcp4       0      0 127.0.0.1.5432         *.*                    LISTEN
+tcp4       0      0 10.0.0.5.22            10.0.0.9.51234         ESTABLISHED
+udp4       0      0 *.514                  *.*
+"#;
+        let ports = parse_ports(bsd, OsType::Unix).unwrap();
+        assert_eq!(ports.len(), 3);
+        assert_eq!(
+            (ports[0].local_address.as_str(), ports[0].local_port),
+            ("*", 22)
+        );
+        assert_eq!(ports[1].local_address, "127.0.0.1");
+        assert_eq!(
+            (ports[2].protocol.as_str(), ports[2].local_port),
+            ("udp", 514)
+        );
+
+        let linux = "tcp        0      0 0.0.0.0:8080            0.0.0.0:*               LISTEN\ntcp6       0      0 :::22                   :::*                    LISTEN\n";
+        let ports = parse_ports(linux, OsType::Unix).unwrap();
+        assert_eq!(
+            (ports[0].local_address.as_str(), ports[0].local_port),
+            ("0.0.0.0", 8080)
+        );
+        assert_eq!(
+            (ports[1].local_address.as_str(), ports[1].local_port),
+            ("::", 22)
+        );
+
+        let packages = parse_packages(
+            "nginx-1.24.0_12,3              Robust and small WWW server\npy39-setuptools-63.1.0_1       Python packages installer\n",
+            OsType::Unix,
+            "pkg info 2>/dev/null",
+        )
+        .unwrap();
+        assert_eq!(packages[0].name, "nginx");
+        assert_eq!(packages[0].version, "1.24.0_12,3");
+        assert_eq!(packages[1].name, "py39-setuptools");
+    }
+
+    #[test]
+    fn test_parse_resource_samples() {
+        let now = Utc::now();
+        let stat = "1234 (my app) S 1 1234 1234 0 -1 4194560 2000 0 0 0 250 50 0 0 20 0 4 0 100 500000000 2560 18446744073709551615\n# 100 4096\n";
+        let samples = parse_resource_samples(stat, OsType::Linux, now);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].pid, 1234);
+        assert!((samples[0].cpu_seconds - 3.0).abs() < 1e-9);
+        assert_eq!(samples[0].rss_bytes, 2560 * 4096);
+
+        let ps = "  612   1-02:03:04.50  20480\n  700   0:01.25   1024\n";
+        let samples = parse_resource_samples(ps, OsType::MacOs, now);
+        assert!((samples[0].cpu_seconds - 93784.5).abs() < 1e-9);
+        assert_eq!(samples[1].rss_bytes, 1024 * 1024);
+
+        let windows = r#"{"Id": 4242, "CPU": 12.5, "WorkingSet64": 104857600}"#;
+        let samples = parse_resource_samples(windows, OsType::Windows, now);
+        assert_eq!(samples[0].pid, 4242);
+        assert_eq!(samples[0].rss_bytes, 104857600);
+    }
+
+    #[test]
+    fn test_parse_macos_services() {
+        let list = "PID\tStatus\tLabel\n612\t0\thomebrew.mxcl.nginx\n-\t0\tcom.apple.Finder\n-\t78\tcom.example.worker\n";
+        assert_eq!(
+            parse_service_list(list, OsType::MacOs).unwrap(),
+            vec!["homebrew.mxcl.nginx", "com.example.worker"]
+        );
+
+        let details = r#"{
+	"LimitLoadToSessionType" = "System";
+	"Label" = "homebrew.mxcl.nginx";
+	"LastExitStatus" = 0;
+	"PID" = 612;
+	"Program" = "/opt/homebrew/opt/nginx/bin/nginx";
+	"ProgramArguments" = (
+		"/opt/homebrew/opt/nginx/bin/nginx";
+		"-g";
+		"daemon off;";
+	);
+};
+"#;
+        let service = parse_service_details(details, OsType::MacOs).unwrap();
+        assert_eq!(service.name, "homebrew.mxcl.nginx");
+        assert_eq!(service.state, "running");
+        assert_eq!(service.main_pid, Some(612));
+        assert_eq!(
+            service.exec_start.as_deref(),
+            Some("/opt/homebrew/opt/nginx/bin/nginx -g daemon off;")
+        );
+
+        let plist = r#"{"Label":"com.example.worker","ProgramArguments":["/usr/local/bin/worker","--queue","jobs"],"WorkingDirectory":"/usr/local/var/worker","EnvironmentVariables":{"QUEUE_URL":"redis://localhost"}}"#;
+        let info = parse_launchd_plist(plist);
+        assert_eq!(
+            info.exec_start.as_deref(),
+            Some("/usr/local/bin/worker --queue jobs")
+        );
+        assert_eq!(
+            info.working_directory.as_deref(),
+            Some("/usr/local/var/worker")
+        );
+        assert_eq!(info.environment["QUEUE_URL"], "redis://localhost");
+    }
+
+    #[test]
+    fn test_parse_macos_ports_packages_and_crontab() {
+        let lsof = r#"COMMAND   PID USER   FD   TYPE             DEVICE SIZE/OFF NODE NAME
+nginx     612 www     6u  IPv4 0x1a2b3c4d5e6f7a8b      0t0  TCP *:8080 (LISTEN)
+nginx     612 www     7u  IPv4 0x1a2b3c4d5e6f7a8c      0t0  TCP *:8080 (LISTEN)
+node      700 dev    23u  IPv6 0x2b3c4d5e6f7a8b9c      0t0  TCP [::1]:3000 (LISTEN)
+"#;
+        let ports = parse_ports(lsof, OsType::MacOs).unwrap();
+        assert_eq!(ports.len(), 2);
+        assert_eq!(ports[0].local_address, "*");
+        assert_eq!(ports[0].local_port, 8080);
+        assert_eq!(ports[0].pid, Some(612));
+        assert_eq!(ports[0].state, "LISTEN");
+        assert_eq!(ports[1].local_address, "[::1]");
+        assert_eq!(ports[1].process_name.as_deref(), Some("node"));
+
+        let packages = parse_packages(
+            "com.apple.pkg.CLTools_Executables\norg.python.Python.PythonFramework-3.12\n",
+            OsType::MacOs,
+            "pkgutil --pkgs",
+        )
+        .unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[1].source, "pkgutil");
+
+        let crontab = "SHELL=/bin/sh\n# backups\n0 3 * * * /usr/local/bin/backup --all\n@reboot /usr/local/bin/warm-cache\n";
+        let tasks =
+            parse_scheduled_tasks(crontab, OsType::MacOs, "crontab -l 2>/dev/null").unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].schedule.as_deref(), Some("0 3 * * *"));
+        assert_eq!(
+            tasks[0].command.as_deref(),
+            Some("/usr/local/bin/backup --all")
+        );
+        assert_eq!(tasks[1].schedule.as_deref(), Some("@reboot"));
+    }
+
+    #[test]
+    fn test_parse_cron_and_timers() {
+        let system = "SHELL=/bin/sh\n17 * * * * root cd / && run-parts --report /etc/cron.hourly\n*/5 * * * * app /opt/billing/bin/invoice-export --since 1h\n";
+        let tasks =
+            parse_scheduled_tasks(system, OsType::Linux, "cat /etc/crontab 2>/dev/null").unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[1].name, "invoice-export");
+        assert_eq!(tasks[1].user.as_deref(), Some("app"));
+        assert_eq!(tasks[1].schedule.as_deref(), Some("*/5 * * * *"));
+        assert_eq!(
+            tasks[1].command.as_deref(),
+            Some("/opt/billing/bin/invoice-export --since 1h")
+        );
+
+        let spool = "# user: deploy\n@hourly /home/deploy/sync.sh\n# user: root\n0 2 * * * /usr/local/bin/backup\n";
+        let tasks =
+            parse_scheduled_tasks(spool, OsType::Linux, "for f in /var/spool/cron/*; do ...")
+                .unwrap();
+        assert_eq!(tasks[0].user.as_deref(), Some("deploy"));
+        assert_eq!(tasks[0].name, "sync.sh");
+        assert_eq!(tasks[1].user.as_deref(), Some("root"));
+
+        let timers = "Thu 2024-01-11 00:00:00 UTC 5h left Wed 2024-01-10 00:00:00 UTC 18h ago logrotate.timer logrotate.service\nn/a n/a n/a n/a fstrim.timer fstrim.service\n";
+        let tasks = parse_scheduled_tasks(
+            timers,
+            OsType::Linux,
+            "systemctl list-timers --all --no-pager --no-legend",
+        )
+        .unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].name, "logrotate.timer");
+        assert_eq!(tasks[0].command.as_deref(), Some("logrotate.service"));
+        assert_eq!(tasks[1].task_type, "systemd-timer");
+    }
+
+    #[test]
+    fn test_parse_file_stats() {
+        let stats = parse_file_stats(
+            "640|root|app|/etc/app/app.conf\n2750|app|app|/opt/app\nstat: cannot stat\n",
+        );
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats["/etc/app/app.conf"],
+            ("root:app".to_string(), "0640".to_string())
+        );
+        assert_eq!(stats["/opt/app"].1, "2750");
+    }
+
+    #[test]
+    fn test_parse_socket_owners() {
+        let output = "# tcp
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41234 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:0CEA 0100007F:A1B2 01 00000000:00000000 00:00000000 00000000  1000        0 41300 1 0000000000000000 20 4 30 10 -1
+# tcp6
+  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:1F90 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41235 1 0000000000000000 100 0 0 10 0
+# udp
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  12: 00000000:14E9 00000000:0000 07 00000000:00000000 00:00000000 00000000   999        0 50001 2 0000000000000000 0
+# udp6
+# fd
+/proc/1/fd:
+ls: cannot open directory '/proc/1/fd': Permission denied
+/proc/812/fd:
+total 0
+lrwx------ 1 app app 64 Mar  1 12:00 3 -> socket:[41234]
+lrwx------ 1 app app 64 Mar  1 12:00 4 -> socket:[41235]
+lrwx------ 1 app app 64 Mar  1 12:00 5 -> socket:[41300]
+
+/proc/820/fd:
+lrwx------ 1 app app 64 Mar  1 12:00 3 -> socket:[41234]
+";
+        let owners = parse_socket_owners(output);
+        let owner = |protocol: &str, ipv6: bool, port: u16, pid: u32| SocketOwner {
+            protocol: protocol.to_string(),
+            ipv6,
+            port,
+            pid,
+        };
+        // The established connection and the socket of another user are not
+        assert_eq!(
+            owners,
+            vec![
+                owner("tcp", false, 8080, 812),
+                owner("tcp", true, 8080, 812)
+            ]
+        );
+
+        let lsof = "COMMAND PID USER FD TYPE DEVICE SIZE/OFF NODE NAME
+node 1020 app 20u IPv6 0x1a2b 0t0 TCP *:3000 (LISTEN)
+";
+        assert_eq!(
+            parse_lsof_socket_owners(lsof),
+            vec![owner("tcp", true, 3000, 1020)]
+        );
+    }
+
+    #[test]
+    fn test_parse_process_cwds() {
+        let cwds = parse_process_cwds(
+            "p812\nn/opt/app\np913\nn\np914\nfcwd\nn/srv/old (deleted)\np1020\nfcwd\nn/srv/api\n",
+        );
+        assert_eq!(cwds.len(), 2);
+        assert_eq!(cwds[&812], "/opt/app");
+        assert_eq!(cwds[&1020], "/srv/api");
+    }
+
+    #[test]
+    fn test_parse_windows_services() {
+        let list = r#"[
+            {"Name": "AppSvc", "State": "Running", "StartMode": "Auto",
+             "PathName": "\"C:\\Program Files\\App\\app.exe\" --service",
+             "DisplayName": "App", "Description": null,
+             "StartName": ".\\svc_app", "ProcessId": 4312},
+            {"Name": "AppWorker", "State": "Stopped", "StartMode": "Manual",
+             "PathName": "C:\\Program Files\\App\\worker.exe",
+             "DisplayName": "App worker", "Description": null,
+             "StartName": "LocalSystem", "ProcessId": 0}
+        ]"#;
+        let mut services = parse_windows_services_from_list(list).unwrap();
+        assert_eq!(services[0].user.as_deref(), Some(".\\svc_app"));
+        assert_eq!(services[0].main_pid, Some(4312));
+        assert_eq!(services[1].user.as_deref(), Some("LocalSystem"));
+        assert_eq!(services[1].main_pid, None);
+
+        let dependencies = r#"[
+            {"Antecedent": "RpcSs", "Dependent": "appworker"},
+            {"Antecedent": "AppSvc", "Dependent": "AppWorker"},
+            {"Antecedent": "Tcpip", "Dependent": "Dnscache"}
+        ]"#;
+        apply_windows_service_dependencies(&mut services, dependencies);
+        assert!(services[0].dependencies.is_empty());
+        assert_eq!(services[1].dependencies, vec!["RpcSs", "AppSvc"]);
+
+        let qfailure = "[SC] QueryServiceConfig2 SUCCESS\r\n\r\n\
+            SERVICE_NAME: AppSvc\r\n\
+            \x20       RESET_PERIOD (in seconds)    : 86400\r\n\
+            \x20       REBOOT_MESSAGE               :\r\n\
+            \x20       COMMAND_LINE                 :\r\n\
+            \x20       FAILURE_ACTIONS              : RESTART -- Delay = 60000 milliseconds.\r\n\
+            \x20                                      RESTART -- Delay = 120000 milliseconds.\r\n";
+        let recovery = parse_sc_qfailure(qfailure).unwrap();
+        assert_eq!(recovery.reset_period_secs, Some(86400));
+        assert_eq!(recovery.command, None);
+        assert_eq!(
+            recovery.actions,
+            vec![
+                RecoveryAction {
+                    action: "restart".to_string(),
+                    delay_ms: 60000
+                },
+                RecoveryAction {
+                    action: "restart".to_string(),
+                    delay_ms: 120000
+                },
+            ]
+        );
+        assert!(parse_sc_qfailure(
+            "SERVICE_NAME: Dnscache\n        RESET_PERIOD (in seconds)    : 0\n"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_parse_registry_keys() {
+        let output = r#"[
+            {
+                "Key": "HKEY_LOCAL_MACHINE\\SOFTWARE\\Contoso\\Billing",
+                "Values": { "InstallDir": "C:\\Program Files\\Contoso\\Billing", "DbPassword": "[REDACTED]" }
+            },
+            { "Key": "HKEY_LOCAL_MACHINE\\SOFTWARE\\Contoso\\Billing\\Queue", "Values": {} }
+        ]"#;
+        let keys = parse_registry_keys(output);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].0, "HKLM\\SOFTWARE\\Contoso\\Billing");
+        assert_eq!(
+            keys[0].1.get("InstallDir").map(String::as_str),
+            Some("C:\\Program Files\\Contoso\\Billing")
+        );
+        assert!(keys[1].1.is_empty());
+
+        // A single key is not wrapped in an array
+        let single =
+            r#"{ "Key": "HKEY_LOCAL_MACHINE\\SOFTWARE\\Contoso", "Values": { "Port": "8080" } }"#;
+        assert_eq!(parse_registry_keys(single)[0].1["Port"], "8080");
+        assert!(parse_registry_keys("").is_empty());
+    }
 }
@@ -0,0 +1,1771 @@
+//! Collection phases.
+//!
+//! Each category of information gathered from a target is a
+//! [`CollectionPhase`]. The collector runs the enabled phases in order,
+//! sharing one [`PhaseContext`] so later phases can build on what earlier
+//! ones discovered (e.g. config files come from service working directories).
+
+use crate::collector::{
+    standard_config_paths, Collector, ExecutionResult, SamplingConfig, Subject,
+};
+use crate::commands::{Allowed, CommandSet, InitSystem};
+use crate::executor::Executor;
+use crate::parsers::{self, SocketOwner};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use tracing::warn;
+use xcprobe_bundle_schema::{
+    AuditLog, Evidence, FileInfo, HostLookup, Manifest, PackageOwner, PortInfo, ProcessInfo,
+    RegistryKey, RuntimeProbe, ServiceInfo,
+};
+use xcprobe_common::OsType;
+
+/// Maximum number of executables inspected with `file`.
+const MAX_BINARY_PROBES: usize = 100;
+
+/// Maximum number of processes sampled by the resources phase.
+const MAX_SAMPLED_PROCESSES: usize = 200;
+
+/// Maximum number of processes whose working directory is looked up.
+const MAX_CWD_PROBES: usize = 200;
+
+/// Maximum number of processes whose command line is read from procfs.
+const MAX_CMDLINE_PROBES: usize = 200;
+
+/// Maximum number of files whose owning package is looked up.
+const MAX_PACKAGE_OWNER_PROBES: usize = 200;
+
+/// Maximum number of host names looked up with `--resolve-hosts`.
+const MAX_HOST_LOOKUPS: usize = 50;
+
+/// Maximum number of processes whose open files are listed.
+const MAX_OPEN_FILE_PROBES: usize = 100;
+
+/// Extensions of configuration files.
+const CONFIG_EXTENSIONS: &[&str] = &[
+    "conf",
+    "cfg",
+    "cnf",
+    "config",
+    "ini",
+    "yaml",
+    "yml",
+    "json",
+    "toml",
+    "properties",
+    "xml",
+    "env",
+];
+
+/// System files many processes hold open, none of them application
+/// configuration.
+const SYSTEM_OPEN_FILES: &[&str] = &[
+    "/etc/hosts",
+    "/etc/localtime",
+    "/etc/nsswitch.conf",
+    "/etc/resolv.conf",
+    "/etc/ld.so.cache",
+];
+
+/// Number of paths passed to one `stat` command.
+const STAT_BATCH_SIZE: usize = 100;
+
+/// A category of information collected from the target.
+#[async_trait]
+pub trait CollectionPhase: Send + Sync {
+    /// Name used in progress events, errors and `--skip-phase`/`--only-phase`.
+    fn name(&self) -> &'static str;
+
+    /// Human-readable description, logged when the phase starts.
+    fn description(&self) -> &'static str;
+
+    /// Run the phase, adding what it finds to `ctx.manifest`.
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()>;
+}
+
+/// All built-in phases, in execution order.
+pub fn default_phases() -> Vec<Box<dyn CollectionPhase>> {
+    vec![
+        Box::new(SystemPhase),
+        Box::new(ProcessesPhase),
+        Box::new(ServicesPhase),
+        Box::new(PortsPhase),
+        Box::new(WorkingDirectoriesPhase),
+        Box::new(CommandLinesPhase),
+        Box::new(PackagesPhase),
+        Box::new(RuntimesPhase),
+        Box::new(ScheduledTasksPhase),
+        Box::new(OpenFilesPhase),
+        Box::new(ConfigFilesPhase),
+        Box::new(PackageOwnersPhase),
+        Box::new(NameResolutionPhase),
+        Box::new(RegistryPhase),
+        Box::new(LogsPhase),
+        Box::new(ResourcesPhase),
+    ]
+}
+
+/// Which phases to run.
+#[derive(Debug, Clone, Default)]
+pub struct PhaseSelection {
+    /// Run only these phases; empty means all.
+    pub only: Vec<String>,
+    /// Never run these phases.
+    pub skip: Vec<String>,
+}
+
+impl PhaseSelection {
+    /// Whether the named phase should run.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|p| p == name))
+            && !self.skip.iter().any(|p| p == name)
+    }
+
+    /// Check that every selected name is a known phase.
+    pub fn validate(&self, phases: &[Box<dyn CollectionPhase>]) -> Result<()> {
+        for name in self.only.iter().chain(&self.skip) {
+            if !phases.iter().any(|p| p.name() == name) {
+                let known: Vec<_> = phases.iter().map(|p| p.name()).collect();
+                anyhow::bail!(
+                    "Unknown collection phase: {} (expected one of: {})",
+                    name,
+                    known.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// State shared with a running phase.
+pub struct PhaseContext<'a> {
+    pub(crate) collector: &'a Collector,
+    pub(crate) phase: &'static str,
+    pub executor: &'a dyn Executor,
+    pub commands: &'a dyn CommandSet,
+    pub manifest: &'a mut Manifest,
+    pub(crate) audit_log: &'a mut AuditLog,
+    pub(crate) evidence: &'a mut HashMap<String, Evidence>,
+}
+
+impl PhaseContext<'_> {
+    /// Target operating system.
+    pub fn os_type(&self) -> OsType {
+        self.collector.os_type()
+    }
+
+    /// Run a command, recording its evidence and audit entry under `category`.
+    pub async fn execute(&mut self, command: &str, category: &str) -> Result<ExecutionResult> {
+        self.collector
+            .execute_and_record(
+                self.executor,
+                command,
+                category,
+                Subject::None,
+                self.audit_log,
+                self.evidence,
+            )
+            .await
+    }
+
+    /// Run a command about the service `name`, whose evidence files are
+    /// grouped in a directory named after it.
+    pub async fn execute_for(
+        &mut self,
+        command: &str,
+        category: &str,
+        name: &str,
+    ) -> Result<ExecutionResult> {
+        self.collector
+            .execute_and_record(
+                self.executor,
+                command,
+                category,
+                Subject::Service(name),
+                self.audit_log,
+                self.evidence,
+            )
+            .await
+    }
+
+    /// Run a command printing the file at `path`. JSON, YAML and INI files
+    /// are redacted key by key instead of by entropy.
+    pub async fn execute_file(
+        &mut self,
+        command: &str,
+        category: &str,
+        path: &str,
+    ) -> Result<ExecutionResult> {
+        self.collector
+            .execute_and_record(
+                self.executor,
+                command,
+                category,
+                Subject::File(path),
+                self.audit_log,
+                self.evidence,
+            )
+            .await
+    }
+
+    /// The command built by the allowlist, if any. A refused command is
+    /// recorded under `category` in the audit log and the manifest errors.
+    pub fn allowed(&mut self, built: Allowed, category: &str) -> Option<String> {
+        match built {
+            Ok(command) => command,
+            Err(denied) => {
+                self.collector
+                    .record_denied(&denied, category, self.audit_log);
+                None
+            }
+        }
+    }
+
+    /// Report a step of the phase when the number of steps is known.
+    pub fn step(&self, current: usize, total: usize) {
+        self.collector.step(self.phase, current, total);
+    }
+
+    /// Resource sampling window, if sampling was requested.
+    pub fn sampling(&self) -> Option<&SamplingConfig> {
+        self.collector.sampling()
+    }
+
+    /// Whether host names of configuration files are looked up.
+    pub fn resolve_hosts(&self) -> bool {
+        self.collector.resolve_hosts()
+    }
+
+    /// Redact secrets from collected content.
+    pub fn redact(&self, content: &str) -> String {
+        self.collector.redactor().redact(content).content
+    }
+
+    /// Redact secrets from the content of the file at `path`.
+    pub fn redact_file(&self, content: &str, path: &str) -> String {
+        self.collector.redactor().redact_file(content, path).content
+    }
+}
+
+/// Hostname, OS version, kernel, architecture and privileges.
+pub struct SystemPhase;
+
+#[async_trait]
+impl CollectionPhase for SystemPhase {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+
+    fn description(&self) -> &'static str {
+        "system information"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let result = ctx.execute(ctx.commands.hostname_cmd(), "system").await?;
+        ctx.manifest.system.hostname = result.stdout.trim().to_string();
+
+        if let Some(cmd) = ctx.commands.os_version_cmd() {
+            if let Ok(result) = ctx.execute(cmd, "system").await {
+                ctx.manifest.system.os_version = Some(result.stdout.trim().to_string());
+            }
+        }
+
+        if let Some(cmd) = ctx.commands.kernel_version_cmd() {
+            if let Ok(result) = ctx.execute(cmd, "system").await {
+                ctx.manifest.system.kernel_version = Some(result.stdout.trim().to_string());
+            }
+        }
+
+        if let Some(cmd) = ctx.commands.architecture_cmd() {
+            if let Ok(result) = ctx.execute(cmd, "system").await {
+                ctx.manifest.system.architecture = Some(result.stdout.trim().to_string());
+            }
+        }
+
+        if let Ok(result) = ctx.execute(ctx.commands.privilege_cmd(), "system").await {
+            let privileged = parsers::parse_privileged(&result.stdout, ctx.os_type());
+            if privileged == Some(false) {
+                warn!(
+                    "Collecting without root or Administrator privileges: working \
+                     directories, environment and sockets of other users' processes \
+                     will be missing"
+                );
+            }
+            ctx.manifest.system.privileged = privileged;
+        }
+
+        Ok(())
+    }
+}
+
+/// Running processes.
+pub struct ProcessesPhase;
+
+#[async_trait]
+impl CollectionPhase for ProcessesPhase {
+    fn name(&self) -> &'static str {
+        "processes"
+    }
+
+    fn description(&self) -> &'static str {
+        "process information"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        for cmd in ctx.commands.process_cmds() {
+            if let Ok(result) = ctx.execute(cmd, "process").await {
+                let processes = parsers::parse_processes(&result.stdout, ctx.os_type())?;
+                for mut proc in processes {
+                    proc.evidence_ref = Some(result.evidence_ref.clone());
+                    ctx.manifest.processes.push(proc);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Services, with unit file details on Linux.
+pub struct ServicesPhase;
+
+#[async_trait]
+impl CollectionPhase for ServicesPhase {
+    fn name(&self) -> &'static str {
+        "services"
+    }
+
+    fn description(&self) -> &'static str {
+        "service information"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        // Linux: SysVinit and OpenRC hosts have no systemctl
+        if let Some(cmd) = ctx.commands.init_system_cmd() {
+            let result = ctx.execute(cmd, "service").await?;
+            let init = parsers::parse_init_system(&result.stdout);
+            ctx.manifest.system.init_system = Some(init.to_string());
+            if let Some(list_cmd) = ctx.commands.init_service_list_cmd(init) {
+                return collect_init_services(ctx, list_cmd, init).await;
+            }
+        }
+
+        let result = ctx
+            .execute(ctx.commands.service_list_cmd(), "service")
+            .await?;
+
+        // Windows and generic Unix: the list output is all there is (single query)
+        let listed = match ctx.os_type() {
+            OsType::Windows => Some(parsers::parse_windows_services_from_list(&result.stdout)?),
+            OsType::Unix => Some(parsers::parse_rc_services(&result.stdout)),
+            OsType::Linux | OsType::MacOs => None,
+        };
+        if let Some(mut services) = listed {
+            for service in &mut services {
+                service.evidence_ref = Some(result.evidence_ref.clone());
+            }
+            if ctx.os_type().is_windows() {
+                collect_windows_service_details(ctx, &mut services).await;
+            }
+            ctx.manifest.services.extend(services);
+            return Ok(());
+        }
+
+        // Linux/macOS: list names then query each service for details + unit
+        // files (launchd plists on macOS)
+        let service_names = parsers::parse_service_list(&result.stdout, ctx.os_type())?;
+
+        let total = service_names.len();
+        let first = ctx.manifest.services.len();
+        let mut before: Vec<(String, Vec<String>)> = Vec::new();
+        for (i, name) in service_names.into_iter().enumerate() {
+            ctx.step(i + 1, total);
+            let Some(show_cmd) = ctx.allowed(ctx.commands.service_show_cmd(&name), "service")
+            else {
+                continue;
+            };
+            let Ok(show_result) = ctx.execute_for(&show_cmd, "service", &name).await else {
+                continue;
+            };
+            let Ok(mut service) =
+                parsers::parse_service_details(&show_result.stdout, ctx.os_type())
+            else {
+                continue;
+            };
+            service.evidence_ref = Some(show_result.evidence_ref.clone());
+
+            if let Some(cat_cmd) = ctx.allowed(ctx.commands.service_cat_cmd(&name), "service") {
+                if let Ok(cat_result) = ctx.execute_for(&cat_cmd, "service", &name).await {
+                    let unit_info = if ctx.os_type().is_macos() {
+                        parsers::parse_launchd_plist(&cat_result.stdout)
+                    } else {
+                        parsers::parse_systemd_unit(&cat_result.stdout)
+                    };
+                    if let Some(exec) = unit_info.exec_start {
+                        service.exec_start = Some(exec);
+                    }
+                    if let Some(wd) = unit_info.working_directory {
+                        service.working_directory = Some(wd);
+                    }
+                    service
+                        .environment_files
+                        .extend(unit_info.environment_files);
+                    service.dependencies = unit_info.dependencies;
+                    service.wanted_by = unit_info.wanted_by;
+                    if !unit_info.before.is_empty() {
+                        before.push((service.name.clone(), unit_info.before));
+                    }
+                }
+            }
+
+            ctx.manifest.services.push(service);
+        }
+        parsers::apply_unit_ordering(&mut ctx.manifest.services[first..], &before);
+
+        Ok(())
+    }
+}
+
+/// Windows: add the dependencies between services, then the failure
+/// recovery settings of the services installed outside the Windows
+/// directory.
+async fn collect_windows_service_details(ctx: &mut PhaseContext<'_>, services: &mut [ServiceInfo]) {
+    if let Some(cmd) = ctx.commands.service_dependencies_cmd() {
+        if let Ok(result) = ctx.execute(cmd, "service").await {
+            parsers::apply_windows_service_dependencies(services, &result.stdout);
+        }
+    }
+
+    let applications: Vec<usize> = services
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| is_windows_application(s))
+        .map(|(i, _)| i)
+        .collect();
+    let total = applications.len();
+    for (step, i) in applications.into_iter().enumerate() {
+        ctx.step(step + 1, total);
+        let Some(cmd) = ctx.allowed(
+            ctx.commands.service_recovery_cmd(&services[i].name),
+            "service",
+        ) else {
+            continue;
+        };
+        if let Ok(result) = ctx.execute_for(&cmd, "service", &services[i].name).await {
+            services[i].recovery = parsers::parse_sc_qfailure(&result.stdout);
+        }
+    }
+}
+
+/// A Windows service installed outside the Windows directory.
+fn is_windows_application(service: &ServiceInfo) -> bool {
+    service
+        .exec_start
+        .as_deref()
+        .is_some_and(|path| !path.to_lowercase().contains("\\windows\\"))
+}
+
+/// Collect SysVinit or OpenRC services, gleaning their command line from
+/// the init scripts.
+async fn collect_init_services(
+    ctx: &mut PhaseContext<'_>,
+    list_cmd: &str,
+    init: InitSystem,
+) -> Result<()> {
+    let result = ctx.execute(list_cmd, "service").await?;
+    let services = parsers::parse_init_services(&result.stdout, init);
+
+    let total = services.len();
+    for (i, mut service) in services.into_iter().enumerate() {
+        ctx.step(i + 1, total);
+        service.evidence_ref = Some(result.evidence_ref.clone());
+        if let Some(cmd) = ctx.allowed(ctx.commands.init_script_cmd(&service.name), "service") {
+            if let Ok(script) = ctx.execute_for(&cmd, "service", &service.name).await {
+                let info = parsers::parse_init_script(&service.name, &script.stdout);
+                service.exec_start = info.exec_start;
+                service.working_directory = info.working_directory;
+                service.environment_files = info.environment_files;
+                service.evidence_ref = Some(script.evidence_ref.clone());
+            }
+        }
+        ctx.manifest.services.push(service);
+    }
+
+    Ok(())
+}
+
+/// Listening ports.
+pub struct PortsPhase;
+
+#[async_trait]
+impl CollectionPhase for PortsPhase {
+    fn name(&self) -> &'static str {
+        "ports"
+    }
+
+    fn description(&self) -> &'static str {
+        "port information"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let result = ctx.execute(ctx.commands.ports_cmd(), "ports").await?;
+        let ports = parsers::parse_ports(&result.stdout, ctx.os_type())?;
+
+        for mut port in ports {
+            port.evidence_ref = Some(result.evidence_ref.clone());
+            ctx.manifest.ports.push(port);
+        }
+
+        // Unprivileged, ss lists no process for the sockets of other users
+        for fallback in [false, true] {
+            if ctx
+                .manifest
+                .ports
+                .iter()
+                .all(|p| p.pid.is_some() || p.is_unix())
+            {
+                break;
+            }
+            let cmd = if fallback {
+                ctx.commands.port_owner_fallback_cmd()
+            } else {
+                ctx.commands.port_owner_cmd()
+            };
+            let Some(cmd) = cmd else {
+                continue;
+            };
+            let Ok(result) = ctx.execute(cmd, "ports").await else {
+                continue;
+            };
+            let (source, owners) = if fallback {
+                ("lsof", parsers::parse_lsof_socket_owners(&result.stdout))
+            } else {
+                ("proc_net", parsers::parse_socket_owners(&result.stdout))
+            };
+            attribute_ports(
+                &mut ctx.manifest.ports,
+                &ctx.manifest.processes,
+                &owners,
+                source,
+                &result.evidence_ref,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Give the listeners without a process the holder of a socket on the same
+/// protocol and port, preferring one of the same address family.
+fn attribute_ports(
+    ports: &mut [PortInfo],
+    processes: &[ProcessInfo],
+    owners: &[SocketOwner],
+    source: &str,
+    evidence_ref: &str,
+) {
+    for port in ports.iter_mut().filter(|p| p.pid.is_none() && !p.is_unix()) {
+        let ipv6 = parsers::is_ipv6_address(&port.local_address);
+        let candidates = || {
+            owners
+                .iter()
+                .filter(|o| o.protocol == port.protocol && o.port == port.local_port)
+        };
+        let Some(owner) = candidates()
+            .find(|o| o.ipv6 == ipv6)
+            .or_else(|| candidates().next())
+        else {
+            continue;
+        };
+        port.pid = Some(owner.pid);
+        port.process_name = processes
+            .iter()
+            .find(|p| p.pid == owner.pid)
+            .and_then(|p| p.command.rsplit('/').next())
+            .map(str::to_string);
+        port.pid_source = Some(source.to_string());
+        port.pid_evidence_ref = Some(evidence_ref.to_string());
+    }
+}
+
+/// Working directories of the processes most likely to be applications,
+/// from `/proc/<pid>/cwd` on Linux with `lsof` as fallback.
+pub struct WorkingDirectoriesPhase;
+
+#[async_trait]
+impl CollectionPhase for WorkingDirectoriesPhase {
+    fn name(&self) -> &'static str {
+        "working_directories"
+    }
+
+    fn description(&self) -> &'static str {
+        "process working directories"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let mut pending = business_pids(ctx.manifest);
+        pending.retain(|pid| {
+            ctx.manifest
+                .processes
+                .iter()
+                .any(|p| p.pid == *pid && p.working_directory.is_none())
+        });
+        pending.truncate(MAX_CWD_PROBES);
+
+        for fallback in [false, true] {
+            let cmd = if fallback {
+                ctx.commands.process_cwd_fallback_cmd(&pending)
+            } else {
+                ctx.commands.process_cwd_cmd(&pending)
+            };
+            let Some(cmd) = cmd else {
+                continue;
+            };
+            let Ok(result) = ctx.execute(&cmd, "working_directories").await else {
+                continue;
+            };
+            let cwds = parsers::parse_process_cwds(&result.stdout);
+            pending.retain(|pid| !cwds.contains_key(pid));
+
+            // Daemons conventionally chdir to `/`, which says nothing
+            let cwd = |pid: u32| cwds.get(&pid).filter(|d| d.as_str() != "/").cloned();
+            for process in &mut ctx.manifest.processes {
+                if process.working_directory.is_none() {
+                    process.working_directory = cwd(process.pid);
+                }
+            }
+            for service in &mut ctx.manifest.services {
+                if service.working_directory.is_none() {
+                    service.working_directory = service.main_pid.and_then(cwd);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Untruncated command lines of the processes most likely to be
+/// applications, from `/proc/<pid>/cmdline`: `ps` output may be cut short
+/// in containers, losing the `-D` flags and configuration paths of long
+/// Java and Node command lines. The `ps` listing stays as evidence.
+pub struct CommandLinesPhase;
+
+#[async_trait]
+impl CollectionPhase for CommandLinesPhase {
+    fn name(&self) -> &'static str {
+        "command_lines"
+    }
+
+    fn description(&self) -> &'static str {
+        "process command lines"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let mut pids = business_pids(ctx.manifest);
+        pids.truncate(MAX_CMDLINE_PROBES);
+        let Some(cmd) = ctx.commands.process_cmdline_cmd(&pids) else {
+            return Ok(());
+        };
+        let Ok(result) = ctx.execute(&cmd, "process").await else {
+            return Ok(());
+        };
+        let cmdlines = parsers::parse_process_cmdlines(&result.stdout);
+        apply_cmdlines(&mut ctx.manifest.processes, &cmdlines, &result.evidence_ref);
+        Ok(())
+    }
+}
+
+/// Replace the command lines given by `ps` with the arguments read from
+/// procfs. Command and arguments are only split anew when there are
+/// several: retitled processes such as `nginx: worker process` have one.
+fn apply_cmdlines(
+    processes: &mut [ProcessInfo],
+    cmdlines: &HashMap<u32, Vec<String>>,
+    evidence_ref: &str,
+) {
+    for process in processes {
+        let Some(argv) = cmdlines.get(&process.pid) else {
+            continue;
+        };
+        process.full_cmdline = argv.join(" ");
+        if let [command, args @ ..] = argv.as_slice() {
+            if !args.is_empty() {
+                process.command = command.clone();
+                process.args = args.to_vec();
+            }
+        }
+        process.cmdline_evidence_ref = Some(evidence_ref.to_string());
+    }
+}
+
+/// Installed packages, from the first package manager that answers.
+pub struct PackagesPhase;
+
+#[async_trait]
+impl CollectionPhase for PackagesPhase {
+    fn name(&self) -> &'static str {
+        "packages"
+    }
+
+    fn description(&self) -> &'static str {
+        "package information"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        for cmd in ctx.commands.package_cmds() {
+            if let Ok(result) = ctx.execute(cmd, "packages").await {
+                if result.exit_code != Some(0) {
+                    continue; // Package manager not installed
+                }
+                let packages = parsers::parse_packages(&result.stdout, ctx.os_type(), cmd)?;
+                for mut package in packages {
+                    package.evidence_ref = Some(result.evidence_ref.clone());
+                    ctx.manifest.packages.push(package);
+                }
+                break; // Only use first successful package manager
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Language runtimes and the types of executables started by services and
+/// processes.
+pub struct RuntimesPhase;
+
+#[async_trait]
+impl CollectionPhase for RuntimesPhase {
+    fn name(&self) -> &'static str {
+        "runtimes"
+    }
+
+    fn description(&self) -> &'static str {
+        "runtime information"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        for (language, cmd) in ctx.commands.runtime_version_cmds() {
+            if let Ok(result) = ctx.execute(cmd, "runtimes").await {
+                if result.exit_code != Some(0) {
+                    continue;
+                }
+                if let Some(version) = parsers::parse_runtime_version(&result.stdout) {
+                    ctx.manifest.runtimes.push(RuntimeProbe {
+                        language: language.to_string(),
+                        version,
+                        evidence_ref: Some(result.evidence_ref.clone()),
+                    });
+                }
+            }
+        }
+
+        // Identify the executables started by services and processes
+        let mut paths: Vec<String> = ctx
+            .manifest
+            .services
+            .iter()
+            .filter_map(|s| s.exec_start.as_deref())
+            .filter_map(|e| e.split_whitespace().next())
+            .chain(ctx.manifest.processes.iter().map(|p| p.command.as_str()))
+            .filter(|p| p.starts_with('/'))
+            .map(|p| p.to_string())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths.truncate(MAX_BINARY_PROBES);
+
+        for (i, path) in paths.iter().enumerate() {
+            ctx.step(i + 1, paths.len());
+            if let Some(cmd) = ctx.allowed(ctx.commands.binary_info_cmd(path), "binaries") {
+                if let Ok(result) = ctx.execute(&cmd, "binaries").await {
+                    if result.stdout.trim().is_empty() {
+                        continue;
+                    }
+                    let mut info = parsers::parse_binary_info(path, &result.stdout);
+                    info.evidence_ref = Some(result.evidence_ref.clone());
+                    ctx.manifest.binaries.push(info);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Cron jobs, systemd timers and Windows scheduled tasks.
+pub struct ScheduledTasksPhase;
+
+#[async_trait]
+impl CollectionPhase for ScheduledTasksPhase {
+    fn name(&self) -> &'static str {
+        "scheduled_tasks"
+    }
+
+    fn description(&self) -> &'static str {
+        "scheduled tasks"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        for cmd in ctx.commands.scheduled_task_cmds() {
+            if let Ok(result) = ctx.execute(cmd, "scheduled_tasks").await {
+                let tasks = parsers::parse_scheduled_tasks(&result.stdout, ctx.os_type(), cmd)?;
+                for mut task in tasks {
+                    task.evidence_ref = Some(result.evidence_ref.clone());
+                    ctx.manifest.scheduled_tasks.push(task);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Configuration and log files held open by the processes most likely to
+/// be applications, wherever they are: `/proc/<pid>/fd` on Linux, with
+/// `lsof` as fallback.
+pub struct OpenFilesPhase;
+
+/// What an open file looks like from its path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenFileKind {
+    Config,
+    Log,
+}
+
+#[async_trait]
+impl CollectionPhase for OpenFilesPhase {
+    fn name(&self) -> &'static str {
+        "open_files"
+    }
+
+    fn description(&self) -> &'static str {
+        "open files"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let mut pending = business_pids(ctx.manifest);
+        pending.truncate(MAX_OPEN_FILE_PROBES);
+
+        // Path -> (kind, processes holding it, listing evidence)
+        let mut opened: BTreeMap<String, (OpenFileKind, Vec<u32>, String)> = BTreeMap::new();
+        for fallback in [false, true] {
+            let cmd = if fallback {
+                ctx.commands.open_files_fallback_cmd(&pending)
+            } else {
+                ctx.commands.open_files_cmd(&pending)
+            };
+            let Some(cmd) = cmd else {
+                continue;
+            };
+            let Ok(result) = ctx.execute(&cmd, "open_files").await else {
+                continue;
+            };
+            let names = parsers::parse_lsof_names(&result.stdout);
+            pending.retain(|pid| !names.iter().any(|(p, _)| p == pid));
+            for (pid, path) in names {
+                let Some(kind) = open_file_kind(&path) else {
+                    continue;
+                };
+                let (_, pids, _) = opened
+                    .entry(path)
+                    .or_insert_with(|| (kind, Vec::new(), result.evidence_ref.clone()));
+                if !pids.contains(&pid) {
+                    pids.push(pid);
+                }
+            }
+        }
+
+        let total = opened.len();
+        for (i, (path, (kind, pids, evidence_ref))) in opened.into_iter().enumerate() {
+            ctx.step(i + 1, total);
+            let known = match kind {
+                OpenFileKind::Config => &mut ctx.manifest.config_files,
+                OpenFileKind::Log => &mut ctx.manifest.log_files,
+            };
+            if let Some(file) = known.iter_mut().find(|f| f.path == path) {
+                file.opened_by.extend(pids);
+                continue;
+            }
+
+            // Log content is left to the logs phase; configuration files
+            // are read when they are in an allowed location
+            let mut file_info = FileInfo {
+                path: path.clone(),
+                size_bytes: 0,
+                modified_at: None,
+                owner: None,
+                permissions: None,
+                content_hash: None,
+                attachment_ref: None,
+                discovery_method: "open_fd".to_string(),
+                discovery_evidence_ref: Some(evidence_ref),
+                opened_by: pids,
+            };
+            if kind == OpenFileKind::Log {
+                ctx.manifest.log_files.push(file_info);
+                continue;
+            }
+            if let Some(cmd) = ctx.allowed(ctx.commands.read_file_cmd(&path), "config") {
+                if let Ok(result) = ctx.execute_file(&cmd, "config", &path).await {
+                    let redacted = ctx.redact_file(&result.stdout, &path);
+                    file_info.size_bytes = result.stdout.len() as u64;
+                    file_info.content_hash = Some(xcprobe_common::hash::sha256_str(&redacted));
+                    file_info.attachment_ref = Some(result.evidence_ref.clone());
+                }
+            }
+            ctx.manifest.config_files.push(file_info);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether an open file is a configuration or log file, from its location
+/// and extension. Libraries, devices, sockets and system files are not.
+fn open_file_kind(path: &str) -> Option<OpenFileKind> {
+    const SYSTEM_PREFIXES: &[&str] = &[
+        "/dev/",
+        "/proc/",
+        "/sys/",
+        "/run/",
+        "/tmp/",
+        "/lib/",
+        "/lib64/",
+        "/usr/lib/",
+        "/usr/lib64/",
+        "/usr/share/",
+    ];
+    if !path.starts_with('/')
+        || path.ends_with(" (deleted)")
+        || SYSTEM_PREFIXES.iter().any(|p| path.starts_with(p))
+        || SYSTEM_OPEN_FILES.contains(&path)
+    {
+        return None;
+    }
+
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let extension = std::path::Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    let in_log_dir =
+        path.starts_with("/var/log/") || path.contains("/log/") || path.contains("/logs/");
+    // Rotated logs keep the extension before a counter: app.log.1
+    if extension.as_deref() == Some("log") || file_name.contains(".log.") || in_log_dir {
+        return Some(OpenFileKind::Log);
+    }
+    extension
+        .filter(|e| CONFIG_EXTENSIONS.contains(&e.as_str()))
+        .map(|_| OpenFileKind::Config)
+}
+
+/// Config files from discovered service and process paths, plus the
+/// standard locations, with the owner and mode of the files and working
+/// directories.
+pub struct ConfigFilesPhase;
+
+#[async_trait]
+impl CollectionPhase for ConfigFilesPhase {
+    fn name(&self) -> &'static str {
+        "config_files"
+    }
+
+    fn description(&self) -> &'static str {
+        "configuration files"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        // Collect config files from known service paths
+        let mut config_paths: Vec<String> = Vec::new();
+        let mut directories: Vec<String> = Vec::new();
+
+        // Add paths from services
+        for service in &ctx.manifest.services {
+            if let Some(ref wd) = service.working_directory {
+                directories.push(wd.clone());
+            }
+            for env_file in &service.environment_files {
+                config_paths.push(env_file.clone());
+            }
+        }
+
+        // Add paths from process working directories
+        for process in &ctx.manifest.processes {
+            if let Some(ref wd) = process.working_directory {
+                directories.push(wd.clone());
+            }
+        }
+        directories.retain(|d| d != "/");
+        directories.sort();
+        directories.dedup();
+        config_paths.extend(directories.iter().cloned());
+
+        // Standard config paths
+        for path in standard_config_paths(ctx.os_type()) {
+            config_paths.push(path.to_string());
+        }
+
+        // Deduplicate and collect; files held open were read already
+        config_paths.sort();
+        config_paths.dedup();
+        config_paths.retain(|p| !ctx.manifest.config_files.iter().any(|c| &c.path == p));
+
+        for (i, path) in config_paths.iter().enumerate() {
+            ctx.step(i + 1, config_paths.len());
+            if let Some(cmd) = ctx.allowed(ctx.commands.read_file_cmd(path), "config") {
+                if let Ok(result) = ctx.execute_file(&cmd, "config", path).await {
+                    // Redact content before storing
+                    let redacted = ctx.redact_file(&result.stdout, path);
+                    let file_info = FileInfo {
+                        path: path.clone(),
+                        size_bytes: result.stdout.len() as u64,
+                        modified_at: None,
+                        owner: None,
+                        permissions: None,
+                        content_hash: Some(xcprobe_common::hash::sha256_str(&redacted)),
+                        attachment_ref: Some(result.evidence_ref.clone()),
+                        discovery_method: "service_path".to_string(),
+                        discovery_evidence_ref: None,
+                        opened_by: Vec::new(),
+                    };
+                    ctx.manifest.config_files.push(file_info);
+                }
+            }
+        }
+
+        collect_file_stats(ctx, &directories).await;
+
+        Ok(())
+    }
+}
+
+/// Packages owning the executables of services and processes and the
+/// collected configuration files, from the package manager that listed the
+/// installed packages. Files no package owns were deployed by hand.
+pub struct PackageOwnersPhase;
+
+#[async_trait]
+impl CollectionPhase for PackageOwnersPhase {
+    fn name(&self) -> &'static str {
+        "package_owners"
+    }
+
+    fn description(&self) -> &'static str {
+        "package ownership of files"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let Some(source) = ctx.manifest.packages.first().map(|p| p.source.clone()) else {
+            return Ok(());
+        };
+        let mut paths = owned_file_candidates(ctx.manifest);
+        paths.truncate(MAX_PACKAGE_OWNER_PROBES);
+
+        let names: Vec<&str> = paths.iter().map(|(path, _)| path.as_str()).collect();
+        let mut owners = Vec::new();
+        for batch in names.chunks(STAT_BATCH_SIZE) {
+            let Some(cmd) = ctx.commands.package_owner_cmd(&source, batch) else {
+                continue;
+            };
+            if let Ok(result) = ctx.execute(&cmd, "packages").await {
+                for (path, packages) in parsers::parse_package_owners(&result.stdout) {
+                    owners.push(PackageOwner {
+                        services: paths
+                            .iter()
+                            .find(|(p, _)| *p == path)
+                            .map(|(_, services)| services.clone())
+                            .unwrap_or_default(),
+                        path,
+                        packages,
+                        source: source.clone(),
+                        evidence_ref: Some(result.evidence_ref.clone()),
+                    });
+                }
+            }
+        }
+        ctx.manifest.package_owners.extend(owners);
+        Ok(())
+    }
+}
+
+/// Executables of services and business processes and collected
+/// configuration files, each with the services it belongs to: the service
+/// starting the executable or running the process, the services whose
+/// main process holds a file open, reads it as an environment file or runs
+/// in the directory holding it. Executables come first.
+fn owned_file_candidates(manifest: &Manifest) -> Vec<(String, Vec<String>)> {
+    let mut files: Vec<(String, Vec<String>)> = Vec::new();
+    let mut add = |path: &str, service: Option<&str>| {
+        if !path.starts_with('/') {
+            return;
+        }
+        let i = match files.iter().position(|(p, _)| p == path) {
+            Some(i) => i,
+            None => {
+                files.push((path.to_string(), Vec::new()));
+                files.len() - 1
+            }
+        };
+        let services = &mut files[i].1;
+        if let Some(service) = service.filter(|s| !services.iter().any(|n| n == s)) {
+            services.push(service.to_string());
+        }
+    };
+
+    let services_of = |pid: u32| {
+        manifest
+            .services
+            .iter()
+            .filter(move |s| s.main_pid == Some(pid))
+    };
+    for service in &manifest.services {
+        if let Some(exe) = service
+            .exec_start
+            .as_deref()
+            .and_then(|e| e.split_whitespace().next())
+        {
+            add(exe, Some(&service.name));
+        }
+    }
+    for pid in business_pids(manifest) {
+        let Some(process) = manifest.processes.iter().find(|p| p.pid == pid) else {
+            continue;
+        };
+        add(&process.command, None);
+        for service in services_of(pid) {
+            add(&process.command, Some(&service.name));
+        }
+    }
+    for file in &manifest.config_files {
+        add(&file.path, None);
+        for service in &manifest.services {
+            let opened = file
+                .opened_by
+                .iter()
+                .any(|pid| service.main_pid == Some(*pid));
+            let env_file = service.environment_files.contains(&file.path);
+            let in_workdir = service
+                .working_directory
+                .as_deref()
+                .filter(|wd| *wd != "/")
+                .is_some_and(|wd| {
+                    file.path
+                        .starts_with(&format!("{}/", wd.trim_end_matches('/')))
+                });
+            if opened || env_file || in_workdir {
+                add(&file.path, Some(&service.name));
+            }
+        }
+    }
+    files
+}
+
+/// The hosts file and resolver configuration of the target and, with
+/// `--resolve-hosts`, the addresses the host names of configuration files
+/// and service environments resolve to there. They tell the analyzer which
+/// dependencies are on the source network and which are Internet services.
+pub struct NameResolutionPhase;
+
+#[async_trait]
+impl CollectionPhase for NameResolutionPhase {
+    fn name(&self) -> &'static str {
+        "name_resolution"
+    }
+
+    fn description(&self) -> &'static str {
+        "host name resolution"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        if let Some(cmd) = ctx.commands.hosts_file_cmd() {
+            if let Ok(result) = ctx.execute(cmd, "network").await {
+                let resolution = &mut ctx.manifest.name_resolution;
+                resolution.hosts = parsers::parse_hosts_file(&result.stdout);
+                resolution.evidence_refs.push(result.evidence_ref);
+            }
+        }
+        if let Some(cmd) = ctx.commands.resolver_config_cmd() {
+            if let Ok(result) = ctx.execute(cmd, "network").await {
+                let (nameservers, search_domains) = parsers::parse_resolv_conf(&result.stdout);
+                let resolution = &mut ctx.manifest.name_resolution;
+                resolution.nameservers = nameservers;
+                resolution.search_domains = search_domains;
+                resolution.evidence_refs.push(result.evidence_ref);
+            }
+        }
+        if !ctx.resolve_hosts() {
+            return Ok(());
+        }
+
+        let mut names = configured_host_names(ctx.manifest, ctx.evidence);
+        names.truncate(MAX_HOST_LOOKUPS);
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let Some(cmd) = ctx.commands.host_lookup_cmd(&names) else {
+            return Ok(());
+        };
+        if let Ok(result) = ctx.execute(&cmd, "network").await {
+            for (name, addresses) in parsers::parse_host_lookups(&result.stdout) {
+                ctx.manifest.name_resolution.lookups.push(HostLookup {
+                    name,
+                    addresses,
+                    evidence_ref: Some(result.evidence_ref.clone()),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Host names of the collected configuration files and of service and
+/// process environments, other than the target's own name.
+fn configured_host_names(manifest: &Manifest, evidence: &HashMap<String, Evidence>) -> Vec<String> {
+    let contents = manifest
+        .config_files
+        .iter()
+        .filter_map(|f| evidence.get(f.attachment_ref.as_deref()?)?.output());
+    let environments = manifest
+        .services
+        .iter()
+        .map(|s| &s.environment)
+        .chain(
+            manifest
+                .processes
+                .iter()
+                .filter_map(|p| p.environment.as_ref()),
+        )
+        .map(|env| {
+            let mut vars: Vec<_> = env.iter().collect();
+            vars.sort();
+            vars.iter()
+                .map(|(key, value)| format!("{}={}\n", key, value))
+                .collect::<String>()
+        });
+
+    let own = manifest.system.hostname.to_lowercase();
+    let mut names: Vec<String> = Vec::new();
+    for content in contents.chain(environments) {
+        for name in parsers::config_host_names(&content) {
+            let own_name = name == own || name.split('.').next() == Some(own.as_str());
+            if !own_name && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Record the owner and mode of the collected config files, and of the
+/// working directories in `manifest.directories`.
+async fn collect_file_stats(ctx: &mut PhaseContext<'_>, directories: &[String]) {
+    let paths: Vec<String> = ctx
+        .manifest
+        .config_files
+        .iter()
+        .map(|f| f.path.clone())
+        .chain(directories.iter().cloned())
+        .collect();
+
+    let mut stats = HashMap::new();
+    let mut evidence_refs = HashMap::new();
+    for batch in paths.chunks(STAT_BATCH_SIZE) {
+        let batch: Vec<&str> = batch.iter().map(String::as_str).collect();
+        let Some(cmd) = ctx.commands.file_stat_cmd(&batch) else {
+            continue;
+        };
+        if let Ok(result) = ctx.execute(&cmd, "file_stats").await {
+            for (path, stat) in parsers::parse_file_stats(&result.stdout) {
+                evidence_refs.insert(path.clone(), result.evidence_ref.clone());
+                stats.insert(path, stat);
+            }
+        }
+    }
+
+    for file in ctx.manifest.config_files.iter_mut() {
+        if let Some((owner, permissions)) = stats.get(&file.path) {
+            file.owner = Some(owner.clone());
+            file.permissions = Some(permissions.clone());
+        }
+    }
+    for path in directories {
+        let Some((owner, permissions)) = stats.remove(path) else {
+            continue;
+        };
+        ctx.manifest.directories.push(FileInfo {
+            path: path.clone(),
+            size_bytes: 0,
+            modified_at: None,
+            owner: Some(owner),
+            permissions: Some(permissions),
+            content_hash: None,
+            attachment_ref: evidence_refs.remove(path),
+            discovery_method: "working_directory".to_string(),
+            discovery_evidence_ref: None,
+            opened_by: Vec::new(),
+        });
+    }
+}
+
+/// Registry keys of the Windows application services: the service key and
+/// the `HKLM\\SOFTWARE` key of the vendor's product (Windows).
+pub struct RegistryPhase;
+
+#[async_trait]
+impl CollectionPhase for RegistryPhase {
+    fn name(&self) -> &'static str {
+        "registry"
+    }
+
+    fn description(&self) -> &'static str {
+        "registry keys"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        if !ctx.os_type().is_windows() {
+            return Ok(());
+        }
+
+        let mut keys: Vec<(String, String)> = Vec::new();
+        for service in ctx
+            .manifest
+            .services
+            .iter()
+            .filter(|s| is_windows_application(s))
+        {
+            keys.push((
+                service.name.clone(),
+                format!("SYSTEM\\CurrentControlSet\\Services\\{}", service.name),
+            ));
+            for key in software_keys(service.exec_start.as_deref().unwrap_or_default()) {
+                if !keys.iter().any(|(_, k)| k.eq_ignore_ascii_case(&key)) {
+                    keys.push((service.name.clone(), key));
+                }
+            }
+        }
+
+        let total = keys.len();
+        for (i, (service, key)) in keys.into_iter().enumerate() {
+            ctx.step(i + 1, total);
+            let Some(cmd) = ctx.allowed(ctx.commands.registry_key_cmd(&key), "registry") else {
+                continue;
+            };
+            let Ok(result) = ctx.execute_file(&cmd, "registry", &key).await else {
+                continue;
+            };
+            let redacted = ctx.redact_file(&result.stdout, &key);
+            for (path, values) in parsers::parse_registry_keys(&redacted) {
+                if path
+                    .to_lowercase()
+                    .ends_with(&format!("\\services\\{}", service.to_lowercase()))
+                {
+                    merge_service_environment(ctx.manifest, &service, &values);
+                }
+                ctx.manifest.registry_keys.push(RegistryKey {
+                    path,
+                    service: service.clone(),
+                    values,
+                    evidence_ref: Some(result.evidence_ref.clone()),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `SOFTWARE` keys where a product installed under `Program Files` keeps
+/// its settings: `Vendor\\Product` (or `Vendor` alone), native and 32-bit.
+fn software_keys(exec_start: &str) -> Vec<String> {
+    let lower = exec_start.to_lowercase();
+    let path = match lower.find(".exe") {
+        Some(end) => &exec_start[..end],
+        None => exec_start,
+    };
+    let components: Vec<&str> = path.trim_start_matches('"').split('\\').collect();
+    let Some(start) = components
+        .iter()
+        .position(|c| c.to_lowercase().starts_with("program files"))
+    else {
+        return Vec::new();
+    };
+    // Directories only: the last component is the executable
+    let directories = components
+        .get(start + 1..components.len() - 1)
+        .unwrap_or_default();
+    if directories.is_empty() {
+        return Vec::new();
+    }
+    let product = directories[..directories.len().min(2)].join("\\");
+    vec![
+        format!("SOFTWARE\\{}", product),
+        format!("SOFTWARE\\WOW6432Node\\{}", product),
+    ]
+}
+
+/// Add the `Environment` value of a service key (`NAME=value` lines) to
+/// the environment of the service.
+fn merge_service_environment(
+    manifest: &mut Manifest,
+    service: &str,
+    values: &BTreeMap<String, String>,
+) {
+    let Some(environment) = values.get("Environment") else {
+        return;
+    };
+    let Some(service) = manifest.services.iter_mut().find(|s| s.name == service) else {
+        return;
+    };
+    for line in environment.lines() {
+        if let Some((name, value)) = line.split_once('=') {
+            service
+                .environment
+                .entry(name.trim().to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+}
+
+/// Recent journal entries for each service (Linux).
+pub struct LogsPhase;
+
+#[async_trait]
+impl CollectionPhase for LogsPhase {
+    fn name(&self) -> &'static str {
+        "logs"
+    }
+
+    fn description(&self) -> &'static str {
+        "log snippets"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        // journald only exists alongside systemd
+        let init = ctx.manifest.system.init_system.as_deref();
+        if !ctx.os_type().is_linux() || init.is_some_and(|i| i != "systemd") {
+            return Ok(());
+        }
+
+        let names: Vec<String> = ctx
+            .manifest
+            .services
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            ctx.step(i + 1, names.len());
+            if let Some(cmd) = ctx.allowed(ctx.commands.journal_cmd(name, "1 hour ago"), "logs") {
+                if let Ok(result) = ctx.execute_for(&cmd, "logs", name).await {
+                    let redacted = ctx.redact(&result.stdout);
+                    let file_info = FileInfo {
+                        path: format!("journal:{}", name),
+                        size_bytes: result.stdout.len() as u64,
+                        modified_at: None,
+                        owner: None,
+                        permissions: None,
+                        content_hash: Some(xcprobe_common::hash::sha256_str(&redacted)),
+                        attachment_ref: Some(result.evidence_ref.clone()),
+                        discovery_method: "journalctl".to_string(),
+                        discovery_evidence_ref: None,
+                        opened_by: Vec::new(),
+                    };
+                    ctx.manifest.log_files.push(file_info);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// CPU time and resident memory of processes, sampled over a time window
+/// when requested.
+pub struct ResourcesPhase;
+
+#[async_trait]
+impl CollectionPhase for ResourcesPhase {
+    fn name(&self) -> &'static str {
+        "resources"
+    }
+
+    fn description(&self) -> &'static str {
+        "resource usage samples"
+    }
+
+    async fn collect(&self, ctx: &mut PhaseContext<'_>) -> Result<()> {
+        let Some(sampling) = ctx.sampling().cloned() else {
+            return Ok(());
+        };
+        let mut pids = business_pids(ctx.manifest);
+        pids.truncate(MAX_SAMPLED_PROCESSES);
+        let Some(cmd) = ctx.commands.resource_sample_cmd(&pids) else {
+            return Ok(());
+        };
+
+        let count = sampling.sample_count();
+        let start = tokio::time::Instant::now();
+        for i in 0..count {
+            tokio::time::sleep_until(start + sampling.interval * i as u32).await;
+            ctx.step(i + 1, count);
+            let timestamp = chrono::Utc::now();
+            let result = ctx.execute(&cmd, "resources").await?;
+            for mut sample in
+                parsers::parse_resource_samples(&result.stdout, ctx.os_type(), timestamp)
+            {
+                sample.evidence_ref = Some(result.evidence_ref.clone());
+                ctx.manifest.resource_samples.push(sample);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Processes most likely to be applications: service main processes and
+/// listeners, then the largest other user-space processes.
+fn business_pids(manifest: &Manifest) -> Vec<u32> {
+    let mut pids: Vec<u32> = manifest
+        .services
+        .iter()
+        .filter_map(|s| s.main_pid)
+        .chain(
+            manifest
+                .ports
+                .iter()
+                .filter(|p| !p.is_unix())
+                .filter_map(|p| p.pid),
+        )
+        .filter(|pid| *pid > 0)
+        .collect();
+
+    let mut others: Vec<_> = manifest
+        .processes
+        .iter()
+        // Kernel threads show as [name]
+        .filter(|p| p.pid > 1 && !p.command.starts_with('['))
+        .collect();
+    others.sort_by(|a, b| {
+        b.memory_percent
+            .unwrap_or(0.0)
+            .total_cmp(&a.memory_percent.unwrap_or(0.0))
+    });
+    pids.extend(others.iter().map(|p| p.pid));
+
+    let mut seen = std::collections::HashSet::new();
+    pids.retain(|pid| seen.insert(*pid));
+    pids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_selection() {
+        let all = PhaseSelection::default();
+        assert!(all.is_enabled("logs"));
+
+        let selection = PhaseSelection {
+            only: vec!["processes".to_string(), "ports".to_string()],
+            skip: vec!["ports".to_string()],
+        };
+        assert!(selection.is_enabled("processes"));
+        assert!(!selection.is_enabled("ports"));
+        assert!(!selection.is_enabled("logs"));
+        assert!(selection.validate(&default_phases()).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_phase_rejected() {
+        let selection = PhaseSelection {
+            only: Vec::new(),
+            skip: vec!["log".to_string()],
+        };
+        let err = selection.validate(&default_phases()).unwrap_err();
+        assert!(err.to_string().contains("Unknown collection phase: log"));
+        assert!(err.to_string().contains("scheduled_tasks"));
+    }
+
+    #[test]
+    fn test_open_file_kind() {
+        let kind = open_file_kind;
+        assert_eq!(
+            kind("/data/billing/conf/app.yml"),
+            Some(OpenFileKind::Config)
+        );
+        assert_eq!(kind("/etc/billing.properties"), Some(OpenFileKind::Config));
+        assert_eq!(kind("/data/billing/logs/out.txt"), Some(OpenFileKind::Log));
+        assert_eq!(kind("/srv/app/app.log.1"), Some(OpenFileKind::Log));
+        assert_eq!(kind("/usr/lib/x86_64-linux-gnu/libssl.so.3"), None);
+        assert_eq!(kind("/etc/resolv.conf"), None);
+        assert_eq!(kind("/data/billing/app.jar"), None);
+        assert_eq!(kind("socket:[81234]"), None);
+        assert_eq!(kind("/opt/app/app.conf (deleted)"), None);
+    }
+
+    #[test]
+    fn test_software_keys() {
+        assert_eq!(
+            software_keys(r#""C:\Program Files\Contoso\Billing\bin\billing.exe" -c C:\conf"#),
+            vec![
+                r"SOFTWARE\Contoso\Billing",
+                r"SOFTWARE\WOW6432Node\Contoso\Billing"
+            ]
+        );
+        assert_eq!(
+            software_keys(r"C:\Program Files (x86)\Acme\acme.exe"),
+            vec![r"SOFTWARE\Acme", r"SOFTWARE\WOW6432Node\Acme"]
+        );
+        assert!(software_keys(r"C:\Program Files\acme.exe").is_empty());
+        assert!(software_keys(r"D:\apps\acme\acme.exe").is_empty());
+    }
+
+    #[test]
+    fn test_apply_cmdlines() {
+        let process = |pid: u32, cmdline: &str| ProcessInfo {
+            pid,
+            ppid: 1,
+            user: "app".to_string(),
+            command: cmdline.split(' ').next().unwrap().to_string(),
+            args: cmdline.split(' ').skip(1).map(str::to_string).collect(),
+            full_cmdline: cmdline.to_string(),
+            start_time: None,
+            elapsed_time: None,
+            cpu_percent: None,
+            memory_percent: None,
+            working_directory: None,
+            environment: None,
+            evidence_ref: Some("evidence/process/ps.txt".to_string()),
+            cmdline_evidence_ref: None,
+        };
+        let mut processes = vec![
+            process(812, "/usr/bin/java -Xmx1g -Dapp.config=/etc/a"),
+            process(900, "nginx worker process"),
+            process(913, "/usr/sbin/cron"),
+        ];
+        let cmdlines = HashMap::from([
+            (
+                812,
+                vec![
+                    "/usr/bin/java".to_string(),
+                    "-Xmx1g".to_string(),
+                    "-Dapp.config=/etc/app/app.properties".to_string(),
+                    "-jar".to_string(),
+                    "/opt/app.jar".to_string(),
+                ],
+            ),
+            (900, vec!["nginx: worker process".to_string()]),
+        ]);
+        apply_cmdlines(&mut processes, &cmdlines, "evidence/process/cmdline.txt");
+
+        assert_eq!(
+            processes[0].full_cmdline,
+            "/usr/bin/java -Xmx1g -Dapp.config=/etc/app/app.properties -jar /opt/app.jar"
+        );
+        assert_eq!(processes[0].args.len(), 4);
+        assert_eq!(
+            processes[0].cmdline_evidence_ref.as_deref(),
+            Some("evidence/process/cmdline.txt")
+        );
+        assert_eq!(
+            processes[0].evidence_ref.as_deref(),
+            Some("evidence/process/ps.txt")
+        );
+        assert_eq!(processes[1].full_cmdline, "nginx: worker process");
+        assert_eq!(processes[1].command, "nginx");
+        assert_eq!(processes[2].cmdline_evidence_ref, None);
+    }
+
+    #[test]
+    fn test_owned_file_candidates() {
+        let mut manifest = Manifest::default();
+        manifest.services.push(
+            serde_json::from_value(serde_json::json!({
+                "name": "nginx.service", "state": "active",
+                "exec_start": "/usr/sbin/nginx -g 'daemon off;'",
+                "exec_start_pre": [], "exec_start_post": [], "environment": {},
+                "environment_files": ["/etc/default/nginx"], "dependencies": [],
+                "wanted_by": [], "main_pid": 700
+            }))
+            .unwrap(),
+        );
+        manifest.processes.push(
+            serde_json::from_value(serde_json::json!({
+                "pid": 700, "ppid": 1, "user": "root", "command": "/usr/sbin/nginx",
+                "args": [], "full_cmdline": "/usr/sbin/nginx", "start_time": null,
+                "elapsed_time": null, "cpu_percent": null, "memory_percent": null,
+                "working_directory": null, "environment": null, "evidence_ref": null
+            }))
+            .unwrap(),
+        );
+        let file = |path: &str, opened_by: Vec<u32>| FileInfo {
+            path: path.to_string(),
+            size_bytes: 1,
+            modified_at: None,
+            owner: None,
+            permissions: None,
+            content_hash: None,
+            attachment_ref: None,
+            discovery_method: "open_files".to_string(),
+            discovery_evidence_ref: None,
+            opened_by,
+        };
+        manifest.config_files = vec![
+            file("/etc/nginx/nginx.conf", vec![700]),
+            file("/etc/default/nginx", Vec::new()),
+            file("/etc/hostname", Vec::new()),
+        ];
+
+        let nginx = vec!["nginx.service".to_string()];
+        assert_eq!(
+            owned_file_candidates(&manifest),
+            vec![
+                ("/usr/sbin/nginx".to_string(), nginx.clone()),
+                ("/etc/nginx/nginx.conf".to_string(), nginx.clone()),
+                ("/etc/default/nginx".to_string(), nginx),
+                ("/etc/hostname".to_string(), Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_configured_host_names() {
+        let mut manifest = Manifest::default();
+        manifest.system.hostname = "app01".to_string();
+        manifest.services.push(
+            serde_json::from_value(serde_json::json!({
+                "name": "billing.service", "state": "active",
+                "exec_start": "/opt/billing/bin/billing",
+                "exec_start_pre": [], "exec_start_post": [],
+                "environment": {"PAYMENTS_URL": "https://api.stripe.com", "DB_HOST": "db.internal"},
+                "environment_files": [], "dependencies": [], "wanted_by": []
+            }))
+            .unwrap(),
+        );
+        manifest.config_files.push(FileInfo {
+            path: "/opt/billing/app.yaml".to_string(),
+            size_bytes: 1,
+            modified_at: None,
+            owner: None,
+            permissions: None,
+            content_hash: None,
+            attachment_ref: Some("evidence/config_1.txt".to_string()),
+            discovery_method: "service_path".to_string(),
+            discovery_evidence_ref: None,
+            opened_by: Vec::new(),
+        });
+        let mut evidence = HashMap::new();
+        evidence.insert(
+            "evidence/config_1.txt".to_string(),
+            Evidence::from_file(
+                "config_1",
+                xcprobe_bundle_schema::EvidenceType::ConfigFile,
+                b"cache:\n  host: redis\nadmin: http://app01.corp:8080/\n".to_vec(),
+                "evidence/config_1.txt",
+                "/opt/billing/app.yaml",
+            ),
+        );
+
+        assert_eq!(
+            configured_host_names(&manifest, &evidence),
+            vec!["redis", "api.stripe.com", "db.internal"]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Collection progress reporting.
+//!
+//! The collector emits [`ProgressEvent`]s as phases start, advance and
+//! finish. Reporters render them as a terminal progress line or as JSON
+//! lines for CI wrappers and UIs.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::str::FromStr;
+
+/// Width of the terminal progress bar, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// A progress event emitted during collection.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// A collection phase started.
+    PhaseStarted { phase: String },
+    /// One step of a phase with a known number of steps completed.
+    Step {
+        phase: String,
+        current: usize,
+        total: usize,
+    },
+    /// A collection phase finished.
+    PhaseFinished { phase: String, duration_ms: u64 },
+    /// The whole collection finished.
+    Completed {
+        duration_ms: u64,
+        commands: usize,
+        errors: usize,
+    },
+}
+
+/// Receives progress events from the collector.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, event: ProgressEvent);
+}
+
+/// Output format for progress events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// Progress bar on stderr (only when it is a terminal).
+    Text,
+    /// One JSON object per event on stdout.
+    Json,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ProgressFormat::Text),
+            "json" => Ok(ProgressFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid progress format: {}", s)),
+        }
+    }
+}
+
+impl ProgressFormat {
+    /// Create the reporter for this format.
+    pub fn reporter(self) -> Box<dyn ProgressReporter> {
+        match self {
+            ProgressFormat::Text => Box::new(TerminalProgress),
+            ProgressFormat::Json => Box::new(JsonProgress),
+        }
+    }
+}
+
+/// Discards all events.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn report(&self, _event: ProgressEvent) {}
+}
+
+/// Draws a progress line on stderr.
+pub struct TerminalProgress;
+
+impl ProgressReporter for TerminalProgress {
+    fn report(&self, event: ProgressEvent) {
+        let mut stderr = std::io::stderr();
+        if !stderr.is_terminal() {
+            return;
+        }
+        match event {
+            ProgressEvent::Step {
+                phase,
+                current,
+                total,
+            } => {
+                let _ = write!(stderr, "\r{}", render_bar(&phase, current, total));
+            }
+            ProgressEvent::PhaseFinished { .. } => {
+                // Clear the line so log output is not interleaved with it
+                let _ = write!(stderr, "\r\x1b[2K");
+            }
+            _ => {}
+        }
+        let _ = stderr.flush();
+    }
+}
+
+/// Writes each event as a JSON line on stdout.
+pub struct JsonProgress;
+
+#[derive(Serialize)]
+struct JsonLine {
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: ProgressEvent,
+}
+
+impl ProgressReporter for JsonProgress {
+    fn report(&self, event: ProgressEvent) {
+        let line = JsonLine {
+            timestamp: Utc::now(),
+            event,
+        };
+        if let Ok(json) = serde_json::to_string(&line) {
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{}", json);
+            let _ = stdout.flush();
+        }
+    }
+}
+
+/// Render `phase [#####     ] current/total`.
+fn render_bar(phase: &str, current: usize, total: usize) -> String {
+    let filled = (current.min(total) * BAR_WIDTH)
+        .checked_div(total)
+        .unwrap_or(BAR_WIDTH);
+    format!(
+        "{} [{}{}] {}/{}",
+        phase,
+        "#".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        current,
+        total
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let line = JsonLine {
+            timestamp: Utc::now(),
+            event: ProgressEvent::Step {
+                phase: "services".to_string(),
+                current: 42,
+                total: 130,
+            },
+        };
+        let value = serde_json::to_value(&line).unwrap();
+        assert_eq!(value["event"], "step");
+        assert_eq!(value["phase"], "services");
+        assert_eq!(value["current"], 42);
+        assert_eq!(value["total"], 130);
+        assert!(value["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_render_bar() {
+        assert_eq!(
+            render_bar("services", 15, 30),
+            format!("services [{}{}] 15/30", "#".repeat(15), " ".repeat(15))
+        );
+        assert!(render_bar("logs", 0, 0).ends_with("] 0/0"));
+        assert_eq!(
+            "JSON".parse::<ProgressFormat>().unwrap(),
+            ProgressFormat::Json
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Bundle locations.
+//!
+//! A bundle path is either a local file or an object of an S3 bucket
+//! (`s3://bucket/key`), so fleets can keep their bundles in one place and
+//! analyze them from there. Google Cloud Storage is reached through its
+//! S3-compatible API; Azure Blob Storage has none and is not supported.
+
+use crate::bundle::{self, BundleFormat};
+use crate::s3::{S3Client, S3Config, S3Location};
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use xcprobe_bundle_schema::Bundle;
+
+/// Where a bundle is read from or written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleLocation {
+    File(PathBuf),
+    S3(S3Location),
+}
+
+impl BundleLocation {
+    pub fn parse(path: &Path) -> Result<Self> {
+        let Some(uri) = path.to_str() else {
+            return Ok(Self::File(path.to_path_buf()));
+        };
+        if uri.starts_with("s3://") {
+            return Ok(Self::S3(S3Location::parse(uri)?));
+        }
+        if let Some(rest) = uri.strip_prefix("gs://") {
+            bail!(
+                "gs:// is not supported: use s3://{} with \
+                 AWS_ENDPOINT_URL=https://storage.googleapis.com and an HMAC key",
+                rest
+            );
+        }
+        if uri.starts_with("az://") {
+            bail!("az:// is not supported: copy the bundle with azcopy and use the local file");
+        }
+        Ok(Self::File(path.to_path_buf()))
+    }
+}
+
+/// Whether `path` names an object in a bucket rather than a local file.
+pub fn is_remote(path: &Path) -> bool {
+    !matches!(BundleLocation::parse(path), Ok(BundleLocation::File(_)))
+}
+
+/// Read a bundle from a file or a bucket.
+pub async fn read_bundle(path: &Path) -> Result<Bundle> {
+    match BundleLocation::parse(path)? {
+        BundleLocation::File(path) => bundle::read_bundle(&path),
+        BundleLocation::S3(location) => {
+            let data = S3Client::new(S3Config::from_env()?)
+                .get_object(&location)
+                .await?;
+            bundle::read_bundle_from(data.as_slice())
+        }
+    }
+}
+
+/// Write a bundle to a file or a bucket, in the given archive layout.
+pub async fn write_bundle(bundle: &Bundle, path: &Path, format: BundleFormat) -> Result<()> {
+    match BundleLocation::parse(path)? {
+        BundleLocation::File(path) => {
+            let file = File::create(&path).context("Failed to create bundle file")?;
+            bundle::write_bundle_as(bundle, file, format)
+        }
+        BundleLocation::S3(location) => {
+            let mut data = Vec::new();
+            bundle::write_bundle_as(bundle, &mut data, format)?;
+            S3Client::new(S3Config::from_env()?)
+                .put_object(&location, &data)
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bundle_location() {
+        assert_eq!(
+            BundleLocation::parse(Path::new("s3://bundles/web01.tgz")).unwrap(),
+            BundleLocation::S3(S3Location {
+                bucket: "bundles".to_string(),
+                key: "web01.tgz".to_string(),
+            })
+        );
+        assert_eq!(
+            BundleLocation::parse(Path::new("out/bundle.tgz")).unwrap(),
+            BundleLocation::File(PathBuf::from("out/bundle.tgz"))
+        );
+        assert!(BundleLocation::parse(Path::new("gs://bundles/web01.tgz")).is_err());
+        assert!(is_remote(Path::new("s3://bundles/web01.tgz")));
+        assert!(!is_remote(Path::new("bundle.tgz")));
+    }
+}
@@ -0,0 +1,357 @@
+//! Bundle upload to a collection server.
+//!
+//! Fleet collections send their bundles to a central server instead of
+//! leaving them to be fetched over SSH. A bundle is sent in chunks, each a
+//! `PUT <url>/<name>` with a `Content-Range`. After a failed chunk the
+//! uploader asks the server how much it already has (`HEAD`, answered with
+//! `Upload-Offset`) and resumes from there. The server confirms the SHA-256
+//! of the complete file in `X-Xcprobe-Sha256`, which must match the local
+//! one. Each upload is recorded in `uploads.jsonl` next to the bundle.
+
+use crate::bundle::BundleFormat;
+use crate::collector::RetryPolicy;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::header::{AUTHORIZATION, CONTENT_RANGE, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use tracing::{debug, info, warn};
+use xcprobe_bundle_schema::Bundle;
+use xcprobe_common::hash::sha256_reader;
+
+/// File recording the uploaded bundles, in the bundle's directory.
+pub const UPLOADS_FILE: &str = "uploads.jsonl";
+
+/// Bytes sent per request.
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Header with the bytes of the file the server already stores.
+const OFFSET_HEADER: &str = "Upload-Offset";
+
+/// Header with the SHA-256 of the complete file, sent by the uploader and
+/// confirmed by the server.
+const SHA256_HEADER: &str = "X-Xcprobe-Sha256";
+
+/// An uploaded bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadRecord {
+    /// Local path of the bundle.
+    pub bundle: String,
+    /// URL of the bundle on the server.
+    pub url: String,
+    pub sha256: String,
+    pub size: u64,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// Uploads bundles to a collection server.
+pub struct Uploader {
+    base_url: String,
+    token: Option<String>,
+    chunk_size: usize,
+    retry: RetryPolicy,
+    client: reqwest::Client,
+}
+
+impl Uploader {
+    /// Upload to `base_url`, authenticating with a bearer `token`.
+    pub fn new(base_url: &str, token: Option<String>) -> Result<Self> {
+        if !base_url.starts_with("https://") && !base_url.starts_with("http://") {
+            bail!("Upload URL must be http:// or https://: {}", base_url);
+        }
+        if base_url.starts_with("http://") && token.is_some() {
+            warn!("The upload token is sent unencrypted to {}", base_url);
+        }
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            retry: RetryPolicy::default(),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Attempts per chunk, and the delay between them.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Upload the file at `path` as `name`, resuming where the server's
+    /// copy ends.
+    pub async fn upload(&self, path: &Path, name: &str) -> Result<UploadRecord> {
+        let url = format!("{}/{}", self.base_url, name);
+        let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let size = file.metadata()?.len();
+        let sha256 = sha256_reader(&mut file)?;
+
+        // A complete copy on the server is only checked
+        let (stored, mut confirmed) = self.remote_state(&url).await?;
+        let mut offset = stored.min(size);
+        if offset > 0 && offset < size {
+            info!("Resuming upload of {} at byte {}", name, offset);
+        }
+        let mut failures = 0;
+        // An empty file still takes one request
+        while offset < size || (size == 0 && confirmed.is_none()) {
+            let len = (size - offset).min(self.chunk_size as u64);
+            let mut chunk = vec![0; len as usize];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut chunk)?;
+
+            match self.put_chunk(&url, chunk, offset, size, &sha256).await {
+                Ok(server_sha256) => {
+                    offset += len;
+                    confirmed = server_sha256;
+                    failures = 0;
+                }
+                Err(e) => {
+                    failures += 1;
+                    if failures >= self.retry.max_attempts {
+                        return Err(e.context(format!("Failed to upload {}", name)));
+                    }
+                    warn!("Chunk at byte {} failed: {:#}", offset, e);
+                    tokio::time::sleep(self.retry.backoff(failures)).await;
+                    if let Ok((stored, _)) = self.remote_state(&url).await {
+                        offset = stored.min(size);
+                    }
+                }
+            }
+        }
+
+        match confirmed {
+            Some(server_sha256) if server_sha256 == sha256 => {}
+            Some(server_sha256) => bail!(
+                "Checksum mismatch for {}: sent {}, server stored {}",
+                name,
+                sha256,
+                server_sha256
+            ),
+            None => bail!("The server did not confirm the checksum of {}", name),
+        }
+
+        Ok(UploadRecord {
+            bundle: path.display().to_string(),
+            url,
+            sha256,
+            size,
+            uploaded_at: Utc::now(),
+        })
+    }
+
+    /// Bytes of the file the server already stores, and their checksum
+    /// when the server reports it.
+    async fn remote_state(&self, url: &str) -> Result<(u64, Option<String>)> {
+        let response = self.authorize(self.client.head(url)).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok((0, None));
+        }
+        let response = response.error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Ok((
+            header(OFFSET_HEADER)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            header(SHA256_HEADER),
+        ))
+    }
+
+    /// Send the bytes at `offset`, and return the checksum the server
+    /// reports, if any.
+    async fn put_chunk(
+        &self,
+        url: &str,
+        chunk: Vec<u8>,
+        offset: u64,
+        size: u64,
+        sha256: &str,
+    ) -> Result<Option<String>> {
+        debug!("PUT {} ({})", url, content_range(offset, chunk.len(), size));
+        let response = self
+            .authorize(self.client.put(url))
+            .header(CONTENT_TYPE, content_type(url))
+            .header(CONTENT_RANGE, content_range(offset, chunk.len(), size))
+            .header(SHA256_HEADER, sha256)
+            .body(chunk)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response
+            .headers()
+            .get(SHA256_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string))
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.token {
+            Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+/// Name of a bundle on the server: its collection id, unique across hosts
+/// and runs, with the extension of its format.
+pub fn remote_name(bundle: &Bundle, format: BundleFormat) -> String {
+    format!("{}.{}", bundle.manifest.collection_id, format.extension())
+}
+
+/// Media type of a bundle, from the extension of its name.
+fn content_type(name: &str) -> &'static str {
+    if name.ends_with(&format!(".{}", BundleFormat::V2.extension())) {
+        "application/zstd"
+    } else {
+        "application/gzip"
+    }
+}
+
+/// `Content-Range` of `len` bytes at `offset` of a `size`-byte file.
+fn content_range(offset: u64, len: usize, size: u64) -> String {
+    if len == 0 {
+        format!("bytes */{}", size)
+    } else {
+        format!("bytes {}-{}/{}", offset, offset + len as u64 - 1, size)
+    }
+}
+
+/// Append an upload to the `uploads.jsonl` of `dir`.
+pub fn record_upload(dir: &Path, record: &UploadRecord) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(UPLOADS_FILE))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use xcprobe_common::hash::sha256_bytes;
+
+    /// Minimal upload server storing into `stored`; the second PUT fails.
+    async fn serve(stored: Arc<Mutex<Vec<u8>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut puts = 0;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                // Read the head, then the body it announces
+                let head_end = loop {
+                    let n = socket.read(&mut buffer).await.unwrap();
+                    if n == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buffer[..n]);
+                    if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break Some(i + 4);
+                    }
+                };
+                let Some(head_end) = head_end else { continue };
+                let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+                let header = |name: &str| {
+                    head.lines()
+                        .find_map(|l| l.strip_prefix(&format!("{}: ", name)))
+                        .map(str::to_string)
+                };
+                let length: usize = header("content-length")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                while request.len() < head_end + length {
+                    let n = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                let body = &request[head_end..head_end + length];
+
+                let response = if head.starts_with("head") {
+                    let len = stored.lock().unwrap().len();
+                    format!("HTTP/1.1 200 OK\r\nUpload-Offset: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", len)
+                } else {
+                    puts += 1;
+                    assert_eq!(header("authorization").as_deref(), Some("bearer secret"));
+                    if puts == 2 {
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                    } else {
+                        let range = header("content-range").unwrap();
+                        let start: usize = range
+                            .trim_start_matches("bytes ")
+                            .split('-')
+                            .next()
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        let mut stored = stored.lock().unwrap();
+                        stored.truncate(start);
+                        stored.extend_from_slice(body);
+                        format!(
+                            "HTTP/1.1 200 OK\r\nX-Xcprobe-Sha256: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            sha256_bytes(&stored)
+                        )
+                    }
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}/bundles", address)
+    }
+
+    #[tokio::test]
+    async fn test_upload_resumes_after_failed_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.tgz");
+        let content: Vec<u8> = (0..100u8).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let stored = Arc::new(Mutex::new(Vec::new()));
+        let url = serve(stored.clone()).await;
+        let uploader = Uploader::new(&url, Some("secret".to_string()))
+            .unwrap()
+            .with_chunk_size(30)
+            .with_retry(RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            });
+
+        let record = uploader.upload(&path, "web01.tgz").await.unwrap();
+
+        assert_eq!(*stored.lock().unwrap(), content);
+        assert_eq!(record.url, format!("{}/web01.tgz", url));
+        assert_eq!(record.sha256, sha256_bytes(&content));
+        assert_eq!(record.size, 100);
+
+        record_upload(dir.path(), &record).unwrap();
+        record_upload(dir.path(), &record).unwrap();
+        let uploads = std::fs::read_to_string(dir.path().join(UPLOADS_FILE)).unwrap();
+        assert_eq!(uploads.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_content_range() {
+        assert_eq!(content_range(0, 30, 100), "bytes 0-29/100");
+        assert_eq!(content_range(90, 10, 100), "bytes 90-99/100");
+        assert_eq!(content_range(0, 0, 0), "bytes */0");
+        assert!(Uploader::new("ftp://server/bundles", None).is_err());
+    }
+}
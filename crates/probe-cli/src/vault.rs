@@ -0,0 +1,154 @@
+//! Encrypted vault of redacted values.
+//!
+//! When collecting with a vault key, the original of every redacted value is
+//! kept in a `secrets.vault` sidecar next to the bundle, keyed by its hash
+//! placeholder, so operators can recover it at migration cutover without
+//! collecting again. The vault is AES-256-GCM encrypted with a key derived
+//! from the passphrase with PBKDF2-HMAC-SHA256.
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use openssl::hash::MessageDigest;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// File name of the vault, written next to the bundle.
+pub const VAULT_FILE_NAME: &str = "secrets.vault";
+
+const VAULT_VERSION: u32 = 1;
+const PBKDF2_ITERATIONS: usize = 600_000;
+/// Authenticated with the ciphertext, binding it to the vault format.
+const AAD: &[u8] = b"xcprobe-vault-v1";
+
+/// On-disk vault: encryption parameters and the encrypted JSON map.
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: String,
+    iterations: usize,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+    tag: String,
+}
+
+/// Read a vault key file. Surrounding whitespace is ignored.
+pub fn read_key_file(path: &Path) -> Result<String> {
+    let key = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read vault key file {}", path.display()))?;
+    let key = key.trim();
+    if key.is_empty() {
+        anyhow::bail!("Vault key file {} is empty", path.display());
+    }
+    Ok(key.to_string())
+}
+
+/// Encrypt placeholder → original mappings.
+pub fn seal(originals: &BTreeMap<String, String>, passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    openssl::rand::rand_bytes(&mut salt).context("Failed to generate vault salt")?;
+    openssl::rand::rand_bytes(&mut nonce).context("Failed to generate vault nonce")?;
+    let key = derive_key(passphrase, &salt, PBKDF2_ITERATIONS)?;
+
+    let plaintext = serde_json::to_vec(originals)?;
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&nonce),
+        AAD,
+        &plaintext,
+        &mut tag,
+    )
+    .context("Failed to encrypt vault")?;
+
+    let file = VaultFile {
+        version: VAULT_VERSION,
+        kdf: "pbkdf2-hmac-sha256".to_string(),
+        iterations: PBKDF2_ITERATIONS,
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+        tag: STANDARD.encode(tag),
+    };
+    Ok(serde_json::to_vec_pretty(&file)?)
+}
+
+/// Decrypt a sealed vault.
+pub fn open(content: &[u8], passphrase: &str) -> Result<BTreeMap<String, String>> {
+    let file: VaultFile = serde_json::from_slice(content).context("Invalid vault file")?;
+    if file.version != VAULT_VERSION {
+        anyhow::bail!("Unsupported vault version {}", file.version);
+    }
+    let key = derive_key(passphrase, &STANDARD.decode(&file.salt)?, file.iterations)?;
+    let plaintext = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(&STANDARD.decode(&file.nonce)?),
+        AAD,
+        &STANDARD.decode(&file.ciphertext)?,
+        &STANDARD.decode(&file.tag)?,
+    )
+    .map_err(|_| anyhow::anyhow!("Wrong vault key or corrupted vault"))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Write an encrypted vault file, readable by its owner only.
+pub fn write_vault(
+    path: &Path,
+    originals: &BTreeMap<String, String>,
+    passphrase: &str,
+) -> Result<()> {
+    let sealed = seal(originals, passphrase)?;
+    std::fs::write(path, sealed)
+        .with_context(|| format!("Failed to write vault {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Read and decrypt a vault file.
+pub fn read_vault(path: &Path, passphrase: &str) -> Result<BTreeMap<String, String>> {
+    let content =
+        std::fs::read(path).with_context(|| format!("Failed to read vault {}", path.display()))?;
+    open(&content, passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: usize) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    openssl::pkcs5::pbkdf2_hmac(
+        passphrase.as_bytes(),
+        salt,
+        iterations,
+        MessageDigest::sha256(),
+        &mut key,
+    )
+    .context("Failed to derive vault key")?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let originals = BTreeMap::from([(
+            "[HASH:0123456789ab]".to_string(),
+            "DB_PASSWORD=hunter2".to_string(),
+        )]);
+        let sealed = seal(&originals, "correct horse").unwrap();
+        assert!(!String::from_utf8_lossy(&sealed).contains("hunter2"));
+
+        assert_eq!(open(&sealed, "correct horse").unwrap(), originals);
+        let err = open(&sealed, "wrong horse").unwrap_err();
+        assert!(err.to_string().contains("Wrong vault key"));
+    }
+}
@@ -0,0 +1,318 @@
+//! Continuous collection.
+//!
+//! A single snapshot misses what only happens now and then: weekly batch
+//! jobs, services started on demand, connections opened at peak hours. The
+//! watcher collects a target again and again, on an interval or sooner
+//! when its services change, writes each bundle to a directory under a
+//! timestamped name, optionally uploads it to a collection server, and
+//! removes the bundles that fall out of the retention policy.
+
+use crate::bundle::{write_bundle_as, BundleFormat};
+use crate::collector::Collector;
+use crate::upload::{record_upload, remote_name, Uploader};
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+use tracing::{info, warn};
+
+/// File name prefix of the bundles written by the watcher.
+const BUNDLE_PREFIX: &str = "bundle-";
+
+/// Collection time in bundle file names, sortable and unique to the
+/// millisecond.
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Watch configuration.
+pub struct WatchConfig {
+    /// Directory of the bundles.
+    pub out_dir: PathBuf,
+    /// Archive layout of the bundles.
+    pub format: BundleFormat,
+    /// Time between two collections.
+    pub interval: Duration,
+    /// Check the service list this often and collect as soon as it
+    /// changed; disabled when `None`.
+    pub service_poll: Option<Duration>,
+    /// Uploads each bundle to a collection server.
+    pub upload: Option<Uploader>,
+    /// Bundles kept in the directory.
+    pub retention: Retention,
+    /// Stop after this many collections; run until interrupted when `None`.
+    pub count: Option<usize>,
+}
+
+/// Which bundles are kept in the directory.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    /// Keep at most this many bundles, the most recent.
+    pub max_bundles: Option<usize>,
+    /// Remove bundles collected longer ago than this.
+    pub max_age: Option<Duration>,
+}
+
+/// Collects a target repeatedly.
+pub struct Watcher {
+    collector: Collector,
+    config: WatchConfig,
+}
+
+impl Watcher {
+    pub fn new(collector: Collector, config: WatchConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.out_dir)
+            .with_context(|| format!("Failed to create bundle directory {:?}", config.out_dir))?;
+        Ok(Self { collector, config })
+    }
+
+    /// Collect until `count` collections are done or Ctrl-C is pressed, and
+    /// return the bundles written. A failure of the first collection is
+    /// returned, as it is most likely a configuration error; later ones are
+    /// logged and the next collection is attempted as planned.
+    pub async fn run(&self) -> Result<Vec<PathBuf>> {
+        let mut written = Vec::new();
+        let mut attempts = 0;
+        loop {
+            let baseline = self.service_state().await;
+            match self.collect_once().await {
+                Ok(path) => written.push(path),
+                Err(e) if attempts == 0 => return Err(e),
+                Err(e) => warn!("Collection failed: {:#}", e),
+            }
+            attempts += 1;
+            if self.config.count.is_some_and(|count| attempts >= count) {
+                break;
+            }
+
+            tokio::select! {
+                reason = self.wait(baseline) => info!("Collecting again: {}", reason),
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Interrupted, stopping");
+                    break;
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    /// Collect once, then write, upload and prune the bundles.
+    async fn collect_once(&self) -> Result<PathBuf> {
+        let started = Utc::now();
+        let bundle = self.collector.collect().await?;
+        let path = self
+            .config
+            .out_dir
+            .join(bundle_file_name(started, self.config.format));
+        let file = File::create(&path).context("Failed to create bundle file")?;
+        write_bundle_as(&bundle, file, self.config.format)?;
+        info!("Bundle written to {:?}", path);
+
+        if let Some(ref uploader) = self.config.upload {
+            // The bundle stays on disk when the upload fails
+            match uploader
+                .upload(&path, &remote_name(&bundle, self.config.format))
+                .await
+            {
+                Ok(record) => {
+                    info!("Bundle uploaded to {}", record.url);
+                    record_upload(&self.config.out_dir, &record)?;
+                }
+                Err(e) => warn!("Failed to upload {:?}: {:#}", path, e),
+            }
+        }
+
+        for removed in apply_retention(&self.config.out_dir, self.config.retention, Utc::now())? {
+            info!("Removed {:?} (retention)", removed);
+        }
+        Ok(path)
+    }
+
+    /// Wait for the next collection: the interval elapsed, or the service
+    /// list differs from `baseline`.
+    async fn wait(&self, baseline: Option<String>) -> &'static str {
+        let deadline = Instant::now() + self.config.interval;
+        let (Some(poll), Some(baseline)) = (self.config.service_poll, baseline) else {
+            tokio::time::sleep_until(deadline).await;
+            return "interval elapsed";
+        };
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return "interval elapsed";
+            }
+            sleep(poll.min(deadline - now)).await;
+            if Instant::now() >= deadline {
+                return "interval elapsed";
+            }
+            if self
+                .service_state()
+                .await
+                .is_some_and(|state| state != baseline)
+            {
+                return "services changed";
+            }
+        }
+    }
+
+    /// The service list, when service changes are watched.
+    async fn service_state(&self) -> Option<String> {
+        self.config.service_poll?;
+        match self.collector.service_state().await {
+            Ok(state) => Some(state),
+            Err(e) => {
+                warn!("Failed to list services: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Name of the bundle collected at `at`.
+pub fn bundle_file_name(at: DateTime<Utc>, format: BundleFormat) -> String {
+    format!(
+        "{}{}.{}",
+        BUNDLE_PREFIX,
+        at.format(TIMESTAMP_FORMAT),
+        format.extension()
+    )
+}
+
+/// Collection time of a bundle written by the watcher, from its name, in
+/// either format: the format may have changed between runs.
+fn bundle_time(name: &str) -> Option<DateTime<Utc>> {
+    let name = name.strip_prefix(BUNDLE_PREFIX)?;
+    let timestamp = [BundleFormat::V1, BundleFormat::V2]
+        .into_iter()
+        .find_map(|format| name.strip_suffix(&format!(".{}", format.extension())))?;
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Remove the bundles of `dir` outside the retention policy, and return
+/// them. Other files are left alone.
+pub fn apply_retention(
+    dir: &Path,
+    retention: Retention,
+    now: DateTime<Utc>,
+) -> Result<Vec<PathBuf>> {
+    let mut bundles: Vec<(DateTime<Utc>, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let time = bundle_time(&entry.file_name().to_string_lossy())?;
+            Some((time, entry.path()))
+        })
+        .collect();
+    // Most recent first
+    bundles.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+
+    let mut removed = Vec::new();
+    for (index, (time, path)) in bundles.into_iter().enumerate() {
+        let too_many = retention.max_bundles.is_some_and(|max| index >= max);
+        let too_old = retention
+            .max_age
+            .is_some_and(|max_age| (now - time).to_std().is_ok_and(|age| age > max_age));
+        if too_many || too_old {
+            std::fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::Executor;
+    use chrono::TimeZone;
+
+    struct HostnameExecutor;
+
+    #[async_trait::async_trait]
+    impl Executor for HostnameExecutor {
+        async fn execute(&self, _command: &str) -> Result<(Option<i32>, String, String)> {
+            Ok((Some(0), "web01".to_string(), String::new()))
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = |d| Utc.with_ymd_and_hms(2026, 3, d, 12, 0, 0).unwrap();
+        for d in 1..=5 {
+            // Bundles of both formats count
+            let format = if d % 2 == 0 {
+                BundleFormat::V2
+            } else {
+                BundleFormat::V1
+            };
+            std::fs::write(dir.path().join(bundle_file_name(day(d), format)), b"").unwrap();
+        }
+        std::fs::write(dir.path().join("notes.txt"), b"").unwrap();
+
+        let removed = apply_retention(
+            dir.path(),
+            Retention {
+                max_bundles: Some(3),
+                max_age: Some(Duration::from_secs(60 * 3600)),
+            },
+            day(6),
+        )
+        .unwrap();
+
+        assert_eq!(removed.len(), 3);
+        let mut left: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec![
+                "bundle-20260304T120000.000Z.tar.zst",
+                "bundle-20260305T120000.000Z.tgz",
+                "notes.txt"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_writes_timestamped_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = Collector::builder()
+            .only_phases(["system"])
+            .executor(HostnameExecutor)
+            .build()
+            .unwrap();
+        let watcher = Watcher::new(
+            collector,
+            WatchConfig {
+                out_dir: dir.path().to_path_buf(),
+                format: BundleFormat::V2,
+                interval: Duration::from_millis(10),
+                service_poll: Some(Duration::from_millis(2)),
+                upload: None,
+                retention: Retention {
+                    max_bundles: Some(2),
+                    max_age: None,
+                },
+                count: Some(3),
+            },
+        )
+        .unwrap();
+
+        let written = watcher.run().await.unwrap();
+
+        assert_eq!(written.len(), 3);
+        assert!(!written[0].exists());
+        assert!(written[2].exists());
+        assert!(written[2].to_string_lossy().ends_with(".tar.zst"));
+        let bundle = crate::bundle::read_bundle(&written[2]).unwrap();
+        assert_eq!(bundle.manifest.system.hostname, "web01");
+    }
+}
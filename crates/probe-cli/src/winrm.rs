@@ -0,0 +1,408 @@
+//! WS-Management (WinRM) remote shell protocol messages.
+//!
+//! A command runs through the full shell lifecycle: Create a `cmd` shell,
+//! start the Command, Receive its output streams until the command state is
+//! Done, Signal terminate, then Delete the shell.
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use regex::Regex;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+const SHELL_RESOURCE_URI: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/cmd";
+const ACTION_CREATE: &str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Create";
+const ACTION_DELETE: &str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Delete";
+const ACTION_COMMAND: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Command";
+const ACTION_RECEIVE: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Receive";
+const ACTION_SIGNAL: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Signal";
+const SIGNAL_TERMINATE: &str =
+    "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/signal/terminate";
+
+/// Server-side operation timeout for a single request (ISO 8601 duration).
+const OPERATION_TIMEOUT: &str = "PT60S";
+/// Maximum SOAP envelope size accepted from the server.
+const MAX_ENVELOPE_SIZE: u32 = 512_000;
+/// WS-Man fault code returned when a Receive times out without output.
+const OPERATION_TIMEOUT_FAULT: &str = "2150858793";
+
+/// WinRM authentication scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WinRmAuth {
+    /// HTTP Basic (local accounts only, must be enabled on the server).
+    Basic,
+    /// NTLMv2 over the Negotiate HTTP scheme.
+    Ntlm,
+    /// Kerberos through SPNEGO, falling back to NTLM when no Kerberos
+    /// credentials or service ticket can be obtained.
+    Negotiate,
+}
+
+impl FromStr for WinRmAuth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "basic" => Ok(WinRmAuth::Basic),
+            "ntlm" => Ok(WinRmAuth::Ntlm),
+            "negotiate" | "kerberos" => Ok(WinRmAuth::Negotiate),
+            _ => Err(anyhow::anyhow!("Invalid WinRM auth scheme: {}", s)),
+        }
+    }
+}
+
+/// A WinRM user name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Account {
+    /// `DOMAIN\user`, with the NetBIOS name of the domain.
+    DownLevel { domain: String, user: String },
+    /// `user@realm`, a user principal name.
+    Principal { user: String, realm: String },
+    /// A bare name, a local account of the target.
+    Local(String),
+}
+
+impl Account {
+    pub fn parse(name: &str) -> Self {
+        if let Some((domain, user)) = name.split_once('\\') {
+            return Account::DownLevel {
+                domain: domain.to_string(),
+                user: user.to_string(),
+            };
+        }
+        match name.rsplit_once('@') {
+            Some((user, realm)) if !user.is_empty() && !realm.is_empty() => Account::Principal {
+                user: user.to_string(),
+                realm: realm.to_string(),
+            },
+            _ => Account::Local(name.to_string()),
+        }
+    }
+
+    /// Kerberos principal of a domain account. The realm of a NetBIOS
+    /// domain is unknown, so that of the Kerberos configuration applies.
+    pub fn kerberos_principal(&self) -> Option<String> {
+        match self {
+            Account::DownLevel { user, .. } => Some(user.clone()),
+            Account::Principal { user, realm } => {
+                Some(format!("{}@{}", user, realm.to_uppercase()))
+            }
+            Account::Local(_) => None,
+        }
+    }
+}
+
+/// Output gathered from one or more Receive responses.
+#[derive(Debug, Default)]
+pub struct ReceiveOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub done: bool,
+    pub exit_code: Option<i32>,
+}
+
+/// Create Shell request.
+pub fn create_shell_envelope(endpoint: &str) -> String {
+    envelope(
+        endpoint,
+        ACTION_CREATE,
+        None,
+        r#"<w:OptionSet><w:Option Name="WINRS_NOPROFILE">TRUE</w:Option><w:Option Name="WINRS_CODEPAGE">65001</w:Option></w:OptionSet>"#,
+        "<rsp:Shell><rsp:InputStreams>stdin</rsp:InputStreams><rsp:OutputStreams>stdout stderr</rsp:OutputStreams></rsp:Shell>",
+    )
+}
+
+/// Command request running `command` through PowerShell.
+pub fn command_envelope(endpoint: &str, shell_id: &str, command: &str) -> String {
+    // PowerShell expects the encoded command as UTF-16LE base64
+    let utf16: Vec<u8> = command
+        .encode_utf16()
+        .flat_map(|c| c.to_le_bytes())
+        .collect();
+    let body = format!(
+        "<rsp:CommandLine><rsp:Command>powershell.exe</rsp:Command><rsp:Arguments>-NoProfile -NonInteractive -EncodedCommand {}</rsp:Arguments></rsp:CommandLine>",
+        BASE64.encode(utf16)
+    );
+    envelope(
+        endpoint,
+        ACTION_COMMAND,
+        Some(shell_id),
+        r#"<w:OptionSet><w:Option Name="WINRS_CONSOLEMODE_STDIN">TRUE</w:Option><w:Option Name="WINRS_SKIP_CMD_SHELL">FALSE</w:Option></w:OptionSet>"#,
+        &body,
+    )
+}
+
+/// Receive request for the stdout and stderr streams of a command.
+pub fn receive_envelope(endpoint: &str, shell_id: &str, command_id: &str) -> String {
+    let body = format!(
+        r#"<rsp:Receive><rsp:DesiredStream CommandId="{}">stdout stderr</rsp:DesiredStream></rsp:Receive>"#,
+        command_id
+    );
+    envelope(endpoint, ACTION_RECEIVE, Some(shell_id), "", &body)
+}
+
+/// Signal request terminating a command.
+pub fn signal_envelope(endpoint: &str, shell_id: &str, command_id: &str) -> String {
+    let body = format!(
+        r#"<rsp:Signal CommandId="{}"><rsp:Code>{}</rsp:Code></rsp:Signal>"#,
+        command_id, SIGNAL_TERMINATE
+    );
+    envelope(endpoint, ACTION_SIGNAL, Some(shell_id), "", &body)
+}
+
+/// Delete Shell request.
+pub fn delete_shell_envelope(endpoint: &str, shell_id: &str) -> String {
+    envelope(endpoint, ACTION_DELETE, Some(shell_id), "", "")
+}
+
+fn envelope(
+    endpoint: &str,
+    action: &str,
+    shell_id: Option<&str>,
+    options: &str,
+    body: &str,
+) -> String {
+    let selector = shell_id
+        .map(|id| {
+            format!(
+                r#"<w:SelectorSet><w:Selector Name="ShellId">{}</w:Selector></w:SelectorSet>"#,
+                xml_escape(id)
+            )
+        })
+        .unwrap_or_default();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope" xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing" xmlns:w="http://schemas.dmtf.org/wbem/wsman/1/wsman.xsd" xmlns:rsp="http://schemas.microsoft.com/wbem/wsman/1/windows/shell">
+  <s:Header>
+    <a:To>{endpoint}</a:To>
+    <a:ReplyTo><a:Address s:mustUnderstand="true">http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</a:Address></a:ReplyTo>
+    <w:MaxEnvelopeSize s:mustUnderstand="true">{max_size}</w:MaxEnvelopeSize>
+    <a:MessageID>uuid:{message_id}</a:MessageID>
+    <w:Locale xml:lang="en-US" s:mustUnderstand="false"/>
+    <w:OperationTimeout>{timeout}</w:OperationTimeout>
+    <w:ResourceURI s:mustUnderstand="true">{resource}</w:ResourceURI>
+    <a:Action s:mustUnderstand="true">{action}</a:Action>
+    {selector}{options}
+  </s:Header>
+  <s:Body>{body}</s:Body>
+</s:Envelope>"#,
+        endpoint = xml_escape(endpoint),
+        max_size = MAX_ENVELOPE_SIZE,
+        message_id = uuid::Uuid::new_v4().to_string().to_uppercase(),
+        timeout = OPERATION_TIMEOUT,
+        resource = SHELL_RESOURCE_URI,
+        action = action,
+        selector = selector,
+        options = options,
+        body = body,
+    )
+}
+
+/// Extract the ShellId from a Create response.
+pub fn parse_shell_id(response: &str) -> Result<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r#"<(?:\w+:)?(?:ShellId>|Selector Name="ShellId">)\s*([^<\s]+)\s*<"#).unwrap()
+    });
+    re.captures(response)
+        .map(|c| c[1].to_string())
+        .context("No ShellId in WinRM Create response")
+}
+
+/// Extract the CommandId from a Command response.
+pub fn parse_command_id(response: &str) -> Result<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"<(?:\w+:)?CommandId>\s*([^<\s]+)\s*<").unwrap());
+    re.captures(response)
+        .map(|c| c[1].to_string())
+        .context("No CommandId in WinRM Command response")
+}
+
+/// Decode the output streams, command state and exit code of a Receive
+/// response, appending to `output`.
+pub fn parse_receive(response: &str, output: &mut ReceiveOutput) -> Result<()> {
+    static STREAM_RE: OnceLock<Regex> = OnceLock::new();
+    static DONE_RE: OnceLock<Regex> = OnceLock::new();
+    static EXIT_RE: OnceLock<Regex> = OnceLock::new();
+
+    let stream_re = STREAM_RE.get_or_init(|| {
+        Regex::new(r#"<(?:\w+:)?Stream\b([^>]*?)(?:/>|>([^<]*)</(?:\w+:)?Stream>)"#).unwrap()
+    });
+    let done_re = DONE_RE
+        .get_or_init(|| Regex::new(r#"<(?:\w+:)?CommandState\b[^>]*State="[^"]*/Done""#).unwrap());
+    let exit_re = EXIT_RE.get_or_init(|| Regex::new(r"<(?:\w+:)?ExitCode>\s*(-?\d+)\s*<").unwrap());
+
+    for caps in stream_re.captures_iter(response) {
+        let content = match caps.get(2) {
+            Some(m) if !m.as_str().trim().is_empty() => m.as_str().trim(),
+            _ => continue,
+        };
+        let decoded = BASE64
+            .decode(content)
+            .context("Invalid base64 in WinRM output stream")?;
+
+        let attrs = &caps[1];
+        if attrs.contains(r#"Name="stderr""#) {
+            output.stderr.extend_from_slice(&decoded);
+        } else if attrs.contains(r#"Name="stdout""#) {
+            output.stdout.extend_from_slice(&decoded);
+        }
+    }
+
+    if done_re.is_match(response) {
+        output.done = true;
+        if let Some(caps) = exit_re.captures(response) {
+            // Windows exit codes are DWORDs; negative NTSTATUS values wrap
+            output.exit_code = caps[1].parse::<i64>().ok().map(|code| code as u32 as i32);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a fault response is the benign Receive operation timeout.
+pub fn is_operation_timeout(response: &str) -> bool {
+    response.contains(OPERATION_TIMEOUT_FAULT)
+}
+
+/// Human-readable message from a SOAP fault.
+pub fn fault_message(response: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"(?s)<(?:\w+:)?(?:Message|Text)\b[^>]*>\s*([^<]+?)\s*</").unwrap()
+    });
+    re.captures(response)
+        .map(|c| c[1].to_string())
+        .unwrap_or_else(|| response.chars().take(200).collect())
+}
+
+/// Turn PowerShell's CLIXML error serialization on stderr into plain text.
+pub fn decode_clixml(stderr: &str) -> String {
+    const MARKER: &str = "#< CLIXML";
+    if !stderr.trim_start().starts_with(MARKER) {
+        return stderr.to_string();
+    }
+
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r#"<S S="Error">([^<]*)</S>"#).unwrap());
+
+    re.captures_iter(stderr)
+        .map(|c| {
+            c[1].replace("_x000D_", "\r")
+                .replace("_x000A_", "\n")
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+        .collect()
+}
+
+/// Raise an error if the response is a SOAP fault.
+pub fn check_fault(status: u16, response: &str) -> Result<()> {
+    if status >= 400 {
+        bail!("WinRM error: {} - {}", status, fault_message(response));
+    }
+    Ok(())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shell_and_command_ids() {
+        let create = r#"<s:Body><x:ResourceCreated><a:ReferenceParameters><w:SelectorSet><w:Selector Name="ShellId">11111111-AAAA</w:Selector></w:SelectorSet></a:ReferenceParameters></x:ResourceCreated><rsp:Shell><rsp:ShellId>11111111-AAAA</rsp:ShellId></rsp:Shell></s:Body>"#;
+        assert_eq!(parse_shell_id(create).unwrap(), "11111111-AAAA");
+
+        let command =
+            "<rsp:CommandResponse><rsp:CommandId>2222-BBBB</rsp:CommandId></rsp:CommandResponse>";
+        assert_eq!(parse_command_id(command).unwrap(), "2222-BBBB");
+        assert!(parse_command_id("<empty/>").is_err());
+
+        let env = command_envelope("http://host:5985/wsman", "11111111-AAAA", "hostname");
+        assert!(env.contains(r#"<w:Selector Name="ShellId">11111111-AAAA</w:Selector>"#));
+        assert!(env.contains("-EncodedCommand aABvAHMAdABuAGEAbQBlAA=="));
+    }
+
+    #[test]
+    fn test_parse_receive_streams_and_exit_code() {
+        let running = r#"<rsp:ReceiveResponse>
+<rsp:Stream Name="stdout" CommandId="C1">aGVsbG8g</rsp:Stream>
+<rsp:Stream Name="stderr" CommandId="C1">b29wcw==</rsp:Stream>
+<rsp:CommandState CommandId="C1" State="http://schemas.microsoft.com/wbem/wsman/1/windows/shell/CommandState/Running"/>
+</rsp:ReceiveResponse>"#;
+        let done = r#"<rsp:ReceiveResponse>
+<rsp:Stream Name="stdout" CommandId="C1">d29ybGQ=</rsp:Stream>
+<rsp:Stream Name="stdout" CommandId="C1" End="true"></rsp:Stream>
+<rsp:Stream Name="stderr" CommandId="C1" End="true"/>
+<rsp:CommandState CommandId="C1" State="http://schemas.microsoft.com/wbem/wsman/1/windows/shell/CommandState/Done"><rsp:ExitCode>3</rsp:ExitCode></rsp:CommandState>
+</rsp:ReceiveResponse>"#;
+
+        let mut output = ReceiveOutput::default();
+        parse_receive(running, &mut output).unwrap();
+        assert!(!output.done);
+        parse_receive(done, &mut output).unwrap();
+
+        assert!(output.done);
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stdout, b"hello world");
+        assert_eq!(output.stderr, b"oops");
+    }
+
+    #[test]
+    fn test_faults_and_clixml() {
+        let fault = r#"<s:Fault><s:Code><s:Value>s:Receiver</s:Value></s:Code><s:Reason><s:Text xml:lang="en-US">The WS-Management service cannot process the request.</s:Text></s:Reason><s:Detail><f:WSManFault Code="2150858793"/></s:Detail></s:Fault>"#;
+        assert!(is_operation_timeout(fault));
+        assert_eq!(
+            fault_message(fault),
+            "The WS-Management service cannot process the request."
+        );
+        assert!(check_fault(500, fault).is_err());
+        assert!(check_fault(200, "").is_ok());
+
+        let clixml = r#"#< CLIXML
+<Objs Version="1.1.0.1"><S S="Error">Get-Foo : not found_x000D__x000A_</S><S S="Error">At line:1 &lt;x&gt;_x000D__x000A_</S></Objs>"#;
+        assert_eq!(
+            decode_clixml(clixml),
+            "Get-Foo : not found\r\nAt line:1 <x>\r\n"
+        );
+        assert_eq!(decode_clixml("plain"), "plain");
+        assert_eq!("NTLM".parse::<WinRmAuth>().unwrap(), WinRmAuth::Ntlm);
+        assert_eq!(
+            "negotiate".parse::<WinRmAuth>().unwrap(),
+            WinRmAuth::Negotiate
+        );
+    }
+
+    #[test]
+    fn test_account_names() {
+        assert_eq!(
+            Account::parse("CORP\\svc-probe"),
+            Account::DownLevel {
+                domain: "CORP".to_string(),
+                user: "svc-probe".to_string()
+            }
+        );
+        let upn = Account::parse("svc-probe@corp.example.com");
+        assert_eq!(
+            upn.kerberos_principal().as_deref(),
+            Some("svc-probe@CORP.EXAMPLE.COM")
+        );
+        assert_eq!(
+            Account::parse("Administrator"),
+            Account::Local("Administrator".to_string())
+        );
+        assert_eq!(Account::parse("Administrator").kerberos_principal(), None);
+        assert_eq!(Account::parse("@corp"), Account::Local("@corp".to_string()));
+    }
+}
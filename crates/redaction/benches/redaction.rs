@@ -0,0 +1,46 @@
+//! Redaction throughput on multi-megabyte evidence.
+//!
+//! Run with `cargo bench -p xcprobe-redaction`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use xcprobe_redaction::Redactor;
+
+/// Log-like content with a secret every `secret_every` lines.
+fn log_content(size: usize, secret_every: usize) -> String {
+    let mut content = String::with_capacity(size + 256);
+    let mut line = 0;
+    while content.len() < size {
+        line += 1;
+        if line % secret_every == 0 {
+            content.push_str(&format!(
+                "2024-01-15T10:00:{:02}Z app[1234]: connecting to postgres://app:s3cr3t{}@db:5432/app\n",
+                line % 60,
+                line
+            ));
+        } else {
+            content.push_str(&format!(
+                "2024-01-15T10:00:{:02}Z app[1234]: GET /api/orders/{} 200 12ms user=alice\n",
+                line % 60,
+                line
+            ));
+        }
+    }
+    content
+}
+
+fn bench_redact(c: &mut Criterion) {
+    let redactor = Redactor::new();
+    let mut group = c.benchmark_group("redact");
+    group.sample_size(10);
+    for (name, secret_every) in [("clean", usize::MAX), ("secrets", 50)] {
+        let content = log_content(4 * 1024 * 1024, secret_every);
+        group.throughput(Throughput::Bytes(content.len() as u64));
+        group.bench_with_input(BenchmarkId::new(name, "4MiB"), &content, |b, content| {
+            b.iter(|| redactor.redact(content))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_redact);
+criterion_main!(benches);
@@ -48,10 +48,77 @@ pub fn is_high_entropy(s: &str, threshold: f64) -> bool {
 /// Default entropy threshold for secret detection.
 pub const DEFAULT_ENTROPY_THRESHOLD: f64 = 4.0;
 
+/// Default minimum length of a token.
+pub const DEFAULT_MIN_TOKEN_LENGTH: usize = 16;
+
+/// Default minimum number of character classes in a token.
+pub const DEFAULT_MIN_CHAR_CLASSES: usize = 2;
+
+/// Thresholds a word must pass to be considered a token.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenThresholds {
+    /// Minimum length in bytes.
+    pub min_length: usize,
+    /// Minimum number of character classes, see [`char_classes`].
+    pub min_char_classes: usize,
+    /// Minimum Shannon entropy in bits per character.
+    pub entropy: f64,
+}
+
+impl Default for TokenThresholds {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_MIN_TOKEN_LENGTH,
+            min_char_classes: DEFAULT_MIN_CHAR_CLASSES,
+            entropy: DEFAULT_ENTROPY_THRESHOLD,
+        }
+    }
+}
+
+/// Number of character classes (lowercase, uppercase, digits, other) in a
+/// string.
+pub fn char_classes(s: &str) -> usize {
+    let mut classes = [false; 4];
+    for c in s.chars() {
+        let class = if c.is_lowercase() {
+            0
+        } else if c.is_uppercase() {
+            1
+        } else if c.is_numeric() {
+            2
+        } else {
+            3
+        };
+        classes[class] = true;
+    }
+    classes.iter().filter(|&&present| present).count()
+}
+
+/// Whether a string has the shape of a known non-secret value: a UUID, a
+/// hex digest (git SHAs, checksums) or a file path.
+pub fn is_safe_shape(s: &str) -> bool {
+    is_uuid(s)
+        || (s.len() >= 7 && s.chars().all(|c| c.is_ascii_hexdigit()))
+        || ["/", "./", "../", "~/"].iter().any(|p| s.starts_with(p))
+}
+
+fn is_uuid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
 /// Check if a string looks like a secret token.
 pub fn looks_like_token(s: &str) -> bool {
-    // Must be at least 16 chars
-    if s.len() < 16 {
+    looks_like_token_with(s, &TokenThresholds::default())
+}
+
+/// Check if a string looks like a secret token with custom thresholds.
+pub fn looks_like_token_with(s: &str, thresholds: &TokenThresholds) -> bool {
+    if s.len() < thresholds.min_length || is_safe_shape(s) {
         return false;
     }
 
@@ -60,12 +127,12 @@ pub fn looks_like_token(s: &str) -> bool {
     let alphanumeric_ratio = alphanumeric_count as f64 / s.len() as f64;
 
     // Tokens are usually mostly alphanumeric with some symbols
-    if alphanumeric_ratio < 0.7 {
+    if alphanumeric_ratio < 0.7 || char_classes(s) < thresholds.min_char_classes {
         return false;
     }
 
     // Check entropy
-    is_high_entropy(s, DEFAULT_ENTROPY_THRESHOLD)
+    s.len() <= 256 && shannon_entropy(s) >= thresholds.entropy
 }
 
 #[cfg(test)]
@@ -121,4 +188,20 @@ mod tests {
         assert!(!looks_like_token("hello world this is a sentence"));
         assert!(!looks_like_token("short"));
     }
+
+    #[test]
+    fn test_safe_shapes_are_not_tokens() {
+        assert!(!looks_like_token("3f2b8c1e-9a4d-4e6f-b7c2-1d5e8f9a0b3c"));
+        assert!(!looks_like_token(
+            "9fceb02d0ae598e95dc970b74767f19372d61af8"
+        ));
+        assert!(!looks_like_token("/var/lib/app/Xk9mQ2vL8nR4tW7yB3cF"));
+
+        assert_eq!(char_classes("abcdef0123"), 2);
+        let strict = TokenThresholds {
+            min_char_classes: 4,
+            ..Default::default()
+        };
+        assert!(!looks_like_token_with("sk1234567890abcdefghij", &strict));
+    }
 }
@@ -6,8 +6,12 @@
 pub mod entropy;
 pub mod patterns;
 pub mod redactor;
+pub mod structured;
 
-pub use redactor::{RedactionResult, RedactionStats, Redactor};
+pub use redactor::{
+    RedactionConfigError, RedactionResult, RedactionStats, Redactor, RedactorConfig,
+};
+pub use structured::FileFormat;
 
 /// Default placeholder for redacted values.
 pub const REDACTED_PLACEHOLDER: &str = "[REDACTED]";
@@ -10,7 +10,11 @@ pub static SECRET_KEY_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
 
 /// Pattern for Authorization headers.
 pub static AUTH_HEADER_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"(?i)(Authorization|X-Api-Key|X-Auth-Token|X-Access-Token):\s*\S+").unwrap()
+    Regex::new(concat!(
+        r"(?i)(Authorization|X-Api-Key|X-Auth-Token|X-Access-Token):\s*",
+        r"(?:(?:Bearer|Basic|Digest|Token)\s+)?\S+"
+    ))
+    .unwrap()
 });
 
 /// Pattern for connection strings.
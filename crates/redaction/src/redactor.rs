@@ -1,22 +1,62 @@
 //! Main redactor implementation.
 
-use crate::entropy::{looks_like_token, DEFAULT_ENTROPY_THRESHOLD};
+use crate::entropy::{
+    looks_like_token_with, TokenThresholds, DEFAULT_ENTROPY_THRESHOLD, DEFAULT_MIN_CHAR_CLASSES,
+    DEFAULT_MIN_TOKEN_LENGTH,
+};
 use crate::patterns::{self, is_sensitive_key};
 use crate::{hash_placeholder, REDACTED_PLACEHOLDER};
-use regex::Regex;
-use tracing::{debug, trace};
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{debug, trace, warn};
+
+/// How far before a high-entropy word, on the same line, a secret keyword
+/// is looked for.
+const KEYWORD_WINDOW: usize = 64;
 
 /// Configuration for the redactor.
-#[derive(Debug, Clone)]
+///
+/// Can be loaded from a YAML rules file:
+///
+/// ```yaml
+/// patterns: ['INV-\d{8}']
+/// sensitive_keys: [PIN_CODE]
+/// allow_keys: [PASSWORD_POLICY]
+/// hash_placeholders: true
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct RedactorConfig {
     /// Use hash placeholders instead of generic [REDACTED].
+    #[serde(rename = "hash_placeholders")]
     pub use_hash_placeholders: bool,
     /// Entropy threshold for high-entropy detection.
     pub entropy_threshold: f64,
     /// Enable entropy-based detection.
     pub enable_entropy_detection: bool,
+    /// Minimum length of a word considered for entropy detection.
+    pub entropy_min_length: usize,
+    /// Minimum number of character classes (lowercase, uppercase, digits,
+    /// symbols) of a word considered for entropy detection.
+    pub entropy_min_char_classes: usize,
+    /// Only redact high-entropy words preceded by a secret keyword
+    /// (`password`, `token`, a sensitive key...) on the same line.
+    pub entropy_require_keyword: bool,
     /// Additional patterns to match (regex strings).
+    #[serde(rename = "patterns")]
     pub additional_patterns: Vec<String>,
+    /// Key names redacted in addition to the built-in secret key names.
+    pub sensitive_keys: Vec<String>,
+    /// Key names never redacted, even when they look like secret keys.
+    #[serde(rename = "allow_keys")]
+    pub allowed_keys: Vec<String>,
+    /// Keep the original of every redacted value, keyed by its hash
+    /// placeholder (implies hash placeholders). Off by default.
+    #[serde(skip)]
+    pub record_originals: bool,
 }
 
 impl Default for RedactorConfig {
@@ -25,8 +65,57 @@ impl Default for RedactorConfig {
             use_hash_placeholders: false,
             entropy_threshold: DEFAULT_ENTROPY_THRESHOLD,
             enable_entropy_detection: true,
+            entropy_min_length: DEFAULT_MIN_TOKEN_LENGTH,
+            entropy_min_char_classes: DEFAULT_MIN_CHAR_CLASSES,
+            entropy_require_keyword: true,
             additional_patterns: Vec::new(),
+            sensitive_keys: Vec::new(),
+            allowed_keys: Vec::new(),
+            record_originals: false,
+        }
+    }
+}
+
+/// Error loading a redaction rules file.
+#[derive(Debug, thiserror::Error)]
+pub enum RedactionConfigError {
+    #[error("Failed to read redaction config {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Invalid redaction config {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("Invalid redaction pattern '{pattern}': {source}")]
+    Pattern {
+        pattern: String,
+        source: regex::Error,
+    },
+}
+
+impl RedactorConfig {
+    /// Load a YAML rules file. Every pattern must be a valid regex.
+    pub fn load(path: &Path) -> Result<Self, RedactionConfigError> {
+        let display = path.display().to_string();
+        let content = std::fs::read_to_string(path).map_err(|source| RedactionConfigError::Io {
+            path: display.clone(),
+            source,
+        })?;
+        let config: Self =
+            serde_yaml::from_str(&content).map_err(|source| RedactionConfigError::Parse {
+                path: display,
+                source,
+            })?;
+        for pattern in &config.additional_patterns {
+            Regex::new(pattern).map_err(|source| RedactionConfigError::Pattern {
+                pattern: pattern.clone(),
+                source,
+            })?;
         }
+        Ok(config)
     }
 }
 
@@ -74,7 +163,14 @@ impl RedactionStats {
 /// The main redactor.
 pub struct Redactor {
     config: RedactorConfig,
-    additional_patterns: Vec<Regex>,
+    /// Built-in patterns (named) followed by the additional patterns.
+    patterns: Vec<(Option<&'static str>, Regex)>,
+    /// All of `patterns`, to find which ones match in a single scan; `None`
+    /// when the combined set exceeds the regex size limit, in which case
+    /// each pattern is tried in turn.
+    pattern_set: Option<RegexSet>,
+    /// Redacted values by placeholder, when recording originals.
+    originals: Mutex<BTreeMap<String, String>>,
 }
 
 impl Redactor {
@@ -84,37 +180,135 @@ impl Redactor {
     }
 
     /// Create a new redactor with custom configuration.
-    pub fn with_config(config: RedactorConfig) -> Self {
-        let additional_patterns = config
-            .additional_patterns
-            .iter()
-            .filter_map(|p| Regex::new(p).ok())
+    pub fn with_config(mut config: RedactorConfig) -> Self {
+        config.use_hash_placeholders |= config.record_originals;
+        let mut patterns: Vec<(Option<&'static str>, Regex)> = patterns::all_redaction_patterns()
+            .into_iter()
+            .map(|(name, pattern)| (Some(name), pattern.clone()))
             .collect();
+        patterns.extend(
+            config
+                .additional_patterns
+                .iter()
+                .filter_map(|p| Regex::new(p).ok())
+                .map(|pattern| (None, pattern)),
+        );
+
+        // `KEY=value`, `KEY: value` and `"KEY": "value"` for extra key names
+        if !config.sensitive_keys.is_empty() {
+            let keys: Vec<String> = config
+                .sensitive_keys
+                .iter()
+                .map(|k| regex::escape(k))
+                .collect();
+            let pattern = format!(
+                r#"(?i)\b(?:{})\b["']?\s*[=:]\s*["']?[^\s"',;}}]+"#,
+                keys.join("|")
+            );
+            patterns.extend(Regex::new(&pattern).ok().map(|pattern| (None, pattern)));
+        }
 
+        // Patterns that compile one by one may still be too large together
+        let pattern_set = match RegexSet::new(patterns.iter().map(|(_, p)| p.as_str())) {
+            Ok(set) => Some(set),
+            Err(e) => {
+                warn!("Redaction patterns are scanned one by one: {}", e);
+                None
+            }
+        };
         Self {
             config,
-            additional_patterns,
+            patterns,
+            pattern_set,
+            originals: Mutex::new(BTreeMap::new()),
         }
     }
 
-    /// Redact sensitive content from a string.
-    pub fn redact(&self, content: &str) -> RedactionResult {
-        let mut stats = RedactionStats::default();
-        let mut result = content.to_string();
-
-        // Apply pattern-based redaction
-        for (name, pattern) in patterns::all_redaction_patterns() {
-            let before_len = result.len();
-            result = self.apply_pattern_redaction(&result, pattern, &mut stats);
-            if result.len() != before_len {
-                stats.matched_patterns.push(name.to_string());
-            }
+    /// Take the originals of the values redacted so far, by placeholder.
+    /// Empty unless [`RedactorConfig::record_originals`] is set.
+    pub fn take_originals(&self) -> BTreeMap<String, String> {
+        std::mem::take(&mut *self.originals.lock().unwrap())
+    }
+
+    /// Placeholder replacing a redacted value.
+    pub(crate) fn placeholder(&self, value: &str) -> String {
+        if !self.config.use_hash_placeholders {
+            return REDACTED_PLACEHOLDER.to_string();
+        }
+        let placeholder = hash_placeholder(value);
+        if self.config.record_originals {
+            self.originals
+                .lock()
+                .unwrap()
+                .insert(placeholder.clone(), value.to_string());
+        }
+        placeholder
+    }
+
+    /// Whether a key name holds a secret: a built-in or configured secret
+    /// key name that is not allowlisted.
+    pub fn is_sensitive_key(&self, key: &str) -> bool {
+        !self.is_allowed_key(key)
+            && (is_sensitive_key(key)
+                || self
+                    .config
+                    .sensitive_keys
+                    .iter()
+                    .any(|k| k.eq_ignore_ascii_case(key)))
+    }
+
+    /// Whether a key name is allowlisted and never redacted.
+    pub fn is_allowed_key(&self, key: &str) -> bool {
+        self.config
+            .allowed_keys
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(key))
+    }
+
+    fn token_thresholds(&self) -> TokenThresholds {
+        TokenThresholds {
+            min_length: self.config.entropy_min_length,
+            min_char_classes: self.config.entropy_min_char_classes,
+            entropy: self.config.entropy_threshold,
         }
+    }
 
-        // Apply additional patterns
-        for pattern in &self.additional_patterns {
-            result = self.apply_pattern_redaction(&result, pattern, &mut stats);
+    /// Whether the word at `start..end` of `content`, or the text shortly
+    /// before it on the same line, contains a secret keyword.
+    fn has_keyword_context(&self, content: &str, start: usize, end: usize) -> bool {
+        let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+        let mut window_start = start.saturating_sub(KEYWORD_WINDOW).max(line_start);
+        while !content.is_char_boundary(window_start) {
+            window_start += 1;
+        }
+        let context = &content[window_start..end];
+        if patterns::SECRET_KEY_PATTERN.is_match(context) {
+            return true;
         }
+        let context = context.to_lowercase();
+        self.config
+            .sensitive_keys
+            .iter()
+            .any(|k| context.contains(&k.to_lowercase()))
+    }
+
+    /// Whether a pattern match is an assignment to an allowlisted key.
+    fn is_allowed_match(&self, matched: &str) -> bool {
+        if self.config.allowed_keys.is_empty() {
+            return false;
+        }
+        let key: String = matched
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            .collect();
+        self.is_allowed_key(&key)
+    }
+
+    /// Redact sensitive content from a string.
+    pub fn redact(&self, content: &str) -> RedactionResult {
+        let mut stats = RedactionStats::default();
+        let mut result = self.redact_patterns(content, &mut stats);
 
         // Apply entropy-based detection on remaining potential tokens
         if self.config.enable_entropy_detection {
@@ -127,71 +321,98 @@ impl Redactor {
         }
     }
 
+    /// Apply the built-in and additional patterns, without entropy detection.
+    pub(crate) fn redact_patterns(&self, content: &str, stats: &mut RedactionStats) -> String {
+        self.apply_pattern_redaction(content, stats)
+    }
+
     /// Redact a key-value pair (for environment variables, config keys, etc.).
     pub fn redact_key_value(&self, key: &str, value: &str) -> (String, RedactionStats) {
         let mut stats = RedactionStats::default();
 
-        if is_sensitive_key(key) {
+        if self.is_allowed_key(key) {
+            return (value.to_string(), stats);
+        }
+
+        if self.is_sensitive_key(key) {
             stats.key_redactions += 1;
             stats.total_chars_redacted += value.len();
-            let placeholder = if self.config.use_hash_placeholders {
-                hash_placeholder(value)
-            } else {
-                REDACTED_PLACEHOLDER.to_string()
-            };
+            let placeholder = self.placeholder(value);
             return (placeholder, stats);
         }
 
-        // Check if value looks like a token
-        if self.config.enable_entropy_detection && looks_like_token(value) {
+        // Check if value looks like a token; the key is its only context, so
+        // no keyword is required
+        if self.config.enable_entropy_detection
+            && looks_like_token_with(value, &self.token_thresholds())
+        {
             stats.entropy_redactions += 1;
             stats.total_chars_redacted += value.len();
-            let placeholder = if self.config.use_hash_placeholders {
-                hash_placeholder(value)
-            } else {
-                REDACTED_PLACEHOLDER.to_string()
-            };
+            let placeholder = self.placeholder(value);
             return (placeholder, stats);
         }
 
         (value.to_string(), stats)
     }
 
-    /// Apply pattern-based redaction.
-    fn apply_pattern_redaction(
-        &self,
-        content: &str,
-        pattern: &Regex,
-        stats: &mut RedactionStats,
-    ) -> String {
-        let mut result = content.to_string();
-        let mut offset: i64 = 0;
-
-        for mat in pattern.find_iter(content) {
-            let matched = mat.as_str();
-            let start = (mat.start() as i64 + offset) as usize;
-            let end = (mat.end() as i64 + offset) as usize;
-
-            let replacement = if self.config.use_hash_placeholders {
-                hash_placeholder(matched)
-            } else {
-                REDACTED_PLACEHOLDER.to_string()
-            };
-
-            stats.pattern_redactions += 1;
-            stats.total_chars_redacted += matched.len();
+    /// Replace the pattern matches in `content`, merging overlapping
+    /// matches into one redaction.
+    fn apply_pattern_redaction(&self, content: &str, stats: &mut RedactionStats) -> String {
+        let candidates: Vec<usize> = match &self.pattern_set {
+            Some(set) => set.matches(content).into_iter().collect(),
+            None => (0..self.patterns.len()).collect(),
+        };
+        let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+        for index in candidates {
+            let (_, pattern) = &self.patterns[index];
+            spans.extend(
+                pattern
+                    .find_iter(content)
+                    .filter(|m| !self.is_allowed_match(m.as_str()))
+                    .map(|m| (m.start(), m.end(), index)),
+            );
+        }
+        if spans.is_empty() {
+            return content.to_string();
+        }
+        spans.sort_unstable();
 
-            let len_diff = replacement.len() as i64 - matched.len() as i64;
-            result.replace_range(start..end, &replacement);
-            offset += len_diff;
+        let mut result = String::with_capacity(content.len());
+        let mut copied = 0;
+        let mut spans = spans.into_iter().peekable();
+        while let Some((start, mut end, index)) = spans.next() {
+            let mut matched = vec![index];
+            while let Some(&(next_start, next_end, next_index)) = spans.peek() {
+                if next_start >= end {
+                    break;
+                }
+                end = end.max(next_end);
+                matched.push(next_index);
+                spans.next();
+            }
 
+            let original = &content[start..end];
+            let replacement = self.placeholder(original);
+            stats.pattern_redactions += 1;
+            stats.total_chars_redacted += original.len();
+            for index in matched {
+                if let Some(name) = self.patterns[index].0 {
+                    if !stats.matched_patterns.iter().any(|n| n == name) {
+                        stats.matched_patterns.push(name.to_string());
+                    }
+                }
+            }
             trace!(
                 "Redacted pattern match: {} -> {}",
-                matched.len(),
+                original.len(),
                 replacement.len()
             );
-        }
 
+            result.push_str(&content[copied..start]);
+            result.push_str(&replacement);
+            copied = end;
+        }
+        result.push_str(&content[copied..]);
         result
     }
 
@@ -199,48 +420,37 @@ impl Redactor {
     fn apply_entropy_redaction(&self, content: &str, stats: &mut RedactionStats) -> String {
         // Split content into words and check each for high entropy
         let mut result = String::with_capacity(content.len());
-        let mut current_word = String::new();
-
-        for c in content.chars() {
-            if c.is_alphanumeric() || c == '_' || c == '-' || c == '+' || c == '/' || c == '=' {
-                current_word.push(c);
-            } else {
-                if !current_word.is_empty() {
-                    if looks_like_token(&current_word) {
-                        let replacement = if self.config.use_hash_placeholders {
-                            hash_placeholder(&current_word)
-                        } else {
-                            REDACTED_PLACEHOLDER.to_string()
-                        };
-                        stats.entropy_redactions += 1;
-                        stats.total_chars_redacted += current_word.len();
-                        result.push_str(&replacement);
-                        debug!("Entropy redaction: {} chars", current_word.len());
-                    } else {
-                        result.push_str(&current_word);
-                    }
-                    current_word.clear();
-                }
-                result.push(c);
+        let mut copied = 0;
+        let mut word_start = None;
+        let thresholds = self.token_thresholds();
+
+        for (i, c) in content
+            .char_indices()
+            .chain(std::iter::once((content.len(), ' ')))
+        {
+            if c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '/' | '=') {
+                word_start.get_or_insert(i);
+                continue;
             }
-        }
-
-        // Handle last word
-        if !current_word.is_empty() {
-            if looks_like_token(&current_word) {
-                let replacement = if self.config.use_hash_placeholders {
-                    hash_placeholder(&current_word)
-                } else {
-                    REDACTED_PLACEHOLDER.to_string()
-                };
+            let Some(start) = word_start.take() else {
+                continue;
+            };
+            let word = &content[start..i];
+            let redact = looks_like_token_with(word, &thresholds)
+                && !self.is_allowed_match(word)
+                && (!self.config.entropy_require_keyword
+                    || self.has_keyword_context(content, start, i));
+            if redact {
+                result.push_str(&content[copied..start]);
+                result.push_str(&self.placeholder(word));
+                copied = i;
                 stats.entropy_redactions += 1;
-                stats.total_chars_redacted += current_word.len();
-                result.push_str(&replacement);
-            } else {
-                result.push_str(&current_word);
+                stats.total_chars_redacted += word.len();
+                debug!("Entropy redaction: {} chars", word.len());
             }
         }
 
+        result.push_str(&content[copied..]);
         result
     }
 }
@@ -311,6 +521,104 @@ mod tests {
         assert!(value.ends_with("]"));
     }
 
+    #[test]
+    fn test_custom_rules() {
+        let config: RedactorConfig = serde_yaml::from_str(
+            r#"
+patterns: ['INV-\d{8}']
+sensitive_keys: [PIN_CODE]
+allow_keys: [PASSWORD_POLICY]
+"#,
+        )
+        .unwrap();
+        let redactor = Redactor::with_config(config);
+
+        let result = redactor.redact("invoice INV-20240101 for card PIN_CODE=4321");
+        assert!(!result.content.contains("INV-20240101"));
+        assert!(!result.content.contains("4321"));
+        assert_eq!(
+            redactor.redact("PASSWORD_POLICY=strict").content,
+            "PASSWORD_POLICY=strict"
+        );
+
+        assert!(redactor.is_sensitive_key("pin_code"));
+        assert!(!redactor.is_sensitive_key("PASSWORD_POLICY"));
+        let (value, stats) = redactor.redact_key_value("PASSWORD_POLICY", "strict");
+        assert_eq!(value, "strict");
+        assert_eq!(stats.total(), 0);
+
+        assert!(serde_yaml::from_str::<RedactorConfig>("allowed: [X]").is_err());
+    }
+
+    #[test]
+    fn test_patterns_too_large_together() {
+        // Each pattern compiles on its own, but not all of them in one set
+        let mut additional_patterns: Vec<String> =
+            (0..3).map(|i| format!(r"\w{{200}}-{}", i)).collect();
+        assert!(additional_patterns.iter().all(|p| Regex::new(p).is_ok()));
+        assert!(RegexSet::new(&additional_patterns).is_err());
+        additional_patterns.push(r"INV-\d{8}".to_string());
+
+        let redactor = Redactor::with_config(RedactorConfig {
+            additional_patterns,
+            ..Default::default()
+        });
+        assert!(redactor.pattern_set.is_none());
+        let result = redactor.redact("invoice INV-20240101, DATABASE_PASSWORD=mysecret123");
+        assert!(!result.content.contains("INV-20240101"));
+        assert!(!result.content.contains("mysecret123"));
+    }
+
+    #[test]
+    fn test_record_originals() {
+        let redactor = Redactor::with_config(RedactorConfig {
+            record_originals: true,
+            ..Default::default()
+        });
+        let (value, _) = redactor.redact_key_value("DB_PASSWORD", "hunter2");
+        assert!(value.starts_with(crate::HASH_PLACEHOLDER_PREFIX));
+
+        let originals = redactor.take_originals();
+        assert_eq!(originals[&value], "hunter2");
+        assert!(redactor.take_originals().is_empty());
+        assert!(Redactor::new().take_originals().is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_matches_redacted_once() {
+        let redactor = Redactor::new();
+        // connection_string and db_url both match the URL
+        let result = redactor
+            .redact("url=postgres://app:hunter2@db:5432/app\nAuthorization: Bearer abc\nport=5432");
+        assert_eq!(result.content, "url=[REDACTED]\n[REDACTED]\nport=5432");
+        assert_eq!(result.stats.pattern_redactions, 2);
+        assert_eq!(
+            result.stats.matched_patterns,
+            vec!["connection_string", "db_url", "auth_header"]
+        );
+    }
+
+    #[test]
+    fn test_entropy_requires_keyword_context() {
+        let redactor = Redactor::new();
+        let line = "deploy Xk9mQ2vL8nR4tW7yB3cFz6 from 9fceb02d0ae598e95dc970b74767f19372d61af8";
+        assert_eq!(redactor.redact(line).content, line);
+        let result = redactor.redact("login ok, session cookie Xk9mQ2vL8nR4tW7yB3cFz6 issued");
+        assert_eq!(result.stats.entropy_redactions, 1);
+        assert!(!result.content.contains("Xk9mQ2vL8nR4tW7yB3cFz6"));
+
+        let redactor = Redactor::with_config(RedactorConfig {
+            entropy_require_keyword: false,
+            ..Default::default()
+        });
+        assert!(!redactor
+            .redact(line)
+            .content
+            .contains("Xk9mQ2vL8nR4tW7yB3cFz6"));
+        // Git SHAs are never tokens
+        assert!(redactor.redact(line).content.contains("9fceb02d0ae5"));
+    }
+
     #[test]
     fn test_no_secret_passes_through() {
         let redactor = Redactor::new();
@@ -0,0 +1,283 @@
+//! Format-aware redaction of configuration files.
+//!
+//! Entropy detection works on arbitrary text but mangles structured files:
+//! certificates and base64 payloads look like tokens, and a bare
+//! `[REDACTED]` is a list in YAML. For JSON, YAML and INI-style files the
+//! values of sensitive keys are replaced instead, keeping keys, quoting,
+//! indentation and comments. Other values only go through the regex
+//! patterns (connection strings, auth headers, ...). Files in other formats
+//! fall back to [`Redactor::redact`].
+
+use crate::redactor::{RedactionResult, RedactionStats, Redactor};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// `key: value` in YAML, optionally a list item or quoted key.
+static YAML_ENTRY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(\s*(?:-\s+)?["']?)([A-Za-z0-9_.\-]+)(["']?\s*:)(\s.*|)$"#).unwrap()
+});
+
+/// `key = value` or `key: value` in INI, properties and env files.
+static INI_ENTRY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\s*(?:export\s+)?)([A-Za-z0-9_.\-]+)(\s*[=:]\s*)(.*)$").unwrap()
+});
+
+/// A JSON member whose value is a string, number or boolean.
+static JSON_MEMBER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#""((?:[^"\\]|\\.)*)"(\s*:\s*)("(?:[^"\\]|\\.)*"|-?\d[\d.eE+\-]*|true|false)"#)
+        .unwrap()
+});
+
+/// Structured formats redacted key by key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Json,
+    Yaml,
+    /// INI, Java properties and `KEY=value` environment files.
+    Ini,
+}
+
+impl FileFormat {
+    /// Detect the format of a file from its extension, or from its content
+    /// for other extensions. JSON and YAML files must parse.
+    pub fn detect(path: &str, content: &str) -> Option<Self> {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("json") => Self::parses_as_json(content).then_some(Self::Json),
+            Some("yaml" | "yml") => serde_yaml::from_str::<serde_yaml::Value>(content)
+                .is_ok()
+                .then_some(Self::Yaml),
+            Some("ini" | "cfg" | "cnf" | "properties" | "env") => Some(Self::Ini),
+            _ if Self::parses_as_json(content) => Some(Self::Json),
+            _ if Self::looks_like_ini(content) => Some(Self::Ini),
+            _ => None,
+        }
+    }
+
+    fn parses_as_json(content: &str) -> bool {
+        matches!(
+            serde_json::from_str::<serde_json::Value>(content),
+            Ok(serde_json::Value::Object(_) | serde_json::Value::Array(_))
+        )
+    }
+
+    /// Every significant line is a comment, a section or an assignment.
+    fn looks_like_ini(content: &str) -> bool {
+        let mut assignments = 0;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                continue;
+            }
+            if !INI_ENTRY.is_match(line) {
+                return false;
+            }
+            assignments += 1;
+        }
+        assignments > 0
+    }
+}
+
+impl Redactor {
+    /// Redact a collected file, key by key when its format is recognized.
+    pub fn redact_file(&self, content: &str, path: &str) -> RedactionResult {
+        let Some(format) = FileFormat::detect(path, content) else {
+            return self.redact(content);
+        };
+        let mut stats = RedactionStats::default();
+        let content = match format {
+            FileFormat::Json => self.redact_json(content, &mut stats),
+            FileFormat::Yaml => self.redact_yaml(content, &mut stats),
+            FileFormat::Ini => self.redact_ini(content, &mut stats),
+        };
+        RedactionResult { content, stats }
+    }
+
+    fn redact_json(&self, content: &str, stats: &mut RedactionStats) -> String {
+        let mut result = String::with_capacity(content.len());
+        let mut last = 0;
+        for caps in JSON_MEMBER.captures_iter(content) {
+            let whole = caps.get(0).unwrap();
+            let value = caps.get(3).unwrap();
+            result.push_str(&content[last..value.start()]);
+            if self.is_sensitive_key(&caps[1]) {
+                let inner = value.as_str().trim_matches('"');
+                result.push('"');
+                result.push_str(&self.redact_value(inner, stats));
+                result.push('"');
+            } else {
+                result.push_str(&self.redact_patterns(value.as_str(), stats));
+            }
+            last = whole.end();
+        }
+        result.push_str(&content[last..]);
+        result
+    }
+
+    fn redact_yaml(&self, content: &str, stats: &mut RedactionStats) -> String {
+        let mut lines = Vec::new();
+        // Indentation of a sensitive key whose value is a block scalar
+        let mut block: Option<usize> = None;
+        for line in content.lines() {
+            let indent = line.len() - line.trim_start().len();
+            if let Some(key_indent) = block {
+                if line.trim().is_empty() || indent > key_indent {
+                    if line.trim().is_empty() {
+                        lines.push(line.to_string());
+                    } else {
+                        let value = self.redact_value(line.trim(), stats);
+                        lines.push(format!("{}{}", &line[..indent], value));
+                    }
+                    continue;
+                }
+                block = None;
+            }
+
+            let Some(caps) = YAML_ENTRY.captures(line) else {
+                lines.push(self.redact_patterns(line, stats));
+                continue;
+            };
+            let rest = &caps[4];
+            let (value, comment) = split_yaml_comment(rest.trim_start());
+            let spacing = &rest[..rest.len() - rest.trim_start().len()];
+            if !self.is_sensitive_key(&caps[2]) || value.is_empty() {
+                lines.push(self.redact_patterns(line, stats));
+                continue;
+            }
+            let redacted = if value.starts_with(['|', '>']) {
+                block = Some(indent);
+                value.to_string()
+            } else {
+                let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'');
+                let inner = value.trim_matches(|c| Some(c) == quote);
+                // Quoted so that placeholders are not read as flow sequences
+                let q = quote.unwrap_or('"');
+                format!("{}{}{}", q, self.redact_value(inner, stats), q)
+            };
+            lines.push(format!(
+                "{}{}{}{}{}{}",
+                &caps[1], &caps[2], &caps[3], spacing, redacted, comment
+            ));
+        }
+        rejoin(content, lines)
+    }
+
+    fn redact_ini(&self, content: &str, stats: &mut RedactionStats) -> String {
+        let lines = content
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if trimmed.starts_with(['#', ';', '[']) {
+                    return line.to_string();
+                }
+                let Some(caps) = INI_ENTRY.captures(line) else {
+                    return self.redact_patterns(line, stats);
+                };
+                let value = &caps[4];
+                if !self.is_sensitive_key(&caps[2]) || value.trim().is_empty() {
+                    return self.redact_patterns(line, stats);
+                }
+                let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'');
+                let inner = value.trim_matches(|c| Some(c) == quote);
+                let quote = quote.map(String::from).unwrap_or_default();
+                format!(
+                    "{}{}{}{}{}{}",
+                    &caps[1],
+                    &caps[2],
+                    &caps[3],
+                    quote,
+                    self.redact_value(inner, stats),
+                    quote
+                )
+            })
+            .collect();
+        rejoin(content, lines)
+    }
+
+    /// Placeholder for the value of a sensitive key.
+    fn redact_value(&self, value: &str, stats: &mut RedactionStats) -> String {
+        stats.key_redactions += 1;
+        stats.total_chars_redacted += value.len();
+        self.placeholder(value)
+    }
+}
+
+/// Split a YAML value from a trailing ` # comment`, outside quotes.
+fn split_yaml_comment(value: &str) -> (&str, &str) {
+    let start = match value.chars().next() {
+        Some(q @ ('"' | '\'')) => value[1..].find(q).map(|i| i + 2).unwrap_or(value.len()),
+        _ => 0,
+    };
+    match value[start..].find(" #") {
+        Some(i) => {
+            let (value, comment) = value.split_at(start + i);
+            (value.trim_end(), comment)
+        }
+        None => (value.trim_end(), ""),
+    }
+}
+
+/// Join lines, keeping a trailing newline.
+fn rejoin(original: &str, lines: Vec<String>) -> String {
+    let mut result = lines.join("\n");
+    if original.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::REDACTED_PLACEHOLDER;
+
+    #[test]
+    fn test_redact_yaml_keeps_structure() {
+        let content = "# database\ndb:\n  host: db.internal\n  password: s3cr3t # rotated\n  url: postgres://app:hunter2@db:5432/app\ntls:\n  cert: |\n    MIIDdzCCAl+gAwIBAgIEAgAAuTANBgkqhkiG9w0BAQUFADBaMQswCQYDVQQGEwJJ\nprivate_key: |\n  c2VjcmV0a2V5bWF0ZXJpYWw=\nport: 8080\n";
+        let redacted = Redactor::new()
+            .redact_file(content, "/etc/app/config.yml")
+            .content;
+
+        assert!(redacted.contains("# database\n"));
+        assert!(redacted.contains("  password: \"[REDACTED]\" # rotated\n"));
+        assert!(!redacted.contains("hunter2"));
+        // Certificates of non-sensitive keys are left alone
+        assert!(
+            redacted.contains("MIIDdzCCAl+gAwIBAgIEAgAAuTANBgkqhkiG9w0BAQUFADBaMQswCQYDVQQGEwJJ")
+        );
+        assert!(!redacted.contains("c2VjcmV0a2V5bWF0ZXJpYWw="));
+        assert!(redacted.ends_with("port: 8080\n"));
+        assert!(serde_yaml::from_str::<serde_yaml::Value>(&redacted).is_ok());
+    }
+
+    #[test]
+    fn test_redact_json_and_ini() {
+        let redactor = Redactor::new();
+        let json = "{\n  \"user\": \"app\",\n  \"apiKey\": \"abc123\",\n  \"pin\": 1234,\n  \"retries\": 3\n}";
+        let redacted = redactor.redact_file(json, "settings.json").content;
+        assert!(redacted.contains("\"apiKey\": \"[REDACTED]\""));
+        assert!(redacted.contains("\"retries\": 3"));
+        assert!(serde_json::from_str::<serde_json::Value>(&redacted).is_ok());
+
+        let env = "# app settings\nexport DB_PASSWORD=\"hunter2\"\nLOG_LEVEL=debug\n";
+        let redacted = redactor.redact_file(env, "/etc/default/app").content;
+        assert_eq!(
+            redacted,
+            format!(
+                "# app settings\nexport DB_PASSWORD=\"{}\"\nLOG_LEVEL=debug\n",
+                REDACTED_PLACEHOLDER
+            )
+        );
+
+        // Unknown formats go through the regex and entropy pipeline
+        assert_eq!(
+            FileFormat::detect("/etc/nginx/nginx.conf", "server {\n}\n"),
+            None
+        );
+    }
+}
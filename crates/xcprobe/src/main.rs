@@ -1,10 +1,29 @@
 //! XCProbe - System discovery, collection and containerization tool.
 
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-use tracing::info;
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{error, info, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-use xcprobe_common::OsType;
+use xcprobe_bundle_schema::schema::SchemaKind;
+use xcprobe_bundle_schema::{CommandStatus, PackPlan};
+use xcprobe_collector::bundle::BundleFormat;
+use xcprobe_collector::collector::{is_local_target, CollectionMode, RetryPolicy, SamplingConfig};
+use xcprobe_collector::credentials::{CredentialSources, SSH_PASSWORD_ENV};
+use xcprobe_collector::executor::{HostKeyCheck, SshTransport, WinRmTls};
+use xcprobe_collector::inventory::Inventory;
+use xcprobe_collector::phases::PhaseSelection;
+use xcprobe_collector::progress::ProgressFormat;
+use xcprobe_collector::storage;
+use xcprobe_collector::upload;
+use xcprobe_collector::vault;
+use xcprobe_collector::watch::{Retention, WatchConfig, Watcher};
+use xcprobe_common::{Error as XcError, ErrorSummary, OsType};
+use xcprobe_redaction::RedactorConfig;
 
 #[derive(Parser)]
 #[command(name = "xcprobe")]
@@ -23,65 +42,102 @@ struct Cli {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Collect system information from a target host
     Collect {
-        /// Target host (hostname or IP). Required for remote mode, defaults to localhost otherwise.
+        #[command(flatten)]
+        collection: CollectionArgs,
+
+        /// Output bundle file path, or s3://<bucket>/<key>
+        #[arg(long, short, required_unless_present_any = ["dry_run", "emit_stdout"])]
+        out: Option<PathBuf>,
+
+        /// Write the bundle to stdout instead of a file, logs going to
+        /// stderr (`ssh host ./xcprobe collect --emit-stdout > bundle.tgz`)
+        #[arg(long, conflicts_with_all = ["out", "dry_run", "inventory", "vault_key_file", "format", "upload_url"])]
+        emit_stdout: bool,
+
+        /// Bundle archive layout: v1 (gzip tarball) or v2 (zstd tarball,
+        /// manifest split by category, evidence index)
+        #[arg(long, default_value = "v1")]
+        bundle_format: String,
+
+        #[command(flatten)]
+        upload: UploadArgs,
+
+        /// Print the commands that would be executed and exit
         #[arg(long)]
-        target: Option<String>,
+        dry_run: bool,
+
+        /// With --dry-run, do not connect to the target (per-service commands stay templated)
+        #[arg(long, requires = "dry_run")]
+        offline: bool,
 
-        /// Target operating system (linux, windows). Auto-detected in local mode.
+        /// Ansible inventory (INI or YAML); --target then names a host or
+        /// group, and bundles of several hosts go to <out>/<host>/bundle.tgz
+        /// (bundle.tar.zst with --bundle-format v2)
         #[arg(long)]
-        os: Option<String>,
+        inventory: Option<PathBuf>,
 
-        /// Collection mode (remote, local-ephemeral)
-        #[arg(long, default_value = "local-ephemeral")]
-        mode: String,
+        /// Output: text (progress bar) or json (JSON lines on stdout, ending
+        /// with a summary of each bundle)
+        #[arg(long, default_value = "text")]
+        format: String,
 
-        /// Output bundle file path
+        /// Keep the original of every redacted value in an encrypted
+        /// secrets.vault next to the bundle, using the passphrase in this file
+        #[arg(long)]
+        vault_key_file: Option<PathBuf>,
+    },
+
+    /// Collect a target repeatedly, keeping timestamped bundles
+    Watch {
+        #[command(flatten)]
+        collection: CollectionArgs,
+
+        /// Directory of the bundles, named bundle-<time>.tgz (.tar.zst with
+        /// --bundle-format v2)
         #[arg(long, short)]
         out: PathBuf,
 
-        /// SSH port (for remote Linux)
-        #[arg(long, default_value = "22")]
-        ssh_port: u16,
+        /// Bundle archive layout: v1 (gzip tarball) or v2 (zstd tarball,
+        /// manifest split by category, evidence index)
+        #[arg(long, default_value = "v1")]
+        bundle_format: String,
 
-        /// SSH user
-        #[arg(long)]
-        ssh_user: Option<String>,
+        /// Seconds between two collections
+        #[arg(long, default_value = "3600")]
+        interval: u64,
 
-        /// SSH private key path
+        /// Also collect as soon as the target's services start, stop,
+        /// appear or disappear
         #[arg(long)]
-        ssh_key: Option<PathBuf>,
+        on_service_change: bool,
 
-        /// SSH password
-        #[arg(long)]
-        ssh_password: Option<String>,
+        /// Seconds between two checks of the service list
+        #[arg(long, default_value = "60", requires = "on_service_change")]
+        poll_interval: u64,
 
-        /// WinRM port (for remote Windows)
-        #[arg(long, default_value = "5985")]
-        winrm_port: u16,
+        #[command(flatten)]
+        upload: UploadArgs,
 
-        /// WinRM user
+        /// Keep at most this many bundles in the directory
         #[arg(long)]
-        winrm_user: Option<String>,
+        keep: Option<usize>,
 
-        /// WinRM password
+        /// Remove bundles collected more than this many hours ago
         #[arg(long)]
-        winrm_password: Option<String>,
+        max_age_hours: Option<u64>,
 
-        /// Use HTTPS for WinRM
+        /// Stop after this many collections [default: run until interrupted]
         #[arg(long)]
-        winrm_https: bool,
-
-        /// Collection timeout in seconds
-        #[arg(long, default_value = "300")]
-        timeout: u64,
+        count: Option<usize>,
     },
 
     /// Analyze a bundle and generate Docker artifacts
     Analyze {
-        /// Input bundle file path
+        /// Input bundle file path, or s3://<bucket>/<key>
         #[arg(long)]
         bundle: PathBuf,
 
@@ -93,14 +149,622 @@ enum Commands {
         #[arg(long, default_value = "app")]
         cluster_prefix: String,
 
+        /// Clustering strategy: per-service, merged (shared working directory)
+        /// or aggressive (also shared service user)
+        #[arg(long, default_value = "merged")]
+        clustering_strategy: String,
+
+        /// YAML file assigning services to clusters, forcing app types,
+        /// declaring dependencies or excluding services
+        #[arg(long)]
+        hints: Option<PathBuf>,
+
         /// Minimum confidence threshold (0.0-1.0)
         #[arg(long, default_value = "0.7")]
         min_confidence: f64,
+
+        /// Record clusters below the threshold in packplan.json for review
+        #[arg(long)]
+        include_rejected: bool,
+
+        /// TOML calibration of process scoring and cluster confidence:
+        /// keyword lists, scores, business-process threshold, evidence weights
+        #[arg(long)]
+        scoring_config: Option<PathBuf>,
+
+        /// YAML redaction rules: extra patterns, sensitive and allowlisted
+        /// key names, hash placeholders
+        #[arg(long)]
+        redaction_config: Option<PathBuf>,
+
+        /// Run `docker build` for each generated cluster and record the results
+        #[arg(long)]
+        build_check: bool,
+
+        /// Directory of .hbs templates overriding the built-in ones
+        #[arg(long)]
+        templates_dir: Option<PathBuf>,
+
+        /// Also generate docker-compose.dev.yaml with local stand-ins for
+        /// external dependencies (postgres, redis, mailhog, localstack...)
+        #[arg(long)]
+        dev_stubs: bool,
+
+        /// For a Windows host, generate Linux artifacts and a
+        /// COMPATIBILITY.md report on moving each application to Linux,
+        /// instead of Windows containers
+        #[arg(long)]
+        advise_rewrite: bool,
+
+        /// Replace the artifacts of a previous run in the output directory
+        #[arg(long, conflicts_with = "merge")]
+        force: bool,
+
+        /// Regenerate the artifacts of a previous run in the output
+        /// directory, keeping the files edited since
+        #[arg(long)]
+        merge: bool,
+
+        /// Date the plan with the bundle's collection time, so that the same
+        /// bundle always gives byte-identical output
+        #[arg(long)]
+        reproducible: bool,
+
+        /// Worker threads for per-cluster analysis and artifact generation
+        /// (default: one per CPU)
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: Option<u16>,
+
+        /// Output: text (logs) or json (plan summary on stdout)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Regenerate Docker artifacts from an existing or edited packplan.json
+    Generate {
+        /// Pack plan to generate artifacts from
+        #[arg(long)]
+        plan: PathBuf,
+
+        /// Output directory for artifacts
+        #[arg(long, short)]
+        out: PathBuf,
+
+        /// Directory of .hbs templates overriding the built-in ones
+        #[arg(long)]
+        templates_dir: Option<PathBuf>,
+
+        /// Also generate docker-compose.dev.yaml with local stand-ins for
+        /// external dependencies (postgres, redis, mailhog, localstack...)
+        #[arg(long)]
+        dev_stubs: bool,
+
+        /// For a Windows host, generate Linux artifacts and a
+        /// COMPATIBILITY.md report on moving each application to Linux,
+        /// instead of Windows containers
+        #[arg(long)]
+        advise_rewrite: bool,
+
+        /// Replace the artifacts of a previous run in the output directory
+        #[arg(long, conflicts_with = "merge")]
+        force: bool,
+
+        /// Regenerate the artifacts of a previous run in the output
+        /// directory, keeping the files edited since
+        #[arg(long)]
+        merge: bool,
+
+        /// Worker threads for rendering and writing the artifacts
+        /// (default: one per CPU)
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: Option<u16>,
+    },
+
+    /// Render a migration assessment (clusters, dependency diagram, open
+    /// questions, warnings, checklists) from a packplan.json
+    Report {
+        /// Pack plan to report on
+        #[arg(long)]
+        plan: PathBuf,
+
+        /// Report file (default: stdout)
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+
+        /// Report format (markdown, html)
+        #[arg(long, default_value = "markdown")]
+        format: String,
+
+        /// Directory of .hbs templates overriding the built-in ones
+        #[arg(long)]
+        templates_dir: Option<PathBuf>,
+    },
+
+    /// Trace a decision of a packplan.json back to the commands and output
+    /// behind it
+    TraceDecision {
+        /// Pack plan holding the decision
+        #[arg(long)]
+        plan: PathBuf,
+
+        /// Bundle the plan was analyzed from (a file or s3://<bucket>/<key>)
+        #[arg(long)]
+        bundle: PathBuf,
+
+        /// Decision index in the cluster's decisions, or text found in the
+        /// decision (case insensitive; every match is traced)
+        decision: String,
+
+        /// Cluster holding the decision (required for an index when the
+        /// plan has several clusters)
+        #[arg(long)]
+        cluster: Option<String>,
+
+        /// Output: text or json
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Fetch application files from the target and add them to the generated images
+    Pack {
+        /// Artifacts directory produced by `analyze` (contains packplan.json)
+        #[arg(long, short)]
+        out: PathBuf,
+
+        /// Target host (hostname or IP)
+        #[arg(long, default_value = "localhost")]
+        target: String,
+
+        /// Target operating system (linux, windows, macos, unix)
+        #[arg(long, default_value = "linux")]
+        os: String,
+
+        /// SSH port
+        #[arg(long, default_value = "22")]
+        ssh_port: u16,
+
+        /// SSH user
+        #[arg(long)]
+        ssh_user: Option<String>,
+
+        /// SSH private key path
+        #[arg(long)]
+        ssh_key: Option<PathBuf>,
+
+        /// SSH host key checking (yes, accept-new, no)
+        #[arg(long, default_value = "accept-new")]
+        ssh_strict_host_key_checking: String,
+
+        /// known_hosts file (default: ~/.ssh/known_hosts)
+        #[arg(long)]
+        ssh_known_hosts: Option<PathBuf>,
+
+        /// SSH client: libssh2 (built-in) or openssh (system ssh binary,
+        /// honoring ~/.ssh/config)
+        #[arg(long, default_value = "libssh2")]
+        transport: String,
+
+        /// YAML redaction rules: extra patterns, sensitive and allowlisted
+        /// key names, hash placeholders
+        #[arg(long)]
+        redaction_config: Option<PathBuf>,
+
+        /// Directory of .hbs templates overriding the built-in ones
+        #[arg(long)]
+        templates_dir: Option<PathBuf>,
+
+        /// For a Windows host, generate Linux artifacts and a
+        /// COMPATIBILITY.md report instead of Windows containers
+        #[arg(long)]
+        advise_rewrite: bool,
+
+        /// Keep the generated files edited since the last run instead of
+        /// regenerating them
+        #[arg(long)]
+        merge: bool,
+
+        /// Fetch only the plan entries matching a glob, e.g. '**/*.conf'
+        /// (repeatable)
+        #[arg(long = "include", value_name = "GLOB")]
+        includes: Vec<String>,
+
+        /// Leave out the plan entries and the files in directory archives
+        /// matching a glob, e.g. '**/logs/**' (repeatable; .env, *.pem and
+        /// *.key files always are)
+        #[arg(long = "exclude", value_name = "GLOB")]
+        excludes: Vec<String>,
+
+        /// Skip files larger than this size when it was collected, e.g. 10M
+        #[arg(long, value_parser = xcprobe_collector::pack::parse_size)]
+        max_file_size: Option<u64>,
+
+        /// Largest compressed archive fetched for one directory, in MiB
+        #[arg(long, default_value_t = xcprobe_collector::pack::DEFAULT_MAX_ARCHIVE_MB)]
+        max_archive_size: u64,
+
+        /// List the entries that would be fetched and their known size,
+        /// without connecting to the target
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Worker threads for rendering and writing the artifacts
+        /// (default: one per CPU)
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: Option<u16>,
+
+        #[command(flatten)]
+        credentials: CredentialArgs,
+    },
+    /// Export the package inventory of a bundle as CycloneDX or SPDX SBOMs
+    ExportSbom {
+        /// Bundle file to read packages from, or s3://<bucket>/<key>
+        #[arg(long)]
+        bundle: PathBuf,
+
+        /// Output directory for the SBOM files
+        #[arg(long, short)]
+        out: PathBuf,
+
+        /// SBOM format (cyclonedx, spdx)
+        #[arg(long, default_value = "cyclonedx")]
+        format: String,
+
+        /// Pack plan from `analyze`: also write one SBOM per cluster, scoped
+        /// to the packages of its runtime
+        #[arg(long)]
+        plan: Option<PathBuf>,
+    },
+
+    /// Check a bundle against the schema, its evidence and its checksums,
+    /// or any JSON document against a published schema
+    Validate {
+        /// Bundle file to validate
+        #[arg(long, required_unless_present = "schema")]
+        bundle: Option<PathBuf>,
+
+        /// Validate DOCUMENT against this schema instead: manifest,
+        /// packplan or truth (`validate --schema manifest manifest.json`)
+        #[arg(long, requires = "document", conflicts_with_all = ["bundle", "check_evidence", "verify_checksums"])]
+        schema: Option<String>,
+
+        /// JSON document to validate with --schema
+        #[arg(requires = "schema")]
+        document: Option<PathBuf>,
+
+        /// Check that every evidence reference of the manifest exists
+        #[arg(long)]
+        check_evidence: bool,
+
+        /// Hash the evidence stored in the archive against checksums.json and
+        /// check the audit log references
+        #[arg(long)]
+        verify_checksums: bool,
+
+        /// Output: text or json (errors and warnings on stdout)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Check a pack plan against the schema
+    ValidatePlan {
+        /// Pack plan to validate
+        #[arg(long)]
+        plan: PathBuf,
+
+        /// Output: text or json (errors and warnings on stdout)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Print the JSON schema of manifests, pack plans or E2E truth files
+    Schema {
+        /// manifest, packplan or truth
+        name: String,
+    },
+
+    /// Recover redacted values from a secrets.vault
+    Vault {
+        /// Vault file written by `collect --vault-key-file`
+        #[arg(long)]
+        vault: PathBuf,
+
+        /// File containing the vault passphrase
+        #[arg(long)]
+        key_file: PathBuf,
+
+        /// Print only the original of this placeholder (e.g. [HASH:0123456789ab])
+        #[arg(long)]
+        placeholder: Option<String>,
     },
 }
 
+/// Target, connection and collection options shared by `collect` and
+/// `watch`.
+#[derive(Args)]
+struct CollectionArgs {
+    /// Target host (hostname or IP). Required for remote mode, defaults to localhost otherwise.
+    #[arg(long)]
+    target: Option<String>,
+
+    /// Target operating system (linux, windows, macos, unix). Auto-detected in local mode.
+    #[arg(long)]
+    os: Option<String>,
+
+    /// Collection mode: remote (SSH or WinRM), or local on this machine
+    /// (local, local-ephemeral). Remote collection of localhost runs locally.
+    #[arg(long, default_value = "local-ephemeral")]
+    mode: String,
+
+    /// SSH port (for remote Linux) [default: 22]
+    #[arg(long)]
+    ssh_port: Option<u16>,
+
+    /// SSH user
+    #[arg(long)]
+    ssh_user: Option<String>,
+
+    /// SSH private key path
+    #[arg(long)]
+    ssh_key: Option<PathBuf>,
+
+    /// SSH password; visible to other local users, prefer $XCPROBE_SSH_PASSWORD,
+    /// --password-file or --credential-command
+    #[arg(long)]
+    ssh_password: Option<String>,
+
+    /// SSH host key checking (yes, accept-new, no)
+    #[arg(long, default_value = "accept-new")]
+    ssh_strict_host_key_checking: String,
+
+    /// known_hosts file (default: ~/.ssh/known_hosts)
+    #[arg(long)]
+    ssh_known_hosts: Option<PathBuf>,
+
+    /// SSH client: libssh2 (built-in) or openssh (system ssh binary,
+    /// honoring ~/.ssh/config)
+    #[arg(long, default_value = "libssh2")]
+    transport: String,
+
+    /// WinRM port (for remote Windows) [default: 5986 with --winrm-https,
+    /// 5985 otherwise]
+    #[arg(long)]
+    winrm_port: Option<u16>,
+
+    /// WinRM user
+    #[arg(long)]
+    winrm_user: Option<String>,
+
+    /// WinRM password; visible to other local users, prefer
+    /// $XCPROBE_WINRM_PASSWORD, --password-file or --credential-command
+    #[arg(long)]
+    winrm_password: Option<String>,
+
+    /// Use HTTPS for WinRM
+    #[arg(long)]
+    winrm_https: bool,
+
+    /// Allow WinRM over plain HTTP, whose messages are not encrypted; only
+    /// hosts with AllowUnencrypted set accept them
+    #[arg(long, conflicts_with = "winrm_https")]
+    winrm_allow_unencrypted: bool,
+
+    /// PEM file of CA certificates trusted for the WinRM HTTPS listener,
+    /// in addition to the built-in roots
+    #[arg(long, requires = "winrm_https")]
+    winrm_ca_cert: Option<PathBuf>,
+
+    /// SHA-256 fingerprint of the WinRM HTTPS certificate to accept, in hex
+    /// (for self-signed listener certificates)
+    #[arg(long, requires = "winrm_https", conflicts_with = "winrm_ca_cert")]
+    winrm_cert_fingerprint: Option<String>,
+
+    /// Accept any WinRM HTTPS certificate (test environments only)
+    #[arg(
+        long,
+        requires = "winrm_https",
+        conflicts_with_all = ["winrm_ca_cert", "winrm_cert_fingerprint"]
+    )]
+    winrm_insecure: bool,
+
+    /// WinRM authentication scheme (negotiate: Kerberos, falling back to
+    /// NTLM; ntlm; basic)
+    #[arg(long, default_value = "ntlm")]
+    winrm_auth: String,
+
+    /// Collection timeout in seconds
+    #[arg(long, default_value = "300")]
+    timeout: u64,
+
+    /// Per-command timeout in seconds
+    #[arg(long, default_value = "30")]
+    command_timeout: u64,
+
+    /// Attempts per command on connection/transport errors (1 disables retries)
+    #[arg(long, default_value = "3")]
+    max_attempts: u32,
+
+    /// Initial retry backoff in milliseconds (doubled after each attempt)
+    #[arg(long, default_value = "500")]
+    retry_backoff_ms: u64,
+
+    /// Collection phases to skip (comma-separated, e.g. logs,packages)
+    #[arg(long, value_delimiter = ',')]
+    skip_phase: Vec<String>,
+
+    /// Run only these collection phases (comma-separated, e.g. processes,ports)
+    #[arg(long, value_delimiter = ',')]
+    only_phase: Vec<String>,
+
+    /// YAML redaction rules: extra patterns, sensitive and allowlisted
+    /// key names, hash placeholders
+    #[arg(long)]
+    redaction_config: Option<PathBuf>,
+
+    /// Sample CPU and memory of processes for this many seconds, for
+    /// right-sizing resource requests
+    #[arg(long)]
+    sample_duration: Option<u64>,
+
+    /// Seconds between two resource samples
+    #[arg(long, default_value = "5", requires = "sample_duration")]
+    sample_interval: u64,
+
+    /// Look up on the target the host names configuration files name, to
+    /// tell internal dependencies from Internet services
+    #[arg(long)]
+    resolve_hosts: bool,
+
+    /// Abort on the first failed phase instead of recording it in the
+    /// manifest errors and collecting the others
+    #[arg(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    credentials: CredentialArgs,
+}
+
+/// Sources of the SSH or WinRM password that keep it out of the command
+/// line.
+#[derive(Args)]
+struct CredentialArgs {
+    /// Read the SSH or WinRM password from the first line of this file
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+
+    /// Run this shell command and use its output as the SSH or WinRM
+    /// password; {host} is replaced by each target host
+    #[arg(long, conflicts_with = "password_file")]
+    credential_command: Option<String>,
+}
+
+impl CredentialArgs {
+    fn sources(&self) -> CredentialSources {
+        CredentialSources {
+            password_file: self.password_file.clone(),
+            credential_command: self.credential_command.clone(),
+        }
+    }
+}
+
+/// Upload of bundles to a collection server.
+#[derive(Args)]
+struct UploadArgs {
+    /// Upload each bundle to this collection server URL, in resumable
+    /// chunks (PUT <url>/<collection id>.tgz, or .tar.zst for v2 bundles)
+    #[arg(long)]
+    upload_url: Option<String>,
+
+    /// Bearer token for the collection server
+    #[arg(long, requires = "upload_url")]
+    upload_token: Option<String>,
+}
+
+impl UploadArgs {
+    /// The uploader, retrying chunks like collection commands.
+    fn uploader(&self, retry: &RetryPolicy) -> anyhow::Result<Option<upload::Uploader>> {
+        self.upload_url
+            .as_deref()
+            .map(|url| {
+                Ok(
+                    upload::Uploader::new(url, self.upload_token.clone())?
+                        .with_retry(retry.clone()),
+                )
+            })
+            .transpose()
+    }
+}
+
+impl CollectionArgs {
+    /// Whether the target is this machine: local mode, or a remote
+    /// collection of localhost.
+    fn is_local(&self) -> bool {
+        self.mode == "local-ephemeral"
+            || self.mode == "local"
+            || self.target.as_deref().is_some_and(is_local_target)
+    }
+
+    /// The target host, `localhost` in local mode.
+    fn target(&self) -> anyhow::Result<String> {
+        match &self.target {
+            Some(target) => Ok(target.clone()),
+            None if self.is_local() => Ok("localhost".to_string()),
+            None => anyhow::bail!("--target is required for remote collection"),
+        }
+    }
+
+    /// The target OS: given, that of this machine in local mode, or left
+    /// to the inventory.
+    fn os_type(&self, inventory: bool) -> anyhow::Result<Option<OsType>> {
+        Ok(match &self.os {
+            Some(s) => Some(s.parse()?),
+            None if inventory => None,
+            None if self.is_local() => Some(OsType::host()),
+            None => anyhow::bail!("--os is required for remote collection"),
+        })
+    }
+
+    /// WinRM port of the listener for the chosen transport.
+    fn default_winrm_port(&self) -> u16 {
+        if self.winrm_https {
+            5986
+        } else {
+            5985
+        }
+    }
+
+    /// Collector configuration, with the target left to the caller.
+    fn config(
+        &self,
+        os_type: Option<OsType>,
+    ) -> anyhow::Result<xcprobe_collector::collector::CollectorConfig> {
+        Ok(xcprobe_collector::collector::CollectorConfig {
+            target: String::new(),
+            os_type: os_type.unwrap_or(OsType::Linux),
+            mode: self.mode.parse()?,
+            ssh_port: self.ssh_port.unwrap_or(22),
+            ssh_user: self.ssh_user.clone(),
+            ssh_key: self.ssh_key.clone(),
+            ssh_password: self.ssh_password.clone(),
+            ssh_host_key_check: HostKeyCheck {
+                policy: self.ssh_strict_host_key_checking.parse()?,
+                known_hosts: self.ssh_known_hosts.clone(),
+            },
+            ssh_transport: self.transport.parse()?,
+            winrm_port: self.winrm_port.unwrap_or(self.default_winrm_port()),
+            winrm_user: self.winrm_user.clone(),
+            winrm_password: self.winrm_password.clone(),
+            winrm_https: self.winrm_https,
+            winrm_allow_unencrypted: self.winrm_allow_unencrypted,
+            winrm_auth: self.winrm_auth.parse()?,
+            winrm_tls: WinRmTls {
+                ca_cert: self.winrm_ca_cert.clone(),
+                cert_fingerprint: self.winrm_cert_fingerprint.clone(),
+                insecure: self.winrm_insecure,
+            },
+            timeout_seconds: self.timeout,
+            command_timeout_seconds: self.command_timeout,
+            retry: RetryPolicy {
+                max_attempts: self.max_attempts.max(1),
+                initial_backoff: Duration::from_millis(self.retry_backoff_ms),
+                ..Default::default()
+            },
+            phases: PhaseSelection {
+                only: self.only_phase.clone(),
+                skip: self.skip_phase.clone(),
+            },
+            redaction: load_redaction(self.redaction_config.as_deref())?,
+            sampling: self.sample_duration.map(|duration| SamplingConfig {
+                duration: Duration::from_secs(duration),
+                interval: Duration::from_secs(self.sample_interval.max(1)),
+            }),
+            resolve_hosts: self.resolve_hosts,
+            strict: self.strict,
+        })
+    }
+}
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
     let filter = if cli.verbose {
@@ -109,94 +773,837 @@ async fn main() -> anyhow::Result<()> {
         EnvFilter::new("info")
     };
 
+    // Keep stdout clean for JSON output and reports
+    let json_output = match &cli.command {
+        // The bundle itself goes to stdout
+        Commands::Collect {
+            emit_stdout: true, ..
+        } => true,
+        Commands::Collect { format, .. }
+        | Commands::Analyze { format, .. }
+        | Commands::Validate { format, .. }
+        | Commands::ValidatePlan { format, .. }
+        | Commands::TraceDecision { format, .. } => {
+            matches!(format.parse(), Ok(OutputFormat::Json))
+        }
+        Commands::Report { out: None, .. } => true,
+        _ => false,
+    };
+    let log_layer = if json_output {
+        fmt::layer()
+            .with_target(false)
+            .with_writer(std::io::stderr)
+            .boxed()
+    } else {
+        fmt::layer().with_target(false).boxed()
+    };
+
     tracing_subscriber::registry()
-        .with(fmt::layer().with_target(false))
+        .with(log_layer)
         .with(filter)
         .init();
 
-    match cli.command {
+    match run(cli.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Human-readable error, then a JSON line for scripts
+            eprintln!("Error: {:?}", e);
+            let summary = ErrorSummary::new(&e);
+            if let Ok(json) = serde_json::to_string(&summary) {
+                eprintln!("{}", json);
+            }
+            ExitCode::from(summary.exit_code)
+        }
+    }
+}
+
+async fn run(command: Commands) -> anyhow::Result<()> {
+    match command {
         Commands::Collect {
-            target,
-            os,
-            mode,
+            collection,
             out,
-            ssh_port,
-            ssh_user,
-            ssh_key,
-            ssh_password,
-            winrm_port,
-            winrm_user,
-            winrm_password,
-            winrm_https,
-            timeout,
+            emit_stdout,
+            bundle_format,
+            upload,
+            dry_run,
+            offline,
+            inventory,
+            format,
+            vault_key_file,
         } => {
-            let is_local = mode == "local-ephemeral" || mode == "local";
-
-            let os_type: OsType = match os {
-                Some(s) => s.parse()?,
-                None if is_local => {
-                    if cfg!(target_os = "windows") {
-                        OsType::Windows
-                    } else {
-                        OsType::Linux
-                    }
+            let os_type = collection.os_type(inventory.is_some())?;
+            let mut config = collection.config(os_type)?;
+            config.redaction.record_originals = vault_key_file.is_some();
+            let uploader = upload.uploader(&config.retry)?;
+            let bundle_format: BundleFormat = bundle_format.parse()?;
+            // The vault and the upload record are written next to the bundle
+            if out.as_deref().is_some_and(storage::is_remote)
+                && (vault_key_file.is_some() || uploader.is_some())
+            {
+                anyhow::bail!("--vault-key-file and --upload-url need a local --out");
+            }
+
+            // (inventory host name, configuration) of each target
+            let targets = match inventory {
+                Some(path) => {
+                    let pattern = collection
+                        .target
+                        .clone()
+                        .context("--target (a host or group) is required with --inventory")?;
+                    Inventory::load(&path)?
+                        .resolve(&pattern)?
+                        .into_iter()
+                        .map(|host| {
+                            let mut config = config.clone();
+                            let windows =
+                                matches!(host.connection.as_deref(), Some("winrm" | "psrp"));
+                            config.target = host.address.clone();
+                            config.mode = if host.connection.as_deref() == Some("local") {
+                                CollectionMode::LocalEphemeral
+                            } else {
+                                CollectionMode::Remote
+                            };
+                            // Command line settings win over inventory variables
+                            if windows {
+                                config.os_type = os_type.unwrap_or(OsType::Windows);
+                                config.winrm_port = collection
+                                    .winrm_port
+                                    .or(host.port)
+                                    .unwrap_or(collection.default_winrm_port());
+                                config.winrm_user =
+                                    collection.winrm_user.clone().or(host.user.clone());
+                            } else {
+                                config.ssh_port = collection.ssh_port.or(host.port).unwrap_or(22);
+                                config.ssh_user = collection.ssh_user.clone().or(host.user.clone());
+                                config.ssh_key =
+                                    collection.ssh_key.clone().or(host.private_key.clone());
+                            }
+                            (Some(host.name), config)
+                        })
+                        .collect()
+                }
+                None => {
+                    config.target = collection.target()?;
+                    vec![(None, config)]
                 }
-                None => anyhow::bail!("--os is required for remote collection"),
             };
 
-            let target = match target {
-                Some(t) => t,
-                None if is_local => "localhost".to_string(),
-                None => anyhow::bail!("--target is required for remote collection"),
-            };
+            let progress: ProgressFormat = format.parse()?;
+            let several = targets.len() > 1;
+            let mut collection_errors = 0;
+            let mut upload_failures = 0;
+            let credentials = collection.credentials.sources();
+            for (host, mut config) in targets {
+                info!("Collecting from {} ({:?})", config.target, config.os_type);
+                if !offline {
+                    credentials.fill(&mut config)?;
+                }
 
-            info!("Collecting from {} ({:?})", target, os_type);
+                if dry_run {
+                    if several {
+                        println!("# {}", host.as_deref().unwrap_or(&config.target));
+                    }
+                    let collector = xcprobe_collector::collector::Collector::new(config)?;
+                    let plan = collector.dry_run(offline).await?;
+                    match progress {
+                        ProgressFormat::Json => {
+                            println!("{}", serde_json::to_string_pretty(&plan)?)
+                        }
+                        ProgressFormat::Text => {
+                            for planned in &plan {
+                                match &planned.note {
+                                    Some(note) => println!(
+                                        "[{}] {}  # {}",
+                                        planned.phase, planned.command, note
+                                    ),
+                                    None => println!("[{}] {}", planned.phase, planned.command),
+                                }
+                            }
+                        }
+                    }
+                    continue;
+                }
 
-            let config = xcprobe_collector::collector::CollectorConfig {
-                target,
-                os_type,
-                mode: mode.parse()?,
-                ssh_port,
-                ssh_user,
-                ssh_key,
-                ssh_password,
-                winrm_port,
-                winrm_user,
-                winrm_password,
-                winrm_https,
-                timeout_seconds: timeout,
-            };
+                if emit_stdout {
+                    let stdout = std::io::stdout();
+                    if stdout.is_terminal() {
+                        anyhow::bail!(
+                            "Refusing to write the bundle to a terminal: redirect stdout to a file"
+                        );
+                    }
+                    let collector = xcprobe_collector::collector::Collector::new(config)?
+                        .with_progress(progress.reporter());
+                    let bundle = collector.collect().await?;
+                    xcprobe_collector::bundle::write_bundle_as(
+                        &bundle,
+                        stdout.lock(),
+                        bundle_format,
+                    )?;
+                    collection_errors += collection_failures(&bundle.manifest);
+                    continue;
+                }
 
-            let collector = xcprobe_collector::collector::Collector::new(config)?;
-            let bundle = collector.collect().await?;
+                // Several hosts get one directory each under --out
+                let out = out.clone().context("--out is required")?;
+                let out = match host.filter(|_| several) {
+                    Some(name) => {
+                        let dir = out.join(name);
+                        if !storage::is_remote(&dir) {
+                            std::fs::create_dir_all(&dir)?;
+                        }
+                        dir.join(format!("bundle.{}", bundle_format.extension()))
+                    }
+                    None => out,
+                };
+                let collector = xcprobe_collector::collector::Collector::new(config)?
+                    .with_progress(progress.reporter());
+                let bundle = collector.collect().await?;
+
+                storage::write_bundle(&bundle, &out, bundle_format).await?;
+                info!("Bundle written to {:?}", out);
+                collection_errors += collection_failures(&bundle.manifest);
+
+                let mut vault_path = None;
+                if let Some(ref key_file) = vault_key_file {
+                    let passphrase = vault::read_key_file(key_file)?;
+                    let originals = collector.take_redacted_originals();
+                    let path = out
+                        .parent()
+                        .unwrap_or(Path::new("."))
+                        .join(vault::VAULT_FILE_NAME);
+                    vault::write_vault(&path, &originals, &passphrase)?;
+                    info!("{} redacted values written to {:?}", originals.len(), path);
+                    vault_path = Some(path);
+                }
+
+                // A failed upload leaves the bundle on disk and moves on to
+                // the next host
+                let mut uploaded = None;
+                if let Some(ref uploader) = uploader {
+                    match uploader
+                        .upload(&out, &upload::remote_name(&bundle, bundle_format))
+                        .await
+                    {
+                        Ok(record) => {
+                            info!("Bundle uploaded to {}", record.url);
+                            upload::record_upload(out.parent().unwrap_or(Path::new(".")), &record)?;
+                            uploaded = Some(record);
+                        }
+                        Err(e) => {
+                            error!("Failed to upload {:?}: {:#}", out, e);
+                            upload_failures += 1;
+                        }
+                    }
+                }
 
-            xcprobe_collector::bundle::write_bundle(&bundle, &out)?;
-            info!("Bundle written to {:?}", out);
+                if progress == ProgressFormat::Json {
+                    // One line, like the progress events before it
+                    let summary =
+                        collect_summary(&bundle, &out, vault_path.as_deref(), uploaded.as_ref());
+                    println!("{}", serde_json::to_string(&summary)?);
+                }
+            }
+
+            if upload_failures > 0 {
+                anyhow::bail!("{} bundles could not be uploaded", upload_failures);
+            }
+            if collection_errors > 0 {
+                return Err(XcError::PartialCollection {
+                    errors: collection_errors,
+                }
+                .into());
+            }
+        }
+
+        Commands::Watch {
+            collection,
+            out,
+            bundle_format,
+            interval,
+            on_service_change,
+            poll_interval,
+            upload,
+            keep,
+            max_age_hours,
+            count,
+        } => {
+            let mut config = collection.config(collection.os_type(false)?)?;
+            config.target = collection.target()?;
+            collection.credentials.sources().fill(&mut config)?;
+            let watch_config = WatchConfig {
+                out_dir: out,
+                format: bundle_format.parse()?,
+                interval: Duration::from_secs(interval),
+                service_poll: on_service_change.then(|| Duration::from_secs(poll_interval.max(1))),
+                upload: upload.uploader(&config.retry)?,
+                retention: Retention {
+                    max_bundles: keep,
+                    max_age: max_age_hours.map(|hours| Duration::from_secs(hours * 3600)),
+                },
+                count,
+            };
+            info!(
+                "Watching {} ({:?}) every {}s",
+                config.target, config.os_type, interval
+            );
+            let collector = xcprobe_collector::collector::Collector::new(config)?;
+            let written = Watcher::new(collector, watch_config)?.run().await?;
+            info!("{} bundles collected", written.len());
         }
 
         Commands::Analyze {
             bundle,
             out,
             cluster_prefix,
+            clustering_strategy,
+            hints,
             min_confidence,
+            include_rejected,
+            scoring_config,
+            redaction_config,
+            build_check,
+            templates_dir,
+            dev_stubs,
+            advise_rewrite,
+            force,
+            merge,
+            reproducible,
+            jobs,
+            format,
         } => {
+            let format: OutputFormat = format.parse()?;
             info!("Analyzing bundle: {:?}", bundle);
 
-            let bundle_data = xcprobe_collector::bundle::read_bundle(&bundle)?;
+            let bundle_data = storage::read_bundle(&bundle).await?;
+
+            let analyze_options = xcprobe_analyzer::AnalyzeOptions {
+                cluster_prefix,
+                clustering_strategy: clustering_strategy.parse()?,
+                hints: hints
+                    .as_deref()
+                    .map(xcprobe_analyzer::hints::load_hints)
+                    .transpose()?,
+                min_confidence,
+                include_rejected,
+                scoring: scoring_config
+                    .as_deref()
+                    .map(xcprobe_analyzer::scoring::ScoringConfig::load)
+                    .transpose()?
+                    .unwrap_or_default(),
+                redaction: load_redaction(redaction_config.as_deref())?,
+                reproducible,
+                jobs: jobs.map(usize::from),
+                ..Default::default()
+            };
+            let mut pack_plan = xcprobe_analyzer::analyze_bundle(&bundle_data, &analyze_options)?;
+
+            std::fs::create_dir_all(&out)?;
+            mark_packed_files(&mut pack_plan, &out)?;
+            let options = xcprobe_analyzer::GenerateOptions {
+                templates_dir,
+                dev_stubs,
+                overwrite: overwrite_mode(force, merge),
+                advise_rewrite,
+                jobs: jobs.map(usize::from),
+            };
+            let generation = xcprobe_analyzer::generate_artifacts(&pack_plan, &out, &options)?;
+            pack_plan.warnings.extend(generation.lint_warnings);
+            xcprobe_analyzer::record_artifacts(&mut pack_plan, generation.artifacts);
+            if build_check {
+                xcprobe_analyzer::build_check::check_builds(&mut pack_plan, &out, "docker")?;
+            }
+
+            let plan_path = out.join("packplan.json");
+            let plan_json = serde_json::to_string_pretty(&pack_plan)?;
+            std::fs::write(&plan_path, plan_json)?;
+
+            for warning in &pack_plan.warnings {
+                warn!("[{}] {}", warning.code, warning.message);
+            }
+            info!(
+                "Analysis complete (overall confidence {:.2}). Artifacts written to {:?}",
+                pack_plan.overall_confidence, out
+            );
+            if format == OutputFormat::Json {
+                let summary = plan_summary(&pack_plan, &plan_path, &out);
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            }
+            if pack_plan.overall_confidence < min_confidence {
+                return Err(XcError::LowConfidence {
+                    confidence: pack_plan.overall_confidence,
+                    minimum: min_confidence,
+                }
+                .into());
+            }
+        }
+
+        Commands::Generate {
+            plan,
+            out,
+            templates_dir,
+            dev_stubs,
+            advise_rewrite,
+            force,
+            merge,
+            jobs,
+        } => {
+            info!("Generating artifacts from plan: {:?}", plan);
 
-            let pack_plan =
-                xcprobe_analyzer::analyze_bundle(&bundle_data, &cluster_prefix, min_confidence)?;
+            let mut pack_plan = xcprobe_analyzer::load_plan(&plan)?;
 
             std::fs::create_dir_all(&out)?;
-            xcprobe_analyzer::generate_artifacts(&pack_plan, &out)?;
+            mark_packed_files(&mut pack_plan, &out)?;
+            let options = xcprobe_analyzer::GenerateOptions {
+                templates_dir,
+                dev_stubs,
+                overwrite: overwrite_mode(force, merge),
+                advise_rewrite,
+                jobs: jobs.map(usize::from),
+            };
+            let generation = xcprobe_analyzer::generate_artifacts(&pack_plan, &out, &options)?;
+            for warning in &generation.lint_warnings {
+                warn!("[{}] {}", warning.code, warning.message);
+            }
+
+            // Keep the plan next to the artifacts for `pack`, listing them
+            xcprobe_analyzer::record_artifacts(&mut pack_plan, generation.artifacts);
+            let plan_json = serde_json::to_string_pretty(&pack_plan)?;
+            std::fs::write(out.join("packplan.json"), plan_json)?;
+
+            info!("Artifacts written to {:?}", out);
+        }
+
+        Commands::Report {
+            plan,
+            out,
+            format,
+            templates_dir,
+        } => {
+            let format: xcprobe_analyzer::report::ReportFormat = format.parse()?;
+            let pack_plan = xcprobe_analyzer::load_plan(&plan)?;
+            let engine = xcprobe_analyzer::templates::TemplateEngine::with_overrides(
+                templates_dir.as_deref(),
+            )?;
+            let report = xcprobe_analyzer::report::generate_report(&engine, &pack_plan, format)?;
+
+            match out {
+                Some(out) => {
+                    std::fs::write(&out, report)?;
+                    info!("Report written to {:?}", out);
+                }
+                None => print!("{}", report),
+            }
+        }
+
+        Commands::TraceDecision {
+            plan,
+            bundle,
+            decision,
+            cluster,
+            format,
+        } => {
+            let format: OutputFormat = format.parse()?;
+            let pack_plan = xcprobe_analyzer::load_plan(&plan)?;
+            let bundle_data = storage::read_bundle(&bundle).await?;
+            let traces = xcprobe_analyzer::trace::trace_decision(
+                &pack_plan,
+                &bundle_data,
+                &decision,
+                cluster.as_deref(),
+            )?;
+            match format {
+                OutputFormat::Text => print!("{}", xcprobe_analyzer::trace::render_text(&traces)),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&traces)?),
+            }
+        }
 
+        Commands::Pack {
+            out,
+            target,
+            os,
+            ssh_port,
+            ssh_user,
+            ssh_key,
+            ssh_strict_host_key_checking,
+            ssh_known_hosts,
+            transport,
+            redaction_config,
+            templates_dir,
+            advise_rewrite,
+            merge,
+            includes,
+            excludes,
+            max_file_size,
+            max_archive_size,
+            dry_run,
+            jobs,
+            credentials,
+        } => {
             let plan_path = out.join("packplan.json");
+            let mut pack_plan = xcprobe_analyzer::load_plan(&plan_path)?;
+            let pack_options = xcprobe_collector::pack::PackOptions {
+                includes,
+                excludes,
+                max_file_bytes: max_file_size,
+                max_archive_bytes: max_archive_size.saturating_mul(1024 * 1024),
+            };
+            if dry_run {
+                print_pack_listing(&xcprobe_collector::pack::plan_fetches(
+                    &pack_plan,
+                    &pack_options,
+                )?);
+                return Ok(());
+            }
+            let transport: SshTransport = transport.parse()?;
+            let ssh_password = if is_local_target(&target) || transport == SshTransport::OpenSsh {
+                None
+            } else {
+                credentials
+                    .sources()
+                    .resolve(None, SSH_PASSWORD_ENV, &target)?
+            };
+
+            info!("Packing application files from {}", target);
+            let mismatches = xcprobe_collector::pack::execute_pack(
+                &mut pack_plan,
+                &target,
+                os.parse()?,
+                ssh_port,
+                ssh_user,
+                ssh_key,
+                ssh_password,
+                &HostKeyCheck {
+                    policy: ssh_strict_host_key_checking.parse()?,
+                    known_hosts: ssh_known_hosts,
+                },
+                transport,
+                &load_redaction(redaction_config.as_deref())?,
+                &pack_options,
+                &out,
+            )
+            .await?;
+
+            // Regenerate artifacts so Dockerfiles copy the collected files
+            let options = xcprobe_analyzer::GenerateOptions {
+                templates_dir,
+                overwrite: overwrite_mode(true, merge),
+                advise_rewrite,
+                jobs: jobs.map(usize::from),
+                ..Default::default()
+            };
+            let generation = xcprobe_analyzer::generate_artifacts(&pack_plan, &out, &options)?;
+            xcprobe_analyzer::replace_lint_warnings(&mut pack_plan, generation.lint_warnings);
+            xcprobe_analyzer::record_artifacts(&mut pack_plan, generation.artifacts);
+
             let plan_json = serde_json::to_string_pretty(&pack_plan)?;
             std::fs::write(&plan_path, plan_json)?;
 
-            info!("Analysis complete. Artifacts written to {:?}", out);
+            info!("Pack complete. Artifacts updated in {:?}", out);
+            if mismatches > 0 {
+                return Err(XcError::Pack(format!(
+                    "{} fetched files differ from the target's checksums",
+                    mismatches
+                ))
+                .into());
+            }
+        }
+
+        Commands::ExportSbom {
+            bundle,
+            out,
+            format,
+            plan,
+        } => {
+            let format: xcprobe_analyzer::sbom::SbomFormat = format.parse()?;
+            let bundle_data = storage::read_bundle(&bundle).await?;
+            let clusters = match plan {
+                Some(plan) => xcprobe_analyzer::load_plan(&plan)?.clusters,
+                None => Vec::new(),
+            };
+            let written = xcprobe_analyzer::sbom::write_sboms(
+                &bundle_data.manifest,
+                &clusters,
+                format,
+                &out,
+            )?;
+            info!(
+                "Wrote {} SBOMs ({} packages) to {:?}",
+                written.len(),
+                bundle_data.manifest.packages.len(),
+                out
+            );
+        }
+
+        Commands::Validate {
+            bundle,
+            schema,
+            document,
+            check_evidence,
+            verify_checksums,
+            format,
+        } => {
+            let format: OutputFormat = format.parse()?;
+            if let (Some(schema), Some(document)) = (schema, document) {
+                let kind: SchemaKind = schema.parse()?;
+                let json = std::fs::read_to_string(&document)
+                    .with_context(|| format!("Failed to read {}", document.display()))?;
+                let value: serde_json::Value = serde_json::from_str(&json)
+                    .with_context(|| format!("Invalid JSON in {}", document.display()))?;
+                let result = xcprobe_bundle_schema::validation::validate_document(kind, &value)?;
+                report_validation(&document, &result, format, XcError::SchemaValidation)?;
+                return Ok(());
+            }
+            let bundle = bundle.context("--bundle is required")?;
+            let result = xcprobe_collector::bundle::validate_bundle_file(
+                &bundle,
+                check_evidence,
+                verify_checksums,
+            )?;
+            report_validation(&bundle, &result, format, XcError::InvalidBundle)?;
+        }
+
+        Commands::Schema { name } => {
+            let kind: SchemaKind = name.parse()?;
+            println!("{}", kind.schema_text());
+        }
+
+        Commands::ValidatePlan { plan, format } => {
+            let format: OutputFormat = format.parse()?;
+            let plan_json = std::fs::read_to_string(&plan)
+                .with_context(|| format!("Failed to read pack plan {}", plan.display()))?;
+            let plan_value: serde_json::Value = serde_json::from_str(&plan_json)
+                .with_context(|| format!("Invalid pack plan {}", plan.display()))?;
+            let result = xcprobe_bundle_schema::validation::validate_packplan(&plan_value)?;
+            report_validation(&plan, &result, format, XcError::InvalidPlan)?;
+        }
+
+        Commands::Vault {
+            vault: vault_path,
+            key_file,
+            placeholder,
+        } => {
+            let passphrase = vault::read_key_file(&key_file)?;
+            let originals = vault::read_vault(&vault_path, &passphrase)?;
+            match placeholder {
+                Some(placeholder) => {
+                    let original = originals
+                        .get(&placeholder)
+                        .with_context(|| format!("{} is not in the vault", placeholder))?;
+                    println!("{}", original);
+                }
+                None => println!("{}", serde_json::to_string_pretty(&originals)?),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Output format of the commands: logs for humans, or JSON on stdout for
+/// pipelines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid output format: {}", s)),
+        }
+    }
+}
+
+/// Errors of a collection, leaving out the commands the allowlist refused:
+/// they are expected on most hosts and do not make a collection partial.
+fn collection_failures(manifest: &xcprobe_bundle_schema::Manifest) -> usize {
+    manifest.errors.iter().filter(|e| !e.denied).count()
+}
+
+/// Summary of a collected bundle for `collect --format json`.
+fn collect_summary(
+    bundle: &xcprobe_bundle_schema::Bundle,
+    path: &Path,
+    vault_path: Option<&Path>,
+    upload: Option<&upload::UploadRecord>,
+) -> serde_json::Value {
+    let manifest = &bundle.manifest;
+    serde_json::json!({
+        "event": "summary",
+        "bundle": path,
+        "vault": vault_path,
+        "upload": upload.map(|u| &u.url),
+        "collection_id": manifest.collection_id,
+        "hostname": manifest.system.hostname,
+        "os_type": manifest.system.os_type,
+        "collection_mode": manifest.collection_mode,
+        "duration_ms": manifest
+            .completed_at
+            .map(|end| (end - manifest.collected_at).num_milliseconds().max(0)),
+        "stats": {
+            "commands": bundle
+                .audit
+                .iter()
+                .filter(|e| e.status == CommandStatus::Executed)
+                .count(),
+            "denied": manifest.errors.iter().filter(|e| e.denied).count(),
+            "errors": collection_failures(manifest),
+            "processes": manifest.processes.len(),
+            "services": manifest.services.len(),
+            "ports": manifest.ports.len(),
+            "connections": manifest.connections.len(),
+            "packages": manifest.packages.len(),
+            "config_files": manifest.config_files.len(),
+            "log_files": manifest.log_files.len(),
+            "evidence": bundle.evidence.len(),
+        },
+    })
+}
+
+/// Summary of a pack plan for `analyze --format json`.
+fn plan_summary(
+    plan: &xcprobe_bundle_schema::PackPlan,
+    plan_path: &Path,
+    out: &Path,
+) -> serde_json::Value {
+    let clusters: Vec<serde_json::Value> = plan
+        .clusters
+        .iter()
+        .map(|c| {
+            serde_json::json!({
+                "id": c.id,
+                "name": c.name,
+                "app_type": c.app_type,
+                "confidence": c.confidence,
+                "ports": c.ports.iter().map(|p| p.port).collect::<Vec<_>>(),
+                "depends_on": c.depends_on,
+            })
+        })
+        .collect();
+    let rejected: Vec<&str> = plan
+        .rejected_clusters
+        .iter()
+        .map(|r| r.id.as_str())
+        .collect();
+    serde_json::json!({
+        "plan": plan_path,
+        "output_dir": out,
+        "source_bundle_id": plan.source_bundle_id,
+        "overall_confidence": plan.overall_confidence,
+        "clusters": clusters,
+        "rejected_clusters": rejected,
+        "external_dependencies": plan.external_dependencies.len(),
+        "warnings": plan.warnings,
+    })
+}
+
+/// Print the result of `validate` or `validate-plan` and fail with the
+/// `invalid` error when the file is invalid.
+fn report_validation(
+    path: &Path,
+    result: &xcprobe_bundle_schema::validation::ValidationResult,
+    format: OutputFormat,
+    invalid: fn(String) -> XcError,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let errors: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+            let report = serde_json::json!({
+                "path": path,
+                "valid": result.valid,
+                "errors": errors,
+                "warnings": result.warnings,
+            });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        OutputFormat::Text => {
+            for warning in &result.warnings {
+                warn!("{}", warning);
+            }
+            for error in &result.errors {
+                println!("error: {}", error);
+            }
+        }
+    }
+    if !result.valid {
+        return Err(invalid(format!(
+            "{} ({} errors)",
+            path.display(),
+            result.errors.len()
+        ))
+        .into());
+    }
+    if format == OutputFormat::Text {
+        println!("{} is valid", path.display());
+    }
+    Ok(())
+}
+
+/// How to treat existing artifacts, from `--force` and `--merge`.
+/// Print the plan entries `pack` would fetch or skip, and the known transfer
+/// size.
+fn print_pack_listing(fetches: &[xcprobe_collector::pack::PlannedFetch]) {
+    use xcprobe_collector::pack::format_size;
+
+    for fetch in fetches {
+        let size = fetch
+            .size
+            .map(format_size)
+            .unwrap_or_else(|| "?".to_string());
+        match &fetch.skipped {
+            Some(reason) => println!(
+                "skip   {}  {} ({})  # {}",
+                fetch.cluster_id, fetch.source_path, fetch.kind, reason
+            ),
+            None => println!(
+                "fetch  {}  {} ({}, {})",
+                fetch.cluster_id, fetch.source_path, fetch.kind, size
+            ),
         }
     }
+    let fetched: Vec<_> = fetches.iter().filter(|f| f.skipped.is_none()).collect();
+    let known: u64 = fetched.iter().filter_map(|f| f.size).sum();
+    let unknown = fetched.iter().filter(|f| f.size.is_none()).count();
+    println!(
+        "{} of {} entries to fetch, estimated {}{}",
+        fetched.len(),
+        fetches.len(),
+        format_size(known),
+        if unknown > 0 {
+            format!(" plus {} of unknown size", unknown)
+        } else {
+            String::new()
+        }
+    );
+}
 
+fn overwrite_mode(force: bool, merge: bool) -> xcprobe_analyzer::OverwriteMode {
+    match (force, merge) {
+        (_, true) => xcprobe_analyzer::OverwriteMode::Merge,
+        (true, false) => xcprobe_analyzer::OverwriteMode::Force,
+        (false, false) => xcprobe_analyzer::OverwriteMode::Refuse,
+    }
+}
+
+/// Mark the application files an earlier `pack` fetched into `out` as
+/// collected, so that the regenerated Dockerfiles copy them.
+fn mark_packed_files(plan: &mut PackPlan, out: &Path) -> anyhow::Result<()> {
+    let marked = xcprobe_analyzer::artifacts::mark_packed_files(plan, out)?;
+    if marked > 0 {
+        info!("Copying {} application files fetched by pack", marked);
+    }
     Ok(())
 }
+
+/// Redaction rules from `--redaction-config`, or the defaults.
+fn load_redaction(path: Option<&Path>) -> anyhow::Result<RedactorConfig> {
+    Ok(path
+        .map(RedactorConfig::load)
+        .transpose()?
+        .unwrap_or_default())
+}